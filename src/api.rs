@@ -0,0 +1,353 @@
+// The representation served under `/api/v1`, apart from the internal types.
+//
+// Internal structs serialize as they are stored, snake_case and with details
+// such as the tracker's activity key, and the unversioned `/api` routes keep
+// answering with them for existing scripts. The versioned routes answer with
+// these instead: camelCase, times as `...Secs` lengths and Unix-second
+// timestamps, and only what a frontend needs. Each is built from its internal
+// type with `From`, so a refactor there only has to keep the conversion
+// compiling. A change that would break a consumer goes into a new version
+// next to this one rather than into these types.
+
+use serde::Serialize;
+
+use crate::identifier::ActivityKind;
+use crate::units::{DurationSecs, Timestamp};
+use crate::{dashboard, forecast, goals, pauses, refresh, safe_mode, DashboardData, RecentActivity};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dashboard {
+    pub current_app: Option<String>,
+    pub current_window: Option<String>,
+    pub current_url: Option<String>,
+    pub active_apps: Vec<ActiveApp>,
+    pub recent_activity: Vec<Activity>,
+    pub total_apps: usize,
+    pub uptime_secs: DurationSecs,
+    pub safe_mode: Option<SafeMode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveApp {
+    pub app_name: String,
+    pub kind: ActivityKind, // Whether `detail` is a URL or a window title
+    pub detail: String,
+    pub active_secs: DurationSecs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Activity {
+    pub app_name: String,
+    pub window_title: String,
+    pub url: Option<String>,
+    pub duration_secs: DurationSecs,
+    pub ended_at: Timestamp,
+    pub partial: bool,
+}
+
+impl From<DashboardData> for Dashboard {
+    fn from(data: DashboardData) -> Self {
+        Self {
+            current_app: data.current_app,
+            current_window: data.current_window,
+            current_url: data.current_url,
+            active_apps: data
+                .active_apps
+                .into_iter()
+                .map(|(key, active_secs)| ActiveApp { app_name: key.app_name, kind: key.kind, detail: key.detail, active_secs })
+                .collect(),
+            recent_activity: data.recent_activity.into_iter().map(Activity::from).collect(),
+            total_apps: data.total_apps,
+            uptime_secs: data.uptime,
+            safe_mode: data.safe_mode.map(SafeMode::from),
+        }
+    }
+}
+
+impl From<RecentActivity> for Activity {
+    fn from(activity: RecentActivity) -> Self {
+        Self {
+            app_name: activity.app_name,
+            window_title: activity.window_title,
+            url: activity.url,
+            duration_secs: activity.duration,
+            ended_at: activity.timestamp,
+            partial: activity.partial,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeMode {
+    pub reason: String,
+    pub crashed_starts: u32,
+    pub since: Timestamp,
+}
+
+impl From<safe_mode::SafeMode> for SafeMode {
+    fn from(safe_mode: safe_mode::SafeMode) -> Self {
+        Self { reason: safe_mode.reason, crashed_starts: safe_mode.crashed_starts, since: Timestamp::from_secs(safe_mode.since) }
+    }
+}
+
+// Next to `data` rather than in it, like `version`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    pub poll_secs: DurationSecs,
+    pub last_flush: Option<Timestamp>,
+    pub generated_at: Timestamp,
+}
+
+impl From<refresh::Meta> for Meta {
+    fn from(meta: refresh::Meta) -> Self {
+        Self {
+            poll_secs: DurationSecs::secs(meta.poll_secs),
+            last_flush: meta.last_flush.map(Timestamp::from_secs),
+            generated_at: Timestamp::from_secs(meta.generated_at),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Layout {
+    pub columns: u32,
+    pub widgets: Vec<dashboard::Widget>,
+    pub panels: Vec<String>,
+    pub bookmarks: Vec<String>,
+    pub saved: bool, // False for the built-in layout
+    pub updated_at: Option<Timestamp>,
+}
+
+impl From<dashboard::StoredLayout> for Layout {
+    fn from(stored: dashboard::StoredLayout) -> Self {
+        Self {
+            columns: stored.layout.columns,
+            widgets: stored.layout.widgets,
+            panels: stored.layout.panels,
+            bookmarks: stored.layout.bookmarks,
+            saved: stored.saved,
+            updated_at: stored.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tracking {
+    pub paused: bool,
+    pub remaining_secs: Option<DurationSecs>, // Until an open pause with a timer resumes
+    pub pause: Option<Pause>,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pause {
+    pub id: i64,
+    pub start: Timestamp,
+    pub end: Option<Timestamp>, // None while paused
+    pub reason: Option<String>,
+    pub resume_at: Option<Timestamp>,
+}
+
+impl Tracking {
+    pub fn new(pause: Option<pauses::Pause>, reasons: Vec<String>, now: Timestamp) -> Self {
+        Self {
+            paused: pause.is_some(),
+            remaining_secs: pause.as_ref().and_then(|pause| pause.remaining(now)),
+            pause: pause.map(Pause::from),
+            reasons,
+        }
+    }
+}
+
+impl From<pauses::Pause> for Pause {
+    fn from(pause: pauses::Pause) -> Self {
+        Self {
+            id: pause.id,
+            start: pause.start,
+            end: pause.end,
+            reason: pause.reason,
+            resume_at: pause.resume_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Forecast {
+    pub date: String,
+    pub as_of: Timestamp,
+    pub history_days: usize,
+    pub productive: Projection,
+    pub budgets: Vec<Budget>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Projection {
+    pub so_far_secs: DurationSecs,
+    pub projected_secs: DurationSecs,
+    pub pace: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Budget {
+    pub category: String,
+    pub budget_secs: DurationSecs,
+    pub used_secs: DurationSecs,
+    pub projected_secs: DurationSecs,
+    pub exhausted: bool,
+    pub exhausted_at: Option<Timestamp>,
+}
+
+impl From<forecast::Forecast> for Forecast {
+    fn from(forecast: forecast::Forecast) -> Self {
+        let productive = forecast.productive;
+        Self {
+            date: forecast.date,
+            as_of: forecast.as_of,
+            history_days: forecast.history_days,
+            productive: Projection {
+                so_far_secs: DurationSecs::secs(productive.so_far_secs),
+                projected_secs: DurationSecs::secs(productive.projected_secs),
+                pace: productive.pace,
+            },
+            budgets: forecast
+                .budgets
+                .into_iter()
+                .map(|budget| Budget {
+                    category: budget.category,
+                    budget_secs: DurationSecs::secs(budget.budget_secs),
+                    used_secs: DurationSecs::secs(budget.used_secs),
+                    projected_secs: DurationSecs::secs(budget.projected_secs),
+                    exhausted: budget.exhausted,
+                    exhausted_at: budget.exhausted_at,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Week {
+    pub week_start: String,
+    pub week_end: String,
+    pub goals: Vec<Goal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Goal {
+    pub category: String,
+    pub target_secs: DurationSecs,
+    pub done_secs: DurationSecs,
+    pub remaining_secs: DurationSecs,
+    pub days_left: u64, // Including today
+    pub required_daily_secs: DurationSecs,
+    pub on_track: bool,
+    pub by_weekday: Vec<Day>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Day {
+    pub date: String,
+    pub weekday: String,
+    pub seconds: DurationSecs,
+}
+
+impl From<goals::WeekProgress> for Week {
+    fn from(week: goals::WeekProgress) -> Self {
+        Self {
+            week_start: week.week_start,
+            week_end: week.week_end,
+            goals: week
+                .goals
+                .into_iter()
+                .map(|goal| Goal {
+                    category: goal.category,
+                    target_secs: DurationSecs::secs(goal.target_secs),
+                    done_secs: DurationSecs::secs(goal.done_secs),
+                    remaining_secs: DurationSecs::secs(goal.remaining_secs),
+                    days_left: goal.days_left,
+                    required_daily_secs: DurationSecs::secs(goal.required_daily_secs),
+                    on_track: goal.on_track,
+                    by_weekday: goal.by_weekday.into_iter().map(|day| Day { date: day.date, weekday: day.weekday, seconds: DurationSecs::secs(day.seconds) }).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifier::ActivityKey;
+
+    #[test]
+    fn dashboard_is_camel_case_without_internal_keys() {
+        let key = ActivityKey::new("chrome.exe", "GitHub", Some("https://github.com"));
+        let data = DashboardData {
+            current_app: Some("chrome.exe".into()),
+            current_window: Some("GitHub".into()),
+            current_url: Some("https://github.com".into()),
+            active_apps: vec![(key.clone(), DurationSecs::secs(45))],
+            recent_activity: vec![RecentActivity {
+                key,
+                app_name: "chrome.exe".into(),
+                window_title: "GitHub".into(),
+                url: Some("https://github.com".into()),
+                duration: DurationSecs::mins(15),
+                timestamp: Timestamp::from_secs(1_703_123_456),
+                partial: false,
+            }],
+            total_apps: 1,
+            uptime: DurationSecs::secs(135),
+            safe_mode: None,
+        };
+        let json = serde_json::to_value(Dashboard::from(data)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "currentApp": "chrome.exe",
+                "currentWindow": "GitHub",
+                "currentUrl": "https://github.com",
+                "activeApps": [{"appName": "chrome.exe", "kind": "url", "detail": "https://github.com", "activeSecs": 45}],
+                "recentActivity": [{
+                    "appName": "chrome.exe",
+                    "windowTitle": "GitHub",
+                    "url": "https://github.com",
+                    "durationSecs": 900,
+                    "endedAt": 1_703_123_456,
+                    "partial": false
+                }],
+                "totalApps": 1,
+                "uptimeSecs": 135,
+                "safeMode": null
+            })
+        );
+    }
+
+    #[test]
+    fn tracking_carries_the_pause_timer() {
+        let pause = pauses::Pause {
+            id: 3,
+            start: Timestamp::from_secs(1_000),
+            end: None,
+            reason: Some("Meeting".into()),
+            resume_at: Some(Timestamp::from_secs(2_800)),
+        };
+        let json = serde_json::to_value(Tracking::new(Some(pause), vec!["Meeting".into()], Timestamp::from_secs(1_600))).unwrap();
+        assert_eq!(json["remainingSecs"], 1_200);
+        assert_eq!(json["pause"], serde_json::json!({"id": 3, "start": 1_000, "end": null, "reason": "Meeting", "resumeAt": 2_800}));
+    }
+}
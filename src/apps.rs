@@ -0,0 +1,169 @@
+// Human-readable names for apps, from what the tracker saw of them.
+//
+// Sessions only carry the exe name, which reads poorly in reports:
+// `WINWORD.EXE`, `msedge.exe`. When the tracker sees an app with a new image
+// path it stores, in `apps`, the `FileDescription` of the executable's version
+// resource ("Microsoft Word"), and with each new window class that class
+// ("OpusApp"). Reports show the description as the app's `display_name`, so
+// no aliases have to be configured by hand.
+//
+// `/api/apps/lifecycle` lists every app ever tracked with when it was first
+// and last in front and its time over all days, oldest use first, to find
+// software not worth keeping. Days whose sessions were archived still count
+// through their rollups; for those only the day is known, so an app last seen
+// on one counts as seen until its end. An app unused for `stale_days`
+// (default 90) is `stale`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::units::{DurationSecs, Timestamp};
+use crate::{reports, rollups};
+
+const MAX_DESCRIPTION_CHARS: usize = 100;
+const DAY_SECS: u64 = 24 * 3600;
+pub const STALE_DAYS: u64 = 90;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LifecycleQuery {
+    pub stale_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Lifecycle {
+    pub app_name: String,
+    pub display_name: Option<String>,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+    pub days_used: u64,
+    pub lifetime_secs: DurationSecs,
+    pub lifetime_hours: f64, // To a tenth
+    pub unused_days: u64, // Whole days since `last_seen`
+    pub stale: bool,
+}
+
+/// A description worth showing: trimmed, and None when it is empty or only
+/// repeats the exe name.
+pub fn clean_description(description: &str, executable: &str) -> Option<String> {
+    let description: String = description.chars().filter(|c| !c.is_control()).collect::<String>().trim().chars().take(MAX_DESCRIPTION_CHARS).collect();
+    let stem = executable.rsplit_once('.').map_or(executable, |(stem, _)| stem);
+    let repeats = description.eq_ignore_ascii_case(executable) || description.eq_ignore_ascii_case(stem);
+    (!description.is_empty() && !repeats).then_some(description)
+}
+
+/// Stored description per app name from the `apps` table.
+pub fn display_names(conn: &Connection) -> SqlResult<HashMap<String, String>> {
+    let mut stmt = conn.prepare("SELECT app_name, description FROM apps WHERE description IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+    rows.collect()
+}
+
+/// Every app with a session, a rollup or an `apps` row, least recently
+/// used first.
+pub fn lifecycle(conn: &Connection, today: NaiveDate, now: Timestamp, stale_days: u64) -> SqlResult<Vec<Lifecycle>> {
+    // (first seen, last seen, days used, time) per app
+    let mut apps: BTreeMap<String, (Option<Timestamp>, Option<Timestamp>, u64, DurationSecs)> = BTreeMap::new();
+    let mut seen = |app_name: String, first: Timestamp, last: Timestamp| {
+        let app = apps.entry(app_name).or_default();
+        app.0 = Some(app.0.map_or(first, |known| known.min(first)));
+        app.1 = Some(app.1.map_or(last, |known| known.max(last)));
+    };
+    let mut stmt = conn.prepare("SELECT app_name, MIN(timestamp - duration), MAX(timestamp) FROM usage_logs GROUP BY app_name")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)))?;
+    for row in rows {
+        let (app_name, first, last) = row?;
+        seen(app_name, first, last);
+    }
+    let mut stmt = conn.prepare("SELECT app_name, first_seen, last_seen FROM apps")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)))?;
+    for row in rows {
+        let (app_name, first, last) = row?;
+        seen(app_name, first, last);
+    }
+
+    let first_rolled: Option<String> = conn.query_row("SELECT MIN(day) FROM daily_rollups", [], |row| row.get(0)).optional()?.flatten();
+    let first_day = first_rolled
+        .and_then(|day| NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok())
+        .into_iter()
+        .chain(apps.values().filter_map(|app| app.0).map(|first| reports::local_date(first.as_secs())))
+        .min();
+    if let Some(first_day) = first_day {
+        for total in rollups::load(conn, first_day, today.succ_opt().unwrap_or(today))? {
+            let (start, end) = reports::day_bounds(total.day);
+            let app = apps.entry(total.app_name).or_default();
+            app.2 += 1;
+            app.3 += total.seconds;
+            // Only days past what the sessions tell: those were archived
+            if app.0.is_none_or(|first| total.day < reports::local_date(first.as_secs())) {
+                app.0 = Some(Timestamp::from_secs(start.max(0) as u64));
+            }
+            if app.1.is_none_or(|last| total.day > reports::local_date(last.as_secs())) {
+                app.1 = Some(Timestamp::from_secs(end.max(0) as u64).min(now));
+            }
+        }
+    }
+
+    let display_names = display_names(conn)?;
+    let mut lifecycles: Vec<Lifecycle> = apps
+        .into_iter()
+        .filter_map(|(app_name, (first, last, days_used, seconds))| {
+            let (first_seen, last_seen) = (first?, last?);
+            let unused_days = now.since(last_seen).as_secs() / DAY_SECS;
+            Some(Lifecycle {
+                display_name: display_names.get(&app_name).cloned(),
+                app_name,
+                first_seen,
+                last_seen,
+                days_used,
+                lifetime_secs: seconds,
+                lifetime_hours: (seconds.as_secs() as f64 / 360.0).round() / 10.0,
+                unused_days,
+                stale: unused_days >= stale_days,
+            })
+        })
+        .collect();
+    lifecycles.sort_by(|a, b| a.last_seen.cmp(&b.last_seen).then_with(|| a.app_name.cmp(&b.app_name)));
+    Ok(lifecycles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn descriptions_that_add_nothing_are_dropped() {
+        assert_eq!(clean_description(" Microsoft Word\0", "WINWORD.EXE").as_deref(), Some("Microsoft Word"));
+        assert_eq!(clean_description("Google Chrome", "chrome.exe").as_deref(), Some("Google Chrome"));
+        assert_eq!(clean_description("notepad", "notepad.exe"), None);
+        assert_eq!(clean_description("  ", "tool.exe"), None);
+        assert_eq!(clean_description(&"x".repeat(300), "tool.exe").unwrap().len(), MAX_DESCRIPTION_CHARS);
+    }
+
+    #[test]
+    fn lifecycle_spans_sessions_and_archived_days() {
+        let conn = fixtures::empty().unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let midnight = reports::day_bounds(today).0 as u64;
+        let now = Timestamp::from_secs(midnight) + DurationSecs::hours(12);
+
+        // Code ran today and a year ago, from a day whose sessions are archived
+        conn.execute("INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('a', 'code.exe', '', ?1, 5400)", [now - DurationSecs::mins(10)]).unwrap();
+        conn.execute("INSERT INTO daily_rollups (day, app_name, seconds, sessions) VALUES ('2023-06-01', 'code.exe', 1800, 1)", []).unwrap();
+        // Paint was only ever seen when its details were stored, 100 days ago
+        conn.execute("INSERT INTO apps (app_name, executable, description, first_seen, last_seen) VALUES ('mspaint.exe', 'mspaint.exe', 'Paint', ?1, ?1)", [now - DurationSecs::days(100)]).unwrap();
+
+        let lifecycle = lifecycle(&conn, today, now, STALE_DAYS).unwrap();
+        assert_eq!(lifecycle.len(), 2);
+        let (paint, code) = (&lifecycle[0], &lifecycle[1]);
+        assert_eq!((paint.app_name.as_str(), paint.display_name.as_deref(), paint.days_used), ("mspaint.exe", Some("Paint"), 0));
+        assert!(paint.stale && paint.unused_days == 100 && paint.lifetime_secs == DurationSecs::ZERO);
+
+        assert_eq!(code.first_seen.as_secs(), reports::day_bounds(NaiveDate::from_ymd_opt(2023, 6, 1).unwrap()).0 as u64);
+        assert_eq!((code.last_seen, code.days_used, code.lifetime_secs), (now - DurationSecs::mins(10), 2, DurationSecs::hours(2)));
+        assert_eq!((code.lifetime_hours, code.stale), (2.0, false));
+    }
+}
@@ -0,0 +1,395 @@
+// Cold storage for old sessions.
+//
+// With `archive.after_months` set, sessions that started before the first of
+// the month that many months back are moved out of SQLite into one gzipped
+// JSON Lines file per (local) month, `usage-YYYY-MM.jsonl.gz` in
+// `archive.dir`. Files are rewritten whole when more rows of their month are
+// archived later, e.g. after a late import. Reads that pass
+// `include_archive` merge the files back in. `retention` applies to the files
+// too: expired URLs and titles are redacted in place and expired sessions
+// dropped, removing a month's file once nothing is left in it.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDate};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::ActivityKey;
+use crate::reports;
+use crate::retention::{self, Pruned, RetentionConfig, EXPIRED_TITLE};
+use crate::sessions::{self, Session};
+use crate::units::{DurationSecs, Timestamp};
+
+const FILE_PREFIX: &str = "usage-";
+const FILE_SUFFIX: &str = ".jsonl.gz";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub after_months: Option<u32>, // Unset keeps everything in the database
+    pub dir: String, // Relative paths are next to the database
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self { after_months: None, dir: "archive".to_string() }
+    }
+}
+
+impl ArchiveConfig {
+    pub fn dir_for(&self, db_path: &str) -> PathBuf {
+        let dir = Path::new(&self.dir);
+        match Path::new(db_path).parent() {
+            Some(parent) if dir.is_relative() => parent.join(dir),
+            _ => dir.to_path_buf(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ArchiveQuery {
+    #[serde(default)]
+    pub include_archive: bool,
+}
+
+/// A `usage_logs` row as stored in the archive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ArchiveRow {
+    pub identifier: String,
+    pub app_name: String,
+    pub window_title: String,
+    pub url: Option<String>,
+    pub timestamp: Timestamp,
+    pub duration: DurationSecs,
+    pub partial: bool,
+    pub source: Option<String>,
+    pub clock_skew: Option<i64>,
+}
+
+impl ArchiveRow {
+    fn start(&self) -> Timestamp {
+        self.timestamp.saturating_sub(self.duration)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchivedMonth {
+    pub month: String,
+    pub rows: usize,
+    pub file: String,
+}
+
+fn month_start(year: i32, month: u32) -> Timestamp {
+    let date = NaiveDate::from_ymd_opt(year, month, 1).unwrap_or_default();
+    Timestamp::from_secs(reports::day_bounds(date).0.max(0) as u64)
+}
+
+fn month_of(timestamp: Timestamp) -> (i32, u32) {
+    let date = reports::local_date(timestamp.as_secs());
+    (date.year(), date.month())
+}
+
+fn next_month((year, month): (i32, u32)) -> (i32, u32) {
+    if month == 12 { (year + 1, 1) } else { (year, month + 1) }
+}
+
+/// Start of the local month `months` before the one containing `now`.
+pub fn cutoff(now: Timestamp, months: u32) -> Timestamp {
+    let (year, month) = month_of(now);
+    let months_since_zero = year * 12 + month as i32 - 1 - months as i32;
+    month_start(months_since_zero.div_euclid(12), months_since_zero.rem_euclid(12) as u32 + 1)
+}
+
+fn file_name((year, month): (i32, u32)) -> String {
+    format!("{}{:04}-{:02}{}", FILE_PREFIX, year, month, FILE_SUFFIX)
+}
+
+fn parse_file_name(name: &str) -> Option<(i32, u32)> {
+    let month = name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    let (year, month) = month.split_once('-')?;
+    Some((year.parse().ok()?, month.parse().ok().filter(|month| (1..=12).contains(month))?))
+}
+
+fn read_file(path: &Path) -> Result<Vec<ArchiveRow>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    let mut rows = Vec::new();
+    for (number, line) in BufReader::new(MultiGzDecoder::new(file)).lines().enumerate() {
+        let line = line.map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        if line.is_empty() {
+            continue;
+        }
+        rows.push(serde_json::from_str(&line).map_err(|e| format!("{} line {}: {}", path.display(), number + 1, e))?);
+    }
+    Ok(rows)
+}
+
+// Written next to the target and renamed, so a crash never leaves half a month
+fn write_file(path: &Path, rows: &[ArchiveRow]) -> Result<(), String> {
+    let temp = path.with_extension("tmp");
+    let write = || -> std::io::Result<()> {
+        let mut encoder = GzEncoder::new(fs::File::create(&temp)?, Compression::default());
+        for row in rows {
+            serde_json::to_writer(&mut encoder, row)?;
+            encoder.write_all(b"\n")?;
+        }
+        encoder.finish()?.sync_all()?;
+        fs::rename(&temp, path)
+    };
+    write().map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+/// Moves sessions starting before `cutoff` into the monthly files in `dir`.
+pub fn archive(conn: &mut Connection, dir: &Path, cutoff: Timestamp) -> Result<Vec<ArchivedMonth>, String> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|e| e.to_string())?;
+    let mut months: BTreeMap<(i32, u32), Vec<ArchiveRow>> = BTreeMap::new();
+    {
+        let mut stmt = tx
+            .prepare(
+                "SELECT identifier, app_name, window_title, url, timestamp, duration, partial, source, clock_skew
+                 FROM usage_logs
+                 WHERE timestamp - duration < ?1
+                 ORDER BY timestamp, id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![cutoff], |row| {
+                Ok(ArchiveRow {
+                    identifier: row.get(0)?,
+                    app_name: row.get(1)?,
+                    window_title: row.get(2)?,
+                    url: row.get(3)?,
+                    timestamp: row.get(4)?,
+                    duration: row.get(5)?,
+                    partial: row.get(6)?,
+                    source: row.get(7)?,
+                    clock_skew: row.get(8)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let row = row.map_err(|e| e.to_string())?;
+            months.entry(month_of(row.start())).or_default().push(row);
+        }
+    }
+    if months.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let mut archived = Vec::new();
+    for (month, rows) in months {
+        let path = dir.join(file_name(month));
+        let mut contents = if path.exists() { read_file(&path)? } else { Vec::new() };
+        // Rows a run that failed before deleting already wrote aren't repeated
+        let known: HashSet<ArchiveRow> = contents.iter().cloned().collect();
+        let count = rows.len();
+        contents.extend(rows.into_iter().filter(|row| !known.contains(row)));
+        write_file(&path, &contents)?;
+        archived.push(ArchivedMonth {
+            month: format!("{:04}-{:02}", month.0, month.1),
+            rows: count,
+            file: path.display().to_string(),
+        });
+    }
+    // Only once every file is written; the assignments would be orphans
+    tx.execute("DELETE FROM usage_logs WHERE timestamp - duration < ?1", params![cutoff]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM session_categories WHERE start < ?1", params![cutoff]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM session_labels WHERE start < ?1", params![cutoff]).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(archived)
+}
+
+/// Redacts and drops what outlived its `retention` class in the files in `dir`.
+pub fn enforce_retention(dir: &Path, config: &RetentionConfig, now: u64) -> Result<Pruned, String> {
+    let url_cutoff = Timestamp::from_secs(retention::cutoff(config.urls_days, now));
+    let title_cutoff = Timestamp::from_secs(retention::cutoff(config.titles_days, now));
+    let sessions_cutoff = Timestamp::from_secs(retention::cutoff(config.sessions_days, now));
+    let latest = [url_cutoff, title_cutoff, sessions_cutoff].into_iter().max().unwrap_or_default();
+    let mut pruned = Pruned::default();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(pruned); // Nothing archived yet
+    };
+    // Only months that started before a cutoff can hold anything expired
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| parse_file_name(&entry.file_name().to_string_lossy()).is_some_and(|month| month_start(month.0, month.1) < latest))
+        .map(|entry| entry.path())
+        .collect();
+    files.sort();
+    for path in files {
+        let mut rows = read_file(&path)?;
+        let count = rows.len();
+        rows.retain(|row| row.timestamp >= sessions_cutoff);
+        let mut changed = rows.len() < count;
+        pruned.sessions_deleted += count - rows.len();
+        for row in &mut rows {
+            let start = row.start();
+            let url = row.url.as_deref().filter(|_| start < url_cutoff).map(retention::url_origin);
+            let url_due = url.is_some() && url != row.url;
+            let title_due = start < title_cutoff && row.window_title != EXPIRED_TITLE;
+            if url_due {
+                row.url = url;
+                pruned.urls_redacted += 1;
+            }
+            if title_due {
+                row.window_title = EXPIRED_TITLE.to_string();
+                pruned.titles_redacted += 1;
+            }
+            if url_due || title_due {
+                row.identifier = ActivityKey::new(&row.app_name, &row.window_title, row.url.as_deref()).to_string();
+                changed = true;
+            }
+        }
+        if !changed {
+            continue;
+        }
+        if rows.is_empty() {
+            fs::remove_file(&path).map_err(|e| format!("cannot remove {}: {}", path.display(), e))?;
+        } else {
+            write_file(&path, &rows)?;
+        }
+    }
+    Ok(pruned)
+}
+
+/// Archived rows of sessions starting in `[since, until)`.
+pub fn load_rows(dir: &Path, since: Timestamp, until: Timestamp) -> Result<Vec<ArchiveRow>, String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(Vec::new()); // Nothing archived yet
+    };
+    let mut files: Vec<((i32, u32), PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| Some((parse_file_name(&entry.file_name().to_string_lossy())?, entry.path())))
+        .filter(|(month, _)| {
+            let next = next_month(*month);
+            month_start(month.0, month.1) < until && month_start(next.0, next.1) > since
+        })
+        .collect();
+    files.sort();
+    let mut rows = Vec::new();
+    for (_, path) in files {
+        rows.extend(read_file(&path)?.into_iter().filter(|row| (since..until).contains(&row.start())));
+    }
+    Ok(rows)
+}
+
+/// Sessions starting in `[since, until)`, including archived ones when asked.
+pub fn load_sessions(
+    conn: &Connection,
+    dir: &Path,
+    since: Timestamp,
+    until: Timestamp,
+    include_archive: bool,
+) -> Result<Vec<Session>, String> {
+    let mut loaded = sessions::load_sessions(conn, since, until).map_err(|e| e.to_string())?;
+    if include_archive {
+        // Same grouping as `sessions::load_sessions`: the longest row per start
+        let mut archived: BTreeMap<(Timestamp, String), Session> = BTreeMap::new();
+        for row in load_rows(dir, since, until)? {
+            let start = row.start();
+            let session = archived.entry((start, row.identifier.clone())).or_insert_with(|| Session {
+                identifier: row.identifier.clone(),
+                app_name: row.app_name.clone(),
+                start,
+                end: start,
+            });
+            session.end = session.end.max(row.timestamp);
+        }
+        loaded.extend(archived.into_values());
+        loaded.sort_by(|a, b| (a.start, &a.identifier).cmp(&(b.start, &b.identifier)));
+    }
+    Ok(loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn db() -> Connection {
+        fixtures::empty().unwrap()
+    }
+
+    fn log(conn: &Connection, app: &str, start: Timestamp, duration: u64) {
+        conn.execute(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?1, '', ?2, ?3)",
+            params![app, start + DurationSecs::secs(duration), duration],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn cutoff_is_a_month_boundary() {
+        let now = month_start(2024, 3) + DurationSecs::days(10);
+        assert_eq!(cutoff(now, 0), month_start(2024, 3));
+        assert_eq!(cutoff(now, 3), month_start(2023, 12));
+        assert_eq!(cutoff(now, 14), month_start(2023, 1));
+        assert_eq!(parse_file_name(&file_name((2023, 12))), Some((2023, 12)));
+    }
+
+    #[test]
+    fn old_sessions_move_to_monthly_files() {
+        let dir = std::env::temp_dir().join(format!("sysmonitor-archive-{}", std::process::id()));
+        let mut conn = db();
+        let january = month_start(2024, 1) + DurationSecs::days(1);
+        let february = month_start(2024, 2) + DurationSecs::days(1);
+        log(&conn, "code.exe", january, 60);
+        log(&conn, "code.exe", january, 120); // Later flush of the same session
+        log(&conn, "vim", february, 30);
+        log(&conn, "recent.exe", month_start(2024, 3) + DurationSecs::mins(1), 30);
+
+        let archived = archive(&mut conn, &dir, month_start(2024, 3)).unwrap();
+        let months: Vec<(&str, usize)> = archived.iter().map(|month| (month.month.as_str(), month.rows)).collect();
+        assert_eq!(months, vec![("2024-01", 2), ("2024-02", 1)]);
+        let left: i64 = conn.query_row("SELECT COUNT(*) FROM usage_logs", [], |row| row.get(0)).unwrap();
+        assert_eq!(left, 1);
+        assert!(archive(&mut conn, &dir, month_start(2024, 3)).unwrap().is_empty());
+
+        let until = month_start(2024, 4);
+        let stored = load_sessions(&conn, &dir, Timestamp::EPOCH, until, false).unwrap();
+        assert_eq!(stored.len(), 1);
+        let all = load_sessions(&conn, &dir, Timestamp::EPOCH, until, true).unwrap();
+        let apps: Vec<(&str, u64)> = all.iter().map(|session| (session.app_name.as_str(), session.duration().as_secs())).collect();
+        assert_eq!(apps, vec![("code.exe", 120), ("vim", 30), ("recent.exe", 30)]);
+        assert_eq!(load_rows(&dir, february, until).unwrap().len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn retention_reaches_archived_months() {
+        let dir = std::env::temp_dir().join(format!("sysmonitor-archive-retention-{}", std::process::id()));
+        let mut conn = db();
+        let row = |conn: &Connection, title: &str, url: &str, start: Timestamp| {
+            let key = ActivityKey::new("firefox", title, Some(url)).to_string();
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, url, timestamp, duration) VALUES (?1, 'firefox', ?2, ?3, ?4, 60)",
+                params![key, title, url, start + DurationSecs::mins(1)],
+            )
+            .unwrap();
+        };
+        row(&conn, "Statement", "https://bank.example/accounts/42", month_start(2024, 1) + DurationSecs::days(1));
+        row(&conn, "Inbox", "https://mail.example/inbox/7", month_start(2024, 2) + DurationSecs::days(1));
+        archive(&mut conn, &dir, month_start(2024, 3)).unwrap();
+
+        // January's session has expired, February's URL and title have
+        let now = (month_start(2024, 2) + DurationSecs::days(11)).as_secs();
+        let config = RetentionConfig { urls_days: Some(5), titles_days: Some(5), sessions_days: Some(20), ..Default::default() };
+        let pruned = enforce_retention(&dir, &config, now).unwrap();
+        assert_eq!((pruned.urls_redacted, pruned.titles_redacted, pruned.sessions_deleted), (1, 1, 1));
+        assert_eq!(enforce_retention(&dir, &config, now).unwrap(), Pruned::default());
+
+        assert!(!dir.join(file_name((2024, 1))).exists());
+        let rows = load_rows(&dir, Timestamp::EPOCH, Timestamp::MAX).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].window_title.as_str(), rows[0].url.as_deref()), (EXPIRED_TITLE, Some("https://mail.example/")));
+        assert_eq!(rows[0].identifier, ActivityKey::new("firefox", EXPIRED_TITLE, Some("https://mail.example/")).to_string());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
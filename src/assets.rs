@@ -0,0 +1,121 @@
+// Dashboard files compiled into the binary, served under content-hashed names.
+//
+// `index.html` is rewritten at startup to reference `/static/<name>.<hash>.<ext>`,
+// so hashed assets can be cached forever and a new build is picked up on the
+// next page load without a forced refresh. The plain names keep working for
+// anything that still links to them, but are always revalidated.
+
+use std::sync::OnceLock;
+
+use crate::http_cache::{self, Conditional};
+use warp::reply::Response;
+
+const INDEX_HTML: &str = include_str!("../web/index.html");
+
+struct StaticAsset {
+    name: &'static str,
+    content_type: &'static str,
+    body: &'static [u8],
+}
+
+const STATIC_ASSETS: [StaticAsset; 2] = [
+    StaticAsset {
+        name: "style.css",
+        content_type: "text/css; charset=utf-8",
+        body: include_bytes!("../web/static/style.css"),
+    },
+    StaticAsset {
+        name: "script.js",
+        content_type: "application/javascript; charset=utf-8",
+        body: include_bytes!("../web/static/script.js"),
+    },
+];
+
+pub struct AssetManifest {
+    index: String,
+    hashed_names: Vec<String>, // Parallel to STATIC_ASSETS
+}
+
+/// `style.css` -> `style.0123abcd.css`
+fn fingerprinted_name(name: &str, body: &[u8]) -> String {
+    let hash = format!("{:016x}", http_cache::content_hash(body));
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => format!("{}.{}.{}", stem, &hash[..8], ext),
+        None => format!("{}.{}", name, &hash[..8]),
+    }
+}
+
+fn build_manifest() -> AssetManifest {
+    let hashed_names: Vec<String> = STATIC_ASSETS
+        .iter()
+        .map(|asset| fingerprinted_name(asset.name, asset.body))
+        .collect();
+    let mut index = INDEX_HTML.to_string();
+    for (asset, hashed) in STATIC_ASSETS.iter().zip(&hashed_names) {
+        index = index.replace(&format!("/static/{}\"", asset.name), &format!("/static/{}\"", hashed));
+    }
+    AssetManifest { index, hashed_names }
+}
+
+pub fn manifest() -> &'static AssetManifest {
+    static MANIFEST: OnceLock<AssetManifest> = OnceLock::new();
+    MANIFEST.get_or_init(build_manifest)
+}
+
+impl AssetManifest {
+    pub fn asset_count(&self) -> usize {
+        self.hashed_names.len()
+    }
+
+    pub fn index_response(&self, conditional: &Conditional) -> Response {
+        http_cache::conditional_response(
+            conditional,
+            self.index.clone().into_bytes(),
+            "text/html; charset=utf-8",
+            http_cache::REVALIDATE,
+            None,
+        )
+    }
+
+    /// Looks up a static file by hashed or plain name.
+    pub fn static_response(&self, name: &str, conditional: &Conditional) -> Option<Response> {
+        STATIC_ASSETS.iter().zip(&self.hashed_names).find_map(|(asset, hashed)| {
+            let cache_control = if name == hashed {
+                http_cache::IMMUTABLE
+            } else if name == asset.name {
+                http_cache::REVALIDATE
+            } else {
+                return None;
+            };
+            Some(http_cache::conditional_response(
+                conditional,
+                asset.body.to_vec(),
+                asset.content_type,
+                cache_control,
+                None,
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_references_fingerprinted_assets() {
+        let manifest = manifest();
+        for hashed in &manifest.hashed_names {
+            assert!(manifest.index.contains(&format!("/static/{}", hashed)));
+        }
+        assert!(!manifest.index.contains("/static/style.css\""));
+        assert!(!manifest.index.contains("/static/script.js\""));
+    }
+
+    #[test]
+    fn fingerprints_follow_content() {
+        assert_eq!(fingerprinted_name("app.js", b"a"), fingerprinted_name("app.js", b"a"));
+        assert_ne!(fingerprinted_name("app.js", b"a"), fingerprinted_name("app.js", b"b"));
+        assert!(fingerprinted_name("app.js", b"a").starts_with("app.") && fingerprinted_name("app.js", b"a").ends_with(".js"));
+    }
+}
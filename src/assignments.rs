@@ -0,0 +1,277 @@
+// Materialized category assignments.
+//
+// Categorizing a session means parsing its identifier and trying every rule,
+// which is too slow to repeat for each report over months of data. Instead
+// each session's category is stored in `session_categories` together with the
+// version of the rules that produced it, a hash of the rules themselves.
+// `refresh` runs in the background: it assigns sessions stored since the last
+// run and re-evaluates a batch of rows whose version is stale, so editing the
+// rules never rewrites the table in one go. Queries use stored assignments and
+// only categorize the sessions `refresh` has not reached yet. A label set by
+// hand in `session_labels` (see `labels`) wins over either.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::categories::{CategoryConfig, UNCATEGORIZED};
+use crate::identifier::ActivityKey;
+use crate::reports;
+
+pub const REFRESH_BATCH: usize = 2000; // Rows assigned or re-evaluated per refresh
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RefreshStats {
+    pub assigned: usize,
+    pub reevaluated: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub seconds: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryTotals {
+    pub rules_version: String,
+    pub pending: usize, // Sessions categorized for this answer, not yet stored
+    pub categories: Vec<CategoryTotal>, // Largest first
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS session_categories (
+            identifier TEXT NOT NULL,
+            start INTEGER NOT NULL,
+            category TEXT,
+            rules_version TEXT NOT NULL,
+            PRIMARY KEY (identifier, start)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_session_categories_version ON session_categories (rules_version)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS session_labels (
+            identifier TEXT NOT NULL,
+            start INTEGER NOT NULL,
+            category TEXT NOT NULL,
+            change_id INTEGER NOT NULL,
+            PRIMARY KEY (identifier, start)
+        )",
+        [],
+    )?;
+    // Newest usage_logs row already assigned
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_refresh (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            last_log_id INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Short hash of the rules; productivity settings don't change assignments.
+pub fn rules_version(categories: &CategoryConfig) -> String {
+    let rules = serde_json::to_string(&categories.rules).unwrap_or_default();
+    Sha256::digest(rules.as_bytes()).iter().take(8).map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn categorize<'a>(categories: &'a CategoryConfig, identifier: &str) -> Option<&'a str> {
+    categories.categorize(&ActivityKey::parse(identifier))
+}
+
+fn last_log_id(conn: &Connection) -> SqlResult<i64> {
+    let id = conn
+        .query_row("SELECT last_log_id FROM category_refresh WHERE id = 1", [], |row| row.get(0))
+        .optional()?;
+    Ok(id.unwrap_or(0))
+}
+
+/// Assigns up to `limit` new usage_logs rows and re-evaluates up to `limit`
+/// stale assignments.
+pub fn refresh(conn: &mut Connection, categories: &CategoryConfig, limit: usize) -> SqlResult<RefreshStats> {
+    let version = rules_version(categories);
+    let tx = conn.transaction()?;
+    let mut stats = RefreshStats::default();
+
+    let after = last_log_id(&tx)?;
+    let rows: Vec<(i64, String, i64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, identifier, timestamp - duration FROM usage_logs WHERE id > ?1 ORDER BY id LIMIT ?2",
+        )?;
+        let rows = stmt
+            .query_map(params![after, limit as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<SqlResult<_>>()?;
+        rows
+    };
+    if let Some(&(newest, _, _)) = rows.last() {
+        // Flushes rewrite the running session, so most rows repeat a key
+        let mut insert = tx.prepare(
+            "INSERT OR IGNORE INTO session_categories (identifier, start, category, rules_version)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (_, identifier, start) in &rows {
+            stats.assigned += insert.execute(params![identifier, start, categorize(categories, identifier), version])?;
+        }
+        tx.execute(
+            "INSERT INTO category_refresh (id, last_log_id) VALUES (1, ?1)
+             ON CONFLICT(id) DO UPDATE SET last_log_id = excluded.last_log_id",
+            params![newest],
+        )?;
+    }
+
+    let stale: Vec<(String, i64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT identifier, start FROM session_categories WHERE rules_version != ?1 LIMIT ?2",
+        )?;
+        let stale = stmt
+            .query_map(params![version, limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<_>>()?;
+        stale
+    };
+    {
+        let mut update = tx.prepare(
+            "UPDATE session_categories SET category = ?3, rules_version = ?4 WHERE identifier = ?1 AND start = ?2",
+        )?;
+        for (identifier, start) in &stale {
+            stats.reevaluated += update.execute(params![identifier, start, categorize(categories, identifier), version])?;
+        }
+    }
+    tx.commit()?;
+    Ok(stats)
+}
+
+pub struct CategorizedSession {
+    pub identifier: String,
+    pub start: u64,
+    pub category: String,
+    pub seconds: u64,
+}
+
+/// Sessions starting in `[since, until)`, and how many had no current assignment.
+pub fn categorized_sessions(
+    conn: &Connection,
+    categories: &CategoryConfig,
+    version: &str,
+    since: u64,
+    until: u64,
+) -> SqlResult<(Vec<CategorizedSession>, usize)> {
+    let mut stmt = conn.prepare(
+        "SELECT s.identifier, s.start, c.category, c.rules_version, s.duration, l.category
+         FROM (SELECT identifier, timestamp - duration AS start, MAX(duration) AS duration
+               FROM usage_logs
+               WHERE timestamp - duration >= ?1 AND timestamp - duration < ?2
+               GROUP BY identifier, start) s
+         LEFT JOIN session_categories c ON c.identifier = s.identifier AND c.start = s.start
+         LEFT JOIN session_labels l ON l.identifier = s.identifier AND l.start = s.start",
+    )?;
+    let mut rows = stmt.query(params![since, until])?;
+    let mut pending = 0;
+    let mut sessions = Vec::new();
+    while let Some(row) = rows.next()? {
+        let identifier: String = row.get(0)?;
+        let stored: Option<String> = row.get(2)?;
+        let stored_version: Option<String> = row.get(3)?;
+        let labeled: Option<String> = row.get(5)?;
+        let category = if labeled.is_some() {
+            labeled
+        } else if stored_version.as_deref() == Some(version) {
+            stored
+        } else {
+            pending += 1;
+            categorize(categories, &identifier).map(str::to_string)
+        };
+        sessions.push(CategorizedSession {
+            identifier,
+            start: row.get::<_, i64>(1)?.max(0) as u64,
+            category: category.unwrap_or_else(|| UNCATEGORIZED.to_string()),
+            seconds: row.get::<_, i64>(4)?.max(0) as u64,
+        });
+    }
+    Ok((sessions, pending))
+}
+
+/// Seconds per category for sessions starting in `[since, until)`, largest first.
+pub fn totals(conn: &Connection, categories: &CategoryConfig, since: u64, until: u64) -> SqlResult<CategoryTotals> {
+    let version = rules_version(categories);
+    let (sessions, pending) = categorized_sessions(conn, categories, &version, since, until)?;
+    let mut by_category: BTreeMap<String, u64> = BTreeMap::new();
+    for session in sessions {
+        *by_category.entry(session.category).or_default() += session.seconds;
+    }
+    let mut totals: Vec<CategoryTotal> =
+        by_category.into_iter().map(|(category, seconds)| CategoryTotal { category, seconds }).collect();
+    totals.sort_by_key(|total| std::cmp::Reverse(total.seconds));
+    Ok(CategoryTotals { rules_version: version, pending, categories: totals })
+}
+
+/// Seconds per local day and category, as (day, category, seconds).
+pub fn daily_totals(
+    conn: &Connection,
+    categories: &CategoryConfig,
+    since: u64,
+    until: u64,
+) -> SqlResult<Vec<(NaiveDate, String, u64)>> {
+    let (sessions, _) = categorized_sessions(conn, categories, &rules_version(categories), since, until)?;
+    let mut by_day: BTreeMap<(NaiveDate, String), u64> = BTreeMap::new();
+    for session in sessions {
+        *by_day.entry((reports::local_date(session.start), session.category)).or_default() += session.seconds;
+    }
+    Ok(by_day.into_iter().map(|((day, category), seconds)| (day, category, seconds)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, identifier};
+
+    fn db() -> Connection {
+        fixtures::empty().unwrap()
+    }
+
+    fn log(conn: &Connection, app: &str, start: u64, duration: u64) {
+        conn.execute(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?2, 'x', ?3, ?4)",
+            params![identifier::encode(app, "x", None), app, start + duration, duration],
+        )
+        .unwrap();
+    }
+
+    fn rules(app: &str) -> CategoryConfig {
+        serde_json::from_value(serde_json::json!({ "rules": [{ "category": "Dev", "app": app }] })).unwrap()
+    }
+
+    #[test]
+    fn edited_rules_are_reevaluated_in_batches() {
+        let mut conn = db();
+        log(&conn, "code.exe", 0, 60);
+        log(&conn, "code.exe", 0, 120); // Later flush of the same session
+        log(&conn, "vim", 200, 30);
+        let before = rules("code.exe");
+        assert_eq!(refresh(&mut conn, &before, 10).unwrap(), RefreshStats { assigned: 2, reevaluated: 0 });
+        assert_eq!(refresh(&mut conn, &before, 10).unwrap(), RefreshStats::default());
+
+        let totals_before = totals(&conn, &before, 0, 1000).unwrap();
+        assert_eq!(totals_before.pending, 0);
+        assert_eq!(totals_before.categories[0], CategoryTotal { category: "Dev".into(), seconds: 120 });
+
+        // Stale rows are still answered correctly before the refresh reaches them
+        let after = rules("vim");
+        assert_ne!(rules_version(&before), rules_version(&after));
+        let totals_after = totals(&conn, &after, 0, 1000).unwrap();
+        assert_eq!(totals_after.pending, 2);
+        assert_eq!(totals_after.categories[1], CategoryTotal { category: "Dev".into(), seconds: 30 });
+
+        assert_eq!(refresh(&mut conn, &after, 1).unwrap().reevaluated, 1);
+        assert_eq!(refresh(&mut conn, &after, 1).unwrap().reevaluated, 1);
+        assert_eq!(totals(&conn, &after, 0, 1000).unwrap(), CategoryTotals { pending: 0, ..totals_after });
+    }
+}
@@ -0,0 +1,432 @@
+// API tokens with scopes and optional expiry.
+//
+// Requests without a token keep the local dashboard working: they may read and
+// change the dashboard's own settings (`write`), which read-only tokens handed
+// to a Grafana or widget host may not. Any web page can make the browser send
+// such a request, so tokenless ones that change something are refused when
+// they carry an `Origin` other than the server's own. Ingest sources (watchers,
+// browser extensions, sync clients) get `ingest` tokens and cannot reach admin
+// endpoints such as token management. Only a SHA-256 of each token is stored;
+// the token itself is shown once on creation.
+
+use std::fmt;
+#[cfg(feature = "web")]
+use std::sync::Arc;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "web")]
+use warp::{
+    http::{Method, StatusCode},
+    Filter,
+};
+
+use crate::units::{DurationSecs, Timestamp};
+#[cfg(feature = "web")]
+use crate::SystemMonitor;
+
+const TOKEN_PREFIX: &str = "smt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    Write, // Dashboard settings such as panels
+    Ingest,
+    Admin,
+}
+
+impl Scope {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "read" => Some(Scope::Read),
+            "write" => Some(Scope::Write),
+            "ingest" => Some(Scope::Ingest),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Ingest => "ingest",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+pub fn parse_scopes(value: &str) -> Result<Vec<Scope>, String> {
+    value
+        .split(',')
+        .filter(|scope| !scope.trim().is_empty())
+        .map(|scope| Scope::parse(scope).ok_or_else(|| format!("unknown scope '{}'", scope.trim())))
+        .collect()
+}
+
+fn join_scopes(scopes: &[Scope]) -> String {
+    scopes.iter().map(|scope| scope.as_str()).collect::<Vec<_>>().join(",")
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: Timestamp,
+    pub expires_at: Option<Timestamp>,
+    pub last_used_at: Option<Timestamp>,
+}
+
+impl ApiToken {
+    fn allows(&self, required: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&required)
+    }
+}
+
+/// Who made a request.
+#[derive(Debug, Clone)]
+pub enum Caller {
+    Local, // No token: the dashboard or a local script
+    Token(ApiToken),
+}
+
+impl Caller {
+    pub fn allows(&self, required: Scope) -> bool {
+        match self {
+            Caller::Local => matches!(required, Scope::Read | Scope::Write),
+            Caller::Token(token) => token.allows(required),
+        }
+    }
+}
+
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Caller::Local => f.write_str("local"),
+            Caller::Token(token) => write!(f, "{} ({})", token.id, token.name),
+        }
+    }
+}
+
+#[cfg(feature = "web")]
+#[derive(Debug)]
+pub struct AuthError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+#[cfg(feature = "web")]
+impl warp::reject::Reject for AuthError {}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buffer = vec![0u8; bytes];
+    getrandom::getrandom(&mut buffer).expect("operating system random source unavailable");
+    buffer.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_tokens (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            scopes TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER,
+            last_used_at INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn token_from_row(row: &rusqlite::Row) -> SqlResult<ApiToken> {
+    Ok(ApiToken {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        scopes: parse_scopes(&row.get::<_, String>(2)?).unwrap_or_default(),
+        created_at: row.get(3)?,
+        expires_at: row.get(4)?,
+        last_used_at: row.get(5)?,
+    })
+}
+
+/// Creates a token and returns its record with the secret, which is not stored.
+pub fn create_token(conn: &Connection, name: &str, scopes: &[Scope], expires_at: Option<Timestamp>) -> SqlResult<(ApiToken, String)> {
+    let id = random_hex(4);
+    let secret = format!("{}_{}_{}", TOKEN_PREFIX, id, random_hex(32));
+    let created_at = Timestamp::now();
+    conn.execute(
+        "INSERT INTO api_tokens (id, name, token_hash, scopes, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![id, name, hash_token(&secret), join_scopes(scopes), created_at, expires_at],
+    )?;
+    let token = ApiToken {
+        id,
+        name: name.to_string(),
+        scopes: scopes.to_vec(),
+        created_at,
+        expires_at,
+        last_used_at: None,
+    };
+    Ok((token, secret))
+}
+
+pub fn list_tokens(conn: &Connection) -> SqlResult<Vec<ApiToken>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, scopes, created_at, expires_at, last_used_at FROM api_tokens ORDER BY created_at",
+    )?;
+    let tokens = stmt.query_map([], token_from_row)?.collect();
+    tokens
+}
+
+pub fn update_token(
+    conn: &Connection,
+    id: &str,
+    name: Option<&str>,
+    scopes: Option<&[Scope]>,
+    expires_at: Option<Option<Timestamp>>,
+) -> SqlResult<Option<ApiToken>> {
+    if let Some(name) = name {
+        conn.execute("UPDATE api_tokens SET name = ?1 WHERE id = ?2", params![name, id])?;
+    }
+    if let Some(scopes) = scopes {
+        conn.execute("UPDATE api_tokens SET scopes = ?1 WHERE id = ?2", params![join_scopes(scopes), id])?;
+    }
+    if let Some(expires_at) = expires_at {
+        conn.execute("UPDATE api_tokens SET expires_at = ?1 WHERE id = ?2", params![expires_at, id])?;
+    }
+    conn.query_row(
+        "SELECT id, name, scopes, created_at, expires_at, last_used_at FROM api_tokens WHERE id = ?1",
+        [id],
+        token_from_row,
+    )
+    .optional()
+}
+
+pub fn revoke_token(conn: &Connection, id: &str) -> SqlResult<bool> {
+    Ok(conn.execute("DELETE FROM api_tokens WHERE id = ?1", [id])? > 0)
+}
+
+fn find_token(conn: &Connection, secret: &str) -> Option<ApiToken> {
+    conn.query_row(
+        "SELECT id, name, scopes, created_at, expires_at, last_used_at FROM api_tokens WHERE token_hash = ?1",
+        [hash_token(secret)],
+        token_from_row,
+    )
+    .optional()
+    .ok()
+    .flatten()
+}
+
+/// Token a request claims to be from, without checking scopes or expiry, for
+/// attributing requests that may have been refused.
+pub fn identify(conn: &Connection, header: Option<&str>) -> Option<ApiToken> {
+    let secret = header?.strip_prefix("Bearer ")?.trim();
+    find_token(conn, secret)
+}
+
+#[cfg(feature = "web")]
+fn authenticate(db_path: &str, header: Option<&str>) -> Result<Caller, AuthError> {
+    let Some(header) = header else {
+        return Ok(Caller::Local);
+    };
+    let unauthorized = |message: &str| AuthError { status: StatusCode::UNAUTHORIZED, message: message.to_string() };
+    let secret = header
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .ok_or_else(|| unauthorized("Authorization header must be `Bearer <token>`"))?;
+
+    let conn = Connection::open(db_path).map_err(|e| AuthError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        message: format!("Token lookup failed: {}", e),
+    })?;
+    let token = find_token(&conn, secret).ok_or_else(|| unauthorized("Unknown API token"))?;
+
+    let current_time = Timestamp::now();
+    if token.expires_at.is_some_and(|expires_at| expires_at <= current_time) {
+        return Err(unauthorized("API token has expired"));
+    }
+    // Usage tracking is best effort; a failed write must not fail the request
+    let _ = conn.execute("UPDATE api_tokens SET last_used_at = ?1 WHERE id = ?2", params![current_time, token.id]);
+    Ok(Caller::Token(token))
+}
+
+// Whether a tokenless request may change something: browsers send `Origin` with
+// every cross-site POST, scripts and curl don't send one at all
+#[cfg(feature = "web")]
+fn same_origin(origin: Option<&str>, host: Option<&str>) -> bool {
+    let Some(origin) = origin else {
+        return true;
+    };
+    let origin_host = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://"));
+    origin_host.is_some_and(|origin_host| host.is_some_and(|host| origin_host.eq_ignore_ascii_case(host)))
+}
+
+/// Resolves the caller and rejects it unless it holds `scope`.
+#[cfg(feature = "web")]
+pub fn require(
+    scope: Scope,
+    monitor: Arc<SystemMonitor>,
+) -> impl Filter<Extract = (Caller,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::method())
+        .and(warp::header::optional::<String>("origin"))
+        .and(warp::header::optional::<String>("host"))
+        .and_then(move |header: Option<String>, method: Method, origin: Option<String>, host: Option<String>| {
+            let monitor = monitor.clone();
+            async move {
+                if header.is_none() && monitor.require_token {
+                    return Err(warp::reject::custom(AuthError {
+                        status: StatusCode::UNAUTHORIZED,
+                        message: "This server only accepts requests with an API token".to_string(),
+                    }));
+                }
+                let caller = authenticate(&monitor.db_path, header.as_deref()).map_err(warp::reject::custom)?;
+                let changes = !matches!(method, Method::GET | Method::HEAD | Method::OPTIONS);
+                if matches!(caller, Caller::Local) && changes && !same_origin(origin.as_deref(), host.as_deref()) {
+                    return Err(warp::reject::custom(AuthError {
+                        status: StatusCode::FORBIDDEN,
+                        message: "Requests from other sites need an API token".to_string(),
+                    }));
+                }
+                if !caller.allows(scope) {
+                    return Err(warp::reject::custom(AuthError {
+                        status: if matches!(caller, Caller::Local) { StatusCode::UNAUTHORIZED } else { StatusCode::FORBIDDEN },
+                        message: format!("This endpoint requires the '{}' scope", scope.as_str()),
+                    }));
+                }
+                Ok(caller)
+            }
+        })
+}
+
+/// Like `require`, for handlers that do not need to know the caller.
+#[cfg(feature = "web")]
+pub fn guard(scope: Scope, monitor: Arc<SystemMonitor>) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    require(scope, monitor).map(|_| ()).untuple_one()
+}
+
+fn format_time(timestamp: Option<Timestamp>) -> String {
+    timestamp
+        .and_then(|at| chrono::DateTime::from_timestamp(at.as_secs() as i64, 0))
+        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// `sysmonitor token create <name> --scopes read,ingest [--expires-days N]`,
+/// `sysmonitor token list` and `sysmonitor token revoke <id>`.
+pub fn run_command(conn: &Connection, args: &[String]) -> Result<(), String> {
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+            .map(String::as_str)
+    };
+    match args.first().map(String::as_str) {
+        Some("create") => {
+            let name = args.get(1).filter(|name| !name.starts_with("--")).ok_or("usage: token create <name> --scopes <list>")?;
+            let scopes = parse_scopes(option("--scopes").ok_or("--scopes is required (read, write, ingest, admin)")?)?;
+            let expires_at = match option("--expires-days") {
+                Some(days) => Some(Timestamp::now() + DurationSecs::days(days.parse().map_err(|_| "--expires-days must be a number")?)),
+                None => None,
+            };
+            let (token, secret) = create_token(conn, name, &scopes, expires_at).map_err(|e| e.to_string())?;
+            println!("Created token {} ({}) with scopes {}", token.id, token.name, join_scopes(&token.scopes));
+            println!("{}", secret);
+            println!("This is the only time the token is shown.");
+            Ok(())
+        }
+        Some("list") => {
+            let tokens = list_tokens(conn).map_err(|e| e.to_string())?;
+            if tokens.is_empty() {
+                println!("No API tokens");
+            }
+            for token in tokens {
+                println!(
+                    "{}  {:<20} {:<18} expires {}  last used {}",
+                    token.id,
+                    token.name,
+                    join_scopes(&token.scopes),
+                    format_time(token.expires_at),
+                    format_time(token.last_used_at)
+                );
+            }
+            Ok(())
+        }
+        Some("revoke") => {
+            let id = args.get(1).ok_or("usage: token revoke <id>")?;
+            if revoke_token(conn, id).map_err(|e| e.to_string())? {
+                println!("Revoked token {}", id);
+                Ok(())
+            } else {
+                Err(format!("no token with id {}", id))
+            }
+        }
+        _ => Err("usage: token create|list|revoke".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "web")]
+    use warp::http::StatusCode;
+
+    #[test]
+    fn admin_implies_every_scope() {
+        let token = |scopes: Vec<Scope>| ApiToken {
+            id: "id".into(),
+            name: "n".into(),
+            scopes,
+            created_at: Timestamp::EPOCH,
+            expires_at: None,
+            last_used_at: None,
+        };
+        assert!(Caller::Token(token(vec![Scope::Admin])).allows(Scope::Ingest));
+        assert!(!Caller::Token(token(vec![Scope::Ingest])).allows(Scope::Admin));
+        assert!(!Caller::Token(token(vec![Scope::Ingest])).allows(Scope::Read));
+        assert!(Caller::Local.allows(Scope::Read) && !Caller::Local.allows(Scope::Ingest));
+        assert!(Caller::Local.allows(Scope::Write) && !Caller::Token(token(vec![Scope::Read])).allows(Scope::Write));
+    }
+
+    #[test]
+    #[cfg(feature = "web")]
+    fn tokens_are_checked_by_hash_and_expiry() {
+        let dir = std::env::temp_dir().join(format!("sysmonitor-auth-{}", random_hex(4)));
+        let db_path = dir.to_string_lossy().to_string();
+        let conn = Connection::open(&db_path).unwrap();
+        init_tables(&conn).unwrap();
+
+        let (_, secret) = create_token(&conn, "extension", &[Scope::Ingest], None).unwrap();
+        let (_, expired) = create_token(&conn, "old", &[Scope::Read], Some(Timestamp::from_secs(1))).unwrap();
+        let header = |secret: &str| format!("Bearer {}", secret);
+
+        assert!(matches!(authenticate(&db_path, Some(&header(&secret))), Ok(Caller::Token(token)) if token.name == "extension"));
+        assert_eq!(authenticate(&db_path, Some(&header(&expired))).unwrap_err().status, StatusCode::UNAUTHORIZED);
+        assert!(authenticate(&db_path, Some("Bearer smt_nope")).is_err());
+        assert!(matches!(authenticate(&db_path, None), Ok(Caller::Local)));
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    #[cfg(feature = "web")]
+    fn other_sites_cannot_change_anything_without_a_token() {
+        assert!(same_origin(None, Some("localhost:3030")));
+        assert!(same_origin(Some("http://localhost:3030"), Some("localhost:3030")));
+        assert!(!same_origin(Some("https://evil.example"), Some("localhost:3030")));
+        assert!(!same_origin(Some("null"), Some("localhost:3030")));
+        assert!(!same_origin(Some("http://localhost:3030"), None));
+    }
+
+    #[test]
+    fn scopes_parse_from_lists() {
+        assert_eq!(parse_scopes("read, ingest"), Ok(vec![Scope::Read, Scope::Ingest]));
+        assert!(parse_scopes("read,purge").is_err());
+    }
+}
@@ -0,0 +1,55 @@
+// Timings of the refreshes and queries the dashboard leans on, over a year of
+// fixture data. Ignored by default; run them with
+// `cargo test --release bench -- --ignored --nocapture --test-threads 1`
+// and compare the printed times before and after a change.
+
+use std::time::Instant;
+
+use chrono::{Days, NaiveDate};
+
+use crate::categories::CategoryConfig;
+use crate::fixtures::{self, Shape};
+use crate::units::{DurationSecs, Timestamp};
+use crate::{assignments, daily_summary, grafana, reports, rollups, sessions, timeline};
+
+fn time<T>(name: &str, job: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = job();
+    println!("{:<32} {:>8.1} ms", name, started.elapsed().as_secs_f64() * 1000.0);
+    result
+}
+
+fn categories() -> CategoryConfig {
+    serde_json::from_value(serde_json::json!({ "rules": [
+        { "category": "Dev > Editor", "app": "app001.exe" },
+        { "category": "Dev > Terminal", "app": "app002.exe" },
+        { "category": "Web", "app": "app003.exe" },
+        { "category": "Chat", "app": "app004.exe" },
+    ] }))
+    .unwrap()
+}
+
+#[test]
+#[ignore]
+fn bench_a_year_of_two_hundred_apps() {
+    let today = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+    let now = Timestamp::from_secs(reports::day_bounds(today).0 as u64) + DurationSecs::hours(18);
+    let shape = Shape { days: 365, apps: Some(200), midnight_spans: true, ..Shape::default() };
+    let mut conn = time("generate", || fixtures::database(&shape, now.as_secs()).unwrap());
+    let categories = categories();
+
+    let days = time("rollups::refresh", || rollups::refresh(&mut conn, today).unwrap());
+    assert!(days > 300);
+    time("assignments::refresh", || assignments::refresh(&mut conn, &categories, usize::MAX).unwrap());
+    time("daily_summary::refresh", || daily_summary::refresh(&mut conn, &categories, now).unwrap());
+    time("grafana::refresh", || grafana::refresh(&mut conn, &categories, now.as_secs()).unwrap());
+
+    let week = now - DurationSecs::days(7);
+    let clipped = time("sessions::clipped (week)", || sessions::clipped(&conn, week, now).unwrap());
+    assert!(!clipped.is_empty());
+    time("assignments::totals (month)", || assignments::totals(&conn, &categories, (now - DurationSecs::days(30)).as_secs(), now.as_secs()).unwrap());
+    time("rollups::load (year)", || rollups::load(&conn, today - Days::new(365), today).unwrap());
+    for zoom in 0..timeline::LEVELS.len() {
+        time(&format!("timeline::window (zoom {})", zoom), || timeline::window(&conn, now.as_secs(), zoom).unwrap());
+    }
+}
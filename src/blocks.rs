@@ -0,0 +1,168 @@
+// "Work blocks": runs of sessions separated by short gaps, so reports can
+// show a two-hour coding stretch instead of hundreds of editor/terminal/browser
+// micro-sessions.
+//
+// Blocks are persisted in `work_blocks` and refreshed incrementally: every
+// block before the newest is final (a longer gap followed it), so only the
+// newest one is rebuilt together with anything that started after it.
+
+use std::collections::BTreeMap;
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::sessions::{self, Session};
+use crate::units::{DurationSecs, Timestamp};
+
+pub const MAX_GAP: DurationSecs = DurationSecs::mins(5); // Longer breaks end a block
+const TOP_APPS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppShare {
+    pub app_name: String,
+    pub duration: DurationSecs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkBlock {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub active_secs: DurationSecs, // Focused time; gaps inside the block are excluded
+    pub session_count: u64,
+    pub app_switches: u64,
+    pub top_apps: Vec<AppShare>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BlockQuery {
+    pub since: Option<Timestamp>,
+    pub until: Option<Timestamp>,
+}
+
+fn block_from(sessions: &[Session]) -> WorkBlock {
+    let mut per_app: BTreeMap<&str, DurationSecs> = BTreeMap::new();
+    for session in sessions {
+        *per_app.entry(&session.app_name).or_default() += session.duration();
+    }
+    let mut top_apps: Vec<AppShare> = per_app
+        .into_iter()
+        .map(|(app_name, duration)| AppShare { app_name: app_name.to_string(), duration })
+        .collect();
+    top_apps.sort_by(|a, b| b.duration.cmp(&a.duration).then_with(|| a.app_name.cmp(&b.app_name)));
+    top_apps.truncate(TOP_APPS);
+
+    WorkBlock {
+        start: sessions[0].start,
+        end: sessions.iter().map(|session| session.end).max().unwrap_or(sessions[0].end),
+        active_secs: sessions.iter().map(Session::duration).sum(),
+        session_count: sessions.len() as u64,
+        app_switches: sessions.windows(2).filter(|pair| pair[0].app_name != pair[1].app_name).count() as u64,
+        top_apps,
+    }
+}
+
+/// Groups start-ordered sessions into blocks wherever the gap after the block
+/// so far exceeds `max_gap`.
+pub fn cluster(sessions: &[Session], max_gap: DurationSecs) -> Vec<WorkBlock> {
+    let mut blocks = Vec::new();
+    let mut block_start = 0;
+    let mut block_end = Timestamp::EPOCH;
+    for (index, session) in sessions.iter().enumerate() {
+        if index > block_start && session.start > block_end + max_gap {
+            blocks.push(block_from(&sessions[block_start..index]));
+            block_start = index;
+        }
+        block_end = if index == block_start { session.end } else { block_end.max(session.end) };
+    }
+    if block_start < sessions.len() {
+        blocks.push(block_from(&sessions[block_start..]));
+    }
+    blocks
+}
+
+/// Rebuilds the newest stored block and everything after it.
+pub fn refresh(conn: &Connection, now: Timestamp) -> SqlResult<usize> {
+    let resume_from = conn
+        .query_row("SELECT MAX(start_time) FROM work_blocks", [], |row| row.get::<_, Option<Timestamp>>(0))?
+        .unwrap_or_default();
+
+    let sessions = sessions::load_sessions(conn, resume_from, now + DurationSecs::secs(1))?;
+    let blocks = cluster(&sessions, MAX_GAP);
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM work_blocks WHERE start_time >= ?1", params![resume_from])?;
+    for block in &blocks {
+        tx.execute(
+            "INSERT INTO work_blocks (start_time, end_time, active_secs, session_count, app_switches, top_apps)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                block.start,
+                block.end,
+                block.active_secs,
+                block.session_count,
+                block.app_switches,
+                serde_json::to_string(&block.top_apps).unwrap_or_default()
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(blocks.len())
+}
+
+/// Stored blocks overlapping `[since, until)`.
+pub fn load_blocks(conn: &Connection, since: Timestamp, until: Timestamp) -> SqlResult<Vec<WorkBlock>> {
+    let mut stmt = conn.prepare(
+        "SELECT start_time, end_time, active_secs, session_count, app_switches, top_apps
+         FROM work_blocks
+         WHERE end_time >= ?1 AND start_time < ?2
+         ORDER BY start_time",
+    )?;
+    let blocks = stmt
+        .query_map(params![since, until], |row| {
+            Ok(WorkBlock {
+                start: row.get(0)?,
+                end: row.get(1)?,
+                active_secs: row.get(2)?,
+                session_count: row.get::<_, i64>(3)? as u64,
+                app_switches: row.get::<_, i64>(4)? as u64,
+                top_apps: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or_default(),
+            })
+        })?
+        .collect();
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(app: &str, start: u64, end: u64) -> Session {
+        Session { identifier: format!("{}:t:x", app), app_name: app.to_string(), start: Timestamp::from_secs(start), end: Timestamp::from_secs(end) }
+    }
+
+    fn span(block: &WorkBlock) -> (u64, u64) {
+        (block.start.as_secs(), block.end.as_secs())
+    }
+
+    #[test]
+    fn short_gaps_stay_in_one_block() {
+        let sessions = [
+            session("code.exe", 0, 600),
+            session("wt.exe", 660, 900),
+            session("code.exe", 1000, 2000),
+            // 20 minute break
+            session("slack.exe", 3200, 3300),
+        ];
+        let blocks = cluster(&sessions, MAX_GAP);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((span(&blocks[0]), blocks[0].active_secs), ((0, 2000), DurationSecs::secs(1840)));
+        assert_eq!((blocks[0].session_count, blocks[0].app_switches), (3, 2));
+        assert_eq!(blocks[0].top_apps[0], AppShare { app_name: "code.exe".to_string(), duration: DurationSecs::secs(1600) });
+        assert_eq!(span(&blocks[1]), (3200, 3300));
+    }
+
+    #[test]
+    fn no_sessions_no_blocks() {
+        assert!(cluster(&[], MAX_GAP).is_empty());
+    }
+}
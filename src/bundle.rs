@@ -0,0 +1,366 @@
+// Evidence bundles: everything recorded over a range of local days, in one
+// signed and checksummed tar.
+//
+// `export bundle --from YYYY-MM-DD --to YYYY-MM-DD` writes
+// `sysmonitor-bundle-<from>-<to>/` with one JSON Lines file per data class
+// (sessions, including archived ones; full titles; pauses; runtime metrics;
+// the audit log), a `SHA256SUMS` that `sha256sum -c` accepts, a
+// `manifest.json` naming the range, host and each file's rows and hash, and
+// `manifest.json.sig`, its HMAC under `SYSMONITOR_SIGNING_KEY`. Changing any
+// file breaks its hash; changing the manifest to match breaks the signature.
+// `export verify <file>` checks both.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{self, ArchiveRow};
+#[cfg(feature = "web")]
+use crate::audit::AuditEntry;
+use crate::signing::{self, SigningKey, KEY_ENV};
+use crate::units::Timestamp;
+use crate::{pauses, reports, vault};
+
+pub const FORMAT: &str = "sysmonitor-bundle/1";
+const MANIFEST: &str = "manifest.json";
+const SIGNATURE: &str = "manifest.json.sig";
+const SUMS: &str = "SHA256SUMS";
+const BLOCK: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleFile {
+    pub name: String,
+    pub rows: usize,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub from: String, // Local days, inclusive
+    pub to: String,
+    pub range_start: Timestamp, // `[range_start, range_end)`
+    pub range_end: Timestamp,
+    pub created_at: Timestamp,
+    pub host: String,
+    pub version: String,
+    pub key_id: String, // `SigningKey::id` of the key that signed it
+    pub files: Vec<BundleFile>,
+}
+
+#[derive(Debug, Serialize)]
+struct TitleRow {
+    identifier: String,
+    full_title: String,
+    first_seen: u64,
+    last_seen: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct RuntimeRow {
+    started_at: u64,
+    ended_at: u64,
+    stats: serde_json::Value,
+}
+
+struct DataFile {
+    name: &'static str,
+    contents: Vec<u8>,
+    rows: usize,
+}
+
+fn jsonl<T: Serialize>(name: &'static str, rows: &[T]) -> Result<DataFile, String> {
+    let mut contents = Vec::new();
+    for row in rows {
+        serde_json::to_writer(&mut contents, row).map_err(|e| e.to_string())?;
+        contents.push(b'\n');
+    }
+    Ok(DataFile { name, contents, rows: rows.len() })
+}
+
+// Sessions starting in the range, from the database and the archive
+fn sessions(conn: &Connection, archive_dir: &Path, start: Timestamp, end: Timestamp) -> Result<Vec<ArchiveRow>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT identifier, app_name, window_title, url, timestamp, duration, partial, source, clock_skew
+             FROM usage_logs
+             WHERE timestamp - duration >= ?1 AND timestamp - duration < ?2
+             ORDER BY timestamp, id",
+        )
+        .map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query_map(params![start, end], |row| {
+            Ok(ArchiveRow {
+                identifier: row.get(0)?,
+                app_name: row.get(1)?,
+                window_title: row.get(2)?,
+                url: row.get(3)?,
+                timestamp: row.get(4)?,
+                duration: row.get(5)?,
+                partial: row.get(6)?,
+                source: row.get(7)?,
+                clock_skew: row.get(8)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    rows.extend(archive::load_rows(archive_dir, start, end)?);
+    rows.sort_by_key(|row| (row.timestamp.saturating_sub(row.duration), row.identifier.clone()));
+    Ok(rows)
+}
+
+// The data files of a range, in bundle order
+fn collect(conn: &Connection, archive_dir: &Path, start: Timestamp, end: Timestamp) -> Result<Vec<DataFile>, String> {
+    let sql = |e: rusqlite::Error| e.to_string();
+    let titles: Vec<TitleRow> = conn
+        .prepare(
+            "SELECT identifier, full_title, first_seen, last_seen FROM title_history
+             WHERE last_seen >= ?1 AND first_seen < ?2 ORDER BY first_seen, id",
+        )
+        .map_err(sql)?
+        .query_map(params![start, end], |row| {
+            Ok(TitleRow { identifier: row.get(0)?, full_title: row.get(1)?, first_seen: row.get(2)?, last_seen: row.get(3)? })
+        })
+        .map_err(sql)?
+        .collect::<Result<_, _>>()
+        .map_err(sql)?;
+    let metrics: Vec<RuntimeRow> = conn
+        .prepare("SELECT started_at, ended_at, stats FROM runtime_stats WHERE ended_at >= ?1 AND started_at < ?2 ORDER BY started_at, id")
+        .map_err(sql)?
+        .query_map(params![start, end], |row| {
+            let stats: String = row.get(2)?;
+            Ok(RuntimeRow {
+                started_at: row.get(0)?,
+                ended_at: row.get(1)?,
+                stats: serde_json::from_str(&stats).unwrap_or(serde_json::Value::String(stats)),
+            })
+        })
+        .map_err(sql)?
+        .collect::<Result<_, _>>()
+        .map_err(sql)?;
+    Ok(vec![
+        jsonl("sessions.jsonl", &sessions(conn, archive_dir, start, end)?)?,
+        jsonl("titles.jsonl", &titles)?,
+        jsonl("pauses.jsonl", &pauses::list(conn, start, end).map_err(sql)?)?,
+        jsonl("metrics.jsonl", &metrics)?,
+        jsonl("audit.jsonl", &audit(conn, start, end).map_err(sql)?)?,
+    ])
+}
+
+// Only builds with `web` keep an audit log; the others bundle an empty one
+#[cfg(feature = "web")]
+fn audit(conn: &Connection, start: Timestamp, end: Timestamp) -> rusqlite::Result<Vec<AuditEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, token_id, caller, method, path, status, payload FROM audit_log
+         WHERE timestamp >= ?1 AND timestamp < ?2 ORDER BY timestamp, id",
+    )?;
+    let rows = stmt.query_map(params![start, end], |row| {
+        Ok(AuditEntry {
+            timestamp: row.get(0)?,
+            token_id: row.get(1)?,
+            caller: row.get(2)?,
+            method: row.get(3)?,
+            path: row.get(4)?,
+            status: row.get(5)?,
+            payload: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+#[cfg(not(feature = "web"))]
+fn audit(_: &Connection, _: Timestamp, _: Timestamp) -> rusqlite::Result<Vec<serde_json::Value>> {
+    Ok(Vec::new())
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+// One ustar entry: header block, then the contents padded to whole blocks
+fn tar_entry(tar: &mut Vec<u8>, name: &str, contents: &[u8], mtime: u64) {
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], contents.len() as u64);
+    octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // The checksum is taken with its own field as spaces
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|&byte| u64::from(byte)).sum();
+    octal(&mut header[148..155], checksum);
+    header[154] = 0;
+    tar.extend_from_slice(&header);
+    tar.extend_from_slice(contents);
+    tar.resize(tar.len().div_ceil(BLOCK) * BLOCK, 0);
+}
+
+// Regular files of a tar by base name
+fn read_tar(tar: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let broken = || "not a bundle: the tar is truncated or damaged".to_string();
+    let mut files = BTreeMap::new();
+    let mut offset = 0;
+    while offset + BLOCK <= tar.len() {
+        let header = &tar[offset..offset + BLOCK];
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(files);
+        }
+        let field = |range: std::ops::Range<usize>| {
+            let raw = &header[range];
+            String::from_utf8_lossy(&raw[..raw.iter().position(|&byte| byte == 0).unwrap_or(raw.len())]).trim().to_string()
+        };
+        let size = usize::from_str_radix(&field(124..136), 8).map_err(|_| broken())?;
+        let start = offset + BLOCK;
+        let contents = tar.get(start..start + size).ok_or_else(broken)?;
+        if matches!(header[156], b'0' | 0) {
+            let name = field(0..100);
+            files.insert(name.rsplit('/').next().unwrap_or_default().to_string(), contents.to_vec());
+        }
+        offset = start + size.div_ceil(BLOCK) * BLOCK;
+    }
+    Err(broken())
+}
+
+/// The bundle of the local days `from..=to`, signed with `key`.
+pub fn build(conn: &Connection, archive_dir: &Path, from: NaiveDate, to: NaiveDate, key: &SigningKey, now: Timestamp) -> Result<Vec<u8>, String> {
+    if to < from {
+        return Err("--to is before --from".to_string());
+    }
+    let bounds = |bound: i64| Timestamp::from_secs(bound.max(0) as u64);
+    let (start, end) = (bounds(reports::day_bounds(from).0), bounds(reports::day_bounds(to).1));
+    let data = collect(conn, archive_dir, start, end)?;
+
+    let files: Vec<BundleFile> = data
+        .iter()
+        .map(|file| BundleFile { name: file.name.to_string(), rows: file.rows, sha256: signing::sha256_hex(&file.contents) })
+        .collect();
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        from: from.to_string(),
+        to: to.to_string(),
+        range_start: start,
+        range_end: end,
+        created_at: now,
+        host: vault::default_device(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        key_id: key.id(),
+        files,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    let mut sums: String = manifest.files.iter().map(|file| format!("{}  {}\n", file.sha256, file.name)).collect();
+    sums.push_str(&format!("{}  {}\n", signing::sha256_hex(&manifest_json), MANIFEST));
+
+    let dir = format!("sysmonitor-bundle-{}-{}/", from, to);
+    let mut tar = Vec::new();
+    for file in &data {
+        tar_entry(&mut tar, &format!("{}{}", dir, file.name), &file.contents, now.as_secs());
+    }
+    tar_entry(&mut tar, &format!("{}{}", dir, SUMS), sums.as_bytes(), now.as_secs());
+    tar_entry(&mut tar, &format!("{}{}", dir, MANIFEST), &manifest_json, now.as_secs());
+    tar_entry(&mut tar, &format!("{}{}", dir, SIGNATURE), format!("{}\n", key.sign(&manifest_json)).as_bytes(), now.as_secs());
+    tar.resize(tar.len() + 2 * BLOCK, 0);
+    Ok(tar)
+}
+
+/// Checks a bundle's signature and every file's hash; its manifest if all hold.
+pub fn verify(tar: &[u8], key: &SigningKey) -> Result<Manifest, String> {
+    let files = read_tar(tar)?;
+    let manifest_json = files.get(MANIFEST).ok_or("not a bundle: it has no manifest.json")?;
+    let signature = files.get(SIGNATURE).ok_or("the bundle is not signed")?;
+    if !key.verify(manifest_json, &String::from_utf8_lossy(signature)) {
+        return Err("the manifest signature does not match: it was changed, or signed with another key".to_string());
+    }
+    let manifest: Manifest = serde_json::from_slice(manifest_json).map_err(|e| format!("manifest.json is invalid: {}", e))?;
+    for file in &manifest.files {
+        let contents = files.get(&file.name).ok_or_else(|| format!("{} is missing", file.name))?;
+        if signing::sha256_hex(contents) != file.sha256 {
+            return Err(format!("{} was changed since the bundle was made", file.name));
+        }
+    }
+    Ok(manifest)
+}
+
+/// `sysmonitor export keygen|bundle|verify`.
+pub fn run_command(conn: &Connection, archive_dir: &Path, args: &[String], now: Timestamp) -> Result<(), String> {
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+            .map(String::as_str)
+    };
+    let date = |name: &str| {
+        let value = option(name).ok_or_else(|| format!("{} YYYY-MM-DD is required", name))?;
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("{} must be YYYY-MM-DD, got '{}'", name, value))
+    };
+    match args.first().map(String::as_str) {
+        Some("keygen") => {
+            println!("{}={}", KEY_ENV, SigningKey::generate().to_hex());
+            println!("Keep it safe; whoever checks a bundle needs the same key.");
+            Ok(())
+        }
+        Some("bundle") => {
+            let key = SigningKey::from_env()?;
+            let (from, to) = (date("--from")?, date("--to")?);
+            let tar = build(conn, archive_dir, from, to, &key, now)?;
+            let out = option("--out").map_or_else(|| format!("sysmonitor-bundle-{}-{}.tar", from, to), str::to_string);
+            std::fs::write(&out, &tar).map_err(|e| format!("cannot write {}: {}", out, e))?;
+            let manifest = verify(&tar, &key)?;
+            for file in &manifest.files {
+                println!("{}: {} rows", file.name, file.rows);
+            }
+            println!("Wrote {} (key {})", out, manifest.key_id);
+            Ok(())
+        }
+        Some("verify") => {
+            let key = SigningKey::from_env()?;
+            let path = args.get(1).ok_or("usage: export verify <bundle.tar>")?;
+            let tar = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+            let manifest = verify(&tar, &key)?;
+            println!("OK: {} to {} from {}, signed with key {}", manifest.from, manifest.to, manifest.host, manifest.key_id);
+            Ok(())
+        }
+        _ => Err("usage: export keygen | bundle --from YYYY-MM-DD --to YYYY-MM-DD [--out <file>] | verify <file>".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn bundles_verify_and_catch_tampering() {
+        let conn = fixtures::empty().unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let (start, _) = reports::day_bounds(day);
+        for (offset, title) in [(3600, "Invoice 12"), (-3600, "Yesterday")] {
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('code.exe', 'code.exe', ?1, ?2, 600)",
+                params![title, start + offset + 600],
+            )
+            .unwrap();
+        }
+
+        let key = SigningKey::generate();
+        let tar = build(&conn, Path::new("no-archive"), day, day, &key, Timestamp::from_secs(1_800_000_000)).unwrap();
+        let manifest = verify(&tar, &key).unwrap();
+        let rows: Vec<(&str, usize)> = manifest.files.iter().map(|file| (file.name.as_str(), file.rows)).collect();
+        assert_eq!(rows[..2], [("sessions.jsonl", 1), ("titles.jsonl", 0)]);
+        assert!(verify(&tar, &SigningKey::generate()).unwrap_err().contains("signature"));
+
+        // Any change to a data file shows, even one that keeps its length
+        let at = tar.windows(10).position(|window| window == b"Invoice 12").unwrap();
+        let mut tampered = tar.clone();
+        tampered[at + 9] = b'9';
+        assert_eq!(verify(&tampered, &key).unwrap_err(), "sessions.jsonl was changed since the bundle was made");
+    }
+}
@@ -0,0 +1,146 @@
+// What this build can actually do where it runs, for `/api/capabilities` and
+// the log at startup.
+//
+// Several features need more than the build: reading the foreground window
+// needs an interactive desktop (and xdotool under X11), toasts need PowerShell
+// or notify-send, idle and sleep detection need xprintidle and gdbus outside
+// Windows, and elevated apps only give their full process details to an
+// elevated sysmonitor. Each is probed once, the first time it is asked for,
+// so the dashboard can hide what cannot work and say what runs degraded.
+// Media sessions and event hooks are not part of this build at all; they are
+// listed too, so frontends need not guess.
+
+use std::process::{Command, Stdio};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Capability {
+    pub id: &'static str,
+    pub available: bool,
+    pub detail: String,
+    pub without: Option<&'static str>, // What degrades, when unavailable
+}
+
+impl Capability {
+    fn available(id: &'static str, detail: impl Into<String>) -> Self {
+        Self { id, available: true, detail: detail.into(), without: None }
+    }
+
+    fn missing(id: &'static str, detail: impl Into<String>, without: &'static str) -> Self {
+        Self { id, available: false, detail: detail.into(), without: Some(without) }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Probes {
+    pub foreground_app: Option<String>, // None when no foreground window could be read
+    pub browser_integration: bool,
+    pub notifications: bool,
+    pub notifier: bool, // PowerShell or notify-send found
+    pub idle: bool,
+    pub power: bool,
+    pub elevated: bool,
+}
+
+impl Probes {
+    pub fn run(foreground_app: Option<String>) -> Self {
+        Self {
+            foreground_app,
+            browser_integration: cfg!(feature = "browser-integration"),
+            notifications: cfg!(feature = "notifications"),
+            notifier: installed(if cfg!(windows) { "powershell" } else { "notify-send" }),
+            idle: crate::idle::idle_secs().is_some(),
+            power: cfg!(windows) || installed("gdbus"),
+            elevated: elevated(),
+        }
+    }
+}
+
+// Whether `program` can be started at all; its answer doesn't matter
+fn installed(program: &str) -> bool {
+    Command::new(program).arg("--version").stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok()
+}
+
+#[cfg(windows)]
+fn elevated() -> bool {
+    unsafe { windows::Win32::UI::Shell::IsUserAnAdmin() }.as_bool()
+}
+
+#[cfg(not(windows))]
+fn elevated() -> bool {
+    // `Uid:` lists the real, effective, saved and filesystem ids
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| status.lines().find_map(|line| line.strip_prefix("Uid:")).and_then(|ids| ids.split_whitespace().nth(1).map(|id| id == "0")))
+        .unwrap_or(false)
+}
+
+pub fn detect(probes: &Probes) -> Vec<Capability> {
+    vec![
+        match &probes.foreground_app {
+            Some(app) => Capability::available("window_tracking", format!("foreground app is {}", app)),
+            None if cfg!(windows) => Capability::missing("window_tracking", "no foreground window could be read; sysmonitor may run as a service", "nothing is tracked"),
+            None => Capability::missing("window_tracking", "no foreground window could be read; xdotool and an X11 session are needed", "nothing is tracked"),
+        },
+        if probes.browser_integration {
+            Capability::available("url_capture", "read from browser window titles; UI Automation is not used")
+        } else {
+            Capability::missing(
+                "url_capture",
+                "built without the `browser-integration` feature",
+                "sessions are stored without URLs, so domain rules and site icons have nothing to go on",
+            )
+        },
+        match (probes.notifications, probes.notifier) {
+            (false, _) => Capability::missing("notifications", "built without the `notifications` feature", "goal, budget and error alerts are not sent"),
+            (true, false) if cfg!(windows) => {
+                Capability::missing("notifications", "PowerShell was not found", "desktop toasts are not shown; webhook, Slack, email and MQTT channels still work")
+            }
+            (true, false) => {
+                Capability::missing("notifications", "notify-send was not found", "desktop toasts are not shown; webhook, Slack, email and MQTT channels still work")
+            }
+            (true, true) => Capability::available("notifications", "desktop toasts and the other channels"),
+        },
+        if probes.idle {
+            Capability::available("idle_detection", "time since the last input is readable")
+        } else {
+            Capability::missing("idle_detection", "xprintidle was not found or has no display", "time away from the machine counts toward the app in front")
+        },
+        if probes.power {
+            Capability::available("sleep_detection", "suspend and resume are reported")
+        } else {
+            Capability::missing("sleep_detection", "gdbus was not found", "a session open at a suspend only ends when its heartbeats are found missing")
+        },
+        if probes.elevated {
+            Capability::available("admin_rights", "running elevated")
+        } else {
+            Capability::missing("admin_rights", "not running elevated", "windows of elevated apps are stored with limited process details")
+        },
+        Capability::missing("media_session", "not part of this build", "what plays in the background is not tracked"),
+        Capability::missing("event_hooks", "not part of this build; the foreground window is polled", "focus changes are seen at the next poll, up to `poll_interval_ms` late"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_capabilities_say_what_degrades() {
+        let probes = Probes { foreground_app: Some("code.exe".into()), browser_integration: true, notifications: true, idle: true, ..Probes::default() };
+        let capabilities = detect(&probes);
+        let find = |id: &str| capabilities.iter().find(|capability| capability.id == id).unwrap();
+        assert!(find("window_tracking").available && find("url_capture").available && find("idle_detection").available);
+        assert_eq!(find("window_tracking").detail, "foreground app is code.exe");
+
+        let toasts = find("notifications");
+        assert!(!toasts.available);
+        assert!(toasts.without.unwrap().contains("webhook"));
+        assert!(capabilities.iter().all(|capability| capability.available == capability.without.is_none()));
+
+        let bare = detect(&Probes::default());
+        assert!(bare.iter().all(|capability| !capability.available));
+        assert_eq!(bare.iter().find(|capability| capability.id == "notifications").unwrap().detail, "built without the `notifications` feature");
+    }
+}
@@ -0,0 +1,167 @@
+// Categories for activities, from the `categories` section of the config.
+//
+// Rules are tried in order and the first match names the category; each
+// category can be marked productive or distracting for insights. Activities no
+// rule matches are uncategorized and neutral.
+//
+// Categories form a tree through their names: "Dev > Code Review" is a child
+// of "Dev", and projects are a branch like "Projects > Acme > Website". A
+// child without a productivity of its own takes its nearest ancestor's, and
+// goals and totals on a category include its descendants.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::ActivityKey;
+
+pub const UNCATEGORIZED: &str = "Uncategorized";
+pub const SEPARATOR: char = '>';
+
+/// The parts of a category path, e.g. `["Dev", "Code Review"]`.
+pub fn path(category: &str) -> Vec<&str> {
+    category.split(SEPARATOR).map(str::trim).filter(|part| !part.is_empty()).collect()
+}
+
+/// `category` spelled the one way, e.g. "Dev>Code Review" as "Dev > Code Review".
+pub fn normalize(category: &str) -> String {
+    path(category).join(&format!(" {} ", SEPARATOR))
+}
+
+/// `category` followed by each of its ancestors, closest first.
+pub fn ancestors(category: &str) -> Vec<String> {
+    let parts = path(category);
+    (1..=parts.len()).rev().map(|len| parts[..len].join(&format!(" {} ", SEPARATOR))).collect()
+}
+
+/// Whether `category` is `ancestor` or one of its descendants.
+pub fn is_within(category: &str, ancestor: &str) -> bool {
+    let ancestor = path(ancestor);
+    !ancestor.is_empty() && path(category).starts_with(&ancestor)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Productivity {
+    Productive,
+    Distracting,
+    #[default]
+    #[serde(other)]
+    Neutral, // Also used for values this build doesn't know
+}
+
+/// All given conditions must hold; a rule without conditions never matches.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryRule {
+    pub category: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>, // App name, case-insensitive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>, // URL host, also matching subdomains
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_contains: Option<String>, // Case-insensitive substring of the window title
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryConfig {
+    pub rules: Vec<CategoryRule>,
+    pub productivity: BTreeMap<String, Productivity>, // Category -> productivity
+}
+
+pub fn url_host(url: &str) -> Option<String> {
+    url::Url::parse(url).ok()?.host_str().map(|host| host.to_lowercase())
+}
+
+impl CategoryRule {
+    pub fn matches(&self, key: &ActivityKey) -> bool {
+        if self.app.is_none() && self.host.is_none() && self.title_contains.is_none() {
+            return false;
+        }
+        if let Some(ref app) = self.app {
+            if !app.eq_ignore_ascii_case(&key.app_name) {
+                return false;
+            }
+        }
+        if let Some(ref host) = self.host {
+            let host = host.to_lowercase();
+            match key.url().and_then(url_host) {
+                Some(actual) if actual == host || actual.ends_with(&format!(".{}", host)) => {}
+                _ => return false,
+            }
+        }
+        if let Some(ref needle) = self.title_contains {
+            if key.url().is_some() || !key.window_title().to_lowercase().contains(&needle.to_lowercase()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl CategoryConfig {
+    pub fn categorize(&self, key: &ActivityKey) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(key))
+            .map(|rule| rule.category.as_str())
+    }
+
+    pub fn productivity(&self, key: &ActivityKey) -> Productivity {
+        self.categorize(key).map(|category| self.category_productivity(category)).unwrap_or_default()
+    }
+
+    /// The productivity set for `category` or its nearest ancestor.
+    pub fn category_productivity(&self, category: &str) -> Productivity {
+        let configured: BTreeMap<String, Productivity> =
+            self.productivity.iter().map(|(category, productivity)| (normalize(category), *productivity)).collect();
+        ancestors(category).iter().find_map(|ancestor| configured.get(ancestor).copied()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CategoryConfig {
+        serde_json::from_str(
+            r#"{
+                "rules": [
+                    { "category": "Social", "host": "twitter.com" },
+                    { "category": "Dev", "app": "code.exe" },
+                    { "category": "Dev", "app": "chrome.exe", "title_contains": "pull request" }
+                ],
+                "productivity": { "Dev": "productive", "Social": "distracting" }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let config = config();
+        assert_eq!(config.categorize(&ActivityKey::new("chrome.exe", "", Some("https://mobile.twitter.com/home"))), Some("Social"));
+        assert_eq!(config.categorize(&ActivityKey::new("Code.exe", "main.rs", None)), Some("Dev"));
+        assert_eq!(config.categorize(&ActivityKey::new("chrome.exe", "Review Pull Request #4", None)), Some("Dev"));
+        assert_eq!(config.categorize(&ActivityKey::new("chrome.exe", "", Some("https://nottwitter.com/"))), None);
+    }
+
+    #[test]
+    fn uncategorized_activities_are_neutral() {
+        let config = config();
+        assert_eq!(config.productivity(&ActivityKey::new("code.exe", "x", None)), Productivity::Productive);
+        assert_eq!(config.productivity(&ActivityKey::new("steam.exe", "x", None)), Productivity::Neutral);
+    }
+
+    #[test]
+    fn children_inherit_from_the_nearest_ancestor() {
+        let mut config = config();
+        config.productivity.insert("Dev>Meetings".to_string(), Productivity::Neutral);
+        assert_eq!(config.category_productivity("Dev > Code Review > Nits"), Productivity::Productive);
+        assert_eq!(config.category_productivity("Dev > Meetings > Standup"), Productivity::Neutral);
+        assert_eq!(ancestors("Dev>Code Review"), vec!["Dev > Code Review", "Dev"]);
+        assert!(is_within("Dev > Code Review", "Dev") && is_within("Dev", "Dev"));
+        assert!(!is_within("Devops", "Dev") && !is_within("Dev", "Dev > Code Review"));
+    }
+}
@@ -0,0 +1,61 @@
+// Child processes that are given up on after a timeout.
+//
+// Integrations shell out to `curl`, `mosquitto_pub` and the like, and a broker
+// or server that accepts the connection but never answers would otherwise
+// keep the caller waiting forever. `wait` polls the child until it exits or
+// the timeout passes, then kills it; its output is read on the side so a full
+// pipe can't hold it up.
+
+use std::io::Read;
+use std::process::{Child, Output};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::units::DurationSecs;
+
+const POLL: Duration = Duration::from_millis(50);
+
+fn drain(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+/// Waits for `child` to exit, killing it once `timeout` has passed.
+pub fn wait(mut child: Child, program: &str, timeout: DurationSecs) -> Result<Output, String> {
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+    let deadline = Instant::now() + timeout.to_std();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("{} gave no answer within {}", program, timeout));
+        }
+        thread::sleep(POLL);
+    };
+    let collect = |reader: Option<JoinHandle<Vec<u8>>>| reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+    Ok(Output { status, stdout: collect(stdout), stderr: collect(stderr) })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn children_past_their_timeout_are_killed() {
+        let quick = Command::new("sh").args(["-c", "echo done"]).stdout(Stdio::piped()).spawn().unwrap();
+        assert_eq!(wait(quick, "sh", DurationSecs::secs(5)).unwrap().stdout, b"done\n");
+
+        let started = Instant::now();
+        let stuck = Command::new("sleep").arg("30").spawn().unwrap();
+        assert!(wait(stuck, "sleep", DurationSecs::secs(1)).unwrap_err().contains("within 1s"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
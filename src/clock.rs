@@ -0,0 +1,233 @@
+// Wall-clock jumps: NTP corrections, manual changes, a wrong time zone fixed.
+//
+// Sessions are stored as wall-clock times, so a jump while one is open would
+// stretch it by the jump or, backwards, let the next ones overlap it. The
+// tracker compares how far the wall clock moved between polls with the
+// monotonic clock; when they disagree by more than `JUMP_THRESHOLD_SECS` it
+// ends the open session where the old clock left off, starts it again on the
+// new one and records the jump in `clock_jumps`.
+//
+// `sysmonitor clock repair` fixes what older versions stored. Tracker sessions
+// (those without a `source`) follow each other in the order their rows were
+// inserted, so one that overlaps the next, or ends in the future, was stored
+// on a clock that was ahead. Walking back from now, such sessions are moved
+// earlier by as much as it takes to end where the next one starts; the rest of
+// that stretch keeps the same offset until a session fits as stored again.
+
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::Serialize;
+
+use crate::categories::CategoryConfig;
+use crate::{grafana, reports, rollups, signatures};
+
+pub const JUMP_THRESHOLD_SECS: u64 = 30; // Polls are a second or two apart
+const LISTED: usize = 20; // Sessions printed by `repair`
+
+/// A jump seen between two polls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Jump {
+    pub expected: u64, // What the wall clock would read without the jump
+    pub actual: u64,
+}
+
+impl Jump {
+    pub fn offset(&self) -> i64 {
+        self.actual as i64 - self.expected as i64
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JumpDetector {
+    last: Option<(Instant, u64)>, // Monotonic and wall-clock reading of the previous poll
+}
+
+impl JumpDetector {
+    pub fn check(&mut self, monotonic: Instant, wall: u64) -> Option<Jump> {
+        let (then, then_wall) = self.last.replace((monotonic, wall))?;
+        let expected = then_wall + monotonic.saturating_duration_since(then).as_secs();
+        (wall.abs_diff(expected) > JUMP_THRESHOLD_SECS).then_some(Jump { expected, actual: wall })
+    }
+
+    /// Forgets the previous poll, e.g. after a sleep that already ended the sessions.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Whether `interval` has passed since `last`; also when `last` is ahead of
+/// `now` because the clock went back, so timers don't stall until it catches up.
+pub fn due(now: SystemTime, last: SystemTime, interval: Duration) -> bool {
+    now.duration_since(last).map_or(true, |elapsed| elapsed >= interval)
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS clock_jumps (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            detected_at INTEGER NOT NULL,
+            offset_secs INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn record(conn: &Connection, jump: &Jump) -> SqlResult<()> {
+    conn.execute("INSERT INTO clock_jumps (detected_at, offset_secs) VALUES (?1, ?2)", params![jump.actual, jump.offset()])?;
+    Ok(())
+}
+
+/// A stored session to move by `shift` seconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fix {
+    pub identifier: String,
+    pub app_name: String,
+    pub start: i64,
+    pub duration: i64,
+    pub shift: i64, // Negative: earlier
+}
+
+/// Tracker sessions stored on a clock that was ahead, newest first.
+pub fn find_fixes(conn: &Connection, now: u64) -> SqlResult<Vec<Fix>> {
+    let mut stmt = conn.prepare(
+        "SELECT identifier, app_name, timestamp - duration AS start, MAX(duration)
+         FROM usage_logs WHERE source IS NULL
+         GROUP BY identifier, start ORDER BY MIN(id) DESC",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?)))?;
+    let tolerance = JUMP_THRESHOLD_SECS as i64;
+    let (mut cursor, mut shift) = (now as i64, 0);
+    let mut fixes = Vec::new();
+    for row in rows {
+        let (identifier, app_name, start, duration) = row?;
+        let end = start + duration;
+        shift = if end <= cursor + tolerance { 0 } else { shift.max(end - cursor) };
+        if shift > 0 {
+            fixes.push(Fix { identifier, app_name, start, duration, shift: -shift });
+        }
+        cursor = cursor.min(start - shift);
+    }
+    Ok(fixes)
+}
+
+/// Moves the sessions of `fixes` with their category assignments, then
+/// rebuilds the rollups and Grafana tables they fed.
+pub fn apply(conn: &mut Connection, categories: &CategoryConfig, fixes: &[Fix], now: u64) -> SqlResult<()> {
+    let mut days = BTreeSet::new();
+    let tx = conn.transaction()?;
+    for fix in fixes {
+        let ids: Vec<i64> = tx
+            .prepare("SELECT id FROM usage_logs WHERE identifier = ?1 AND timestamp - duration = ?2 AND source IS NULL")?
+            .query_map(params![fix.identifier, fix.start], |row| row.get(0))?
+            .collect::<SqlResult<_>>()?;
+        for id in ids {
+            tx.execute("UPDATE usage_logs SET timestamp = timestamp + ?1 WHERE id = ?2", params![fix.shift, id])?;
+            signatures::sign_row(&tx, id)?;
+        }
+        tx.execute(
+            "UPDATE OR REPLACE session_categories SET start = start + ?1 WHERE identifier = ?2 AND start = ?3",
+            params![fix.shift, fix.identifier, fix.start],
+        )?;
+        for table in ["session_labels", "label_change_sessions"] {
+            tx.execute(
+                &format!("UPDATE OR REPLACE {} SET start = start + ?1 WHERE identifier = ?2 AND start = ?3", table),
+                params![fix.shift, fix.identifier, fix.start],
+            )?;
+        }
+        days.insert(reports::local_date(fix.start.max(0) as u64));
+        days.insert(reports::local_date((fix.start + fix.shift).max(0) as u64));
+    }
+    tx.commit()?;
+    rollups::rebuild(conn, &days)?;
+    if let Some(earliest) = fixes.iter().map(|fix| fix.start + fix.shift).min() {
+        grafana::rebuild_since(conn, categories, earliest.max(0) as u64, now)?;
+    }
+    Ok(())
+}
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0).map_or_else(|| "-".to_string(), |time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// `sysmonitor clock jumps` and `sysmonitor clock repair [--apply]`.
+pub fn run_command(conn: &mut Connection, categories: &CategoryConfig, args: &[String], now: u64) -> Result<(), String> {
+    let sql = |e: rusqlite::Error| e.to_string();
+    match args.first().map(String::as_str) {
+        Some("jumps") => {
+            let mut stmt = conn.prepare("SELECT detected_at, offset_secs FROM clock_jumps ORDER BY id").map_err(sql)?;
+            let jumps = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))).map_err(sql)?;
+            let mut any = false;
+            for jump in jumps {
+                let (at, offset) = jump.map_err(sql)?;
+                println!("{}  {:+}s", format_time(at), offset);
+                any = true;
+            }
+            if !any {
+                println!("No clock jumps recorded");
+            }
+            Ok(())
+        }
+        Some("repair") => {
+            let fixes = find_fixes(conn, now).map_err(sql)?;
+            if fixes.is_empty() {
+                println!("No sessions need repairing");
+                return Ok(());
+            }
+            for fix in fixes.iter().take(LISTED) {
+                println!("  {} {} ({}s): move {:+}s", format_time(fix.start), fix.app_name, fix.duration, fix.shift);
+            }
+            if fixes.len() > LISTED {
+                println!("  ... and {} more", fixes.len() - LISTED);
+            }
+            if !args.iter().any(|arg| arg == "--apply") {
+                println!("{} sessions would be moved; run again with --apply to move them", fixes.len());
+                return Ok(());
+            }
+            apply(conn, categories, &fixes, now).map_err(sql)?;
+            println!("Moved {} sessions", fixes.len());
+            Ok(())
+        }
+        _ => Err("usage: sysmonitor clock jumps | clock repair [--apply]".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn sessions_stored_while_the_clock_was_ahead_are_moved_back() {
+        let mut detector = JumpDetector::default();
+        let start = Instant::now();
+        assert_eq!(detector.check(start, 10_000), None);
+        assert_eq!(detector.check(start, 10_010), None);
+        let jump = detector.check(start, 10_010 - 3600).unwrap();
+        assert_eq!((jump.expected, jump.offset()), (10_010, -3600));
+
+        let conn = fixtures::empty().unwrap();
+        let log = |app: &str, start: i64, duration: i64, source: Option<&str>| {
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration, source) VALUES (?1, ?1, '', ?2, ?3, ?4)",
+                params![app, start + duration, duration, source],
+            )
+            .unwrap();
+        };
+        // The clock ran an hour ahead from 10_000 until NTP set it back at 12_600
+        log("early", 9_000, 900, None);
+        log("ahead", 10_000 + 3600, 600, None);
+        log("ahead-2", 10_800 + 3600, 1200, None);
+        log("ahead-2", 10_800 + 3600, 1800, None); // A later flush of the same session
+        log("phone", 12_000, 600, Some("phone"));
+        log("after", 12_600, 300, None);
+
+        let fixes = find_fixes(&conn, 20_000).unwrap();
+        let moved: Vec<(&str, i64)> = fixes.iter().map(|fix| (fix.identifier.as_str(), fix.shift)).collect();
+        // `ahead` keeps the stretch's offset though less would do; `early` already fits
+        assert_eq!(moved, vec![("ahead-2", -3600), ("ahead", -3600)]);
+    }
+}
@@ -0,0 +1,150 @@
+// Gzip and deflate encoding, and response compression negotiated from
+// `Accept-Encoding`.
+//
+// warp's own `compression` filters compress unconditionally, so API replies
+// are buffered and encoded here only when the client asked for it and the body
+// is large enough to be worth it. Exports use `compress` without the server.
+
+use std::io::Write;
+
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+#[cfg(feature = "web")]
+use warp::http::header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, VARY};
+#[cfg(feature = "web")]
+use warp::hyper::body::{to_bytes, Body};
+#[cfg(feature = "web")]
+use warp::reply::Response;
+#[cfg(feature = "web")]
+use warp::Reply;
+
+// Tiny bodies (health checks, errors) grow when compressed
+#[cfg(feature = "web")]
+const MIN_COMPRESS_BYTES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    Identity,
+}
+
+impl Encoding {
+    #[cfg(feature = "web")]
+    fn header_value(self) -> Option<&'static str> {
+        match self {
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Deflate => Some("deflate"),
+            Encoding::Identity => None,
+        }
+    }
+}
+
+/// Picks the preferred supported encoding, honouring `q` weights; gzip wins ties.
+pub fn negotiate(accept_encoding: &str) -> Encoding {
+    let mut best = (Encoding::Identity, 0.0f32);
+    for part in accept_encoding.split(',') {
+        let mut pieces = part.trim().split(';');
+        let name = pieces.next().unwrap_or("").trim().to_lowercase();
+        let quality = pieces
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let encoding = match name.as_str() {
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            "deflate" => Encoding::Deflate,
+            _ => continue,
+        };
+        if quality > best.1 || (quality == best.1 && quality > 0.0 && encoding == Encoding::Gzip) {
+            best = (encoding, quality);
+        }
+    }
+    best.0
+}
+
+pub fn compress(encoding: Encoding, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Encoding::Identity => Ok(data.to_vec()),
+    }
+}
+
+/// Re-encodes a reply's body for the client. Replies that already carry a
+/// `Content-Encoding` (pre-compressed exports) and event streams are passed
+/// through untouched.
+#[cfg(feature = "web")]
+pub async fn compress_reply(
+    accept_encoding: Option<String>,
+    reply: impl Reply,
+) -> Result<Response, warp::Rejection> {
+    let response = reply.into_response();
+    let encoding = accept_encoding.as_deref().map(negotiate).unwrap_or(Encoding::Identity);
+    let Some(encoding_name) = encoding.header_value() else {
+        return Ok(response);
+    };
+    // Streams never end, so they can't be buffered to encode them
+    let streaming = response.headers().get(CONTENT_TYPE).is_some_and(|value| value.as_bytes().starts_with(b"text/event-stream"));
+    if streaming || response.headers().contains_key(CONTENT_ENCODING) {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Ok(Response::from_parts(parts, Body::empty())),
+    };
+    parts.headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    if bytes.len() < MIN_COMPRESS_BYTES {
+        return Ok(Response::from_parts(parts, Body::from(bytes)));
+    }
+
+    match compress(encoding, &bytes) {
+        Ok(compressed) => {
+            parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding_name));
+            parts.headers.remove(CONTENT_LENGTH);
+            // The encoded bytes differ from what a strong ETag vouches for
+            if let Some(tag) = parts.headers.get(ETAG).and_then(|tag| tag.to_str().ok()) {
+                if !tag.starts_with("W/") {
+                    if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", tag)) {
+                        parts.headers.insert(ETAG, weak);
+                    }
+                }
+            }
+            Ok(Response::from_parts(parts, Body::from(compressed)))
+        }
+        Err(_) => Ok(Response::from_parts(parts, Body::from(bytes))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_honours_quality_values() {
+        assert_eq!(negotiate("gzip, deflate, br"), Encoding::Gzip);
+        assert_eq!(negotiate("deflate;q=1.0, gzip;q=0.5"), Encoding::Deflate);
+        assert_eq!(negotiate("gzip;q=0, br"), Encoding::Identity);
+        assert_eq!(negotiate(""), Encoding::Identity);
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        use std::io::Read;
+        let data = "activity ".repeat(500);
+        let compressed = compress(Encoding::Gzip, data.as_bytes()).unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        assert!(compressed.len() < data.len());
+    }
+}
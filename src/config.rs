@@ -0,0 +1,103 @@
+// Optional user settings from `sysmonitor.json` next to `usage.db`.
+//
+// Every section has defaults, so a missing file or a file that only sets one
+// option is fine. A malformed file is reported and ignored rather than keeping
+// the tracker from starting.
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "compression")]
+use crate::archive::ArchiveConfig;
+#[cfg(feature = "web")]
+use crate::favicons::FaviconConfig;
+#[cfg(feature = "web")]
+use crate::refresh::RefreshConfig;
+use crate::categories::CategoryConfig;
+use crate::crash::CrashConfig;
+use crate::daily_note::DailyNoteConfig;
+use crate::debounce::DebounceConfig;
+use crate::devices::DeviceConfig;
+use crate::forecast::ForecastConfig;
+use crate::goals::GoalConfig;
+use crate::home_assistant::HomeAssistantConfig;
+use crate::idle::IdleConfig;
+use crate::influx::InfluxConfig;
+use crate::ingest::IngestConfig;
+use crate::logs;
+use crate::maintenance::MaintenanceConfig;
+use crate::memory::MemoryConfig;
+use crate::notify::NotificationConfig;
+use crate::notion::NotionConfig;
+use crate::outbound::OutboundConfig;
+use crate::pauses::PauseConfig;
+use crate::profiles::AppProfile;
+use crate::queries::QueryConfig;
+use crate::retention::RetentionConfig;
+use crate::safe_mode::SafeModeConfig;
+use crate::signatures::SigningConfig;
+use crate::summarize::SummaryConfig;
+use crate::team::TeamConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::url_cache::UrlCacheConfig;
+use crate::warm_start::WarmStartConfig;
+use crate::writer::FlushConfig;
+
+pub const CONFIG_PATH: &str = "sysmonitor.json";
+pub const DB_PATH: &str = "usage.db";
+// Override both paths, e.g. to keep them on a container volume
+pub const DB_PATH_ENV: &str = "SYSMONITOR_DB";
+pub const CONFIG_PATH_ENV: &str = "SYSMONITOR_CONFIG";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub notifications: NotificationConfig,
+    pub app_profiles: BTreeMap<String, AppProfile>, // App name -> tracking overrides
+    pub title_summaries: SummaryConfig,
+    pub url_cache: UrlCacheConfig,
+    pub debounce: DebounceConfig,
+    pub warm_start: WarmStartConfig, // Picking the open session up again after a restart
+    pub flush: FlushConfig,
+    pub idle: IdleConfig,
+    pub categories: CategoryConfig,
+    pub ingest: IngestConfig,
+    pub devices: DeviceConfig,
+    pub team: TeamConfig,
+    pub pauses: PauseConfig,
+    pub forecast: ForecastConfig,
+    pub goals: GoalConfig,
+    pub queries: QueryConfig,
+    pub daily_notes: DailyNoteConfig,
+    pub notion: NotionConfig,
+    pub outbound: OutboundConfig, // Retries and circuit breaking for the integrations
+    pub home_assistant: HomeAssistantConfig,
+    pub influx: InfluxConfig,
+    pub telemetry: TelemetryConfig,
+    pub retention: RetentionConfig,
+    pub maintenance: MaintenanceConfig, // Vacuum and backup schedules
+    pub signing: SigningConfig,
+    pub crash_reports: CrashConfig,
+    pub safe_mode: SafeModeConfig, // After repeated crashed starts
+    pub memory: MemoryConfig,
+    #[cfg(feature = "compression")]
+    pub archive: ArchiveConfig,
+    #[cfg(feature = "web")]
+    pub favicons: FaviconConfig,
+    #[cfg(feature = "web")]
+    pub refresh: RefreshConfig, // Poll hints in dashboard and stats answers
+}
+
+impl Config {
+    pub fn load(path: &str) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                logs::error!("Ignoring {}: {}", path, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+// Crash reports, with `crash_reports.enabled` on.
+//
+// The tracker and server run for weeks in the background, so when one dies
+// there is usually nobody watching its output. A panic on any thread writes a
+// JSON report to `crash_reports.dir` (`crashes` next to the database): the
+// message and where it happened, the thread, a backtrace, the version, how
+// long the process had run and its last log lines. On Windows a minidump of
+// the process goes next to it, also for crashes that never reach Rust's panic
+// handling, such as an access violation inside a system call, which an
+// unhandled exception filter catches. Reports stay on the machine; `sysmonitor
+// crash list` and `crash show <id>` read them back.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::logs;
+
+const LOG_LINES: usize = 30; // Of the process log, in each report
+const LISTED_MESSAGE_CHARS: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrashConfig {
+    pub enabled: bool,
+    pub dir: String, // Relative paths are next to the database
+    pub minidumps: bool, // Windows only
+    pub keep: usize, // Newest reports kept; older ones go when the process starts
+}
+
+impl Default for CrashConfig {
+    fn default() -> Self {
+        Self { enabled: false, dir: "crashes".to_string(), minidumps: true, keep: 20 }
+    }
+}
+
+impl CrashConfig {
+    pub fn dir_for(&self, db_path: &str) -> PathBuf {
+        let dir = Path::new(&self.dir);
+        match Path::new(db_path).parent() {
+            Some(parent) if dir.is_relative() => parent.join(dir),
+            _ => dir.to_path_buf(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashKind {
+    Panic,
+    Exception, // A Windows structured exception
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub id: String,
+    pub at: u64,
+    pub kind: CrashKind,
+    pub version: String,
+    pub message: String,
+    pub location: Option<String>, // file:line:column of a panic
+    pub thread: Option<String>,
+    pub uptime_secs: u64,
+    pub backtrace: String,
+    pub log: Vec<String>,
+    pub minidump: Option<String>, // File name next to the report
+}
+
+// Where an installed handler writes, and since when the process runs
+struct Installed {
+    dir: PathBuf,
+    minidumps: bool,
+    started: Instant,
+}
+
+static INSTALLED: OnceLock<Installed> = OnceLock::new();
+
+/// Installs the handlers when `config` turns them on, after pruning old reports.
+pub fn install(config: &CrashConfig, db_path: &str) {
+    if !config.enabled {
+        return;
+    }
+    let dir = config.dir_for(db_path);
+    if let Err(e) = prune(&dir, config.keep) {
+        logs::error!("Error pruning crash reports in {}: {}", dir.display(), e);
+    }
+    if INSTALLED.set(Installed { dir, minidumps: config.minidumps, started: Instant::now() }).is_err() {
+        return;
+    }
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "(no message)".to_string());
+        let location = info.location().map(|location| format!("{}:{}:{}", location.file(), location.line(), location.column()));
+        match write_report(CrashKind::Panic, message, location, None) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Could not write a crash report: {}", e),
+        }
+    }));
+    #[cfg(windows)]
+    unsafe {
+        windows::Win32::System::Diagnostics::Debug::SetUnhandledExceptionFilter(Some(on_exception));
+    }
+}
+
+// Runs in the failing thread, so it only formats and writes files
+fn write_report(kind: CrashKind, message: String, location: Option<String>, exception: Option<*const std::ffi::c_void>) -> std::io::Result<PathBuf> {
+    let installed = INSTALLED.get().ok_or_else(|| std::io::Error::other("crash reports are not installed"))?;
+    fs::create_dir_all(&installed.dir)?;
+    let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let id = format!("{}-{}", at, std::process::id());
+    let minidump = (cfg!(windows) && installed.minidumps)
+        .then(|| format!("{}.dmp", id))
+        .filter(|name| write_minidump(&installed.dir.join(name), exception).is_ok());
+    let report = CrashReport {
+        id: id.clone(),
+        at,
+        kind,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        message,
+        location,
+        thread: std::thread::current().name().map(str::to_string),
+        uptime_secs: installed.started.elapsed().as_secs(),
+        backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+        log: logs::try_recent(LOG_LINES).into_iter().map(|record| record.message).collect(),
+        minidump,
+    };
+    let path = installed.dir.join(format!("{}.json", id));
+    fs::write(&path, serde_json::to_vec_pretty(&report).unwrap_or_default())?;
+    Ok(path)
+}
+
+#[cfg(windows)]
+fn write_minidump(path: &Path, exception: Option<*const std::ffi::c_void>) -> std::io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::{FALSE, HANDLE};
+    use windows::Win32::System::Diagnostics::Debug::{
+        MiniDumpWithThreadInfo, MiniDumpWriteDump, EXCEPTION_POINTERS, MINIDUMP_EXCEPTION_INFORMATION,
+    };
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId};
+
+    let file = fs::File::create(path)?;
+    let information = exception.map(|pointers| MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: unsafe { GetCurrentThreadId() },
+        ExceptionPointers: pointers as *mut EXCEPTION_POINTERS,
+        ClientPointers: FALSE,
+    });
+    unsafe {
+        MiniDumpWriteDump(
+            GetCurrentProcess(),
+            GetCurrentProcessId(),
+            HANDLE(file.as_raw_handle() as isize),
+            MiniDumpWithThreadInfo,
+            information.as_ref().map(|information| information as *const _),
+            None,
+            None,
+        )
+    }
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+#[cfg(not(windows))]
+fn write_minidump(_path: &Path, _exception: Option<*const std::ffi::c_void>) -> std::io::Result<()> {
+    Err(std::io::Error::other("minidumps are only written on Windows"))
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn on_exception(pointers: *const windows::Win32::System::Diagnostics::Debug::EXCEPTION_POINTERS) -> i32 {
+    let code = pointers.as_ref().and_then(|pointers| pointers.ExceptionRecord.as_ref()).map_or(0, |record| record.ExceptionCode.0);
+    let _ = write_report(CrashKind::Exception, format!("Unhandled exception 0x{:08X}", code as u32), None, Some(pointers.cast()));
+    0 // EXCEPTION_CONTINUE_SEARCH: Windows goes on to end the process
+}
+
+/// Stored reports, newest first.
+pub fn list(dir: &Path) -> std::io::Result<Vec<CrashReport>> {
+    let mut reports = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(reports),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            // A report this version can't read is left out
+            if let Ok(report) = serde_json::from_slice::<CrashReport>(&fs::read(&path)?) {
+                reports.push(report);
+            }
+        }
+    }
+    reports.sort_by(|a, b| b.at.cmp(&a.at).then_with(|| b.id.cmp(&a.id)));
+    Ok(reports)
+}
+
+/// Deletes all but the newest `keep` reports with their minidumps.
+pub fn prune(dir: &Path, keep: usize) -> std::io::Result<usize> {
+    let mut old = list(dir)?;
+    let old = old.split_off(keep.min(old.len()));
+    for report in &old {
+        fs::remove_file(dir.join(format!("{}.json", report.id)))?;
+        if let Some(ref minidump) = report.minidump {
+            let _ = fs::remove_file(dir.join(minidump));
+        }
+    }
+    Ok(old.len())
+}
+
+/// `sysmonitor crash list`, `crash show <id>` and `crash test`, which panics
+/// to check that reports get written.
+pub fn run_command(config: &CrashConfig, db_path: &str, args: &[String]) -> Result<(), String> {
+    let dir = config.dir_for(db_path);
+    let reports = list(&dir).map_err(|e| format!("Could not read {}: {}", dir.display(), e))?;
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("list"), _) => {
+            if reports.is_empty() {
+                println!("No crash reports in {}{}", dir.display(), if config.enabled { "" } else { " (crash_reports.enabled is off)" });
+            }
+            for report in &reports {
+                let when = chrono::DateTime::from_timestamp(report.at as i64, 0)
+                    .map_or_else(|| "-".to_string(), |time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string());
+                let message: String = report.message.lines().next().unwrap_or_default().chars().take(LISTED_MESSAGE_CHARS).collect();
+                println!("{}  {}  v{}  {}", report.id, when, report.version, message);
+            }
+            Ok(())
+        }
+        (Some("show"), Some(id)) => {
+            let report = reports.iter().find(|report| &report.id == id).ok_or_else(|| format!("No crash report {} in {}", id, dir.display()))?;
+            println!("{}", serde_json::to_string_pretty(report).map_err(|e| e.to_string())?);
+            if let Some(ref minidump) = report.minidump {
+                println!("Minidump: {}", dir.join(minidump).display());
+            }
+            Ok(())
+        }
+        (Some("test"), _) if config.enabled => {
+            install(config, db_path);
+            panic!("Crash report test from `sysmonitor crash test`");
+        }
+        (Some("test"), _) => Err("Turn on crash_reports.enabled in the config first".to_string()),
+        _ => Err("usage: sysmonitor crash list | crash show <id> | crash test".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_reports_are_pruned_newest_first() {
+        let dir = std::env::temp_dir().join(format!("sysmonitor-crash-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for at in [100, 300, 200] {
+            let report = CrashReport {
+                id: format!("{}-1", at),
+                at,
+                kind: CrashKind::Panic,
+                version: "0.1.0".to_string(),
+                message: "index out of bounds".to_string(),
+                location: Some("src/main.rs:1:1".to_string()),
+                thread: None,
+                uptime_secs: 5,
+                backtrace: String::new(),
+                log: Vec::new(),
+                minidump: Some(format!("{}-1.dmp", at)),
+            };
+            fs::write(dir.join(format!("{}.json", report.id)), serde_json::to_vec(&report).unwrap()).unwrap();
+            fs::write(dir.join(format!("{}-1.dmp", at)), b"MDMP").unwrap();
+        }
+        fs::write(dir.join("notes.txt"), b"not a report").unwrap();
+
+        assert_eq!(prune(&dir, 2).unwrap(), 1);
+        let ids: Vec<String> = list(&dir).unwrap().into_iter().map(|report| report.id).collect();
+        assert_eq!(ids, vec!["300-1", "200-1"]);
+        assert!(!dir.join("100-1.dmp").exists() && dir.join("notes.txt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
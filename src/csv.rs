@@ -0,0 +1,76 @@
+// Minimal RFC 4180 CSV: quoting for writers and a parser for imports.
+
+pub fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Splits `text` into records of fields, honouring quoted commas, quotes and
+/// line breaks. Blank lines are skipped.
+pub fn parse(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+                line += 1;
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("unterminated quote on line {}", line));
+    }
+    record.push(field);
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_quoted_when_needed() {
+        assert_eq!(field("plain"), "plain");
+        assert_eq!(field("a, \"b\""), "\"a, \"\"b\"\"\"");
+    }
+
+    #[test]
+    fn quoted_fields_parse_back() {
+        let text = format!("a,{}\r\n\n{},c\n", field("x, \"y\""), field("two\nlines"));
+        assert_eq!(
+            parse(&text).unwrap(),
+            vec![vec!["a".to_string(), "x, \"y\"".to_string()], vec!["two\nlines".to_string(), "c".to_string()]]
+        );
+        assert!(parse("\"open").is_err());
+    }
+}
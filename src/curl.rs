@@ -0,0 +1,114 @@
+// Secrets for `curl` without putting them on its command line.
+//
+// Everything sysmonitor sends out goes through curl, and any local user can
+// read a command line with `ps` or in `/proc/<pid>/cmdline`. Request bodies
+// already go over stdin, so credentials (`--user`), headers that carry them
+// and URLs with a secret in them (webhooks) go in a config file instead: created new, readable by the current user
+// only, passed with `-K` and removed when the `SecretFile` is dropped, after
+// curl is done with it.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    lines: Vec<String>,
+}
+
+impl Secrets {
+    /// `user:password` for basic or SMTP authentication.
+    pub fn user(mut self, credentials: &str) -> Self {
+        self.lines.push(format!("user = {}", quote(credentials)));
+        self
+    }
+
+    /// A header such as `Authorization: Bearer <token>`.
+    pub fn header(mut self, header: &str) -> Self {
+        self.lines.push(format!("header = {}", quote(header)));
+        self
+    }
+
+    /// A URL that is itself a secret, such as a Slack webhook.
+    pub fn url(mut self, url: &str) -> Self {
+        self.lines.push(format!("url = {}", quote(url)));
+        self
+    }
+
+    /// Writes the config file; without secrets there is none to pass.
+    pub fn write(&self) -> Result<SecretFile, String> {
+        if self.lines.is_empty() {
+            return Ok(SecretFile(None));
+        }
+        let mut suffix = [0u8; 8];
+        getrandom::getrandom(&mut suffix).map_err(|e| e.to_string())?;
+        let name: String = suffix.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let path = std::env::temp_dir().join(format!("sysmonitor-curl-{}.conf", name));
+
+        let mut options = OpenOptions::new();
+        // A file planted under the name beforehand makes this fail
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        // Removed again if writing fails
+        let secret = SecretFile(Some(path));
+        file.write_all(self.lines.join("\n").as_bytes()).map_err(|e| e.to_string())?;
+        Ok(secret)
+    }
+}
+
+// Double-quoted, as curl's config parser unescapes it
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\t' => quoted.push_str("\\t"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The config file of a `Secrets`, removed on drop.
+pub struct SecretFile(Option<PathBuf>);
+
+impl SecretFile {
+    /// `-K <file>`, nothing when there are no secrets.
+    pub fn args(&self) -> Vec<String> {
+        self.0.iter().flat_map(|path| ["-K".to_string(), path.display().to_string()]).collect()
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_go_to_a_private_file_that_is_removed_afterwards() {
+        assert!(Secrets::default().write().unwrap().args().is_empty());
+
+        let file = Secrets::default().user("me:p\"a\\ss").header("Authorization: Bearer abc").url("https://hooks.example/T0/B1").write().unwrap();
+        let args = file.args();
+        assert_eq!(args[0], "-K");
+        let path = PathBuf::from(&args[1]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "user = \"me:p\\\"a\\\\ss\"\nheader = \"Authorization: Bearer abc\"\nurl = \"https://hooks.example/T0/B1\"");
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+        drop(file);
+        assert!(!path.exists());
+    }
+}
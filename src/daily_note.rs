@@ -0,0 +1,240 @@
+// Markdown daily notes, e.g. for an Obsidian vault's daily notes folder.
+//
+// The generated part sits between two HTML comments, so rewriting a note only
+// replaces that part: a note the user created first, their `## Notes` and
+// anything else they wrote stay as they are. Notes are written for the day
+// that just ended while tracking, or on demand with `sysmonitor note`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::blocks;
+use crate::identifier::ActivityKey;
+use crate::reports;
+use crate::sessions::{self, Session};
+use crate::units::{DurationSecs, Timestamp};
+
+const START_MARKER: &str = "<!-- sysmonitor:start -->";
+const END_MARKER: &str = "<!-- sysmonitor:end -->";
+const TOP_APPS: usize = 10;
+const MIN_TIMELINE: DurationSecs = DurationSecs::mins(1); // Shorter stretches are left out of the timeline
+const TIMELINE_GAP: DurationSecs = DurationSecs::mins(2); // Sessions of one app this close are one timeline row
+const FOCUS_BLOCK: DurationSecs = DurationSecs::mins(25); // Work blocks with less active time aren't focus blocks
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyNoteConfig {
+    pub dir: Option<String>, // Unset writes no notes on its own
+    pub file_name: String, // strftime pattern; Obsidian's default is YYYY-MM-DD
+}
+
+impl Default for DailyNoteConfig {
+    fn default() -> Self {
+        Self { dir: None, file_name: "%Y-%m-%d.md".to_string() }
+    }
+}
+
+fn duration(length: DurationSecs) -> String {
+    let seconds = length.as_secs();
+    match (seconds / 3600, seconds % 3600 / 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h {:02}m", hours, minutes),
+    }
+}
+
+fn clock(timestamp: Timestamp) -> String {
+    Local.timestamp_opt(timestamp.as_secs() as i64, 0).earliest().map(|time| time.format("%H:%M").to_string()).unwrap_or_default()
+}
+
+// Table cells can't hold pipes or line breaks
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+struct TimelineRow {
+    start: Timestamp,
+    end: Timestamp,
+    app_name: String,
+    detail: String, // Window title or URL of the longest session
+    longest: DurationSecs,
+}
+
+fn timeline(sessions: &[Session]) -> Vec<TimelineRow> {
+    let mut rows: Vec<TimelineRow> = Vec::new();
+    for session in sessions {
+        let detail = ActivityKey::parse(&session.identifier).detail;
+        match rows.last_mut() {
+            Some(row) if row.app_name == session.app_name && session.start <= row.end + TIMELINE_GAP => {
+                row.end = row.end.max(session.end);
+                if session.duration() > row.longest {
+                    row.longest = session.duration();
+                    row.detail = detail;
+                }
+            }
+            _ => rows.push(TimelineRow {
+                start: session.start,
+                end: session.end,
+                app_name: session.app_name.clone(),
+                detail,
+                longest: session.duration(),
+            }),
+        }
+    }
+    rows.retain(|row| row.end.since(row.start) >= MIN_TIMELINE);
+    rows
+}
+
+/// The generated part of the note for start-ordered `sessions` of one day.
+pub fn render(sessions: &[Session]) -> String {
+    let mut per_app: BTreeMap<&str, DurationSecs> = BTreeMap::new();
+    for session in sessions {
+        *per_app.entry(&session.app_name).or_default() += session.duration();
+    }
+    let mut apps: Vec<(&str, DurationSecs)> = per_app.into_iter().collect();
+    apps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let total: DurationSecs = apps.iter().map(|(_, length)| *length).sum();
+
+    let mut note = format!("{}\n## Tracked time\n\n{} in total.\n", START_MARKER, duration(total));
+    if !apps.is_empty() {
+        note.push_str("\n### Top apps\n\n| App | Time |\n| --- | ---: |\n");
+        for (app_name, length) in apps.iter().take(TOP_APPS) {
+            note.push_str(&format!("| {} | {} |\n", cell(app_name), duration(*length)));
+        }
+    }
+
+    let focus: Vec<blocks::WorkBlock> = blocks::cluster(sessions, blocks::MAX_GAP)
+        .into_iter()
+        .filter(|block| block.active_secs >= FOCUS_BLOCK)
+        .collect();
+    if !focus.is_empty() {
+        note.push_str("\n### Focus blocks\n\n");
+        for block in focus {
+            let apps: Vec<&str> = block.top_apps.iter().map(|app| app.app_name.as_str()).collect();
+            note.push_str(&format!(
+                "- {}–{} ({} active): {}\n",
+                clock(block.start),
+                clock(block.end),
+                duration(block.active_secs),
+                apps.join(", ")
+            ));
+        }
+    }
+
+    let rows = timeline(sessions);
+    if !rows.is_empty() {
+        note.push_str("\n### Timeline\n\n| From | To | App | Window |\n| --- | --- | --- | --- |\n");
+        for row in rows {
+            note.push_str(&format!("| {} | {} | {} | {} |\n", clock(row.start), clock(row.end), cell(&row.app_name), cell(&row.detail)));
+        }
+    }
+    note.push_str(END_MARKER);
+    note
+}
+
+/// Puts `section` into the note's existing text, or starts a new note.
+fn merge(existing: Option<&str>, date: NaiveDate, section: &str) -> String {
+    let Some(existing) = existing else {
+        return format!("# {}\n\n{}\n\n## Notes\n\n", date.format("%Y-%m-%d"), section);
+    };
+    match (existing.find(START_MARKER), existing.find(END_MARKER)) {
+        (Some(start), Some(end)) if start < end => {
+            format!("{}{}{}", &existing[..start], section, &existing[end + END_MARKER.len()..])
+        }
+        _ => format!("{}\n\n{}\n", existing.trim_end(), section),
+    }
+}
+
+// The pattern may name subfolders, e.g. `%Y/%m/%Y-%m-%d.md`
+fn path_for(dir: &str, config: &DailyNoteConfig, date: NaiveDate) -> Result<PathBuf, String> {
+    let mut file_name = String::new();
+    write!(file_name, "{}", date.format(&config.file_name))
+        .map_err(|_| format!("Invalid daily_notes.file_name '{}'", config.file_name))?;
+    Ok(Path::new(dir).join(file_name))
+}
+
+/// Writes or updates the note of `date` in `dir`. Without `keep_empty`, a day
+/// with nothing tracked and no note yet gets none.
+pub fn write(
+    conn: &Connection,
+    config: &DailyNoteConfig,
+    dir: &str,
+    date: NaiveDate,
+    keep_empty: bool,
+) -> Result<Option<PathBuf>, String> {
+    let (since, until) = reports::day_span(date);
+    let sessions = sessions::load_sessions(conn, since, until)
+        .map_err(|e| format!("Failed to load sessions: {}", e))?;
+    let path = path_for(dir, config, date)?;
+    let existing = fs::read_to_string(&path).ok();
+    if sessions.is_empty() && existing.is_none() && !keep_empty {
+        return Ok(None);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&path, merge(existing.as_deref(), date, &render(&sessions)))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(Some(path))
+}
+
+/// `sysmonitor note [--date YYYY-MM-DD] [--dir PATH]`; today by default.
+pub fn run_command(conn: &Connection, config: &DailyNoteConfig, args: &[String]) -> Result<(), String> {
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let date = match option("--date") {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date))?,
+        None => Local::now().date_naive(),
+    };
+    let Some(dir) = option("--dir").or(config.dir.as_ref()) else {
+        return Err("Set daily_notes.dir in the config or pass --dir PATH".to_string());
+    };
+    if let Some(path) = write(conn, config, dir, date, true)? {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifier;
+
+    fn session(app: &str, title: &str, start: u64, end: u64) -> Session {
+        let (start, end) = (Timestamp::from_secs(start), Timestamp::from_secs(end));
+        Session { identifier: identifier::encode(app, title, None), app_name: app.to_string(), start, end }
+    }
+
+    #[test]
+    fn notes_list_apps_focus_blocks_and_a_timeline() {
+        let base = 1_700_000_000;
+        let sessions = [
+            session("code.exe", "main.rs | sysmonitor", base, base + 1800),
+            session("code.exe", "web.rs", base + 1830, base + 1900),
+            session("x.exe", "Home", base + 1900, base + 1930), // Too short for the timeline
+        ];
+        let note = render(&sessions);
+        assert!(note.starts_with(START_MARKER) && note.ends_with(END_MARKER));
+        assert!(note.contains("31m in total.") && note.contains("| code.exe | 31m |\n| x.exe | 0m |"));
+        assert!(note.contains("### Focus blocks\n\n- ") && note.contains("(31m active): code.exe, x.exe"));
+        // Both code.exe sessions make one row, named after the longer one
+        assert!(note.contains("| code.exe | main.rs \\| sysmonitor |\n") && !note.contains("Home |"));
+    }
+
+    #[test]
+    fn rewriting_keeps_what_the_user_wrote() {
+        let date = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        let first = merge(None, date, &format!("{}\nold\n{}", START_MARKER, END_MARKER));
+        assert!(first.starts_with("# 2026-10-14\n\n") && first.ends_with("## Notes\n\n"));
+        let edited = format!("{}Called the bank.\n", first);
+        let section = format!("{}\nnew\n{}", START_MARKER, END_MARKER);
+        let rewritten = merge(Some(&edited), date, &section);
+        assert!(rewritten.contains("\nnew\n") && !rewritten.contains("old") && rewritten.ends_with("Called the bank.\n"));
+        // A note started in Obsidian gets the section appended
+        assert_eq!(merge(Some("# Today\n"), date, &section), format!("# Today\n\n{}\n", section));
+    }
+}
@@ -0,0 +1,329 @@
+// Per-day totals by app and category kept in `daily_summary`.
+//
+// The dashboard asks for today's totals on every load, and working them out
+// means going through the day's sessions again. Instead each flush adds what
+// its rows grew by since the last one; `daily_summary_sessions` remembers how
+// long each recent session was when last flushed. Everything else that writes
+// sessions (imports, collectors, edits, retention, labels set by hand) is
+// caught by triggers, which move `dirty_from` back to the earliest session
+// start they touched. `refresh` then rebuilds the days from there, and the
+// whole table when the category rules changed. Until it has, reads return
+// None and callers fall back to the raw sessions.
+
+use std::collections::BTreeMap;
+
+use chrono::{Days, NaiveDate};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+
+use crate::assignments::{self, CategoryTotal, CategoryTotals};
+use crate::categories::{CategoryConfig, UNCATEGORIZED};
+use crate::identifier::ActivityKey;
+use crate::reports;
+use crate::units::{DurationSecs, Timestamp};
+
+const DAY_FORMAT: &str = "%Y-%m-%d";
+const OPEN: DurationSecs = DurationSecs::days(2); // How long a session's flushed length is remembered
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS daily_summary (
+            day TEXT NOT NULL,
+            app_name TEXT NOT NULL,
+            category TEXT NOT NULL,
+            seconds INTEGER NOT NULL,
+            sessions INTEGER NOT NULL,
+            PRIMARY KEY (day, app_name, category)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS daily_summary_sessions (
+            identifier TEXT NOT NULL,
+            start INTEGER NOT NULL,
+            duration INTEGER NOT NULL,
+            PRIMARY KEY (identifier, start)
+        )",
+        [],
+    )?;
+    // Rules the categories were taken from, and the earliest session start
+    // written since that isn't counted yet
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS daily_summary_state (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            rules_version TEXT NOT NULL,
+            dirty_from INTEGER
+        )",
+        [],
+    )?;
+    let mark = |start: &str| format!("UPDATE daily_summary_state SET dirty_from = MIN(COALESCE(dirty_from, {0}), {0});", start);
+    for (table, event, starts) in [
+        ("usage_logs", "INSERT", vec!["NEW.timestamp - NEW.duration"]),
+        ("usage_logs", "DELETE", vec!["OLD.timestamp - OLD.duration"]),
+        ("usage_logs", "UPDATE", vec!["OLD.timestamp - OLD.duration", "NEW.timestamp - NEW.duration"]),
+        ("session_labels", "INSERT", vec!["NEW.start"]),
+        ("session_labels", "DELETE", vec!["OLD.start"]),
+        ("session_labels", "UPDATE", vec!["OLD.start", "NEW.start"]),
+    ] {
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS daily_summary_{}_{} AFTER {} ON {} BEGIN {} END",
+            table,
+            event.to_lowercase(),
+            event,
+            table,
+            starts.iter().map(|start| mark(start)).collect::<String>()
+        ))?;
+    }
+    Ok(())
+}
+
+fn state(conn: &Connection) -> SqlResult<Option<(String, Option<i64>)>> {
+    conn.query_row("SELECT rules_version, dirty_from FROM daily_summary_state WHERE id = 1", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()
+}
+
+fn add(conn: &Connection, day: NaiveDate, app_name: &str, category: &str, seconds: i64, sessions: i64) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO daily_summary (day, app_name, category, seconds, sessions) VALUES (?1, ?2, ?3, MAX(?4, 0), ?5)
+         ON CONFLICT(day, app_name, category) DO UPDATE
+         SET seconds = MAX(seconds + excluded.seconds, 0), sessions = sessions + excluded.sessions",
+        params![day.format(DAY_FORMAT).to_string(), app_name, category, seconds, sessions],
+    )?;
+    Ok(())
+}
+
+/// The summary as a flush found it.
+pub struct Flush {
+    dirty_from: Option<i64>,
+}
+
+/// Starts keeping the summary up with a flush, inside its transaction; None
+/// when there is no summary yet, so `refresh` builds it whole.
+pub fn begin_flush(conn: &Connection) -> SqlResult<Option<Flush>> {
+    Ok(state(conn)?.map(|(_, dirty_from)| Flush { dirty_from }))
+}
+
+impl Flush {
+    /// Counts the seconds the session of `key` at `start` grew, or shrank, by
+    /// since it was last flushed.
+    pub fn record(&self, conn: &Connection, categories: &CategoryConfig, key: &ActivityKey, start: Timestamp, duration: DurationSecs) -> SqlResult<()> {
+        let identifier = key.to_string();
+        let before: DurationSecs = conn
+            .query_row(
+                "SELECT duration FROM daily_summary_sessions WHERE identifier = ?1 AND start = ?2",
+                params![identifier, start],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or_default();
+        if before == duration {
+            return Ok(());
+        }
+        conn.execute(
+            "INSERT INTO daily_summary_sessions (identifier, start, duration) VALUES (?1, ?2, ?3)
+             ON CONFLICT(identifier, start) DO UPDATE SET duration = excluded.duration",
+            params![identifier, start, duration],
+        )?;
+        let category = categories.categorize(key).unwrap_or(UNCATEGORIZED);
+        let (from, to, sign) = if duration > before { (start + before, start + duration, 1) } else { (start + duration, start + before, -1) };
+        for (index, (day, seconds)) in reports::split_by_day(from.as_secs(), to.as_secs()).into_iter().enumerate() {
+            let new_session = before == DurationSecs::ZERO && index == 0;
+            add(conn, day, &key.app_name, category, sign * seconds as i64, new_session as i64)?;
+        }
+        Ok(())
+    }
+
+    /// Takes back the marks the flush's own rows left, as they are counted.
+    pub fn finish(self, conn: &Connection) -> SqlResult<()> {
+        conn.execute("UPDATE daily_summary_state SET dirty_from = ?1 WHERE id = 1", params![self.dirty_from])?;
+        Ok(())
+    }
+}
+
+/// Rebuilds what changed outside the flushes, or everything after the rules
+/// changed. Returns how many days were written.
+pub fn refresh(conn: &mut Connection, categories: &CategoryConfig, now: Timestamp) -> SqlResult<usize> {
+    let version = assignments::rules_version(categories);
+    let tx = conn.transaction()?;
+    let from_day = match state(&tx)? {
+        Some((built, None)) if built == version => {
+            tx.execute("DELETE FROM daily_summary_sessions WHERE start < ?1", params![now.saturating_sub(OPEN)])?;
+            tx.commit()?;
+            return Ok(0);
+        }
+        Some((built, Some(dirty_from))) if built == version => Some(reports::local_date(dirty_from.max(0) as u64)),
+        _ => None,
+    };
+    match from_day {
+        Some(day) => tx.execute("DELETE FROM daily_summary WHERE day >= ?1", params![day.format(DAY_FORMAT).to_string()])?,
+        None => tx.execute("DELETE FROM daily_summary", [])?,
+    };
+
+    // The tracker ends sessions at midnight, so the day before has every
+    // session reaching into the first rebuilt one
+    let since = from_day.map_or(0, |day| reports::day_bounds(day - Days::new(1)).0.max(0) as u64);
+    let (sessions, _) = assignments::categorized_sessions(&tx, categories, &version, since, i64::MAX as u64)?;
+    let mut totals: BTreeMap<(NaiveDate, String, String), (u64, u64)> = BTreeMap::new();
+    {
+        let mut remember = tx.prepare(
+            "INSERT INTO daily_summary_sessions (identifier, start, duration) VALUES (?1, ?2, ?3)
+             ON CONFLICT(identifier, start) DO UPDATE SET duration = excluded.duration",
+        )?;
+        for session in sessions {
+            let app_name = ActivityKey::parse(&session.identifier).app_name;
+            for (index, (day, seconds)) in reports::split_by_day(session.start, session.start + session.seconds).into_iter().enumerate() {
+                if from_day.is_none_or(|from_day| day >= from_day) {
+                    let total = totals.entry((day, app_name.clone(), session.category.clone())).or_default();
+                    total.0 += seconds;
+                    total.1 += (index == 0) as u64;
+                }
+            }
+            if Timestamp::from_secs(session.start) + OPEN >= now {
+                remember.execute(params![session.identifier, session.start, session.seconds])?;
+            }
+        }
+    }
+    for ((day, app_name, category), (seconds, sessions)) in &totals {
+        add(&tx, *day, app_name, category, *seconds as i64, *sessions as i64)?;
+    }
+    tx.execute("DELETE FROM daily_summary_sessions WHERE start < ?1", params![now.saturating_sub(OPEN)])?;
+    tx.execute(
+        "INSERT INTO daily_summary_state (id, rules_version, dirty_from) VALUES (1, ?1, NULL)
+         ON CONFLICT(id) DO UPDATE SET rules_version = excluded.rules_version, dirty_from = NULL",
+        params![version],
+    )?;
+    tx.commit()?;
+    let mut days: Vec<NaiveDate> = totals.keys().map(|(day, _, _)| *day).collect();
+    days.dedup();
+    Ok(days.len())
+}
+
+/// Seconds per app on `day`, largest first; None while the summary is behind.
+pub fn app_totals(conn: &Connection, day: NaiveDate) -> SqlResult<Option<Vec<(String, DurationSecs)>>> {
+    if !matches!(state(conn)?, Some((_, None))) {
+        return Ok(None);
+    }
+    let mut stmt = conn.prepare(
+        "SELECT app_name, SUM(seconds) AS total FROM daily_summary WHERE day = ?1
+         GROUP BY app_name HAVING total > 0 ORDER BY total DESC, app_name",
+    )?;
+    let rows = stmt.query_map(params![day.format(DAY_FORMAT).to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect::<SqlResult<_>>().map(Some)
+}
+
+// Seconds per day and category in `[from, until)`, when the summary is up to
+// date under `categories`' rules
+fn categories_by_day(conn: &Connection, categories: &CategoryConfig, from: NaiveDate, until: NaiveDate) -> SqlResult<Option<Vec<(NaiveDate, String, u64)>>> {
+    let version = assignments::rules_version(categories);
+    if !matches!(state(conn)?, Some((built, None)) if built == version) {
+        return Ok(None);
+    }
+    let mut stmt = conn.prepare(
+        "SELECT day, category, SUM(seconds) AS total FROM daily_summary WHERE day >= ?1 AND day < ?2
+         GROUP BY day, category HAVING total > 0 ORDER BY day, category",
+    )?;
+    let rows = stmt.query_map(params![from.format(DAY_FORMAT).to_string(), until.format(DAY_FORMAT).to_string()], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?.max(0) as u64))
+    })?;
+    let mut totals = Vec::new();
+    for row in rows {
+        let (day, category, seconds) = row?;
+        if let Ok(day) = NaiveDate::parse_from_str(&day, DAY_FORMAT) {
+            totals.push((day, category, seconds));
+        }
+    }
+    Ok(Some(totals))
+}
+
+/// As `assignments::totals` for `day`; None while the summary is behind or
+/// was built under other rules.
+pub fn category_totals(conn: &Connection, categories: &CategoryConfig, day: NaiveDate) -> SqlResult<Option<CategoryTotals>> {
+    let Some(totals) = categories_by_day(conn, categories, day, day.succ_opt().unwrap_or(day))? else {
+        return Ok(None);
+    };
+    let mut totals: Vec<CategoryTotal> = totals.into_iter().map(|(_, category, seconds)| CategoryTotal { category, seconds }).collect();
+    totals.sort_by_key(|total| std::cmp::Reverse(total.seconds));
+    Ok(Some(CategoryTotals { rules_version: assignments::rules_version(categories), pending: 0, categories: totals }))
+}
+
+/// As `assignments::daily_totals` for the days in `[from, until)`.
+pub fn daily_totals(conn: &Connection, categories: &CategoryConfig, from: NaiveDate, until: NaiveDate) -> SqlResult<Option<Vec<(NaiveDate, String, u64)>>> {
+    categories_by_day(conn, categories, from, until)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, identifier};
+
+    fn db() -> Connection {
+        fixtures::empty().unwrap()
+    }
+
+    fn rules(app: &str) -> CategoryConfig {
+        serde_json::from_value(serde_json::json!({ "rules": [{ "category": "Dev", "app": app }] })).unwrap()
+    }
+
+    // A flush of the session of `app` at `start`, as the tracker writes it
+    fn flush(conn: &mut Connection, categories: &CategoryConfig, app: &str, start: u64, duration: u64) {
+        let (start, duration) = (Timestamp::from_secs(start), DurationSecs::secs(duration));
+        let key = ActivityKey::parse(&identifier::encode(app, "x", None));
+        let tx = conn.transaction().unwrap();
+        let summary = begin_flush(&tx).unwrap();
+        tx.execute(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?2, 'x', ?3, ?4)",
+            params![key.to_string(), key.app_name, start + duration, duration],
+        )
+        .unwrap();
+        if let Some(summary) = summary {
+            summary.record(&tx, categories, &key, start, duration).unwrap();
+            summary.finish(&tx).unwrap();
+        }
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn flushes_add_up_and_other_writes_wait_for_a_refresh() {
+        let mut conn = db();
+        let categories = rules("code.exe");
+        let day = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let nine = reports::day_bounds(day).0 as u64 + 9 * 3600;
+        let now = Timestamp::from_secs(nine) + DurationSecs::hours(1);
+        flush(&mut conn, &categories, "code.exe", nine, 60);
+        assert_eq!(app_totals(&conn, day).unwrap(), None);
+        assert_eq!(refresh(&mut conn, &categories, now).unwrap(), 1);
+
+        // The same session flushed twice more, then a new one
+        flush(&mut conn, &categories, "code.exe", nine, 120);
+        flush(&mut conn, &categories, "code.exe", nine, 300);
+        flush(&mut conn, &categories, "chrome.exe", nine + 300, 100);
+        assert_eq!(app_totals(&conn, day).unwrap(), Some(vec![("code.exe".to_string(), DurationSecs::secs(300)), ("chrome.exe".to_string(), DurationSecs::secs(100))]));
+        let totals = category_totals(&conn, &categories, day).unwrap().unwrap();
+        let by_category: Vec<(&str, u64)> = totals.categories.iter().map(|total| (total.category.as_str(), total.seconds)).collect();
+        assert_eq!(by_category, vec![("Dev", 300), (UNCATEGORIZED, 100)]);
+        let sessions: i64 = conn.query_row("SELECT SUM(sessions) FROM daily_summary", [], |row| row.get(0)).unwrap();
+        assert_eq!(sessions, 2);
+        assert_eq!(refresh(&mut conn, &categories, now).unwrap(), 0);
+
+        // A row written elsewhere, say an import, is only counted after a refresh
+        conn.execute(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, 'vlc.exe', 'x', ?2, 50)",
+            params![identifier::encode("vlc.exe", "x", None), nine + 1000],
+        )
+        .unwrap();
+        assert_eq!(app_totals(&conn, day).unwrap(), None);
+        assert_eq!(refresh(&mut conn, &categories, now).unwrap(), 1);
+        assert_eq!(app_totals(&conn, day).unwrap().unwrap().len(), 3);
+
+        // Other rules: apps still add up, categories wait for the rebuild
+        let other = rules("chrome.exe");
+        assert!(app_totals(&conn, day).unwrap().is_some());
+        assert_eq!(category_totals(&conn, &other, day).unwrap(), None);
+        refresh(&mut conn, &other, now).unwrap();
+        let daily = daily_totals(&conn, &other, day, day.succ_opt().unwrap()).unwrap().unwrap();
+        assert!(daily.contains(&(day, "Dev".to_string(), 100)));
+
+        // Flushing after the rebuild goes on from the stored length
+        flush(&mut conn, &other, "code.exe", nine, 360);
+        assert_eq!(app_totals(&conn, day).unwrap().unwrap()[0], ("code.exe".to_string(), DurationSecs::secs(360)));
+    }
+}
@@ -0,0 +1,183 @@
+// The dashboard's layout, kept server-side so it follows the user across
+// browsers and is there for any other frontend.
+//
+// A layout places each widget (a dashboard card, by id) on a grid and lists
+// the panels that are turned on and the saved queries bookmarked as reports
+// (see `queries`). It is stored as JSON under one key of the
+// `settings` table; until one is saved, `load` returns the built-in
+// arrangement. Ids are not checked against the cards this dashboard has, so a
+// frontend with widgets of its own can keep them here too.
+
+use std::collections::BTreeSet;
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::units::Timestamp;
+
+const LAYOUT_KEY: &str = "dashboard.layout";
+const DEFAULT_COLUMNS: u32 = 2;
+const MAX_COLUMNS: u32 = 24;
+const MAX_ROWS: u32 = 100;
+const MAX_ITEMS: usize = 50; // Widgets, panels and bookmarks each
+const MAX_ID_LEN: usize = 64;
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Widget {
+    pub id: String, // e.g. `current-activity`
+    pub column: u32, // From 0
+    pub row: u32,
+    #[serde(default = "one")]
+    pub width: u32, // In columns
+    #[serde(default = "one")]
+    pub height: u32, // In rows
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    #[serde(default = "default_columns")]
+    pub columns: u32,
+    pub widgets: Vec<Widget>,
+    #[serde(default)]
+    pub panels: Vec<String>, // Ids of the panels turned on, in order
+    #[serde(default)]
+    pub bookmarks: Vec<String>, // Names of saved queries, in order
+}
+
+fn default_columns() -> u32 {
+    DEFAULT_COLUMNS
+}
+
+impl Default for Layout {
+    // The cards of `web/index.html` as they are laid out without a saved layout
+    fn default() -> Self {
+        let widget = |id: &str, column, row, width| Widget { id: id.to_string(), column, row, width, height: 1, hidden: false };
+        Self {
+            columns: DEFAULT_COLUMNS,
+            widgets: vec![
+                widget("current-activity", 0, 0, 1),
+                widget("statistics", 1, 0, 1),
+                widget("weekly-goals", 0, 1, 2),
+                widget("recent-activity", 0, 2, 2),
+            ],
+            panels: Vec::new(),
+            bookmarks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredLayout {
+    #[serde(flatten)]
+    pub layout: Layout,
+    pub saved: bool, // False for the built-in layout
+    pub updated_at: Option<Timestamp>,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn validate_id(kind: &str, id: &str, seen: &mut BTreeSet<String>) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_ID_LEN || !id.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.') {
+        return Err(format!("{} id '{}' must be 1 to {} letters, digits, '-', '_' or '.'", kind, id, MAX_ID_LEN));
+    }
+    if !seen.insert(id.to_string()) {
+        return Err(format!("{} '{}' is listed more than once", kind, id));
+    }
+    Ok(())
+}
+
+pub fn validate(layout: &Layout) -> Result<(), String> {
+    if layout.columns == 0 || layout.columns > MAX_COLUMNS {
+        return Err(format!("columns must be between 1 and {}", MAX_COLUMNS));
+    }
+    if [layout.widgets.len(), layout.panels.len(), layout.bookmarks.len()].iter().any(|count| *count > MAX_ITEMS) {
+        return Err(format!("at most {} widgets, panels and bookmarks each can be placed", MAX_ITEMS));
+    }
+    let mut seen = BTreeSet::new();
+    for widget in &layout.widgets {
+        validate_id("widget", &widget.id, &mut seen)?;
+        if widget.width == 0 || widget.column + widget.width > layout.columns {
+            return Err(format!("widget '{}' must fit within the {} columns", widget.id, layout.columns));
+        }
+        if widget.height == 0 || widget.row + widget.height > MAX_ROWS {
+            return Err(format!("widget '{}' must fit within {} rows", widget.id, MAX_ROWS));
+        }
+    }
+    let mut seen = BTreeSet::new();
+    for panel in &layout.panels {
+        validate_id("panel", panel, &mut seen)?;
+    }
+    let mut seen = BTreeSet::new();
+    for bookmark in &layout.bookmarks {
+        validate_id("bookmark", bookmark, &mut seen)?;
+    }
+    Ok(())
+}
+
+/// The saved layout, or the built-in one.
+pub fn load(conn: &Connection) -> SqlResult<StoredLayout> {
+    let stored: Option<(String, Timestamp)> = conn
+        .query_row("SELECT value, updated_at FROM settings WHERE key = ?1", params![LAYOUT_KEY], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()?;
+    // A value this version can't read (e.g. written by a newer one) falls back too
+    Ok(match stored.and_then(|(value, updated_at)| Some((serde_json::from_str::<Layout>(&value).ok()?, updated_at))) {
+        Some((layout, updated_at)) => StoredLayout { layout, saved: true, updated_at: Some(updated_at) },
+        None => StoredLayout { layout: Layout::default(), saved: false, updated_at: None },
+    })
+}
+
+pub fn save(conn: &Connection, layout: &Layout, now: Timestamp) -> SqlResult<()> {
+    let value = serde_json::to_string(layout).unwrap_or_default();
+    conn.execute(
+        "INSERT INTO settings (key, value, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+        params![LAYOUT_KEY, value, now],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_layouts_replace_the_built_in_one() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        let built_in = load(&conn).unwrap();
+        assert!(!built_in.saved && built_in.layout == Layout::default());
+        assert!(validate(&built_in.layout).is_ok());
+
+        let mut layout: Layout = serde_json::from_str(
+            r#"{"widgets": [{"id": "recent-activity", "column": 0, "row": 0}, {"id": "statistics", "column": 1, "row": 0, "hidden": true}],
+                "panels": ["focus-by-hour"]}"#,
+        )
+        .unwrap();
+        assert_eq!((layout.columns, layout.widgets[0].width), (DEFAULT_COLUMNS, 1));
+        save(&conn, &layout, Timestamp::from_secs(1000)).unwrap();
+        let stored = load(&conn).unwrap();
+        assert_eq!((stored.saved, stored.updated_at), (true, Some(Timestamp::from_secs(1000))));
+        assert_eq!(stored.layout, layout);
+
+        layout.widgets[1].width = 2;
+        assert!(validate(&layout).unwrap_err().contains("columns"));
+        layout.widgets[1] = Widget { id: "recent-activity".to_string(), ..layout.widgets[0].clone() };
+        assert!(validate(&layout).unwrap_err().contains("more than once"));
+    }
+}
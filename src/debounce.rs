@@ -0,0 +1,63 @@
+// Debouncing of quick focus switches, from the `debounce` section of the config.
+//
+// Alt-tabbing through windows gives each a focus of a second or less. A session
+// shorter than `min_session_secs` is never stored, and with `fold` on, coming
+// back to a window within that time picks its session up again, so the glance
+// elsewhere counts towards it instead of splitting it in two.
+
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::units::DurationSecs;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebounceConfig {
+    pub min_session_secs: u64, // 0 stores every session
+    pub fold: bool, // `false` drops short focuses but still ends the session around them
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self { min_session_secs: 2, fold: true }
+    }
+}
+
+impl DebounceConfig {
+    /// Whether a session of `duration` is stored.
+    pub fn keeps(&self, duration: DurationSecs) -> bool {
+        duration > DurationSecs::ZERO && duration >= self.min_session()
+    }
+
+    /// The shortest session stored, and how soon a return picks one up again.
+    pub fn min_session(&self) -> DurationSecs {
+        DurationSecs::secs(self.min_session_secs)
+    }
+
+    /// Whether a window whose session stopped at `left` continues it at `now`
+    /// rather than starting a new one.
+    pub fn resumes(&self, left: Option<Instant>, now: Instant) -> bool {
+        self.fold && left.is_some_and(|left| DurationSecs::from(now.saturating_duration_since(left)) < self.min_session())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn short_focuses_are_dropped_and_returns_fold_in() {
+        let config = DebounceConfig::default();
+        assert!(!config.keeps(DurationSecs::ZERO) && !config.keeps(DurationSecs::secs(1)) && config.keeps(DurationSecs::secs(2)));
+        let left = Instant::now();
+        assert!(config.resumes(Some(left), left + Duration::from_millis(1500)));
+        assert!(!config.resumes(Some(left), left + Duration::from_secs(2)));
+        assert!(!config.resumes(None, left));
+        assert!(!DebounceConfig { fold: false, ..config }.resumes(Some(left), left));
+
+        let off = DebounceConfig { min_session_secs: 0, fold: true };
+        assert!(off.keeps(DurationSecs::secs(1)) && !off.resumes(Some(left), left));
+    }
+}
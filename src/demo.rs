@@ -0,0 +1,100 @@
+// `sysmonitor demo`: the dashboard over synthetic usage data.
+//
+// A fresh database is filled with weeks of one persona's usage from
+// `fixtures`: workdays with a morning and an afternoon stretch around lunch,
+// lighter weekends, and sessions drawn from the persona's apps, window titles
+// and sites. The same seed gives the same data, so screenshots can be retaken.
+// The database lives in the temp directory and is removed on exit; nothing
+// is tracked and the real database is never opened.
+
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::Connection;
+
+use crate::fixtures::{self, Persona, Shape};
+use crate::{web, SystemMonitor};
+
+const DEMO_PORT: u16 = 3031; // Next to a running tracker's dashboard
+const DEFAULT_WEEKS: u32 = 4;
+const MAX_WEEKS: u32 = 52;
+const DEFAULT_SEED: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemoOptions {
+    pub weeks: u32,
+    pub persona: Persona,
+    pub seed: u64,
+    pub bind: SocketAddr,
+}
+
+impl DemoOptions {
+    // `demo [--weeks N] [--persona developer|designer|student] [--seed N] [--bind ADDR]`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let option = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|index| args.get(index + 1))
+                .map(String::as_str)
+        };
+        let weeks = match option("--weeks") {
+            Some(weeks) => weeks.parse().ok().filter(|weeks| (1..=MAX_WEEKS).contains(weeks))
+                .ok_or_else(|| format!("--weeks must be between 1 and {}, got '{}'", MAX_WEEKS, weeks))?,
+            None => DEFAULT_WEEKS,
+        };
+        let seed = match option("--seed") {
+            Some(seed) => seed.parse().map_err(|_| format!("--seed must be a number, got '{}'", seed))?,
+            None => DEFAULT_SEED,
+        };
+        let bind = match option("--bind") {
+            Some(bind) => bind.parse().map_err(|_| format!("--bind must be host:port, got '{}'", bind))?,
+            None => SocketAddr::from(([127, 0, 0, 1], DEMO_PORT)),
+        };
+        Ok(Self { weeks, persona: Persona::parse(option("--persona").unwrap_or("developer"))?, seed, bind })
+    }
+}
+
+/// `sysmonitor demo [...]`
+pub async fn command(args: &[String], debug_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let options = DemoOptions::from_args(args)?;
+    let path = env::temp_dir().join(format!("sysmonitor-demo-{}.db", std::process::id()));
+    let mut monitor = SystemMonitor::new(debug_mode);
+    monitor.db_path = path.to_string_lossy().to_string();
+    monitor.init_database()?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let shape = Shape { days: options.weeks * 7, persona: options.persona, seed: options.seed, ..Shape::default() };
+    let sessions = fixtures::generate(&mut Connection::open(&monitor.db_path)?, &shape, now)?;
+    monitor.refresh_rollups()?;
+    monitor.refresh_categories()?;
+    monitor.load_existing_data()?;
+    println!(
+        "Demo: {} weeks of {} usage ({} sessions) at http://{}",
+        options.weeks,
+        options.persona.name(),
+        sessions,
+        options.bind
+    );
+    println!("Data is in {} and removed on exit (Ctrl+C)", monitor.db_path);
+
+    tokio::select! {
+        _ = web::start_web_server(Arc::new(monitor), options.bind) => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_default_to_four_weeks_of_a_developer() {
+        let options = DemoOptions::from_args(&["--seed".into(), "7".into()]).unwrap();
+        assert_eq!((options.weeks, options.persona, options.seed), (DEFAULT_WEEKS, Persona::Developer, 7));
+        assert!(DemoOptions::from_args(&["--weeks".into(), "0".into()]).is_err());
+    }
+}
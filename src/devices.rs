@@ -0,0 +1,180 @@
+// Collector devices seen by this server.
+//
+// A device checks in with every ingest batch (named by the batch's `device`,
+// or by its token) and with `POST /api/devices/heartbeat` while it has nothing
+// to upload. Devices silent for longer than `offline_after_mins` are offline;
+// each outage raises one `device_offline` notification. Collectors that send
+// their clock along have its latest skew recorded.
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::units::{DurationSecs, Timestamp};
+
+const MAX_DEVICE_ID_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    pub offline_after_mins: u64,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self { offline_after_mins: 15 }
+    }
+}
+
+impl DeviceConfig {
+    pub fn offline_after(&self) -> DurationSecs {
+        DurationSecs::mins(self.offline_after_mins.max(1))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Device {
+    pub id: String,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+    pub last_events: u64, // Events in the latest batch; 0 for a heartbeat
+    pub total_events: u64,
+    pub clock_skew: Option<i64>, // Latest collector clock minus server clock
+    pub online: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Heartbeat {
+    pub device: Option<String>, // The token's name when missing
+    pub sent_at: Option<Timestamp>,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS devices (
+            id TEXT PRIMARY KEY,
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL,
+            last_events INTEGER NOT NULL DEFAULT 0,
+            total_events INTEGER NOT NULL DEFAULT 0,
+            alerted INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn validate_id(id: &str) -> Result<&str, String> {
+    let id = id.trim();
+    if id.is_empty() || id.len() > MAX_DEVICE_ID_LEN || id.chars().any(char::is_control) {
+        return Err(format!("device must be 1 to {} printable characters", MAX_DEVICE_ID_LEN));
+    }
+    Ok(id)
+}
+
+/// Records that `id` reported, clearing any offline alert.
+pub fn check_in(conn: &Connection, id: &str, events: u64, clock_skew: Option<i64>, now: Timestamp) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO devices (id, first_seen, last_seen, last_events, total_events, alerted, clock_skew)
+         VALUES (?1, ?2, ?2, ?3, ?3, 0, ?4)
+         ON CONFLICT(id) DO UPDATE SET
+             last_seen = MAX(last_seen, excluded.last_seen),
+             last_events = excluded.last_events,
+             total_events = total_events + excluded.last_events,
+             alerted = 0,
+             clock_skew = COALESCE(excluded.clock_skew, clock_skew)",
+        params![id, now, events, clock_skew],
+    )?;
+    Ok(())
+}
+
+pub fn list(conn: &Connection, config: &DeviceConfig, now: Timestamp) -> SqlResult<Vec<Device>> {
+    let offline_before = now - config.offline_after();
+    let mut stmt = conn.prepare(
+        "SELECT id, first_seen, last_seen, last_events, total_events, clock_skew FROM devices
+         ORDER BY last_seen DESC, id",
+    )?;
+    let devices = stmt
+        .query_map([], |row| {
+            let last_seen = row.get(2)?;
+            Ok(Device {
+                id: row.get(0)?,
+                first_seen: row.get(1)?,
+                last_seen,
+                last_events: row.get::<_, i64>(3)? as u64,
+                total_events: row.get::<_, i64>(4)? as u64,
+                clock_skew: row.get(5)?,
+                online: last_seen >= offline_before,
+            })
+        })?
+        .collect();
+    devices
+}
+
+/// Devices that went offline since the last call; each is returned once per outage.
+pub fn newly_offline(conn: &Connection, config: &DeviceConfig, now: Timestamp) -> SqlResult<Vec<Device>> {
+    let offline_before = now - config.offline_after();
+    let devices: Vec<Device> = list(conn, config, now)?.into_iter().filter(|device| !device.online).collect();
+    let mut alerted = Vec::new();
+    for device in devices {
+        let updated = conn.execute(
+            "UPDATE devices SET alerted = 1 WHERE id = ?1 AND alerted = 0 AND last_seen < ?2",
+            params![device.id, offline_before],
+        )?;
+        if updated > 0 {
+            alerted.push(device);
+        }
+    }
+    Ok(alerted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        conn.execute_batch("ALTER TABLE devices ADD COLUMN clock_skew INTEGER").unwrap();
+        conn
+    }
+
+    fn at(secs: u64) -> Timestamp {
+        Timestamp::from_secs(secs)
+    }
+
+    #[test]
+    fn check_ins_accumulate_and_set_online_status() {
+        let conn = db();
+        let config = DeviceConfig::default();
+        check_in(&conn, "laptop", 120, None, at(1000)).unwrap();
+        check_in(&conn, "laptop", 30, Some(-400), at(1100)).unwrap();
+        check_in(&conn, "laptop", 0, None, at(1100)).unwrap();
+        check_in(&conn, "desktop", 0, None, at(100)).unwrap();
+
+        let devices = list(&conn, &config, at(1200)).unwrap();
+        assert_eq!(devices[0].id, "laptop");
+        assert_eq!((devices[0].first_seen, devices[0].last_events, devices[0].total_events), (at(1000), 0, 150));
+        assert_eq!(devices[0].clock_skew, Some(-400));
+        assert!(devices[0].online && !devices[1].online);
+    }
+
+    #[test]
+    fn offline_devices_alert_once_per_outage() {
+        let conn = db();
+        let config = DeviceConfig { offline_after_mins: 10 };
+        check_in(&conn, "laptop", 5, None, at(0)).unwrap();
+        assert!(newly_offline(&conn, &config, at(300)).unwrap().is_empty());
+        assert_eq!(newly_offline(&conn, &config, at(700)).unwrap().len(), 1);
+        assert!(newly_offline(&conn, &config, at(800)).unwrap().is_empty());
+
+        // Reporting again re-arms the alert
+        check_in(&conn, "laptop", 0, None, at(900)).unwrap();
+        assert_eq!(newly_offline(&conn, &config, at(1600)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn device_ids_are_validated() {
+        assert_eq!(validate_id(" laptop ").unwrap(), "laptop");
+        assert!(validate_id("").is_err() && validate_id(&"x".repeat(65)).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+// Splits generic runtime hosts into the logical apps they run.
+//
+// Every Java desktop app runs as `javaw.exe`/`java.exe` and unbranded Electron
+// apps as `electron.exe`, so the exe name alone lumps unrelated apps together.
+// The command line (jar or main class, Electron app path) or the install folder
+// tells them apart; the window class is the last resort.
+
+use std::fs;
+use std::path::Path;
+
+const JAVA_HOSTS: [&str; 2] = ["javaw.exe", "java.exe"];
+const ELECTRON_HOSTS: [&str; 1] = ["electron.exe"];
+
+// Java launcher options that consume the following argument
+const JAVA_OPTIONS_WITH_VALUE: [&str; 6] = ["-cp", "-classpath", "--class-path", "-p", "--module-path", "--add-modules"];
+
+pub fn is_disambiguated_host(app_name: &str) -> bool {
+    let lower = app_name.to_lowercase();
+    JAVA_HOSTS.contains(&lower.as_str()) || ELECTRON_HOSTS.contains(&lower.as_str())
+}
+
+/// Splits a Windows command line the way `CommandLineToArgvW` does for the
+/// common cases: whitespace separated, double quotes group, `\"` is a quote.
+pub fn split_command_line(command_line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+    let mut chars = command_line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+                has_arg = true;
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
+}
+
+fn file_stem(path: &str) -> Option<String> {
+    let name = path.rsplit(['\\', '/']).next()?;
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    (!stem.is_empty()).then(|| stem.to_string())
+}
+
+/// Jar name for `-jar app.jar`, otherwise the main class or module.
+pub fn java_app_from_command_line(command_line: &str) -> Option<String> {
+    let args = split_command_line(command_line);
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "-jar" {
+            return args.next().and_then(|jar| file_stem(jar));
+        }
+        if arg == "-m" || arg == "--module" {
+            return args.next().map(|module| module.split('/').next().unwrap_or(module).to_string());
+        }
+        if JAVA_OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+            args.next();
+            continue;
+        }
+        if !arg.starts_with('-') {
+            // Main class: keep the simple name of a fully-qualified class
+            return arg.rsplit('.').next().map(|name| name.to_string());
+        }
+    }
+    None
+}
+
+/// First positional argument of `electron.exe <app dir or asar>`.
+pub fn electron_app_from_command_line(command_line: &str) -> Option<String> {
+    split_command_line(command_line)
+        .iter()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .and_then(|path| file_stem(path.trim_end_matches(['\\', '/'])))
+}
+
+// Bundled Electron apps keep their metadata next to the exe
+fn electron_product_name(image_path: &str) -> Option<String> {
+    let dir = Path::new(image_path).parent()?;
+    let package = fs::read_to_string(dir.join("resources").join("app").join("package.json")).ok()?;
+    let package: serde_json::Value = serde_json::from_str(&package).ok()?;
+    package
+        .get("productName")
+        .or_else(|| package.get("name"))
+        .and_then(|name| name.as_str())
+        .map(|name| name.to_string())
+}
+
+fn install_folder(image_path: &str) -> Option<String> {
+    let mut parts = image_path.rsplit(['\\', '/']).skip(1);
+    parts.next().map(|dir| dir.to_string()).filter(|dir| !dir.is_empty())
+}
+
+/// Returns a logical app name such as `javaw.exe (minecraft)` for generic
+/// hosts, or `None` when `app_name` is not a host or nothing better is known.
+pub fn logical_app_name(
+    app_name: &str,
+    image_path: Option<&str>,
+    command_line: Option<&str>,
+    window_class: Option<&str>,
+) -> Option<String> {
+    let lower = app_name.to_lowercase();
+    let context = if JAVA_HOSTS.contains(&lower.as_str()) {
+        command_line
+            .and_then(java_app_from_command_line)
+            .or_else(|| window_class.map(|class| class.to_string()))
+    } else if ELECTRON_HOSTS.contains(&lower.as_str()) {
+        command_line
+            .and_then(electron_app_from_command_line)
+            .or_else(|| image_path.and_then(electron_product_name))
+            .or_else(|| image_path.and_then(install_folder))
+    } else {
+        None
+    }?;
+    Some(format!("{} ({})", app_name, context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_arguments_stay_together() {
+        assert_eq!(
+            split_command_line(r#""C:\Program Files\Java\bin\javaw.exe" -jar "C:\My Apps\tool.jar""#),
+            vec![r"C:\Program Files\Java\bin\javaw.exe", "-jar", r"C:\My Apps\tool.jar"]
+        );
+    }
+
+    #[test]
+    fn java_apps_are_named_by_jar_or_main_class() {
+        assert_eq!(java_app_from_command_line(r"javaw.exe -Xmx2G -jar C:\mc\launcher.jar"), Some("launcher".to_string()));
+        assert_eq!(java_app_from_command_line("java -cp lib/* org.example.Studio --flag"), Some("Studio".to_string()));
+        assert_eq!(java_app_from_command_line("javaw.exe"), None);
+    }
+
+    #[test]
+    fn electron_apps_are_named_by_app_path() {
+        assert_eq!(
+            logical_app_name("electron.exe", None, Some(r"electron.exe --inspect C:\apps\notes\"), None),
+            Some("electron.exe (notes)".to_string())
+        );
+        assert_eq!(logical_app_name("code.exe", None, Some("code.exe ."), None), None);
+    }
+}
@@ -0,0 +1,159 @@
+// Distraction chains: a short productive session that leads straight into a
+// long run of distracting ones ("quick check of Slack -> 40 minutes of
+// Twitter"). The short session is reported as the trigger.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use rusqlite::{Connection, Result as SqlResult};
+use serde::Serialize;
+
+use crate::categories::{CategoryConfig, Productivity};
+use crate::identifier::ActivityKey;
+use crate::reports;
+use crate::sessions::{self, Session};
+use crate::units::{DurationSecs, Timestamp};
+
+const TRIGGER_MAX: DurationSecs = DurationSecs::mins(5); // Longer productive sessions are real work, not a "quick check"
+const MIN_CHAIN: DurationSecs = DurationSecs::mins(10); // Distracted time needed to count as a chain
+const MAX_GAP: DurationSecs = DurationSecs::mins(2); // Breaks tolerated between sessions of one chain
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DistractionChain {
+    pub trigger_app: String,
+    pub trigger_start: Timestamp,
+    pub start: Timestamp, // First distracting session
+    pub end: Timestamp,
+    pub distracted_secs: DurationSecs,
+    pub distracting_apps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TriggerStats {
+    pub app_name: String,
+    pub chains: u64,
+    pub distracted_secs: DurationSecs,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DistractionReport {
+    pub date: String,
+    pub chain_count: u64,
+    pub distracted_secs: DurationSecs,
+    pub top_triggers: Vec<TriggerStats>,
+    pub chains: Vec<DistractionChain>,
+}
+
+/// Finds chains in start-ordered sessions.
+pub fn detect(sessions: &[Session], productivity: impl Fn(&Session) -> Productivity) -> Vec<DistractionChain> {
+    let mut chains = Vec::new();
+    let mut index = 0;
+    while index < sessions.len() {
+        let trigger = &sessions[index];
+        if productivity(trigger) != Productivity::Productive || trigger.duration() > TRIGGER_MAX {
+            index += 1;
+            continue;
+        }
+
+        let mut next = index + 1;
+        let mut end = trigger.end;
+        let mut distracted_secs = DurationSecs::ZERO;
+        let mut distracting_apps: Vec<String> = Vec::new();
+        while let Some(session) = sessions.get(next) {
+            if productivity(session) != Productivity::Distracting || session.start > end + MAX_GAP {
+                break;
+            }
+            distracted_secs += session.duration();
+            end = end.max(session.end);
+            if !distracting_apps.contains(&session.app_name) {
+                distracting_apps.push(session.app_name.clone());
+            }
+            next += 1;
+        }
+
+        if distracted_secs >= MIN_CHAIN {
+            chains.push(DistractionChain {
+                trigger_app: trigger.app_name.clone(),
+                trigger_start: trigger.start,
+                start: sessions[index + 1].start,
+                end,
+                distracted_secs,
+                distracting_apps,
+            });
+            index = next;
+        } else {
+            index += 1;
+        }
+    }
+    chains
+}
+
+pub fn daily_report(db_path: &str, date: NaiveDate, categories: &CategoryConfig) -> SqlResult<DistractionReport> {
+    let (start, end) = reports::day_span(date);
+    let conn = Connection::open(db_path)?;
+    let sessions = sessions::load_sessions(&conn, start, end)?;
+    let chains = detect(&sessions, |session| categories.productivity(&ActivityKey::parse(&session.identifier)));
+
+    let mut triggers: BTreeMap<&str, (u64, DurationSecs)> = BTreeMap::new();
+    for chain in &chains {
+        let stats = triggers.entry(&chain.trigger_app).or_default();
+        stats.0 += 1;
+        stats.1 += chain.distracted_secs;
+    }
+    let mut top_triggers: Vec<TriggerStats> = triggers
+        .into_iter()
+        .map(|(app_name, (chains, distracted_secs))| TriggerStats { app_name: app_name.to_string(), chains, distracted_secs })
+        .collect();
+    top_triggers.sort_by_key(|stats| std::cmp::Reverse(stats.distracted_secs));
+
+    Ok(DistractionReport {
+        date: date.format("%Y-%m-%d").to_string(),
+        chain_count: chains.len() as u64,
+        distracted_secs: chains.iter().map(|chain| chain.distracted_secs).sum(),
+        top_triggers,
+        chains,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(app: &str, start: u64, end: u64) -> Session {
+        Session { identifier: format!("{}:t:x", app), app_name: app.to_string(), start: Timestamp::from_secs(start), end: Timestamp::from_secs(end) }
+    }
+
+    fn productivity(session: &Session) -> Productivity {
+        match session.app_name.as_str() {
+            "slack.exe" | "code.exe" => Productivity::Productive,
+            "twitter" | "youtube" => Productivity::Distracting,
+            _ => Productivity::Neutral,
+        }
+    }
+
+    #[test]
+    fn quick_check_followed_by_long_distraction_is_a_chain() {
+        let sessions = [
+            session("code.exe", 0, 3600),
+            session("slack.exe", 3600, 3660),
+            session("twitter", 3660, 5000),
+            session("youtube", 5030, 6200),
+            session("code.exe", 6200, 7000),
+        ];
+        let chains = detect(&sessions, productivity);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].trigger_app, "slack.exe");
+        let chain = &chains[0];
+        assert_eq!((chain.start.as_secs(), chain.end.as_secs(), chain.distracted_secs.as_secs()), (3660, 6200, 2510));
+        assert_eq!(chains[0].distracting_apps, vec!["twitter", "youtube"]);
+    }
+
+    #[test]
+    fn short_distractions_and_long_work_are_not_chains() {
+        let brief = [session("slack.exe", 0, 60), session("twitter", 60, 200)];
+        assert!(detect(&brief, productivity).is_empty());
+        // The first session is too long to be a quick check
+        let long_work = [session("code.exe", 0, 3600), session("twitter", 3600, 6000)];
+        assert!(detect(&long_work, productivity).is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+// `sysmonitor doctor`: environment checks with suggested fixes.
+//
+// Every check is independent and reports instead of failing fast, so one run
+// shows everything that is wrong with an installation.
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::Connection;
+
+#[cfg(feature = "web")]
+use crate::web::WEB_PORT;
+#[cfg(feature = "web")]
+use crate::{assets, is_port_in_use};
+use crate::SystemMonitor;
+
+// Anything earlier means the RTC battery died or the clock was never set
+const MIN_PLAUSIBLE_TIMESTAMP: u64 = 1_704_067_200; // 2024-01-01
+const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    status: CheckStatus,
+    detail: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Ok, detail: detail.into(), fix: None }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Warn, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Fail, detail: detail.into(), fix: Some(fix.into()) }
+    }
+}
+
+fn check_window_api(monitor: &SystemMonitor) -> Check {
+    match monitor.get_foreground_window_info() {
+        Some(info) if info.access.is_partial() => Check::warn(
+            "window tracking",
+            format!("foreground window found ({}) but process details are incomplete", info.app_name),
+            "Elevated windows can only be fully resolved when sysmonitor runs elevated too",
+        ),
+        Some(info) => Check::ok("window tracking", format!("foreground app is {}", info.app_name)),
+        None => Check::fail(
+            "window tracking",
+            "no foreground window could be read",
+            if cfg!(windows) {
+                "Run sysmonitor from an interactive desktop session, not as a service"
+            } else {
+                "Install xdotool and run under an X11 session"
+            },
+        ),
+    }
+}
+
+fn check_database(db_path: &str) -> Check {
+    let result = Connection::open(db_path).and_then(|mut conn| {
+        // Prove writability without leaving anything behind
+        let tx = conn.transaction()?;
+        tx.execute_batch("CREATE TABLE doctor_probe (id INTEGER); DROP TABLE doctor_probe;")?;
+        tx.rollback()
+    });
+    match result {
+        Ok(()) => Check::ok("database", format!("{} is writable", db_path)),
+        Err(e) => Check::fail(
+            "database",
+            format!("{} is not writable: {}", db_path, e),
+            "Check file permissions and that no other program holds an exclusive lock on it",
+        ),
+    }
+}
+
+#[cfg(feature = "web")]
+fn check_port() -> Check {
+    if is_port_in_use(WEB_PORT) {
+        Check::warn(
+            "web port",
+            format!("port {} is in use", WEB_PORT),
+            "If sysmonitor is not already running, stop the program bound to the port",
+        )
+    } else {
+        Check::ok("web port", format!("port {} is free", WEB_PORT))
+    }
+}
+
+#[cfg(not(feature = "web"))]
+fn check_port() -> Check {
+    Check::ok("web port", "built without the dashboard (`web` feature)")
+}
+
+#[cfg(windows)]
+fn check_autostart() -> Check {
+    let output = Command::new("reg")
+        .args(["query", r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run"])
+        .output();
+    match output {
+        Ok(output) if String::from_utf8_lossy(&output.stdout).to_lowercase().contains("sysmonitor") => {
+            Check::ok("autostart", "registered in HKCU Run")
+        }
+        Ok(_) => Check::warn(
+            "autostart",
+            "not registered to start with Windows",
+            r"Add sysmonitor.exe to HKCU\Software\Microsoft\Windows\CurrentVersion\Run to track from login",
+        ),
+        Err(e) => Check::warn("autostart", format!("could not query the registry: {}", e), "Run `reg query` manually"),
+    }
+}
+
+#[cfg(not(windows))]
+fn check_autostart() -> Check {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let desktop_entry = std::path::Path::new(&home).join(".config/autostart/sysmonitor.desktop");
+    let user_unit = Command::new("systemctl")
+        .args(["--user", "is-enabled", "sysmonitor"])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false);
+    if desktop_entry.exists() || user_unit {
+        Check::ok("autostart", "registered to start with the session")
+    } else {
+        Check::warn(
+            "autostart",
+            "not registered to start with the session",
+            "Add ~/.config/autostart/sysmonitor.desktop or a systemd user unit",
+        )
+    }
+}
+
+fn check_clock(db_path: &str) -> Check {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    if now < MIN_PLAUSIBLE_TIMESTAMP {
+        return Check::fail("clock", format!("system time {} is implausibly old", now), "Enable time synchronization");
+    }
+
+    // Rows from the future mean the clock was set back since they were written
+    let latest: Option<i64> = Connection::open(db_path)
+        .and_then(|conn| conn.query_row("SELECT MAX(timestamp) FROM usage_logs", [], |row| row.get(0)))
+        .unwrap_or(None);
+    match latest {
+        Some(latest) if latest as u64 > now + MAX_CLOCK_SKEW_SECS => Check::warn(
+            "clock",
+            format!("newest activity is {}s in the future", latest as u64 - now),
+            "The clock moved backwards; enable time synchronization and check durations around that time",
+        ),
+        _ => Check::ok("clock", "system time looks sane"),
+    }
+}
+
+#[cfg(feature = "web")]
+fn check_web_assets() -> Check {
+    // Compiled in, so this only fails for a broken build
+    let count = assets::manifest().asset_count();
+    if count > 0 {
+        Check::ok("web assets", format!("dashboard embedded ({} fingerprinted assets)", count))
+    } else {
+        Check::fail("web assets", "no dashboard assets embedded", "Rebuild sysmonitor from a checkout with the `web` folder")
+    }
+}
+
+#[cfg(not(feature = "web"))]
+fn check_web_assets() -> Check {
+    Check::ok("web assets", "built without the dashboard (`web` feature)")
+}
+
+/// Runs all checks and prints the report. Returns `false` if any check failed.
+pub fn run(monitor: &SystemMonitor) -> bool {
+    let checks = [
+        check_window_api(monitor),
+        check_database(&monitor.db_path),
+        check_port(),
+        check_autostart(),
+        check_clock(&monitor.db_path),
+        check_web_assets(),
+    ];
+
+    println!("sysmonitor doctor");
+    for check in &checks {
+        let marker = match check.status {
+            CheckStatus::Ok => "ok  ",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        println!("  [{}] {}: {}", marker, check.name, check.detail);
+        if let Some(ref fix) = check.fix {
+            println!("         fix: {}", fix);
+        }
+    }
+    checks.iter().all(|check| check.status != CheckStatus::Fail)
+}
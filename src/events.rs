@@ -0,0 +1,491 @@
+// The tracker's raw history as an append-only event log.
+//
+// Every focus change is recorded as it happens, along with whatever ended a
+// session: an untracked app, a pause, idle input, the lock screen, sleep, a
+// clock jump or the tracker restarting. Heartbeats mark a session still open, so one left open by a
+// crash ends at the last of them. Manual entries are events too.
+//
+// Sessions are a projection of the log: `project` replays it with the current
+// rules (debouncing, the AFK grace, midnight splits) and `rebuild` replaces the stored
+// tracker and manual sessions from a date on with the result, then the daily
+// and Grafana aggregates over them. Bulk labels and title languages move over
+// to the rebuilt session that covers most of the one they were on. A fix in how sessions are derived reaches
+// back as far as the log does; history from before it is left as stored.
+// Events are replayed in the order they were appended, which stays right
+// when the clock jumps back.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::categories::CategoryConfig;
+use crate::debounce::DebounceConfig;
+use crate::identifier::ActivityKey;
+use crate::idle::IdleConfig;
+use crate::units::{DurationSecs, Timestamp};
+use crate::{grafana, labels, reports, rollups, signatures};
+
+pub const HEARTBEAT: DurationSecs = DurationSecs::mins(1); // While a session is open
+const STALE: DurationSecs = DurationSecs::mins(3); // Without an event for this long, the tracker had stopped
+const LOOKBACK: DurationSecs = DurationSecs::days(1); // How far before a rebuild a session reaching into it may start
+pub const MANUAL_SOURCE: &str = "manual";
+const LISTED: usize = 50; // Events printed by `events list`
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Focus, // A tracked window came into focus
+    Heartbeat,
+    Untracked, // Focus went to an app that isn't tracked
+    Pause,
+    Idle, // At the last input
+    Lock,
+    Suspend,
+    ClockJump, // Where the old clock left off
+    Restart, // The tracker started over, over its memory ceiling
+    Manual, // A session entered by hand, with its duration
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Focus => "focus",
+            Self::Heartbeat => "heartbeat",
+            Self::Untracked => "untracked",
+            Self::Pause => "pause",
+            Self::Idle => "idle",
+            Self::Lock => "lock",
+            Self::Suspend => "suspend",
+            Self::ClockJump => "clock_jump",
+            Self::Restart => "restart",
+            Self::Manual => "manual",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(text.to_string())).ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub timestamp: Timestamp,
+    pub kind: EventKind,
+    pub key: Option<ActivityKey>, // For focus and manual events
+    pub partial: bool,
+    pub duration: DurationSecs, // Of a manual entry
+}
+
+impl Event {
+    pub fn new(timestamp: Timestamp, kind: EventKind) -> Self {
+        Self { timestamp, kind, key: None, partial: false, duration: DurationSecs::ZERO }
+    }
+
+    pub fn focus(timestamp: Timestamp, key: ActivityKey, partial: bool) -> Self {
+        Self { key: Some(key), partial, ..Self::new(timestamp, EventKind::Focus) }
+    }
+}
+
+/// A session derived from the log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projected {
+    pub key: ActivityKey,
+    pub start: Timestamp,
+    pub duration: DurationSecs,
+    pub partial: bool,
+    pub manual: bool,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            identifier TEXT,
+            partial INTEGER NOT NULL DEFAULT 0,
+            duration INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_events_timestamp ON events(timestamp);",
+    )
+}
+
+pub fn append(conn: &Connection, event: &Event) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO events (timestamp, kind, identifier, partial, duration) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![event.timestamp, event.kind.as_str(), event.key.as_ref().map(ActivityKey::to_string), event.partial, event.duration],
+    )?;
+    Ok(())
+}
+
+/// Events stamped at `since` or later, in the order they were appended.
+pub fn load(conn: &Connection, since: Timestamp) -> SqlResult<Vec<Event>> {
+    let mut stmt = conn.prepare("SELECT timestamp, kind, identifier, partial, duration FROM events WHERE timestamp >= ?1 ORDER BY id")?;
+    let rows = stmt.query_map(params![since], |row| {
+        Ok((row.get::<_, Timestamp>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, bool>(3)?, row.get::<_, DurationSecs>(4)?))
+    })?;
+    let mut events = Vec::new();
+    for row in rows {
+        let (timestamp, kind, identifier, partial, duration) = row?;
+        // Kinds from a newer version are skipped
+        if let Some(kind) = EventKind::parse(&kind) {
+            let key = identifier.as_deref().map(ActivityKey::parse);
+            events.push(Event { timestamp, kind, key, partial, duration });
+        }
+    }
+    Ok(events)
+}
+
+// The session being replayed
+struct Open {
+    key: ActivityKey,
+    start: Timestamp,
+    partial: bool,
+    last_seen: Timestamp,
+}
+
+fn close(sessions: &mut Vec<Projected>, open: Open, end: Timestamp) {
+    let end = end.max(open.start);
+    sessions.push(Projected { key: open.key, start: open.start, duration: end - open.start, partial: open.partial, manual: false });
+}
+
+/// Sessions `events` describe, in order, under `debounce` and `idle`: short
+/// focuses left out or folded into the session around them, sessions
+/// interrupted by a short time away joined up, each session cut at local
+/// midnight. One still open at the end ends at its last event.
+pub fn project(events: &[Event], debounce: &DebounceConfig, idle: &IdleConfig) -> Vec<Projected> {
+    let mut sessions: Vec<Projected> = Vec::new();
+    let mut open: Option<Open> = None;
+    let mut away: Option<usize> = None; // The session idle input or the lock screen ended, until the next focus
+    for event in events {
+        // Manual entries, added afterwards, say nothing about the tracker then
+        if event.kind != EventKind::Manual && open.as_ref().is_some_and(|current| event.timestamp > current.last_seen + STALE) {
+            let current = open.take().unwrap();
+            let last_seen = current.last_seen;
+            close(&mut sessions, current, last_seen);
+        }
+        match event.kind {
+            EventKind::Focus => {
+                let Some(key) = event.key.clone() else {
+                    continue;
+                };
+                if let Some(current) = open.as_mut().filter(|current| current.key == key) {
+                    current.last_seen = event.timestamp;
+                    continue;
+                }
+                if let Some(current) = open.take() {
+                    close(&mut sessions, current, event.timestamp);
+                }
+                // Back within the debounce time: only too-short sessions came after, so
+                // they go (manual entries stay) and the earlier one goes on
+                let resumed = debounce.fold.then(|| {
+                    sessions.iter().rposition(|session| {
+                        !session.manual && session.key == key && event.timestamp.since(session.start + session.duration) < debounce.min_session()
+                    })
+                });
+                let returned = away.take().filter(|&index| {
+                    let session = &sessions[index];
+                    session.key == key && idle.merges(event.timestamp.since(session.start + session.duration))
+                });
+                open = Some(match resumed.flatten().or(returned) {
+                    Some(index) => {
+                        let later = sessions.split_off(index + 1);
+                        sessions.extend(later.into_iter().filter(|session| session.manual));
+                        let session = sessions.remove(index);
+                        Open { key, start: session.start, partial: session.partial || event.partial, last_seen: event.timestamp }
+                    }
+                    None => Open { key, start: event.timestamp, partial: event.partial, last_seen: event.timestamp },
+                });
+            }
+            EventKind::Heartbeat => {
+                if let Some(current) = open.as_mut() {
+                    current.last_seen = current.last_seen.max(event.timestamp);
+                }
+            }
+            EventKind::Manual => {
+                if let Some(key) = event.key.clone() {
+                    sessions.push(Projected { key, start: event.timestamp, duration: event.duration, partial: false, manual: true });
+                }
+            }
+            EventKind::Idle | EventKind::Lock => {
+                if let Some(current) = open.take() {
+                    close(&mut sessions, current, event.timestamp);
+                    away = Some(sessions.len() - 1);
+                }
+            }
+            EventKind::Untracked | EventKind::Pause | EventKind::Suspend | EventKind::ClockJump | EventKind::Restart => {
+                if let Some(current) = open.take() {
+                    close(&mut sessions, current, event.timestamp);
+                }
+            }
+        }
+    }
+    if let Some(current) = open {
+        let last_seen = current.last_seen;
+        close(&mut sessions, current, last_seen);
+    }
+
+    let mut projected = Vec::new();
+    for session in sessions.into_iter().filter(|session| session.manual || debounce.keeps(session.duration)) {
+        let mut start = session.start;
+        for (_, seconds) in reports::split_by_day(session.start.as_secs(), (session.start + session.duration).as_secs()) {
+            projected.push(Projected { start, duration: DurationSecs::secs(seconds), ..session.clone() });
+            start += DurationSecs::secs(seconds);
+        }
+    }
+    projected
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Rebuilt {
+    pub from: Timestamp, // Where the rebuild started, after clamping to the log
+    pub removed: usize, // Stored sessions replaced
+    pub stored: usize,
+}
+
+/// Sessions from the log starting at `from` or later, with `from` clamped to
+/// what the log and usage_logs still cover. None while the log is empty.
+pub fn projection(conn: &Connection, debounce: &DebounceConfig, idle: &IdleConfig, from: Timestamp) -> SqlResult<Option<(Timestamp, Vec<Projected>)>> {
+    let first_event: Option<Timestamp> = conn.query_row("SELECT MIN(timestamp) FROM events", [], |row| row.get(0))?;
+    let Some(first_event) = first_event else {
+        return Ok(None);
+    };
+    // Archived or pruned history has no rows left to replace
+    let first_row: Option<Timestamp> = conn.query_row("SELECT MIN(timestamp - duration) FROM usage_logs", [], |row| row.get(0))?;
+    let from = from.max(first_event).max(first_row.unwrap_or_default());
+    let events = load(conn, from.saturating_sub(LOOKBACK))?;
+    let sessions = project(&events, debounce, idle).into_iter().filter(|session| session.start >= from).collect();
+    Ok(Some((from, sessions)))
+}
+
+/// Replaces the tracker's and manual sessions starting at `from` or later
+/// with the projection of the log, and rebuilds the aggregates over them.
+pub fn rebuild(conn: &mut Connection, categories: &CategoryConfig, debounce: &DebounceConfig, idle: &IdleConfig, from: Timestamp, now: Timestamp) -> SqlResult<Rebuilt> {
+    let Some((from, sessions)) = projection(conn, debounce, idle, from)? else {
+        return Ok(Rebuilt { from, ..Rebuilt::default() });
+    };
+    let tx = conn.transaction()?;
+    let replaced: Vec<(String, Timestamp, Timestamp, Option<String>)> = tx
+        .prepare(
+            "SELECT identifier, timestamp - duration AS start, MAX(timestamp), MAX(language) FROM usage_logs
+             WHERE timestamp - duration >= ?1 AND (source IS NULL OR source = ?2)
+             GROUP BY identifier, start",
+        )?
+        .query_map(params![from, MANUAL_SOURCE], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<SqlResult<_>>()?;
+    let languages: HashMap<&str, &str> =
+        replaced.iter().filter_map(|(identifier, _, _, language)| Some((identifier.as_str(), language.as_deref()?))).collect();
+    // Assignments of the sessions going away; the new rows get theirs on the next refresh
+    tx.execute(
+        "DELETE FROM session_categories WHERE start >= ?1 AND EXISTS (
+             SELECT 1 FROM usage_logs u
+             WHERE u.identifier = session_categories.identifier AND u.timestamp - u.duration = session_categories.start
+               AND (u.source IS NULL OR u.source = ?2)
+         )",
+        params![from, MANUAL_SOURCE],
+    )?;
+    tx.execute("DELETE FROM usage_logs WHERE timestamp - duration >= ?1 AND (source IS NULL OR source = ?2)", params![from, MANUAL_SOURCE])?;
+    for session in &sessions {
+        let identifier = session.key.to_string();
+        tx.execute(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, url, timestamp, duration, partial, source, language)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                identifier,
+                session.key.app_name,
+                session.key.window_title(),
+                session.key.url(),
+                session.start + session.duration,
+                session.duration,
+                session.partial,
+                session.manual.then_some(MANUAL_SOURCE),
+                languages.get(identifier.as_str())
+            ],
+        )?;
+        signatures::sign_row(&tx, tx.last_insert_rowid())?;
+    }
+    // Each replaced session's labels go to the new one of its identifier it overlaps most
+    let mut spans: HashMap<String, Vec<(Timestamp, Timestamp)>> = HashMap::new();
+    for session in &sessions {
+        spans.entry(session.key.to_string()).or_default().push((session.start, session.start + session.duration));
+    }
+    let moves: Vec<(String, i64, i64)> = replaced
+        .iter()
+        .filter_map(|(identifier, start, end, _)| {
+            let (successor, _) = spans
+                .get(identifier)?
+                .iter()
+                .map(|&(new_start, new_end)| (new_start, new_end.min(*end).since(new_start.max(*start))))
+                .filter(|(_, overlap)| *overlap > DurationSecs::ZERO)
+                .max_by_key(|(_, overlap)| *overlap)?;
+            (successor != *start).then(|| (identifier.clone(), start.as_secs() as i64, successor.as_secs() as i64))
+        })
+        .collect();
+    labels::rekey(&tx, &moves)?;
+    tx.commit()?;
+
+    let mut days = BTreeSet::new();
+    let (mut day, today) = (reports::local_date(from.as_secs()), reports::local_date(now.as_secs()));
+    while day <= today {
+        days.insert(day);
+        match day.succ_opt() {
+            Some(next) => day = next,
+            None => break,
+        }
+    }
+    rollups::rebuild(conn, &days)?;
+    grafana::rebuild_since(conn, categories, from.as_secs(), now.as_secs())?;
+    Ok(Rebuilt { from, removed: replaced.len(), stored: sessions.len() })
+}
+
+fn format_time(timestamp: Timestamp) -> String {
+    chrono::DateTime::from_timestamp(timestamp.as_secs() as i64, 0)
+        .map_or_else(|| "-".to_string(), |time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// `sysmonitor events list [--from YYYY-MM-DD]` and
+/// `sysmonitor events add --app NAME [--title TEXT] --start "YYYY-MM-DD HH:MM" --minutes N`.
+pub fn run_command(conn: &mut Connection, categories: &CategoryConfig, debounce: &DebounceConfig, idle: &IdleConfig, args: &[String], now: Timestamp) -> Result<(), String> {
+    let sql = |e: rusqlite::Error| e.to_string();
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1)).map(String::as_str);
+    match args.first().map(String::as_str) {
+        Some("list") => {
+            let since = match option("--from") {
+                Some(value) => {
+                    let day = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("--from must be YYYY-MM-DD, got '{}'", value))?;
+                    Timestamp::from_secs(reports::day_bounds(day).0.max(0) as u64)
+                }
+                None => now.saturating_sub(LOOKBACK),
+            };
+            let events = load(conn, since).map_err(sql)?;
+            for event in events.iter().rev().take(LISTED).rev() {
+                let what = event.key.as_ref().map_or(String::new(), |key| format!(" {} \"{}\"", key.app_name, key.window_title()));
+                let length = if event.kind == EventKind::Manual { format!(" ({})", event.duration) } else { String::new() };
+                println!("{}  {}{}{}", format_time(event.timestamp), event.kind.as_str(), what, length);
+            }
+            if events.len() > LISTED {
+                println!("({} earlier events not shown)", events.len() - LISTED);
+            }
+            Ok(())
+        }
+        Some("add") => {
+            let app = option("--app").ok_or("--app is required")?;
+            let start = option("--start").ok_or("--start is required")?;
+            let start = chrono::NaiveDateTime::parse_from_str(start, "%Y-%m-%d %H:%M")
+                .ok()
+                .and_then(|time| time.and_local_timezone(chrono::Local).earliest())
+                .ok_or_else(|| format!("--start must be \"YYYY-MM-DD HH:MM\", got '{}'", start))?
+                .timestamp()
+                .max(0) as u64;
+            let start = Timestamp::from_secs(start);
+            let minutes: u64 = option("--minutes").and_then(|value| value.parse().ok()).filter(|minutes| *minutes > 0).ok_or("--minutes must be a positive number")?;
+            if start + DurationSecs::mins(minutes) > now {
+                return Err("A manual entry can't end in the future".to_string());
+            }
+            let key = ActivityKey::new(app, option("--title").unwrap_or(""), None);
+            append(conn, &Event { key: Some(key), duration: DurationSecs::mins(minutes), ..Event::new(start, EventKind::Manual) }).map_err(sql)?;
+            let day_start = Timestamp::from_secs(reports::day_bounds(reports::local_date(start.as_secs())).0.max(0) as u64);
+            let rebuilt = rebuild(conn, categories, debounce, idle, day_start, now).map_err(sql)?;
+            println!("Added {} minutes of {}; {} sessions stored since {}", minutes, app, rebuilt.stored, format_time(rebuilt.from));
+            Ok(())
+        }
+        _ => Err("usage: sysmonitor events list [--from YYYY-MM-DD] | events add --app NAME [--title TEXT] --start \"YYYY-MM-DD HH:MM\" --minutes N".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn sessions_are_projected_from_the_log() {
+        let key = |app: &str| ActivityKey::new(app, "", None);
+        let at = Timestamp::from_secs;
+        let focus = |secs, app: &str| Event::focus(at(secs), key(app), false);
+        let events = vec![
+            focus(1_000, "code.exe"),
+            Event::new(at(1_060), EventKind::Heartbeat),
+            focus(1_100, "slack.exe"),
+            focus(1_101, "code.exe"), // A glance: folded back into code.exe
+            Event::new(at(1_250), EventKind::Heartbeat),
+            Event::new(at(1_400), EventKind::Idle),
+            focus(2_000, "chrome.exe"),
+            Event::new(at(2_030), EventKind::Heartbeat), // Then the tracker stopped
+            focus(9_000, "code.exe"),
+            Event::new(at(9_150), EventKind::Heartbeat),
+            Event { key: Some(key("paper")), duration: DurationSecs::mins(10), ..Event::new(at(3_000), EventKind::Manual) },
+            Event::new(at(9_300), EventKind::Lock),
+        ];
+        let sessions = project(&events, &DebounceConfig::default(), &IdleConfig::default());
+        let summary: Vec<(&str, u64, u64, bool)> = sessions
+            .iter()
+            .map(|session| (session.key.app_name.as_str(), session.start.as_secs(), session.duration.as_secs(), session.manual))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("code.exe", 1_000, 400, false), ("chrome.exe", 2_000, 30, false), ("paper", 3_000, 600, true), ("code.exe", 9_000, 300, false)]
+        );
+
+        // Without folding the glance still goes, but splits the session
+        let split = project(&events, &DebounceConfig { fold: false, ..DebounceConfig::default() }, &IdleConfig::default());
+        assert_eq!(split.iter().take(2).map(|session| (session.start.as_secs(), session.duration.as_secs())).collect::<Vec<_>>(), vec![(1_000, 100), (1_101, 299)]);
+
+        // Back at code.exe 90 seconds after the screen locked: within the grace, one session
+        let mut away = events[..4].to_vec();
+        away.extend([Event::new(at(1_200), EventKind::Lock), focus(1_290, "code.exe"), Event::new(at(1_400), EventKind::Idle)]);
+        let grace = IdleConfig { grace_secs: 120, ..IdleConfig::default() };
+        let joined = project(&away, &DebounceConfig::default(), &grace);
+        assert_eq!(joined.iter().map(|session| (session.start.as_secs(), session.duration.as_secs())).collect::<Vec<_>>(), vec![(1_000, 400)]);
+        assert_eq!(project(&away, &DebounceConfig::default(), &IdleConfig::default()).len(), 2);
+    }
+
+    #[test]
+    fn labels_and_languages_follow_rebuilt_sessions() {
+        let mut conn = fixtures::empty().unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let start = Timestamp::from_secs(reports::day_bounds(day).0 as u64 + 9 * 3600);
+        let (now, at) = (start + DurationSecs::hours(1), |secs| start + DurationSecs::secs(secs));
+        let key = ActivityKey::new("code.exe", "Rechnung", None);
+        let mut events = vec![
+            Event::focus(start, key.clone(), false),
+            Event::focus(at(100), ActivityKey::new("slack.exe", "", None), false),
+            Event::focus(at(101), key.clone(), false),
+        ];
+        events.extend((1..8).map(|beat| Event::new(at(beat * 50), EventKind::Heartbeat)));
+        events.push(Event::new(at(400), EventKind::Idle));
+        events.sort_by_key(|event| event.timestamp);
+        for event in &events {
+            append(&conn, event).unwrap();
+        }
+        let (categories, idle, split) = (CategoryConfig::default(), IdleConfig::default(), DebounceConfig { fold: false, ..DebounceConfig::default() });
+        let labeled = |conn: &Connection| -> Vec<(String, i64)> {
+            let mut stmt = conn.prepare("SELECT category, start FROM session_labels").unwrap();
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+            rows.map(Result::unwrap).collect()
+        };
+
+        rebuild(&mut conn, &categories, &DebounceConfig::default(), &idle, Timestamp::EPOCH, now).unwrap();
+        conn.execute("UPDATE usage_logs SET language = 'de'", []).unwrap();
+        let filter = labels::LabelFilter { app: Some("code.exe".into()), title_regex: None, from: "2026-03-02".into(), to: "2026-03-02".into() };
+        let (selection, category) = labels::validate(labels::BulkLabel { filter, category: "Clients > Acme".into(), dry_run: false }).unwrap();
+        let change = labels::relabel(&mut conn, &selection, &category, false, now.as_secs()).unwrap();
+        assert_eq!(labeled(&conn), vec![("Clients > Acme".to_string(), start.as_secs() as i64)]);
+
+        // Unfolded, the glance splits the session; the label goes to the longer part
+        let rebuilt = rebuild(&mut conn, &categories, &split, &idle, Timestamp::EPOCH, now).unwrap();
+        assert_eq!((rebuilt.removed, rebuilt.stored), (1, 2));
+        assert_eq!(labeled(&conn), vec![("Clients > Acme".to_string(), at(101).as_secs() as i64)]);
+        let languages: Vec<Option<String>> =
+            conn.prepare("SELECT language FROM usage_logs ORDER BY id").unwrap().query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(languages, vec![Some("de".to_string()); 2]);
+
+        // And back, with the history undo needs
+        rebuild(&mut conn, &categories, &DebounceConfig::default(), &idle, Timestamp::EPOCH, now).unwrap();
+        assert_eq!(labeled(&conn), vec![("Clients > Acme".to_string(), start.as_secs() as i64)]);
+        assert_eq!(labels::undo(&mut conn, change.id.unwrap(), now.as_secs()).unwrap().unwrap().restored, 1);
+        assert!(labeled(&conn).is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+// Full usage history exports, optionally compressed for archiving.
+//
+// `.json.gz` suits tools that already speak gzip; `.csv.zst` is the compact
+// choice for long-term archives.
+
+use rusqlite::{Connection, Result as SqlResult};
+use serde::Serialize;
+
+use crate::archive::ArchiveRow;
+use crate::compression::{self, Encoding};
+use crate::csv;
+use crate::units::{DurationSecs, Timestamp};
+
+const CSV_HEADER: &str = "identifier,app_name,window_title,url,duration,timestamp,partial";
+const ZSTD_LEVEL: i32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    JsonGz,
+    CsvZst,
+}
+
+impl ExportFormat {
+    /// Maps an export file name such as `history.csv.zst` to its format.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        match name {
+            "history.json" => Some(ExportFormat::Json),
+            "history.csv" => Some(ExportFormat::Csv),
+            "history.json.gz" => Some(ExportFormat::JsonGz),
+            "history.csv.zst" => Some(ExportFormat::CsvZst),
+            _ => None,
+        }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        matches!(self, ExportFormat::JsonGz | ExportFormat::CsvZst)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::JsonGz => "application/gzip",
+            ExportFormat::CsvZst => "application/zstd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportRow {
+    identifier: String,
+    app_name: String,
+    window_title: String,
+    url: Option<String>,
+    duration: DurationSecs,
+    timestamp: Timestamp,
+    partial: bool,
+}
+
+impl From<ArchiveRow> for ExportRow {
+    fn from(row: ArchiveRow) -> Self {
+        ExportRow {
+            identifier: row.identifier,
+            app_name: row.app_name,
+            window_title: row.window_title,
+            url: row.url,
+            duration: row.duration,
+            timestamp: row.timestamp,
+            partial: row.partial,
+        }
+    }
+}
+
+pub fn load_history(db_path: &str) -> SqlResult<Vec<ExportRow>> {
+    let conn = Connection::open(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT identifier, app_name, window_title, url, duration, timestamp, partial
+         FROM usage_logs
+         ORDER BY timestamp",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(ExportRow {
+            identifier: row.get(0)?,
+            app_name: row.get(1)?,
+            window_title: row.get(2)?,
+            url: row.get(3)?,
+            duration: row.get(4)?,
+            timestamp: row.get(5)?,
+            partial: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn to_csv(rows: &[ExportRow]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+    for row in rows {
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            csv::field(&row.identifier),
+            csv::field(&row.app_name),
+            csv::field(&row.window_title),
+            csv::field(row.url.as_deref().unwrap_or("")),
+            row.duration.as_secs(),
+            row.timestamp.as_secs(),
+            row.partial
+        ));
+    }
+    out
+}
+
+/// Serializes and, for the archive formats, compresses the rows.
+pub fn render(rows: &[ExportRow], format: ExportFormat) -> std::io::Result<Vec<u8>> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_vec(rows)?),
+        ExportFormat::Csv => Ok(to_csv(rows).into_bytes()),
+        ExportFormat::JsonGz => compression::compress(Encoding::Gzip, &serde_json::to_vec(rows)?),
+        ExportFormat::CsvZst => zstd::encode_all(to_csv(rows).as_bytes(), ZSTD_LEVEL),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zstd_archive_round_trips() {
+        let rows = vec![ExportRow {
+            identifier: "code.exe:t:main.rs".to_string(),
+            app_name: "code.exe".to_string(),
+            window_title: "main.rs".to_string(),
+            url: None,
+            duration: DurationSecs::secs(42),
+            timestamp: Timestamp::from_secs(1_700_000_000),
+            partial: false,
+        }];
+        let archive = render(&rows, ExportFormat::CsvZst).unwrap();
+        let decoded = String::from_utf8(zstd::decode_all(&archive[..]).unwrap()).unwrap();
+        assert_eq!(decoded, to_csv(&rows));
+    }
+}
@@ -0,0 +1,294 @@
+// Site icons for domain-level views, fetched once and kept on disk.
+//
+// `/api/domains/{domain}/icon` answers from `favicons.dir` when it has a
+// fresh icon. Otherwise it tries `https://<domain>/favicon.ico` and then the
+// `<link rel="icon">` of the home page, through `curl` like the other
+// integrations, and stores what it got. Only images up to `max_bytes` are
+// kept, recognized by their bytes rather than what the server claims. When
+// fetching fails, e.g. offline, a stale icon is still served, or else a
+// letter badge generated here; the failure is remembered for `retry_hours`
+// so a page full of icons doesn't retry every one on each load.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const FETCH_TIMEOUT_SECS: u64 = 5;
+const MAX_PAGE_BYTES: usize = 256 * 1024; // Home page read for its icon link
+const FAILED: &str = "failed"; // Extension of the marker left by a failed fetch
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaviconConfig {
+    pub fetch: bool, // False only serves what is already stored, or badges
+    pub dir: String, // Relative paths are next to the database
+    pub max_bytes: usize,
+    pub max_age_days: u64, // Refetched after this, keeping the old one on failure
+    pub retry_hours: u64,
+}
+
+impl Default for FaviconConfig {
+    fn default() -> Self {
+        Self { fetch: true, dir: "favicons".to_string(), max_bytes: 64 * 1024, max_age_days: 30, retry_hours: 24 }
+    }
+}
+
+impl FaviconConfig {
+    pub fn dir_for(&self, db_path: &str) -> PathBuf {
+        let dir = Path::new(&self.dir);
+        match Path::new(db_path).parent() {
+            Some(parent) if dir.is_relative() => parent.join(dir),
+            _ => dir.to_path_buf(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageType {
+    Png,
+    Ico,
+    Gif,
+    Jpeg,
+    Webp,
+    Svg,
+}
+
+const TYPES: [ImageType; 6] = [ImageType::Png, ImageType::Ico, ImageType::Gif, ImageType::Jpeg, ImageType::Webp, ImageType::Svg];
+
+impl ImageType {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageType::Png => "image/png",
+            ImageType::Ico => "image/x-icon",
+            ImageType::Gif => "image/gif",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Webp => "image/webp",
+            ImageType::Svg => "image/svg+xml",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ImageType::Png => "png",
+            ImageType::Ico => "ico",
+            ImageType::Gif => "gif",
+            ImageType::Jpeg => "jpg",
+            ImageType::Webp => "webp",
+            ImageType::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Icon {
+    pub image_type: ImageType,
+    pub bytes: Vec<u8>,
+    pub fallback: bool, // A generated badge, not the site's icon
+}
+
+/// The domain lowercased, if it is a DNS name; IP addresses and single
+/// labels such as `localhost` are refused, so icons are only fetched from
+/// public sites.
+pub fn normalize_domain(domain: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let labels: Vec<&str> = domain.split('.').collect();
+    let valid = domain.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        })
+        && labels.last().is_some_and(|tld| tld.bytes().any(|byte| byte.is_ascii_alphabetic()));
+    if valid {
+        Ok(domain)
+    } else {
+        Err(format!("'{}' is not a domain name", domain))
+    }
+}
+
+/// What the bytes are, if an image format browsers show as an icon.
+pub fn sniff(bytes: &[u8]) -> Option<ImageType> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(ImageType::Png);
+    }
+    if bytes.starts_with(&[0, 0, 1, 0]) && bytes.len() > 6 {
+        return Some(ImageType::Ico);
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some(ImageType::Gif);
+    }
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        return Some(ImageType::Jpeg);
+    }
+    if bytes.len() > 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        return Some(ImageType::Webp);
+    }
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]).to_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    if head.starts_with("<svg") || (head.starts_with("<?xml") && head.contains("<svg")) {
+        return Some(ImageType::Svg);
+    }
+    None
+}
+
+/// The icon of `domain`: stored, fetched, or a badge.
+pub fn icon(config: &FaviconConfig, dir: &Path, domain: &str, now: SystemTime) -> Result<Icon, String> {
+    let domain = normalize_domain(domain)?;
+    let stored = stored(dir, &domain);
+    let max_age = Duration::from_secs(config.max_age_days * 86_400);
+    if let Some((icon, modified)) = &stored {
+        if now.duration_since(*modified).unwrap_or_default() < max_age {
+            return Ok(icon.clone());
+        }
+    }
+    let failed = path(dir, &domain, FAILED);
+    let retry = Duration::from_secs(config.retry_hours * 3600);
+    let failed_recently = modified(&failed).is_some_and(|at| now.duration_since(at).unwrap_or_default() < retry);
+    if config.fetch && !failed_recently {
+        match fetch(&domain, config.max_bytes) {
+            Some(icon) => {
+                save(dir, &domain, &icon);
+                return Ok(icon);
+            }
+            None => {
+                let _ = fs::create_dir_all(dir).and_then(|_| fs::write(&failed, b""));
+            }
+        }
+    }
+    Ok(stored.map(|(icon, _)| icon).unwrap_or_else(|| badge(&domain)))
+}
+
+fn path(dir: &Path, domain: &str, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{}", domain, extension))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn stored(dir: &Path, domain: &str) -> Option<(Icon, SystemTime)> {
+    TYPES.iter().find_map(|&image_type| {
+        let path = path(dir, domain, image_type.extension());
+        let bytes = fs::read(&path).ok()?;
+        Some((Icon { image_type, bytes, fallback: false }, modified(&path)?))
+    })
+}
+
+// Replaces whatever was stored for the domain, including a failure marker
+fn save(dir: &Path, domain: &str, icon: &Icon) {
+    if fs::create_dir_all(dir).is_err() {
+        return;
+    }
+    for image_type in TYPES.iter().filter(|&&image_type| image_type != icon.image_type) {
+        let _ = fs::remove_file(path(dir, domain, image_type.extension()));
+    }
+    let _ = fs::remove_file(path(dir, domain, FAILED));
+    let _ = fs::write(path(dir, domain, icon.image_type.extension()), &icon.bytes);
+}
+
+fn fetch(domain: &str, max_bytes: usize) -> Option<Icon> {
+    let image = |url: &str| {
+        let bytes = download(url, max_bytes)?;
+        Some(Icon { image_type: sniff(&bytes)?, bytes, fallback: false })
+    };
+    let home = format!("https://{}/", domain);
+    image(&format!("{}favicon.ico", home)).or_else(|| {
+        let page = download(&home, MAX_PAGE_BYTES)?;
+        let href = icon_link(&String::from_utf8_lossy(&page))?;
+        let url = url::Url::parse(&home).ok()?.join(&href).ok()?;
+        matches!(url.scheme(), "https" | "http").then_some(())?;
+        image(url.as_str())
+    })
+}
+
+fn download(url: &str, max_bytes: usize) -> Option<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["-sSfL", "--max-redirs", "3", "--proto", "=https,http", "--max-time", &FETCH_TIMEOUT_SECS.to_string()])
+        .args(["--max-filesize", &max_bytes.to_string(), "-A", "sysmonitor", url])
+        .output()
+        .ok()?;
+    // --max-filesize can't stop a body sent without a length
+    (output.status.success() && !output.stdout.is_empty() && output.stdout.len() <= max_bytes).then_some(output.stdout)
+}
+
+/// `href` of the first `<link rel="icon">` (or `shortcut icon`,
+/// `apple-touch-icon`) of a page.
+pub fn icon_link(html: &str) -> Option<String> {
+    let link = Regex::new(r#"(?is)<link\b[^>]*>"#).ok()?;
+    let rel = Regex::new(r#"(?i)\brel\s*=\s*["']?([^"'>]*icon[^"'>]*)"#).ok()?;
+    let href = Regex::new(r#"(?i)\bhref\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).ok()?;
+    let found = link.find_iter(html).map(|tag| tag.as_str()).filter(|tag| rel.is_match(tag)).find_map(|tag| {
+        let captures = href.captures(tag)?;
+        let value = captures.get(1).or(captures.get(2)).or(captures.get(3))?.as_str().trim();
+        (!value.is_empty()).then(|| value.replace("&amp;", "&"))
+    });
+    found
+}
+
+/// A round badge with the domain's first letter, in a color of its own.
+pub fn badge(domain: &str) -> Icon {
+    let name = domain.strip_prefix("www.").unwrap_or(domain);
+    let letter = name.chars().next().unwrap_or('?').to_ascii_uppercase();
+    let digest = Sha256::digest(name.as_bytes());
+    let hue = u16::from_be_bytes([digest[0], digest[1]]) % 360;
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"32\" viewBox=\"0 0 32 32\">\
+         <circle cx=\"16\" cy=\"16\" r=\"16\" fill=\"hsl({}, 55%, 45%)\"/>\
+         <text x=\"16\" y=\"21.5\" font-family=\"sans-serif\" font-size=\"16\" font-weight=\"600\" fill=\"#fff\" text-anchor=\"middle\">{}</text></svg>",
+        hue, letter
+    );
+    Icon { image_type: ImageType::Svg, bytes: svg.into_bytes(), fallback: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_domain_names_are_accepted() {
+        assert_eq!(normalize_domain("GitHub.com.").unwrap(), "github.com");
+        assert_eq!(normalize_domain("docs.rs").unwrap(), "docs.rs");
+        for refused in ["localhost", "192.168.1.1", "-x.com", "a..com", "evil.com/../x", "x.com:8080", ""] {
+            assert!(normalize_domain(refused).is_err(), "{}", refused);
+        }
+    }
+
+    #[test]
+    fn images_are_recognized_by_their_bytes() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), Some(ImageType::Png));
+        assert_eq!(sniff(&[0, 0, 1, 0, 1, 0, 16, 16]), Some(ImageType::Ico));
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"...\"></svg>"), Some(ImageType::Svg));
+        assert_eq!(sniff(b"<!DOCTYPE html><html>Not found</html>"), None);
+
+        let page = r#"<head><link rel="stylesheet" href="/a.css"><LINK REL='shortcut icon' HREF='/static/fav.png?v=1&amp;x=2'></head>"#;
+        assert_eq!(icon_link(page).as_deref(), Some("/static/fav.png?v=1&x=2"));
+        assert_eq!(icon_link("<link rel=icon href=icon.svg>").as_deref(), Some("icon.svg"));
+    }
+
+    #[test]
+    fn offline_serves_the_stored_icon_or_a_badge() {
+        let dir = std::env::temp_dir().join(format!("sysmonitor-favicons-{}", std::process::id()));
+        let config = FaviconConfig { fetch: false, ..FaviconConfig::default() };
+        let now = SystemTime::now();
+
+        let fallback = icon(&config, &dir, "www.example.com", now).unwrap();
+        assert!(fallback.fallback);
+        assert!(String::from_utf8(fallback.bytes).unwrap().contains(">E</text>"));
+
+        let png = Icon { image_type: ImageType::Png, bytes: b"\x89PNG\r\n\x1a\nicon".to_vec(), fallback: false };
+        save(&dir, "example.com", &png);
+        assert_eq!(icon(&config, &dir, "Example.com", now).unwrap(), png);
+        // Past max_age_days, with nothing fetched, the old one still serves
+        assert_eq!(icon(&config, &dir, "example.com", now + Duration::from_secs(365 * 86_400)).unwrap(), png);
+        assert!(icon(&config, &dir, "localhost", now).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
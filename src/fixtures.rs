@@ -0,0 +1,382 @@
+// Synthetic usage databases, for tests, benchmarks and `demo` alike.
+//
+// A `Shape` says what to make up: how many days, whose apps (a persona's, or
+// any number of made-up ones), and how the day goes: stretches of work with
+// breaks in between, going back and forth between two apps the way alt-tab
+// does, idle gaps, and evenings that run past midnight. Sessions are drawn
+// from a seeded generator, so the same shape always gives the same rows, on
+// every platform. `sysmonitor fixture` writes one into a file for profiling
+// and benchmarks.
+
+use chrono::{Datelike, Days, Weekday};
+use rusqlite::{params, Connection, Result as SqlResult};
+
+use crate::identifier::ActivityKey;
+use crate::reports;
+
+pub const MAX_DAYS: u32 = 366 * 5;
+pub const MAX_APPS: usize = 10_000;
+// Each day's last stretch ends this long after midnight with `midnight_spans`
+const PAST_MIDNIGHT_MINS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Persona {
+    Developer,
+    Designer,
+    Student,
+}
+
+// An app and what it shows: window titles, or URLs for browsers
+struct PersonaApp {
+    app_name: &'static str,
+    weight: u64,
+    max_minutes: u64,
+    titles: &'static [&'static str],
+    urls: &'static [&'static str],
+}
+
+const DEVELOPER: &[PersonaApp] = &[
+    PersonaApp {
+        app_name: "Code.exe",
+        weight: 40,
+        max_minutes: 45,
+        titles: &["main.rs - sysmonitor - Visual Studio Code", "web.rs - sysmonitor - Visual Studio Code", "README.md - sysmonitor - Visual Studio Code"],
+        urls: &[],
+    },
+    PersonaApp { app_name: "WindowsTerminal.exe", weight: 15, max_minutes: 15, titles: &["cargo test", "git log", "PowerShell"], urls: &[] },
+    PersonaApp {
+        app_name: "chrome.exe",
+        weight: 25,
+        max_minutes: 20,
+        titles: &[],
+        urls: &["https://github.com/pulls", "https://docs.rs/tokio", "https://stackoverflow.com/questions", "https://www.youtube.com/watch", "https://news.ycombinator.com"],
+    },
+    PersonaApp { app_name: "slack.exe", weight: 12, max_minutes: 8, titles: &["#dev - Slack", "#general - Slack", "Direct messages - Slack"], urls: &[] },
+    PersonaApp { app_name: "ms-teams.exe", weight: 5, max_minutes: 50, titles: &["Standup | Microsoft Teams", "Sprint planning | Microsoft Teams"], urls: &[] },
+    PersonaApp { app_name: "Spotify.exe", weight: 3, max_minutes: 4, titles: &["Spotify Premium"], urls: &[] },
+];
+
+const DESIGNER: &[PersonaApp] = &[
+    PersonaApp { app_name: "Figma.exe", weight: 40, max_minutes: 50, titles: &["Onboarding flow - Figma", "Design system - Figma", "Landing page - Figma"], urls: &[] },
+    PersonaApp { app_name: "Photoshop.exe", weight: 15, max_minutes: 40, titles: &["hero-banner.psd @ 66.7% (RGB/8)", "mockup.psd @ 50% (RGB/8)"], urls: &[] },
+    PersonaApp {
+        app_name: "chrome.exe",
+        weight: 25,
+        max_minutes: 20,
+        titles: &[],
+        urls: &["https://dribbble.com/shots", "https://www.behance.net/galleries", "https://fonts.google.com", "https://www.pinterest.com", "https://www.youtube.com/watch"],
+    },
+    PersonaApp { app_name: "slack.exe", weight: 12, max_minutes: 8, titles: &["#design - Slack", "#general - Slack"], urls: &[] },
+    PersonaApp { app_name: "ms-teams.exe", weight: 8, max_minutes: 45, titles: &["Design review | Microsoft Teams"], urls: &[] },
+];
+
+const STUDENT: &[PersonaApp] = &[
+    PersonaApp { app_name: "WINWORD.EXE", weight: 20, max_minutes: 40, titles: &["Essay draft.docx - Word", "Lab report.docx - Word"], urls: &[] },
+    PersonaApp {
+        app_name: "chrome.exe",
+        weight: 40,
+        max_minutes: 30,
+        titles: &[],
+        urls: &["https://scholar.google.com", "https://en.wikipedia.org/wiki/Thermodynamics", "https://www.khanacademy.org", "https://www.youtube.com/watch", "https://www.reddit.com"],
+    },
+    PersonaApp { app_name: "Discord.exe", weight: 15, max_minutes: 15, titles: &["#homework - Discord", "Study group - Discord"], urls: &[] },
+    PersonaApp { app_name: "Zoom.exe", weight: 10, max_minutes: 55, titles: &["Zoom Meeting"], urls: &[] },
+    PersonaApp { app_name: "Spotify.exe", weight: 5, max_minutes: 4, titles: &["Spotify Free"], urls: &[] },
+    PersonaApp { app_name: "steam.exe", weight: 10, max_minutes: 60, titles: &["Steam"], urls: &[] },
+];
+
+impl Persona {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "developer" => Ok(Self::Developer),
+            "designer" => Ok(Self::Designer),
+            "student" => Ok(Self::Student),
+            _ => Err(format!("unknown persona '{}', expected developer, designer or student", name)),
+        }
+    }
+
+    #[cfg(feature = "fixtures")]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Developer => "developer",
+            Self::Designer => "designer",
+            Self::Student => "student",
+        }
+    }
+
+    fn apps(self) -> &'static [PersonaApp] {
+        match self {
+            Self::Developer => DEVELOPER,
+            Self::Designer => DESIGNER,
+            Self::Student => STUDENT,
+        }
+    }
+
+    // Stretches of a day in minutes after midnight, before jitter
+    fn schedule(self, weekend: bool) -> &'static [(u64, u64)] {
+        match (self, weekend) {
+            (Self::Student, false) => &[(10 * 60, 12 * 60 + 30), (14 * 60, 17 * 60), (20 * 60, 23 * 60)],
+            (Self::Student, true) => &[(13 * 60, 17 * 60)],
+            (_, false) => &[(9 * 60, 12 * 60), (13 * 60, 17 * 60 + 30)],
+            (_, true) => &[(11 * 60, 13 * 60)],
+        }
+    }
+}
+
+/// What to make up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shape {
+    pub days: u32, // Up to and including the day of `now`
+    pub persona: Persona, // Whose apps and hours
+    pub apps: Option<usize>, // Made-up `app001.exe`... instead of the persona's apps
+    pub seed: u64,
+    pub return_percent: u64, // Chance of going back to the app before, as alt-tab does
+    pub idle_gap_percent: u64, // Chance of a 5 to 15 minute break after a session
+    pub midnight_spans: bool, // Each day's last stretch runs into the next day
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Self { days: 28, persona: Persona::Developer, apps: None, seed: 1, return_percent: 25, idle_gap_percent: 10, midnight_spans: false }
+    }
+}
+
+impl Shape {
+    // `[--days N] [--apps N] [--persona P] [--seed N] [--returns PCT] [--idle-gaps PCT] [--midnight]`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1)).map(String::as_str);
+        let number = |name: &str, max: u64| -> Result<Option<u64>, String> {
+            option(name)
+                .map(|value| value.parse().ok().filter(|value| *value <= max).ok_or_else(|| format!("{} must be a number up to {}, got '{}'", name, max, value)))
+                .transpose()
+        };
+        let defaults = Self::default();
+        Ok(Self {
+            days: number("--days", MAX_DAYS as u64)?.filter(|days| *days > 0).map_or(defaults.days, |days| days as u32),
+            persona: option("--persona").map(Persona::parse).transpose()?.unwrap_or(defaults.persona),
+            apps: number("--apps", MAX_APPS as u64)?.filter(|apps| *apps > 0).map(|apps| apps as usize),
+            seed: number("--seed", u64::MAX)?.unwrap_or(defaults.seed),
+            return_percent: number("--returns", 100)?.unwrap_or(defaults.return_percent),
+            idle_gap_percent: number("--idle-gaps", 100)?.unwrap_or(defaults.idle_gap_percent),
+            midnight_spans: args.iter().any(|arg| arg == "--midnight"),
+        })
+    }
+}
+
+// xorshift64*; good enough for made-up data and stable across platforms
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    fn percent(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<'a>(&mut self, items: &'a [String]) -> &'a str {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+struct App {
+    app_name: String,
+    weight: u64,
+    max_minutes: u64,
+    titles: Vec<String>,
+    urls: Vec<String>,
+}
+
+fn apps_for(shape: &Shape) -> Vec<App> {
+    let owned = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+    match shape.apps {
+        // Zipf-like: the first app is used most, each later one less
+        Some(count) => (1..=count as u64)
+            .map(|rank| App {
+                app_name: format!("app{:03}.exe", rank),
+                weight: (10_000 / rank).max(1),
+                max_minutes: 5 + rank * 7 % 40,
+                titles: (1..=3).map(|document| format!("Document {} - App {}", document, rank)).collect(),
+                urls: Vec::new(),
+            })
+            .collect(),
+        None => shape
+            .persona
+            .apps()
+            .iter()
+            .map(|app| App {
+                app_name: app.app_name.to_string(),
+                weight: app.weight,
+                max_minutes: app.max_minutes,
+                titles: owned(app.titles),
+                urls: owned(app.urls),
+            })
+            .collect(),
+    }
+}
+
+fn pick_app(rng: &mut Rng, apps: &[App], total_weight: u64) -> usize {
+    let mut roll = rng.below(total_weight);
+    for (index, app) in apps.iter().enumerate() {
+        if roll < app.weight {
+            return index;
+        }
+        roll -= app.weight;
+    }
+    0
+}
+
+/// Writes `shape`'s sessions up to `now` into usage_logs. Returns how many
+/// were written.
+pub fn generate(conn: &mut Connection, shape: &Shape, now: u64) -> SqlResult<usize> {
+    let mut rng = Rng::new(shape.seed);
+    let apps = apps_for(shape);
+    let total_weight: u64 = apps.iter().map(|app| app.weight).sum();
+    let today = reports::local_date(now);
+    let tx = conn.transaction()?;
+    let mut sessions = 0;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, url, timestamp, duration, partial)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0)",
+        )?;
+        // The app in focus and the one before it
+        let (mut current, mut previous): (Option<usize>, Option<usize>) = (None, None);
+        for offset in (0..shape.days.clamp(1, MAX_DAYS) as u64).rev() {
+            let day = today - Days::new(offset);
+            let weekend = matches!(day.weekday(), Weekday::Sat | Weekday::Sun);
+            // Some weekends are off entirely
+            if weekend && rng.below(10) < 4 {
+                continue;
+            }
+            let midnight = reports::day_bounds(day).0.max(0) as u64;
+            let schedule = shape.persona.schedule(weekend);
+            for (stretch, &(from, to)) in schedule.iter().enumerate() {
+                let to = if shape.midnight_spans && stretch + 1 == schedule.len() { 24 * 60 + PAST_MIDNIGHT_MINS } else { to };
+                let mut cursor = midnight + (from + rng.below(40)) * 60;
+                let end = (midnight + (to + rng.below(40)) * 60).min(now);
+                while cursor < end {
+                    let index = match previous.filter(|_| rng.percent(shape.return_percent)) {
+                        Some(back) => back,
+                        None => pick_app(&mut rng, &apps, total_weight),
+                    };
+                    if current != Some(index) {
+                        (previous, current) = (current, Some(index));
+                    }
+                    let app = &apps[index];
+                    let duration = ((1 + rng.below(app.max_minutes)) * 60 + rng.below(60)).min(end - cursor);
+                    let key = if app.urls.is_empty() {
+                        ActivityKey::new(&app.app_name, rng.pick(&app.titles), None)
+                    } else {
+                        ActivityKey::new(&app.app_name, "", Some(rng.pick(&app.urls)))
+                    };
+                    insert.execute(params![key.to_string(), key.app_name, key.window_title(), key.url(), cursor + duration, duration])?;
+                    sessions += 1;
+                    // Mostly straight on to the next app, now and then away for a while
+                    cursor += duration + if rng.percent(shape.idle_gap_percent) { 5 * 60 + rng.below(10 * 60) } else { rng.below(20) };
+                }
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(sessions)
+}
+
+/// An in-memory database with every table `init_database` makes, and no rows.
+#[cfg(test)]
+pub fn empty() -> SqlResult<Connection> {
+    let conn = Connection::open_in_memory()?;
+    crate::SystemMonitor::init_schema(&conn, false)?;
+    Ok(conn)
+}
+
+/// `empty`, filled with `shape`.
+#[cfg(test)]
+pub fn database(shape: &Shape, now: u64) -> SqlResult<Connection> {
+    let mut conn = empty()?;
+    generate(&mut conn, shape, now)?;
+    Ok(conn)
+}
+
+/// `sysmonitor fixture <path> [...]`: a complete database for benchmarks.
+#[cfg(feature = "fixtures")]
+pub fn command(args: &[String], debug_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let path = args.first().filter(|path| !path.starts_with("--")).ok_or(
+        "Usage: sysmonitor fixture <path> [--days N] [--apps N] [--persona developer|designer|student] [--seed N] [--returns PCT] [--idle-gaps PCT] [--midnight]",
+    )?;
+    if std::path::Path::new(path).exists() {
+        return Err(format!("{} already exists", path).into());
+    }
+    let shape = Shape::from_args(&args[1..])?;
+    let mut monitor = crate::SystemMonitor::new(debug_mode);
+    monitor.db_path = path.clone();
+    monitor.init_database()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let sessions = generate(&mut Connection::open(path)?, &shape, now)?;
+    monitor.refresh_rollups()?;
+    monitor.refresh_categories()?;
+    println!("Wrote {} sessions over {} days to {}", sessions, shape.days, path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions(conn: &Connection) -> Vec<(String, u64, u64)> {
+        let mut stmt = conn.prepare("SELECT identifier, timestamp - duration, timestamp FROM usage_logs ORDER BY id").unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    fn noon(year: i32, month: u32, day: u32) -> u64 {
+        reports::day_bounds(chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap()).0 as u64 + 15 * 3600
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_non_overlapping_sessions() {
+        let now = noon(2024, 3, 15);
+        let shape = Shape { days: 14, seed: 7, ..Shape::default() };
+        let (first, second) = (database(&shape, now).unwrap(), database(&shape, now).unwrap());
+        let rows = sessions(&first);
+        assert!(rows.len() > 50 && rows == sessions(&second));
+        assert!(rows.windows(2).all(|pair| pair[0].2 <= pair[1].1));
+        assert!(rows.iter().all(|(_, start, end)| start < end && *end <= now));
+        assert_ne!(sessions(&database(&Shape { seed: 8, ..shape }, now).unwrap()), rows);
+    }
+
+    #[test]
+    fn shapes_control_apps_gaps_and_midnight() {
+        let now = noon(2024, 3, 15);
+        let shape = Shape { days: 7, apps: Some(40), midnight_spans: true, idle_gap_percent: 0, ..Shape::default() };
+        let conn = database(&shape, now).unwrap();
+        let apps: i64 = conn.query_row("SELECT COUNT(DISTINCT app_name) FROM usage_logs", [], |row| row.get(0)).unwrap();
+        assert!(apps > 10 && apps <= 40);
+        let rows = sessions(&conn);
+        let across_midnight =
+            rows.iter().filter(|(_, start, end)| reports::local_date(*start) != reports::local_date(end - 1)).count();
+        assert!(across_midnight > 0);
+        // Without idle gaps no break is longer than the 20 seconds between switches,
+        // within a stretch
+        let longest_gap = rows.windows(2).map(|pair| pair[1].1 - pair[0].2).filter(|gap| *gap < 30 * 60).max().unwrap();
+        assert!(longest_gap < 20);
+
+        let args: Vec<String> = ["--days", "3", "--apps", "5", "--midnight"].iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(Shape::from_args(&args).unwrap(), Shape { days: 3, apps: Some(5), midnight_spans: true, ..Shape::default() });
+        assert!(Shape::from_args(&["--idle-gaps".to_string(), "120".to_string()]).is_err());
+    }
+}
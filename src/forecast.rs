@@ -0,0 +1,260 @@
+// End-of-day forecast: where today's productive time and category budgets are
+// heading.
+//
+// Time is bucketed by local hour. The average of the last `HISTORY_DAYS`
+// tracked days says how much usually follows the current time of day; today's
+// pace against those days so far scales it. So a slow morning on a day that
+// usually picks up after lunch still projects a full afternoon, just a smaller one.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Days, NaiveDate};
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::categories::{CategoryConfig, Productivity};
+use crate::identifier::ActivityKey;
+use crate::reports;
+use crate::sessions;
+use crate::units::{DurationSecs, Timestamp};
+
+pub const HISTORY_DAYS: u64 = 28;
+const BUCKET_SECS: u64 = 3600;
+const BUCKETS: usize = 25; // Days with a DST change have 23 or 25 hours
+const MIN_PACE: f64 = 0.5; // Bounds on today's pace, so one odd hour doesn't swing the projection
+const MAX_PACE: f64 = 2.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForecastConfig {
+    pub budgets: BTreeMap<String, u64>, // Category -> daily minutes
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Projection {
+    pub so_far_secs: u64,
+    pub projected_secs: u64,
+    pub pace: f64, // Today against the usual time by now; 1.0 without history
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetForecast {
+    pub category: String,
+    pub budget_secs: u64,
+    pub used_secs: u64,
+    pub projected_secs: u64,
+    pub exhausted: bool,
+    pub exhausted_at: Option<Timestamp>, // When it ran out, or is likely to today
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Forecast {
+    pub date: String,
+    pub as_of: Timestamp,
+    pub history_days: usize,
+    pub productive: Projection,
+    pub budgets: Vec<BudgetForecast>,
+}
+
+// Seconds per local hour of today, and summed over the history days
+#[derive(Debug, Clone)]
+struct Series {
+    today: [f64; BUCKETS],
+    history: [f64; BUCKETS],
+    spans: Vec<(u64, u64)>, // Today's sessions, to tell when a budget actually ran out
+}
+
+impl Default for Series {
+    fn default() -> Self {
+        Self { today: [0.0; BUCKETS], history: [0.0; BUCKETS], spans: Vec::new() }
+    }
+}
+
+// Spreads `[start, end)`, as offsets from local midnight, over the hour buckets
+fn add(buckets: &mut [f64; BUCKETS], start: u64, end: u64) {
+    let mut at = start;
+    while at < end {
+        let bucket = (at / BUCKET_SECS) as usize;
+        if bucket >= BUCKETS {
+            break;
+        }
+        let bucket_end = ((bucket as u64 + 1) * BUCKET_SECS).min(end);
+        buckets[bucket] += (bucket_end - at) as f64;
+        at = bucket_end;
+    }
+}
+
+// Seconds in the buckets before `offset`, counting part of the current bucket
+fn before(buckets: &[f64; BUCKETS], offset: u64) -> f64 {
+    let full = ((offset / BUCKET_SECS) as usize).min(BUCKETS);
+    let partial = buckets.get(full).map_or(0.0, |seconds| seconds * (offset % BUCKET_SECS) as f64 / BUCKET_SECS as f64);
+    buckets[..full].iter().sum::<f64>() + partial
+}
+
+// Offset at which the buckets, from `offset` on, add up to `needed` seconds
+fn reaches(buckets: &[f64; BUCKETS], offset: u64, needed: f64) -> Option<u64> {
+    let mut remaining = needed;
+    let mut at = offset;
+    for (bucket, seconds) in buckets.iter().enumerate() {
+        let bucket_end = (bucket as u64 + 1) * BUCKET_SECS;
+        if bucket_end <= at || *seconds <= 0.0 {
+            continue;
+        }
+        at = at.max(bucket_end - BUCKET_SECS);
+        let available = seconds * (bucket_end - at) as f64 / BUCKET_SECS as f64;
+        if available >= remaining {
+            return Some(at + (remaining / seconds * BUCKET_SECS as f64).ceil() as u64);
+        }
+        remaining -= available;
+        at = bucket_end;
+    }
+    None
+}
+
+impl Series {
+    fn record(&mut self, today: bool, start: u64, end: u64) {
+        if today {
+            add(&mut self.today, start, end);
+            self.spans.push((start, end));
+        } else {
+            add(&mut self.history, start, end);
+        }
+    }
+
+    // Offset at which today's sessions added up to `needed` seconds
+    fn spent(&self, needed: u64) -> Option<u64> {
+        let mut remaining = needed;
+        for &(start, end) in &self.spans {
+            if end.saturating_sub(start) >= remaining {
+                return Some(start + remaining);
+            }
+            remaining -= end.saturating_sub(start);
+        }
+        None
+    }
+
+    fn project(&self, days: usize, now: u64) -> Projection {
+        let so_far = before(&self.today, now);
+        let (usual_by_now, usual_total) = match days {
+            0 => (0.0, 0.0),
+            days => (before(&self.history, now) / days as f64, self.history.iter().sum::<f64>() / days as f64),
+        };
+        let pace = if usual_by_now > 0.0 { (so_far / usual_by_now).clamp(MIN_PACE, MAX_PACE) } else { 1.0 };
+        Projection {
+            so_far_secs: so_far.round() as u64,
+            projected_secs: (so_far + (usual_total - usual_by_now).max(0.0) * pace).round() as u64,
+            pace: (pace * 100.0).round() / 100.0,
+        }
+    }
+
+    // Offsets from local midnight, as `exhausted_at` too
+    fn budget(&self, category: &str, budget_secs: u64, days: usize, now: u64) -> (BudgetForecast, Option<u64>) {
+        let projection = self.project(days, now);
+        let exhausted = projection.so_far_secs >= budget_secs;
+        let exhausted_at = if exhausted {
+            self.spent(budget_secs)
+        } else if days > 0 && projection.projected_secs > budget_secs {
+            let usual = self.history.map(|seconds| seconds / days as f64 * projection.pace);
+            reaches(&usual, now, budget_secs as f64 - before(&self.today, now))
+        } else {
+            None
+        };
+        let forecast = BudgetForecast {
+            category: category.to_string(),
+            budget_secs,
+            used_secs: projection.so_far_secs,
+            projected_secs: projection.projected_secs,
+            exhausted,
+            exhausted_at: None,
+        };
+        (forecast, exhausted_at)
+    }
+}
+
+/// Forecast for `date` as of `now`, from the sessions of that day and the
+/// tracked days before it.
+pub fn today(
+    conn: &Connection,
+    categories: &CategoryConfig,
+    config: &ForecastConfig,
+    date: NaiveDate,
+    now: Timestamp,
+) -> SqlResult<Forecast> {
+    let (day_start, _) = reports::day_span(date);
+    let (history_since, _) = reports::day_span(date - Days::new(HISTORY_DAYS));
+    let sessions = sessions::load_sessions(conn, history_since, now)?;
+
+    let mut productive = Series::default();
+    let mut budgets: BTreeMap<&str, Series> = config.budgets.keys().map(|category| (category.as_str(), Series::default())).collect();
+    let mut history_days = BTreeSet::new();
+    for session in &sessions {
+        let day = reports::local_date(session.start.as_secs());
+        let (midnight, _) = reports::day_span(day);
+        let (start, end) = (session.start.since(midnight).as_secs(), session.end.min(now).since(midnight).as_secs());
+        if day != date {
+            history_days.insert(day);
+        }
+
+        let key = ActivityKey::parse(&session.identifier);
+        if categories.productivity(&key) == Productivity::Productive {
+            productive.record(day == date, start, end);
+        }
+        if let Some(series) = categories.categorize(&key).and_then(|category| budgets.get_mut(category)) {
+            series.record(day == date, start, end);
+        }
+    }
+
+    let days = history_days.len();
+    let offset = now.since(day_start).as_secs();
+    let forecast_budgets: Vec<BudgetForecast> = budgets
+        .iter()
+        .map(|(category, series)| {
+            let (budget, exhausted_at) = series.budget(category, config.budgets[*category] * 60, days, offset);
+            BudgetForecast { exhausted_at: exhausted_at.map(|offset| day_start + DurationSecs::secs(offset)), ..budget }
+        })
+        .collect();
+    Ok(Forecast {
+        date: date.format("%Y-%m-%d").to_string(),
+        as_of: now,
+        history_days: days,
+        productive: productive.project(days, offset),
+        budgets: forecast_budgets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = BUCKET_SECS;
+
+    #[test]
+    fn projection_scales_the_usual_rest_of_the_day_by_todays_pace() {
+        let mut series = Series::default();
+        // Two history days: an hour each from 9 and from 14 o'clock
+        for _ in 0..2 {
+            series.record(false, 9 * HOUR, 10 * HOUR);
+            series.record(false, 14 * HOUR, 15 * HOUR);
+        }
+        // Today at 12: twice the usual morning so far
+        series.record(true, 9 * HOUR, 11 * HOUR);
+        let projection = series.project(2, 12 * HOUR);
+        assert_eq!(projection, Projection { so_far_secs: 2 * HOUR, projected_secs: 4 * HOUR, pace: 2.0 });
+        // Without history there's nothing to go on but what's done
+        assert_eq!(series.project(0, 12 * HOUR).projected_secs, 2 * HOUR);
+    }
+
+    #[test]
+    fn budgets_report_when_they_ran_or_will_run_out() {
+        let mut series = Series::default();
+        series.record(false, 20 * HOUR, 22 * HOUR);
+        series.record(true, 8 * HOUR, 8 * HOUR + 1800);
+        // Half an hour used of 90 minutes; the usual 2 hours from 20 o'clock exceed it
+        let (budget, exhausted_at) = series.budget("Social", 90 * 60, 1, 12 * HOUR);
+        assert!(!budget.exhausted);
+        assert_eq!(exhausted_at, Some(21 * HOUR));
+
+        let (spent, exhausted_at) = series.budget("Social", 20 * 60, 1, 12 * HOUR);
+        assert_eq!((spent.exhausted, exhausted_at), (true, Some(8 * HOUR + 1200)));
+    }
+}
@@ -0,0 +1,137 @@
+// Response formats besides JSON, picked by the `Accept` header.
+//
+// The stats and activity endpoints answer `text/csv` with the rows of their
+// table, for spreadsheets, and `application/msgpack` with their data as
+// MessagePack, for clients that count bytes. Both are rendered from the value
+// the JSON would carry, so the three can't drift apart. Neither has the
+// envelope: errors are answered in JSON whatever was asked for, with their
+// status, and the version is in the `Api-Version` header.
+
+use serde_json::Value;
+
+use crate::{csv, msgpack};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    MessagePack,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::MessagePack => "application/msgpack",
+        }
+    }
+}
+
+/// Picks the preferred supported format, honouring `q` weights; the first
+/// listed wins ties, and JSON is the answer to `*/*` and anything else.
+pub fn negotiate(accept: &str) -> Format {
+    let mut best = (Format::Json, 0.0f32);
+    for part in accept.split(',') {
+        let mut pieces = part.trim().split(';');
+        let name = pieces.next().unwrap_or("").trim().to_lowercase();
+        let quality = pieces
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let format = match name.as_str() {
+            "application/json" => Format::Json,
+            "text/csv" => Format::Csv,
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => Format::MessagePack,
+            _ => continue,
+        };
+        if quality > best.1 {
+            best = (format, quality);
+        }
+    }
+    best.0
+}
+
+/// `data` in a format other than JSON. CSV takes the array at `table`, a
+/// JSON pointer (`""` for `data` itself), one row per item.
+pub fn render(format: Format, data: &Value, table: &str) -> Vec<u8> {
+    match format {
+        Format::Json => serde_json::to_vec(data).unwrap_or_default(),
+        Format::Csv => to_csv(data.pointer(table).unwrap_or(&Value::Null)).into_bytes(),
+        Format::MessagePack => msgpack::encode(data),
+    }
+}
+
+// A column per key, in the order first seen; nested values as JSON. Rows
+// that aren't objects go in a single `value` column
+fn to_csv(table: &Value) -> String {
+    let rows: Vec<&Value> = match table {
+        Value::Array(items) => items.iter().collect(),
+        Value::Null => Vec::new(),
+        row => vec![row],
+    };
+    let mut columns: Vec<&str> = Vec::new();
+    for row in &rows {
+        match row {
+            Value::Object(map) => {
+                for key in map.keys() {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+            _ if !columns.contains(&"value") => columns.push("value"),
+            _ => {}
+        }
+    }
+
+    let mut out = columns.iter().map(|column| csv::field(column)).collect::<Vec<_>>().join(",");
+    out.push('\n');
+    for row in rows {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|&column| match row {
+                Value::Object(map) => cell(map.get(column)),
+                other if column == "value" => cell(Some(other)),
+                _ => String::new(),
+            })
+            .collect();
+        out.push_str(&cells.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => csv::field(text),
+        Some(other @ (Value::Bool(_) | Value::Number(_))) => other.to_string(),
+        Some(nested) => csv::field(&nested.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn picks_the_preferred_supported_type() {
+        assert_eq!(negotiate("text/csv"), Format::Csv);
+        assert_eq!(negotiate("application/json;q=0.5, application/msgpack"), Format::MessagePack);
+        assert_eq!(negotiate("text/csv;q=0, */*"), Format::Json);
+        assert_eq!(negotiate("text/html,application/xhtml+xml,*/*;q=0.8"), Format::Json);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_item_of_the_table() {
+        let data = json!({"date": "2026-03-02", "apps": [
+            {"app_name": "code.exe", "duration": 3600},
+            {"app_name": "Slack, Inc", "duration": 60, "origin": {"kind": "store"}},
+        ]});
+        let csv = String::from_utf8(render(Format::Csv, &data, "/apps")).unwrap();
+        assert_eq!(csv, "app_name,duration,origin\ncode.exe,3600,\n\"Slack, Inc\",60,\"{\"\"kind\"\":\"\"store\"\"}\"\n");
+        assert_eq!(String::from_utf8(render(Format::Csv, &json!([]), "")).unwrap(), "\n");
+    }
+}
@@ -0,0 +1,276 @@
+// Weekly targets per category and progress towards them.
+//
+// Goals are set through the API and kept in `weekly_goals`. Progress for a
+// week (Monday to Sunday) comes from the stored category assignments, broken
+// down by weekday, with the daily pace still needed on the days left. A goal on
+// a parent category counts its children's time too. Once a day, from
+// `goals.digest_hour` on, a `digest` notification sums it up.
+
+use std::collections::BTreeSet;
+
+use chrono::{Datelike, Days, NaiveDate};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::assignments;
+use crate::categories::{self, CategoryConfig};
+use crate::reports;
+
+const MAX_GOALS: usize = 50;
+const MAX_TARGET_MINUTES: u64 = 7 * 24 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GoalConfig {
+    pub digest_hour: Option<u32>, // Local hour of the daily digest; None sends none
+    pub digest_pdf: bool, // Attach the week's PDF report to it
+}
+
+impl Default for GoalConfig {
+    fn default() -> Self {
+        Self { digest_hour: Some(18), digest_pdf: false }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeeklyGoal {
+    pub category: String,
+    pub target_minutes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WeeklyGoals {
+    pub goals: Vec<WeeklyGoal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayProgress {
+    pub date: String,
+    pub weekday: String, // "mon" .. "sun"
+    pub seconds: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GoalProgress {
+    pub category: String,
+    pub target_secs: u64,
+    pub done_secs: u64,
+    pub remaining_secs: u64,
+    pub days_left: u64, // Including today
+    pub required_daily_secs: u64, // Pace needed on each day left to hit the target
+    pub on_track: bool, // At least the target's share of the days already over
+    pub by_weekday: Vec<DayProgress>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WeekProgress {
+    pub week_start: String,
+    pub week_end: String,
+    pub goals: Vec<GoalProgress>,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS weekly_goals (
+            category TEXT PRIMARY KEY,
+            target_minutes INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    // Last local day a digest went out
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS goal_digests (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            last_day TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn validate(goals: Vec<WeeklyGoal>) -> Result<Vec<WeeklyGoal>, String> {
+    if goals.len() > MAX_GOALS {
+        return Err(format!("at most {} goals can be set", MAX_GOALS));
+    }
+    let mut seen = BTreeSet::new();
+    goals
+        .into_iter()
+        .map(|goal| {
+            let category = categories::normalize(&goal.category);
+            if category.is_empty() {
+                return Err("category must not be empty".to_string());
+            }
+            if goal.target_minutes == 0 || goal.target_minutes > MAX_TARGET_MINUTES {
+                return Err(format!("target_minutes for '{}' must be between 1 and {}", category, MAX_TARGET_MINUTES));
+            }
+            if !seen.insert(category.clone()) {
+                return Err(format!("'{}' has more than one goal", category));
+            }
+            Ok(WeeklyGoal { category, ..goal })
+        })
+        .collect()
+}
+
+pub fn load(conn: &Connection) -> SqlResult<Vec<WeeklyGoal>> {
+    let mut stmt = conn.prepare("SELECT category, target_minutes FROM weekly_goals ORDER BY category")?;
+    let goals = stmt
+        .query_map([], |row| Ok(WeeklyGoal { category: row.get(0)?, target_minutes: row.get::<_, i64>(1)?.max(0) as u64 }))?
+        .collect();
+    goals
+}
+
+/// Replaces all goals.
+pub fn replace(conn: &mut Connection, goals: &[WeeklyGoal], now: u64) -> SqlResult<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM weekly_goals", [])?;
+    for goal in goals {
+        tx.execute(
+            "INSERT INTO weekly_goals (category, target_minutes, updated_at) VALUES (?1, ?2, ?3)",
+            params![goal.category, goal.target_minutes, now],
+        )?;
+    }
+    tx.commit()
+}
+
+/// Monday of the week `date` is in.
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
+
+/// Progress in the week starting `start` as of `today`, from (day, category,
+/// seconds) totals.
+pub fn progress(goals: &[WeeklyGoal], daily: &[(NaiveDate, String, u64)], start: NaiveDate, today: NaiveDate) -> Vec<GoalProgress> {
+    let days: Vec<NaiveDate> = (0..7).map(|offset| start + Days::new(offset)).collect();
+    // Days over so far, then the days from today to Sunday
+    let over = days.iter().filter(|day| **day < today).count() as u64;
+    let days_left = 7 - over;
+    goals
+        .iter()
+        .map(|goal| {
+            let by_weekday: Vec<DayProgress> = days
+                .iter()
+                .map(|day| DayProgress {
+                    date: day.format("%Y-%m-%d").to_string(),
+                    weekday: day.format("%a").to_string().to_lowercase(),
+                    seconds: daily
+                        .iter()
+                        .filter(|(date, category, _)| date == day && categories::is_within(category, &goal.category))
+                        .map(|(_, _, seconds)| seconds)
+                        .sum(),
+                })
+                .collect();
+            let target_secs = goal.target_minutes * 60;
+            let done_secs: u64 = by_weekday.iter().map(|day| day.seconds).sum();
+            let remaining_secs = target_secs.saturating_sub(done_secs);
+            GoalProgress {
+                category: goal.category.clone(),
+                target_secs,
+                done_secs,
+                remaining_secs,
+                days_left,
+                required_daily_secs: if days_left == 0 { 0 } else { remaining_secs.div_ceil(days_left) },
+                on_track: done_secs * 7 >= target_secs * over,
+                by_weekday,
+            }
+        })
+        .collect()
+}
+
+/// Progress of the stored goals in the week of `date`.
+pub fn week(conn: &Connection, categories: &CategoryConfig, date: NaiveDate, today: NaiveDate) -> SqlResult<WeekProgress> {
+    let start = week_start(date);
+    let end = start + Days::new(6);
+    let daily = assignments::daily_totals(
+        conn,
+        categories,
+        reports::day_bounds(start).0.max(0) as u64,
+        reports::day_bounds(end).1.max(0) as u64,
+    )?;
+    Ok(WeekProgress {
+        week_start: start.format("%Y-%m-%d").to_string(),
+        week_end: end.format("%Y-%m-%d").to_string(),
+        goals: progress(&load(conn)?, &daily, start, today),
+    })
+}
+
+fn hours(seconds: u64) -> String {
+    format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+}
+
+/// One line per goal for the digest notification.
+pub fn digest_message(week: &WeekProgress) -> String {
+    week.goals
+        .iter()
+        .map(|goal| {
+            let pace = match (goal.remaining_secs, goal.days_left) {
+                (0, _) => "reached".to_string(),
+                (_, 0) => format!("missed by {}", hours(goal.remaining_secs)),
+                (_, days) => format!("{} a day needed over {} days", hours(goal.required_daily_secs), days),
+            };
+            format!("{}: {} of {}, {}", goal.category, hours(goal.done_secs), hours(goal.target_secs), pace)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Records `day` as digested; false if it already was.
+pub fn mark_digested(conn: &Connection, day: NaiveDate) -> SqlResult<bool> {
+    let day = day.format("%Y-%m-%d").to_string();
+    let last: Option<String> = conn.query_row("SELECT last_day FROM goal_digests WHERE id = 1", [], |row| row.get(0)).optional()?;
+    if last.as_deref() == Some(day.as_str()) {
+        return Ok(false);
+    }
+    conn.execute(
+        "INSERT INTO goal_digests (id, last_day) VALUES (1, ?1) ON CONFLICT(id) DO UPDATE SET last_day = excluded.last_day",
+        params![day],
+    )?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn progress_is_broken_down_by_weekday_with_the_pace_left() {
+        let goals = vec![WeeklyGoal { category: "Dev".into(), target_minutes: 20 * 60 }];
+        let daily = vec![
+            (day("2026-10-12"), "Dev".to_string(), 5 * 3600),
+            (day("2026-10-13"), "Dev".to_string(), 2 * 3600),
+            (day("2026-10-13"), "Dev > Code Review".to_string(), 3600),
+            (day("2026-10-13"), "Social".to_string(), 3600),
+        ];
+        // Wednesday: Monday and Tuesday are over
+        let start = week_start(day("2026-10-14"));
+        assert_eq!(start, day("2026-10-12"));
+        let progress = progress(&goals, &daily, start, day("2026-10-14"));
+        let dev = &progress[0];
+        assert_eq!((dev.done_secs, dev.remaining_secs, dev.days_left), (8 * 3600, 12 * 3600, 5));
+        assert_eq!(dev.required_daily_secs, 12 * 3600 / 5);
+        assert!(dev.on_track);
+        assert_eq!(dev.by_weekday[1], DayProgress { date: "2026-10-13".into(), weekday: "tue".into(), seconds: 3 * 3600 });
+        assert_eq!(
+            digest_message(&WeekProgress { week_start: String::new(), week_end: String::new(), goals: progress }),
+            "Dev: 8h 00m of 20h 00m, 2h 24m a day needed over 5 days"
+        );
+    }
+
+    #[test]
+    fn goals_are_validated_and_digested_once_a_day() {
+        let goal = |category: &str, target_minutes| WeeklyGoal { category: category.into(), target_minutes };
+        assert_eq!(validate(vec![goal(" Dev ", 60), goal("Dev>Code Review", 30)]), Ok(vec![goal("Dev", 60), goal("Dev > Code Review", 30)]));
+        assert!(validate(vec![goal("Dev", 60), goal("Dev", 30)]).is_err());
+        assert!(validate(vec![goal("Dev", 0)]).is_err());
+
+        let conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        assert!(mark_digested(&conn, day("2026-10-14")).unwrap());
+        assert!(!mark_digested(&conn, day("2026-10-14")).unwrap());
+        assert!(mark_digested(&conn, day("2026-10-15")).unwrap());
+    }
+}
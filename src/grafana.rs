@@ -0,0 +1,201 @@
+// Grafana-ready tables and the series served to its JSON datasources.
+//
+// `app_usage_hourly` (seconds per app and hour, sessions split at the hour)
+// and `category_daily` (seconds per category and local day) are plain tables
+// kept up to date by `refresh`, so Grafana's SQLite datasource can chart
+// usage.db as is. The last `REBUILD_HOURS` are materialized again on each
+// refresh, which picks up sessions still being extended and late uploads.
+// `/api/grafana/...` serves the same rows to the Infinity and JSON plugins.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::Serialize;
+
+use crate::assignments;
+use crate::categories::CategoryConfig;
+use crate::reports;
+use crate::sessions;
+use crate::units::Timestamp;
+
+const HOUR_SECS: u64 = 3600;
+const REBUILD_HOURS: u64 = 48;
+
+pub const APP_USAGE_HOURLY: &str = "app_usage_hourly";
+pub const CATEGORY_DAILY: &str = "category_daily";
+pub const TABLES: [&str; 2] = [APP_USAGE_HOURLY, CATEGORY_DAILY];
+
+/// A row of either table; `time` is its hour or day start in Unix milliseconds,
+/// which is what Grafana expects of time fields.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageRow {
+    pub time: u64,
+    pub name: String, // App name or category
+    pub seconds: u64,
+}
+
+/// One series of the JSON datasource's `timeserie` answer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Series {
+    pub target: String,
+    pub datapoints: Vec<(u64, u64)>, // [seconds, time in ms]
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS app_usage_hourly (
+            hour_start INTEGER NOT NULL,
+            app_name TEXT NOT NULL,
+            seconds INTEGER NOT NULL,
+            PRIMARY KEY (hour_start, app_name)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_daily (
+            day TEXT NOT NULL,
+            day_start INTEGER NOT NULL,
+            category TEXT NOT NULL,
+            seconds INTEGER NOT NULL,
+            PRIMARY KEY (day, category)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Seconds of `[start, end)` in each hour it touches, as (hour start, seconds).
+fn split_hours(start: u64, end: u64) -> Vec<(u64, u64)> {
+    let mut parts = Vec::new();
+    let mut hour = start - start % HOUR_SECS;
+    while hour < end {
+        let seconds = end.min(hour + HOUR_SECS) - start.max(hour);
+        if seconds > 0 {
+            parts.push((hour, seconds));
+        }
+        hour += HOUR_SECS;
+    }
+    parts
+}
+
+/// Materializes both tables from the last rebuilt hour on; the number of hours written.
+pub fn refresh(conn: &mut Connection, categories: &CategoryConfig, now: u64) -> SqlResult<usize> {
+    let latest: Option<i64> = conn.query_row("SELECT MAX(hour_start) FROM app_usage_hourly", [], |row| row.get(0))?;
+    let from = match latest {
+        Some(latest) => (latest.max(0) as u64).saturating_sub((REBUILD_HOURS - 1) * HOUR_SECS),
+        None => {
+            let first: Option<i64> =
+                conn.query_row("SELECT MIN(timestamp - duration) FROM usage_logs", [], |row| row.get(0)).optional()?.flatten();
+            match first {
+                Some(first) => first.max(0) as u64,
+                None => return Ok(0),
+            }
+        }
+    };
+    rebuild_since(conn, categories, from, now)
+}
+
+/// Materializes both tables again from the hour of `from` on, e.g. after
+/// stored sessions were corrected. Returns how many hours were written.
+pub fn rebuild_since(conn: &mut Connection, categories: &CategoryConfig, from: u64, now: u64) -> SqlResult<usize> {
+    let from = from - from % HOUR_SECS;
+    let mut hourly: BTreeMap<(u64, String), u64> = BTreeMap::new();
+    for session in sessions::overlapping(conn, Timestamp::from_secs(from), Timestamp::from_secs(now + 1))? {
+        for (hour, seconds) in split_hours(session.start.as_secs(), session.end.as_secs()) {
+            if hour >= from {
+                *hourly.entry((hour, session.app_name.clone())).or_default() += seconds;
+            }
+        }
+    }
+    let first_day = reports::local_date(from);
+    let day_start = reports::day_bounds(first_day).0.max(0) as u64;
+    let daily = assignments::daily_totals(conn, categories, day_start, now + 1)?;
+
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM app_usage_hourly WHERE hour_start >= ?1", params![from])?;
+    for ((hour, app_name), seconds) in &hourly {
+        tx.execute(
+            "INSERT INTO app_usage_hourly (hour_start, app_name, seconds) VALUES (?1, ?2, ?3)",
+            params![hour, app_name, seconds],
+        )?;
+    }
+    tx.execute("DELETE FROM category_daily WHERE day >= ?1", params![first_day.format("%Y-%m-%d").to_string()])?;
+    for (day, category, seconds) in &daily {
+        tx.execute(
+            "INSERT INTO category_daily (day, day_start, category, seconds) VALUES (?1, ?2, ?3, ?4)",
+            params![day.format("%Y-%m-%d").to_string(), reports::day_bounds(*day).0, category, seconds],
+        )?;
+    }
+    tx.commit()?;
+    Ok(hourly.keys().map(|(hour, _)| hour).collect::<BTreeSet<_>>().len())
+}
+
+/// Rows of `table` whose hour or day starts within `[from, to)` (Unix seconds), oldest first.
+pub fn rows(conn: &Connection, table: &str, from: u64, to: u64) -> SqlResult<Vec<UsageRow>> {
+    let sql = match table {
+        APP_USAGE_HOURLY => {
+            "SELECT hour_start, app_name, seconds FROM app_usage_hourly
+             WHERE hour_start >= ?1 AND hour_start < ?2 ORDER BY hour_start, app_name"
+        }
+        _ => {
+            "SELECT day_start, category, seconds FROM category_daily
+             WHERE day_start >= ?1 AND day_start < ?2 ORDER BY day_start, category"
+        }
+    };
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt
+        .query_map(params![from, to], |row| {
+            Ok(UsageRow {
+                time: row.get::<_, i64>(0)?.max(0) as u64 * 1000,
+                name: row.get(1)?,
+                seconds: row.get::<_, i64>(2)?.max(0) as u64,
+            })
+        })?
+        .collect();
+    rows
+}
+
+/// One series per app or category, for the JSON datasource's `/query`.
+pub fn series(rows: Vec<UsageRow>) -> Vec<Series> {
+    let mut by_name: BTreeMap<String, Vec<(u64, u64)>> = BTreeMap::new();
+    for row in rows {
+        by_name.entry(row.name).or_default().push((row.seconds, row.time));
+    }
+    by_name.into_iter().map(|(target, datapoints)| Series { target, datapoints }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, identifier};
+
+    #[test]
+    fn sessions_are_split_at_the_hour_and_rebuilt_on_refresh() {
+        assert_eq!(split_hours(3500, 7300), vec![(0, 100), (3600, 3600), (7200, 100)]);
+        assert_eq!(split_hours(3600, 3600), vec![]);
+
+        let mut conn = fixtures::empty().unwrap();
+        let log = |conn: &Connection, start: u64, duration: u64| {
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, 'code.exe', 'x', ?2, ?3)",
+                params![identifier::encode("code.exe", "x", None), start + duration, duration],
+            )
+            .unwrap();
+        };
+        let base = 1_760_000_400 - 1_760_000_400 % HOUR_SECS; // An hour start
+        log(&conn, base + 3000, 1200);
+        let categories: CategoryConfig =
+            serde_json::from_value(serde_json::json!({ "rules": [{ "category": "Dev", "app": "code.exe" }] })).unwrap();
+        assert_eq!(refresh(&mut conn, &categories, base + 4200).unwrap(), 2);
+        // The session grows with the next flush; its hours are rewritten, not added to
+        log(&conn, base + 3000, 1800);
+        refresh(&mut conn, &categories, base + 4800).unwrap();
+
+        let hourly = rows(&conn, APP_USAGE_HOURLY, base, base + 2 * HOUR_SECS).unwrap();
+        let seconds: Vec<(u64, u64)> = hourly.iter().map(|row| (row.time / 1000 - base, row.seconds)).collect();
+        assert_eq!(seconds, vec![(0, 600), (3600, 1200)]);
+        let daily = rows(&conn, CATEGORY_DAILY, 0, u64::MAX / 1000).unwrap();
+        assert_eq!((daily[0].name.as_str(), daily.iter().map(|row| row.seconds).sum::<u64>()), ("Dev", 1800));
+        assert_eq!(series(hourly)[0].datapoints[1], (1200, (base + 3600) * 1000));
+    }
+}
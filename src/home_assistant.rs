@@ -0,0 +1,246 @@
+// Home Assistant sensors: the current app, whether anyone is at the machine and
+// today's screen time.
+//
+// `/api/homeassistant` serves them as one flat JSON object for RESTful sensors,
+// and `/api/homeassistant/config.yaml` the configuration that reads it. With
+// `home_assistant.mqtt` set, the tracker announces the same sensors through MQTT
+// discovery and publishes their state as it changes, so they show up without
+// any YAML.
+
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::child;
+use crate::units::DurationSecs;
+
+const PUBLISH_TIMEOUT: DurationSecs = DurationSecs::secs(10); // Per message, so an unresponsive broker is given up on
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttDiscovery {
+    pub host: String,
+    pub port: u16,
+    pub discovery_prefix: String,
+    pub node_id: String, // Also the state topic's prefix, so one broker can serve several machines
+}
+
+impl Default for MqttDiscovery {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1883,
+            discovery_prefix: "homeassistant".to_string(),
+            node_id: "sysmonitor".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HomeAssistantConfig {
+    pub afk_after_secs: u64, // Input idle time after which the machine counts as unattended
+    pub mqtt: Option<MqttDiscovery>,
+}
+
+impl Default for HomeAssistantConfig {
+    fn default() -> Self {
+        Self { afk_after_secs: 300, mqtt: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+    Active,
+    Afk,
+    Paused,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorState {
+    pub current_app: Option<String>,
+    pub current_category: Option<String>,
+    pub presence: Presence,
+    pub afk: bool,
+    pub idle_secs: Option<u64>, // None where input idle time can't be read
+    pub screen_time_today_secs: u64,
+    pub screen_time_today_minutes: u64,
+}
+
+pub fn sensor_state(
+    current_app: Option<String>,
+    current_category: Option<String>,
+    paused: bool,
+    idle_secs: Option<u64>,
+    screen_time_today_secs: u64,
+    config: &HomeAssistantConfig,
+) -> SensorState {
+    let afk = idle_secs.is_some_and(|idle| idle >= config.afk_after_secs);
+    SensorState {
+        current_app,
+        current_category,
+        presence: match (paused, afk) {
+            (true, _) => Presence::Paused,
+            (false, true) => Presence::Afk,
+            (false, false) => Presence::Active,
+        },
+        afk,
+        idle_secs,
+        screen_time_today_secs,
+        screen_time_today_minutes: screen_time_today_secs / 60,
+    }
+}
+
+struct Sensor {
+    object_id: &'static str,
+    name: &'static str,
+    binary: bool,
+    field: &'static str, // Of `SensorState`
+    icon: &'static str,
+    unit: Option<&'static str>,
+}
+
+const SENSORS: [Sensor; 5] = [
+    Sensor { object_id: "current_app", name: "Current app", binary: false, field: "current_app", icon: "mdi:application", unit: None },
+    Sensor {
+        object_id: "current_category",
+        name: "Current category",
+        binary: false,
+        field: "current_category",
+        icon: "mdi:shape",
+        unit: None,
+    },
+    Sensor { object_id: "presence", name: "Presence", binary: false, field: "presence", icon: "mdi:account-clock", unit: None },
+    Sensor { object_id: "afk", name: "Away from keyboard", binary: true, field: "afk", icon: "mdi:sleep", unit: None },
+    Sensor {
+        object_id: "screen_time_today",
+        name: "Screen time today",
+        binary: false,
+        field: "screen_time_today_minutes",
+        icon: "mdi:monitor-eye",
+        unit: Some("min"),
+    },
+];
+
+fn state_topic(mqtt: &MqttDiscovery) -> String {
+    format!("{}/state", mqtt.node_id)
+}
+
+/// Retained discovery messages as (topic, payload), one per sensor.
+pub fn discovery_messages(mqtt: &MqttDiscovery) -> Vec<(String, Value)> {
+    SENSORS
+        .iter()
+        .map(|sensor| {
+            let component = if sensor.binary { "binary_sensor" } else { "sensor" };
+            let mut payload = json!({
+                "name": sensor.name,
+                "unique_id": format!("{}_{}", mqtt.node_id, sensor.object_id),
+                "state_topic": state_topic(mqtt),
+                "value_template": if sensor.binary {
+                    format!("{{{{ 'ON' if value_json.{} else 'OFF' }}}}", sensor.field)
+                } else {
+                    format!("{{{{ value_json.{} }}}}", sensor.field)
+                },
+                "icon": sensor.icon,
+                "device": { "identifiers": [mqtt.node_id], "name": mqtt.node_id, "manufacturer": "sysmonitor" },
+            });
+            if let Some(unit) = sensor.unit {
+                payload["unit_of_measurement"] = json!(unit);
+                payload["device_class"] = json!("duration");
+                payload["state_class"] = json!("total_increasing");
+            }
+            (format!("{}/{}/{}/{}/config", mqtt.discovery_prefix, component, mqtt.node_id, sensor.object_id), payload)
+        })
+        .collect()
+}
+
+/// A `rest:` block for `configuration.yaml` reading `/api/homeassistant` at `base_url`.
+pub fn rest_config(base_url: &str) -> String {
+    let mut sensors = String::new();
+    let mut binary_sensors = String::new();
+    for sensor in &SENSORS {
+        let entry = format!(
+            "      - name: \"sysmonitor {}\"\n        unique_id: sysmonitor_{}\n        value_template: \"{{{{ value_json.{} }}}}\"\n        icon: {}\n{}",
+            sensor.name.to_lowercase(),
+            sensor.object_id,
+            sensor.field,
+            sensor.icon,
+            sensor.unit.map(|unit| format!("        unit_of_measurement: {}\n", unit)).unwrap_or_default(),
+        );
+        if sensor.binary {
+            binary_sensors.push_str(&entry);
+        } else {
+            sensors.push_str(&entry);
+        }
+    }
+    format!(
+        "# Store \"Bearer smt_...\" (a read token) as sysmonitor_authorization in secrets.yaml\n\
+         rest:\n  - resource: {}/api/homeassistant\n    scan_interval: 30\n    headers:\n      \
+         Authorization: !secret sysmonitor_authorization\n    sensor:\n{}    binary_sensor:\n{}",
+        base_url.trim_end_matches('/'),
+        sensors,
+        binary_sensors
+    )
+}
+
+fn publish(mqtt: &MqttDiscovery, topic: &str, payload: &Value) -> Result<(), String> {
+    let port = mqtt.port.to_string();
+    let payload = payload.to_string();
+    // Retained, so Home Assistant gets configs and the last state when it (re)connects
+    let publisher = Command::new("mosquitto_pub")
+        .args(["-h", &mqtt.host, "-p", &port, "-t", topic, "-r", "-m", &payload])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run mosquitto_pub: {}", e))?;
+    let output = child::wait(publisher, "mosquitto_pub", PUBLISH_TIMEOUT)?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("mosquitto_pub failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+pub fn publish_discovery(mqtt: &MqttDiscovery) -> Result<(), String> {
+    for (topic, payload) in discovery_messages(mqtt) {
+        publish(mqtt, &topic, &payload)?;
+    }
+    Ok(())
+}
+
+pub fn publish_state(mqtt: &MqttDiscovery, state: &SensorState) -> Result<(), String> {
+    publish(mqtt, &state_topic(mqtt), &serde_json::to_value(state).map_err(|e| e.to_string())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_time_and_pauses_decide_presence() {
+        let config = HomeAssistantConfig::default();
+        let state = |paused, idle| sensor_state(Some("teams.exe".into()), None, paused, idle, 3720, &config);
+        assert_eq!((state(false, Some(10)).presence, state(false, Some(10)).afk), (Presence::Active, false));
+        assert_eq!((state(false, Some(300)).presence, state(false, Some(300)).afk), (Presence::Afk, true));
+        assert_eq!(state(true, Some(300)).presence, Presence::Paused);
+        assert_eq!(state(false, None).presence, Presence::Active);
+        assert_eq!(state(false, None).screen_time_today_minutes, 62);
+    }
+
+    #[test]
+    fn discovery_announces_every_sensor_on_one_state_topic() {
+        let messages = discovery_messages(&MqttDiscovery::default());
+        assert_eq!(messages.len(), SENSORS.len());
+        let (topic, afk) = &messages[3];
+        assert_eq!(topic, "homeassistant/binary_sensor/sysmonitor/afk/config");
+        assert_eq!(afk["state_topic"], "sysmonitor/state");
+        assert_eq!(afk["value_template"], "{{ 'ON' if value_json.afk else 'OFF' }}");
+        assert_eq!(messages[4].1["unit_of_measurement"], "min");
+
+        let yaml = rest_config("http://desk:3030/");
+        assert!(yaml.contains("resource: http://desk:3030/api/homeassistant\n"));
+        assert!(yaml.contains("    binary_sensor:\n      - name: \"sysmonitor away from keyboard\"\n"));
+    }
+}
@@ -0,0 +1,117 @@
+// HTTP caching helpers: strong ETags, `Last-Modified` and conditional
+// `If-None-Match`/`If-Modified-Since` handling.
+
+use chrono::{DateTime, Utc};
+use warp::http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+use warp::http::StatusCode;
+use warp::hyper::Body;
+use warp::reply::Response;
+
+// Content that can never change again: a year is the longest lifetime caches honour
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+// Content that may still change: always revalidate, which is cheap with an ETag
+pub const REVALIDATE: &str = "no-cache";
+
+/// Request headers relevant to conditional GETs.
+#[derive(Debug, Clone, Default)]
+pub struct Conditional {
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
+}
+
+// 64-bit FNV-1a; ETags only need to change with the content, not be secret
+pub fn content_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+pub fn etag(data: &[u8]) -> String {
+    format!("\"{:016x}\"", content_hash(data))
+}
+
+pub fn http_date(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+fn parse_http_date(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value.trim()).ok().map(|time| time.timestamp())
+}
+
+impl Conditional {
+    /// `If-None-Match` takes precedence over `If-Modified-Since` (RFC 9110).
+    pub fn is_fresh(&self, etag: &str, last_modified: Option<i64>) -> bool {
+        if let Some(ref tags) = self.if_none_match {
+            return tags
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag);
+        }
+        match (self.if_modified_since.as_deref().and_then(parse_http_date), last_modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+}
+
+/// Builds a `200` with caching headers, or a bodiless `304` when the client's
+/// copy is still current.
+pub fn conditional_response(
+    conditional: &Conditional,
+    body: Vec<u8>,
+    content_type: &'static str,
+    cache_control: &'static str,
+    last_modified: Option<i64>,
+) -> Response {
+    let tag = etag(&body);
+    let fresh = conditional.is_fresh(&tag, last_modified);
+
+    let mut response = if fresh {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        response
+    } else {
+        let mut response = Response::new(Body::from(body));
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        response
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    if let Ok(value) = HeaderValue::from_str(&tag) {
+        headers.insert(ETAG, value);
+    }
+    if let Some(modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&http_date(modified)) {
+            headers.insert(LAST_MODIFIED, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_etag_is_fresh() {
+        let tag = etag(b"report");
+        let conditional = Conditional { if_none_match: Some(format!("\"other\", {}", tag)), if_modified_since: None };
+        assert!(conditional.is_fresh(&tag, None));
+        assert!(!conditional.is_fresh(&etag(b"changed"), None));
+    }
+
+    #[test]
+    fn if_modified_since_compares_timestamps() {
+        let conditional = Conditional { if_none_match: None, if_modified_since: Some(http_date(1_700_000_000)) };
+        assert!(conditional.is_fresh("\"x\"", Some(1_700_000_000)));
+        assert!(!conditional.is_fresh("\"x\"", Some(1_700_000_001)));
+        assert!(!conditional.is_fresh("\"x\"", None));
+    }
+}
@@ -0,0 +1,206 @@
+// Identifier encoding for tracked activities.
+//
+// Activities are keyed by `ActivityKey` in memory and in the API. In storage the
+// key is written in its textual form `<app>:<kind>:<detail>` where `kind` is `u`
+// for a URL and `t` for a window title. Backslashes and colons inside `app` and
+// `detail` are escaped with a backslash, so titles such as "Re: meeting" or
+// "C:\Users" survive a round trip.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+const ESCAPE: char = '\\';
+const SEPARATOR: char = ':';
+const KIND_URL: &str = "u";
+const KIND_TITLE: &str = "t";
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == ESCAPE || c == SEPARATOR {
+            escaped.push(ESCAPE);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Splits on unescaped separators and unescapes each part
+fn split_escaped(identifier: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = identifier.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ESCAPE => {
+                // A trailing lone escape is kept literally
+                current.push(chars.next().unwrap_or(ESCAPE));
+            }
+            SEPARATOR => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+pub fn encode(app_name: &str, window_title: &str, url: Option<&str>) -> String {
+    let (kind, detail) = match url {
+        Some(url) => (KIND_URL, url),
+        None => (KIND_TITLE, window_title),
+    };
+    format!("{}{}{}{}{}", escape(app_name), SEPARATOR, kind, SEPARATOR, escape(detail))
+}
+
+/// Returns `(app_name, window_title, url)`. For URL identifiers the URL doubles
+/// as the window title, matching what gets stored in `usage_logs`.
+pub fn decode(identifier: &str) -> (String, String, Option<String>) {
+    let parts = split_escaped(identifier);
+    if parts.len() == 3 {
+        let mut parts = parts.into_iter();
+        let app = parts.next().unwrap();
+        let kind = parts.next().unwrap();
+        let detail = parts.next().unwrap();
+        match kind.as_str() {
+            KIND_URL => return (app, detail.clone(), Some(detail)),
+            KIND_TITLE => return (app, detail, None),
+            _ => {}
+        }
+    }
+    decode_legacy(identifier)
+}
+
+// Identifiers written before escaping was introduced: `<app>:<title or url>`
+fn decode_legacy(identifier: &str) -> (String, String, Option<String>) {
+    match identifier.split_once(SEPARATOR) {
+        Some((app, rest)) if rest.starts_with("http") => {
+            (app.to_string(), rest.to_string(), Some(rest.to_string()))
+        }
+        Some((app, rest)) => (app.to_string(), rest.to_string(), None),
+        None => (identifier.to_string(), "Unknown".to_string(), None),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    Url,
+    Title,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ActivityKey {
+    pub app_name: String,
+    pub kind: ActivityKind,
+    pub detail: String,
+}
+
+impl ActivityKey {
+    pub fn new(app_name: &str, window_title: &str, url: Option<&str>) -> Self {
+        match url {
+            Some(url) => Self {
+                app_name: app_name.to_string(),
+                kind: ActivityKind::Url,
+                detail: url.to_string(),
+            },
+            None => Self {
+                app_name: app_name.to_string(),
+                kind: ActivityKind::Title,
+                detail: window_title.to_string(),
+            },
+        }
+    }
+
+    pub fn parse(identifier: &str) -> Self {
+        let (app_name, window_title, url) = decode(identifier);
+        Self::new(&app_name, &window_title, url.as_deref())
+    }
+
+    pub fn window_title(&self) -> &str {
+        &self.detail
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        match self.kind {
+            ActivityKind::Url => Some(&self.detail),
+            ActivityKind::Title => None,
+        }
+    }
+}
+
+impl fmt::Display for ActivityKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&encode(&self.app_name, &self.detail, self.url()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn title_with_separators_round_trips() {
+        let id = encode("code.exe", "main.rs: C:\\src\\", None);
+        assert_eq!(
+            decode(&id),
+            ("code.exe".to_string(), "main.rs: C:\\src\\".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn title_starting_with_http_is_not_a_url() {
+        let id = encode("notepad.exe", "http notes", None);
+        assert_eq!(decode(&id).2, None);
+    }
+
+    #[test]
+    fn legacy_identifiers_still_decode() {
+        assert_eq!(
+            decode("chrome.exe:https://example.com"),
+            (
+                "chrome.exe".to_string(),
+                "https://example.com".to_string(),
+                Some("https://example.com".to_string())
+            )
+        );
+        assert_eq!(
+            decode("explorer.exe"),
+            ("explorer.exe".to_string(), "Unknown".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn key_display_parses_back() {
+        let key = ActivityKey::new("chrome.exe", "Docs: intro", Some("https://a.b/c:d"));
+        assert_eq!(ActivityKey::parse(&key.to_string()), key);
+        assert_eq!(key.url(), Some("https://a.b/c:d"));
+    }
+
+    proptest! {
+        #[test]
+        fn keys_round_trip_through_storage_form(app in ".*", title in ".*", is_url in any::<bool>()) {
+            let key = ActivityKey::new(&app, &title, is_url.then_some(title.as_str()));
+            prop_assert_eq!(ActivityKey::parse(&key.to_string()), key);
+        }
+
+        #[test]
+        fn title_identifiers_round_trip(app in ".*", title in ".*") {
+            let id = encode(&app, &title, None);
+            prop_assert_eq!(decode(&id), (app, title, None));
+        }
+
+        #[test]
+        fn url_identifiers_round_trip(app in ".*", url in ".*") {
+            let id = encode(&app, "ignored", Some(&url));
+            prop_assert_eq!(decode(&id), (app, url.clone(), Some(url)));
+        }
+
+        #[test]
+        fn decode_never_panics(identifier in ".*") {
+            let _ = decode(&identifier);
+        }
+    }
+}
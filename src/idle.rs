@@ -0,0 +1,56 @@
+// Time since the last keyboard or mouse input, to tell when nobody is at the
+// machine. Windows asks `GetLastInputInfo`; on Linux `xprintidle` answers for
+// the X11 session, like `xdotool` does for the foreground window. None when
+// the platform can't tell.
+//
+// After `idle.after_secs` without input the tracker ends the open session at
+// the last input. Coming back to the same window within `grace_secs` of that,
+// or of locking the screen, continues the session with the time away in it, so
+// a look at the phone doesn't split it in two.
+
+use serde::{Deserialize, Serialize};
+
+use crate::units::DurationSecs;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    pub after_secs: u64, // 0 never counts the machine as idle
+    pub grace_secs: u64, // 0 always starts a new session; above `after_secs` to bridge idle stretches
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self { after_secs: 300, grace_secs: 0 }
+    }
+}
+
+impl IdleConfig {
+    /// Whether a session that ended with `away` from the machine goes on.
+    pub fn merges(&self, away: DurationSecs) -> bool {
+        away < DurationSecs::secs(self.grace_secs)
+    }
+}
+
+#[cfg(windows)]
+pub fn idle_secs() -> Option<u64> {
+    use windows::Win32::System::SystemInformation::GetTickCount;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut info = LASTINPUTINFO { cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32, dwTime: 0 };
+    if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+        return None;
+    }
+    // Both tick counts wrap after 49.7 days
+    Some(u64::from(unsafe { GetTickCount() }.wrapping_sub(info.dwTime) / 1000))
+}
+
+#[cfg(not(windows))]
+pub fn idle_secs() -> Option<u64> {
+    let output = std::process::Command::new("xprintidle").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let millis: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+    Some(millis / 1000)
+}
@@ -0,0 +1,287 @@
+// Usage and runtime metrics pushed in InfluxDB line protocol.
+//
+// Time is cut into `interval_secs` buckets; each finished one becomes a
+// `sysmonitor_usage` point per app and category, with the runtime counters as
+// one `sysmonitor_runtime` point next to them. `influx_export` remembers the
+// end of the last bucket the endpoint accepted, so a failed push is retried
+// from there on the next run, and buckets missed while the tracker was off are
+// caught up. Writes go through `curl` to `/write` (v1, also VictoriaMetrics)
+// or `/api/v2/write` (v2).
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::categories::{CategoryConfig, UNCATEGORIZED};
+use crate::curl::Secrets;
+use crate::identifier::ActivityKey;
+use crate::metrics::MetricsSnapshot;
+use crate::sessions;
+use crate::units::{DurationSecs, Timestamp};
+
+const MAX_BATCH_LINES: usize = 5000;
+const MAX_CATCH_UP_SECS: u64 = 7 * 86400; // Older buckets are skipped after a long outage
+const SETTLE_SECS: u64 = 10; // The tracker's last seconds may not be flushed yet
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InfluxVersion {
+    V1,
+    #[default]
+    V2,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InfluxConfig {
+    pub url: Option<String>, // e.g. http://localhost:8086; unset disables the exporter
+    pub version: InfluxVersion,
+    pub database: String, // v1
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub org: Option<String>, // v2
+    pub bucket: String,
+    pub token: Option<String>,
+    pub interval_secs: u64,
+    pub host: Option<String>, // `host` tag of every point; the machine's name by default
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            version: InfluxVersion::V2,
+            database: "sysmonitor".to_string(),
+            username: None,
+            password: None,
+            org: None,
+            bucket: "sysmonitor".to_string(),
+            token: None,
+            interval_secs: 60,
+            host: None,
+        }
+    }
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS influx_export (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            pushed_until INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+// Measurement names and tags can't hold unescaped commas, spaces or equal signs
+fn escape_tag(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            ',' | '=' | ' ' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' | '\t' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `sysmonitor_usage` lines of the bucket `[start, end)`, one per app and category.
+pub fn usage_lines(sessions: &[sessions::Session], categories: &CategoryConfig, host: &str, start: u64, end: u64) -> Vec<String> {
+    let mut seconds: BTreeMap<(String, String), u64> = BTreeMap::new();
+    for session in sessions {
+        let overlap = session.overlap(Timestamp::from_secs(start), Timestamp::from_secs(end));
+        if overlap > DurationSecs::ZERO {
+            let key = ActivityKey::parse(&session.identifier);
+            let category = categories.categorize(&key).unwrap_or(UNCATEGORIZED).to_string();
+            *seconds.entry((session.app_name.clone(), category)).or_default() += overlap.as_secs();
+        }
+    }
+    seconds
+        .into_iter()
+        .map(|((app_name, category), seconds)| {
+            format!(
+                "sysmonitor_usage,host={},app={},category={} seconds={}i {}",
+                escape_tag(host),
+                escape_tag(&app_name),
+                escape_tag(&category),
+                seconds,
+                end
+            )
+        })
+        .collect()
+}
+
+pub fn runtime_line(metrics: &MetricsSnapshot, host: &str, timestamp: u64) -> String {
+    format!(
+        "sysmonitor_runtime,host={} events_processed={}i,focus_changes={}i,flushes={}i,flush_errors={}i,loop_iterations={}i,avg_loop_latency_micros={}i,db_size_bytes={}i {}",
+        escape_tag(host),
+        metrics.events_processed,
+        metrics.focus_changes,
+        metrics.flushes,
+        metrics.flush_errors,
+        metrics.loop_iterations,
+        metrics.avg_loop_latency_micros,
+        metrics.db_size_now,
+        timestamp
+    )
+}
+
+/// Write endpoint of `config`, with second precision.
+pub fn write_url(config: &InfluxConfig) -> Result<url::Url, String> {
+    let base = config.url.as_deref().ok_or("Set influx.url in the config")?;
+    let mut write_url = url::Url::parse(base).map_err(|e| format!("Invalid influx.url '{}': {}", base, e))?;
+    let path = match config.version {
+        InfluxVersion::V1 => "write",
+        InfluxVersion::V2 => "api/v2/write",
+    };
+    write_url.set_path(&format!("{}/{}", write_url.path().trim_end_matches('/'), path));
+    {
+        let mut query = write_url.query_pairs_mut();
+        match config.version {
+            InfluxVersion::V1 => query.append_pair("db", &config.database),
+            InfluxVersion::V2 => query.append_pair("org", config.org.as_deref().unwrap_or_default()).append_pair("bucket", &config.bucket),
+        };
+        query.append_pair("precision", "s");
+    }
+    Ok(write_url)
+}
+
+fn send(config: &InfluxConfig, write_url: &url::Url, lines: &[String]) -> Result<(), String> {
+    // curl retries transient failures itself; anything else waits for the next push
+    let mut args: Vec<String> = ["-sS", "-f", "--retry", "2", "--max-time", "30"].map(String::from).to_vec();
+    let secrets = match (config.version, &config.username, &config.token) {
+        (InfluxVersion::V1, Some(username), _) => Secrets::default().user(&format!("{}:{}", username, config.password.as_deref().unwrap_or_default())),
+        (InfluxVersion::V2, _, Some(token)) => Secrets::default().header(&format!("Authorization: Token {}", token)),
+        _ => Secrets::default(),
+    }
+    .write()?;
+    args.extend(secrets.args());
+    args.extend(["--data-binary".to_string(), "@-".to_string(), write_url.to_string()]);
+    let mut child = Command::new("curl")
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(lines.join("\n").as_bytes()).map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Influx write failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// End of the last interval written, None before the first push.
+pub fn pushed_until(conn: &Connection) -> SqlResult<Option<u64>> {
+    let until: Option<i64> = conn.query_row("SELECT pushed_until FROM influx_export WHERE id = 1", [], |row| row.get(0)).optional()?;
+    Ok(until.map(|until| until.max(0) as u64))
+}
+
+fn save_pushed_until(conn: &Connection, until: u64) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO influx_export (id, pushed_until) VALUES (1, ?1) ON CONFLICT(id) DO UPDATE SET pushed_until = excluded.pushed_until",
+        params![until],
+    )?;
+    Ok(())
+}
+
+/// Pushes the buckets finished since the last push; how many were sent.
+pub fn push(
+    conn: &Connection,
+    config: &InfluxConfig,
+    categories: &CategoryConfig,
+    host: &str,
+    metrics: Option<&MetricsSnapshot>,
+    now: u64,
+) -> Result<usize, String> {
+    let interval = config.interval_secs.max(1);
+    let Some(settled) = now.checked_sub(SETTLE_SECS) else {
+        return Ok(0);
+    };
+    let last_end = settled - settled % interval;
+    // A first run starts with the bucket underway rather than the whole history
+    let Some(from) = pushed_until(conn).map_err(|e| e.to_string())? else {
+        save_pushed_until(conn, last_end).map_err(|e| e.to_string())?;
+        return Ok(0);
+    };
+    // Realigned after `interval_secs` changes; points written twice just overwrite
+    let from = from.max(last_end.saturating_sub(MAX_CATCH_UP_SECS));
+    let from = from - from % interval;
+    if from >= last_end {
+        return Ok(0);
+    }
+    let write_url = write_url(config)?;
+    let sessions = sessions::overlapping(conn, Timestamp::from_secs(from), Timestamp::from_secs(last_end)).map_err(|e| e.to_string())?;
+
+    let mut lines = Vec::new();
+    let mut buckets = 0;
+    let mut start = from;
+    while start < last_end {
+        let end = start + interval;
+        lines.extend(usage_lines(&sessions, categories, host, start, end));
+        buckets += 1;
+        if end == last_end {
+            if let Some(metrics) = metrics {
+                lines.push(runtime_line(metrics, host, end));
+            }
+        }
+        // Whole buckets per batch, so the saved position always falls between two
+        if lines.len() >= MAX_BATCH_LINES || end == last_end {
+            if !lines.is_empty() {
+                send(config, &write_url, &lines)?;
+            }
+            save_pushed_until(conn, end).map_err(|e| e.to_string())?;
+            lines.clear();
+        }
+        start = end;
+    }
+    Ok(buckets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifier;
+
+    #[test]
+    fn buckets_become_escaped_lines_per_app_and_category() {
+        let categories: CategoryConfig =
+            serde_json::from_value(serde_json::json!({ "rules": [{ "category": "Dev Tools", "app": "code.exe" }] })).unwrap();
+        let session = |app: &str, start, end| sessions::Session {
+            identifier: identifier::encode(app, "x", None),
+            app_name: app.to_string(),
+            start: Timestamp::from_secs(start),
+            end: Timestamp::from_secs(end),
+        };
+        let sessions = [session("code.exe", 30, 150), session("my app,v2", 100, 110), session("code.exe", 200, 300)];
+        assert_eq!(
+            usage_lines(&sessions, &categories, "desk", 60, 120),
+            vec![
+                "sysmonitor_usage,host=desk,app=code.exe,category=Dev\\ Tools seconds=60i 120",
+                "sysmonitor_usage,host=desk,app=my\\ app\\,v2,category=Uncategorized seconds=10i 120",
+            ]
+        );
+        assert!(usage_lines(&sessions, &categories, "desk", 150, 200).is_empty());
+    }
+
+    #[test]
+    fn write_urls_follow_the_api_version() {
+        let mut config = InfluxConfig { url: Some("http://influx:8086/".into()), org: Some("home lab".into()), ..Default::default() };
+        assert_eq!(write_url(&config).unwrap().as_str(), "http://influx:8086/api/v2/write?org=home+lab&bucket=sysmonitor&precision=s");
+        config.version = InfluxVersion::V1;
+        config.url = Some("http://vm:8428".into());
+        assert_eq!(write_url(&config).unwrap().as_str(), "http://vm:8428/write?db=sysmonitor&precision=s");
+    }
+}
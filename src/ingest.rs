@@ -0,0 +1,321 @@
+// Bulk ingestion for watchers, importers and the sync client.
+//
+// Each event is validated on its own; valid ones are inserted in a single
+// transaction and the response reports accepted/rejected per item, so one bad
+// event never costs the whole batch. Events overlapping stored sessions go
+// through `overlap::resolve`.
+//
+// Payloads are versioned by `schema_version`. Fields are only ever added, so
+// any version parses: fields this build does not know are ignored and listed
+// back per item, and events are parsed one at a time so an event that doesn't
+// fit is rejected on its own.
+//
+// Batches may carry the collector's clock as `sent_at`. Skew beyond
+// `clock_skew.flag_after_secs` is reported with the batch and recorded on the
+// rows it stores; with `clock_skew.correct` the events are shifted onto the
+// server's clock first, so timelines from several machines interleave right.
+
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::ActivityKey;
+use crate::overlap::{self, OverlapPolicy, StoredSession};
+use crate::quotas::QuotaConfig;
+use crate::units::{DurationSecs, Timestamp};
+use crate::{parse_url_candidate, title};
+
+pub const SCHEMA_VERSION: u32 = 1;
+pub const MAX_BATCH_EVENTS: usize = 5000;
+pub const MAX_BATCH_BYTES: u64 = 8 * 1024 * 1024;
+const MAX_EVENT_DURATION: DurationSecs = DurationSecs::days(1);
+const MAX_FUTURE_SKEW: DurationSecs = DurationSecs::mins(5);
+pub const MIN_TIMESTAMP: Timestamp = Timestamp::from_secs(946_684_800); // 2000-01-01; anything earlier is a unit mistake
+
+const EVENT_FIELDS: [&str; 5] = ["app_name", "window_title", "url", "start", "duration"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IngestEvent {
+    pub app_name: String,
+    #[serde(default)]
+    pub window_title: String,
+    pub url: Option<String>,
+    pub start: Timestamp,
+    pub duration: DurationSecs,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IngestBatch {
+    #[serde(default = "first_version")]
+    pub schema_version: u32,
+    #[serde(default = "default_source")]
+    pub source: String, // Ranked by `source_precedence` when events overlap
+    pub device: Option<String>, // Collector id for `/api/devices`; the token's name when missing
+    pub sent_at: Option<Timestamp>, // Collector clock when sending
+    pub events: Vec<serde_json::Value>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+fn default_source() -> String {
+    "import".to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestConfig {
+    pub overlap: OverlapPolicy,
+    pub source_precedence: Vec<String>, // Highest first; the tracker's own rows are `local`
+    pub clock_skew: SkewConfig,
+    pub quotas: QuotaConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SkewConfig {
+    pub flag_after_secs: u64,
+    pub correct: bool, // Shift flagged batches onto the server's clock
+}
+
+impl Default for SkewConfig {
+    fn default() -> Self {
+        Self { flag_after_secs: 120, correct: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ClockSkew {
+    pub secs: i64, // Collector clock minus server clock
+    pub corrected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItemResult {
+    pub index: usize,
+    pub accepted: bool,
+    pub stored_secs: DurationSecs, // Less than the event's duration when it was split around stored sessions
+    pub conflicts: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignored_fields: Vec<String>, // Newer than this build's schema
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub schema_version: u32, // Current version on this side, so clients can tell what will be stored
+    pub accepted: usize,
+    pub rejected: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_skew: Option<ClockSkew>, // Only when flagged
+    pub items: Vec<ItemResult>,
+}
+
+// A validated event, ready for `usage_logs`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidEvent {
+    pub key: ActivityKey,
+    pub start: Timestamp,
+    pub duration: DurationSecs,
+}
+
+pub fn validate(event: &IngestEvent, now: Timestamp) -> Result<ValidEvent, String> {
+    let app_name = title::normalize_title(&event.app_name);
+    if app_name.is_empty() {
+        return Err("app_name is required".to_string());
+    }
+    if event.duration == DurationSecs::ZERO || event.duration > MAX_EVENT_DURATION {
+        return Err(format!("duration must be between 1 and {} seconds", MAX_EVENT_DURATION.as_secs()));
+    }
+    if event.start < MIN_TIMESTAMP {
+        return Err("start must be Unix seconds".to_string());
+    }
+    if event.start + event.duration > now + MAX_FUTURE_SKEW {
+        return Err("event ends in the future".to_string());
+    }
+
+    let url = match event.url.as_deref().map(str::trim).filter(|url| !url.is_empty()) {
+        Some(url) => Some(parse_url_candidate(url).ok_or_else(|| format!("invalid url '{}'", url))?),
+        None => None,
+    };
+    let window_title = title::normalize_title(&event.window_title);
+    let window_title = if window_title.is_empty() { "Unknown".to_string() } else { window_title };
+    let stored_title = title::storage_title(&window_title);
+
+    Ok(ValidEvent {
+        key: ActivityKey::new(&app_name, &stored_title, url.as_deref()),
+        start: event.start,
+        duration: event.duration,
+    })
+}
+
+/// Parses one event of any schema version, returning the fields it ignored.
+pub fn parse_event(value: &serde_json::Value) -> Result<(IngestEvent, Vec<String>), String> {
+    let fields = value.as_object().ok_or_else(|| "event must be an object".to_string())?;
+    let ignored = fields
+        .keys()
+        .filter(|field| !EVENT_FIELDS.contains(&field.as_str()))
+        .cloned()
+        .collect();
+    let event = IngestEvent::deserialize(value).map_err(|e| format!("invalid event: {}", e))?;
+    Ok((event, ignored))
+}
+
+/// Collector clock minus server clock for a batch sent at `sent_at`.
+pub fn skew_secs(sent_at: Timestamp, now: Timestamp) -> i64 {
+    sent_at.as_secs() as i64 - now.as_secs() as i64
+}
+
+/// The skew to report for a batch, or `None` when its clock is close enough.
+pub fn flagged_skew(sent_at: Option<Timestamp>, now: Timestamp, config: &SkewConfig) -> Option<ClockSkew> {
+    let secs = skew_secs(sent_at?, now);
+    (secs.unsigned_abs() >= config.flag_after_secs).then_some(ClockSkew { secs, corrected: config.correct })
+}
+
+impl ValidEvent {
+    fn stored_session(&self, source: &str, skew: Option<ClockSkew>) -> StoredSession {
+        StoredSession {
+            identifier: self.key.to_string(),
+            app_name: self.key.app_name.clone(),
+            window_title: self.key.window_title().to_string(),
+            url: self.key.url().map(str::to_string),
+            source: Some(source.to_string()),
+            clock_skew: skew.map(|skew| skew.secs),
+            start: self.start,
+            end: self.start + self.duration,
+        }
+    }
+}
+
+impl ItemResult {
+    pub fn pending(index: usize) -> Self {
+        Self { index, accepted: false, stored_secs: DurationSecs::ZERO, conflicts: 0, ignored_fields: Vec::new(), error: None }
+    }
+}
+
+/// Parses and validates one event, moving it onto the server's clock when the
+/// batch's skew is corrected. Ignored fields are recorded on `item`.
+pub fn prepare_event(
+    item: &mut ItemResult,
+    value: &serde_json::Value,
+    skew: Option<ClockSkew>,
+    now: Timestamp,
+) -> Option<ValidEvent> {
+    let valid = parse_event(value).and_then(|(mut event, ignored)| {
+        item.ignored_fields = ignored;
+        if let Some(skew) = skew.filter(|skew| skew.corrected) {
+            event.start = Timestamp::from_secs(event.start.as_secs().saturating_add_signed(-skew.secs));
+        }
+        validate(&event, now)
+    });
+    valid.map_err(|e| item.error = Some(e)).ok()
+}
+
+fn ingest_event(
+    conn: &Connection,
+    config: &IngestConfig,
+    source: &str,
+    skew: Option<ClockSkew>,
+    index: usize,
+    value: &serde_json::Value,
+    now: Timestamp,
+) -> ItemResult {
+    let mut item = ItemResult::pending(index);
+    let Some(valid) = prepare_event(&mut item, value, skew, now) else {
+        return item;
+    };
+    match overlap::resolve(conn, config.overlap, &config.source_precedence, &valid.stored_session(source, skew), now) {
+        Ok(resolution) => {
+            item.accepted = resolution.rejected.is_none();
+            item.stored_secs = resolution.stored.iter().map(|(start, end)| end.since(*start)).sum();
+            item.conflicts = resolution.conflicts;
+            item.error = resolution.rejected;
+        }
+        Err(e) => item.error = Some(e.to_string()),
+    }
+    item
+}
+
+pub fn ingest_batch(conn: &mut Connection, batch: &IngestBatch, config: &IngestConfig, now: Timestamp) -> SqlResult<BatchResult> {
+    let skew = flagged_skew(batch.sent_at, now, &config.clock_skew);
+    let tx = conn.transaction()?;
+    let items: Vec<ItemResult> = batch
+        .events
+        .iter()
+        .enumerate()
+        .map(|(index, event)| ingest_event(&tx, config, &batch.source, skew, index, event, now))
+        .collect();
+    tx.commit()?;
+
+    Ok(BatchResult::new(items, skew))
+}
+
+impl BatchResult {
+    pub fn new(items: Vec<ItemResult>, clock_skew: Option<ClockSkew>) -> Self {
+        let accepted = items.iter().filter(|item| item.accepted).count();
+        Self {
+            schema_version: SCHEMA_VERSION,
+            accepted,
+            rejected: items.len() - accepted,
+            clock_skew,
+            items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: Timestamp = Timestamp::from_secs(1_800_000_000);
+
+    fn event(app: &str, url: Option<&str>, start: Timestamp, duration: u64) -> IngestEvent {
+        IngestEvent {
+            app_name: app.to_string(),
+            window_title: "Inbox".to_string(),
+            url: url.map(str::to_string),
+            start,
+            duration: DurationSecs::secs(duration),
+        }
+    }
+
+    #[test]
+    fn valid_events_become_activity_keys() {
+        let valid = validate(&event("firefox.exe", Some("https://mail.example.com/"), NOW - DurationSecs::secs(100), 60), NOW).unwrap();
+        assert_eq!(valid.key.url(), Some("https://mail.example.com/"));
+        assert_eq!(validate(&event("code.exe", None, NOW - DurationSecs::secs(100), 60), NOW).unwrap().key.window_title(), "Inbox");
+    }
+
+    #[test]
+    fn invalid_events_are_rejected_with_a_reason() {
+        assert!(validate(&event(" ", None, NOW - DurationSecs::secs(100), 60), NOW).is_err());
+        assert!(validate(&event("a.exe", None, NOW - DurationSecs::secs(100), 0), NOW).is_err());
+        assert!(validate(&event("a.exe", None, NOW + DurationSecs::hours(1), 60), NOW).is_err());
+        assert!(validate(&event("a.exe", None, Timestamp::from_secs(1_800_000), 60), NOW).is_err());
+        assert!(validate(&event("a.exe", Some("not a url"), NOW - DurationSecs::secs(100), 60), NOW).is_err());
+    }
+
+    #[test]
+    fn newer_event_fields_are_ignored_and_reported() {
+        let value = serde_json::json!({
+            "app_name": "code.exe", "start": NOW.as_secs() - 100, "duration": 60,
+            "category": "Dev", "confidence": 0.9, "machine_id": "laptop"
+        });
+        let (event, mut ignored) = parse_event(&value).unwrap();
+        ignored.sort();
+        assert_eq!((event.app_name.as_str(), event.duration), ("code.exe", DurationSecs::mins(1)));
+        assert_eq!(ignored, vec!["category", "confidence", "machine_id"]);
+        assert!(parse_event(&serde_json::json!({"app_name": "code.exe"})).is_err());
+        assert!(parse_event(&serde_json::json!("code.exe")).is_err());
+    }
+
+    #[test]
+    fn skew_is_flagged_beyond_the_threshold() {
+        let config = SkewConfig::default();
+        assert_eq!(flagged_skew(Some(NOW + DurationSecs::secs(30)), NOW, &config), None);
+        assert_eq!(flagged_skew(None, NOW, &config), None);
+        assert_eq!(flagged_skew(Some(NOW - DurationSecs::mins(10)), NOW, &config), Some(ClockSkew { secs: -600, corrected: false }));
+        let correcting = SkewConfig { correct: true, ..config };
+        assert!(flagged_skew(Some(NOW + DurationSecs::mins(15)), NOW, &correcting).unwrap().corrected);
+    }
+}
@@ -0,0 +1,187 @@
+// One line per configured integration, for telling why data is missing
+// somewhere downstream.
+//
+// Each notification channel, the Notion, InfluxDB, telemetry and Home
+// Assistant pushes, every collector that checked in (browser extensions,
+// watchers, other machines) and every vault server this device syncs with gets
+// a `health` and, when that isn't `ok`, the `reason`. Health comes from the
+// outbound breakers for what this process sends, from the stored cursors for
+// how far behind a sync is, and from the last check-in for collectors.
+
+use rusqlite::{Connection, Result as SqlResult};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::outbound::{CircuitState, IntegrationStatus};
+use crate::units::{DurationSecs, Timestamp};
+use crate::{devices, influx, notion, vault};
+
+// Influx buckets this many intervals behind count as lagging
+const INFLUX_LAG_INTERVALS: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrationKind {
+    Notification,
+    Notion,
+    Influx,
+    Telemetry,
+    HomeAssistant,
+    Collector, // Sends here: a browser extension, watcher or another machine
+    Vault, // A server this device syncs its encrypted sessions with
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Ok,
+    Waiting, // Nothing went out since the start yet
+    Lagging, // Works, but is behind or hasn't been heard from
+    Failing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Integration {
+    pub name: String, // As in `/api/integrations/status` for what this process sends
+    pub kind: IntegrationKind,
+    pub health: Health,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>, // Why it isn't `ok`
+    pub last_success: Option<Timestamp>, // The last heartbeat, for collectors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<Timestamp>, // Synced up to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lag_secs: Option<DurationSecs>,
+}
+
+impl Integration {
+    fn new(name: &str, kind: IntegrationKind) -> Self {
+        Self { name: name.to_string(), kind, health: Health::Waiting, reason: None, last_success: None, cursor: None, lag_secs: None }
+    }
+
+    // Health of what this process sends, from its breaker
+    fn sent(mut self, breakers: &[IntegrationStatus]) -> Self {
+        let Some(breaker) = breakers.iter().find(|breaker| breaker.name == self.name) else {
+            self.reason = Some("nothing sent since the start".to_string());
+            return self;
+        };
+        self.last_success = breaker.last_success;
+        let error = breaker.last_error.as_deref().unwrap_or("unknown error");
+        (self.health, self.reason) = match breaker.state {
+            CircuitState::Closed => (Health::Ok, None),
+            CircuitState::Retrying => (Health::Failing, Some(format!("retrying after {} failures: {}", breaker.consecutive_failures, error))),
+            CircuitState::Open => (Health::Failing, Some(format!("circuit open after {} failures: {}", breaker.consecutive_failures, error))),
+            CircuitState::HalfOpen => (Health::Failing, Some(format!("circuit about to be retried: {}", error))),
+        };
+        self
+    }
+
+    fn behind(mut self, cursor: Timestamp, now: Timestamp, allowed: DurationSecs) -> Self {
+        let lag = now.since(cursor);
+        (self.cursor, self.lag_secs) = (Some(cursor), Some(lag));
+        if lag > allowed && self.health == Health::Ok {
+            self.health = Health::Lagging;
+            self.reason = Some(format!("{} behind", lag));
+        }
+        self
+    }
+}
+
+/// Every integration `config` sets up or that checked in, grouped by kind.
+pub fn summarize(conn: &Connection, config: &Config, breakers: &[IntegrationStatus], now: Timestamp) -> SqlResult<Vec<Integration>> {
+    let mut integrations = Vec::new();
+    for name in config.notifications.channels.keys() {
+        integrations.push(Integration::new(&format!("notification:{}", name), IntegrationKind::Notification).sent(breakers));
+    }
+    if config.notion.token.is_some() && config.notion.database_id.is_some() {
+        let mut integration = Integration::new("notion", IntegrationKind::Notion).sent(breakers);
+        integration.cursor = notion::last_pushed_at(conn)?.map(Timestamp::from_secs);
+        integrations.push(integration);
+    }
+    if config.influx.url.is_some() {
+        let integration = Integration::new("influx", IntegrationKind::Influx).sent(breakers);
+        integrations.push(match influx::pushed_until(conn)? {
+            Some(until) => integration.behind(Timestamp::from_secs(until), now, DurationSecs::secs(config.influx.interval_secs.max(1) * INFLUX_LAG_INTERVALS)),
+            None => integration,
+        });
+    }
+    if config.telemetry.endpoint.is_some() && cfg!(feature = "otel") {
+        integrations.push(Integration::new("telemetry", IntegrationKind::Telemetry).sent(breakers));
+    }
+    if config.home_assistant.mqtt.is_some() {
+        integrations.push(Integration::new("home_assistant", IntegrationKind::HomeAssistant).sent(breakers));
+    }
+    for device in devices::list(conn, &config.devices, now)? {
+        let mut integration = Integration::new(&format!("collector:{}", device.id), IntegrationKind::Collector);
+        integration.last_success = Some(device.last_seen);
+        (integration.health, integration.reason) = if device.online {
+            (Health::Ok, None)
+        } else {
+            (Health::Lagging, Some(format!("no check-in for {}", now.since(device.last_seen))))
+        };
+        integrations.push(integration);
+    }
+    for (server, pushed_until) in vault::pushed_until(conn)? {
+        let mut integration = Integration::new(&format!("vault:{}", server), IntegrationKind::Vault);
+        integration.health = Health::Ok;
+        // Synced by hand with `sysmonitor vault push`, so any lag is expected
+        integrations.push(integration.behind(pushed_until, now, DurationSecs::secs(u64::MAX)));
+    }
+    Ok(integrations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(name: &str, state: CircuitState, failures: u32) -> IntegrationStatus {
+        IntegrationStatus {
+            name: name.into(),
+            state,
+            consecutive_failures: failures,
+            retry_at: None,
+            sent: 3,
+            failed: failures as u64,
+            last_success: Some(Timestamp::from_secs(1_000)),
+            last_failure: (failures > 0).then_some(Timestamp::from_secs(1_100)),
+            last_error: (failures > 0).then(|| "curl failed: (7) Couldn't connect".to_string()),
+        }
+    }
+
+    #[test]
+    fn each_integration_says_why_it_is_not_ok() {
+        let conn = Connection::open_in_memory().unwrap();
+        devices::init_tables(&conn).unwrap();
+        conn.execute_batch("ALTER TABLE devices ADD COLUMN clock_skew INTEGER").unwrap();
+        influx::init_tables(&conn).unwrap();
+        vault::init_tables(&conn).unwrap();
+        devices::check_in(&conn, "firefox", 4, None, Timestamp::from_secs(10_000)).unwrap();
+        devices::check_in(&conn, "laptop", 0, None, Timestamp::from_secs(1_000)).unwrap();
+        conn.execute("INSERT INTO influx_export (id, pushed_until) VALUES (1, 9000)", []).unwrap();
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "notifications": { "channels": { "hooks": { "type": "webhook", "url": "https://example.com" },
+                                              "team": { "type": "slack", "webhook_url": "https://hooks.slack.com/x" } } },
+            "influx": { "url": "http://localhost:8086", "interval_secs": 60 },
+            "home_assistant": { "mqtt": { "host": "localhost" } }
+        }))
+        .unwrap();
+        let breakers = [breaker("influx", CircuitState::Closed, 0), breaker("notification:hooks", CircuitState::Open, 5)];
+        let integrations = summarize(&conn, &config, &breakers, Timestamp::from_secs(10_030)).unwrap();
+        let health: Vec<(&str, Health)> = integrations.iter().map(|integration| (integration.name.as_str(), integration.health)).collect();
+        assert_eq!(
+            health,
+            vec![
+                ("notification:hooks", Health::Failing),
+                ("notification:team", Health::Waiting),
+                ("influx", Health::Lagging),
+                ("home_assistant", Health::Waiting),
+                ("collector:firefox", Health::Ok),
+                ("collector:laptop", Health::Lagging),
+            ]
+        );
+        assert_eq!(integrations[0].reason.as_deref(), Some("circuit open after 5 failures: curl failed: (7) Couldn't connect"));
+        assert_eq!((integrations[2].cursor, integrations[2].lag_secs), (Some(Timestamp::from_secs(9_000)), Some(DurationSecs::secs(1_030))));
+        assert_eq!(integrations[5].reason.as_deref(), Some("no check-in for 9030s"));
+    }
+}
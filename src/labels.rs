@@ -0,0 +1,386 @@
+// Bulk relabeling of stored sessions, and undoing it.
+//
+// Categories come from the rules (see `assignments`), which is little help
+// for a backlog the rules never covered: months of a client's sessions that
+// should have gone to their project. `relabel` sets one category on every
+// session a filter matches (app, window title pattern and a range of days)
+// in a single transaction and records it as a change set. Labels are kept in
+// `session_labels` and win over the rules wherever categories are summed.
+// Each change set remembers the labels it replaced, so `undo` can put them
+// back; a session relabeled again since keeps its newer label.
+
+use std::collections::BTreeSet;
+
+use chrono::NaiveDate;
+use regex::{Regex, RegexBuilder};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::{categories, reports};
+
+pub const MAX_RANGE_DAYS: i64 = 366;
+const MAX_PATTERN_BYTES: usize = 1 << 20; // Compiled size of a title pattern
+pub const LISTED_CHANGES: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LabelFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>, // Case-insensitive, as in category rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_regex: Option<String>,
+    pub from: String, // YYYY-MM-DD, local days, inclusive
+    pub to: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkLabel {
+    pub filter: LabelFilter,
+    pub category: String, // A project is a branch, such as "Projects > Acme"
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// A validated filter, ready to match sessions.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    filter: LabelFilter,
+    title: Option<Regex>,
+    since: u64,
+    until: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeSet {
+    pub id: Option<i64>, // None for a dry run; pass it to undo otherwise
+    pub category: String,
+    pub sessions: usize,
+    pub seconds: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LabelChange {
+    pub id: i64,
+    pub created_at: u64,
+    pub filter: LabelFilter,
+    pub category: String,
+    pub sessions: usize,
+    pub undone_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Undone {
+    pub id: i64,
+    pub restored: usize,
+    pub kept: usize, // Relabeled by a later change set, or no longer stored
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS label_changes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            created_at INTEGER NOT NULL,
+            filter TEXT NOT NULL,
+            category TEXT NOT NULL,
+            sessions INTEGER NOT NULL,
+            undone_at INTEGER
+        )",
+        [],
+    )?;
+    // The label each session had before a change set; NULL for none
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS label_change_sessions (
+            change_id INTEGER NOT NULL,
+            identifier TEXT NOT NULL,
+            start INTEGER NOT NULL,
+            previous_category TEXT,
+            previous_change INTEGER,
+            PRIMARY KEY (change_id, identifier, start)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Checks a request; gives its selection and the normalized category.
+pub fn validate(request: BulkLabel) -> Result<(Selection, String), String> {
+    let category = categories::normalize(&request.category);
+    if category.is_empty() {
+        return Err("category must not be empty".to_string());
+    }
+    let mut filter = request.filter;
+    filter.app = filter.app.map(|app| app.trim().to_string()).filter(|app| !app.is_empty());
+    filter.title_regex = filter.title_regex.filter(|pattern| !pattern.is_empty());
+    let title = match &filter.title_regex {
+        Some(pattern) => Some(
+            RegexBuilder::new(pattern)
+                .size_limit(MAX_PATTERN_BYTES)
+                .build()
+                .map_err(|e| format!("Invalid title_regex: {}", e))?,
+        ),
+        None => None,
+    };
+    let parse = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date));
+    let (from, to) = (parse(&filter.from)?, parse(&filter.to)?);
+    if to < from || (to - from).num_days() >= MAX_RANGE_DAYS {
+        return Err(format!("to must be on or after from, at most {} days later", MAX_RANGE_DAYS - 1));
+    }
+    let since = reports::day_bounds(from).0.max(0) as u64;
+    let until = reports::day_bounds(to).1.max(0) as u64;
+    Ok((Selection { filter, title, since, until }, category))
+}
+
+// (identifier, start, seconds) of the sessions the selection matches
+fn matching(conn: &Connection, selection: &Selection) -> SqlResult<Vec<(String, i64, u64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT identifier, timestamp - duration AS start, MAX(duration), window_title
+         FROM usage_logs
+         WHERE timestamp - duration >= ?1 AND timestamp - duration < ?2 AND (?3 IS NULL OR app_name = ?3 COLLATE NOCASE)
+         GROUP BY identifier, start",
+    )?;
+    let rows = stmt.query_map(params![selection.since, selection.until, selection.filter.app], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, Option<String>>(3)?))
+    })?;
+    let mut sessions = Vec::new();
+    for row in rows {
+        let (identifier, start, duration, title) = row?;
+        if selection.title.as_ref().is_none_or(|pattern| pattern.is_match(title.as_deref().unwrap_or(""))) {
+            sessions.push((identifier, start, duration.max(0) as u64));
+        }
+    }
+    Ok(sessions)
+}
+
+/// Labels every session `selection` matches as `category`, as one change set.
+/// A dry run only counts them.
+pub fn relabel(conn: &mut Connection, selection: &Selection, category: &str, dry_run: bool, now: u64) -> SqlResult<ChangeSet> {
+    let tx = conn.transaction()?;
+    let sessions = matching(&tx, selection)?;
+    let mut change = ChangeSet { id: None, category: category.to_string(), sessions: sessions.len(), seconds: sessions.iter().map(|session| session.2).sum() };
+    if dry_run {
+        return Ok(change);
+    }
+    tx.execute(
+        "INSERT INTO label_changes (created_at, filter, category, sessions) VALUES (?1, ?2, ?3, ?4)",
+        params![now, serde_json::to_string(&selection.filter).unwrap_or_default(), category, sessions.len()],
+    )?;
+    let id = tx.last_insert_rowid();
+    for (identifier, start, _) in &sessions {
+        let previous: Option<(String, i64)> = tx
+            .query_row(
+                "SELECT category, change_id FROM session_labels WHERE identifier = ?1 AND start = ?2",
+                params![identifier, start],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        tx.execute(
+            "INSERT INTO label_change_sessions (change_id, identifier, start, previous_category, previous_change) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, identifier, start, previous.as_ref().map(|p| &p.0), previous.as_ref().map(|p| p.1)],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO session_labels (identifier, start, category, change_id) VALUES (?1, ?2, ?3, ?4)",
+            params![identifier, start, category, id],
+        )?;
+    }
+    tx.commit()?;
+    change.id = Some(id);
+    Ok(change)
+}
+
+/// Moves the labels of sessions that now start elsewhere, given as
+/// `(identifier, old start, new start)`, along with the history `undo` goes
+/// back through. Two labels landing on one session keep one of them.
+pub fn rekey(tx: &Transaction, moves: &[(String, i64, i64)]) -> SqlResult<()> {
+    // All are taken out before any is put back, so a label moving onto a start
+    // that another one leaves is not overwritten by it
+    let mut labels = Vec::new();
+    let mut history = Vec::new();
+    for (identifier, from, to) in moves {
+        let label: Option<(String, i64)> = tx
+            .query_row(
+                "SELECT category, change_id FROM session_labels WHERE identifier = ?1 AND start = ?2",
+                params![identifier, from],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        labels.extend(label.map(|(category, change)| (identifier, *to, category, change)));
+        let changes: Vec<(i64, Option<String>, Option<i64>)> = tx
+            .prepare("SELECT change_id, previous_category, previous_change FROM label_change_sessions WHERE identifier = ?1 AND start = ?2")?
+            .query_map(params![identifier, from], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<SqlResult<_>>()?;
+        history.extend(changes.into_iter().map(|(change, category, previous)| (identifier, *to, change, category, previous)));
+        tx.execute("DELETE FROM session_labels WHERE identifier = ?1 AND start = ?2", params![identifier, from])?;
+        tx.execute("DELETE FROM label_change_sessions WHERE identifier = ?1 AND start = ?2", params![identifier, from])?;
+    }
+    for (identifier, start, category, change) in labels {
+        tx.execute(
+            "INSERT OR REPLACE INTO session_labels (identifier, start, category, change_id) VALUES (?1, ?2, ?3, ?4)",
+            params![identifier, start, category, change],
+        )?;
+    }
+    for (identifier, start, change, category, previous) in history {
+        tx.execute(
+            "INSERT OR REPLACE INTO label_change_sessions (change_id, identifier, start, previous_category, previous_change) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![change, identifier, start, category, previous],
+        )?;
+    }
+    Ok(())
+}
+
+/// Change sets, newest first.
+pub fn list(conn: &Connection, limit: usize) -> SqlResult<Vec<LabelChange>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, created_at, filter, category, sessions, undone_at FROM label_changes ORDER BY id DESC LIMIT ?1",
+    )?;
+    let changes = stmt
+        .query_map(params![limit], |row| {
+            Ok(LabelChange {
+                id: row.get(0)?,
+                created_at: row.get(1)?,
+                filter: serde_json::from_str(&row.get::<_, String>(2)?).unwrap_or_default(),
+                category: row.get(3)?,
+                sessions: row.get(4)?,
+                undone_at: row.get(5)?,
+            })
+        })?
+        .collect();
+    changes
+}
+
+/// Puts back the labels change set `id` replaced, on the sessions it still
+/// labels. None if there is no such change set or it was already undone.
+pub fn undo(conn: &mut Connection, id: i64, now: u64) -> SqlResult<Option<Undone>> {
+    let tx = conn.transaction()?;
+    let sessions: Option<usize> = tx
+        .query_row("SELECT sessions FROM label_changes WHERE id = ?1 AND undone_at IS NULL", params![id], |row| row.get(0))
+        .optional()?;
+    let Some(sessions) = sessions else {
+        return Ok(None);
+    };
+    let labeled: Vec<(String, i64, Option<String>, Option<i64>)> = tx
+        .prepare(
+            "SELECT h.identifier, h.start, h.previous_category, h.previous_change
+             FROM label_change_sessions h
+             JOIN session_labels l ON l.identifier = h.identifier AND l.start = h.start AND l.change_id = h.change_id
+             WHERE h.change_id = ?1",
+        )?
+        .query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<SqlResult<_>>()?;
+    for (identifier, start, category, change) in &labeled {
+        match restored_label(&tx, identifier, *start, category.clone(), *change)? {
+            Some((category, change)) => tx.execute(
+                "UPDATE session_labels SET category = ?3, change_id = ?4 WHERE identifier = ?1 AND start = ?2",
+                params![identifier, start, category, change],
+            )?,
+            None => tx.execute("DELETE FROM session_labels WHERE identifier = ?1 AND start = ?2", params![identifier, start])?,
+        };
+    }
+    tx.execute("UPDATE label_changes SET undone_at = ?2 WHERE id = ?1", params![id, now])?;
+    tx.commit()?;
+    Ok(Some(Undone { id, restored: labeled.len(), kept: sessions.saturating_sub(labeled.len()) }))
+}
+
+// The label to go back to: the previous one, or, when the change set that
+// set it was undone too, the one before that
+fn restored_label(tx: &Transaction, identifier: &str, start: i64, mut category: Option<String>, mut change: Option<i64>) -> SqlResult<Option<(String, i64)>> {
+    let mut seen = BTreeSet::new();
+    while let Some(id) = change {
+        let undone: bool = tx
+            .query_row("SELECT undone_at IS NOT NULL FROM label_changes WHERE id = ?1", params![id], |row| row.get(0))
+            .optional()?
+            .unwrap_or(true);
+        if !undone || !seen.insert(id) {
+            break;
+        }
+        (category, change) = tx
+            .query_row(
+                "SELECT previous_category, previous_change FROM label_change_sessions WHERE change_id = ?1 AND identifier = ?2 AND start = ?3",
+                params![id, identifier, start],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .unwrap_or((None, None));
+    }
+    Ok(category.zip(change))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assignments, fixtures};
+    use crate::categories::CategoryConfig;
+    use crate::identifier::ActivityKey;
+
+    fn db() -> Connection {
+        fixtures::empty().unwrap()
+    }
+
+    fn log(conn: &Connection, app: &str, title: &str, start: u64, duration: u64) {
+        conn.execute(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![ActivityKey::new(app, title, None).to_string(), app, title, start + duration, duration],
+        )
+        .unwrap();
+    }
+
+    fn request(app: Option<&str>, title_regex: Option<&str>, category: &str) -> (Selection, String) {
+        let filter = LabelFilter { app: app.map(String::from), title_regex: title_regex.map(String::from), from: "2026-03-02".into(), to: "2026-03-03".into() };
+        validate(BulkLabel { filter, category: category.into(), dry_run: false }).unwrap()
+    }
+
+    fn category_of(conn: &Connection, app: &str, title: &str) -> String {
+        let day = reports::day_bounds(NaiveDate::from_ymd_opt(2026, 3, 2).unwrap());
+        let categories = CategoryConfig::default();
+        let (sessions, _) = assignments::categorized_sessions(conn, &categories, &assignments::rules_version(&categories), 0, day.1 as u64 + 86_400).unwrap();
+        let key = ActivityKey::new(app, title, None).to_string();
+        sessions.into_iter().find(|session| session.identifier == key).unwrap().category
+    }
+
+    #[test]
+    fn relabels_what_the_filter_matches_and_undoes_in_order() {
+        let mut conn = db();
+        let start = reports::day_bounds(NaiveDate::from_ymd_opt(2026, 3, 2).unwrap()).0 as u64 + 3600;
+        log(&conn, "code.exe", "acme-site - main.rs", start, 600);
+        log(&conn, "Code.exe", "acme-api - lib.rs", start + 600, 300);
+        log(&conn, "code.exe", "notes.md", start + 900, 60);
+        log(&conn, "slack.exe", "acme", start + 960, 60);
+        log(&conn, "code.exe", "acme-old", start - 86_400 * 2, 60); // Before the range
+
+        let (acme, project) = request(Some("code.exe"), Some("^acme-"), "projects>Acme");
+        assert_eq!(project, "projects > Acme");
+        assert_eq!(
+            relabel(&mut conn, &acme, &project, true, 10).unwrap(),
+            ChangeSet { id: None, category: project.clone(), sessions: 2, seconds: 900 }
+        );
+        assert_eq!(category_of(&conn, "code.exe", "acme-site - main.rs"), categories::UNCATEGORIZED);
+
+        let first = relabel(&mut conn, &acme, &project, false, 10).unwrap().id.unwrap();
+        assert_eq!(category_of(&conn, "code.exe", "acme-site - main.rs"), project);
+        let (site, client) = request(None, Some("site"), "Clients > Acme");
+        let second = relabel(&mut conn, &site, &client, false, 20).unwrap().id.unwrap();
+        assert_eq!(category_of(&conn, "code.exe", "acme-site - main.rs"), "Clients > Acme");
+
+        // Undoing the first leaves the session the second relabeled alone
+        assert_eq!(undo(&mut conn, first, 30).unwrap(), Some(Undone { id: first, restored: 1, kept: 1 }));
+        assert_eq!(category_of(&conn, "Code.exe", "acme-api - lib.rs"), categories::UNCATEGORIZED);
+        assert_eq!(category_of(&conn, "code.exe", "acme-site - main.rs"), "Clients > Acme");
+        assert_eq!(undo(&mut conn, first, 40).unwrap(), None);
+        // and undoing the second skips the label the first set
+        assert_eq!(undo(&mut conn, second, 50).unwrap(), Some(Undone { id: second, restored: 1, kept: 0 }));
+        assert_eq!(category_of(&conn, "code.exe", "acme-site - main.rs"), categories::UNCATEGORIZED);
+
+        let listed = list(&conn, LISTED_CHANGES).unwrap();
+        assert_eq!(listed.iter().map(|change| (change.id, change.undone_at)).collect::<Vec<_>>(), vec![(second, Some(50)), (first, Some(30))]);
+        assert_eq!(listed[1].filter, acme.filter);
+    }
+
+    #[test]
+    fn rejects_bad_filters() {
+        let filter = |title_regex: &str, to: &str| LabelFilter { app: None, title_regex: Some(title_regex.into()), from: "2026-03-02".into(), to: to.into() };
+        let check = |filter: LabelFilter, category: &str| validate(BulkLabel { filter, category: category.into(), dry_run: true }).map(|_| ());
+        assert!(check(filter("ok", "2026-03-02"), "Dev").is_ok());
+        assert!(check(filter("(", "2026-03-02"), "Dev").unwrap_err().starts_with("Invalid title_regex"));
+        assert!(check(filter("ok", "2026-03-01"), "Dev").is_err());
+        assert!(check(filter("ok", "2027-03-03"), "Dev").is_err());
+        assert!(check(filter("ok", "2026-03-02"), " > ").is_err());
+    }
+}
@@ -0,0 +1,192 @@
+// The language of window titles, for reports such as time spent reading German
+// against English.
+//
+// Detection is whatlang's trigram model. Titles are a handful of words, often
+// around app and site names, so a guess below `MIN_CONFIDENCE` counts as `und`
+// (undetermined), like most editor and terminal titles. Languages are stored as
+// ISO 639-1 codes. The tracker records the language in `usage_logs.language`
+// as it writes a row; rows from before, or written elsewhere, are detected as
+// reports come to cover them.
+
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+use crate::sessions;
+use crate::units::{DurationSecs, Timestamp};
+
+pub const UNDETERMINED: &str = "und";
+const MIN_CONFIDENCE: f64 = 0.5;
+const BACKFILL_ROWS: usize = 5000; // Rows detected per report
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LanguageQuery {
+    pub since: Option<Timestamp>,
+    pub until: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LanguageTotal {
+    pub language: String,
+    pub seconds: DurationSecs,
+    pub sessions: u64,
+}
+
+/// The ISO 639-1 code of `title`'s language, or `und`.
+pub fn detect(title: &str) -> &'static str {
+    match whatlang::detect(title) {
+        Some(info) if info.confidence() >= MIN_CONFIDENCE => iso_639_1(info.lang()),
+        _ => UNDETERMINED,
+    }
+}
+
+fn iso_639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Afr => "af",
+        Lang::Aka => "ak",
+        Lang::Amh => "am",
+        Lang::Ara => "ar",
+        Lang::Aze => "az",
+        Lang::Bel => "be",
+        Lang::Ben => "bn",
+        Lang::Bul => "bg",
+        Lang::Cat => "ca",
+        Lang::Ces => "cs",
+        Lang::Cmn => "zh",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Epo => "eo",
+        Lang::Est => "et",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Guj => "gu",
+        Lang::Heb => "he",
+        Lang::Hin => "hi",
+        Lang::Hrv => "hr",
+        Lang::Hun => "hu",
+        Lang::Hye => "hy",
+        Lang::Ind => "id",
+        Lang::Ita => "it",
+        Lang::Jav => "jv",
+        Lang::Jpn => "ja",
+        Lang::Kan => "kn",
+        Lang::Kat => "ka",
+        Lang::Khm => "km",
+        Lang::Kor => "ko",
+        Lang::Lat => "la",
+        Lang::Lav => "lv",
+        Lang::Lit => "lt",
+        Lang::Mal => "ml",
+        Lang::Mar => "mr",
+        Lang::Mkd => "mk",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Nld => "nl",
+        Lang::Nob => "nb",
+        Lang::Ori => "or",
+        Lang::Pan => "pa",
+        Lang::Pes => "fa",
+        Lang::Pol => "pl",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Sin => "si",
+        Lang::Slk => "sk",
+        Lang::Slv => "sl",
+        Lang::Sna => "sn",
+        Lang::Spa => "es",
+        Lang::Srp => "sr",
+        Lang::Swe => "sv",
+        Lang::Tam => "ta",
+        Lang::Tel => "te",
+        Lang::Tgl => "tl",
+        Lang::Tha => "th",
+        Lang::Tuk => "tk",
+        Lang::Tur => "tr",
+        Lang::Ukr => "uk",
+        Lang::Urd => "ur",
+        Lang::Uzb => "uz",
+        Lang::Vie => "vi",
+        Lang::Yid => "yi",
+        Lang::Zul => "zu",
+    }
+}
+
+/// Detects rows of `[since, until)` stored without a language.
+pub fn backfill(conn: &mut Connection, since: Timestamp, until: Timestamp) -> SqlResult<usize> {
+    let rows: Vec<(i64, String)> = conn
+        .prepare("SELECT id, window_title FROM usage_logs WHERE language IS NULL AND timestamp >= ?1 AND timestamp - duration < ?2 LIMIT ?3")?
+        .query_map(params![since, until, BACKFILL_ROWS], |row| Ok((row.get(0)?, row.get::<_, Option<String>>(1)?.unwrap_or_default())))?
+        .collect::<SqlResult<_>>()?;
+    let tx = conn.transaction()?;
+    for (id, title) in &rows {
+        tx.execute("UPDATE usage_logs SET language = ?1 WHERE id = ?2", params![detect(title), id])?;
+    }
+    tx.commit()?;
+    Ok(rows.len())
+}
+
+/// Time per language within `[since, until)`, most first. Sessions reaching
+/// past either end count only their part inside.
+pub fn totals(conn: &mut Connection, since: Timestamp, until: Timestamp) -> SqlResult<Vec<LanguageTotal>> {
+    backfill(conn, since, until)?;
+    // Keyed by where each session starts once clipped, as `sessions::clipped` does
+    let languages: HashMap<(String, Timestamp), String> = conn
+        .prepare(
+            "SELECT identifier, MAX(timestamp - duration, ?1) AS start, COALESCE(MAX(language), ?3)
+             FROM usage_logs WHERE timestamp > ?1 AND timestamp - duration < ?2
+             GROUP BY identifier, timestamp - duration",
+        )?
+        .query_map(params![since, until, UNDETERMINED], |row| Ok(((row.get(0)?, row.get(1)?), row.get(2)?)))?
+        .collect::<SqlResult<_>>()?;
+    let mut by_language: HashMap<&str, (DurationSecs, u64)> = HashMap::new();
+    for session in sessions::clipped(conn, since, until)? {
+        let language = languages.get(&(session.identifier.clone(), session.start)).map_or(UNDETERMINED, String::as_str);
+        let total = by_language.entry(language).or_default();
+        *total = (total.0 + session.duration(), total.1 + 1);
+    }
+    let mut totals: Vec<LanguageTotal> = by_language
+        .into_iter()
+        .map(|(language, (seconds, sessions))| LanguageTotal { language: language.to_string(), seconds, sessions })
+        .collect();
+    totals.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.language.cmp(&b.language)));
+    Ok(totals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn titles_get_the_language_they_are_written_in() {
+        assert_eq!(detect("Die Geschichte der Stadt und ihre Menschen – Wikipedia"), "de");
+        assert_eq!(detect("How to learn the piano in a year - YouTube"), "en");
+        assert_eq!(detect("Comment apprendre le piano pour les débutants"), "fr");
+        assert_eq!(detect("Как выучить английский"), "ru");
+        assert_eq!(detect("Jak nauczyć się gotować"), "pl");
+        assert_eq!(detect("東京の天気 - Google 検索"), "ja");
+        assert_eq!(detect("main.rs - sysmonitor - Visual Studio Code"), UNDETERMINED);
+
+        let mut conn = fixtures::empty().unwrap();
+        conn.execute_batch(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration, language) VALUES ('a', 'a', 'x', 1060, 60, 'de');
+             INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('b', 'b', 'Die Geschichte der Stadt und ihre Menschen', 2000, 300);
+             INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('b', 'b', 'Die Geschichte der Stadt und ihre Menschen', 2600, 900);
+             INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('c', 'c', 'What is the weather', 3000, 100);",
+        )
+        .unwrap();
+        let totals = totals(&mut conn, Timestamp::EPOCH, Timestamp::from_secs(10_000)).unwrap();
+        let summary: Vec<(&str, u64, u64)> = totals.iter().map(|total| (total.language.as_str(), total.seconds.as_secs(), total.sessions)).collect();
+        assert_eq!(summary, vec![("de", 960, 2), ("en", 100, 1)]);
+
+        // Sessions over either end of the range count only their part inside
+        let edges = super::totals(&mut conn, Timestamp::from_secs(1800), Timestamp::from_secs(2950)).unwrap();
+        let summary: Vec<(&str, u64, u64)> = edges.iter().map(|total| (total.language.as_str(), total.seconds.as_secs(), total.sessions)).collect();
+        assert_eq!(summary, vec![("de", 800, 1), ("en", 50, 1)]);
+    }
+}
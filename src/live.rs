@@ -0,0 +1,79 @@
+// Every activity the tracker saw since it started, a page at a time, for
+// `/api/activity/live`.
+//
+// The dashboard answer only carries the activities in focus, capped at
+// `MAX_ACTIVE_APPS`, so it stays small however long the tracker runs and however
+// many windows it went through. Anything wanting the whole list pages through
+// it here: in focus first, then by when each was last seen.
+
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::ActivityKey;
+use crate::units::{DurationSecs, Timestamp};
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LiveQuery {
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>, // Up to `MAX_LIMIT`
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiveActivity {
+    pub key: ActivityKey,
+    pub active: bool,
+    pub active_secs: DurationSecs, // Of the current or last session
+    pub last_seen: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LivePage {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub next_offset: Option<usize>, // None on the last page
+    pub activities: Vec<LiveActivity>,
+}
+
+/// The page of `activities` that `query` asks for.
+pub fn page(mut activities: Vec<LiveActivity>, query: &LiveQuery) -> LivePage {
+    activities.sort_by(|a, b| (b.active, b.last_seen, &a.key.detail).cmp(&(a.active, a.last_seen, &b.key.detail)));
+    let total = activities.len();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = query.offset.min(total);
+    let activities: Vec<LiveActivity> = activities.into_iter().skip(offset).take(limit).collect();
+    let next_offset = (offset + activities.len() < total).then_some(offset + activities.len());
+    LivePage { total, offset, limit, next_offset, activities }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(title: &str, active: bool, last_seen: u64) -> LiveActivity {
+        LiveActivity {
+            key: ActivityKey::new("code.exe", title, None),
+            active,
+            active_secs: DurationSecs::secs(10),
+            last_seen: Timestamp::from_secs(last_seen),
+        }
+    }
+
+    #[test]
+    fn pages_go_from_the_focused_to_the_longest_unseen() {
+        let activities: Vec<LiveActivity> =
+            (0..120).map(|index| activity(&format!("file{}.rs", index), index == 7, 1_000 + index)).collect();
+        let first = page(activities.clone(), &LiveQuery::default());
+        assert_eq!((first.total, first.limit, first.next_offset), (120, DEFAULT_LIMIT, Some(50)));
+        assert_eq!(first.activities[0].key.detail, "file7.rs");
+        assert_eq!(first.activities[1].key.detail, "file119.rs");
+
+        let last = page(activities.clone(), &LiveQuery { offset: 100, limit: Some(50) });
+        assert_eq!((last.activities.len(), last.next_offset), (20, None));
+        assert_eq!(last.activities[19].key.detail, "file0.rs");
+        assert!(page(activities, &LiveQuery { offset: 500, limit: Some(10_000) }).activities.is_empty());
+    }
+}
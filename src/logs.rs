@@ -0,0 +1,132 @@
+// The process's own log: what the tracker and server print while running,
+// kept in memory for `/api/logs/tail`.
+//
+// A tray or service instance has no console to look at, so each line printed
+// through `info!` or `error!` also goes into a ring of the last
+// `CAPACITY` records and out to whoever is tailing. Command output that is
+// meant for the terminal (reports, `status`, `diagnose`) is printed directly
+// and never lands here.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+pub const CAPACITY: usize = 1000;
+pub const DEFAULT_LINES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TailQuery {
+    pub lines: Option<usize>, // Recent records sent before the live ones; `DEFAULT_LINES` by default
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    pub seq: u64,
+    pub at: u64,
+    pub level: Level,
+    pub message: String,
+}
+
+struct Log {
+    recent: Mutex<(u64, VecDeque<Record>)>, // Next sequence number, last records
+    live: broadcast::Sender<Record>,
+}
+
+impl Log {
+    fn new() -> Self {
+        Self { recent: Mutex::new((0, VecDeque::new())), live: broadcast::channel(CAPACITY).0 }
+    }
+
+    fn record(&self, level: Level, message: String) {
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let mut recent = self.recent.lock().unwrap();
+        let record = Record { seq: recent.0, at, level, message };
+        recent.0 += 1;
+        if recent.1.len() == CAPACITY {
+            recent.1.pop_front();
+        }
+        recent.1.push_back(record.clone());
+        // Sent under the lock so a tail never sees a record both as recent and live
+        let _ = self.live.send(record);
+    }
+
+    fn tail(&self, lines: usize) -> (Vec<Record>, broadcast::Receiver<Record>) {
+        let recent = self.recent.lock().unwrap();
+        let skip = recent.1.len().saturating_sub(lines);
+        (recent.1.iter().skip(skip).cloned().collect(), self.live.subscribe())
+    }
+}
+
+fn log() -> &'static Log {
+    static LOG: OnceLock<Log> = OnceLock::new();
+    LOG.get_or_init(Log::new)
+}
+
+/// Prints `message` (errors to stderr) and records it.
+pub fn write(level: Level, message: String) {
+    match level {
+        Level::Info => println!("{}", message),
+        Level::Error => eprintln!("{}", message),
+    }
+    log().record(level, message);
+}
+
+/// The last `lines` records without waiting on the lock, which a thread that
+/// panicked while logging may still hold; empty then.
+pub fn try_recent(lines: usize) -> Vec<Record> {
+    match log().recent.try_lock() {
+        Ok(recent) => recent.1.iter().skip(recent.1.len().saturating_sub(lines)).cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The last `lines` records, oldest first, and a receiver for the ones after them.
+pub fn tail(lines: usize) -> (Vec<Record>, broadcast::Receiver<Record>) {
+    log().tail(lines)
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logs::write($crate::logs::Level::Info, format!($($arg)*))
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logs::write($crate::logs::Level::Error, format!($($arg)*))
+    };
+}
+
+pub(crate) use {error, info};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_tail_gets_the_last_lines_then_what_follows() {
+        // Tests log concurrently, so this one keeps its own
+        let log = Log::new();
+        for line in 0..CAPACITY + 5 {
+            log.record(Level::Info, format!("line {}", line));
+        }
+        let (recent, mut live) = log.tail(3);
+        let messages: Vec<&str> = recent.iter().map(|record| record.message.as_str()).collect();
+        assert_eq!(messages, vec![format!("line {}", CAPACITY + 2), format!("line {}", CAPACITY + 3), format!("line {}", CAPACITY + 4)]);
+        assert_eq!(log.tail(CAPACITY * 2).0.len(), CAPACITY);
+
+        log.record(Level::Error, "failed: disk full".to_string());
+        let next = live.try_recv().unwrap();
+        assert_eq!((next.seq, next.level, next.message.as_str()), (recent[2].seq + 1, Level::Error, "failed: disk full"));
+    }
+}
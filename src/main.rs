@@ -1,57 +1,249 @@
+// Reduced feature sets leave query helpers and notifier plumbing without callers
+#![cfg_attr(not(all(feature = "web", feature = "notifications")), allow(dead_code))]
+// The HTTP API's warp filter chain outgrows the default nesting depth
+#![recursion_limit = "512"]
+
 use std::env;
-use std::path::Path;
+#[cfg(feature = "web")]
 use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "web")]
 use std::net::{TcpListener, SocketAddr};
 
+#[cfg(feature = "web")]
+mod api;
+mod apps;
+#[cfg(feature = "compression")]
+mod archive;
+#[cfg(feature = "web")]
+mod assets;
+mod assignments;
+#[cfg(feature = "web")]
+mod audit;
+mod auth;
+#[cfg(test)]
+mod bench;
+mod blocks;
+#[cfg(feature = "compression")]
+mod bundle;
+mod capabilities;
+mod categories;
+mod child;
+mod clock;
+#[cfg(feature = "compression")]
+mod compression;
+mod config;
+mod crash;
+mod curl;
+mod csv;
+mod daily_note;
+mod daily_summary;
+mod dashboard;
+mod debounce;
+#[cfg(all(feature = "web", feature = "fixtures"))]
+mod demo;
+mod devices;
+mod disambiguate;
+mod distractions;
+mod doctor;
+mod events;
+#[cfg(feature = "compression")]
+mod export;
+#[cfg(feature = "web")]
+mod favicons;
+#[cfg(any(test, feature = "fixtures"))]
+mod fixtures;
+#[cfg(feature = "web")]
+mod formats;
+mod forecast;
+mod goals;
+mod grafana;
+mod home_assistant;
+#[cfg(feature = "web")]
+mod http_cache;
+mod identifier;
+mod idle;
+mod influx;
+mod ingest;
+#[cfg(feature = "web")]
+mod integrations;
+mod labels;
+mod language;
+#[cfg(feature = "web")]
+mod live;
+mod logs;
+mod maintenance;
+mod memory;
+mod metrics;
+#[cfg(feature = "web")]
+mod msgpack;
+mod notify;
+mod notion;
+mod origin;
+mod outbound;
+mod overlap;
+#[cfg(windows)]
+mod packaged;
+mod panels;
+mod pauses;
+mod pdf;
+mod power;
+mod process;
+mod profiles;
+mod queries;
+mod quotas;
+#[cfg(feature = "web")]
+mod refresh;
+mod reports;
+mod reprocess;
+mod retention;
+mod rollups;
+mod rules;
+mod safe_mode;
+#[cfg(feature = "web")]
+mod schemas;
+#[cfg(feature = "web")]
+mod server;
+mod sessions;
+mod signatures;
+mod signing;
+mod summarize;
+mod team;
+mod telemetry;
+mod timeline;
+mod title;
+mod transitions;
+mod trends;
+mod units;
+mod url_cache;
+#[cfg(feature = "web")]
+mod validation;
+mod vault;
+#[cfg(feature = "web")]
+mod versions;
+mod warm_start;
+#[cfg(feature = "web")]
+mod web;
+#[cfg(feature = "web")]
+mod widgets;
+mod writer;
+
+use config::Config;
+use identifier::ActivityKey;
+use metrics::RuntimeMetrics;
+use notify::{Attachment, Dispatcher, EventKind, Notification};
+#[cfg(windows)]
+use packaged::PackageIdentity;
+use process::{ProcessAccess, ProcessInfo};
+use profiles::{AppProfile, Extractor};
+use units::{DurationSecs, Timestamp};
+
 use hashbrown::HashMap as FastHashMap;
 use rusqlite::{params, Connection, Result as SqlResult};
 use serde::{Deserialize, Serialize};
-use warp::Filter;
+#[cfg(windows)]
 use windows::{
-    Win32::Foundation::BOOL,
-    Win32::System::ProcessStatus::GetProcessImageFileNameW,
-    Win32::System::Threading::{OpenProcess, PROCESS_QUERY_INFORMATION},
-    Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId},
+    Win32::UI::WindowsAndMessaging::{GetClassNameW, GetForegroundWindow, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId},
 };
 
 // Configuration constants
-const ACTIVITY_RETENTION_HOURS: u64 = 24; // Keep activity data for 24 hours (1 day)
+const SCHEMA_VERSION: i32 = 10; // Bumped whenever usage_logs rows need migrating
+const ACTIVITY_RETENTION: DurationSecs = DurationSecs::days(1); // How far back recent activity reaches
 const MAX_RECENT_ACTIVITIES: usize = 1000; // Show all activities (effectively unlimited)
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct UsageEntry {
-    identifier: String,
-    app_name: String,
-    window_title: String,
-    url: Option<String>,
-    last_seen: u64,
-    total_time: u64,
-}
+const MAX_ACTIVE_APPS: usize = 10; // In one dashboard answer; `/api/activity/live` pages through all of them
+const DIAGNOSE_DURATION: DurationSecs = DurationSecs::secs(60); // How long `--diagnose` watches the tracker
+const BLOCK_REFRESH: DurationSecs = DurationSecs::mins(5); // How often work blocks are rebuilt while tracking
+const DEVICE_CHECK: DurationSecs = DurationSecs::mins(1); // How often silent collectors are looked for
+const CATEGORY_REFRESH: DurationSecs = DurationSecs::mins(1); // How often stored category assignments catch up
+const ROLLUP_REFRESH: DurationSecs = DurationSecs::hours(1); // How often finished days are looked for to roll up
+const PAUSE_CHECK: DurationSecs = DurationSecs::secs(5); // How often the server looks for timed pauses to end
+const DIGEST_CHECK: DurationSecs = DurationSecs::mins(5); // How often the goal digest hour is looked for
+const DAILY_NOTE_CHECK: DurationSecs = DurationSecs::mins(10); // How often a day that ended is looked for to write its note
+const NOTION_SYNC: DurationSecs = DurationSecs::hours(1); // How often recent days are pushed to Notion
+const GRAFANA_REFRESH: DurationSecs = DurationSecs::mins(5); // How often the Grafana tables catch up
+const TELEMETRY_EXPORT: DurationSecs = DurationSecs::secs(30); // How often spans and metrics go to the OTLP collector
+const INFLUX_CHECK: DurationSecs = DurationSecs::secs(15); // How often finished Influx intervals are looked for
+const HOME_ASSISTANT_PUBLISH: DurationSecs = DurationSecs::secs(15); // How often Home Assistant sensors are looked at for changes
+const SCREEN_TIME_REFRESH: DurationSecs = DurationSecs::mins(1); // How long today's screen time is reused before it's added up again
+const RETENTION_CHECK: DurationSecs = DurationSecs::hours(1); // How often expired URLs, titles and rows are pruned
+const MAINTENANCE_CHECK: DurationSecs = DurationSecs::mins(1); // How often housekeeping jobs are looked at for being due
+const IDLE_CHECK: DurationSecs = DurationSecs::secs(5); // How often the time since the last input is looked at
+const MEMORY_CHECK: DurationSecs = DurationSecs::mins(1); // How often the process's own memory is sampled
+const LOCK_SCREEN_APP: &str = "LockApp.exe"; // In the foreground while Windows is locked
+#[cfg(feature = "compression")]
+const ARCHIVE_CHECK: DurationSecs = DurationSecs::days(1); // How often old sessions are moved to the archive
+const HIDDEN_TITLE: &str = "(title hidden)"; // Stored for apps whose profile disables titles
+#[cfg(windows)]
+const MAX_TITLE_BUFFER: usize = 4096; // UTF-16 units read from GetWindowTextW
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ActiveEntry {
     status: bool,
-    last_seen: u64,
-    start_time: u64, // When this app first became active
+    last_seen: Timestamp,
+    start_time: Timestamp, // When this app first became active; places the session
+    #[serde(skip)]
+    since: Option<Instant>, // Monotonic reading at `start_time`; measures it
+    #[serde(skip)]
+    left: Option<Instant>, // When it last stopped being active, for debouncing
+    #[serde(skip)]
+    away: bool, // Stopped by idle input or the lock screen, with nothing in focus since
+    full_title: Option<String>, // Untruncated title when the key holds a shortened one
+    partial: bool, // Process details could only be partially resolved
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct ApiResponse {
-    success: bool,
-    data: Option<serde_json::Value>,
-    error: Option<String>,
+// The wall-clock time of `at`
+fn wall_time_at(at: Instant) -> Timestamp {
+    Timestamp::now().saturating_sub(Instant::now().saturating_duration_since(at).into())
+}
+
+// How long `entry` has been active at `now`, by the monotonic clock
+fn active_secs(entry: &ActiveEntry, now: Instant) -> DurationSecs {
+    match entry.since {
+        Some(since) => now.saturating_duration_since(since).into(),
+        None => Timestamp::now().since(entry.start_time),
+    }
+}
+
+// A usage_logs row as produced from the in-memory state at flush time
+#[derive(Debug, Clone)]
+struct PendingRow {
+    key: ActivityKey,
+    timestamp: Timestamp,
+    duration: DurationSecs,
+    partial: bool,
+    full_title: Option<String>,
+    ended: bool, // Its final length: rows flushed past it, e.g. while idle, are removed
+}
+
+// An app-to-app focus switch waiting for the next flush
+#[derive(Debug, Clone)]
+struct PendingTransition {
+    from: String,
+    to: String,
+    timestamp: Timestamp,
+}
+
+type AppDetails = (Option<String>, Option<String>); // Image path and window class
+
+#[derive(Debug, Clone)]
+struct WindowInfo {
+    app_name: String,
+    window_title: String,
+    url: Option<String>,
+    access: ProcessAccess,
+    profile: AppProfile,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct RecentActivity {
-    identifier: String,
+    key: ActivityKey,
     app_name: String,
     window_title: String,
     url: Option<String>,
-    duration: u64,
-    timestamp: u64,
+    duration: DurationSecs,
+    timestamp: Timestamp,
+    partial: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,34 +251,139 @@ struct DashboardData {
     current_app: Option<String>,
     current_window: Option<String>,
     current_url: Option<String>,
-    active_apps: Vec<(String, u64)>,
+    active_apps: Vec<(ActivityKey, DurationSecs)>,
     recent_activity: Vec<RecentActivity>,
     total_apps: usize,
-    uptime: u64,
+    uptime: DurationSecs,
+    safe_mode: Option<safe_mode::SafeMode>,
 }
 
 struct SystemMonitor {
-    usage_data: Arc<Mutex<FastHashMap<String, ActiveEntry>>>,
+    usage_data: Arc<Mutex<FastHashMap<ActivityKey, ActiveEntry>>>,
     db_path: String,
+    config_path: String,
     start_time: u64,
+    started: Instant, // For uptime, which the wall clock can't measure across changes
     debug_mode: bool,
+    diagnose_mode: bool, // Trace every tracker decision (`--diagnose`)
+    diagnose_trace: Mutex<Vec<String>>, // Decisions made during the current poll
+    require_token: bool, // Refuse tokenless API requests (`server` mode listens beyond localhost)
+    metrics: RuntimeMetrics,
+    db_size_start: u64, // Database file size when the process started
+    config: Config,
+    notifier: Dispatcher,
+    outbound: Arc<outbound::Outbound>, // Breakers of the integrations, notifications included
+    telemetry: telemetry::Telemetry,
+    quotas: quotas::QuotaTracker, // Ingest budgets used so far
+    last_app: Mutex<Option<String>>, // App of the previous tracked focus, for transitions
+    pending_transitions: Mutex<Vec<PendingTransition>>,
+    pending_events: Mutex<Vec<events::Event>>, // Appended to the event log with the next flush
+    last_heartbeat: Mutex<Timestamp>, // Of the open session, or its focus event
+    pause: Mutex<Option<pauses::Pause>>, // Open pause; tracking stops while set
+    #[cfg(windows)]
+    package_cache: Mutex<FastHashMap<String, Option<PackageIdentity>>>, // Keyed by package full name
+    app_details: Mutex<FastHashMap<String, AppDetails>>, // Last stored per app, for `apps`
+    url_cache: Mutex<url_cache::UrlCache>, // Browser URLs last read per window
+    warm_start: Mutex<Option<warm_start::OpenSession>>, // Left open by the last shutdown, until the first focus
+    last_daily_note: Mutex<Option<chrono::NaiveDate>>, // Day whose note was last written on its own
+    home_assistant_published: Mutex<Option<home_assistant::SensorState>>, // None until discovery went out
+    screen_time: Mutex<Option<(chrono::NaiveDate, u64, Instant)>>, // Today's tracked seconds, and when they were added up
+    memory: Mutex<memory::Watchdog>,
+    maintenance: Mutex<()>, // Held through a housekeeping job, so two never run at once
+    capabilities: OnceLock<Vec<capabilities::Capability>>, // Probed on first use
+    safe_mode: Option<safe_mode::SafeMode>, // Why this run started in safe mode
+    writer: writer::Writer, // When queued events are flushed
+    summarizers: summarize::Summarizers, // Stable contexts for noisy chat titles
 }
 
 impl SystemMonitor {
     fn new(debug_mode: bool) -> Self {
+        Self::open(debug_mode, None)
+    }
+
+    // For the tracker and the server: counts the start in the crash marker,
+    // and starts in safe mode after repeated crashes or when `force_safe_mode`
+    fn starting(debug_mode: bool, force_safe_mode: bool) -> Self {
+        Self::open(debug_mode, Some(force_safe_mode))
+    }
+
+    fn open(debug_mode: bool, starting: Option<bool>) -> Self {
+        let db_path = env::var(config::DB_PATH_ENV).unwrap_or_else(|_| config::DB_PATH.to_string());
+        let config_path = env::var(config::CONFIG_PATH_ENV).unwrap_or_else(|_| config::CONFIG_PATH.to_string());
+        let mut config = Config::load(&config_path);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let safe_mode = starting.and_then(|forced| safe_mode::start(&config.safe_mode, &safe_mode::marker_path(&db_path), forced, now));
+        if let Some(ref safe_mode) = safe_mode {
+            logs::error!("Starting in safe mode: {}. URL extraction, title summaries, app profiles and integrations are off", safe_mode.reason);
+            safe_mode::restrict(&mut config);
+        }
+        if config.signing.sessions {
+            match config.signing.key() {
+                Ok(key) => signatures::install(key),
+                Err(e) if debug_mode => logs::error!("Sessions will not be signed: {}", e),
+                Err(_) => {}
+            }
+        }
+        let (writer, flush_error) = writer::Writer::from_config(&config.flush);
+        if let Some(e) = flush_error {
+            logs::error!("Ignoring the flush settings: {}", e);
+        }
+        let (summarizers, summary_errors) = summarize::Summarizers::from_config(&config.title_summaries);
+        for e in summary_errors {
+            logs::error!("Ignoring a {}", e);
+        }
+        let outbound = Arc::new(outbound::Outbound::new(&config.outbound, &db_path));
         Self {
             usage_data: Arc::new(Mutex::new(FastHashMap::new())),
-            db_path: "usage.db".to_string(),
+            db_size_start: std::fs::metadata(&db_path).map(|meta| meta.len()).unwrap_or(0),
+            db_path,
+            config_path,
             start_time: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            started: Instant::now(),
             debug_mode,
+            diagnose_mode: false,
+            diagnose_trace: Mutex::new(Vec::new()),
+            require_token: false,
+            metrics: RuntimeMetrics::default(),
+            notifier: Dispatcher::from_config(&config.notifications, outbound.clone()),
+            outbound,
+            telemetry: telemetry::Telemetry::from_config(&config.telemetry),
+            quotas: quotas::QuotaTracker::default(),
+            config,
+            last_app: Mutex::new(None),
+            pending_transitions: Mutex::new(Vec::new()),
+            pending_events: Mutex::new(Vec::new()),
+            last_heartbeat: Mutex::new(Timestamp::EPOCH),
+            pause: Mutex::new(None),
+            #[cfg(windows)]
+            package_cache: Mutex::new(FastHashMap::new()),
+            app_details: Mutex::new(FastHashMap::new()),
+            url_cache: Mutex::new(url_cache::UrlCache::default()),
+            warm_start: Mutex::new(None),
+            last_daily_note: Mutex::new(None),
+            home_assistant_published: Mutex::new(None),
+            screen_time: Mutex::new(None),
+            memory: Mutex::new(memory::Watchdog::default()),
+            maintenance: Mutex::new(()),
+            capabilities: OnceLock::new(),
+            safe_mode,
+            writer,
+            summarizers,
         }
     }
 
     fn init_database(&self) -> SqlResult<()> {
         let conn = Connection::open(&self.db_path)?;
+        Self::init_schema(&conn, self.debug_mode)?;
+        *self.pause.lock().unwrap() = pauses::active(&conn)?;
+        Ok(())
+    }
+
+    // Every table at the current schema version; also what test fixtures start from
+    fn init_schema(conn: &Connection, debug_mode: bool) -> SqlResult<()> {
         conn.execute(
             "CREATE TABLE IF NOT EXISTS usage_logs (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -99,9 +396,176 @@ impl SystemMonitor {
             )",
             [],
         )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS title_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                identifier TEXT NOT NULL,
+                full_title TEXT NOT NULL,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                UNIQUE(identifier, full_title)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS apps (
+                app_name TEXT PRIMARY KEY,
+                executable TEXT NOT NULL,
+                package_full_name TEXT,
+                logo_path TEXT,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS focus_transitions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                from_app TEXT NOT NULL,
+                to_app TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_focus_transitions_timestamp ON focus_transitions (timestamp)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS work_blocks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                start_time INTEGER NOT NULL,
+                end_time INTEGER NOT NULL,
+                active_secs INTEGER NOT NULL,
+                session_count INTEGER NOT NULL,
+                app_switches INTEGER NOT NULL,
+                top_apps TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS runtime_stats (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at INTEGER NOT NULL,
+                ended_at INTEGER NOT NULL,
+                stats TEXT NOT NULL
+            )",
+            [],
+        )?;
+        auth::init_tables(conn)?;
+        #[cfg(feature = "web")]
+        audit::init_tables(conn)?;
+        overlap::init_tables(conn)?;
+        devices::init_tables(conn)?;
+        vault::init_tables(conn)?;
+        team::init_tables(conn)?;
+        assignments::init_tables(conn)?;
+        labels::init_tables(conn)?;
+        daily_summary::init_tables(conn)?;
+        rollups::init_tables(conn)?;
+        pauses::init_tables(conn)?;
+        goals::init_tables(conn)?;
+        clock::init_tables(conn)?;
+        events::init_tables(conn)?;
+        dashboard::init_tables(conn)?;
+        panels::init_tables(conn)?;
+        queries::init_tables(conn)?;
+        notion::init_tables(conn)?;
+        grafana::init_tables(conn)?;
+        influx::init_tables(conn)?;
+        outbound::init_tables(conn)?;
+        warm_start::init_tables(conn)?;
+        maintenance::init_tables(conn)?;
+        Self::migrate_database(conn, debug_mode)
+    }
+
+    fn migrate_database(conn: &Connection, debug_mode: bool) -> SqlResult<()> {
+        let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version >= SCHEMA_VERSION {
+            return Ok(());
+        }
+        if version < 1 {
+            Self::migrate_identifiers(conn)?;
+        }
+        if version < 2 {
+            conn.execute_batch("ALTER TABLE usage_logs ADD COLUMN partial INTEGER NOT NULL DEFAULT 0")?;
+        }
+        if version < 3 {
+            // Origin of imported or synced rows; NULL for the tracker's own
+            conn.execute_batch("ALTER TABLE usage_logs ADD COLUMN source TEXT")?;
+        }
+        if version < 4 {
+            // Clock skew of the collector behind a row, when it was large enough to flag
+            conn.execute_batch(
+                "ALTER TABLE usage_logs ADD COLUMN clock_skew INTEGER;
+                 ALTER TABLE devices ADD COLUMN clock_skew INTEGER;",
+            )?;
+        }
+        if version < 5 {
+            conn.execute_batch("ALTER TABLE pauses ADD COLUMN resume_at INTEGER")?;
+        }
+        if version < 6 {
+            // Where each app was last launched from
+            conn.execute_batch(
+                "ALTER TABLE apps ADD COLUMN image_path TEXT;
+                 ALTER TABLE apps ADD COLUMN origin TEXT;",
+            )?;
+        }
+        if version < 7 {
+            // Details removed by `retention`: 1 the URL, 2 the title
+            conn.execute_batch("ALTER TABLE usage_logs ADD COLUMN redacted INTEGER NOT NULL DEFAULT 0")?;
+        }
+        if version < 8 {
+            // HMAC of the row under the device key, with `signing.sessions` on
+            conn.execute_batch("ALTER TABLE usage_logs ADD COLUMN signature TEXT")?;
+        }
+        if version < 9 {
+            // ISO 639-1 code of the title's language, `und` when there is no telling
+            conn.execute_batch("ALTER TABLE usage_logs ADD COLUMN language TEXT")?;
+        }
+        if version < 10 {
+            // The exe's FileDescription and the class of its last focused window
+            conn.execute_batch(
+                "ALTER TABLE apps ADD COLUMN description TEXT;
+                 ALTER TABLE apps ADD COLUMN window_class TEXT;",
+            )?;
+        }
+        conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+
+        if debug_mode {
+            logs::info!("Migrated database from schema version {} to {}", version, SCHEMA_VERSION);
+        }
         Ok(())
     }
 
+    fn migrate_identifiers(conn: &Connection) -> SqlResult<()> {
+        // Version 0 rows used unescaped `app:title` identifiers and stored missing
+        // URLs as empty strings; rebuild identifiers from the row columns instead
+        let rows = {
+            let mut stmt = conn.prepare("SELECT id, app_name, window_title, url FROM usage_logs")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })?;
+            rows.collect::<SqlResult<Vec<_>>>()?
+        };
+
+        let tx = conn.unchecked_transaction()?;
+        for (id, app_name, window_title, url) in &rows {
+            let url = url.as_deref().filter(|url| !url.is_empty());
+            let key = ActivityKey::new(app_name, window_title, url);
+            tx.execute(
+                "UPDATE usage_logs SET identifier = ?1, url = ?2 WHERE id = ?3",
+                params![key.to_string(), url, id],
+            )?;
+        }
+        tx.commit()
+    }
+
     fn load_existing_data(&self) -> SqlResult<()> {
         let conn = Connection::open(&self.db_path)?;
         let mut stmt = conn.prepare("SELECT identifier, timestamp FROM usage_logs ORDER BY timestamp DESC")?;
@@ -112,30 +576,36 @@ impl SystemMonitor {
         let mut usage_data = self.usage_data.lock().unwrap();
         for row in rows {
             let (identifier, timestamp) = row?;
-            usage_data.insert(identifier, ActiveEntry {
+            let timestamp = Timestamp::from_secs(timestamp.max(0) as u64);
+            usage_data.insert(ActivityKey::parse(&identifier), ActiveEntry {
                 status: false,
-                last_seen: timestamp as u64,
-                start_time: timestamp as u64,
+                last_seen: timestamp,
+                start_time: timestamp,
+                since: None,
+                left: None,
+                away: false,
+                full_title: None,
+                partial: false,
             });
         }
+        drop(usage_data);
+        *self.warm_start.lock().unwrap() = warm_start::take(&conn)?;
         Ok(())
     }
 
-    fn get_foreground_window_info(&self) -> Option<(String, String, Option<String>)> {
+    #[cfg(windows)]
+    fn get_foreground_window_info(&self) -> Option<WindowInfo> {
         unsafe {
             let hwnd = GetForegroundWindow();
             if hwnd.0 == 0 {
                 return None;
             }
 
-            // Get window title
-            let mut title_buffer = [0u16; 256];
+            // Get window title, sized to the actual length so long titles are not cut
+            let wanted_len = (GetWindowTextLengthW(hwnd).max(0) as usize).clamp(256, MAX_TITLE_BUFFER);
+            let mut title_buffer = vec![0u16; wanted_len + 1];
             let title_len = GetWindowTextW(hwnd, &mut title_buffer);
-            let window_title = if title_len > 0 {
-                String::from_utf16_lossy(&title_buffer[..title_len as usize])
-            } else {
-                "Unknown".to_string()
-            };
+            let raw_title = decode_utf16_title(&title_buffer[..title_len.max(0) as usize]);
 
             // Get process ID
             let mut process_id = 0u32;
@@ -144,51 +614,264 @@ impl SystemMonitor {
                 return None;
             }
 
-            // Get process handle
-            let process_handle = OpenProcess(PROCESS_QUERY_INFORMATION, BOOL(0), process_id).ok()?;
-            
-            // Get process image name
-            let mut image_buffer = [0u16; 260];
-            let image_len = GetProcessImageFileNameW(process_handle, &mut image_buffer);
-            let process_path = if image_len > 0 {
-                String::from_utf16_lossy(&image_buffer[..image_len as usize])
-            } else {
-                return None;
-            };
-
-            // Extract executable name
-            let app_name = Path::new(&process_path)
-                .file_name()
-                .and_then(|name| name.to_str())
-                .unwrap_or("Unknown")
-                .to_string();
+            // Resolve the executable, falling back to limited access for elevated processes
+            let mut process_info = process::resolve_process(process_id);
+            let mut app_process_id = process_id;
+            if process_info.app_name.eq_ignore_ascii_case(process::FRAME_HOST_APP) {
+                if let Some(hosted_id) = process::find_hosted_process(hwnd, process_id) {
+                    process_info = process::resolve_process(hosted_id);
+                    app_process_id = hosted_id;
+                }
+            }
 
-            // Detect browser and extract URL
-            let url = self.extract_browser_url(&app_name, &window_title);
+            // Stored in `apps`, and tells generic hosts' apps apart
+            let mut class_buffer = [0u16; 256];
+            let class_len = GetClassNameW(hwnd, &mut class_buffer);
+            let window_class = (class_len > 0).then(|| String::from_utf16_lossy(&class_buffer[..class_len as usize]));
 
-            Some((app_name, window_title, url))
+            Some(self.build_window_info(&raw_title, hwnd.0 as u64, app_process_id, process_info, window_class))
         }
     }
 
-    fn extract_browser_url(&self, app_name: &str, window_title: &str) -> Option<String> {
-        let app_lower = app_name.to_lowercase();
-        
-        if app_lower.contains("chrome") || app_lower.contains("msedge") || app_lower.contains("brave") {
-            self.extract_chromium_url(app_name, window_title)
-        } else if app_lower.contains("firefox") {
-            self.extract_firefox_url(window_title)
+    #[cfg(not(windows))]
+    fn get_foreground_window_info(&self) -> Option<WindowInfo> {
+        let (raw_title, window, process_id) = process::foreground_window()?;
+        let process_info = process::resolve_process(process_id);
+        Some(self.build_window_info(&raw_title, window, process_id, process_info, None))
+    }
+
+    fn build_window_info(
+        &self,
+        raw_title: &str,
+        window: u64,
+        process_id: u32,
+        process_info: ProcessInfo,
+        window_class: Option<String>,
+    ) -> WindowInfo {
+        let window_title = title::normalize_title(raw_title);
+        let window_title = if window_title.is_empty() {
+            "Unknown".to_string()
+        } else {
+            window_title
+        };
+
+        self.diagnose(format!(
+            "process {} -> {} ({:?} access, image {}{})",
+            process_id,
+            process_info.app_name,
+            process_info.access,
+            process_info.image_path.as_deref().unwrap_or("unknown"),
+            process_info.raw_image_path.as_deref().map(|raw| format!(", reported as {}", raw)).unwrap_or_default()
+        ));
+
+        match process_info.access {
+            ProcessAccess::Full => {}
+            ProcessAccess::Limited => RuntimeMetrics::increment(&self.metrics.limited_process_access),
+            ProcessAccess::Denied => RuntimeMetrics::increment(&self.metrics.denied_process_access),
+        }
+
+        // Packaged apps are reported under their manifest display name, and generic
+        // Java/Electron hosts under the app they are running
+        let app_name = if let Some(display_name) = self.packaged_display_name(&process_info) {
+            display_name
+        } else if disambiguate::is_disambiguated_host(&process_info.app_name) && self.safe_mode.is_none() {
+            let command_line = process::query_command_line(process_id);
+            disambiguate::logical_app_name(
+                &process_info.app_name,
+                process_info.image_path.as_deref(),
+                command_line.as_deref(),
+                window_class.as_deref(),
+            )
+            .unwrap_or(process_info.app_name.clone())
+        } else {
+            process_info.app_name.clone()
+        };
+        self.diagnose(format!("app name: {}", app_name));
+        self.record_app_details(&app_name, &process_info.app_name, process_info.image_path.as_deref(), window_class.as_deref());
+
+        let profile = profiles::profile_for(&self.config.app_profiles, &[&app_name, &process_info.app_name]);
+        if profile != AppProfile::default() {
+            self.diagnose(format!("profile: {:?}", profile));
+        }
+
+        // Detect browser and extract URL, reading it again only once the tab changed
+        let url = if cfg!(feature = "browser-integration") && profile.capture_urls && self.safe_mode.is_none() {
+            let (url, cached) = self.url_cache.lock().unwrap().get_or_read(&self.config.url_cache, window, &window_title, Instant::now(), || {
+                RuntimeMetrics::increment(&self.metrics.url_reads);
+                self.extract_browser_url(&process_info.app_name, &window_title, profile.extractor)
+            });
+            if cached {
+                self.diagnose(format!("url cached: {}", url.as_deref().unwrap_or("no url")));
+            }
+            url
         } else {
             None
+        };
+        let window_title = match self.summarizers.summarize(&[&app_name, &process_info.app_name], &window_title) {
+            Some(summary) => {
+                self.diagnose(format!("title summarized as: {}", summary));
+                summary
+            }
+            None => window_title,
+        };
+        let window_title = if profile.capture_titles {
+            window_title
+        } else {
+            HIDDEN_TITLE.to_string()
+        };
+
+        WindowInfo {
+            app_name,
+            window_title,
+            url,
+            access: process_info.access,
+            profile,
+        }
+    }
+
+    #[cfg(windows)]
+    fn packaged_display_name(&self, process_info: &ProcessInfo) -> Option<String> {
+        let package_full_name = process_info.package_full_name.as_deref()?;
+        self.package_identity(package_full_name, &process_info.app_name)
+            .map(|identity| identity.display_name)
+    }
+
+    #[cfg(not(windows))]
+    fn packaged_display_name(&self, _process_info: &ProcessInfo) -> Option<String> {
+        None
+    }
+
+    #[cfg(windows)]
+    fn package_identity(&self, package_full_name: &str, executable: &str) -> Option<PackageIdentity> {
+        let mut cache = self.package_cache.lock().unwrap();
+        if let Some(identity) = cache.get(package_full_name) {
+            return identity.clone();
+        }
+
+        let identity = packaged::resolve_package(package_full_name);
+        // `--diagnose` must not write to the database
+        if let Some(identity) = identity.as_ref().filter(|_| !self.diagnose_mode) {
+            if let Err(e) = self.record_packaged_app(identity, executable) {
+                if self.debug_mode {
+                    logs::error!("Error recording packaged app: {}", e);
+                }
+            }
+        }
+        cache.insert(package_full_name.to_string(), identity.clone());
+        identity
+    }
+
+    #[cfg(windows)]
+    fn record_packaged_app(&self, identity: &PackageIdentity, executable: &str) -> SqlResult<()> {
+        let conn = Connection::open(&self.db_path)?;
+        let current_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        conn.execute(
+            "INSERT INTO apps (app_name, executable, package_full_name, logo_path, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+             ON CONFLICT(app_name) DO UPDATE SET
+                executable = excluded.executable,
+                package_full_name = excluded.package_full_name,
+                logo_path = excluded.logo_path,
+                last_seen = excluded.last_seen",
+            params![
+                identity.display_name,
+                executable,
+                identity.package_full_name,
+                identity.logo_path,
+                current_time
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Stores where an app runs from and its description when it shows up with
+    // a new path, and its window class when that changes
+    fn record_app_details(&self, app_name: &str, executable: &str, image_path: Option<&str>, window_class: Option<&str>) {
+        let mut known = self.app_details.lock().unwrap();
+        let (known_path, known_class) = known.get(app_name).cloned().unwrap_or_default();
+        let new_path = image_path.filter(|path| known_path.as_deref() != Some(*path));
+        let new_class = window_class.filter(|class| known_class.as_deref() != Some(*class));
+        if new_path.is_none() && new_class.is_none() {
+            return;
+        }
+        let origin = new_path.map(origin::classify);
+        let description = new_path.and_then(process::file_description).and_then(|description| apps::clean_description(&description, executable));
+        if let Some(origin) = origin {
+            self.diagnose(format!("origin: {}", origin.as_str()));
+        }
+        if let Some(description) = &description {
+            self.diagnose(format!("description: {}", description));
+        }
+        if let Some(class) = new_class {
+            self.diagnose(format!("window class: {}", class));
+        }
+        // `--diagnose` must not write to the database
+        if self.diagnose_mode {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let stored = Connection::open(&self.db_path).and_then(|conn| {
+            conn.execute(
+                "INSERT INTO apps (app_name, executable, image_path, origin, description, window_class, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)
+                 ON CONFLICT(app_name) DO UPDATE SET
+                    image_path = COALESCE(excluded.image_path, image_path),
+                    origin = COALESCE(excluded.origin, origin),
+                    description = CASE WHEN excluded.image_path IS NULL THEN description ELSE excluded.description END,
+                    window_class = COALESCE(excluded.window_class, window_class),
+                    last_seen = excluded.last_seen",
+                params![app_name, executable, new_path, origin.map(|origin| origin.as_str()), description, new_class, now],
+            )
+        });
+        match stored {
+            Ok(_) => {
+                let path = image_path.map(str::to_string).or(known_path);
+                let class = window_class.map(str::to_string).or(known_class);
+                known.insert(app_name.to_string(), (path, class));
+            }
+            Err(e) if self.debug_mode => logs::error!("Error recording app details: {}", e),
+            Err(_) => {}
+        }
+    }
+
+    fn extract_browser_url(&self, app_name: &str, window_title: &str, extractor: Extractor) -> Option<String> {
+        let app_lower = app_name.to_lowercase();
+        let extractor = match extractor {
+            Extractor::Auto if app_lower.contains("chrome") || app_lower.contains("msedge") || app_lower.contains("brave") => {
+                Extractor::Chromium
+            }
+            Extractor::Auto if app_lower.contains("firefox") => Extractor::Firefox,
+            Extractor::Auto => Extractor::None,
+            chosen => chosen,
+        };
+
+        let (extractor, url) = match extractor {
+            Extractor::Chromium => ("chromium", self.extract_chromium_url(app_name, window_title)),
+            Extractor::Firefox => ("firefox", self.extract_firefox_url(window_title)),
+            _ => ("none", None),
+        };
+        self.diagnose(format!(
+            "url extractor: {} -> {}",
+            extractor,
+            url.as_deref().unwrap_or("no url")
+        ));
+        url
+    }
+
+    fn diagnose(&self, message: String) {
+        if self.diagnose_mode {
+            self.diagnose_trace.lock().unwrap().push(message);
         }
     }
 
     fn extract_chromium_url(&self, _app_name: &str, window_title: &str) -> Option<String> {
         // Try to extract URL from window title (common pattern: "Page Title - Browser Name")
-        let title_parts: Vec<&str> = window_title.split(" - ").collect();
-        if title_parts.len() >= 2 {
-            let potential_url = title_parts.last().unwrap();
-            if potential_url.starts_with("http") || potential_url.contains("://") {
-                return Some(potential_url.to_string());
+        if let Some((_, potential_url)) = window_title.rsplit_once(" - ") {
+            if let Some(url) = parse_url_candidate(potential_url) {
+                return Some(url);
             }
         }
 
@@ -213,10 +896,9 @@ impl SystemMonitor {
         let patterns = [" - Mozilla Firefox", " | Mozilla Firefox", " — Mozilla Firefox"];
         
         for pattern in &patterns {
-            if let Some(pos) = window_title.find(pattern) {
-                let title_part = &window_title[..pos];
-                if title_part.starts_with("http") || title_part.contains("://") {
-                    return Some(title_part.to_string());
+            if let Some(pos) = window_title.rfind(pattern) {
+                if let Some(url) = parse_url_candidate(&window_title[..pos]) {
+                    return Some(url);
                 }
             }
         }
@@ -224,84 +906,407 @@ impl SystemMonitor {
         None
     }
 
-    fn update_usage(&self, identifier: String, _app_name: String, _window_title: String, _url: Option<String>) {
-        let current_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    fn update_usage(&self, key: ActivityKey, full_title: Option<String>, partial: bool) {
+        let current_time = Timestamp::now();
+
+        {
+            let mut last_app = self.last_app.lock().unwrap();
+            if let Some(previous) = last_app.as_ref().filter(|previous| **previous != key.app_name) {
+                // `--diagnose` never flushes, so there is nothing to queue for
+                if !self.diagnose_mode {
+                    self.pending_transitions.lock().unwrap().push(PendingTransition {
+                        from: previous.clone(),
+                        to: key.app_name.clone(),
+                        timestamp: current_time,
+                    });
+                }
+            }
+            *last_app = Some(key.app_name.clone());
+        }
 
         let mut usage_data = self.usage_data.lock().unwrap();
+        let now = Instant::now();
+        let (start_time, since) = self.session_start(&key, current_time, now);
         
         // Update existing entry or create new one
-        if let Some(entry) = usage_data.get_mut(&identifier) {
+        if let Some(entry) = usage_data.get_mut(&key) {
             if !entry.status {
+                self.record_event(events::Event::focus(current_time, key.clone(), partial));
+            }
+            if !entry.status && !self.resumes(entry, now) {
                 // App just became active, set start time
-                entry.start_time = current_time;
+                entry.start_time = start_time;
+                entry.since = Some(since);
+                RuntimeMetrics::increment(&self.metrics.focus_changes);
             }
             entry.status = true;
             entry.last_seen = current_time;
+            entry.full_title = full_title;
+            entry.partial = partial;
         } else {
             // New app, set both start time and last seen to current time
-            usage_data.insert(identifier.clone(), ActiveEntry {
+            RuntimeMetrics::increment(&self.metrics.focus_changes);
+            self.record_event(events::Event::focus(current_time, key.clone(), partial));
+            usage_data.insert(key.clone(), ActiveEntry {
                 status: true,
                 last_seen: current_time,
-                start_time: current_time,
+                start_time,
+                since: Some(since),
+                left: None,
+                away: false,
+                full_title,
+                partial,
             });
         }
 
         // Mark all other entries as inactive
-        for (key, entry) in usage_data.iter_mut() {
-            if *key != identifier {
+        for (other, entry) in usage_data.iter_mut() {
+            if *other != key && entry.status {
                 entry.status = false;
+                entry.left = Some(now);
             }
+            entry.away = false; // The grace only bridges to the next focus
         }
     }
 
-    fn flush_to_database(&self) -> SqlResult<()> {
-        let mut conn = Connection::open(&self.db_path)?;
+    // Where a session focused at `current_time` starts: there, unless it is
+    // the first focus since a restart, on the window the last run left open
+    fn session_start(&self, key: &ActivityKey, current_time: Timestamp, now: Instant) -> (Timestamp, Instant) {
+        let open = self.warm_start.lock().unwrap().take();
+        if let Some(open) = open.filter(|open| open.resumes(&self.config.warm_start, key, current_time)) {
+            if let Some(since) = now.checked_sub(current_time.since(open.start).to_std()) {
+                if self.debug_mode {
+                    logs::info!("Resuming the session of {} from before the restart", key);
+                }
+                return (open.start, since);
+            }
+        }
+        (current_time, now)
+    }
+
+    // Back within the debounce time, or the AFK grace, the session goes on
+    fn resumes(&self, entry: &ActiveEntry, now: Instant) -> bool {
+        self.config.debounce.resumes(entry.left, now)
+            || (entry.away && entry.left.is_some_and(|left| self.config.idle.merges(now.saturating_duration_since(left).into())))
+    }
+
+    // Ends the open session at `at` for `reason`
+    fn mark_all_inactive(&self, reason: events::EventKind, at: Instant) {
+        // A switch through an untracked app is not a transition between its neighbours
+        *self.last_app.lock().unwrap() = None;
+        let away = matches!(reason, events::EventKind::Idle | events::EventKind::Lock);
+        let mut ended = false;
+        for entry in self.usage_data.lock().unwrap().values_mut().filter(|entry| entry.status) {
+            entry.status = false;
+            entry.left = Some(at);
+            entry.away = away;
+            ended = true;
+        }
+        if ended {
+            self.record_event(events::Event::new(wall_time_at(at), reason));
+        }
+    }
+
+    // `--diagnose` never flushes, so there is nothing to queue for
+    fn record_event(&self, event: events::Event) {
+        if self.diagnose_mode {
+            return;
+        }
+        if event.kind == events::EventKind::Focus {
+            *self.last_heartbeat.lock().unwrap() = event.timestamp;
+        }
+        self.pending_events.lock().unwrap().push(event);
+    }
+
+    // The open sessions as they stand at `now`. Each ends its monotonic length
+    // after its wall-clock start, so clock changes neither stretch nor shrink it
+    fn pending_rows(&self, now: Instant) -> Vec<PendingRow> {
         let usage_data = self.usage_data.lock().unwrap();
+        let mut rows = Vec::new();
+        for (key, entry) in usage_data.iter() {
+            if entry.status {
+                let duration = active_secs(entry, now);
+                if self.config.debounce.keeps(duration) {
+                    rows.push(PendingRow {
+                        key: key.clone(),
+                        timestamp: entry.start_time + duration,
+                        duration,
+                        partial: entry.partial,
+                        full_title: entry.full_title.clone(),
+                        ended: false,
+                    });
+                }
+            }
+        }
+        rows
+    }
+
+    // Open sessions that went past local midnight, ended there; each goes on
+    // as a new session from midnight, so every day gets its own part
+    fn split_at_midnight(&self, now: Instant) -> Vec<PendingRow> {
+        let mut usage_data = self.usage_data.lock().unwrap();
+        let mut rows = Vec::new();
+        for (key, entry) in usage_data.iter_mut().filter(|(_, entry)| entry.status) {
+            while let Some(since) = entry.since {
+                let midnight = reports::day_bounds(reports::local_date(entry.start_time.as_secs())).1.max(0) as u64;
+                let midnight = Timestamp::from_secs(midnight);
+                let before = midnight.since(entry.start_time);
+                if before == DurationSecs::ZERO || active_secs(entry, now) < before {
+                    break;
+                }
+                rows.push(PendingRow {
+                    key: key.clone(),
+                    timestamp: midnight,
+                    duration: before,
+                    partial: entry.partial,
+                    full_title: entry.full_title.clone(),
+                    ended: false,
+                });
+                entry.start_time = midnight;
+                entry.since = Some(since + before.to_std());
+            }
+        }
+        rows
+    }
+
+    // Events and transitions waiting for the next flush
+    fn pending_count(&self) -> usize {
+        self.pending_events.lock().unwrap().len() + self.pending_transitions.lock().unwrap().len()
+    }
+
+    fn flush_to_database(&self) -> SqlResult<writer::Flushed> {
+        let now = Instant::now();
+        let mut rows = self.split_at_midnight(now);
+        rows.extend(self.pending_rows(now));
+        let current_time = Timestamp::now();
+        let open = self.usage_data.lock().unwrap().values().any(|entry| entry.status);
+        if open && current_time >= *self.last_heartbeat.lock().unwrap() + events::HEARTBEAT {
+            *self.last_heartbeat.lock().unwrap() = current_time;
+            self.record_event(events::Event::new(current_time, events::EventKind::Heartbeat));
+        }
+        self.flush_rows(rows)
+    }
+
+    fn flush_rows(&self, rows: Vec<PendingRow>) -> SqlResult<writer::Flushed> {
+        let _writing = self.writer.start();
+        let timer = self.telemetry.start("sysmonitor.db.flush");
+        let result = self.write_pending(rows);
+        if result.is_ok() {
+            self.writer.mark_flushed(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+        }
+        let rows = result.as_ref().map_or(0, |flushed| flushed.rows);
+        self.telemetry.finish(timer, vec![("db.rows", rows.into())], result.as_ref().err().map(|e| e.to_string()));
+        result
+    }
+
+    // The flush itself
+    fn write_pending(&self, rows: Vec<PendingRow>) -> SqlResult<writer::Flushed> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let transitions = self.pending_transitions.lock().unwrap().clone();
+        let logged = self.pending_events.lock().unwrap().clone();
         
         let tx = conn.transaction()?;
+        let summary = daily_summary::begin_flush(&tx)?;
+
+        for transition in &transitions {
+            transitions::record(&tx, &transition.from, &transition.to, transition.timestamp)?;
+        }
+        for event in &logged {
+            events::append(&tx, event)?;
+        }
         
-        for (identifier, entry) in usage_data.iter() {
+        for row in &rows {
+            if row.ended {
+                tx.execute(
+                    "DELETE FROM usage_logs WHERE identifier = ?1 AND timestamp - duration = ?2 AND duration > ?3 AND source IS NULL",
+                    params![row.key.to_string(), row.timestamp - row.duration, row.duration],
+                )?;
+            }
+            tx.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, url, timestamp, duration, partial, language) 
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    row.key.to_string(),
+                    row.key.app_name,
+                    row.key.window_title(),
+                    row.key.url(),
+                    row.timestamp,
+                    row.duration,
+                    row.partial,
+                    language::detect(row.full_title.as_deref().unwrap_or(row.key.window_title()))
+                ],
+            )?;
+            signatures::sign_row(&tx, tx.last_insert_rowid())?;
+            if let Some(ref summary) = summary {
+                let start = row.timestamp - row.duration;
+                summary.record(&tx, &self.config.categories, &row.key, start, row.duration)?;
+            }
+
+            if let Some(ref full_title) = row.full_title {
+                tx.execute(
+                    "INSERT INTO title_history (identifier, full_title, first_seen, last_seen)
+                     VALUES (?1, ?2, ?3, ?3)
+                     ON CONFLICT(identifier, full_title) DO UPDATE SET last_seen = excluded.last_seen",
+                    params![row.key.to_string(), full_title, row.timestamp],
+                )?;
+            }
+        }
+        if let Some(summary) = summary {
+            summary.finish(&tx)?;
+        }
+
+        tx.commit()?;
+        // Transitions queued while this flush ran stay for the next one
+        self.pending_transitions.lock().unwrap().drain(..transitions.len());
+        self.pending_events.lock().unwrap().drain(..logged.len());
+        Ok(writer::Flushed { rows: rows.len(), events: transitions.len() + logged.len() })
+    }
+
+    // Ends the open sessions where the old clock left off and starts them again
+    // on the new one, so no session spans the jump. On Linux the monotonic clock
+    // stops during suspend, so waking up looks like a jump ahead and the sleep
+    // is left out the same way
+    fn handle_clock_jump(&self, jump: &clock::Jump) {
+        if self.debug_mode {
+            logs::info!("Wall clock moved {:+}s against the monotonic clock; splitting the open session", jump.offset());
+        }
+        if !self.diagnose_mode {
+            RuntimeMetrics::increment(&self.metrics.flushes);
+            let recorded = self.flush_to_database().and_then(|_| Connection::open(&self.db_path)).and_then(|conn| clock::record(&conn, jump));
+            if let Err(e) = recorded {
+                RuntimeMetrics::increment(&self.metrics.flush_errors);
+                if self.debug_mode {
+                    logs::error!("Error recording the clock jump: {}", e);
+                }
+            }
+        }
+        let now = Instant::now();
+        let actual = Timestamp::from_secs(jump.actual);
+        let mut reopened = Vec::new();
+        for (key, entry) in self.usage_data.lock().unwrap().iter_mut() {
+            entry.left = None; // Resuming would place the session on the old clock
             if entry.status {
-                // Calculate total duration since app became active
-                let current_time = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                let duration = current_time.saturating_sub(entry.start_time);
-                
-                if duration > 0 {
-                    // Extract app name and window title from identifier
-                    let (app_name, window_title, url) = if let Some((app, rest)) = identifier.split_once(':') {
-                        if rest.starts_with("http") {
-                            (app.to_string(), rest.to_string(), Some(rest.to_string()))
-                        } else {
-                            (app.to_string(), rest.to_string(), None)
-                        }
-                    } else {
-                        (identifier.clone(), "Unknown".to_string(), None)
-                    };
-
-                    tx.execute(
-                        "INSERT INTO usage_logs (identifier, app_name, window_title, url, timestamp, duration) 
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                        params![
-                            identifier,
-                            app_name,
-                            window_title,
-                            url.unwrap_or_default(),
-                            current_time,
-                            duration
-                        ],
-                    )?;
+                entry.start_time = actual;
+                entry.since = Some(now);
+                entry.last_seen = actual;
+                reopened.push(events::Event::focus(actual, key.clone(), entry.partial));
+            }
+        }
+        self.record_event(events::Event::new(Timestamp::from_secs(jump.expected), events::EventKind::ClockJump));
+        for event in reopened {
+            self.record_event(event);
+        }
+    }
+
+    // The open sessions as they stood at `at`, when the machine went to sleep
+    // or the last input came before going idle; they are ended there, and
+    // tracking starts new ones afterwards
+    fn end_sessions_at(&self, at: Instant, reason: events::EventKind) -> Vec<PendingRow> {
+        let mut rows = self.split_at_midnight(at);
+        rows.extend(self.pending_rows(at));
+        for row in &mut rows {
+            row.ended = true;
+        }
+        self.mark_all_inactive(reason, at);
+        if reason == events::EventKind::Suspend {
+            // Nothing picks up across the sleep
+            for entry in self.usage_data.lock().unwrap().values_mut() {
+                entry.left = None;
+            }
+        }
+        rows
+    }
+
+    fn end_and_flush(&self, at: Instant, reason: events::EventKind) {
+        let rows = self.end_sessions_at(at, reason);
+        if self.debug_mode {
+            logs::info!("{}: ended {} open sessions", reason.as_str(), rows.len());
+        }
+        if !self.diagnose_mode {
+            RuntimeMetrics::increment(&self.metrics.flushes);
+            if let Err(e) = self.flush_rows(rows) {
+                RuntimeMetrics::increment(&self.metrics.flush_errors);
+                if self.debug_mode {
+                    logs::error!("Error flushing the ended sessions: {}", e);
                 }
             }
         }
-        
-        tx.commit()?;
-        Ok(())
+    }
+
+    // Samples the process's memory, logs steady growth and compacts over the
+    // ceiling. A restart is left to the tracker loop; the server, which has
+    // none, goes on compacting
+    fn check_memory(&self) -> std::io::Result<memory::Action> {
+        let Some(rss) = memory::rss_bytes()? else {
+            return Ok(memory::Action::None);
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let check = self.memory.lock().unwrap().observe(&self.config.memory, now, rss);
+        if let Some(growth) = check.growth_mb_per_hour {
+            logs::info!("Memory use grew by {} MiB an hour over the last hour, now {} MiB", growth, rss >> 20);
+        }
+        if check.action != memory::Action::None {
+            let dropped = self.compact_memory();
+            logs::info!(
+                "Memory use of {} MiB is over the {} MiB ceiling; dropped {} idle windows and the lookup caches",
+                rss >> 20,
+                self.config.memory.ceiling_mb,
+                dropped
+            );
+        }
+        Ok(check.action)
+    }
+
+    fn capabilities(&self) -> &[capabilities::Capability] {
+        self.capabilities.get_or_init(|| {
+            let foreground_app = self.get_foreground_window_info().map(|info| info.app_name);
+            capabilities::detect(&capabilities::Probes::run(foreground_app))
+        })
+    }
+
+    // Logs what can't work here, once at startup
+    fn report_capabilities(&self) {
+        for capability in self.capabilities().iter().filter(|capability| !capability.available) {
+            logs::info!("{} unavailable: {}; {}", capability.id, capability.detail, capability.without.unwrap_or_default());
+        }
+    }
+
+    // Drops what the tracker rebuilds on demand: windows that are neither open
+    // nor able to resume their session, and the package and path caches. How
+    // many windows went
+    fn compact_memory(&self) -> usize {
+        let now = Instant::now();
+        let mut usage_data = self.usage_data.lock().unwrap();
+        let before = usage_data.len();
+        usage_data.retain(|_, entry| entry.status || self.resumes(entry, now));
+        usage_data.shrink_to_fit();
+        #[cfg(windows)]
+        {
+            let mut cache = self.package_cache.lock().unwrap();
+            cache.clear();
+            cache.shrink_to_fit();
+        }
+        let mut details = self.app_details.lock().unwrap();
+        details.clear();
+        details.shrink_to_fit();
+        self.url_cache.lock().unwrap().clear();
+        self.pending_transitions.lock().unwrap().shrink_to_fit();
+        self.pending_events.lock().unwrap().shrink_to_fit();
+        self.diagnose_trace.lock().unwrap().shrink_to_fit();
+        before - usage_data.len()
+    }
+
+    // Ends and stores the open sessions and forgets every window, for the
+    // tracker loop to start over from nothing
+    fn restart_tracker(&self) {
+        logs::info!("Memory use stayed over the {} MiB ceiling; restarting the tracker", self.config.memory.ceiling_mb);
+        self.end_and_flush(Instant::now(), events::EventKind::Restart);
+        let mut usage_data = self.usage_data.lock().unwrap();
+        usage_data.clear();
+        usage_data.shrink_to_fit();
+        drop(usage_data);
+        *self.last_app.lock().unwrap() = None;
+        self.compact_memory();
     }
 
     fn get_recent_activity(&self) -> Vec<RecentActivity> {
@@ -312,7 +1317,7 @@ impl SystemMonitor {
         };
 
         let mut stmt = match conn.prepare(
-            &format!("SELECT identifier, app_name, window_title, url, duration, timestamp 
+            &format!("SELECT identifier, app_name, window_title, url, duration, timestamp, partial 
              FROM usage_logs 
              WHERE timestamp >= ?1 
              ORDER BY timestamp DESC 
@@ -322,21 +1327,17 @@ impl SystemMonitor {
             Err(_) => return Vec::new(),
         };
 
-        // Get retention period ago timestamp (persistent for configured hours)
-        let current_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let retention_cutoff = current_time - (ACTIVITY_RETENTION_HOURS * 3600); // Convert hours to seconds
+        let retention_cutoff = Timestamp::now().saturating_sub(ACTIVITY_RETENTION);
 
-        let rows = match stmt.query_map([retention_cutoff as i64], |row| {
+        let rows = match stmt.query_map([retention_cutoff], |row| {
             Ok(RecentActivity {
-                identifier: row.get::<_, String>(0)?,
+                key: ActivityKey::parse(&row.get::<_, String>(0)?),
                 app_name: row.get::<_, String>(1)?,
                 window_title: row.get::<_, String>(2)?,
                 url: row.get::<_, Option<String>>(3)?,
-                duration: row.get::<_, i64>(4)? as u64,
-                timestamp: row.get::<_, i64>(5)? as u64,
+                duration: row.get(4)?,
+                timestamp: row.get(5)?,
+                partial: row.get::<_, bool>(6)?,
             })
         }) {
             Ok(rows) => rows,
@@ -344,46 +1345,37 @@ impl SystemMonitor {
         };
 
         let mut activities = Vec::new();
-        for row in rows {
-            if let Ok(activity) = row {
-                activities.push(activity);
-            }
+        for activity in rows.flatten() {
+            activities.push(activity);
         }
         activities
     }
 
     fn get_dashboard_data(&self) -> DashboardData {
         let usage_data = self.usage_data.lock().unwrap();
-        let current_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let now = Instant::now();
 
         let mut current_app = None;
         let mut current_window = None;
         let mut current_url = None;
         let mut active_apps = Vec::new();
 
-        for (identifier, entry) in usage_data.iter() {
+        for (key, entry) in usage_data.iter() {
             if entry.status {
-                // Calculate total duration since app became active
-                let duration = current_time.saturating_sub(entry.start_time);
-                active_apps.push((identifier.clone(), duration));
+                let duration = active_secs(entry, now);
+                active_apps.push((key.clone(), duration));
                 
-                // Extract app info from identifier
-                if let Some((app, rest)) = identifier.split_once(':') {
-                    current_app = Some(app.to_string());
-                    if rest.starts_with("http") {
-                        current_url = Some(rest.to_string());
-                    } else {
-                        current_window = Some(rest.to_string());
-                    }
+                current_app = Some(key.app_name.clone());
+                match key.url() {
+                    Some(url) => current_url = Some(url.to_string()),
+                    None => current_window = Some(key.window_title().to_string()),
                 }
             }
         }
 
         // Sort by duration (most recent first)
-        active_apps.sort_by(|a, b| b.1.cmp(&a.1));
+        active_apps.sort_by_key(|(_, duration)| std::cmp::Reverse(*duration));
+        active_apps.truncate(MAX_ACTIVE_APPS);
 
         // Get recent activity from database
         let recent_activity = self.get_recent_activity();
@@ -395,10 +1387,28 @@ impl SystemMonitor {
             active_apps,
             recent_activity,
             total_apps: usage_data.len(),
-            uptime: current_time - self.start_time,
+            uptime: self.started.elapsed().into(),
+            safe_mode: self.safe_mode.clone(),
         }
     }
 
+    // Every activity seen since the start, for paging through
+    #[cfg(feature = "web")]
+    fn live_activities(&self) -> Vec<live::LiveActivity> {
+        let now = Instant::now();
+        self.usage_data
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, entry)| live::LiveActivity {
+                key: key.clone(),
+                active: entry.status,
+                active_secs: if entry.status { active_secs(entry, now) } else { entry.last_seen.since(entry.start_time) },
+                last_seen: entry.last_seen,
+            })
+            .collect()
+    }
+
     fn print_status(&self) {
         if !self.debug_mode {
             return;
@@ -412,7 +1422,7 @@ impl SystemMonitor {
 
         println!("\n=== System Monitor Status ===");
         println!("Timestamp: {}", current_time);
-        println!("Uptime: {} seconds", dashboard_data.uptime);
+        println!("Uptime: {}", dashboard_data.uptime);
         
         if let Some(ref app) = dashboard_data.current_app {
             println!("Current App: {}", app);
@@ -425,58 +1435,784 @@ impl SystemMonitor {
         }
         
         println!("Active Applications:");
-        for (identifier, duration) in &dashboard_data.active_apps {
-            println!("  ✓ {} (active for {}s)", identifier, duration);
+        for (key, duration) in &dashboard_data.active_apps {
+            println!("  ✓ {} (active for {})", key, duration);
         }
         
         println!("Total tracked applications: {}", dashboard_data.total_apps);
     }
 
+    fn refresh_blocks(&self) -> SqlResult<usize> {
+        let conn = Connection::open(&self.db_path)?;
+        blocks::refresh(&conn, Timestamp::now())
+    }
+
+    fn refresh_categories(&self) -> SqlResult<()> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let stats = assignments::refresh(&mut conn, &self.config.categories, assignments::REFRESH_BATCH)?;
+        if self.debug_mode && (stats.assigned > 0 || stats.reevaluated > 0) {
+            logs::info!("Categories: {} sessions assigned, {} re-evaluated", stats.assigned, stats.reevaluated);
+        }
+        let days = daily_summary::refresh(&mut conn, &self.config.categories, Timestamp::now())?;
+        if self.debug_mode && days > 0 {
+            logs::info!("Daily summary: {} days rebuilt", days);
+        }
+        Ok(())
+    }
+
+    fn current_pause(&self) -> Option<pauses::Pause> {
+        self.pause.lock().unwrap().clone()
+    }
+
+    // Ends the running sessions at the pause, so none spans it
+    fn pause_tracking(&self, reason: Option<String>, minutes: Option<u64>) -> SqlResult<pauses::Pause> {
+        let pause = pauses::start(&Connection::open(&self.db_path)?, reason, minutes, Timestamp::now())?;
+        *self.pause.lock().unwrap() = Some(pause.clone());
+        self.mark_all_inactive(events::EventKind::Pause, Instant::now());
+        self.flush_to_database()?;
+        if self.debug_mode {
+            logs::info!("Tracking paused{}", pause.reason.as_deref().map(|reason| format!(": {}", reason)).unwrap_or_default());
+        }
+        Ok(pause)
+    }
+
+    fn resume_tracking(&self) -> SqlResult<Option<pauses::Pause>> {
+        let pause = pauses::resume(&Connection::open(&self.db_path)?, Timestamp::now())?;
+        *self.pause.lock().unwrap() = None;
+        if self.debug_mode && pause.is_some() {
+            logs::info!("Tracking resumed");
+        }
+        Ok(pause)
+    }
+
+    // Resumes once a timed pause's countdown is over
+    fn resume_expired_pause(&self) -> SqlResult<Option<pauses::Pause>> {
+        let now = Timestamp::now();
+        if self.current_pause().and_then(|pause| pause.remaining(now)) != Some(DurationSecs::ZERO) {
+            return Ok(None);
+        }
+        let pause = pauses::expire(&Connection::open(&self.db_path)?, now)?;
+        *self.pause.lock().unwrap() = None;
+        if self.debug_mode && pause.is_some() {
+            logs::info!("Tracking resumed after the pause timer");
+        }
+        Ok(pause)
+    }
+
+    fn refresh_grafana(&self) -> SqlResult<()> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let hours = grafana::refresh(&mut conn, &self.config.categories, now)?;
+        if self.debug_mode && hours > 0 {
+            logs::info!("Materialized {} hours for Grafana", hours);
+        }
+        Ok(())
+    }
+
+    fn refresh_rollups(&self) -> SqlResult<()> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let days = rollups::refresh(&mut conn, chrono::Local::now().date_naive())?;
+        if self.debug_mode && days > 0 {
+            logs::info!("Rolled up {} days", days);
+        }
+        Ok(())
+    }
+
+    // Redact and delete what outlived its `retention` class, archived months too
+    fn enforce_retention(&self) -> Result<retention::Pruned, String> {
+        if self.config.retention.is_empty() {
+            return Ok(retention::Pruned::default());
+        }
+        if self.config.retention.sessions_days.is_some() {
+            // Reports keep the deleted days through their rollups
+            self.refresh_rollups().map_err(|e| e.to_string())?;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let mut conn = Connection::open(&self.db_path).map_err(|e| e.to_string())?;
+        let pruned = retention::enforce(&mut conn, &self.config.retention, now).map_err(|e| e.to_string())?;
+        #[cfg(feature = "compression")]
+        let pruned = pruned + archive::enforce_retention(&self.config.archive.dir_for(&self.db_path), &self.config.retention, now)?;
+        if self.debug_mode && pruned != retention::Pruned::default() {
+            logs::info!(
+                "Retention: {} URLs and {} titles redacted, {} sessions and {} aggregates deleted",
+                pruned.urls_redacted, pruned.titles_redacted, pruned.sessions_deleted, pruned.aggregates_deleted
+            );
+        }
+        Ok(pruned)
+    }
+
+    // Move sessions past `archive.after_months` out of the database
+    #[cfg(feature = "compression")]
+    fn archive_old_sessions(&self) -> Result<Vec<archive::ArchivedMonth>, String> {
+        let Some(months) = self.config.archive.after_months else {
+            return Ok(Vec::new());
+        };
+        // Reports keep the archived days through their rollups
+        self.refresh_rollups().map_err(|e| e.to_string())?;
+        let mut conn = Connection::open(&self.db_path).map_err(|e| e.to_string())?;
+        let archived = archive::archive(&mut conn, &self.config.archive.dir_for(&self.db_path), archive::cutoff(Timestamp::now(), months))?;
+        if self.debug_mode {
+            for month in &archived {
+                logs::info!("Archived {} rows of {} to {}", month.rows, month.month, month.file);
+            }
+        }
+        Ok(archived)
+    }
+
+    // How often `job` runs on its own, None when only on request
+    fn maintenance_every(&self, job: maintenance::Job) -> Option<DurationSecs> {
+        match job {
+            maintenance::Job::Prune => (!self.config.retention.is_empty()).then_some(RETENTION_CHECK),
+            #[cfg(feature = "compression")]
+            maintenance::Job::Archive => self.config.archive.after_months.map(|_| ARCHIVE_CHECK),
+            #[cfg(not(feature = "compression"))]
+            maintenance::Job::Archive => None,
+            maintenance::Job::Vacuum => self.config.maintenance.vacuum_days.map(DurationSecs::days),
+            maintenance::Job::Backup => self.config.maintenance.backup_days.map(DurationSecs::days),
+            maintenance::Job::Compact => None,
+        }
+    }
+
+    fn maintenance_status(&self) -> SqlResult<Vec<maintenance::JobStatus>> {
+        let now = Timestamp::now();
+        let runs = maintenance::last_runs(&Connection::open(&self.db_path)?)?;
+        Ok(maintenance::status(runs, |job| self.maintenance_every(job), now))
+    }
+
+    // Runs `job` now and keeps how it went; the error is the job's own
+    fn run_maintenance(&self, job: maintenance::Job) -> Result<maintenance::Run, String> {
+        let _running = self.maintenance.lock().unwrap();
+        let started_at = Timestamp::now();
+        let result = match job {
+            maintenance::Job::Prune => self.enforce_retention().map(|pruned| serde_json::json!(pruned)),
+            #[cfg(feature = "compression")]
+            maintenance::Job::Archive => self.archive_old_sessions().map(|archived| serde_json::json!({ "months": archived })),
+            #[cfg(not(feature = "compression"))]
+            maintenance::Job::Archive => Err("Archiving needs a build with the `compression` feature".to_string()),
+            maintenance::Job::Vacuum => Connection::open(&self.db_path)
+                .and_then(|conn| maintenance::vacuum(&conn))
+                .map(|vacuumed| serde_json::json!(vacuumed))
+                .map_err(|e| e.to_string()),
+            maintenance::Job::Backup => Connection::open(&self.db_path).map_err(|e| e.to_string()).and_then(|conn| {
+                let config = &self.config.maintenance;
+                maintenance::backup(&conn, &config.backup_dir_for(&self.db_path), config.backup_keep, started_at).map(|backup| serde_json::json!(backup))
+            }),
+            maintenance::Job::Compact => Ok(serde_json::json!({ "windows_dropped": self.compact_memory() })),
+        };
+        let finished_at = Timestamp::now();
+        let run = match result {
+            Ok(result) => maintenance::Run { job, started_at, finished_at, result: Some(result), error: None },
+            Err(e) => maintenance::Run { job, started_at, finished_at, result: None, error: Some(e) },
+        };
+        Connection::open(&self.db_path)
+            .and_then(|conn| maintenance::record(&conn, &run))
+            .map_err(|e| format!("Failed to record the {} run: {}", job.name(), e))?;
+        if self.debug_mode {
+            logs::info!("Ran {} in {}", job.name(), finished_at - started_at);
+        }
+        match run.error {
+            Some(ref e) => Err(format!("{} failed: {}", job.name(), e)),
+            None => Ok(run),
+        }
+    }
+
+    // Every housekeeping job whose time came
+    fn run_due_maintenance(&self) -> Result<(), String> {
+        let now = Timestamp::now();
+        let runs = maintenance::last_runs(&Connection::open(&self.db_path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        let failed: Vec<String> = maintenance::Job::ALL
+            .into_iter()
+            .filter(|job| maintenance::due(runs.get(job), self.maintenance_every(*job), now))
+            .filter_map(|job| self.run_maintenance(job).err())
+            .collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed.join("; "))
+        }
+    }
+
+    // One notification per collector that stopped reporting
+    fn check_devices(&self) -> SqlResult<()> {
+        let now = Timestamp::now();
+        let conn = Connection::open(&self.db_path)?;
+        for device in devices::newly_offline(&conn, &self.config.devices, now)? {
+            let silent_mins = now.since(device.last_seen).as_secs() / 60;
+            self.notifier.notify(Notification {
+                kind: EventKind::DeviceOffline,
+                title: format!("{} stopped reporting", device.id),
+                message: format!("No uploads or heartbeats from {} for {} minutes", device.id, silent_mins),
+                timestamp: now.as_secs(),
+            });
+        }
+        Ok(())
+    }
+
+    // The day's goal progress, once a day from `goals.digest_hour` on
+    fn send_goal_digest(&self) -> SqlResult<()> {
+        use chrono::Timelike;
+
+        let local = chrono::Local::now();
+        if self.config.goals.digest_hour.is_none_or(|hour| local.hour() < hour) {
+            return Ok(());
+        }
+        let conn = Connection::open(&self.db_path)?;
+        let today = local.date_naive();
+        let week = goals::week(&conn, &self.config.categories, today, today)?;
+        if week.goals.is_empty() || !goals::mark_digested(&conn, today)? {
+            return Ok(());
+        }
+        let notification = Notification {
+            kind: EventKind::Digest,
+            title: format!("Weekly goals, week of {}", week.week_start),
+            message: goals::digest_message(&week),
+            timestamp: local.timestamp().max(0) as u64,
+        };
+        if self.config.goals.digest_pdf {
+            let (file_name, data) = self.weekly_pdf(today)?;
+            self.notifier.notify_with(notification, Attachment { file_name, content_type: "application/pdf", data });
+        } else {
+            self.notifier.notify(notification);
+        }
+        Ok(())
+    }
+
+    // The PDF report of the week `date` is in, and its file name
+    fn weekly_pdf(&self, date: chrono::NaiveDate) -> SqlResult<(String, Vec<u8>)> {
+        let period = reports::Period::week_of(date);
+        let report = reports::period_report(&self.db_path, period)?;
+        let today = chrono::Local::now().date_naive();
+        let week = goals::week(&Connection::open(&self.db_path)?, &self.config.categories, date, today)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let pdf = pdf::weekly_report(&report, Some(&week), &vault::default_device(), now);
+        Ok((format!("sysmonitor-{}.pdf", period.label()), pdf))
+    }
+
+    // Yesterday's daily note, once per day, when `daily_notes.dir` is set
+    fn write_daily_note(&self) -> Result<(), String> {
+        let Some(dir) = self.config.daily_notes.dir.as_deref() else {
+            return Ok(());
+        };
+        let Some(yesterday) = chrono::Local::now().date_naive().pred_opt() else {
+            return Ok(());
+        };
+        if *self.last_daily_note.lock().unwrap() == Some(yesterday) {
+            return Ok(());
+        }
+        let conn = Connection::open(&self.db_path).map_err(|e| e.to_string())?;
+        let path = daily_note::write(&conn, &self.config.daily_notes, dir, yesterday, false)?;
+        *self.last_daily_note.lock().unwrap() = Some(yesterday);
+        if let Some(path) = path.filter(|_| self.debug_mode) {
+            logs::info!("Wrote daily note {}", path.display());
+        }
+        Ok(())
+    }
+
+    // Recent daily summaries into the Notion database, when one is configured
+    fn sync_notion(&self) -> Result<usize, String> {
+        if self.config.notion.token.is_none() {
+            return Ok(0);
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let conn = Connection::open(&self.db_path).map_err(|e| e.to_string())?;
+        let config = &self.config.notion;
+        let today = chrono::Local::now().date_naive();
+        let pushed = self
+            .outbound
+            .call("notion", || notion::sync(&conn, config, &self.config.categories, today, config.days, now))?
+            .unwrap_or(0);
+        if self.debug_mode && pushed > 0 {
+            logs::info!("Pushed {} days to Notion", pushed);
+        }
+        Ok(pushed)
+    }
+
+    // Finished intervals to InfluxDB, when an endpoint is configured
+    fn push_influx(&self) -> Result<usize, String> {
+        let config = &self.config.influx;
+        if config.url.is_none() {
+            return Ok(0);
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let conn = Connection::open(&self.db_path).map_err(|e| e.to_string())?;
+        let host = config.host.clone().unwrap_or_else(vault::default_device);
+        let metrics = cfg!(feature = "system-metrics").then(|| self.metrics_snapshot());
+        let buckets = self
+            .outbound
+            .call("influx", || influx::push(&conn, config, &self.config.categories, &host, metrics.as_ref(), now))?
+            .unwrap_or(0);
+        if self.debug_mode && buckets > 0 {
+            logs::info!("Pushed {} intervals to InfluxDB", buckets);
+        }
+        Ok(buckets)
+    }
+
+    fn export_telemetry(&self) -> Result<usize, String> {
+        if !self.telemetry.enabled() {
+            return Ok(0);
+        }
+        let runtime = cfg!(feature = "system-metrics").then(|| self.metrics_snapshot());
+        Ok(self.outbound.call("telemetry", || self.telemetry.export(runtime.as_ref()))?.unwrap_or(0))
+    }
+
+    // Today's tracked seconds, from the daily report at most once per `SCREEN_TIME_REFRESH`
+    fn screen_time_today(&self) -> SqlResult<u64> {
+        let today = chrono::Local::now().date_naive();
+        let mut cached = self.screen_time.lock().unwrap();
+        if let Some((day, seconds, at)) = *cached {
+            if day == today && at.elapsed() < SCREEN_TIME_REFRESH.to_std() {
+                return Ok(seconds);
+            }
+        }
+        let seconds = reports::daily_report(&self.db_path, today)?.total_duration.as_secs();
+        *cached = Some((today, seconds, Instant::now()));
+        Ok(seconds)
+    }
+
+    fn home_assistant_state(&self) -> SqlResult<home_assistant::SensorState> {
+        let current = self.usage_data.lock().unwrap().iter().find(|(_, entry)| entry.status).map(|(key, _)| key.clone());
+        let screen_time = self.screen_time_today()?;
+        Ok(home_assistant::sensor_state(
+            current.as_ref().map(|key| key.app_name.clone()),
+            current.as_ref().and_then(|key| self.config.categories.categorize(key)).map(str::to_string),
+            self.current_pause().is_some(),
+            idle::idle_secs(),
+            screen_time,
+            &self.config.home_assistant,
+        ))
+    }
+
+    // Sensor state over MQTT when it changed, announcing the sensors first
+    fn publish_home_assistant(&self) -> Result<(), String> {
+        let Some(mqtt) = self.config.home_assistant.mqtt.as_ref() else {
+            return Ok(());
+        };
+        let state = self.home_assistant_state().map_err(|e| e.to_string())?;
+        let mut published = self.home_assistant_published.lock().unwrap();
+        if published.as_ref() == Some(&state) {
+            return Ok(());
+        }
+        let sent = self.outbound.call("home_assistant", || {
+            if published.is_none() {
+                home_assistant::publish_discovery(mqtt)?;
+            }
+            home_assistant::publish_state(mqtt, &state)
+        })?;
+        if sent.is_some() {
+            *published = Some(state);
+        }
+        Ok(())
+    }
+
+    fn db_size(&self) -> u64 {
+        std::fs::metadata(&self.db_path).map(|meta| meta.len()).unwrap_or(0)
+    }
+
+    fn metrics_snapshot(&self) -> metrics::MetricsSnapshot {
+        self.metrics.snapshot(self.db_size_start, self.db_size())
+    }
+
+    // Final flush plus the runtime summary (with `system-metrics`), on Ctrl+C
+    fn shutdown(&self) {
+        RuntimeMetrics::increment(&self.metrics.flushes);
+        if let Err(e) = self.flush_to_database() {
+            RuntimeMetrics::increment(&self.metrics.flush_errors);
+            logs::error!("Error flushing to database: {}", e);
+        }
+        // The open session, for the next start to pick up
+        let now = Instant::now();
+        let open = self.usage_data.lock().unwrap().iter().find(|(_, entry)| entry.status).map(|(key, entry)| warm_start::OpenSession {
+            key: key.clone(),
+            start: entry.start_time,
+            stopped_at: entry.start_time + active_secs(entry, now),
+        });
+        if let Err(e) = Connection::open(&self.db_path).and_then(|conn| warm_start::save(&conn, open.as_ref())) {
+            logs::error!("Error keeping the open session: {}", e);
+        }
+        if cfg!(feature = "system-metrics") {
+            self.save_runtime_stats();
+        }
+        safe_mode::clear(&safe_mode::marker_path(&self.db_path));
+    }
+
+    fn save_runtime_stats(&self) {
+        let snapshot = self.metrics_snapshot();
+        let current_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let stored = Connection::open(&self.db_path).and_then(|conn| {
+            conn.execute(
+                "INSERT INTO runtime_stats (started_at, ended_at, stats) VALUES (?1, ?2, ?3)",
+                params![
+                    self.start_time,
+                    current_time,
+                    serde_json::to_string(&snapshot).unwrap_or_default()
+                ],
+            )
+        });
+        if let Err(e) = stored {
+            logs::error!("Error saving runtime stats: {}", e);
+        }
+
+        snapshot.print_summary(self.started.elapsed().as_secs());
+    }
+
+    // `sysmonitor stats --self`: summaries of the most recent runs
+    fn print_runtime_history(&self, limit: usize) -> SqlResult<()> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT started_at, ended_at, stats FROM runtime_stats ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?))
+        })?;
+
+        let mut printed = 0;
+        for row in rows {
+            let (started_at, ended_at, stats) = row?;
+            let Ok(snapshot) = serde_json::from_str::<metrics::MetricsSnapshot>(&stats) else {
+                continue;
+            };
+            let started = chrono::DateTime::from_timestamp(started_at, 0)
+                .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            println!("\nRun started {}", started);
+            snapshot.print_summary(ended_at.saturating_sub(started_at) as u64);
+            printed += 1;
+        }
+        if printed == 0 {
+            println!("No runtime stats recorded yet; they are saved when sysmonitor shuts down cleanly");
+        }
+        Ok(())
+    }
+
+    // Same loop shape as `run_monitoring`, but nothing touches the database
+    async fn run_diagnostics(&self) {
+        let started = SystemTime::now();
+        let mut last_flush = started;
+        let mut last_key: Option<ActivityKey> = None;
+
+        println!("Diagnosing tracker for {} seconds; nothing is written to {}", DIAGNOSE_DURATION.as_secs(), self.db_path);
+        while started.elapsed().unwrap_or_default() < DIAGNOSE_DURATION.to_std() {
+            let info = self.get_foreground_window_info();
+            // Decisions repeat on every poll, so they are only shown when focus changes
+            let trace = std::mem::take(&mut *self.diagnose_trace.lock().unwrap());
+            let poll_interval_ms = info
+                .as_ref()
+                .map_or(profiles::DEFAULT_POLL_INTERVAL_MS, |info| info.profile.poll_interval_ms());
+            match info {
+                Some(info) if !info.profile.track => {
+                    if last_key.take().is_some() {
+                        println!("[event] focus -> {} (not tracked by profile)", info.app_name);
+                    }
+                    self.mark_all_inactive(events::EventKind::Untracked, Instant::now());
+                }
+                Some(info) => {
+                    let stored_title = title::storage_title(&info.window_title);
+                    let key = ActivityKey::new(&info.app_name, &stored_title, info.url.as_deref());
+                    if last_key.as_ref() != Some(&key) {
+                        println!("[event] focus -> {} (title {:?})", key, info.window_title);
+                        for message in &trace {
+                            println!("[diagnose]   {}", message);
+                        }
+                        if stored_title != info.window_title {
+                            println!("[diagnose]   title truncated for storage to {:?}", stored_title);
+                        }
+                        last_key = Some(key.clone());
+                    }
+                    let full_title = (stored_title != info.window_title).then_some(info.window_title);
+                    self.update_usage(key, full_title, info.access.is_partial());
+                }
+                None => {
+                    if last_key.take().is_some() {
+                        println!("[event] no foreground window");
+                    }
+                }
+            }
+
+            let now = SystemTime::now();
+            if clock::due(now, last_flush, Duration::from_secs(5)) {
+                for row in self.pending_rows(Instant::now()) {
+                    println!(
+                        "[db] would insert usage_logs: {} duration={} partial={} language={}",
+                        row.key,
+                        row.duration,
+                        row.partial,
+                        language::detect(row.full_title.as_deref().unwrap_or(row.key.window_title()))
+                    );
+                    if let Some(full_title) = row.full_title {
+                        println!("[db] would upsert title_history: {:?}", full_title);
+                    }
+                }
+                last_flush = now;
+            }
+
+            tokio::time::sleep(Duration::from_millis(poll_interval_ms)).await;
+        }
+        println!("Diagnosis finished");
+    }
+
+    // Returns only when the memory watchdog restarted the tracker
     async fn run_monitoring(&self) {
-        let mut last_flush = SystemTime::now();
-        let flush_interval = Duration::from_secs(5); // Flush every 5 seconds for faster updates
+        let mut last_status = SystemTime::now();
+        let mut flush_failing = false; // Notify once per outage, not on every retry
+        let mut last_block_refresh = SystemTime::now();
+        let mut last_device_check = SystemTime::now();
+        let mut last_daily_note_check = UNIX_EPOCH;
+        let mut last_category_refresh = UNIX_EPOCH; // Catch up right after startup
+        let mut last_rollup_refresh = UNIX_EPOCH;
+        let mut last_grafana_refresh = UNIX_EPOCH;
+        let mut last_maintenance_check = UNIX_EPOCH;
+        let mut clock_jumps = clock::JumpDetector::default();
+        let power_events = power::watch();
+        let mut asleep = false; // Between a suspend and the resume after it
+        let mut last_idle_check = UNIX_EPOCH;
+        let mut idle_since: Option<Instant> = None; // The last input, while idle
+        let mut last_memory_check = SystemTime::now();
         
         loop {
-            if let Some((app_name, window_title, url)) = self.get_foreground_window_info() {
-                let identifier = if let Some(ref url) = url {
-                    format!("{}:{}", app_name, url)
+            let iteration_start = Instant::now();
+            for event in power_events.try_iter() {
+                match event {
+                    power::PowerEvent::Suspend(at) => {
+                        self.end_and_flush(at, events::EventKind::Suspend);
+                        asleep = true;
+                    }
+                    power::PowerEvent::Resume(_) => {
+                        if self.debug_mode {
+                            logs::info!("Resumed from suspend");
+                        }
+                        asleep = false;
+                        clock_jumps.reset();
+                    }
+                }
+            }
+            let wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            if let Some(jump) = clock_jumps.check(iteration_start, wall) {
+                self.handle_clock_jump(&jump);
+            }
+            let poll = self.telemetry.start("sysmonitor.tracker.poll");
+            let mut poll_interval_ms = profiles::DEFAULT_POLL_INTERVAL_MS;
+            if let Err(e) = self.resume_expired_pause() {
+                logs::error!("Error resuming tracking: {}", e);
+            }
+            if clock::due(SystemTime::now(), last_idle_check, IDLE_CHECK.to_std()) {
+                last_idle_check = SystemTime::now();
+                let after = self.config.idle.after_secs;
+                let idle_for = idle::idle_secs().filter(|secs| after > 0 && *secs >= after);
+                let was_idle = idle_since.is_some();
+                idle_since = idle_for.map(|secs| {
+                    idle_since.unwrap_or_else(|| iteration_start.checked_sub(Duration::from_secs(secs)).unwrap_or(iteration_start))
+                });
+                if let (Some(at), false) = (idle_since, was_idle) {
+                    self.end_and_flush(at, events::EventKind::Idle);
+                }
+            }
+            if asleep {
+                self.mark_all_inactive(events::EventKind::Suspend, iteration_start);
+            } else if self.current_pause().is_some() {
+                self.mark_all_inactive(events::EventKind::Pause, iteration_start);
+            } else if idle_since.is_some() {
+                // The open session ended at the last input, when idle began
+            } else if let Some(info) = self.get_foreground_window_info() {
+                RuntimeMetrics::increment(&self.metrics.events_processed);
+                poll_interval_ms = info.profile.poll_interval_ms();
+                if info.app_name.eq_ignore_ascii_case(LOCK_SCREEN_APP) {
+                    self.mark_all_inactive(events::EventKind::Lock, iteration_start);
+                } else if info.profile.track {
+                    let stored_title = title::storage_title(&info.window_title);
+                    let key = ActivityKey::new(&info.app_name, &stored_title, info.url.as_deref());
+                    let full_title = (stored_title != info.window_title).then_some(info.window_title);
+                    self.update_usage(key, full_title, info.access.is_partial());
                 } else {
-                    format!("{}:{}", app_name, window_title)
-                };
-                
-                self.update_usage(identifier, app_name, window_title, url);
+                    // Time in untracked apps ends the previous session
+                    self.mark_all_inactive(events::EventKind::Untracked, iteration_start);
+                }
             }
             
             // Print status every 5 seconds for faster debugging (only in debug mode)
             let now = SystemTime::now();
-            if now.duration_since(last_flush).unwrap() >= Duration::from_secs(5) {
+            if clock::due(now, last_status, Duration::from_secs(5)) {
                 self.print_status();
+                last_status = now;
             }
             
-            // Flush to database every 5 seconds for faster updates
-            if now.duration_since(last_flush).unwrap() >= flush_interval {
+            // Once enough events are queued, or the interval is up; only the
+            // interval while writes fail, so the queue doesn't retry every poll
+            if self.writer.due(if flush_failing { 0 } else { self.pending_count() }, Instant::now()) {
+                RuntimeMetrics::increment(&self.metrics.flushes);
                 if let Err(e) = self.flush_to_database() {
+                    RuntimeMetrics::increment(&self.metrics.flush_errors);
                     if self.debug_mode {
-                        eprintln!("Error flushing to database: {}", e);
+                        logs::error!("Error flushing to database: {}", e);
+                    }
+                    if !flush_failing {
+                        self.notifier.notify(Notification {
+                            kind: EventKind::Error,
+                            title: "Usage data is not being saved".to_string(),
+                            message: format!("Writing to {} failed: {}", self.db_path, e),
+                            timestamp: now.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                        });
+                    }
+                    flush_failing = true;
+                } else {
+                    if self.debug_mode {
+                        logs::info!("Data flushed to database");
+                    }
+                    flush_failing = false;
+                }
+            }
+
+            if clock::due(now, last_block_refresh, BLOCK_REFRESH.to_std()) {
+                if let Err(e) = self.refresh_blocks() {
+                    if self.debug_mode {
+                        logs::error!("Error refreshing work blocks: {}", e);
+                    }
+                }
+                last_block_refresh = now;
+            }
+
+            if clock::due(now, last_device_check, DEVICE_CHECK.to_std()) {
+                if let Err(e) = self.check_devices() {
+                    if self.debug_mode {
+                        logs::error!("Error checking devices: {}", e);
+                    }
+                }
+                last_device_check = now;
+            }
+
+            if clock::due(now, last_daily_note_check, DAILY_NOTE_CHECK.to_std()) {
+                if let Err(e) = self.write_daily_note() {
+                    if self.debug_mode {
+                        logs::error!("Error writing the daily note: {}", e);
+                    }
+                }
+                last_daily_note_check = now;
+            }
+
+            if clock::due(now, last_category_refresh, CATEGORY_REFRESH.to_std()) {
+                if let Err(e) = self.refresh_categories() {
+                    if self.debug_mode {
+                        logs::error!("Error refreshing category assignments: {}", e);
+                    }
+                }
+                last_category_refresh = now;
+            }
+
+            if clock::due(now, last_rollup_refresh, ROLLUP_REFRESH.to_std()) {
+                if let Err(e) = self.refresh_rollups() {
+                    if self.debug_mode {
+                        logs::error!("Error rolling up days: {}", e);
+                    }
+                }
+                last_rollup_refresh = now;
+            }
+
+            if clock::due(now, last_grafana_refresh, GRAFANA_REFRESH.to_std()) {
+                if let Err(e) = self.refresh_grafana() {
+                    if self.debug_mode {
+                        logs::error!("Error refreshing the Grafana tables: {}", e);
+                    }
+                }
+                last_grafana_refresh = now;
+            }
+
+            if clock::due(now, last_maintenance_check, MAINTENANCE_CHECK.to_std()) {
+                if let Err(e) = self.run_due_maintenance() {
+                    if self.debug_mode {
+                        logs::error!("Error running housekeeping: {}", e);
+                    }
+                }
+                last_maintenance_check = now;
+            }
+
+            if clock::due(now, last_memory_check, MEMORY_CHECK.to_std()) {
+                last_memory_check = now;
+                match self.check_memory() {
+                    Ok(memory::Action::Restart) => {
+                        self.restart_tracker();
+                        return;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        if self.debug_mode {
+                            logs::error!("Error checking memory use: {}", e);
+                        }
                     }
-                } else if self.debug_mode {
-                    println!("Data flushed to database");
                 }
-                last_flush = now;
             }
             
-            tokio::time::sleep(Duration::from_millis(500)).await;
+            self.metrics.record_loop(iteration_start.elapsed());
+            self.telemetry.finish(poll, Vec::new(), None);
+            tokio::time::sleep(Duration::from_millis(poll_interval_ms)).await;
+        }
+    }
+}
+
+// A run that lasts `safe_mode.stable_secs` was a good start, whatever happens later
+async fn clear_crash_marker_when_stable(monitor: Arc<SystemMonitor>) {
+    tokio::time::sleep(Duration::from_secs(monitor.config.safe_mode.stable_secs)).await;
+    safe_mode::clear(&safe_mode::marker_path(&monitor.db_path));
+}
+
+// Runs `job` every `period` apart from the tracking loop and on a blocking
+// thread, so a slow or unreachable endpoint holds up neither focus tracking
+// nor a runtime worker. `failing` completes "Error ...".
+async fn run_periodically<T, E>(monitor: Arc<SystemMonitor>, period: DurationSecs, failing: &'static str, job: fn(&SystemMonitor) -> Result<T, E>)
+where
+    T: Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+{
+    let mut interval = tokio::time::interval(period.to_std());
+    // After a suspend, one run catches up; missed ticks don't pile up
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let running = monitor.clone();
+        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || job(&running)).await {
+            if monitor.debug_mode {
+                logs::error!("Error {}: {}", failing, e);
+            }
         }
     }
 }
 
+// Decode a UTF-16 title buffer, dropping a high surrogate left dangling when
+// the title was cut off at the buffer boundary
+#[cfg(any(windows, test))]
+fn decode_utf16_title(buffer: &[u16]) -> String {
+    let buffer = match buffer.last() {
+        Some(&last) if (0xD800..0xDC00).contains(&last) => &buffer[..buffer.len() - 1],
+        _ => buffer,
+    };
+    String::from_utf16_lossy(buffer)
+}
+
+// Accept a title fragment as a URL only if it parses as an absolute URL
+fn parse_url_candidate(candidate: &str) -> Option<String> {
+    let candidate = candidate.trim();
+    if candidate.is_empty() || candidate.chars().any(char::is_whitespace) {
+        return None;
+    }
+    if !(candidate.starts_with("http") || candidate.contains("://")) {
+        return None;
+    }
+    url::Url::parse(candidate).ok().map(|_| candidate.to_string())
+}
+
+#[cfg(feature = "web")]
 fn is_port_in_use(port: u16) -> bool {
     TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port))).is_err()
 }
 
+#[cfg(feature = "web")]
 fn launch_edge_app() -> Result<(), Box<dyn std::error::Error>> {
     let url = "http://localhost:3030";
+    #[cfg(windows)]
     let edge_path = r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe";
+    #[cfg(not(windows))]
+    let edge_path = "microsoft-edge";
     
     Command::new(edge_path)
         .args(&[
@@ -490,118 +2226,380 @@ fn launch_edge_app() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+// The monitor with its database created and migrated, for subcommands that read or write it
+fn ready_monitor(debug_mode: bool) -> SqlResult<SystemMonitor> {
+    let monitor = SystemMonitor::new(debug_mode);
+    monitor.init_database()?;
+    Ok(monitor)
+}
+
+fn open_database(debug_mode: bool) -> SqlResult<(SystemMonitor, Connection)> {
+    let monitor = ready_monitor(debug_mode)?;
+    let conn = Connection::open(&monitor.db_path)?;
+    Ok((monitor, conn))
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() {
     // Check for debug mode
     let debug_mode = env::args().any(|arg| arg == "--debug");
-    
-    if debug_mode {
-        println!("System Monitor v0.1.0 with Web GUI (DEBUG MODE)");
-        println!("Starting web server and monitoring...");
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args, debug_mode).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
-    
-    let monitor = Arc::new(SystemMonitor::new(debug_mode));
-    
-    // Initialize database
-    monitor.init_database()?;
-    monitor.load_existing_data()?;
-    
+}
+
+// `sysmonitor <command> [args]`; anything that isn't a command starts tracking
+async fn run(args: &[String], debug_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (command, args) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("", args),
+    };
+    match command {
+        "doctor" => {
+            if !doctor::run(&SystemMonitor::new(debug_mode)) {
+                return Err("Some checks failed".into());
+            }
+        }
+        "stats" if args.iter().any(|arg| arg == "--self") => {
+            if !cfg!(feature = "system-metrics") {
+                return Err("Runtime stats need a build with the `system-metrics` feature".into());
+            }
+            ready_monitor(debug_mode)?.print_runtime_history(5)?;
+        }
+        #[cfg(feature = "web")]
+        "server" => server::command(args, debug_mode).await?,
+        #[cfg(not(feature = "web"))]
+        "server" => return Err("Server mode needs a build with the `web` feature".into()),
+        #[cfg(all(feature = "web", feature = "fixtures"))]
+        "demo" => demo::command(args, debug_mode).await?,
+        #[cfg(not(all(feature = "web", feature = "fixtures")))]
+        "demo" => return Err("Demo mode needs a build with the `web` and `fixtures` features".into()),
+        #[cfg(feature = "fixtures")]
+        "fixture" => fixtures::command(args, debug_mode)?,
+        #[cfg(not(feature = "fixtures"))]
+        "fixture" => return Err("Fixture databases need a build with the `fixtures` feature".into()),
+        "token" => {
+            let (_, conn) = open_database(debug_mode)?;
+            auth::run_command(&conn, args)?;
+        }
+        "rules" => {
+            let monitor = SystemMonitor::new(debug_mode);
+            rules::run_command(&monitor.config, &monitor.config_path, args)?;
+        }
+        "note" => {
+            let (monitor, conn) = open_database(debug_mode)?;
+            daily_note::run_command(&conn, &monitor.config.daily_notes, args)?;
+        }
+        "notion" => {
+            let (monitor, conn) = open_database(debug_mode)?;
+            notion::run_command(&conn, &monitor.config.notion, &monitor.config.categories, args, Timestamp::now().as_secs())?;
+        }
+        "vault" => {
+            let (monitor, mut conn) = open_database(debug_mode)?;
+            vault::run_command(&mut conn, &monitor.config.ingest, args)?;
+        }
+        #[cfg(feature = "compression")]
+        "archive" => {
+            let mut monitor = ready_monitor(debug_mode)?;
+            // `sysmonitor archive [--months N]` runs now, with the config's age by default
+            if let Some(months) = args.iter().position(|arg| arg == "--months").and_then(|index| args.get(index + 1)) {
+                monitor.config.archive.after_months = Some(months.parse().map_err(|_| format!("--months must be a number, got '{}'", months))?);
+            }
+            if monitor.config.archive.after_months.is_none() {
+                return Err("Set archive.after_months in the config or pass --months N".into());
+            }
+            let archived = monitor.archive_old_sessions()?;
+            if archived.is_empty() {
+                println!("Nothing old enough to archive");
+            }
+            for month in archived {
+                println!("{}: {} rows -> {}", month.month, month.rows, month.file);
+            }
+        }
+        #[cfg(not(feature = "compression"))]
+        "archive" => return Err("Archiving needs a build with the `compression` feature".into()),
+        "clock" => {
+            let (monitor, mut conn) = open_database(debug_mode)?;
+            clock::run_command(&mut conn, &monitor.config.categories, args, Timestamp::now().as_secs())?;
+        }
+        "events" => {
+            let (monitor, mut conn) = open_database(debug_mode)?;
+            events::run_command(&mut conn, &monitor.config.categories, &monitor.config.debounce, &monitor.config.idle, args, Timestamp::now())?;
+        }
+        "crash" => {
+            let monitor = SystemMonitor::new(debug_mode);
+            crash::run_command(&monitor.config.crash_reports, &monitor.db_path, args)?;
+        }
+        "query" => {
+            let (monitor, conn) = open_database(debug_mode)?;
+            queries::run_command(&conn, &monitor.config.categories, &monitor.config.queries, args, Timestamp::now().as_secs())?;
+        }
+        "reprocess" => {
+            let (monitor, mut conn) = open_database(debug_mode)?;
+            reprocess::run_command(&mut conn, &monitor.config.categories, &monitor.config.debounce, &monitor.config.idle, args, Timestamp::now())?;
+        }
+        "verify" => {
+            let (monitor, conn) = open_database(debug_mode)?;
+            signatures::run_command(&conn, &monitor.config.signing, args)?;
+        }
+        #[cfg(feature = "compression")]
+        "export" => {
+            let (monitor, conn) = open_database(debug_mode)?;
+            let archive_dir = monitor.config.archive.dir_for(&monitor.db_path);
+            bundle::run_command(&conn, &archive_dir, args, Timestamp::now())?;
+        }
+        #[cfg(not(feature = "compression"))]
+        "export" => return Err("Export bundles need a build with the `compression` feature".into()),
+        _ if env::args().any(|arg| arg == "--diagnose") => {
+            let mut monitor = SystemMonitor::new(debug_mode);
+            monitor.diagnose_mode = true;
+            monitor.run_diagnostics().await;
+        }
+        _ => track(debug_mode).await?,
+    }
+    Ok(())
+}
+
+async fn track(debug_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // `--headless` (and builds without `web`) only track and store; the data is
+    // reached through the CLI or whatever the collector feeds
+    let headless = !cfg!(feature = "web") || env::args().any(|arg| arg == "--headless");
+
     if debug_mode {
-        println!("Database initialized. Starting web server on http://localhost:3030");
+        logs::info!("System Monitor v0.1.0 with Web GUI (DEBUG MODE)");
+        logs::info!("{}", if headless { "Starting monitoring..." } else { "Starting web server and monitoring..." });
     }
     
-    // Check if port is already in use
-    let port_in_use = is_port_in_use(3030);
-    
-    if port_in_use {
+    // Before anything counts as a start, so a second launch leaves the crash marker alone
+    #[cfg(feature = "web")]
+    if !headless && is_port_in_use(web::WEB_PORT) {
         // Port is in use, just launch Edge
         if debug_mode {
-            println!("Port 3030 is already in use. Launching Edge app...");
+            logs::info!("Port 3030 is already in use. Launching Edge app...");
         }
         launch_edge_app()?;
         return Ok(());
     }
+
+    let monitor = Arc::new(SystemMonitor::starting(debug_mode, env::args().any(|arg| arg == "--safe-mode")));
+    crash::install(&monitor.config.crash_reports, &monitor.db_path);
     
-    // Clone monitor for web server
+    // Initialize database
+    monitor.init_database()?;
+    monitor.load_existing_data()?;
+    monitor.report_capabilities();
+    tokio::spawn(clear_crash_marker_when_stable(monitor.clone()));
+    tokio::spawn(run_periodically(monitor.clone(), NOTION_SYNC, "syncing to Notion", SystemMonitor::sync_notion));
+    tokio::spawn(run_periodically(monitor.clone(), INFLUX_CHECK, "pushing to InfluxDB", SystemMonitor::push_influx));
+    tokio::spawn(run_periodically(monitor.clone(), HOME_ASSISTANT_PUBLISH, "publishing Home Assistant sensors", SystemMonitor::publish_home_assistant));
+    tokio::spawn(run_periodically(monitor.clone(), TELEMETRY_EXPORT, "exporting telemetry", SystemMonitor::export_telemetry));
+    tokio::spawn(run_periodically(monitor.clone(), DIGEST_CHECK, "sending the goal digest", SystemMonitor::send_goal_digest));
+    
+    if debug_mode {
+        logs::info!(
+            "{}",
+            if headless { "Database initialized. Tracking headless, without the web server" } else { "Database initialized. Starting web server on http://localhost:3030" }
+        );
+    }
+    
+    // Clone monitor for web server and shutdown handling
     let monitor_clone = monitor.clone();
+    let shutdown_monitor = monitor.clone();
     
     // Start monitoring in background
     let monitor_task = tokio::spawn(async move {
-        monitor_clone.run_monitoring().await;
-    });
-    
-    // Start web server
-    let web_server_task = tokio::spawn(async move {
-        start_web_server(monitor).await;
+        loop {
+            monitor_clone.run_monitoring().await;
+        }
     });
     
-    // Launch Edge app window after server starts
-    tokio::task::spawn_blocking(move || {
-        std::thread::sleep(Duration::from_secs(2)); // Wait for server to start
-        if let Err(_) = launch_edge_app() {
-            if debug_mode {
-                eprintln!("Failed to launch Edge app");
-                println!("You can manually open http://localhost:3030 in your browser");
+    // Headless: nothing to serve, run until the tracker stops
+    let idle = || tokio::spawn(std::future::pending::<()>());
+    #[cfg(not(feature = "web"))]
+    let web_server_task = idle();
+    #[cfg(feature = "web")]
+    let web_server_task = if headless {
+        idle()
+    } else {
+        // Launch Edge app window after server starts
+        tokio::task::spawn_blocking(move || {
+            std::thread::sleep(Duration::from_secs(2)); // Wait for server to start
+            if launch_edge_app().is_err() && debug_mode {
+                logs::error!("Failed to launch Edge app");
+                logs::info!("You can manually open http://localhost:3030 in your browser");
             }
-        }
-    });
+        });
+        tokio::spawn(async move {
+            web::start_web_server(monitor, SocketAddr::from(([127, 0, 0, 1], web::WEB_PORT))).await;
+        })
+    };
     
-    // Wait for both tasks
-    tokio::try_join!(monitor_task, web_server_task)?;
+    // Run until either task exits or Ctrl+C requests a clean shutdown
+    tokio::select! {
+        result = async { tokio::try_join!(monitor_task, web_server_task) } => {
+            result?;
+        }
+        _ = tokio::signal::ctrl_c() => {
+            shutdown_monitor.shutdown();
+        }
+    }
     
     Ok(())
 }
 
-async fn start_web_server(monitor: Arc<SystemMonitor>) {
-    let monitor_filter = warp::any().map(move || monitor.clone());
-    
-    // Serve static files
-    let static_files = warp::path("static")
-        .and(warp::fs::dir("web/static"));
-    
-    // API routes
-    let api_routes = warp::path("api")
-        .and(
-            // Dashboard data endpoint
-            warp::path("dashboard")
-                .and(warp::get())
-                .and(monitor_filter.clone())
-                .and_then(handle_dashboard)
-                .or(
-                    // Health check endpoint
-                    warp::path("health")
-                        .and(warp::get())
-                        .and_then(handle_health)
-                )
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn firefox_url_is_extracted() {
+        let monitor = SystemMonitor::new(false);
+        assert_eq!(
+            monitor.extract_firefox_url("https://example.com/a - Mozilla Firefox"),
+            Some("https://example.com/a".to_string())
         );
-    
-    // Serve main HTML page
-    let index = warp::path::end()
-        .and(warp::get())
-        .and(warp::fs::file("web/index.html"));
-    
-    let routes = index
-        .or(static_files)
-        .or(api_routes);
-    
-    warp::serve(routes)
-        .run(([127, 0, 0, 1], 3030))
-        .await;
-}
+        assert_eq!(monitor.extract_firefox_url("http is fun - Mozilla Firefox"), None);
+    }
 
-async fn handle_dashboard(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
-    let data = monitor.get_dashboard_data();
-    Ok(warp::reply::json(&ApiResponse {
-        success: true,
-        data: Some(serde_json::to_value(data).unwrap()),
-        error: None,
-    }))
-}
+    #[test]
+    fn chromium_url_uses_last_segment() {
+        let monitor = SystemMonitor::new(false);
+        assert_eq!(
+            monitor.extract_chromium_url("chrome.exe", "A - B - https://example.com"),
+            Some("https://example.com".to_string())
+        );
+        assert_eq!(monitor.extract_chromium_url("chrome.exe", "Docs - Google Chrome"), None);
+    }
+
+    #[test]
+    fn sessions_are_measured_on_the_monotonic_clock() {
+        let monitor = SystemMonitor::new(false);
+        let since = Instant::now();
+        let entry = ActiveEntry { status: true, last_seen: Timestamp::from_secs(1_000), start_time: Timestamp::from_secs(1_000), since: Some(since), left: None, away: false, full_title: None, partial: false };
+        monitor.usage_data.lock().unwrap().insert(ActivityKey::new("code.exe", "main.rs", None), entry);
+        // Whatever the wall clock reads now, the session ends 90s after it started
+        let rows = monitor.pending_rows(since + Duration::from_secs(90));
+        assert_eq!(rows.iter().map(|row| (row.timestamp, row.duration)).collect::<Vec<_>>(), vec![(Timestamp::from_secs(1_090), DurationSecs::secs(90))]);
+    }
+
+    #[test]
+    fn open_sessions_are_split_at_local_midnight() {
+        let monitor = SystemMonitor::new(false);
+        let midnight = reports::day_bounds(chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()).1 as u64;
+        let since = Instant::now();
+        let entry = ActiveEntry { status: true, last_seen: Timestamp::EPOCH, start_time: Timestamp::from_secs(midnight - 600), since: Some(since), left: None, away: false, full_title: None, partial: false };
+        monitor.usage_data.lock().unwrap().insert(ActivityKey::new("code.exe", "main.rs", None), entry);
+
+        let now = since + Duration::from_secs(900);
+        let mut rows = monitor.split_at_midnight(now);
+        rows.extend(monitor.pending_rows(now));
+        let stored: Vec<(u64, u64)> = rows.iter().map(|row| ((row.timestamp - row.duration).as_secs(), row.duration.as_secs())).collect();
+        assert_eq!(stored, vec![(midnight - 600, 600), (midnight, 300)]);
+        assert!(monitor.split_at_midnight(now).is_empty());
+    }
+
+    #[test]
+    fn a_quick_glance_elsewhere_folds_into_the_session() {
+        let monitor = SystemMonitor::new(false);
+        let (code, chat) = (ActivityKey::new("code.exe", "main.rs", None), ActivityKey::new("slack.exe", "general", None));
+        monitor.update_usage(code.clone(), None, false);
+        let started = monitor.usage_data.lock().unwrap()[&code].since;
+        monitor.update_usage(chat.clone(), None, false);
+        monitor.update_usage(code.clone(), None, false);
 
-async fn handle_health() -> Result<impl warp::Reply, warp::Rejection> {
-    Ok(warp::reply::json(&ApiResponse {
-        success: true,
-        data: Some(serde_json::json!({"status": "healthy"})),
-        error: None,
-    }))
-}
\ No newline at end of file
+        let usage_data = monitor.usage_data.lock().unwrap();
+        assert!(usage_data[&code].status && usage_data[&code].since == started);
+        drop(usage_data);
+        // One session, with the glance counted in it
+        let rows = monitor.pending_rows(started.unwrap() + Duration::from_secs(60));
+        assert_eq!(rows.iter().map(|row| row.key.app_name.as_str()).collect::<Vec<_>>(), vec!["code.exe"]);
+    }
+
+    #[test]
+    fn a_short_time_away_keeps_the_session() {
+        let mut monitor = SystemMonitor::new(false);
+        monitor.config.idle = idle::IdleConfig { after_secs: 60, grace_secs: 180 };
+        let key = ActivityKey::new("code.exe", "main.rs", None);
+        monitor.update_usage(key.clone(), None, false);
+        let started = monitor.usage_data.lock().unwrap()[&key].since.unwrap();
+        // Idle from 30 seconds in: the session ended at the last input
+        let rows = monitor.end_sessions_at(started + Duration::from_secs(30), events::EventKind::Idle);
+        assert_eq!(rows.iter().map(|row| (row.duration, row.ended)).collect::<Vec<_>>(), vec![(DurationSecs::secs(30), true)]);
+
+        monitor.update_usage(key.clone(), None, false);
+        let entry = monitor.usage_data.lock().unwrap()[&key].clone();
+        assert!(entry.status && entry.since == Some(started) && !entry.away);
+    }
+
+    #[test]
+    fn sleeping_ends_the_session_at_suspend() {
+        let monitor = SystemMonitor::new(false);
+        let key = ActivityKey::new("code.exe", "main.rs", None);
+        let since = Instant::now();
+        let entry = ActiveEntry { status: true, last_seen: Timestamp::from_secs(1_000), start_time: Timestamp::from_secs(1_000), since: Some(since), left: None, away: false, full_title: None, partial: false };
+        monitor.usage_data.lock().unwrap().insert(key.clone(), entry);
+        // Ten minutes of work, then a night asleep before the tracker gets to the event
+        let rows = monitor.end_sessions_at(since + Duration::from_secs(600), events::EventKind::Suspend);
+        assert_eq!(rows.iter().map(|row| (row.timestamp, row.duration)).collect::<Vec<_>>(), vec![(Timestamp::from_secs(1_600), DurationSecs::mins(10))]);
+        assert!(monitor.pending_rows(since + Duration::from_secs(8 * 3600)).is_empty());
+
+        // The same window afterwards is a new session from when tracking saw it again
+        monitor.update_usage(key.clone(), None, false);
+        let entry = monitor.usage_data.lock().unwrap()[&key].clone();
+        assert!(entry.status && entry.start_time > Timestamp::from_secs(1_000) && entry.since >= Some(since));
+    }
+
+    #[test]
+    fn the_first_focus_after_a_restart_continues_the_open_session() {
+        let monitor = SystemMonitor::new(false);
+        let key = ActivityKey::new("code.exe", "main.rs", None);
+        let now = Timestamp::now();
+        let open = warm_start::OpenSession { key: key.clone(), start: now - DurationSecs::mins(10), stopped_at: now - DurationSecs::secs(5) };
+        *monitor.warm_start.lock().unwrap() = Some(open.clone());
+        monitor.update_usage(key.clone(), None, false);
+        let entry = monitor.usage_data.lock().unwrap()[&key].clone();
+        assert_eq!(entry.start_time, open.start);
+        assert!(monitor.pending_rows(Instant::now())[0].duration >= DurationSecs::mins(10));
+
+        // Only once, and only for the same window
+        *monitor.warm_start.lock().unwrap() = Some(open);
+        let other = ActivityKey::new("code.exe", "web.rs", None);
+        monitor.update_usage(other.clone(), None, false);
+        assert!(monitor.usage_data.lock().unwrap()[&other].start_time >= now);
+        assert!(monitor.warm_start.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn dangling_surrogate_is_dropped() {
+        let mut buffer: Vec<u16> = "ab".encode_utf16().collect();
+        buffer.push(0xD83D);
+        assert_eq!(decode_utf16_title(&buffer), "ab");
+    }
+
+    proptest! {
+        #[test]
+        fn extractors_never_panic(app in ".*", title in ".*") {
+            let monitor = SystemMonitor::new(false);
+            let _ = monitor.extract_browser_url(&app, &title, Extractor::Auto);
+            let _ = monitor.extract_chromium_url(&app, &title);
+            let _ = monitor.extract_firefox_url(&title);
+        }
+
+        #[test]
+        fn extracted_urls_come_from_the_title(title in ".*") {
+            let monitor = SystemMonitor::new(false);
+            if let Some(url) = monitor.extract_firefox_url(&title) {
+                prop_assert!(title.contains(&url));
+                prop_assert!(!url.chars().any(char::is_whitespace));
+            }
+        }
+
+        #[test]
+        fn utf16_decoding_never_panics(buffer in proptest::collection::vec(any::<u16>(), 0..300)) {
+            let _ = decode_utf16_title(&buffer);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Housekeeping jobs, what their last runs did and when they run next, from
+// the `maintenance` section of the config.
+//
+// - prune: redacts and deletes what outlived its `retention` class, hourly
+// - archive: moves sessions past `archive.after_months` to the archive, daily
+// - vacuum: rebuilds the database so the pages freed by the other two go back
+//   to the disk, every `vacuum_days`
+// - backup: a consistent copy of the database, `usage-YYYYMMDD-HHMMSS.db` in
+//   `backup_dir`, every `backup_days`; only the newest `backup_keep` stay
+// - compact: drops what the tracker holds in memory and rebuilds on demand,
+//   also done on its own when memory use passes `memory.ceiling_mb`
+//
+// A job without its setting only runs when asked to, through
+// `POST /api/maintenance/<job>`. Every run ends up in `maintenance_runs`, so
+// `/api/maintenance` still shows it after a restart and the schedule counts
+// from it rather than from the start of the process.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::TimeZone;
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::units::{DurationSecs, Timestamp};
+
+const BACKUP_PREFIX: &str = "usage-";
+const BACKUP_SUFFIX: &str = ".db";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    pub vacuum_days: Option<u64>, // Unset only vacuums on request
+    pub backup_days: Option<u64>, // Unset only backs up on request
+    pub backup_dir: String, // Relative paths are next to the database
+    pub backup_keep: usize, // 0 keeps every backup
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self { vacuum_days: None, backup_days: None, backup_dir: "backups".to_string(), backup_keep: 7 }
+    }
+}
+
+impl MaintenanceConfig {
+    pub fn backup_dir_for(&self, db_path: &str) -> PathBuf {
+        let dir = Path::new(&self.backup_dir);
+        match Path::new(db_path).parent() {
+            Some(parent) if dir.is_relative() => parent.join(dir),
+            _ => dir.to_path_buf(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Job {
+    Prune,
+    Archive,
+    Vacuum,
+    Backup,
+    Compact,
+}
+
+impl Job {
+    // In the order they run when several are due: vacuum after what frees pages
+    pub const ALL: [Job; 5] = [Job::Prune, Job::Archive, Job::Vacuum, Job::Backup, Job::Compact];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::Prune => "prune",
+            Job::Archive => "archive",
+            Job::Vacuum => "vacuum",
+            Job::Backup => "backup",
+            Job::Compact => "compact",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Job> {
+        Job::ALL.into_iter().find(|job| job.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Run {
+    pub job: Job,
+    pub started_at: Timestamp,
+    pub finished_at: Timestamp,
+    pub result: Option<serde_json::Value>, // What the job did, None when it failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobStatus {
+    pub job: Job,
+    pub every_secs: Option<DurationSecs>, // None when it only runs on request
+    pub next_run: Option<Timestamp>,
+    pub last_run: Option<Run>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Vacuumed {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackedUp {
+    pub file: String,
+    pub bytes: u64,
+    pub removed: Vec<String>, // Older backups past `backup_keep`
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS maintenance_runs (
+            job TEXT PRIMARY KEY,
+            started_at INTEGER NOT NULL,
+            finished_at INTEGER NOT NULL,
+            result TEXT,
+            error TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Keeps `run` as the last of its job.
+pub fn record(conn: &Connection, run: &Run) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO maintenance_runs (job, started_at, finished_at, result, error) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![run.job.name(), run.started_at, run.finished_at, run.result.as_ref().map(|result| result.to_string()), run.error],
+    )?;
+    Ok(())
+}
+
+/// The last run of each job that ran.
+pub fn last_runs(conn: &Connection) -> SqlResult<BTreeMap<Job, Run>> {
+    let mut stmt = conn.prepare("SELECT job, started_at, finished_at, result, error FROM maintenance_runs")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Timestamp>(1)?,
+            row.get::<_, Timestamp>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+        ))
+    })?;
+    let mut runs = BTreeMap::new();
+    for row in rows {
+        let (job, started_at, finished_at, result, error) = row?;
+        // A job a later version dropped
+        let Some(job) = Job::parse(&job) else { continue };
+        let result = result.and_then(|result| serde_json::from_str(&result).ok());
+        runs.insert(job, Run { job, started_at, finished_at, result, error });
+    }
+    Ok(runs)
+}
+
+/// When a job run `every` so often runs next; right away when it never ran.
+pub fn next_run(last: Option<&Run>, every: Option<DurationSecs>, now: Timestamp) -> Option<Timestamp> {
+    every.map(|every| last.map_or(now, |last| (last.started_at + every).max(now)))
+}
+
+pub fn due(last: Option<&Run>, every: Option<DurationSecs>, now: Timestamp) -> bool {
+    next_run(last, every, now).is_some_and(|next| next <= now)
+}
+
+pub fn status(mut runs: BTreeMap<Job, Run>, every: impl Fn(Job) -> Option<DurationSecs>, now: Timestamp) -> Vec<JobStatus> {
+    Job::ALL
+        .into_iter()
+        .map(|job| {
+            let last_run = runs.remove(&job);
+            JobStatus { job, every_secs: every(job), next_run: next_run(last_run.as_ref(), every(job), now), last_run }
+        })
+        .collect()
+}
+
+fn size(conn: &Connection) -> SqlResult<u64> {
+    let pages: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    Ok((pages * page_size).max(0) as u64)
+}
+
+/// Rebuilds the database file without its free pages.
+pub fn vacuum(conn: &Connection) -> SqlResult<Vacuumed> {
+    let bytes_before = size(conn)?;
+    conn.execute_batch("VACUUM")?;
+    Ok(Vacuumed { bytes_before, bytes_after: size(conn)? })
+}
+
+/// Copies the database into `dir`, named after `now` in local time, and
+/// removes the oldest backups past `keep`.
+pub fn backup(conn: &Connection, dir: &Path, keep: usize, now: Timestamp) -> Result<BackedUp, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let stamp = chrono::Local.timestamp_opt(now.as_secs() as i64, 0).single().ok_or("Invalid time")?.format("%Y%m%d-%H%M%S");
+    let path = dir.join(format!("{}{}{}", BACKUP_PREFIX, stamp, BACKUP_SUFFIX));
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()));
+    }
+    // Unlike copying the file, this sees one consistent state while flushes go on
+    conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()]).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    let bytes = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_SUFFIX))
+        })
+        .collect();
+    backups.sort();
+    let mut removed = Vec::new();
+    if keep > 0 && backups.len() > keep {
+        for old in &backups[..backups.len() - keep] {
+            fs::remove_file(old).map_err(|e| format!("Failed to remove {}: {}", old.display(), e))?;
+            removed.push(old.display().to_string());
+        }
+    }
+    Ok(BackedUp { file: path.display().to_string(), bytes, removed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, Shape};
+
+    #[test]
+    fn runs_are_kept_and_schedule_the_next() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        let at = Timestamp::from_secs;
+        let hourly = Some(DurationSecs::hours(1));
+        let run = Run { job: Job::Prune, started_at: at(1_000), finished_at: at(1_002), result: Some(serde_json::json!({ "sessions_deleted": 3 })), error: None };
+        record(&conn, &run).unwrap();
+        let runs = last_runs(&conn).unwrap();
+        assert_eq!(runs[&Job::Prune], run);
+
+        assert!(!due(Some(&run), hourly, at(4_599)));
+        assert!(due(Some(&run), hourly, at(4_600)));
+        assert!(due(None, hourly, at(1_000)));
+        assert!(!due(None, None, at(1_000)));
+
+        let status = status(runs, |job| hourly.filter(|_| job == Job::Prune), at(2_000));
+        assert_eq!(status.len(), Job::ALL.len());
+        assert_eq!((status[0].next_run, status[0].last_run.is_some()), (Some(at(4_600)), true));
+        assert_eq!((status[2].job, status[2].next_run), (Job::Vacuum, None));
+        assert_eq!(Job::parse("backup"), Some(Job::Backup));
+    }
+
+    #[test]
+    fn backups_beyond_keep_are_removed_oldest_first() {
+        let dir = std::env::temp_dir().join(format!("sysmonitor-backups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let conn = fixtures::database(&Shape { days: 1, ..Shape::default() }, 1_700_000_000).unwrap();
+        let sessions: i64 = conn.query_row("SELECT COUNT(*) FROM usage_logs", [], |row| row.get(0)).unwrap();
+
+        let first = backup(&conn, &dir, 2, Timestamp::from_secs(1_700_000_000)).unwrap();
+        backup(&conn, &dir, 2, Timestamp::from_secs(1_700_000_060)).unwrap();
+        assert!(backup(&conn, &dir, 2, Timestamp::from_secs(1_700_000_060)).is_err());
+        let third = backup(&conn, &dir, 2, Timestamp::from_secs(1_700_000_120)).unwrap();
+        assert_eq!(third.removed, vec![first.file.clone()]);
+        assert!(third.bytes > 0);
+
+        let copy = Connection::open(&third.file).unwrap();
+        assert_eq!(copy.query_row("SELECT COUNT(*) FROM usage_logs", [], |row| row.get::<_, i64>(0)).unwrap(), sessions);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
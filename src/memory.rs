@@ -0,0 +1,168 @@
+// The process watching its own memory, from the `memory` section of the config.
+//
+// The tracker runs around the clock for weeks, so a slow leak only shows after
+// days. Once a minute the resident set (RSS, the working set on Windows) is
+// sampled and the last `TREND_SAMPLES` kept. When the lower half of its recent
+// readings sits well above the earlier ones, growth is logged, at most once per
+// window. Over `ceiling_mb`, the tracker first compacts: windows that can't
+// resume a session and the lookup caches are dropped, to be rebuilt on demand.
+// Still over at the next check, it ends and stores the open sessions and
+// starts over with empty state, unless `restart_tracker` is off. The server
+// has no tracker and only ever compacts.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+const TREND_SAMPLES: usize = 60; // An hour at one check a minute
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryConfig {
+    pub ceiling_mb: u64, // 0 never compacts or restarts
+    pub log_growth_mb_per_hour: u64, // 0 logs no growth
+    pub restart_tracker: bool, // When compacting did not bring it under the ceiling
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self { ceiling_mb: 512, log_growth_mb_per_hour: 16, restart_tracker: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Compact,
+    Restart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Check {
+    pub action: Action,
+    pub growth_mb_per_hour: Option<u64>, // Set when growth is to be logged
+}
+
+#[derive(Debug, Default)]
+pub struct Watchdog {
+    samples: VecDeque<(u64, u64)>, // Time, resident bytes
+    compacted: bool, // At the previous check, which was over the ceiling
+    logged_growth_at: Option<u64>,
+}
+
+impl Watchdog {
+    /// Takes the reading `rss` at `at` and decides what to do about it.
+    pub fn observe(&mut self, config: &MemoryConfig, at: u64, rss: u64) -> Check {
+        if self.samples.len() == TREND_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, rss));
+
+        let growth_mb_per_hour = self
+            .growth_per_hour()
+            .map(|bytes| bytes / MIB)
+            .filter(|&growth| config.log_growth_mb_per_hour > 0 && growth >= config.log_growth_mb_per_hour)
+            .filter(|_| self.logged_growth_at.is_none_or(|logged| at >= logged + self.window_secs()));
+        if growth_mb_per_hour.is_some() {
+            self.logged_growth_at = Some(at);
+        }
+
+        let over = config.ceiling_mb > 0 && rss > config.ceiling_mb * MIB;
+        let action = match (over, self.compacted) {
+            (false, _) => Action::None,
+            (true, false) => Action::Compact,
+            (true, true) if config.restart_tracker => Action::Restart,
+            (true, true) => Action::Compact,
+        };
+        self.compacted = action == Action::Compact;
+        Check { action, growth_mb_per_hour }
+    }
+
+    fn window_secs(&self) -> u64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(first), Some(last)) => last.0.saturating_sub(first.0),
+            _ => 0,
+        }
+    }
+
+    // How far the floor of the later half of the window rose above the
+    // earlier one, per hour. Floors leave out passing spikes, such as a large
+    // report being built; a full window is needed first
+    fn growth_per_hour(&self) -> Option<u64> {
+        if self.samples.len() < TREND_SAMPLES {
+            return None;
+        }
+        let half = self.samples.len() / 2;
+        let earlier = self.samples.iter().take(half).map(|&(_, rss)| rss).min()?;
+        let later = self.samples.iter().skip(half).map(|&(_, rss)| rss).min()?;
+        let secs = (self.window_secs() / 2).max(1);
+        Some(later.saturating_sub(earlier) * 3600 / secs)
+    }
+}
+
+/// The resident memory of this process in bytes, where the platform tells.
+#[cfg(target_os = "linux")]
+pub fn rss_bytes() -> std::io::Result<Option<u64>> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    Ok(status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kib| kib * 1024))
+}
+
+#[cfg(windows)]
+pub fn rss_bytes() -> std::io::Result<Option<u64>> {
+    use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows::Win32::System::Threading::GetCurrentProcess;
+
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+    unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32) }
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(Some(counters.WorkingSetSize as u64))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn rss_bytes() -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn over_the_ceiling_compacts_then_restarts() {
+        let config = MemoryConfig { ceiling_mb: 100, ..MemoryConfig::default() };
+        let mut watchdog = Watchdog::default();
+        assert_eq!(watchdog.observe(&config, 0, 80 * MIB).action, Action::None);
+        assert_eq!(watchdog.observe(&config, 60, 120 * MIB).action, Action::Compact);
+        assert_eq!(watchdog.observe(&config, 120, 110 * MIB).action, Action::Restart);
+        assert_eq!(watchdog.observe(&config, 180, 105 * MIB).action, Action::Compact);
+        assert_eq!(watchdog.observe(&config, 240, 90 * MIB).action, Action::None);
+
+        let no_restart = MemoryConfig { restart_tracker: false, ..config };
+        let mut watchdog = Watchdog::default();
+        watchdog.observe(&no_restart, 0, 120 * MIB);
+        assert_eq!(watchdog.observe(&no_restart, 60, 120 * MIB).action, Action::Compact);
+    }
+
+    #[test]
+    fn steady_growth_is_logged_once_per_window_and_spikes_are_not() {
+        let config = MemoryConfig { ceiling_mb: 0, log_growth_mb_per_hour: 16, restart_tracker: true };
+        let mut watchdog = Watchdog::default();
+        // A spike every ten minutes over a flat 50 MiB
+        for minute in 0..TREND_SAMPLES as u64 {
+            let rss = if minute % 10 == 9 { 300 * MIB } else { 50 * MIB };
+            assert_eq!(watchdog.observe(&config, minute * 60, rss), Check { action: Action::None, growth_mb_per_hour: None });
+        }
+
+        // Half a MiB a minute, 30 MiB an hour
+        let mut watchdog = Watchdog::default();
+        let logged: Vec<u64> = (0..3 * TREND_SAMPLES as u64)
+            .filter(|minute| watchdog.observe(&config, minute * 60, 50 * MIB + minute * MIB / 2).growth_mb_per_hour.is_some())
+            .collect();
+        assert_eq!(logged, vec![59, 118, 177]);
+    }
+}
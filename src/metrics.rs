@@ -0,0 +1,95 @@
+// Process-local runtime counters exposed through `/api/metrics`, printed on
+// shutdown and kept in `runtime_stats`. Nothing here leaves the machine unless
+// the Influx exporter is configured.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default)]
+pub struct RuntimeMetrics {
+    pub limited_process_access: AtomicU64,
+    pub denied_process_access: AtomicU64,
+    pub events_processed: AtomicU64, // Polls that found a foreground window
+    pub focus_changes: AtomicU64,
+    pub flushes: AtomicU64,
+    pub flush_errors: AtomicU64,
+    pub loop_iterations: AtomicU64,
+    pub loop_latency_micros: AtomicU64, // Total time spent doing work per iteration
+    pub url_reads: AtomicU64, // Browser URL extractions, the rest came from the cache
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub limited_process_access: u64,
+    pub denied_process_access: u64,
+    pub events_processed: u64,
+    pub focus_changes: u64,
+    pub flushes: u64,
+    pub flush_errors: u64,
+    pub loop_iterations: u64,
+    pub avg_loop_latency_micros: u64,
+    #[serde(default)]
+    pub url_reads: u64,
+    pub db_size_start: u64,
+    pub db_size_now: u64,
+}
+
+impl RuntimeMetrics {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_loop(&self, latency: Duration) {
+        Self::increment(&self.loop_iterations);
+        self.loop_latency_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, db_size_start: u64, db_size_now: u64) -> MetricsSnapshot {
+        let loop_iterations = self.loop_iterations.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            limited_process_access: self.limited_process_access.load(Ordering::Relaxed),
+            denied_process_access: self.denied_process_access.load(Ordering::Relaxed),
+            events_processed: self.events_processed.load(Ordering::Relaxed),
+            focus_changes: self.focus_changes.load(Ordering::Relaxed),
+            flushes: self.flushes.load(Ordering::Relaxed),
+            flush_errors: self.flush_errors.load(Ordering::Relaxed),
+            loop_iterations,
+            avg_loop_latency_micros: self
+                .loop_latency_micros
+                .load(Ordering::Relaxed)
+                .checked_div(loop_iterations)
+                .unwrap_or(0),
+            url_reads: self.url_reads.load(Ordering::Relaxed),
+            db_size_start,
+            db_size_now,
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    pub fn print_summary(&self, runtime_secs: u64) {
+        println!("Runtime: {}s", runtime_secs);
+        println!("  events processed:      {}", self.events_processed);
+        println!("  focus changes:         {}", self.focus_changes);
+        println!("  flushes:               {} ({} failed)", self.flushes, self.flush_errors);
+        println!(
+            "  process access:        {} limited, {} denied",
+            self.limited_process_access, self.denied_process_access
+        );
+        println!(
+            "  average loop latency:  {:.2} ms over {} iterations",
+            self.avg_loop_latency_micros as f64 / 1000.0,
+            self.loop_iterations
+        );
+        println!("  browser url reads:     {}", self.url_reads);
+        println!(
+            "  database size:         {} -> {} bytes ({:+})",
+            self.db_size_start,
+            self.db_size_now,
+            self.db_size_now as i64 - self.db_size_start as i64
+        );
+    }
+}
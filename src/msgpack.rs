@@ -0,0 +1,123 @@
+// Minimal MessagePack encoder for JSON values, for `Accept: application/msgpack`.
+
+use serde_json::Value;
+
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write(&mut out, value);
+    out
+}
+
+fn write(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(unsigned), _) => write_unsigned(out, unsigned),
+            (None, Some(signed)) => write_signed(out, signed),
+            _ => {
+                out.push(0xcb);
+                out.extend_from_slice(&number.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        },
+        Value::String(text) => {
+            let len = text.len();
+            match len {
+                0..=31 => out.push(0xa0 | len as u8),
+                32..=0xff => out.extend_from_slice(&[0xd9, len as u8]),
+                0x100..=0xffff => {
+                    out.push(0xda);
+                    out.extend_from_slice(&(len as u16).to_be_bytes());
+                }
+                _ => {
+                    out.push(0xdb);
+                    out.extend_from_slice(&(len as u32).to_be_bytes());
+                }
+            }
+            out.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_header(out, items.len(), 0x90, 0xdc);
+            for item in items {
+                write(out, item);
+            }
+        }
+        Value::Object(map) => {
+            write_header(out, map.len(), 0x80, 0xde);
+            for (key, item) in map {
+                write(out, &Value::String(key.clone()));
+                write(out, item);
+            }
+        }
+    }
+}
+
+fn write_unsigned(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0x7f => out.push(value as u8),
+        0x80..=0xff => out.extend_from_slice(&[0xcc, value as u8]),
+        0x100..=0xffff => {
+            out.push(0xcd);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xcf);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+// Only reached for negative values; the others are unsigned
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    if value >= -32 {
+        out.push(value as i8 as u8);
+    } else if value >= i8::MIN as i64 {
+        out.extend_from_slice(&[0xd0, value as i8 as u8]);
+    } else if value >= i16::MIN as i64 {
+        out.push(0xd1);
+        out.extend_from_slice(&(value as i16).to_be_bytes());
+    } else if value >= i32::MIN as i64 {
+        out.push(0xd2);
+        out.extend_from_slice(&(value as i32).to_be_bytes());
+    } else {
+        out.push(0xd3);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+// Arrays and maps: fix form up to 15 entries, then 16 or 32-bit lengths
+fn write_header(out: &mut Vec<u8>, len: usize, fix: u8, sixteen: u8) {
+    match len {
+        0..=15 => out.push(fix | len as u8),
+        16..=0xffff => {
+            out.push(sixteen);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(sixteen + 1);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn encodes_each_type_in_its_smallest_form() {
+        assert_eq!(encode(&json!({"a": [1, -1, null, true]})), vec![0x81, 0xa1, b'a', 0x94, 0x01, 0xff, 0xc0, 0xc3]);
+        assert_eq!(encode(&json!(200)), vec![0xcc, 200]);
+        assert_eq!(encode(&json!(70_000)), vec![0xce, 0x00, 0x01, 0x11, 0x70]);
+        assert_eq!(encode(&json!(-200)), vec![0xd1, 0xff, 0x38]);
+        assert_eq!(encode(&json!(1.5)), [vec![0xcb], 1.5f64.to_be_bytes().to_vec()].concat());
+        assert_eq!(&encode(&json!("x".repeat(40)))[..2], &[0xd9, 40]);
+        assert_eq!(&encode(&json!(vec![0; 20]))[..3], &[0xdc, 0x00, 20]);
+    }
+}
@@ -0,0 +1,300 @@
+// Notification channels behind one `Notifier` trait.
+//
+// Producers only build a `Notification` and hand it to the `Dispatcher`; which
+// channels receive it is decided by the `routes` in the config, so adding a
+// channel never touches the code that raises events. The built-in channels in
+// `channels` are left out of builds without the `notifications` feature.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::logs;
+use crate::outbound::Outbound;
+
+#[cfg(feature = "notifications")]
+mod channels;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    GoalBreach,
+    Error,
+    Info,
+    DeviceOffline,
+    Digest, // Daily weekly-goal progress
+    #[serde(other)]
+    Unknown, // Kinds from newer configs; routes listing them never fire
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub kind: EventKind,
+    pub title: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+/// A file sent along with a notification, by the channels that can carry one.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub file_name: String,
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 5] = [Self::GoalBreach, Self::Error, Self::Info, Self::DeviceOffline, Self::Digest];
+}
+
+/// Body of `flat` webhooks: top-level fields only, for Zapier-style triggers.
+#[derive(Debug, Clone, Serialize)]
+pub struct FlatEvent {
+    pub event: EventKind,
+    pub title: String,
+    pub message: String,
+    pub timestamp: u64,
+    pub time: String, // Local RFC 3339, for tools that don't parse Unix time
+    pub test: bool, // Sent from POST /api/notifications/test
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    #[default]
+    Notification, // The `Notification` as is
+    Flat,
+    Ifttt, // `value1`..`value3`, all IFTTT's Maker webhooks pass on
+}
+
+/// What a webhook in `format` posts for `notification`.
+pub fn webhook_payload(format: WebhookFormat, notification: &Notification, test: bool) -> Value {
+    let event = serde_json::to_value(notification.kind).unwrap_or_default();
+    match format {
+        WebhookFormat::Notification => serde_json::to_value(notification).unwrap_or_default(),
+        WebhookFormat::Flat => serde_json::to_value(FlatEvent {
+            event: notification.kind,
+            title: notification.title.clone(),
+            message: notification.message.clone(),
+            timestamp: notification.timestamp,
+            time: Local
+                .timestamp_opt(notification.timestamp as i64, 0)
+                .earliest()
+                .map(|time| time.to_rfc3339())
+                .unwrap_or_default(),
+            test,
+        })
+        .unwrap_or_default(),
+        WebhookFormat::Ifttt => json!({ "value1": notification.title, "value2": notification.message, "value3": event }),
+    }
+}
+
+pub trait Notifier: Send + Sync {
+    fn send(&self, notification: &Notification) -> Result<(), String>;
+
+    /// A sample sent on request; channels that mark test payloads override it.
+    fn send_test(&self, notification: &Notification) -> Result<(), String> {
+        self.send(notification)
+    }
+
+    /// With a file; channels that can't attach one send the notification alone.
+    fn send_with(&self, notification: &Notification, _attachment: &Attachment) -> Result<(), String> {
+        self.send(notification)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelConfig {
+    Toast,
+    Webhook {
+        url: String,
+        #[serde(default)]
+        format: WebhookFormat,
+        #[serde(default)]
+        events: Vec<EventKind>, // Sent here without a route
+    },
+    Slack {
+        webhook_url: String,
+    },
+    Email {
+        smtp_url: String, // e.g. smtps://smtp.example.com:465
+        from: String,
+        to: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    Mqtt {
+        host: String,
+        #[serde(default = "default_mqtt_port")]
+        port: u16,
+        topic: String,
+    },
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    pub events: Vec<EventKind>,
+    pub channels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub channels: BTreeMap<String, ChannelConfig>, // Channel name -> settings
+    pub routes: Vec<Route>,
+}
+
+#[derive(Default)]
+pub struct Dispatcher {
+    notifiers: BTreeMap<String, Arc<dyn Notifier>>,
+    routes: Vec<Route>,
+    outbound: Arc<Outbound>, // Retries failed sends and keeps what never got through
+}
+
+impl Dispatcher {
+    #[cfg(feature = "notifications")]
+    pub fn from_config(config: &NotificationConfig, outbound: Arc<Outbound>) -> Self {
+        let mut dispatcher = Self { notifiers: BTreeMap::new(), routes: config.routes.clone(), outbound };
+        for (name, channel) in &config.channels {
+            dispatcher.register(name, channels::build(channel));
+            if let ChannelConfig::Webhook { events, .. } = channel {
+                if !events.is_empty() {
+                    dispatcher.routes.push(Route { events: events.clone(), channels: vec![name.clone()] });
+                }
+            }
+        }
+        dispatcher
+    }
+
+    #[cfg(not(feature = "notifications"))]
+    pub fn from_config(config: &NotificationConfig, outbound: Arc<Outbound>) -> Self {
+        if !config.channels.is_empty() {
+            logs::error!("Ignoring notification channels: built without the `notifications` feature");
+        }
+        Self { outbound, ..Self::default() }
+    }
+
+    /// Adds or replaces a channel; routes refer to it by `name`.
+    pub fn register(&mut self, name: &str, notifier: Arc<dyn Notifier>) {
+        self.notifiers.insert(name.to_string(), notifier);
+    }
+
+    /// Channel names routed for `kind`, deduplicated, in route order.
+    pub fn channels_for(&self, kind: EventKind) -> Vec<&str> {
+        let mut channels: Vec<&str> = Vec::new();
+        for route in self.routes.iter().filter(|route| route.events.contains(&kind)) {
+            for channel in &route.channels {
+                if !channels.contains(&channel.as_str()) {
+                    channels.push(channel);
+                }
+            }
+        }
+        channels
+    }
+
+    /// Sends a sample `kind` event to `channel` right away, routes or not.
+    pub fn test_fire(&self, channel: &str, kind: EventKind, timestamp: u64) -> Result<Notification, String> {
+        let notifier = self.notifiers.get(channel).ok_or_else(|| format!("No notification channel '{}'", channel))?;
+        let notification = Notification {
+            kind,
+            title: "sysmonitor test event".to_string(),
+            message: format!("A sample {} event, sent to check the '{}' channel", json!(kind).as_str().unwrap_or("info"), channel),
+            timestamp,
+        };
+        notifier.send_test(&notification)?;
+        Ok(notification)
+    }
+
+    /// Delivers on background threads so slow channels never stall tracking,
+    /// retrying through the outbound breakers.
+    pub fn notify(&self, notification: Notification) {
+        self.dispatch(notification, None);
+    }
+
+    pub fn notify_with(&self, notification: Notification, attachment: Attachment) {
+        self.dispatch(notification, Some(Arc::new(attachment)));
+    }
+
+    fn dispatch(&self, notification: Notification, attachment: Option<Arc<Attachment>>) {
+        for channel in self.channels_for(notification.kind) {
+            let Some(notifier) = self.notifiers.get(channel).cloned() else {
+                logs::error!("Notification route refers to unknown channel '{}'", channel);
+                continue;
+            };
+            let (notification, attachment) = (notification.clone(), attachment.clone());
+            let (channel, outbound) = (channel.to_string(), self.outbound.clone());
+            std::thread::spawn(move || {
+                let payload = serde_json::to_value(&notification).unwrap_or_default();
+                let sent = outbound.deliver(&format!("notification:{}", channel), &payload, || match attachment.as_deref() {
+                    Some(attachment) => notifier.send_with(&notification, attachment),
+                    None => notifier.send(&notification),
+                });
+                if let Err(e) = sent {
+                    logs::error!("Notification via '{}' failed: {}", channel, e);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "notifications"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_select_channels_per_event() {
+        let config: NotificationConfig = serde_json::from_str(
+            r#"{
+                "channels": {
+                    "desktop": { "type": "toast" },
+                    "team": { "type": "slack", "webhook_url": "https://hooks.slack.com/x" },
+                    "mail": { "type": "email", "smtp_url": "smtps://mail", "from": "a@x", "to": "b@x" }
+                },
+                "routes": [
+                    { "events": ["goal_breach"], "channels": ["desktop", "team"] },
+                    { "events": ["error", "goal_breach"], "channels": ["mail", "desktop"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let dispatcher = Dispatcher::from_config(&config, Arc::default());
+        assert_eq!(dispatcher.channels_for(EventKind::GoalBreach), vec!["desktop", "team", "mail"]);
+        assert_eq!(dispatcher.channels_for(EventKind::Error), vec!["mail", "desktop"]);
+        assert!(dispatcher.channels_for(EventKind::Info).is_empty());
+    }
+
+    #[test]
+    fn webhooks_pick_their_payload_shape_and_events() {
+        let config: NotificationConfig = serde_json::from_str(
+            r#"{
+                "channels": {
+                    "zap": { "type": "webhook", "url": "https://hooks.zapier.com/x", "format": "flat", "events": ["digest"] },
+                    "hooks": { "type": "webhook", "url": "https://example.com/x" }
+                },
+                "routes": [{ "events": ["digest"], "channels": ["hooks"] }]
+            }"#,
+        )
+        .unwrap();
+        let dispatcher = Dispatcher::from_config(&config, Arc::default());
+        assert_eq!(dispatcher.channels_for(EventKind::Digest), vec!["hooks", "zap"]);
+        assert!(dispatcher.test_fire("nope", EventKind::Info, 0).is_err());
+
+        let notification = Notification { kind: EventKind::GoalBreach, title: "Over".into(), message: "2h".into(), timestamp: 1_760_000_000 };
+        let flat = webhook_payload(WebhookFormat::Flat, &notification, true);
+        assert_eq!((&flat["event"], &flat["title"], &flat["test"]), (&json!("goal_breach"), &json!("Over"), &json!(true)));
+        assert!(flat["time"].as_str().unwrap().starts_with("2025-10-"));
+        assert_eq!(
+            webhook_payload(WebhookFormat::Ifttt, &notification, false),
+            json!({ "value1": "Over", "value2": "2h", "value3": "goal_breach" })
+        );
+        assert_eq!(webhook_payload(WebhookFormat::Notification, &notification, false)["kind"], "goal_breach");
+    }
+}
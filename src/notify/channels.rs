@@ -0,0 +1,203 @@
+// Built-in channels. They shell out to standard tools (`curl`, `notify-send`,
+// `mosquitto_pub`, PowerShell) rather than pulling in HTTP, SMTP and MQTT clients.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use super::{webhook_payload, Attachment, ChannelConfig, Notification, Notifier, WebhookFormat};
+use crate::child;
+use crate::curl::Secrets;
+use crate::units::DurationSecs;
+
+const SEND_TIMEOUT: DurationSecs = DurationSecs::secs(90); // Beyond curl's own `--max-time`, for a tool that hangs anyway
+
+fn run_with_stdin(program: &str, args: &[&str], input: &[u8]) -> Result<(), String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", program, e))?;
+    // Written on the side, so a tool that stops reading can't hold this up past the timeout
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.to_vec();
+        std::thread::spawn(move || stdin.write_all(&input));
+    }
+    let output = child::wait(child, program, SEND_TIMEOUT)?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+struct ToastNotifier;
+
+impl Notifier for ToastNotifier {
+    #[cfg(windows)]
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        // Text goes through the environment so titles cannot inject script
+        const SCRIPT: &str = "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+            $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+            $text = $xml.GetElementsByTagName('text'); \
+            $text.Item(0).AppendChild($xml.CreateTextNode($env:SYSMONITOR_TITLE)) > $null; \
+            $text.Item(1).AppendChild($xml.CreateTextNode($env:SYSMONITOR_MESSAGE)) > $null; \
+            [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('sysmonitor').Show([Windows.UI.Notifications.ToastNotification]::new($xml))";
+        let status = Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+            .env("SYSMONITOR_TITLE", &notification.title)
+            .env("SYSMONITOR_MESSAGE", &notification.message)
+            .status()
+            .map_err(|e| format!("could not run powershell: {}", e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err("powershell toast failed".to_string())
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        let status = Command::new("notify-send")
+            .args(["--app-name=sysmonitor", &notification.title, &notification.message])
+            .status()
+            .map_err(|e| format!("could not run notify-send: {}", e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err("notify-send failed".to_string())
+        }
+    }
+}
+
+struct WebhookNotifier {
+    url: String,
+    format: WebhookFormat,
+}
+
+// The URL goes in the config file: for Slack and most webhooks it is the secret
+fn post_json(url: &str, body: &serde_json::Value) -> Result<(), String> {
+    let secrets = Secrets::default().url(url).write()?;
+    let mut args = vec!["-sS", "-f", "--connect-timeout", "10", "--max-time", "30", "-X", "POST", "-H", "Content-Type: application/json", "--data-binary", "@-"];
+    let secret_args = secrets.args();
+    args.extend(secret_args.iter().map(String::as_str));
+    run_with_stdin("curl", &args, body.to_string().as_bytes())
+}
+
+impl Notifier for WebhookNotifier {
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        post_json(&self.url, &webhook_payload(self.format, notification, false))
+    }
+
+    fn send_test(&self, notification: &Notification) -> Result<(), String> {
+        post_json(&self.url, &webhook_payload(self.format, notification, true))
+    }
+}
+
+struct SlackNotifier {
+    webhook_url: String,
+}
+
+impl Notifier for SlackNotifier {
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        let text = format!("*{}*\n{}", notification.title, notification.message);
+        post_json(&self.webhook_url, &serde_json::json!({ "text": text }))
+    }
+}
+
+struct EmailNotifier {
+    smtp_url: String,
+    from: String,
+    to: String,
+    credentials: Option<String>, // `user:password` for curl
+}
+
+// MIME base64, in lines of 76
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for (index, chunk) in data.chunks(3).enumerate() {
+        if index > 0 && index % 19 == 0 {
+            out.push_str("\r\n");
+        }
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            out.push(if i <= chunk.len() { ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char } else { '=' });
+        }
+    }
+    out
+}
+
+impl EmailNotifier {
+    fn deliver(&self, mail: &str) -> Result<(), String> {
+        let secrets = self.credentials.as_deref().map_or_else(Secrets::default, |credentials| Secrets::default().user(credentials)).write()?;
+        let secret_args = secrets.args();
+        // Generous enough for a PDF attachment over a slow uplink
+        let mut args = vec![
+            "-sS", "--connect-timeout", "10", "--max-time", "60", "--url", &self.smtp_url, "--mail-from", &self.from, "--mail-rcpt", &self.to,
+            "--upload-file", "-",
+        ];
+        args.extend(secret_args.iter().map(String::as_str));
+        run_with_stdin("curl", &args, mail.as_bytes())
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        self.deliver(&format!(
+            "From: {}\r\nTo: {}\r\nSubject: [sysmonitor] {}\r\n\r\n{}\r\n",
+            self.from, self.to, notification.title, notification.message
+        ))
+    }
+
+    fn send_with(&self, notification: &Notification, attachment: &Attachment) -> Result<(), String> {
+        const BOUNDARY: &str = "sysmonitor-attachment";
+        self.deliver(&format!(
+            "From: {from}\r\nTo: {to}\r\nSubject: [sysmonitor] {subject}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; boundary=\"{b}\"\r\n\r\n\
+             --{b}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{message}\r\n\
+             --{b}\r\nContent-Type: {kind}\r\nContent-Transfer-Encoding: base64\r\n\
+             Content-Disposition: attachment; filename=\"{file}\"\r\n\r\n{data}\r\n--{b}--\r\n",
+            from = self.from,
+            to = self.to,
+            subject = notification.title,
+            b = BOUNDARY,
+            message = notification.message,
+            kind = attachment.content_type,
+            file = attachment.file_name,
+            data = base64(&attachment.data),
+        ))
+    }
+}
+
+struct MqttNotifier {
+    host: String,
+    port: u16,
+    topic: String,
+}
+
+impl Notifier for MqttNotifier {
+    fn send(&self, notification: &Notification) -> Result<(), String> {
+        let payload = serde_json::to_vec(notification).map_err(|e| e.to_string())?;
+        let port = self.port.to_string();
+        // `-s` publishes stdin as a single message
+        run_with_stdin("mosquitto_pub", &["-h", &self.host, "-p", &port, "-t", &self.topic, "-s"], &payload)
+    }
+}
+
+pub fn build(config: &ChannelConfig) -> Arc<dyn Notifier> {
+    match config.clone() {
+        ChannelConfig::Toast => Arc::new(ToastNotifier),
+        ChannelConfig::Webhook { url, format, .. } => Arc::new(WebhookNotifier { url, format }),
+        ChannelConfig::Slack { webhook_url } => Arc::new(SlackNotifier { webhook_url }),
+        ChannelConfig::Email { smtp_url, from, to, username, password } => Arc::new(EmailNotifier {
+            smtp_url,
+            from,
+            to,
+            credentials: username.map(|user| format!("{}:{}", user, password.unwrap_or_default())),
+        }),
+        ChannelConfig::Mqtt { host, port, topic } => Arc::new(MqttNotifier { host, port, topic }),
+    }
+}
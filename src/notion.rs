@@ -0,0 +1,277 @@
+// Daily summaries as rows of a Notion database.
+//
+// Each day is one page keyed by its date property: the first push looks the
+// page up by date and creates it if there is none, later pushes update it, so
+// re-running a sync never duplicates rows. `notion_pages` remembers the page
+// and what was last sent, so unchanged days cost no requests. Like the other
+// integrations this goes through `curl`.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use chrono::{Days, NaiveDate};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::assignments;
+use crate::curl::Secrets;
+use crate::categories::{CategoryConfig, Productivity, UNCATEGORIZED};
+use crate::reports;
+
+const API: &str = "https://api.notion.com/v1";
+const API_VERSION: &str = "2022-06-28";
+const MAX_SYNC_DAYS: u64 = 90;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotionProperties {
+    pub title: String,
+    pub date: String,
+    pub focus_hours: String,
+    pub top_category: String,
+    pub score: String,
+}
+
+impl Default for NotionProperties {
+    fn default() -> Self {
+        Self {
+            title: "Name".to_string(),
+            date: "Date".to_string(),
+            focus_hours: "Focus hours".to_string(),
+            top_category: "Top category".to_string(),
+            score: "Score".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotionConfig {
+    pub token: Option<String>, // Internal integration secret; unset disables the sync
+    pub database_id: Option<String>,
+    pub days: u64, // Recent days pushed on each sync, today included
+    pub properties: NotionProperties, // Column names in the database
+}
+
+impl Default for NotionConfig {
+    fn default() -> Self {
+        Self { token: None, database_id: None, days: 7, properties: NotionProperties::default() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DaySummary {
+    pub date: NaiveDate,
+    pub focus_secs: u64, // Time in productive categories
+    pub top_category: Option<String>,
+    pub score: Option<u64>, // Focus share of focus and distracting time, 0-100
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notion_pages (
+            day TEXT PRIMARY KEY,
+            page_id TEXT NOT NULL,
+            pushed_hash TEXT NOT NULL,
+            pushed_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Summary of `date` from its category totals, largest first.
+pub fn summarize(date: NaiveDate, totals: &[assignments::CategoryTotal], categories: &CategoryConfig) -> DaySummary {
+    let seconds_of = |productivity: Productivity| -> u64 {
+        totals
+            .iter()
+            .filter(|total| categories.category_productivity(&total.category) == productivity)
+            .map(|total| total.seconds)
+            .sum()
+    };
+    let (focus_secs, distracting_secs) = (seconds_of(Productivity::Productive), seconds_of(Productivity::Distracting));
+    DaySummary {
+        date,
+        focus_secs,
+        top_category: totals.iter().find(|total| total.category != UNCATEGORIZED).map(|total| total.category.clone()),
+        score: (focus_secs + distracting_secs > 0)
+            .then(|| (focus_secs as f64 * 100.0 / (focus_secs + distracting_secs) as f64).round() as u64),
+    }
+}
+
+/// Page properties of a summary, in the shapes Notion expects per column type.
+pub fn page_properties(summary: &DaySummary, names: &NotionProperties) -> Value {
+    let date = summary.date.format("%Y-%m-%d").to_string();
+    let mut properties = serde_json::Map::new();
+    properties.insert(names.title.clone(), json!({ "title": [{ "text": { "content": date } }] }));
+    properties.insert(names.date.clone(), json!({ "date": { "start": date } }));
+    properties.insert(names.focus_hours.clone(), json!({ "number": (summary.focus_secs as f64 / 36.0).round() / 100.0 }));
+    let top_category = summary.top_category.as_deref().map(|category| json!([{ "text": { "content": category } }]));
+    properties.insert(names.top_category.clone(), json!({ "rich_text": top_category.unwrap_or_else(|| json!([])) }));
+    properties.insert(names.score.clone(), json!({ "number": summary.score }));
+    Value::Object(properties)
+}
+
+fn call(method: &str, path: &str, token: &str, body: &Value) -> Result<Value, String> {
+    let secrets = Secrets::default().header(&format!("Authorization: Bearer {}", token)).write()?;
+    let version = format!("Notion-Version: {}", API_VERSION);
+    let url = format!("{}/{}", API, path);
+    let mut child = Command::new("curl")
+        .args(["-sS", "--connect-timeout", "10", "--max-time", "30", "-X", method])
+        .args(secrets.args())
+        .args(["-H", &version, "-H", "Content-Type: application/json", "--data-binary", "@-", &url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body.to_string().as_bytes()).map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("curl failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let response: Value = serde_json::from_slice(&output.stdout).map_err(|_| "Notion did not answer with JSON".to_string())?;
+    if response["object"] == "error" {
+        return Err(format!("Notion refused: {}", response["message"].as_str().unwrap_or("unknown error")));
+    }
+    Ok(response)
+}
+
+/// When a page was last created or updated.
+pub fn last_pushed_at(conn: &Connection) -> SqlResult<Option<u64>> {
+    let at: Option<i64> = conn.query_row("SELECT MAX(pushed_at) FROM notion_pages", [], |row| row.get(0))?;
+    Ok(at.map(|at| at.max(0) as u64))
+}
+
+fn stored_page(conn: &Connection, day: &str) -> SqlResult<Option<(String, String)>> {
+    conn.query_row("SELECT page_id, pushed_hash FROM notion_pages WHERE day = ?1", params![day], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })
+    .optional()
+}
+
+// Creates or updates the day's page; false if it was already up to date
+fn upsert(
+    conn: &Connection,
+    config: &NotionConfig,
+    token: &str,
+    database_id: &str,
+    summary: &DaySummary,
+    now: u64,
+) -> Result<bool, String> {
+    let day = summary.date.format("%Y-%m-%d").to_string();
+    let properties = page_properties(summary, &config.properties);
+    let hash: String =
+        Sha256::digest(properties.to_string().as_bytes()).iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+    let stored = stored_page(conn, &day).map_err(|e| e.to_string())?;
+    if stored.as_ref().is_some_and(|(_, pushed_hash)| *pushed_hash == hash) {
+        return Ok(false);
+    }
+
+    let page_id = match stored {
+        Some((page_id, _)) => Some(page_id),
+        // Pages made by an earlier install or by hand count too
+        None => {
+            let filter = json!({ "filter": { "property": config.properties.date, "date": { "equals": day } }, "page_size": 1 });
+            let found = call("POST", &format!("databases/{}/query", database_id), token, &filter)?;
+            found["results"][0]["id"].as_str().map(str::to_string)
+        }
+    };
+    let page_id = match page_id {
+        Some(page_id) => {
+            call("PATCH", &format!("pages/{}", page_id), token, &json!({ "properties": properties }))?;
+            page_id
+        }
+        None => {
+            let page = json!({ "parent": { "database_id": database_id }, "properties": properties });
+            let created = call("POST", "pages", token, &page)?;
+            created["id"].as_str().ok_or("Notion did not return the new page's id")?.to_string()
+        }
+    };
+    conn.execute(
+        "INSERT INTO notion_pages (day, page_id, pushed_hash, pushed_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(day) DO UPDATE SET page_id = excluded.page_id, pushed_hash = excluded.pushed_hash, pushed_at = excluded.pushed_at",
+        params![day, page_id, hash, now],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Pushes the `days` days up to `today`; the number of pages created or updated.
+pub fn sync(
+    conn: &Connection,
+    config: &NotionConfig,
+    categories: &CategoryConfig,
+    today: NaiveDate,
+    days: u64,
+    now: u64,
+) -> Result<usize, String> {
+    let (Some(token), Some(database_id)) = (config.token.as_deref(), config.database_id.as_deref()) else {
+        return Ok(0);
+    };
+    let mut pushed = 0;
+    for offset in (0..days.clamp(1, MAX_SYNC_DAYS)).rev() {
+        let date = today - Days::new(offset);
+        let (since, until) = reports::day_bounds(date);
+        let totals = assignments::totals(conn, categories, since.max(0) as u64, until.max(0) as u64).map_err(|e| e.to_string())?;
+        if totals.categories.is_empty() {
+            continue;
+        }
+        if upsert(conn, config, token, database_id, &summarize(date, &totals.categories, categories), now)? {
+            pushed += 1;
+        }
+    }
+    Ok(pushed)
+}
+
+/// `sysmonitor notion sync [--days N]`
+pub fn run_command(
+    conn: &Connection,
+    config: &NotionConfig,
+    categories: &CategoryConfig,
+    args: &[String],
+    now: u64,
+) -> Result<(), String> {
+    if args.first().map(String::as_str) != Some("sync") {
+        return Err("Usage: sysmonitor notion sync [--days N]".to_string());
+    }
+    if config.token.is_none() || config.database_id.is_none() {
+        return Err("Set notion.token and notion.database_id in the config".to_string());
+    }
+    let days = match args.iter().position(|arg| arg == "--days").and_then(|index| args.get(index + 1)) {
+        Some(days) => days.parse().map_err(|_| format!("--days must be a number, got '{}'", days))?,
+        None => config.days,
+    };
+    let pushed = sync(conn, config, categories, reports::local_date(now), days, now)?;
+    println!("Pushed {} day(s) to Notion", pushed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assignments::CategoryTotal;
+
+    #[test]
+    fn summaries_become_database_properties() {
+        let categories: CategoryConfig = serde_json::from_value(json!({
+            "productivity": { "Dev": "productive", "Social": "distracting" }
+        }))
+        .unwrap();
+        let total = |category: &str, seconds| CategoryTotal { category: category.into(), seconds };
+        let date = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        let summary = summarize(date, &[total(UNCATEGORIZED, 9000), total("Dev", 5400), total("Social", 1800)], &categories);
+        assert_eq!(summary, DaySummary { date, focus_secs: 5400, top_category: Some("Dev".into()), score: Some(75) });
+
+        let properties = page_properties(&summary, &NotionProperties::default());
+        assert_eq!(properties["Date"], json!({ "date": { "start": "2026-10-14" } }));
+        assert_eq!(properties["Focus hours"], json!({ "number": 1.5 }));
+        assert_eq!(properties["Top category"]["rich_text"][0]["text"]["content"], "Dev");
+        assert_eq!(properties["Score"], json!({ "number": 75 }));
+        assert_eq!(summarize(date, &[], &categories).score, None);
+    }
+}
@@ -0,0 +1,162 @@
+// Where an app's executable lives: local disk, removable media or a network share.
+//
+// Classified from the resolved image path whenever an app is seen with a new
+// path, stored in the `apps` table and attached to the per-app rows of reports.
+// Windows asks `GetDriveTypeW` about the path's drive; elsewhere the mount the
+// path is on decides.
+
+use std::collections::HashMap;
+
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppOrigin {
+    Local,
+    Removable,
+    Network,
+    Unknown,
+}
+
+impl AppOrigin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Removable => "removable",
+            Self::Network => "network",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        [Self::Local, Self::Removable, Self::Network, Self::Unknown].into_iter().find(|origin| origin.as_str() == text)
+    }
+}
+
+// `GetDriveTypeW` results, from WinBase.h
+#[cfg(any(windows, test))]
+const DRIVE_REMOVABLE: u32 = 2;
+#[cfg(any(windows, test))]
+const DRIVE_FIXED: u32 = 3;
+#[cfg(any(windows, test))]
+const DRIVE_REMOTE: u32 = 4;
+#[cfg(any(windows, test))]
+const DRIVE_CDROM: u32 = 5;
+#[cfg(any(windows, test))]
+const DRIVE_RAMDISK: u32 = 6;
+
+/// Origin of a Win32 path; `drive_type` answers `GetDriveTypeW` for a root like `C:\`.
+#[cfg(any(windows, test))]
+fn windows_origin(path: &str, drive_type: impl Fn(&str) -> u32) -> AppOrigin {
+    let path = path.strip_prefix(r"\\?\").unwrap_or(path);
+    if let Some(share) = path.strip_prefix(r"UNC\").or_else(|| path.strip_prefix(r"\\")) {
+        return if share.is_empty() { AppOrigin::Unknown } else { AppOrigin::Network };
+    }
+    let drive = match path.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => format!("{}:\\", letter.to_ascii_uppercase() as char),
+        _ => return AppOrigin::Unknown,
+    };
+    match drive_type(&drive) {
+        DRIVE_FIXED | DRIVE_RAMDISK => AppOrigin::Local,
+        DRIVE_REMOVABLE | DRIVE_CDROM => AppOrigin::Removable,
+        DRIVE_REMOTE => AppOrigin::Network,
+        _ => AppOrigin::Unknown,
+    }
+}
+
+#[cfg(any(not(windows), test))]
+const NETWORK_FILESYSTEMS: [&str; 8] = ["nfs", "nfs4", "cifs", "smb3", "smbfs", "fuse.sshfs", "9p", "davfs"];
+#[cfg(any(not(windows), test))]
+const REMOVABLE_MOUNT_ROOTS: [&str; 3] = ["/media/", "/run/media/", "/mnt/usb"];
+
+/// Origin of a path from the mount table (`/proc/self/mounts` format): the
+/// longest mount point containing it decides.
+#[cfg(any(not(windows), test))]
+fn mount_origin(path: &str, mounts: &str) -> AppOrigin {
+    let contains = |mount_point: &str| {
+        mount_point == "/" || path == mount_point || path.starts_with(&format!("{}/", mount_point.trim_end_matches('/')))
+    };
+    let mount = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, mount_point, kind) = (fields.next()?, fields.next()?, fields.next()?);
+            Some((mount_point.replace("\\040", " "), kind))
+        })
+        .filter(|(mount_point, _)| contains(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len());
+    match mount {
+        Some((_, kind)) if NETWORK_FILESYSTEMS.contains(&kind) => AppOrigin::Network,
+        Some((mount_point, _)) if REMOVABLE_MOUNT_ROOTS.iter().any(|root| mount_point.starts_with(root)) => AppOrigin::Removable,
+        Some(_) => AppOrigin::Local,
+        None => AppOrigin::Unknown,
+    }
+}
+
+#[cfg(windows)]
+pub fn classify(path: &str) -> AppOrigin {
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::GetDriveTypeW;
+
+    windows_origin(path, |root| {
+        let root: Vec<u16> = root.encode_utf16().chain(Some(0)).collect();
+        unsafe { GetDriveTypeW(PCWSTR(root.as_ptr())) }
+    })
+}
+
+#[cfg(not(windows))]
+pub fn classify(path: &str) -> AppOrigin {
+    match std::fs::read_to_string("/proc/self/mounts") {
+        Ok(mounts) => mount_origin(path, &mounts),
+        Err(_) => AppOrigin::Unknown,
+    }
+}
+
+/// Stored origin per app name from the `apps` table.
+pub fn by_app(conn: &Connection) -> SqlResult<HashMap<String, AppOrigin>> {
+    let mut stmt = conn.prepare("SELECT app_name, origin FROM apps WHERE origin IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+    let mut origins = HashMap::new();
+    for row in rows {
+        let (app_name, origin) = row?;
+        if let Some(origin) = AppOrigin::parse(&origin) {
+            origins.insert(app_name, origin);
+        }
+    }
+    Ok(origins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_paths_are_classified_by_drive() {
+        let drive_type = |root: &str| match root {
+            r"C:\" => DRIVE_FIXED,
+            r"E:\" => DRIVE_REMOVABLE,
+            r"Z:\" => DRIVE_REMOTE,
+            _ => 0,
+        };
+        assert_eq!(windows_origin(r"C:\Windows\notepad.exe", drive_type), AppOrigin::Local);
+        assert_eq!(windows_origin(r"e:\Portable\app.exe", drive_type), AppOrigin::Removable);
+        assert_eq!(windows_origin(r"Z:\tools\app.exe", drive_type), AppOrigin::Network);
+        assert_eq!(windows_origin(r"\\fileserver\apps\app.exe", drive_type), AppOrigin::Network);
+        assert_eq!(windows_origin(r"\\?\UNC\fileserver\apps\app.exe", drive_type), AppOrigin::Network);
+        assert_eq!(windows_origin(r"\\?\C:\long\app.exe", drive_type), AppOrigin::Local);
+        assert_eq!(windows_origin(r"\Device\HarddiskVolume9\app.exe", drive_type), AppOrigin::Unknown);
+    }
+
+    #[test]
+    fn unix_paths_are_classified_by_mount() {
+        let mounts = "/dev/nvme0n1p2 / ext4 rw 0 0\n\
+                      server:/apps /opt/shared nfs4 rw 0 0\n\
+                      /dev/sdb1 /media/me/USB\\040STICK vfat rw 0 0\n";
+        assert_eq!(mount_origin("/usr/bin/firefox", mounts), AppOrigin::Local);
+        assert_eq!(mount_origin("/opt/shared/bin/tool", mounts), AppOrigin::Network);
+        assert_eq!(mount_origin("/opt/sharedother/tool", mounts), AppOrigin::Local);
+        assert_eq!(mount_origin("/media/me/USB STICK/app", mounts), AppOrigin::Removable);
+        assert_eq!(mount_origin("/usr/bin/firefox", ""), AppOrigin::Unknown);
+    }
+}
@@ -0,0 +1,345 @@
+// Retries, backoff and circuit breaking for everything the tracker sends out.
+//
+// Notification channels, the Notion sync, InfluxDB and telemetry pushes and
+// the Home Assistant MQTT state all go through an `Outbound`, which keeps a
+// breaker per integration. A failure holds the integration back for a delay
+// that doubles with each failure in a row; after `failure_threshold` of them
+// the circuit opens and nothing is tried for `open_secs`, then one call is let
+// through to see whether it recovered. Notifications are retried up to
+// `max_attempts` times on their background thread and, when they still don't
+// get through, kept in `dead_letters` with the error. The syncs aren't, as they
+// pick up from the database on their next run anyway.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::logs;
+use crate::units::{DurationSecs, Timestamp};
+
+pub const MAX_DEAD_LETTERS: i64 = 1000;
+pub const LISTED_DEAD_LETTERS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutboundConfig {
+    pub max_attempts: u32, // Per notification, the first one included
+    pub base_delay_secs: u64, // Before the first retry, doubling after that
+    pub max_delay_secs: u64,
+    pub failure_threshold: u32, // Failures in a row that open the circuit
+    pub open_secs: u64, // How long an open circuit stays open
+}
+
+impl Default for OutboundConfig {
+    fn default() -> Self {
+        Self { max_attempts: 5, base_delay_secs: 2, max_delay_secs: 300, failure_threshold: 5, open_secs: 600 }
+    }
+}
+
+impl OutboundConfig {
+    /// The hold after `failures` failures in a row.
+    pub fn backoff(&self, failures: u32) -> DurationSecs {
+        let doublings = failures.saturating_sub(1).min(32);
+        DurationSecs::secs(self.base_delay_secs.saturating_mul(1 << doublings).min(self.max_delay_secs))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Retrying, // Failed lately, held back until `retry_at`
+    Open,
+    HalfOpen, // Open time is over; the next call decides
+}
+
+#[derive(Debug, Clone, Default)]
+struct Breaker {
+    failures: u32, // In a row
+    retry_at: Timestamp,
+    sent: u64,
+    failed: u64,
+    last_success: Option<Timestamp>,
+    last_failure: Option<Timestamp>,
+    last_error: Option<String>,
+}
+
+// Whether a call may go out now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Gate {
+    Go,
+    Wait(DurationSecs),
+    Open,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IntegrationStatus {
+    pub name: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<Timestamp>,
+    pub sent: u64,
+    pub failed: u64,
+    pub last_success: Option<Timestamp>,
+    pub last_failure: Option<Timestamp>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub integration: String,
+    pub payload: Value,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutboundStatus {
+    pub integrations: Vec<IntegrationStatus>, // By name; only those used since the start
+    pub dead_letters: u64,
+    pub recent_dead_letters: Vec<DeadLetter>, // Newest first
+}
+
+/// The breakers, shared by every integration.
+#[derive(Debug, Default)]
+pub struct Outbound {
+    config: OutboundConfig,
+    db_path: Option<String>, // Where dead letters go; without one they are only logged
+    breakers: Mutex<BTreeMap<String, Breaker>>,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS dead_letters (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            integration TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            error TEXT NOT NULL,
+            attempts INTEGER NOT NULL,
+            failed_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+impl Outbound {
+    pub fn new(config: &OutboundConfig, db_path: &str) -> Self {
+        Self { config: config.clone(), db_path: Some(db_path.to_string()), breakers: Mutex::new(BTreeMap::new()) }
+    }
+
+    fn gate(&self, name: &str, now: Timestamp) -> Gate {
+        let breakers = self.breakers.lock().unwrap();
+        match breakers.get(name) {
+            Some(breaker) if breaker.failures > 0 && now < breaker.retry_at => {
+                if breaker.failures >= self.config.failure_threshold {
+                    Gate::Open
+                } else {
+                    Gate::Wait(breaker.retry_at - now)
+                }
+            }
+            _ => Gate::Go,
+        }
+    }
+
+    fn record(&self, name: &str, now: Timestamp, error: Option<&str>) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(name.to_string()).or_default();
+        match error {
+            None => {
+                breaker.failures = 0;
+                breaker.sent += 1;
+                breaker.last_success = Some(now);
+            }
+            Some(error) => {
+                breaker.failures += 1;
+                breaker.failed += 1;
+                breaker.last_failure = Some(now);
+                breaker.last_error = Some(error.to_string());
+                let hold = if breaker.failures >= self.config.failure_threshold {
+                    if breaker.failures == self.config.failure_threshold {
+                        logs::error!("Circuit for '{}' opened after {} failures: {}", name, breaker.failures, error);
+                    }
+                    DurationSecs::secs(self.config.open_secs)
+                } else {
+                    self.config.backoff(breaker.failures)
+                };
+                breaker.retry_at = now + hold;
+            }
+        }
+    }
+
+    /// Runs `send` for `name` unless that is held back, which gives `Ok(None)`.
+    /// For calls that are simply made again later, such as periodic syncs.
+    pub fn call<T>(&self, name: &str, send: impl FnOnce() -> Result<T, String>) -> Result<Option<T>, String> {
+        let now = Timestamp::now();
+        if self.gate(name, now) != Gate::Go {
+            return Ok(None);
+        }
+        let result = send();
+        self.record(name, Timestamp::now(), result.as_ref().err().map(String::as_str));
+        result.map(Some)
+    }
+
+    /// Runs `send` for `name` until it succeeds, waiting out the backoff in
+    /// between, up to `max_attempts` times. What doesn't get through, or finds
+    /// the circuit open, is stored as a dead letter with `payload`. Blocks, so
+    /// it belongs on a background thread.
+    pub fn deliver(&self, name: &str, payload: &Value, send: impl Fn() -> Result<(), String>) -> Result<(), String> {
+        let mut attempts = 0;
+        let mut last_error = String::new();
+        while attempts < self.config.max_attempts.max(1) {
+            match self.gate(name, Timestamp::now()) {
+                Gate::Open => {
+                    last_error = format!("circuit open: {}", self.last_error(name).unwrap_or_default());
+                    break;
+                }
+                Gate::Wait(hold) => std::thread::sleep(hold.to_std()),
+                Gate::Go => {
+                    attempts += 1;
+                    let result = send();
+                    self.record(name, Timestamp::now(), result.as_ref().err().map(String::as_str));
+                    match result {
+                        Ok(()) => return Ok(()),
+                        Err(e) => last_error = e,
+                    }
+                }
+            }
+        }
+        self.dead_letter(name, payload, &last_error, attempts);
+        Err(last_error)
+    }
+
+    fn last_error(&self, name: &str) -> Option<String> {
+        self.breakers.lock().unwrap().get(name).and_then(|breaker| breaker.last_error.clone())
+    }
+
+    fn dead_letter(&self, name: &str, payload: &Value, error: &str, attempts: u32) {
+        let Some(db_path) = self.db_path.as_deref() else {
+            logs::error!("Dropping undeliverable '{}' message: {}", name, error);
+            return;
+        };
+        let stored = Connection::open(db_path).and_then(|conn| {
+            conn.execute(
+                "INSERT INTO dead_letters (integration, payload, error, attempts, failed_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![name, payload.to_string(), error, attempts, Timestamp::now()],
+            )?;
+            conn.execute("DELETE FROM dead_letters WHERE id <= (SELECT MAX(id) FROM dead_letters) - ?1", [MAX_DEAD_LETTERS])
+        });
+        if let Err(e) = stored {
+            logs::error!("Could not store dead letter for '{}': {}", name, e);
+        }
+    }
+
+    /// Each integration's breaker, and the dead letters.
+    pub fn status(&self, conn: &Connection, now: Timestamp) -> SqlResult<OutboundStatus> {
+        let integrations = self
+            .breakers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, breaker)| {
+                let held = breaker.failures > 0 && now < breaker.retry_at;
+                let state = match (breaker.failures, held) {
+                    (0, _) => CircuitState::Closed,
+                    (failures, held) if failures >= self.config.failure_threshold => {
+                        if held {
+                            CircuitState::Open
+                        } else {
+                            CircuitState::HalfOpen
+                        }
+                    }
+                    _ => CircuitState::Retrying,
+                };
+                IntegrationStatus {
+                    name: name.clone(),
+                    state,
+                    consecutive_failures: breaker.failures,
+                    retry_at: held.then_some(breaker.retry_at),
+                    sent: breaker.sent,
+                    failed: breaker.failed,
+                    last_success: breaker.last_success,
+                    last_failure: breaker.last_failure,
+                    last_error: breaker.last_error.clone(),
+                }
+            })
+            .collect();
+        let dead_letters: i64 = conn.query_row("SELECT COUNT(*) FROM dead_letters", [], |row| row.get(0))?;
+        let mut stmt = conn.prepare(
+            "SELECT id, integration, payload, error, attempts, failed_at FROM dead_letters ORDER BY id DESC LIMIT ?1",
+        )?;
+        let recent_dead_letters = stmt
+            .query_map([LISTED_DEAD_LETTERS as i64], |row| {
+                let payload: String = row.get(2)?;
+                Ok(DeadLetter {
+                    id: row.get(0)?,
+                    integration: row.get(1)?,
+                    payload: serde_json::from_str(&payload).unwrap_or(Value::String(payload)),
+                    error: row.get(3)?,
+                    attempts: row.get(4)?,
+                    failed_at: row.get(5)?,
+                })
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+        Ok(OutboundStatus { integrations, dead_letters: dead_letters as u64, recent_dead_letters })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::cell::Cell;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let config = OutboundConfig::default();
+        let delays: Vec<u64> = (1..=9).map(|failures| config.backoff(failures).as_secs()).collect();
+        assert_eq!(delays, vec![2, 4, 8, 16, 32, 64, 128, 256, 300]);
+        assert_eq!(config.backoff(u32::MAX), DurationSecs::mins(5));
+    }
+
+    #[test]
+    fn failures_open_the_circuit_and_end_in_dead_letters() {
+        let db = std::env::temp_dir().join(format!("sysmonitor-outbound-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&db);
+        let conn = Connection::open(&db).unwrap();
+        init_tables(&conn).unwrap();
+        let config = OutboundConfig { max_attempts: 3, base_delay_secs: 0, failure_threshold: 4, ..OutboundConfig::default() };
+        let outbound = Outbound::new(&config, db.to_str().unwrap());
+
+        let tries = Cell::new(0);
+        let failing = || {
+            tries.set(tries.get() + 1);
+            Err::<(), _>("HTTP 503".to_string())
+        };
+        assert!(outbound.deliver("notification:hooks", &json!({ "title": "Over" }), failing).is_err());
+        assert_eq!(tries.get(), 3);
+        assert!(outbound.call("notification:hooks", failing).is_err());
+        // Open now: nothing goes out, and the next message is dead-lettered right away
+        assert_eq!(outbound.call("notification:hooks", || Ok(1)), Ok(None));
+        assert_eq!(outbound.deliver("notification:hooks", &json!("second"), failing), Err("circuit open: HTTP 503".to_string()));
+        assert_eq!(tries.get(), 4);
+        assert_eq!(outbound.call("notion", || Ok(2)), Ok(Some(2)));
+
+        let status = outbound.status(&conn, Timestamp::now()).unwrap();
+        let states: Vec<_> = status.integrations.iter().map(|integration| (integration.name.as_str(), integration.state)).collect();
+        assert_eq!(states, vec![("notification:hooks", CircuitState::Open), ("notion", CircuitState::Closed)]);
+        assert_eq!(status.dead_letters, 2);
+        assert_eq!(status.recent_dead_letters[1].payload, json!({ "title": "Over" }));
+        assert_eq!((status.recent_dead_letters[1].attempts, status.recent_dead_letters[0].attempts), (3, 0));
+
+        // Once the open time is over, a success closes it again
+        assert_eq!(outbound.status(&conn, Timestamp::now() + DurationSecs::secs(601)).unwrap().integrations[0].state, CircuitState::HalfOpen);
+        outbound.breakers.lock().unwrap().get_mut("notification:hooks").unwrap().retry_at = Timestamp::EPOCH;
+        assert_eq!(outbound.call("notification:hooks", || Ok(3)), Ok(Some(3)));
+        assert_eq!(outbound.status(&conn, Timestamp::now()).unwrap().integrations[0].state, CircuitState::Closed);
+        let _ = std::fs::remove_file(&db);
+    }
+}
@@ -0,0 +1,382 @@
+// Overlap resolution for backfilled (imported or synced) sessions.
+//
+// Only one window has focus at a time, so an incoming session that overlaps
+// stored ones on the timeline would double-count that time. The configured
+// policy decides what happens, and every overlap is written to
+// `ingest_conflicts` for the conflicts report:
+//
+// - `split`: only the parts of the incoming session not already covered are stored
+// - `prefer-source`: the source ranked higher in `source_precedence` keeps the
+//   overlapping time; the other session is cut around it
+// - `reject`: overlapping sessions are refused
+
+use std::collections::BTreeMap;
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::signatures;
+use crate::units::Timestamp;
+
+pub const LOCAL_SOURCE: &str = "local"; // Rows written by the tracker itself have no source
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlapPolicy {
+    #[default]
+    Split,
+    PreferSource,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSession {
+    pub identifier: String,
+    pub app_name: String,
+    pub window_title: String,
+    pub url: Option<String>,
+    pub source: Option<String>,
+    pub clock_skew: Option<i64>, // Skew of the collector that sent it, when it was flagged
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+impl StoredSession {
+    fn source(&self) -> &str {
+        self.source.as_deref().unwrap_or(LOCAL_SOURCE)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Resolution {
+    pub stored: Vec<(Timestamp, Timestamp)>, // Intervals of the incoming session that were written
+    pub conflicts: usize,
+    pub rejected: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub detected_at: Timestamp,
+    pub source: String,
+    pub identifier: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub existing_source: String,
+    pub existing_identifier: String,
+    pub existing_start: Timestamp,
+    pub existing_end: Timestamp,
+    pub resolution: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictReport {
+    pub total: u64,
+    pub by_resolution: BTreeMap<String, u64>,
+    pub conflicts: Vec<Conflict>, // Most recent first, up to `limit`
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConflictQuery {
+    pub since: Option<Timestamp>,
+    pub limit: Option<u64>,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ingest_conflicts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            detected_at INTEGER NOT NULL,
+            source TEXT NOT NULL,
+            identifier TEXT NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            existing_source TEXT NOT NULL,
+            existing_identifier TEXT NOT NULL,
+            existing_start INTEGER NOT NULL,
+            existing_end INTEGER NOT NULL,
+            resolution TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Parts of `[start, end)` not covered by any of `covered`.
+pub fn subtract(start: Timestamp, end: Timestamp, covered: &[(Timestamp, Timestamp)]) -> Vec<(Timestamp, Timestamp)> {
+    let mut covered: Vec<(Timestamp, Timestamp)> = covered.iter().copied().filter(|&(s, e)| s < end && e > start).collect();
+    covered.sort_unstable();
+    let mut pieces = Vec::new();
+    let mut cursor = start;
+    for (s, e) in covered {
+        if s > cursor {
+            pieces.push((cursor, s));
+        }
+        cursor = cursor.max(e);
+    }
+    if cursor < end {
+        pieces.push((cursor, end));
+    }
+    pieces
+}
+
+// Earlier in `precedence` wins; unlisted sources rank last, and ties keep what is stored
+fn outranks(precedence: &[String], incoming: &str, existing: &str) -> bool {
+    let rank = |source: &str| precedence.iter().position(|s| s == source).unwrap_or(precedence.len());
+    rank(incoming) < rank(existing)
+}
+
+/// Stored sessions intersecting `[start, end)`.
+pub fn overlapping(conn: &Connection, start: Timestamp, end: Timestamp) -> SqlResult<Vec<StoredSession>> {
+    let mut stmt = conn.prepare(
+        "SELECT identifier, app_name, window_title, url, source, timestamp - duration AS start, MAX(duration), clock_skew
+         FROM usage_logs
+         WHERE timestamp > ?1 AND timestamp - duration < ?2
+         GROUP BY identifier, start
+         ORDER BY start",
+    )?;
+    let sessions = stmt
+        .query_map(params![start, end], |row| {
+            let start: Timestamp = row.get(5)?;
+            Ok(StoredSession {
+                identifier: row.get(0)?,
+                app_name: row.get(1)?,
+                window_title: row.get(2)?,
+                url: row.get(3)?,
+                source: row.get(4)?,
+                clock_skew: row.get(7)?,
+                start,
+                end: start + row.get(6)?,
+            })
+        })?
+        .collect();
+    sessions
+}
+
+pub fn insert_session(conn: &Connection, session: &StoredSession, start: Timestamp, end: Timestamp) -> SqlResult<()> {
+    // Rows carry the end of the session as `timestamp`, like tracker flushes
+    conn.execute(
+        "INSERT INTO usage_logs (identifier, app_name, window_title, url, timestamp, duration, partial, source, clock_skew)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, ?7, ?8)",
+        params![
+            session.identifier,
+            session.app_name,
+            session.window_title,
+            session.url,
+            end,
+            end - start,
+            session.source,
+            session.clock_skew
+        ],
+    )?;
+    signatures::sign_row(conn, conn.last_insert_rowid())
+}
+
+// Replaces a stored session by its parts outside `[start, end)`
+fn cut_session(conn: &Connection, session: &StoredSession, start: Timestamp, end: Timestamp) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM usage_logs WHERE identifier = ?1 AND timestamp - duration = ?2",
+        params![session.identifier, session.start],
+    )?;
+    for (piece_start, piece_end) in subtract(session.start, session.end, &[(start, end)]) {
+        insert_session(conn, session, piece_start, piece_end)?;
+    }
+    Ok(())
+}
+
+fn record_conflict(
+    conn: &Connection,
+    incoming: &StoredSession,
+    existing: &StoredSession,
+    resolution: &str,
+    now: Timestamp,
+) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO ingest_conflicts (detected_at, source, identifier, start_time, end_time,
+                                       existing_source, existing_identifier, existing_start, existing_end, resolution)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            now,
+            incoming.source(),
+            incoming.identifier,
+            incoming.start,
+            incoming.end,
+            existing.source(),
+            existing.identifier,
+            existing.start,
+            existing.end,
+            resolution
+        ],
+    )?;
+    Ok(())
+}
+
+/// Stores `incoming` according to `policy`, cutting or refusing it (or, with
+/// `prefer-source`, the stored sessions) where it overlaps.
+pub fn resolve(
+    conn: &Connection,
+    policy: OverlapPolicy,
+    precedence: &[String],
+    incoming: &StoredSession,
+    now: Timestamp,
+) -> SqlResult<Resolution> {
+    let existing = overlapping(conn, incoming.start, incoming.end)?;
+    let mut resolution = Resolution { conflicts: existing.len(), ..Resolution::default() };
+
+    let mut kept = Vec::new();
+    for session in &existing {
+        let outcome = match policy {
+            OverlapPolicy::Reject => "rejected",
+            OverlapPolicy::Split => "split",
+            OverlapPolicy::PreferSource if outranks(precedence, incoming.source(), session.source()) => {
+                cut_session(conn, session, incoming.start, incoming.end)?;
+                "replaced"
+            }
+            OverlapPolicy::PreferSource => "kept-existing",
+        };
+        if outcome != "replaced" {
+            kept.push((session.start, session.end));
+        }
+        record_conflict(conn, incoming, session, outcome, now)?;
+    }
+
+    if policy == OverlapPolicy::Reject && !existing.is_empty() {
+        resolution.rejected = Some(format!("overlaps {} stored session(s)", existing.len()));
+        return Ok(resolution);
+    }
+    resolution.stored = subtract(incoming.start, incoming.end, &kept);
+    if resolution.stored.is_empty() {
+        resolution.rejected = Some("already covered by stored sessions".to_string());
+    }
+    for &(start, end) in &resolution.stored {
+        insert_session(conn, incoming, start, end)?;
+    }
+    Ok(resolution)
+}
+
+pub fn load_conflicts(conn: &Connection, query: &ConflictQuery) -> SqlResult<ConflictReport> {
+    let since = query.since.unwrap_or(Timestamp::EPOCH);
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let mut by_resolution = BTreeMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT resolution, COUNT(*) FROM ingest_conflicts WHERE detected_at >= ?1 GROUP BY resolution",
+    )?;
+    for row in stmt.query_map(params![since], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)))? {
+        let (resolution, count) = row?;
+        by_resolution.insert(resolution, count);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT detected_at, source, identifier, start_time, end_time,
+                existing_source, existing_identifier, existing_start, existing_end, resolution
+         FROM ingest_conflicts
+         WHERE detected_at >= ?1
+         ORDER BY id DESC
+         LIMIT ?2",
+    )?;
+    let conflicts = stmt
+        .query_map(params![since, limit], |row| {
+            Ok(Conflict {
+                detected_at: row.get(0)?,
+                source: row.get(1)?,
+                identifier: row.get(2)?,
+                start: row.get(3)?,
+                end: row.get(4)?,
+                existing_source: row.get(5)?,
+                existing_identifier: row.get(6)?,
+                existing_start: row.get(7)?,
+                existing_end: row.get(8)?,
+                resolution: row.get(9)?,
+            })
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    Ok(ConflictReport {
+        total: by_resolution.values().sum(),
+        by_resolution,
+        conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn db() -> Connection {
+        fixtures::empty().unwrap()
+    }
+
+    fn session(app: &str, source: Option<&str>, start: u64, end: u64) -> StoredSession {
+        StoredSession {
+            identifier: format!("{}:t:", app),
+            app_name: app.to_string(),
+            window_title: "t".to_string(),
+            url: None,
+            source: source.map(str::to_string),
+            clock_skew: None,
+            start: at(start),
+            end: at(end),
+        }
+    }
+
+    fn at(secs: u64) -> Timestamp {
+        Timestamp::from_secs(secs)
+    }
+
+    fn spans(spans: &[(u64, u64)]) -> Vec<(Timestamp, Timestamp)> {
+        spans.iter().map(|&(start, end)| (at(start), at(end))).collect()
+    }
+
+    fn stored(conn: &Connection) -> Vec<(String, u64, u64)> {
+        overlapping(conn, Timestamp::EPOCH, at(u64::MAX / 2))
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.app_name, s.start.as_secs(), s.end.as_secs()))
+            .collect()
+    }
+
+    #[test]
+    fn subtract_leaves_uncovered_pieces() {
+        assert_eq!(subtract(at(0), at(100), &spans(&[(20, 30), (25, 50), (90, 200)])), spans(&[(0, 20), (50, 90)]));
+        assert_eq!(subtract(at(0), at(100), &[]), spans(&[(0, 100)]));
+        assert!(subtract(at(10), at(20), &spans(&[(0, 30)])).is_empty());
+    }
+
+    #[test]
+    fn split_stores_only_uncovered_time() {
+        let conn = db();
+        insert_session(&conn, &session("code.exe", None, 100, 200), at(100), at(200)).unwrap();
+        let resolution = resolve(&conn, OverlapPolicy::Split, &[], &session("phone", Some("sync"), 50, 250), at(0)).unwrap();
+        assert_eq!((resolution.stored, resolution.conflicts), (spans(&[(50, 100), (200, 250)]), 1));
+        assert_eq!(stored(&conn).len(), 3);
+    }
+
+    #[test]
+    fn prefer_source_cuts_the_lower_ranked_session() {
+        let conn = db();
+        insert_session(&conn, &session("code.exe", None, 100, 200), at(100), at(200)).unwrap();
+        let precedence = vec!["sync".to_string(), LOCAL_SOURCE.to_string()];
+        let incoming = session("phone", Some("sync"), 150, 175);
+        resolve(&conn, OverlapPolicy::PreferSource, &precedence, &incoming, at(0)).unwrap();
+        assert_eq!(
+            stored(&conn),
+            vec![("code.exe".to_string(), 100, 150), ("phone".to_string(), 150, 175), ("code.exe".to_string(), 175, 200)]
+        );
+        assert_eq!(load_conflicts(&conn, &ConflictQuery::default()).unwrap().by_resolution["replaced"], 1);
+    }
+
+    #[test]
+    fn reject_refuses_overlaps() {
+        let conn = db();
+        insert_session(&conn, &session("code.exe", None, 100, 200), at(100), at(200)).unwrap();
+        let resolution = resolve(&conn, OverlapPolicy::Reject, &[], &session("phone", Some("import"), 190, 300), at(0)).unwrap();
+        assert!(resolution.rejected.is_some() && resolution.stored.is_empty());
+        assert!(resolve(&conn, OverlapPolicy::Reject, &[], &session("phone", Some("import"), 200, 300), at(0))
+            .unwrap()
+            .rejected
+            .is_none());
+    }
+}
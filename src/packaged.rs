@@ -0,0 +1,93 @@
+// Friendly names for packaged (UWP/MSIX) apps.
+//
+// Packaged processes have generic or cryptic executable names (`Calculator.exe`,
+// `HxOutlook.exe`, `WhatsApp.exe` under WindowsApps), so reports use the
+// `DisplayName` and `Logo` from the package manifest instead.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use windows::{
+    core::{HSTRING, PWSTR},
+    Win32::Storage::Packaging::Appx::GetPackagePathByFullName,
+    Win32::UI::Shell::SHLoadIndirectString,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageIdentity {
+    pub package_full_name: String,
+    pub display_name: String,
+    pub logo_path: Option<String>,
+}
+
+fn package_path(package_full_name: &str) -> Option<PathBuf> {
+    let name = HSTRING::from(package_full_name);
+    let mut buffer = [0u16; 1024];
+    let mut len = buffer.len() as u32;
+    unsafe {
+        GetPackagePathByFullName(&name, &mut len, PWSTR(buffer.as_mut_ptr())).ok()?;
+    }
+    let len = (len as usize).saturating_sub(1).min(buffer.len());
+    Some(PathBuf::from(String::from_utf16_lossy(&buffer[..len])))
+}
+
+// Resolves `ms-resource:` references against the package's resources.pri
+fn load_indirect_string(package_full_name: &str, value: &str) -> Option<String> {
+    if !value.starts_with("ms-resource:") {
+        return Some(value.to_string());
+    }
+    let source = HSTRING::from(format!("@{{{}?{}}}", package_full_name, value));
+    let mut buffer = [0u16; 512];
+    unsafe {
+        SHLoadIndirectString(&source, &mut buffer, None).ok()?;
+    }
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    (len > 0).then(|| String::from_utf16_lossy(&buffer[..len]))
+}
+
+// The `<Properties>` element carries the package-level display name and logo
+fn manifest_property(manifest: &str, element: &str) -> Option<String> {
+    let properties = Regex::new(r"(?s)<Properties>(.*?)</Properties>").ok()?;
+    let block = properties.captures(manifest)?.get(1)?.as_str();
+    let pattern = Regex::new(&format!(r"(?s)<{0}>\s*(.*?)\s*</{0}>", element)).ok()?;
+    Some(pattern.captures(block)?.get(1)?.as_str().to_string())
+}
+
+// Logos are shipped as scale variants (`StoreLogo.scale-100.png`), so fall back
+// to the first file sharing the manifest name's stem
+fn find_logo(package_dir: &Path, relative: &str) -> Option<String> {
+    let declared = package_dir.join(relative.replace('\\', "/"));
+    if declared.exists() {
+        return Some(declared.to_string_lossy().to_string());
+    }
+    let stem = declared.file_stem()?.to_str()?.to_string();
+    let parent = declared.parent()?;
+    fs::read_dir(parent)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&format!("{}.", stem)))
+        })
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+pub fn resolve_package(package_full_name: &str) -> Option<PackageIdentity> {
+    let package_dir = package_path(package_full_name)?;
+    let manifest = fs::read_to_string(package_dir.join("AppxManifest.xml")).ok()?;
+
+    let display_name = manifest_property(&manifest, "DisplayName")
+        .and_then(|value| load_indirect_string(package_full_name, &value))
+        .filter(|name| !name.trim().is_empty())?;
+    let logo_path = manifest_property(&manifest, "Logo").and_then(|logo| find_logo(&package_dir, &logo));
+
+    Some(PackageIdentity {
+        package_full_name: package_full_name.to_string(),
+        display_name,
+        logo_path,
+    })
+}
@@ -0,0 +1,382 @@
+// Dashboard panels defined as data rather than code.
+//
+// A panel is a query spec: a metric (tracked seconds, sessions or distinct
+// apps), filters on app, category, title and URL host, an optional grouping,
+// how many days back it looks, and a hint for how to draw it. Specs are stored
+// in `panels` by id through `/api/panels`, and evaluated here on each request,
+// so a frontend only has to draw the groups it gets back. The ids are the ones
+// `dashboard::Layout::panels` turns on. Saved queries (see `queries`) run the
+// same `Query` over other ranges.
+//
+// Sessions count towards the day, hour and weekday they started in; groups by
+// time come back in order with empty ones filled in, the rest largest first.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Duration, Local, TimeZone, Timelike};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::assignments;
+use crate::categories::{self, CategoryConfig};
+use crate::identifier::ActivityKey;
+use crate::reports;
+use crate::units::Timestamp;
+
+const MAX_PANELS: usize = 50; // Like the layout's panel list
+const MAX_ID_LEN: usize = 64;
+const MAX_TITLE_CHARS: usize = 100;
+const MAX_DAYS: u32 = 366;
+const MAX_FILTER_VALUES: usize = 50;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    #[default]
+    Seconds,
+    Sessions,
+    Apps, // Distinct apps
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    App,
+    Category,
+    Title,
+    Host, // Sessions without a URL are left out
+    Day,
+    Hour, // Of the day, 00 to 23
+    Weekday,
+}
+
+impl GroupBy {
+    fn is_time(self) -> bool {
+        matches!(self, Self::Day | Self::Hour | Self::Weekday)
+    }
+}
+
+/// How the frontend should draw the panel; not used here.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visualization {
+    Number,
+    #[default]
+    Bar,
+    Line,
+    Pie,
+    Table,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filters {
+    pub apps: Vec<String>, // Any of them, case-insensitive; empty matches every app
+    pub exclude_apps: Vec<String>,
+    pub categories: Vec<String>, // Any of them or their subcategories
+    pub title_contains: Option<String>, // Case-insensitive
+    pub host: Option<String>, // Also matching subdomains
+}
+
+fn default_days() -> u32 {
+    7
+}
+
+/// What to count and how to group it, over whatever range it is run for.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Query {
+    #[serde(default)]
+    pub metric: Metric,
+    #[serde(default)]
+    pub filters: Filters,
+    #[serde(default)]
+    pub group_by: Option<GroupBy>,
+    #[serde(default)]
+    pub limit: Option<usize>, // Largest groups kept; groups by time are never cut
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PanelSpec {
+    pub title: String,
+    #[serde(flatten)]
+    pub query: Query,
+    #[serde(default = "default_days")]
+    pub days: u32, // Today and the days before it
+    #[serde(default)]
+    pub visualization: Visualization,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredPanel {
+    pub id: String,
+    #[serde(flatten)]
+    pub spec: PanelSpec,
+    pub updated_at: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Group {
+    pub key: String,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryResult {
+    pub metric: Metric,
+    pub group_by: Option<GroupBy>,
+    pub since: u64,
+    pub until: u64,
+    pub total: u64,
+    pub groups: Vec<Group>, // Empty without `group_by`
+}
+
+/// A panel evaluated over its days.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PanelData {
+    pub title: String,
+    pub visualization: Visualization,
+    #[serde(flatten)]
+    pub result: QueryResult,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS panels (
+            id TEXT PRIMARY KEY,
+            spec TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn validate_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_ID_LEN || !id.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.') {
+        return Err(format!("panel id '{}' must be 1 to {} letters, digits, '-', '_' or '.'", id, MAX_ID_LEN));
+    }
+    Ok(())
+}
+
+pub fn validate(spec: &PanelSpec) -> Result<(), String> {
+    if spec.title.trim().is_empty() || spec.title.chars().count() > MAX_TITLE_CHARS {
+        return Err(format!("title must be 1 to {} characters", MAX_TITLE_CHARS));
+    }
+    if spec.days == 0 || spec.days > MAX_DAYS {
+        return Err(format!("days must be between 1 and {}", MAX_DAYS));
+    }
+    validate_query(&spec.query)
+}
+
+pub fn validate_query(query: &Query) -> Result<(), String> {
+    if query.limit == Some(0) {
+        return Err("limit must be at least 1".to_string());
+    }
+    let filters = &query.filters;
+    if [filters.apps.len(), filters.exclude_apps.len(), filters.categories.len()].iter().any(|count| *count > MAX_FILTER_VALUES) {
+        return Err(format!("each filter takes at most {} values", MAX_FILTER_VALUES));
+    }
+    Ok(())
+}
+
+pub fn list(conn: &Connection) -> SqlResult<Vec<StoredPanel>> {
+    let mut stmt = conn.prepare("SELECT id, spec, updated_at FROM panels ORDER BY id")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?)))?;
+    let mut panels = Vec::new();
+    for row in rows {
+        let (id, spec, updated_at) = row?;
+        // A spec this version can't read (e.g. written by a newer one) is left out
+        if let Ok(spec) = serde_json::from_str(&spec) {
+            panels.push(StoredPanel { id, spec, updated_at });
+        }
+    }
+    Ok(panels)
+}
+
+pub fn get(conn: &Connection, id: &str) -> SqlResult<Option<StoredPanel>> {
+    let stored: Option<(String, Timestamp)> =
+        conn.query_row("SELECT spec, updated_at FROM panels WHERE id = ?1", params![id], |row| Ok((row.get(0)?, row.get(1)?))).optional()?;
+    Ok(stored.and_then(|(spec, updated_at)| {
+        Some(StoredPanel { id: id.to_string(), spec: serde_json::from_str(&spec).ok()?, updated_at })
+    }))
+}
+
+/// Creates or replaces panel `id`; Err when it would be one too many.
+pub fn save(conn: &Connection, id: &str, spec: &PanelSpec, now: Timestamp) -> SqlResult<Result<(), String>> {
+    let exists = conn.query_row("SELECT 1 FROM panels WHERE id = ?1", params![id], |_| Ok(())).optional()?.is_some();
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM panels", [], |row| row.get(0))?;
+    if !exists && count as usize >= MAX_PANELS {
+        return Ok(Err(format!("at most {} panels can be stored", MAX_PANELS)));
+    }
+    conn.execute(
+        "INSERT INTO panels (id, spec, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET spec = excluded.spec, updated_at = excluded.updated_at",
+        params![id, serde_json::to_string(spec).unwrap_or_default(), now],
+    )?;
+    Ok(Ok(()))
+}
+
+pub fn delete(conn: &Connection, id: &str) -> SqlResult<bool> {
+    Ok(conn.execute("DELETE FROM panels WHERE id = ?1", params![id])? > 0)
+}
+
+impl Filters {
+    fn matches(&self, key: &ActivityKey, category: &str) -> bool {
+        let app = |apps: &[String]| apps.iter().any(|app| app.eq_ignore_ascii_case(&key.app_name));
+        if (!self.apps.is_empty() && !app(&self.apps)) || app(&self.exclude_apps) {
+            return false;
+        }
+        if !self.categories.is_empty() && !self.categories.iter().any(|ancestor| categories::is_within(category, ancestor)) {
+            return false;
+        }
+        if let Some(ref needle) = self.title_contains {
+            if !key.window_title().to_lowercase().contains(&needle.to_lowercase()) {
+                return false;
+            }
+        }
+        if let Some(ref host) = self.host {
+            let host = host.to_lowercase();
+            match key.url().and_then(categories::url_host) {
+                Some(actual) if actual == host || actual.ends_with(&format!(".{}", host)) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+// Seconds, sessions and apps of a group as it fills up
+#[derive(Default)]
+struct Tally {
+    seconds: u64,
+    sessions: u64,
+    apps: BTreeSet<String>,
+}
+
+impl Tally {
+    fn add(&mut self, app_name: &str, seconds: u64) {
+        self.seconds += seconds;
+        self.sessions += 1;
+        self.apps.insert(app_name.to_string());
+    }
+
+    fn value(&self, metric: Metric) -> u64 {
+        match metric {
+            Metric::Seconds => self.seconds,
+            Metric::Sessions => self.sessions,
+            Metric::Apps => self.apps.len() as u64,
+        }
+    }
+}
+
+/// Evaluates `spec` over today and the `days - 1` days before it.
+pub fn evaluate(conn: &Connection, categories: &CategoryConfig, spec: &PanelSpec, now: u64) -> SqlResult<PanelData> {
+    let first = reports::local_date(now) - Duration::days(i64::from(spec.days.max(1)) - 1);
+    let since = reports::day_bounds(first).0.max(0) as u64;
+    let result = run(conn, categories, &spec.query, since, now)?;
+    Ok(PanelData { title: spec.title.clone(), visualization: spec.visualization, result })
+}
+
+/// Runs `query` over the sessions starting in `[since, until)`.
+pub fn run(conn: &Connection, categories: &CategoryConfig, query: &Query, since: u64, until: u64) -> SqlResult<QueryResult> {
+    let (sessions, _) = assignments::categorized_sessions(conn, categories, &assignments::rules_version(categories), since, until)?;
+
+    // Groups by time start out empty so charts have every bucket
+    let mut groups: BTreeMap<String, Tally> = BTreeMap::new();
+    match query.group_by {
+        Some(GroupBy::Day) => {
+            let (mut day, last) = (reports::local_date(since), reports::local_date(until.saturating_sub(1).max(since)));
+            while day <= last {
+                groups.insert(day.format("%Y-%m-%d").to_string(), Tally::default());
+                day = day.succ_opt().unwrap_or(last + Duration::days(1));
+            }
+        }
+        Some(GroupBy::Hour) => groups.extend((0..24).map(|hour| (format!("{:02}", hour), Tally::default()))),
+        Some(GroupBy::Weekday) => groups.extend((0..7).map(|day| (day.to_string(), Tally::default()))),
+        _ => {}
+    }
+    let mut total = Tally::default();
+    for session in sessions {
+        let key = ActivityKey::parse(&session.identifier);
+        if !query.filters.matches(&key, &session.category) {
+            continue;
+        }
+        let started = Local.timestamp_opt(session.start as i64, 0).earliest();
+        let group = match query.group_by {
+            None => None,
+            Some(GroupBy::App) => Some(key.app_name.clone()),
+            Some(GroupBy::Category) => Some(session.category.clone()),
+            Some(GroupBy::Title) => Some(key.window_title().to_string()),
+            Some(GroupBy::Host) => match key.url().and_then(categories::url_host) {
+                Some(host) => Some(host),
+                None => continue,
+            },
+            Some(GroupBy::Day) => Some(reports::local_date(session.start).format("%Y-%m-%d").to_string()),
+            Some(GroupBy::Hour) => started.map(|time| format!("{:02}", time.hour())),
+            Some(GroupBy::Weekday) => started.map(|time| time.weekday().num_days_from_monday().to_string()),
+        };
+        if let Some(group) = group {
+            groups.entry(group).or_default().add(&key.app_name, session.seconds);
+        }
+        total.add(&key.app_name, session.seconds);
+    }
+
+    let mut groups: Vec<Group> = groups.into_iter().map(|(key, tally)| Group { key, value: tally.value(query.metric) }).collect();
+    if query.group_by == Some(GroupBy::Weekday) {
+        for group in &mut groups {
+            group.key = WEEKDAYS[group.key.parse::<usize>().unwrap_or(0) % 7].to_string();
+        }
+    }
+    if !query.group_by.is_some_and(GroupBy::is_time) {
+        groups.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.key.cmp(&b.key)));
+        if let Some(limit) = query.limit {
+            groups.truncate(limit);
+        }
+    }
+    Ok(QueryResult { metric: query.metric, group_by: query.group_by, since, until, total: total.value(query.metric), groups })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn specs_are_stored_and_evaluated() {
+        let conn = fixtures::empty().unwrap();
+        let categories: CategoryConfig =
+            serde_json::from_value(serde_json::json!({ "rules": [{ "category": "Dev > Editor", "app": "code.exe" }, { "category": "Web", "app": "chrome.exe" }] })).unwrap();
+        let now = reports::day_bounds(chrono::NaiveDate::from_ymd_opt(2026, 3, 4).unwrap()).0 as u64 + 12 * 3600;
+        let log = |key: ActivityKey, start: u64, duration: u64| {
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![key.to_string(), key.app_name, key.window_title(), start + duration, duration],
+            )
+            .unwrap();
+        };
+        log(ActivityKey::new("code.exe", "main.rs", None), now - 3600, 1200);
+        log(ActivityKey::new("code.exe", "lib.rs", None), now - 86400, 600);
+        log(ActivityKey::new("chrome.exe", "Docs", Some("https://docs.rs/serde")), now - 1800, 300);
+        log(ActivityKey::new("slack.exe", "general", None), now - 10 * 86400, 900); // Outside the week
+
+        let spec: PanelSpec = serde_json::from_value(serde_json::json!({ "title": "Dev by day", "filters": { "categories": ["Dev"] }, "group_by": "day" })).unwrap();
+        assert!(validate(&spec).is_ok());
+        assert_eq!(save(&conn, "dev-by-day", &spec, Timestamp::from_secs(100)).unwrap(), Ok(()));
+        let stored = get(&conn, "dev-by-day").unwrap().unwrap();
+        assert_eq!((stored.spec.days, stored.spec.visualization), (7, Visualization::Bar));
+
+        let data = evaluate(&conn, &categories, &stored.spec, now).unwrap().result;
+        assert_eq!(data.total, 1800);
+        assert_eq!(data.groups.len(), 7);
+        assert_eq!(data.groups.iter().rev().take(2).map(|group| group.value).collect::<Vec<_>>(), vec![1200, 600]);
+
+        let by_host = Query { metric: Metric::Sessions, group_by: Some(GroupBy::Host), ..Query::default() };
+        let data = run(&conn, &categories, &by_host, 0, now).unwrap();
+        assert_eq!((data.total, data.groups), (1, vec![Group { key: "docs.rs".to_string(), value: 1 }]));
+
+        assert!(validate(&PanelSpec { days: 0, ..spec }).is_err());
+        assert!(validate_id("no spaces").is_err());
+        assert!(delete(&conn, "dev-by-day").unwrap() && list(&conn).unwrap().is_empty());
+    }
+}
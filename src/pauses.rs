@@ -0,0 +1,258 @@
+// Deliberate pauses in tracking.
+//
+// A pause is stored with an optional reason and stays open until tracking is
+// resumed, across restarts. `gaps` tells these apart from time when nothing
+// was tracked without a pause, e.g. because the tracker wasn't running.
+// A pause may be given a length; `expire` then closes it at `resume_at`, so a
+// forgotten pause doesn't leave a hole of days.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::sessions::Session;
+use crate::units::{DurationSecs, Timestamp};
+
+const MAX_REASON_LEN: usize = 100;
+const MAX_PAUSE_MINUTES: u64 = 24 * 60;
+const MIN_GAP: DurationSecs = DurationSecs::mins(5); // Shorter breaks between sessions aren't gaps
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PauseConfig {
+    pub reasons: Vec<String>, // Presets offered by the dashboard and tray
+}
+
+impl Default for PauseConfig {
+    fn default() -> Self {
+        Self { reasons: vec!["Meeting room".to_string(), "Personal time".to_string(), "Break".to_string()] }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PauseQuery {
+    pub reason: Option<String>,
+    pub minutes: Option<u64>, // Resume on its own after this long
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pause {
+    pub id: i64,
+    pub start: Timestamp,
+    pub end: Option<Timestamp>, // None while paused
+    pub reason: Option<String>,
+    pub resume_at: Option<Timestamp>,
+}
+
+impl Pause {
+    /// Time until an open pause resumes on its own.
+    pub fn remaining(&self, now: Timestamp) -> Option<DurationSecs> {
+        self.resume_at.filter(|_| self.end.is_none()).map(|resume_at| resume_at.since(now))
+    }
+
+    // When the pause ended or will end, if it's still open at `until`
+    fn end_or(&self, until: Timestamp) -> Timestamp {
+        self.end.or(self.resume_at).map_or(until, |end| end.min(until))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapKind {
+    Paused,
+    Untracked,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gap {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub kind: GapKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pauses (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            start INTEGER NOT NULL,
+            end INTEGER,
+            reason TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn validate_reason(reason: Option<String>) -> Result<Option<String>, String> {
+    match reason.map(|reason| reason.trim().to_string()) {
+        Some(reason) if reason.chars().count() > MAX_REASON_LEN => {
+            Err(format!("reason must be at most {} characters", MAX_REASON_LEN))
+        }
+        Some(reason) if reason.is_empty() => Ok(None),
+        reason => Ok(reason),
+    }
+}
+
+pub fn validate_minutes(minutes: Option<u64>) -> Result<Option<u64>, String> {
+    match minutes {
+        Some(minutes) if minutes == 0 || minutes > MAX_PAUSE_MINUTES => {
+            Err(format!("minutes must be between 1 and {}", MAX_PAUSE_MINUTES))
+        }
+        minutes => Ok(minutes),
+    }
+}
+
+fn from_row(row: &Row) -> SqlResult<Pause> {
+    Ok(Pause { id: row.get(0)?, start: row.get(1)?, end: row.get(2)?, reason: row.get(3)?, resume_at: row.get(4)? })
+}
+
+pub fn active(conn: &Connection) -> SqlResult<Option<Pause>> {
+    conn.query_row(
+        "SELECT id, start, end, reason, resume_at FROM pauses WHERE end IS NULL ORDER BY id DESC LIMIT 1",
+        [],
+        from_row,
+    )
+    .optional()
+}
+
+/// Opens a pause, or gives the open one the new reason and countdown.
+pub fn start(conn: &Connection, reason: Option<String>, minutes: Option<u64>, now: Timestamp) -> SqlResult<Pause> {
+    let resume_at = minutes.map(|minutes| now + DurationSecs::mins(minutes));
+    if let Some(mut pause) = active(conn)? {
+        if reason.is_some() {
+            conn.execute("UPDATE pauses SET reason = ?1 WHERE id = ?2", params![reason, pause.id])?;
+            pause.reason = reason;
+        }
+        if resume_at.is_some() {
+            conn.execute("UPDATE pauses SET resume_at = ?1 WHERE id = ?2", params![resume_at, pause.id])?;
+            pause.resume_at = resume_at;
+        }
+        return Ok(pause);
+    }
+    conn.execute(
+        "INSERT INTO pauses (start, reason, resume_at) VALUES (?1, ?2, ?3)",
+        params![now, reason, resume_at],
+    )?;
+    Ok(Pause { id: conn.last_insert_rowid(), start: now, end: None, reason, resume_at })
+}
+
+/// Closes the open pause, if any.
+pub fn resume(conn: &Connection, now: Timestamp) -> SqlResult<Option<Pause>> {
+    let Some(mut pause) = active(conn)? else {
+        return Ok(None);
+    };
+    let end = now.max(pause.start);
+    conn.execute("UPDATE pauses SET end = ?1 WHERE id = ?2", params![end, pause.id])?;
+    pause.end = Some(end);
+    Ok(Some(pause))
+}
+
+/// Closes the open pause at its `resume_at` once that has passed.
+pub fn expire(conn: &Connection, now: Timestamp) -> SqlResult<Option<Pause>> {
+    match active(conn)? {
+        Some(Pause { resume_at: Some(resume_at), .. }) if resume_at <= now => resume(conn, resume_at),
+        _ => Ok(None),
+    }
+}
+
+/// Pauses overlapping `[since, until)`, oldest first.
+pub fn list(conn: &Connection, since: Timestamp, until: Timestamp) -> SqlResult<Vec<Pause>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, start, end, reason, resume_at FROM pauses
+         WHERE start < ?2 AND (COALESCE(end, resume_at) IS NULL OR COALESCE(end, resume_at) > ?1)
+         ORDER BY start",
+    )?;
+    let pauses = stmt.query_map(params![since, until], from_row)?.collect();
+    pauses
+}
+
+/// Pauses and untracked stretches in `[since, until)`, between the first and
+/// last thing recorded there. Open pauses run until `until` or their `resume_at`.
+pub fn gaps(sessions: &[Session], pauses: &[Pause], since: Timestamp, until: Timestamp) -> Vec<Gap> {
+    let clip = |start: Timestamp, end: Timestamp| (start.max(since), end.min(until));
+    let mut covered: Vec<(Timestamp, Timestamp)> = sessions
+        .iter()
+        .map(|session| clip(session.start, session.end))
+        .chain(pauses.iter().map(|pause| clip(pause.start, pause.end_or(until))))
+        .filter(|(start, end)| start < end)
+        .collect();
+    covered.sort();
+
+    let mut gaps: Vec<Gap> = pauses
+        .iter()
+        .map(|pause| {
+            let (start, end) = clip(pause.start, pause.end_or(until));
+            Gap { start, end, kind: GapKind::Paused, reason: pause.reason.clone() }
+        })
+        .filter(|gap| gap.start < gap.end)
+        .collect();
+    let mut reached: Option<Timestamp> = None;
+    for (start, end) in covered {
+        if let Some(reached) = reached.filter(|reached| start >= *reached + MIN_GAP) {
+            gaps.push(Gap { start: reached, end: start, kind: GapKind::Untracked, reason: None });
+        }
+        reached = Some(reached.map_or(end, |reached| reached.max(end)));
+    }
+    gaps.sort_by_key(|gap| gap.start);
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> Timestamp {
+        Timestamp::from_secs(secs)
+    }
+
+    fn session(start: u64, end: u64) -> Session {
+        Session { identifier: "code.exe".to_string(), app_name: "code.exe".to_string(), start: at(start), end: at(end) }
+    }
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        conn.execute_batch("ALTER TABLE pauses ADD COLUMN resume_at INTEGER").unwrap();
+        conn
+    }
+
+    #[test]
+    fn pauses_stay_open_until_resumed() {
+        let conn = db();
+        let pause = start(&conn, None, None, at(100)).unwrap();
+        assert_eq!(start(&conn, Some("Meeting room".into()), None, at(150)).unwrap(), Pause { reason: Some("Meeting room".into()), ..pause });
+        assert_eq!(resume(&conn, at(400)).unwrap().unwrap().end, Some(at(400)));
+        assert!(active(&conn).unwrap().is_none() && resume(&conn, at(500)).unwrap().is_none());
+        assert_eq!(list(&conn, at(300), at(1000)).unwrap().len(), 1);
+        assert_eq!(validate_reason(Some("  ".into())), Ok(None));
+    }
+
+    #[test]
+    fn timed_pauses_resume_at_the_end_of_the_countdown() {
+        let conn = db();
+        let pause = start(&conn, None, Some(30), at(100)).unwrap();
+        assert_eq!((pause.resume_at, pause.remaining(at(700))), (Some(at(1900)), Some(DurationSecs::mins(20))));
+        assert!(expire(&conn, at(1899)).unwrap().is_none());
+        // Noticed late, e.g. after a restart; the pause still ends on time
+        assert_eq!(expire(&conn, at(9000)).unwrap().unwrap().end, Some(at(1900)));
+        assert!(active(&conn).unwrap().is_none() && validate_minutes(Some(0)).is_err());
+    }
+
+    #[test]
+    fn gaps_tell_pauses_from_untracked_time() {
+        let sessions = vec![session(0, 600), session(850, 1000), session(5000, 5100)];
+        let pauses = vec![
+            Pause { id: 1, start: at(1000), end: Some(at(3000)), reason: Some("Break".into()), resume_at: None },
+            // Not expired yet while the tracker was down
+            Pause { id: 2, start: at(5100), end: None, reason: None, resume_at: Some(at(5400)) },
+        ];
+        let gaps = gaps(&sessions, &pauses, at(0), at(10_000));
+        let kinds: Vec<(u64, u64, GapKind)> = gaps.iter().map(|gap| (gap.start.as_secs(), gap.end.as_secs(), gap.kind)).collect();
+        // 600..850 is too short to count
+        assert_eq!(
+            kinds,
+            vec![(1000, 3000, GapKind::Paused), (3000, 5000, GapKind::Untracked), (5100, 5400, GapKind::Paused)]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// The weekly report as a PDF, laid out with printpdf: text and filled bars on
+// A4 pages, with a new page whenever one fills up.
+//
+// Text is set in TrueType fonts found on the system (`SYSMONITOR_PDF_FONT`
+// first, a list of paths, then the usual Latin and CJK fonts of Windows, macOS
+// and Linux), embedded as the subset of glyphs used. Each character takes the
+// first font that has it, so a Cyrillic or Chinese app title comes out right
+// next to Latin ones. Only characters none of them has fall back to Helvetica,
+// where anything outside WinAnsi is printed as `?`.
+
+use std::path::PathBuf;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use printpdf::{
+    BuiltinFont, Color, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, Pt, Rect, Rgb, TextRenderingMode,
+};
+
+use crate::goals::WeekProgress;
+use crate::reports::PeriodReport;
+
+const PAGE_WIDTH: f32 = 595.0; // A4, in points
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+const LABEL_WIDTH: f32 = 170.0;
+const BAR_WIDTH: f32 = 210.0;
+const LABEL_CHARS: usize = 32;
+const ACCENT: (f32, f32, f32) = (0.22, 0.44, 0.82);
+const DONE: (f32, f32, f32) = (0.24, 0.62, 0.35);
+const TRACK: (f32, f32, f32) = (0.92, 0.92, 0.92);
+const TEXT: (f32, f32, f32) = (0.0, 0.0, 0.0);
+
+// In order of preference; the CJK ones after the Latin ones, as their Latin
+// letters look out of place
+const FONT_FILES: &[&str] = &[
+    "C:\\Windows\\Fonts\\arial.ttf",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\malgun.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu-sans-fonts/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/google-droid-sans-fonts/DroidSansFallbackFull.ttf",
+];
+
+fn duration(seconds: u64) -> String {
+    match (seconds / 3600, seconds % 3600 / 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h {:02}m", hours, minutes),
+    }
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() <= LABEL_CHARS {
+        return text.to_string();
+    }
+    format!("{}...", text.chars().take(LABEL_CHARS - 3).collect::<String>())
+}
+
+fn color((r, g, b): (f32, f32, f32)) -> Color {
+    Color::Rgb(Rgb::new(r, g, b, None))
+}
+
+fn mm(points: f32) -> Mm {
+    Mm::from(Pt(points))
+}
+
+// TrueType fonts that can be embedded: collections and CFF outlines can't
+fn system_fonts() -> Vec<Vec<u8>> {
+    let configured: Vec<PathBuf> = std::env::var_os("SYSMONITOR_PDF_FONT").map(|paths| std::env::split_paths(&paths).collect()).unwrap_or_default();
+    let paths: Vec<PathBuf> = configured.into_iter().chain(FONT_FILES.iter().map(Into::into)).collect();
+    paths
+        .iter()
+        .filter_map(|path| std::fs::read(path).ok())
+        .filter(|bytes| ttf_parser::Face::parse(bytes, 0).is_ok_and(|face| face.tables().glyf.is_some()) && ttf_parser::fonts_in_collection(bytes).is_none())
+        .collect()
+}
+
+// `text` split into runs of the first of `fonts` fonts that `covers` each
+// character, None where none does; whitespace stays in the run it is in
+fn runs(text: &str, fonts: usize, covers: impl Fn(usize, char) -> bool) -> Vec<(Option<usize>, String)> {
+    let mut runs: Vec<(Option<usize>, String)> = Vec::new();
+    for ch in text.chars() {
+        let current = runs.last().map(|(font, _)| *font);
+        let font = match current {
+            Some(Some(font)) if ch.is_whitespace() && covers(font, ch) => Some(font),
+            _ => (0..fonts).find(|font| covers(*font, ch)),
+        };
+        match runs.last_mut() {
+            Some((last, run)) if *last == font => run.push(ch),
+            _ => runs.push((font, ch.to_string())),
+        }
+    }
+    runs
+}
+
+// What Helvetica's WinAnsi encoding can show
+fn win_ansi(text: &str) -> String {
+    text.chars().map(|ch| if matches!(ch as u32, 0x20..=0x7e | 0xa0..=0xff) { ch } else { '?' }).collect()
+}
+
+struct Font {
+    bytes: Vec<u8>,
+    added: Option<IndirectFontRef>, // Once used
+}
+
+struct Writer {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    fonts: Vec<Font>,
+    builtin: Option<IndirectFontRef>,
+    y: f32, // Baseline of the last line
+}
+
+impl Writer {
+    fn new(title: &str, fonts: Vec<Vec<u8>>) -> Self {
+        let (doc, page, layer) = PdfDocument::new(title, mm(PAGE_WIDTH), mm(PAGE_HEIGHT), "Report");
+        let layer = doc.get_page(page).get_layer(layer);
+        let fonts = fonts.into_iter().map(|bytes| Font { bytes, added: None }).collect();
+        Self { doc, layer, fonts, builtin: None, y: PAGE_HEIGHT - MARGIN }
+    }
+
+    // Moves down a line of `height`, onto a new page if it doesn't fit
+    fn advance(&mut self, height: f32) -> f32 {
+        if self.y - height < MARGIN {
+            let (page, layer) = self.doc.add_page(mm(PAGE_WIDTH), mm(PAGE_HEIGHT), "Report");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+        self.y -= height;
+        self.y
+    }
+
+    // The font `index`, or Helvetica without one or if it won't embed, and
+    // whether it was embedded
+    fn font(&mut self, index: Option<usize>) -> Option<(IndirectFontRef, bool)> {
+        if let Some(font) = index.and_then(|index| self.fonts.get_mut(index)) {
+            if font.added.is_none() {
+                font.added = self.doc.add_external_font(font.bytes.as_slice()).ok();
+            }
+            if let Some(added) = &font.added {
+                return Some((added.clone(), true));
+            }
+        }
+        if self.builtin.is_none() {
+            self.builtin = self.doc.add_builtin_font(BuiltinFont::Helvetica).ok();
+        }
+        self.builtin.clone().map(|font| (font, false))
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        let runs = {
+            let faces: Vec<Option<ttf_parser::Face>> = self.fonts.iter().map(|font| ttf_parser::Face::parse(&font.bytes, 0).ok()).collect();
+            runs(text, faces.len(), |font, ch| faces[font].as_ref().is_some_and(|face| face.glyph_index(ch).is_some()))
+        };
+        self.layer.begin_text_section();
+        self.layer.set_text_cursor(mm(x), mm(y));
+        // Bold as outlined text, which works for any font
+        if bold {
+            self.layer.set_outline_color(color(TEXT));
+            self.layer.set_outline_thickness(size / 40.0);
+            self.layer.set_text_rendering_mode(TextRenderingMode::FillStroke);
+        } else {
+            self.layer.set_text_rendering_mode(TextRenderingMode::Fill);
+        }
+        // The cursor moves on by each run, so they follow one another
+        for (index, run) in runs {
+            let Some((font, embedded)) = self.font(index) else { continue };
+            let run = if embedded { run } else { win_ansi(&run) };
+            self.layer.set_font(&font, size);
+            self.layer.write_text(run, &font);
+        }
+        self.layer.end_text_section();
+    }
+
+    fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, fill: (f32, f32, f32)) {
+        self.layer.set_fill_color(color(fill));
+        self.layer.add_rect(Rect::new(mm(x), mm(y), mm(x + width), mm(y + height)));
+        self.layer.set_fill_color(color(TEXT));
+    }
+
+    fn line(&mut self, size: f32, bold: bool, text: &str) {
+        let y = self.advance(size * 1.5);
+        self.text(MARGIN, y, size, bold, text);
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.advance(12.0);
+        self.line(13.0, true, text);
+    }
+
+    fn pair(&mut self, label: &str, value: &str) {
+        let y = self.advance(15.0);
+        self.text(MARGIN, y, 10.0, false, label);
+        self.text(MARGIN + LABEL_WIDTH, y, 10.0, true, value);
+    }
+
+    // A label, a bar `fraction` of the full width and a value after it
+    fn bar(&mut self, label: &str, fraction: f64, value: &str, fill: (f32, f32, f32)) {
+        let y = self.advance(16.0);
+        self.text(MARGIN, y, 10.0, false, &shorten(label));
+        let width = BAR_WIDTH * fraction.clamp(0.0, 1.0) as f32;
+        self.rect(MARGIN + LABEL_WIDTH, y - 1.0, BAR_WIDTH, 9.0, TRACK);
+        if width > 0.0 {
+            self.rect(MARGIN + LABEL_WIDTH, y - 1.0, width.max(1.0), 9.0, fill);
+        }
+        self.text(MARGIN + LABEL_WIDTH + BAR_WIDTH + 10.0, y, 10.0, false, value);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        // Written to memory, so there is no I/O to fail
+        self.doc.save_to_bytes().expect("PDF written to memory")
+    }
+}
+
+/// The week of `report`, with its goals' progress when there are any.
+pub fn weekly_report(report: &PeriodReport, goals: Option<&WeekProgress>, host: &str, now: u64) -> Vec<u8> {
+    let title = format!("Weekly report {}", report.period);
+    let mut pdf = Writer::new(&title, system_fonts());
+    pdf.line(20.0, true, &title);
+    let status = if report.finalized { "" } else { ", week in progress" };
+    pdf.line(11.0, false, &format!("{} to {} on {}{}", report.from, report.to, host, status));
+
+    pdf.heading("Summary");
+    pdf.pair("Tracked", &duration(report.total_secs.as_secs()));
+    pdf.pair("Active days", &report.active_days.to_string());
+    pdf.pair("Average active day", &duration(report.average_active_day_secs.as_secs()));
+    pdf.pair("Sessions", &report.sessions.to_string());
+    let change = report.change_percent.map_or("no data for last week".to_string(), |change| format!("{:+.1}%", change));
+    pdf.pair("Against last week", &change);
+
+    pdf.heading("Per day");
+    let busiest = report.trend.iter().map(|point| point.seconds.as_secs()).max().unwrap_or(0).max(1);
+    for point in &report.trend {
+        let label = NaiveDate::parse_from_str(&point.label, "%Y-%m-%d").map_or(point.label.clone(), |day| day.format("%A %d %b").to_string());
+        pdf.bar(&label, point.seconds.as_secs() as f64 / busiest as f64, &duration(point.seconds.as_secs()), ACCENT);
+    }
+
+    if !report.top_apps.is_empty() {
+        pdf.heading("Top apps");
+        let top = report.top_apps[0].seconds.as_secs().max(1);
+        for app in &report.top_apps {
+            let value = format!("{} ({:.1}%)", duration(app.seconds.as_secs()), app.percent);
+            pdf.bar(&app.app_name, app.seconds.as_secs() as f64 / top as f64, &value, ACCENT);
+        }
+    }
+    if !report.rising.is_empty() {
+        pdf.heading("Growing against last week");
+        for app in &report.rising {
+            pdf.pair(&shorten(&app.app_name), &format!("+{}", duration((app.seconds - app.previous_secs).as_secs())));
+        }
+    }
+    if let Some(goals) = goals.filter(|week| !week.goals.is_empty()) {
+        pdf.heading("Weekly goals");
+        for goal in &goals.goals {
+            let value = format!("{} of {}", duration(goal.done_secs), duration(goal.target_secs));
+            pdf.bar(&goal.category, goal.done_secs as f64 / goal.target_secs.max(1) as f64, &value, DONE);
+        }
+    }
+
+    let generated = Local.timestamp_opt(now as i64, 0).earliest().map(|time| time.format("%Y-%m-%d %H:%M").to_string());
+    pdf.advance(12.0);
+    pdf.line(8.0, false, &format!("Generated by sysmonitor {} on {}", env!("CARGO_PKG_VERSION"), generated.unwrap_or_default()));
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characters_take_the_first_font_that_has_them() {
+        // Font 0 has ASCII, font 1 letters of any script and spaces
+        let covers = |font: usize, ch: char| if font == 0 { ch.is_ascii() } else { ch.is_alphabetic() || ch == ' ' };
+        let split = runs("Mail 邮件 and Почта ✓", 2, covers);
+        let expected = [(Some(0), "Mail "), (Some(1), "邮件 "), (Some(0), "and "), (Some(1), "Почта "), (None, "✓")];
+        assert_eq!(split, expected.map(|(font, run)| (font, run.to_string())));
+        assert_eq!(runs("Café ✓", 0, covers), vec![(None, "Café ✓".to_string())]);
+        assert_eq!(win_ansi("Café (✓)"), "Café (?)");
+    }
+
+    #[test]
+    fn long_documents_get_more_pages() {
+        for fonts in [Vec::new(), system_fonts()] {
+            let mut writer = Writer::new("Test", fonts);
+            for index in 0..80 {
+                writer.line(10.0, index == 0, &format!("Line {} (Caf\u{e9} Почта \u{2713})", index));
+            }
+            let pdf = writer.finish();
+            assert!(pdf.starts_with(b"%PDF-"));
+            assert_eq!(printpdf::lopdf::Document::load_mem(&pdf).unwrap().get_pages().len(), 2);
+        }
+    }
+}
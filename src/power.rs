@@ -0,0 +1,106 @@
+// Suspend and resume notifications, so a session open when the machine goes to
+// sleep ends there instead of counting the night for the foreground app.
+//
+// Windows calls back through `PowerRegisterSuspendResumeNotification`; on Linux
+// `gdbus monitor` reports logind's `PrepareForSleep`, like `xdotool` and
+// `xprintidle` answer for the foreground window and idle time. Each event
+// carries the monotonic time it was seen, which the tracker may only get to
+// after waking up.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    Suspend(Instant),
+    Resume(Instant),
+}
+
+/// Starts listening; events arrive on the receiver until the process exits.
+/// Nothing does when the platform can't tell.
+pub fn watch() -> Receiver<PowerEvent> {
+    let (sender, events) = mpsc::channel();
+    listen(sender);
+    events
+}
+
+#[cfg(windows)]
+fn listen(sender: Sender<PowerEvent>) {
+    use std::ffi::c_void;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::Power::{PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS};
+    use windows::Win32::UI::WindowsAndMessaging::{DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND};
+
+    unsafe extern "system" fn callback(context: *const c_void, kind: u32, _setting: *const c_void) -> u32 {
+        let sender = &*(context as *const Sender<PowerEvent>);
+        let event = match kind {
+            PBT_APMSUSPEND => PowerEvent::Suspend(Instant::now()),
+            PBT_APMRESUMEAUTOMATIC => PowerEvent::Resume(Instant::now()),
+            _ => return 0,
+        };
+        let _ = sender.send(event);
+        0
+    }
+
+    // Both stay registered for the life of the process
+    let context = Box::into_raw(Box::new(sender)) as *mut c_void;
+    let parameters = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS { Callback: Some(callback), Context: context }));
+    let mut registration = std::ptr::null_mut();
+    let _ = unsafe {
+        PowerRegisterSuspendResumeNotification(DEVICE_NOTIFY_CALLBACK, HANDLE(parameters as *mut _ as isize), &mut registration)
+    };
+}
+
+#[cfg(not(windows))]
+fn listen(sender: Sender<PowerEvent>) {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    let child = Command::new("gdbus")
+        .args(["monitor", "--system", "--dest", "org.freedesktop.login1", "--object-path", "/org/freedesktop/login1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return;
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return;
+    };
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(event) = parse_gdbus(&line, Instant::now()) {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+}
+
+// e.g. `/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)`
+#[cfg_attr(windows, allow(dead_code))]
+fn parse_gdbus(line: &str, at: Instant) -> Option<PowerEvent> {
+    let (_, arguments) = line.split_once("org.freedesktop.login1.Manager.PrepareForSleep ")?;
+    match arguments.trim() {
+        "(true,)" => Some(PowerEvent::Suspend(at)),
+        "(false,)" => Some(PowerEvent::Resume(at)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logind_sleep_signals_are_recognised() {
+        let at = Instant::now();
+        let line = |arguments: &str| format!("/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep {}", arguments);
+        assert_eq!(parse_gdbus(&line("(true,)"), at), Some(PowerEvent::Suspend(at)));
+        assert_eq!(parse_gdbus(&line("(false,)"), at), Some(PowerEvent::Resume(at)));
+        assert_eq!(parse_gdbus("/org/freedesktop/login1: org.freedesktop.login1.Manager.SessionNew ('3', objectpath '/x')", at), None);
+    }
+}
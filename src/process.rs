@@ -0,0 +1,137 @@
+// Resolution of a process id to its executable.
+//
+// The platform modules provide `resolve_process`, `query_command_line` and
+// `file_description`; the types here are shared so the tracker does not care
+// which one is compiled in.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(windows))]
+mod linux;
+#[cfg(windows)]
+mod win32;
+
+#[cfg(not(windows))]
+pub use linux::*;
+#[cfg(windows)]
+pub use win32::*;
+
+pub const UNKNOWN_APP: &str = "Unknown";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessAccess {
+    Full,
+    Limited,
+    Denied,
+}
+
+impl ProcessAccess {
+    // Sessions recorded without full process access are flagged in storage
+    pub fn is_partial(self) -> bool {
+        self != ProcessAccess::Full
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub app_name: String,
+    pub image_path: Option<String>,
+    pub raw_image_path: Option<String>, // As the OS reported it, when that differs; for debugging
+    pub access: ProcessAccess,
+    #[cfg(windows)]
+    pub package_full_name: Option<String>, // Set for packaged (AppX/MSIX) processes
+}
+
+#[cfg(any(windows, test))]
+fn strip_prefix_ignore_case<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = path.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &path[prefix.len()..])
+}
+
+#[cfg(any(windows, test))]
+/// Rewrites an NT path such as `\Device\HarddiskVolume3\...` as a Win32 one.
+/// `devices` pairs drives with their devices as `QueryDosDevice` reports them,
+/// e.g. `("C:", "\Device\HarddiskVolume3")`; network paths become UNC paths.
+pub fn nt_to_dos_path(path: &str, devices: &[(String, String)]) -> Option<String> {
+    if let Some(rest) = path.strip_prefix(r"\??\") {
+        return Some(match rest.strip_prefix(r"UNC\") {
+            Some(share) => format!(r"\\{}", share),
+            None => rest.to_string(),
+        });
+    }
+    for redirector in [r"\Device\Mup\", r"\Device\LanmanRedirector\"] {
+        if let Some(mut share) = strip_prefix_ignore_case(path, redirector) {
+            // Mapped drives add components like `;LanmanRedirector\;Z:000000000001f3a1\`
+            while let Some(component) = share.strip_prefix(';') {
+                share = component.split_once('\\')?.1;
+            }
+            return Some(format!(r"\\{}", share));
+        }
+    }
+    devices.iter().find_map(|(drive, device)| {
+        let rest = strip_prefix_ignore_case(path, device)?;
+        // `HarddiskVolume1` must not match `HarddiskVolume10`
+        (rest.is_empty() || rest.starts_with('\\')).then(|| format!("{}{}", drive, rest))
+    })
+}
+
+#[cfg(any(windows, test))]
+/// UTF-16 from the OS with unpaired surrogates, which `from_utf16_lossy`
+/// would replace, written as `\u{d800}`.
+pub fn escape_wide(wide: &[u16]) -> String {
+    char::decode_utf16(wide.iter().copied())
+        .map(|unit| match unit {
+            Ok(c) => c.to_string(),
+            Err(e) => format!("\\u{{{:x}}}", e.unpaired_surrogate()),
+        })
+        .collect()
+}
+
+pub fn app_name_from_path(path: &str) -> String {
+    // Both NT device paths and Win32 paths use backslashes
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.rsplit('\\').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(UNKNOWN_APP)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<(String, String)> {
+        vec![
+            ("C:".to_string(), r"\Device\HarddiskVolume1".to_string()),
+            ("D:".to_string(), r"\Device\HarddiskVolume10".to_string()),
+        ]
+    }
+
+    #[test]
+    fn nt_paths_become_win32_paths() {
+        let convert = |path: &str| nt_to_dos_path(path, &devices());
+        assert_eq!(convert(r"\Device\HarddiskVolume10\Games\game.exe").as_deref(), Some(r"D:\Games\game.exe"));
+        assert_eq!(convert(r"\device\harddiskvolume1\Windows\notepad.exe").as_deref(), Some(r"C:\Windows\notepad.exe"));
+        assert_eq!(convert(r"\Device\Mup\server\share\tool.exe").as_deref(), Some(r"\\server\share\tool.exe"));
+        assert_eq!(
+            convert(r"\Device\Mup\;LanmanRedirector\;Z:000000000001f3a1\server\share\tool.exe").as_deref(),
+            Some(r"\\server\share\tool.exe")
+        );
+        assert_eq!(convert(r"\??\C:\tool.exe").as_deref(), Some(r"C:\tool.exe"));
+        assert_eq!(convert(r"\Device\HarddiskVolume2\tool.exe"), None);
+    }
+
+    #[test]
+    fn unpaired_surrogates_are_escaped() {
+        let mut wide: Vec<u16> = r"C:\a".encode_utf16().collect();
+        wide.push(0xD800);
+        wide.extend(".exe".encode_utf16());
+        assert_eq!(escape_wide(&wide), r"C:\a\u{d800}.exe");
+        assert_eq!(app_name_from_path(&String::from_utf16_lossy(&wide)), "a\u{fffd}.exe");
+    }
+}
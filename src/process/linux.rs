@@ -0,0 +1,123 @@
+// Linux process resolution through /proc, and the X11 foreground window via
+// `xdotool`.
+//
+// Windows programs running under Wine or Proton show up as `wine-preloader` (or
+// `wine64-preloader`) with the Windows path of the program as `argv[0]`, so the
+// exe name is taken from the command line to keep app identities the same as on
+// Windows.
+
+use std::fs;
+use std::process::Command;
+
+use super::{app_name_from_path, ProcessAccess, ProcessInfo, UNKNOWN_APP};
+
+const WINE_LOADERS: [&str; 5] = ["wine-preloader", "wine64-preloader", "wine", "wine64", "wineloader"];
+
+fn read_command_line(process_id: u32) -> Option<Vec<String>> {
+    let raw = fs::read(format!("/proc/{}/cmdline", process_id)).ok()?;
+    let args: Vec<String> = raw
+        .split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).to_string())
+        .collect();
+    (!args.is_empty()).then_some(args)
+}
+
+pub fn query_command_line(process_id: u32) -> Option<String> {
+    read_command_line(process_id).map(|args| args.join(" "))
+}
+
+fn is_wine_loader(exe_path: &str) -> bool {
+    let name = exe_path.rsplit('/').next().unwrap_or(exe_path);
+    WINE_LOADERS.contains(&name)
+}
+
+/// Windows exe name of a program running under a Wine loader: the first
+/// argument naming a `.exe`, with either separator style.
+pub fn wine_exe_name(exe_path: &str, args: &[String]) -> Option<String> {
+    if !is_wine_loader(exe_path) {
+        return None;
+    }
+    args.iter()
+        .find(|arg| arg.to_lowercase().ends_with(".exe"))
+        .and_then(|arg| arg.rsplit(['\\', '/']).next())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+}
+
+// ELF executables carry no description
+pub fn file_description(_image_path: &str) -> Option<String> {
+    None
+}
+
+pub fn resolve_process(process_id: u32) -> ProcessInfo {
+    // Reading another user's /proc/<pid>/exe needs ptrace access
+    let exe = fs::read_link(format!("/proc/{}/exe", process_id)).ok();
+    let exe_path = exe.as_ref().map(|path| path.to_string_lossy().to_string());
+    // Non-UTF-8 paths are kept escaped too
+    let raw_image_path = exe.filter(|path| path.to_str().is_none()).map(|path| format!("{:?}", path));
+    let args = read_command_line(process_id).unwrap_or_default();
+
+    let (app_name, access) = match exe_path {
+        Some(ref path) => (
+            wine_exe_name(path, &args).unwrap_or_else(|| app_name_from_path(path)),
+            ProcessAccess::Full,
+        ),
+        None => match fs::read_to_string(format!("/proc/{}/comm", process_id)) {
+            Ok(comm) => (comm.trim().to_string(), ProcessAccess::Limited),
+            Err(_) => (UNKNOWN_APP.to_string(), ProcessAccess::Denied),
+        },
+    };
+
+    ProcessInfo {
+        app_name,
+        image_path: exe_path,
+        raw_image_path,
+        access,
+    }
+}
+
+fn xdotool(args: &[&str]) -> Option<String> {
+    let output = Command::new("xdotool").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim_end_matches('\n').to_string())
+}
+
+/// Title, window id and process id of the focused X11 window.
+pub fn foreground_window() -> Option<(String, u64, u32)> {
+    let window = xdotool(&["getactivewindow"])?;
+    let title = xdotool(&["getwindowname", &window]).unwrap_or_default();
+    let process_id = xdotool(&["getwindowpid", &window])?.trim().parse().ok()?;
+    Some((title, window.trim().parse().ok()?, process_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn wine_programs_resolve_to_their_exe() {
+        assert_eq!(
+            wine_exe_name("/usr/bin/wine64-preloader", &args(&[r"C:\Program Files\Notepad++\notepad++.exe"])),
+            Some("notepad++.exe".to_string())
+        );
+        assert_eq!(
+            wine_exe_name(
+                "/home/u/.steam/steam/steamapps/common/Proton 8.0/files/bin/wine64-preloader",
+                &args(&["/home/u/.steam/steam/steamapps/common/Game/Game.exe", "-dx11"])
+            ),
+            Some("Game.exe".to_string())
+        );
+    }
+
+    #[test]
+    fn native_programs_are_left_alone() {
+        assert_eq!(wine_exe_name("/usr/bin/firefox", &args(&["/usr/bin/firefox", "setup.exe"])), None);
+    }
+}
@@ -0,0 +1,237 @@
+// Win32 process resolution.
+//
+// `PROCESS_QUERY_INFORMATION` is refused for elevated processes when the monitor
+// runs unelevated, so resolution falls back to `PROCESS_QUERY_LIMITED_INFORMATION`
+// with `QueryFullProcessImageNameW`, which Windows grants across integrity levels.
+// `GetProcessImageFileNameW` reports NT device paths, which are mapped back to
+// drive letters with `QueryDosDeviceW`; the path as reported is kept alongside.
+
+use std::sync::Mutex;
+
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Wdk::System::Threading::{NtQueryInformationProcess, ProcessCommandLineInformation},
+    Win32::Foundation::{CloseHandle, BOOL, HANDLE, HWND, LPARAM, UNICODE_STRING},
+    Win32::Storage::FileSystem::{GetFileVersionInfoSizeW, GetFileVersionInfoW, QueryDosDeviceW, VerQueryValueW},
+    Win32::Storage::Packaging::Appx::GetPackageFullName,
+    Win32::System::ProcessStatus::GetProcessImageFileNameW,
+    Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_ACCESS_RIGHTS, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_INFORMATION, PROCESS_QUERY_LIMITED_INFORMATION,
+    },
+    Win32::UI::WindowsAndMessaging::{EnumChildWindows, GetWindowThreadProcessId},
+};
+
+use super::{app_name_from_path, escape_wide, nt_to_dos_path, ProcessAccess, ProcessInfo, UNKNOWN_APP};
+
+// Hosts the frames of UWP apps; the real app lives in a child window
+pub const FRAME_HOST_APP: &str = "ApplicationFrameHost.exe";
+
+// Closes the wrapped handle when dropped so every exit path releases it
+struct OwnedHandle(HANDLE);
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+fn open_process(access: PROCESS_ACCESS_RIGHTS, process_id: u32) -> Option<OwnedHandle> {
+    unsafe { OpenProcess(access, BOOL(0), process_id).ok().map(OwnedHandle) }
+}
+
+// Drives and the NT devices behind them, reloaded when a path matches none
+static DOS_DEVICES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+fn query_dos_devices() -> Vec<(String, String)> {
+    ('A'..='Z')
+        .filter_map(|letter| {
+            let drive = format!("{}:", letter);
+            let name: Vec<u16> = drive.encode_utf16().chain(Some(0)).collect();
+            let mut target = [0u16; 1024];
+            let len = unsafe { QueryDosDeviceW(PCWSTR(name.as_ptr()), Some(&mut target)) };
+            // A null-separated list; the first entry is the current mapping
+            let device = String::from_utf16_lossy(&target[..len as usize]).split('\0').next()?.to_string();
+            (!device.is_empty()).then_some((drive, device))
+        })
+        .collect()
+}
+
+fn to_dos_path(path: &str) -> Option<String> {
+    let mut devices = DOS_DEVICES.lock().unwrap();
+    if let Some(path) = nt_to_dos_path(path, &devices) {
+        return Some(path);
+    }
+    // A drive may have been mounted since
+    *devices = query_dos_devices();
+    nt_to_dos_path(path, &devices)
+}
+
+// Friendly path, and the raw one when it differs
+fn image_paths(wide: &[u16]) -> (String, Option<String>) {
+    let raw = escape_wide(wide);
+    let lossy = String::from_utf16_lossy(wide);
+    // Win32 paths (`C:\`, `\\server\share`) need no mapping
+    let nt = lossy.starts_with('\\') && !lossy.starts_with(r"\\");
+    let path = nt.then(|| to_dos_path(&lossy)).flatten().unwrap_or(lossy);
+    let raw = (raw != path).then_some(raw);
+    (path, raw)
+}
+
+fn query_image_file_name(handle: &OwnedHandle) -> Option<(String, Option<String>)> {
+    let mut buffer = [0u16; 1024];
+    let len = unsafe { GetProcessImageFileNameW(handle.0, &mut buffer) };
+    (len > 0).then(|| image_paths(&buffer[..len as usize]))
+}
+
+fn query_full_image_name(handle: &OwnedHandle) -> Option<(String, Option<String>)> {
+    let mut buffer = [0u16; 1024];
+    let mut len = buffer.len() as u32;
+    unsafe {
+        QueryFullProcessImageNameW(handle.0, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut len).ok()?;
+    }
+    (len > 0).then(|| image_paths(&buffer[..len as usize]))
+}
+
+// Works with limited access; fails with APPMODEL_ERROR_NO_PACKAGE for ordinary processes
+fn query_package_full_name(handle: &OwnedHandle) -> Option<String> {
+    let mut buffer = [0u16; 256];
+    let mut len = buffer.len() as u32;
+    unsafe {
+        GetPackageFullName(handle.0, &mut len, PWSTR(buffer.as_mut_ptr())).ok()?;
+    }
+    // The reported length includes the terminating null
+    let len = (len as usize).saturating_sub(1).min(buffer.len());
+    (len > 0).then(|| String::from_utf16_lossy(&buffer[..len]))
+}
+
+/// Command line of another process via `ProcessCommandLineInformation`, which
+/// only needs limited query access (Windows 8.1+).
+pub fn query_command_line(process_id: u32) -> Option<String> {
+    let handle = open_process(PROCESS_QUERY_LIMITED_INFORMATION, process_id)?;
+    // The UNICODE_STRING header is followed by its buffer in the same allocation
+    let mut buffer = vec![0u64; 4096];
+    let mut returned = 0u32;
+    let status = unsafe {
+        NtQueryInformationProcess(
+            handle.0,
+            ProcessCommandLineInformation,
+            buffer.as_mut_ptr() as *mut _,
+            (buffer.len() * std::mem::size_of::<u64>()) as u32,
+            &mut returned,
+        )
+    };
+    if status.is_err() {
+        return None;
+    }
+    unsafe {
+        let header = &*(buffer.as_ptr() as *const UNICODE_STRING);
+        if header.Buffer.is_null() || header.Length == 0 {
+            return None;
+        }
+        let chars = std::slice::from_raw_parts(header.Buffer.0, header.Length as usize / 2);
+        Some(String::from_utf16_lossy(chars))
+    }
+}
+
+// A value of a VERSIONINFO block by its path, such as `\VarFileInfo\Translation`, as UTF-16 units
+unsafe fn version_value(data: &[u8], query: &str) -> Option<&[u16]> {
+    let query: Vec<u16> = query.encode_utf16().chain(Some(0)).collect();
+    let mut value = std::ptr::null_mut();
+    let mut len = 0u32;
+    if !VerQueryValueW(data.as_ptr() as *const _, PCWSTR(query.as_ptr()), &mut value, &mut len).as_bool() || value.is_null() || len == 0 {
+        return None;
+    }
+    Some(std::slice::from_raw_parts(value as *const u16, len as usize))
+}
+
+/// `FileDescription` from the version resource of an executable, such as
+/// "Google Chrome" for chrome.exe, in its first listed language.
+pub fn file_description(image_path: &str) -> Option<String> {
+    let path: Vec<u16> = image_path.encode_utf16().chain(Some(0)).collect();
+    unsafe {
+        let size = GetFileVersionInfoSizeW(PCWSTR(path.as_ptr()), None);
+        if size == 0 {
+            return None;
+        }
+        let mut data = vec![0u8; size as usize];
+        GetFileVersionInfoW(PCWSTR(path.as_ptr()), 0, size, data.as_mut_ptr() as *mut _).ok()?;
+        // (language, code page) pairs; US English in Unicode is the usual default
+        let mut translations: Vec<(u16, u16)> = version_value(&data, "\\VarFileInfo\\Translation")
+            .map(|pairs| pairs.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect())
+            .unwrap_or_default();
+        translations.push((0x0409, 0x04b0));
+        translations.into_iter().find_map(|(language, code_page)| {
+            let query = format!("\\StringFileInfo\\{:04x}{:04x}\\FileDescription", language, code_page);
+            // The length counts the terminating null
+            let wide = version_value(&data, &query)?;
+            let end = wide.iter().position(|&unit| unit == 0).unwrap_or(wide.len());
+            Some(String::from_utf16_lossy(&wide[..end]))
+        })
+    }
+}
+
+pub fn resolve_process(process_id: u32) -> ProcessInfo {
+    if let Some(handle) = open_process(PROCESS_QUERY_INFORMATION, process_id) {
+        if let Some((path, raw_image_path)) = query_image_file_name(&handle) {
+            return ProcessInfo {
+                app_name: app_name_from_path(&path),
+                image_path: Some(path),
+                raw_image_path,
+                access: ProcessAccess::Full,
+                package_full_name: query_package_full_name(&handle),
+            };
+        }
+    }
+
+    if let Some(handle) = open_process(PROCESS_QUERY_LIMITED_INFORMATION, process_id) {
+        if let Some((path, raw_image_path)) = query_full_image_name(&handle) {
+            return ProcessInfo {
+                app_name: app_name_from_path(&path),
+                image_path: Some(path),
+                raw_image_path,
+                access: ProcessAccess::Limited,
+                package_full_name: query_package_full_name(&handle),
+            };
+        }
+    }
+
+    ProcessInfo {
+        app_name: UNKNOWN_APP.to_string(),
+        image_path: None,
+        raw_image_path: None,
+        access: ProcessAccess::Denied,
+        package_full_name: None,
+    }
+}
+
+struct HostedSearch {
+    host_process_id: u32,
+    found: Option<u32>,
+}
+
+unsafe extern "system" fn find_hosted_child(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let search = &mut *(lparam.0 as *mut HostedSearch);
+    let mut process_id = 0u32;
+    GetWindowThreadProcessId(hwnd, Some(&mut process_id));
+    if process_id != 0 && process_id != search.host_process_id {
+        search.found = Some(process_id);
+        return BOOL(0); // Stop enumerating
+    }
+    BOOL(1)
+}
+
+/// For a window owned by `ApplicationFrameHost.exe`, returns the process id of
+/// the UWP app whose content is hosted inside it.
+pub fn find_hosted_process(hwnd: HWND, host_process_id: u32) -> Option<u32> {
+    let mut search = HostedSearch {
+        host_process_id,
+        found: None,
+    };
+    unsafe {
+        let _ = EnumChildWindows(hwnd, Some(find_hosted_child), LPARAM(&mut search as *mut _ as isize));
+    }
+    search.found
+}
@@ -0,0 +1,84 @@
+// Per-app tracking profiles from the `app_profiles` section of the config.
+//
+// Profiles are keyed by app name (the resolved name such as a packaged app's
+// display name, or the exe name) and matched case-insensitively. Apps without
+// a profile get `AppProfile::default()`, which is the normal tracker behaviour.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+const MIN_POLL_INTERVAL_MS: u64 = 50; // Below this the loop is mostly overhead
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Extractor {
+    #[default]
+    Auto, // Pick by browser exe name
+    Chromium,
+    Firefox,
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppProfile {
+    pub track: bool, // `false` ignores the app entirely
+    pub poll_interval_ms: u64, // Sampling interval while the app has focus
+    pub capture_titles: bool,
+    pub capture_urls: bool,
+    pub extractor: Extractor,
+}
+
+impl Default for AppProfile {
+    fn default() -> Self {
+        Self {
+            track: true,
+            poll_interval_ms: DEFAULT_POLL_INTERVAL_MS,
+            capture_titles: true,
+            capture_urls: true,
+            extractor: Extractor::Auto,
+        }
+    }
+}
+
+impl AppProfile {
+    pub fn poll_interval_ms(&self) -> u64 {
+        self.poll_interval_ms.max(MIN_POLL_INTERVAL_MS)
+    }
+}
+
+/// Profile for an app, trying each candidate name in order.
+pub fn profile_for(profiles: &BTreeMap<String, AppProfile>, names: &[&str]) -> AppProfile {
+    names
+        .iter()
+        .find_map(|name| {
+            profiles
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, profile)| profile.clone())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_fields_keep_defaults() {
+        let profiles: BTreeMap<String, AppProfile> = serde_json::from_str(
+            r#"{ "Chrome.exe": { "poll_interval_ms": 200, "extractor": "chromium" }, "keepass.exe": { "track": false } }"#,
+        )
+        .unwrap();
+
+        let chrome = profile_for(&profiles, &["Google Chrome", "chrome.exe"]);
+        assert_eq!(chrome.poll_interval_ms(), 200);
+        assert_eq!(chrome.extractor, Extractor::Chromium);
+        assert!(chrome.track && chrome.capture_titles && chrome.capture_urls);
+
+        assert!(!profile_for(&profiles, &["KeePass.exe"]).track);
+        assert_eq!(profile_for(&profiles, &["code.exe"]), AppProfile::default());
+    }
+}
@@ -0,0 +1,333 @@
+// Saved queries: named stats or timeline queries to come back to.
+//
+// A saved query is a panel query (see `panels::Query`) or a timeline, over a
+// range given either as a preset such as `last_sprint` or as fixed dates.
+// Presets are resolved when the query runs, so "last week" is always the week
+// before this one. Sprints are `sprint_days` long and counted from
+// `sprint_start` in the `queries` section of the config. Queries are stored in
+// `saved_queries` by name through `/api/saved-queries`, bookmarked on the
+// dashboard by name (see `dashboard::Layout::bookmarks`) and run from the
+// command line with `sysmonitor query --saved <name>`.
+
+use chrono::{Datelike, Days, NaiveDate};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::categories::CategoryConfig;
+use crate::panels::{self, Metric, QueryResult};
+use crate::reports::{self, Period};
+use crate::timeline::{self, TimelineWindow};
+use crate::units::Timestamp;
+
+const MAX_QUERIES: usize = 100;
+const MAX_NAME_LEN: usize = 64;
+const MAX_DESCRIPTION_CHARS: usize = 200;
+const MAX_RANGE_DAYS: u64 = 366;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryConfig {
+    pub sprint_days: u32,
+    pub sprint_start: Option<NaiveDate>, // First day of any one sprint; sprint presets need it
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self { sprint_days: 14, sprint_start: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    #[default]
+    Stats,
+    Timeline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    Today,
+    Yesterday,
+    ThisWeek,
+    LastWeek,
+    Last7Days,
+    Last30Days,
+    ThisMonth,
+    LastMonth,
+    ThisSprint,
+    LastSprint,
+}
+
+/// A preset, or `from` to `to` (today by default), both days included.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Range {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<Preset>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub kind: Kind,
+    pub range: Range,
+    #[serde(flatten)]
+    pub query: panels::Query, // Only the metric applies to timelines
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredQuery {
+    pub name: String,
+    #[serde(flatten)]
+    pub query: SavedQuery,
+    pub updated_at: Timestamp,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Output {
+    Stats(QueryResult),
+    Timeline(TimelineWindow),
+}
+
+/// A saved query run over the days its range resolved to.
+#[derive(Debug, Clone, Serialize)]
+pub struct Ran {
+    pub name: String,
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+    #[serde(flatten)]
+    pub output: Output,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS saved_queries (
+            name TEXT PRIMARY KEY,
+            query TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.') {
+        return Err(format!("query name '{}' must be 1 to {} letters, digits, '-', '_' or '.'", name, MAX_NAME_LEN));
+    }
+    Ok(())
+}
+
+pub fn validate(query: &SavedQuery, config: &QueryConfig) -> Result<(), String> {
+    if query.description.as_ref().is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_CHARS) {
+        return Err(format!("description must be at most {} characters", MAX_DESCRIPTION_CHARS));
+    }
+    let range = &query.range;
+    match (range.preset, range.from) {
+        (Some(_), None) if range.to.is_none() => {}
+        (None, Some(from)) => {
+            let to = range.to.unwrap_or(from);
+            if to < from || (to - from).num_days() as u64 >= MAX_RANGE_DAYS {
+                return Err(format!("range must run forwards over at most {} days", MAX_RANGE_DAYS));
+            }
+        }
+        _ => return Err("range takes either a preset or from (and optionally to)".to_string()),
+    }
+    if matches!(range.preset, Some(Preset::ThisSprint | Preset::LastSprint)) && (config.sprint_start.is_none() || config.sprint_days == 0) {
+        return Err("sprint presets need queries.sprint_start and a sprint_days above 0 in the config".to_string());
+    }
+    if query.kind == Kind::Timeline && query.query != (panels::Query { metric: query.query.metric, ..panels::Query::default() }) {
+        return Err("timeline queries take no filters, group_by or limit".to_string());
+    }
+    panels::validate_query(&query.query)
+}
+
+/// The first and last day of `range` as of `today`.
+pub fn resolve(range: &Range, config: &QueryConfig, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), String> {
+    let days = |count: u64| Days::new(count);
+    let Some(preset) = range.preset else {
+        let from = range.from.ok_or("range has neither a preset nor from")?;
+        return Ok((from, range.to.unwrap_or(today)));
+    };
+    let sprint = || {
+        let start = config.sprint_start.filter(|_| config.sprint_days > 0).ok_or("sprint presets need queries.sprint_start")?;
+        let length = i64::from(config.sprint_days);
+        let index = (today - start).num_days().div_euclid(length);
+        Ok::<_, String>((start + chrono::Duration::days(index * length), length as u64))
+    };
+    Ok(match preset {
+        Preset::Today => (today, today),
+        Preset::Yesterday => (today - days(1), today - days(1)),
+        Preset::ThisWeek => (Period::week_of(today).bounds().0, today),
+        Preset::LastWeek => {
+            let (first, next) = Period::week_of(today).previous().bounds();
+            (first, next - days(1))
+        }
+        Preset::Last7Days => (today - days(6), today),
+        Preset::Last30Days => (today - days(29), today),
+        Preset::ThisMonth => (Period::Month(today.year(), today.month()).bounds().0, today),
+        Preset::LastMonth => {
+            let (first, next) = Period::Month(today.year(), today.month()).previous().bounds();
+            (first, next - days(1))
+        }
+        Preset::ThisSprint => (sprint()?.0, today),
+        Preset::LastSprint => {
+            let (start, length) = sprint()?;
+            (start - days(length), start - days(1))
+        }
+    })
+}
+
+pub fn list(conn: &Connection) -> SqlResult<Vec<StoredQuery>> {
+    let mut stmt = conn.prepare("SELECT name, query, updated_at FROM saved_queries ORDER BY name")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?)))?;
+    let mut queries = Vec::new();
+    for row in rows {
+        let (name, query, updated_at) = row?;
+        // Like panels, a query this version can't read is left out
+        if let Ok(query) = serde_json::from_str(&query) {
+            queries.push(StoredQuery { name, query, updated_at });
+        }
+    }
+    Ok(queries)
+}
+
+pub fn get(conn: &Connection, name: &str) -> SqlResult<Option<StoredQuery>> {
+    let stored: Option<(String, Timestamp)> = conn
+        .query_row("SELECT query, updated_at FROM saved_queries WHERE name = ?1", params![name], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()?;
+    Ok(stored.and_then(|(query, updated_at)| {
+        Some(StoredQuery { name: name.to_string(), query: serde_json::from_str(&query).ok()?, updated_at })
+    }))
+}
+
+/// Creates or replaces query `name`; Err when it would be one too many.
+pub fn save(conn: &Connection, name: &str, query: &SavedQuery, now: Timestamp) -> SqlResult<Result<(), String>> {
+    let exists = conn.query_row("SELECT 1 FROM saved_queries WHERE name = ?1", params![name], |_| Ok(())).optional()?.is_some();
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM saved_queries", [], |row| row.get(0))?;
+    if !exists && count as usize >= MAX_QUERIES {
+        return Ok(Err(format!("at most {} queries can be saved", MAX_QUERIES)));
+    }
+    conn.execute(
+        "INSERT INTO saved_queries (name, query, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name) DO UPDATE SET query = excluded.query, updated_at = excluded.updated_at",
+        params![name, serde_json::to_string(query).unwrap_or_default(), now],
+    )?;
+    Ok(Ok(()))
+}
+
+pub fn delete(conn: &Connection, name: &str) -> SqlResult<bool> {
+    Ok(conn.execute("DELETE FROM saved_queries WHERE name = ?1", params![name])? > 0)
+}
+
+/// Runs `stored` over its range as of `now`. Timelines use the closest zoom
+/// level that covers the range, starting where it starts.
+pub fn run(conn: &Connection, categories: &CategoryConfig, config: &QueryConfig, stored: &StoredQuery, now: u64) -> Result<Ran, String> {
+    let (first, last) = resolve(&stored.query.range, config, reports::local_date(now))?;
+    let since = reports::day_bounds(first).0.max(0) as u64;
+    let until = (reports::day_bounds(last).1.max(0) as u64).min(now).max(since);
+    let output = match stored.query.kind {
+        Kind::Stats => Output::Stats(panels::run(conn, categories, &stored.query.query, since, until).map_err(|e| e.to_string())?),
+        Kind::Timeline => {
+            let span = reports::day_bounds(last).1.max(0) as u64 - since;
+            let zoom = timeline::LEVELS.iter().position(|&(level, _)| level >= span).unwrap_or(timeline::LEVELS.len() - 1);
+            Output::Timeline(timeline::window(conn, since + timeline::LEVELS[zoom].0 / 2, zoom)?)
+        }
+    };
+    Ok(Ran { name: stored.name.clone(), first, last, output })
+}
+
+fn format_time(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map_or_else(|| "-".to_string(), |time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+}
+
+fn duration(seconds: u64) -> String {
+    match (seconds / 3600, seconds % 3600 / 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h {:02}m", hours, minutes),
+    }
+}
+
+/// `sysmonitor query list` and `sysmonitor query --saved <name> [--json]`.
+pub fn run_command(conn: &Connection, categories: &CategoryConfig, config: &QueryConfig, args: &[String], now: u64) -> Result<(), String> {
+    let sql = |e: rusqlite::Error| e.to_string();
+    if args.first().map(String::as_str) == Some("list") {
+        let queries = list(conn).map_err(sql)?;
+        if queries.is_empty() {
+            println!("No saved queries");
+        }
+        for stored in queries {
+            match stored.query.description {
+                Some(description) => println!("{}  {}", stored.name, description),
+                None => println!("{}", stored.name),
+            }
+        }
+        return Ok(());
+    }
+    let usage = "usage: sysmonitor query list | query --saved <name> [--json]";
+    let name = args.iter().position(|arg| arg == "--saved").and_then(|index| args.get(index + 1)).ok_or(usage)?;
+    let stored = get(conn, name).map_err(sql)?.ok_or_else(|| format!("No saved query named '{}'", name))?;
+    let ran = run(conn, categories, config, &stored, now)?;
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", serde_json::to_string_pretty(&ran).map_err(|e| e.to_string())?);
+        return Ok(());
+    }
+    println!("{}: {} to {}", ran.name, ran.first, ran.last);
+    match ran.output {
+        Output::Stats(result) => {
+            let value = |value: u64| if result.metric == Metric::Seconds { duration(value) } else { value.to_string() };
+            println!("Total: {}", value(result.total));
+            for group in &result.groups {
+                println!("  {}  {}", group.key, value(group.value));
+            }
+        }
+        Output::Timeline(window) => {
+            for bucket in &window.buckets {
+                println!("  {}  {}  {} ({})", format_time(bucket.start), duration(bucket.seconds), bucket.top_app, duration(bucket.top_seconds));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_resolve_relative_to_today() {
+        let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+        let today = date("2026-03-11"); // A Wednesday
+        let config = QueryConfig { sprint_days: 14, sprint_start: Some(date("2026-01-05")) };
+        let preset = |preset| resolve(&Range { preset: Some(preset), ..Range::default() }, &config, today).unwrap();
+        assert_eq!(preset(Preset::LastWeek), (date("2026-03-02"), date("2026-03-08")));
+        assert_eq!(preset(Preset::ThisMonth), (date("2026-03-01"), today));
+        assert_eq!(preset(Preset::LastMonth), (date("2026-02-01"), date("2026-02-28")));
+        assert_eq!(preset(Preset::ThisSprint), (date("2026-03-02"), today));
+        assert_eq!(preset(Preset::LastSprint), (date("2026-02-16"), date("2026-03-01")));
+
+        let query: SavedQuery = serde_json::from_str(r#"{"range": {"preset": "last_sprint"}, "group_by": "app"}"#).unwrap();
+        assert!(validate(&query, &config).is_ok());
+        assert!(validate(&query, &QueryConfig::default()).unwrap_err().contains("sprint_start"));
+        let timeline = SavedQuery { kind: Kind::Timeline, ..query.clone() };
+        assert!(validate(&timeline, &config).unwrap_err().contains("group_by"));
+        let both = SavedQuery { range: Range { from: Some(today), ..query.range.clone() }, ..query };
+        assert!(validate(&both, &config).is_err());
+
+        let conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        save(&conn, "sprint-review", &both, Timestamp::from_secs(100)).unwrap().unwrap();
+        assert_eq!(get(&conn, "sprint-review").unwrap().map(|stored| stored.query), Some(both));
+        assert!(delete(&conn, "sprint-review").unwrap() && list(&conn).unwrap().is_empty());
+    }
+}
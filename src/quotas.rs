@@ -0,0 +1,185 @@
+// Ingest quotas per token and per source.
+//
+// Every caller (its token's name, or `local`) has a budget of events per minute
+// and bytes per UTC day, `default` unless `tokens` has one for it; a batch
+// `source` listed under `sources` has a second budget shared by all tokens. A
+// batch that would go over any of them is refused whole with 429 before
+// anything is stored, and none of its budgets are charged. Counters live in
+// memory, so a restart starts them over.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+const MINUTE_SECS: u64 = 60;
+const DAY_SECS: u64 = 86400;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quota {
+    pub events_per_minute: Option<u64>, // Unlimited when missing
+    pub bytes_per_day: Option<u64>,     // Request bodies as sent
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub default: Quota,
+    pub tokens: BTreeMap<String, Quota>,  // By token name
+    pub sources: BTreeMap<String, Quota>, // By batch `source`
+}
+
+/// What is left of the tightest budget of one kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Status {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_secs: u64, // Until the window starts over
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Decision {
+    pub events: Option<Status>,
+    pub bytes: Option<Status>,
+    pub exceeded: Option<String>, // Refused when set
+    pub retry_after_secs: u64,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    minute: u64,
+    events: u64,
+    day: u64,
+    bytes: u64,
+}
+
+impl Usage {
+    fn roll(&mut self, now: u64) {
+        let (minute, day) = (now / MINUTE_SECS, now / DAY_SECS);
+        if self.minute != minute {
+            self.minute = minute;
+            self.events = 0;
+        }
+        if self.day != day {
+            self.day = day;
+            self.bytes = 0;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct QuotaTracker {
+    usage: Mutex<HashMap<String, Usage>>, // By "token:<name>" or "source:<source>"
+}
+
+// The budgets a request falls under, keyed as in `QuotaTracker::usage`
+fn budgets(config: &QuotaConfig, caller: &str, source: Option<&str>) -> Vec<(String, String, Quota)> {
+    let mut budgets = vec![(
+        format!("token:{}", caller),
+        format!("token '{}'", caller),
+        config.tokens.get(caller).copied().unwrap_or(config.default),
+    )];
+    if let Some((source, quota)) = source.and_then(|source| config.sources.get_key_value(source)) {
+        budgets.push((format!("source:{}", source), format!("source '{}'", source), *quota));
+    }
+    budgets
+}
+
+// The tighter of two statuses
+fn tighter(current: Option<Status>, next: Status) -> Option<Status> {
+    match current {
+        Some(current) if current.remaining <= next.remaining => Some(current),
+        _ => Some(next),
+    }
+}
+
+impl QuotaTracker {
+    /// Charges `events` and `bytes` to every budget of the request, or to none
+    /// of them when one would go over.
+    pub fn charge(&self, config: &QuotaConfig, caller: &str, source: Option<&str>, events: u64, bytes: u64, now: u64) -> Decision {
+        let budgets = budgets(config, caller, source);
+        let mut usage = self.usage.lock().unwrap();
+        let mut decision = Decision { events: None, bytes: None, exceeded: None, retry_after_secs: 0 };
+        let minute_reset = MINUTE_SECS - now % MINUTE_SECS;
+        let day_reset = DAY_SECS - now % DAY_SECS;
+        for (key, name, quota) in &budgets {
+            let used = usage.entry(key.clone()).or_default();
+            used.roll(now);
+            if let Some(limit) = quota.events_per_minute {
+                if used.events + events > limit && decision.exceeded.is_none() {
+                    decision.exceeded = Some(format!("{} may send {} events per minute", name, limit));
+                    decision.retry_after_secs = minute_reset;
+                }
+                let remaining = limit.saturating_sub(used.events);
+                decision.events = tighter(decision.events, Status { limit, remaining, reset_secs: minute_reset });
+            }
+            if let Some(limit) = quota.bytes_per_day {
+                if used.bytes + bytes > limit && decision.exceeded.is_none() {
+                    decision.exceeded = Some(format!("{} may send {} bytes per day", name, limit));
+                    decision.retry_after_secs = day_reset;
+                }
+                let remaining = limit.saturating_sub(used.bytes);
+                decision.bytes = tighter(decision.bytes, Status { limit, remaining, reset_secs: day_reset });
+            }
+        }
+        if decision.exceeded.is_some() {
+            return decision;
+        }
+        for (key, _, _) in &budgets {
+            let used = usage.get_mut(key).unwrap();
+            used.events += events;
+            used.bytes += bytes;
+        }
+        // Remaining after this request
+        if let Some(ref mut status) = decision.events {
+            status.remaining = status.remaining.saturating_sub(events);
+        }
+        if let Some(ref mut status) = decision.bytes {
+            status.remaining = status.remaining.saturating_sub(bytes);
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> QuotaConfig {
+        serde_json::from_value(serde_json::json!({
+            "default": { "events_per_minute": 100 },
+            "tokens": { "phone": { "events_per_minute": 10, "bytes_per_day": 1000 } },
+            "sources": { "plugin": { "events_per_minute": 50 } },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn batches_over_a_budget_are_refused_without_charging() {
+        let (config, tracker) = (config(), QuotaTracker::default());
+        let now = 1_760_000_000 - 1_760_000_000 % 60;
+        let first = tracker.charge(&config, "phone", None, 8, 400, now);
+        assert_eq!((first.exceeded.as_deref(), first.events.unwrap().remaining), (None, 2));
+        assert_eq!(first.bytes, Some(Status { limit: 1000, remaining: 600, reset_secs: 86400 - now % 86400 }));
+
+        let refused = tracker.charge(&config, "phone", None, 3, 10, now + 5);
+        assert_eq!(refused.exceeded.as_deref(), Some("token 'phone' may send 10 events per minute"));
+        assert_eq!((refused.retry_after_secs, refused.events.unwrap().remaining), (55, 2));
+        // Nothing was charged, and the next minute starts over
+        assert!(tracker.charge(&config, "phone", None, 2, 10, now + 6).exceeded.is_none());
+        assert_eq!(tracker.charge(&config, "phone", None, 10, 10, now + 60).events.unwrap().remaining, 0);
+    }
+
+    #[test]
+    fn sources_share_a_budget_across_tokens() {
+        let (config, tracker) = (config(), QuotaTracker::default());
+        let now = 1_760_000_000 - 1_760_000_000 % 60;
+        assert!(tracker.charge(&config, "laptop", Some("plugin"), 30, 0, now).exceeded.is_none());
+        let refused = tracker.charge(&config, "desktop", Some("plugin"), 30, 0, now);
+        assert_eq!(refused.exceeded.as_deref(), Some("source 'plugin' may send 50 events per minute"));
+        // The tightest budget is the one reported
+        assert_eq!(refused.events.unwrap(), Status { limit: 50, remaining: 20, reset_secs: 60 });
+        assert!(tracker.charge(&config, "desktop", Some("import"), 30, 0, now).exceeded.is_none());
+    }
+}
@@ -0,0 +1,63 @@
+// How often a dashboard should ask again, from the `refresh` section of the
+// config.
+//
+// Dashboard and stats answers carry a `meta` block next to `data`: the poll
+// interval the server recommends and when the data last reached the database.
+// The live dashboard moves with the tracker, so it is worth asking every
+// `poll_secs`; stats only change when a flush lands, so asking more often than
+// the flush interval gets the same answer again. While tracking is paused
+// nothing changes, and `paused_poll_secs` applies. `last_flush` lets a
+// frontend show "data as of" instead of guessing.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RefreshConfig {
+    pub poll_secs: u64,
+    pub paused_poll_secs: u64,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self { poll_secs: 5, paused_poll_secs: 60 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    Live, // Follows the tracker, e.g. the dashboard
+    Stored, // Read from the database, changes with each flush
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Meta {
+    pub poll_secs: u64,
+    pub last_flush: Option<u64>, // None before the first flush since starting
+    pub generated_at: u64,
+}
+
+/// The hints for an answer of `freshness` generated at `now`.
+pub fn meta(config: &RefreshConfig, freshness: Freshness, paused: bool, flush_interval: u64, last_flush: Option<u64>, now: u64) -> Meta {
+    let poll_secs = match (paused, freshness) {
+        (true, _) => config.paused_poll_secs,
+        (false, Freshness::Live) => config.poll_secs,
+        (false, Freshness::Stored) => config.poll_secs.max(flush_interval),
+    };
+    Meta { poll_secs: poll_secs.max(1), last_flush, generated_at: now }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_poll_no_faster_than_flushes_and_pauses_poll_slowly() {
+        let config = RefreshConfig::default();
+        assert_eq!(meta(&config, Freshness::Live, false, 30, Some(990), 1_000), Meta { poll_secs: 5, last_flush: Some(990), generated_at: 1_000 });
+        assert_eq!(meta(&config, Freshness::Stored, false, 30, None, 1_000).poll_secs, 30);
+        assert_eq!(meta(&config, Freshness::Stored, false, 2, None, 1_000).poll_secs, 5);
+        assert_eq!(meta(&config, Freshness::Live, true, 30, None, 1_000).poll_secs, 60);
+        assert_eq!(meta(&RefreshConfig { poll_secs: 0, paused_poll_secs: 0 }, Freshness::Live, false, 5, None, 1_000).poll_secs, 1);
+    }
+}
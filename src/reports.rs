@@ -0,0 +1,476 @@
+// Per-day usage reports, aggregated in SQL over the same one-row-per-session
+// view that `sessions::load_sessions` builds, and weekly, monthly or yearly
+// summaries ("your year in apps") built from the daily rollups. Category
+// totals roll up into a tree, parents including their children's time.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::Serialize;
+
+use crate::apps;
+use crate::categories;
+use crate::daily_summary;
+use crate::goals;
+use crate::origin::{self, AppOrigin};
+use crate::rollups::{self, DayTotal};
+use crate::trends::{self, Trend};
+use crate::units::{DurationSecs, Timestamp};
+
+const TOP_APPS: usize = 10;
+const BUSIEST_DAYS: usize = 5;
+const RISING_APPS: usize = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppUsage {
+    pub app_name: String,
+    pub duration: DurationSecs,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<AppOrigin>, // Where it was last launched from, once seen by the tracker
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>, // The exe's description, e.g. "Microsoft Word"
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyReport {
+    pub date: String,
+    pub total_duration: DurationSecs,
+    pub apps: Vec<AppUsage>,
+    pub finalized: bool, // The day is over, so the report can no longer change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trends: Option<Vec<Trend>>, // Per app, with `?trends=true`
+}
+
+/// `day_bounds` as timestamps.
+pub fn day_span(date: NaiveDate) -> (Timestamp, Timestamp) {
+    let (start, end) = day_bounds(date);
+    (Timestamp::from_secs(start.max(0) as u64), Timestamp::from_secs(end.max(0) as u64))
+}
+
+/// Unix time range `[start, end)` of a local calendar day.
+pub fn day_bounds(date: NaiveDate) -> (i64, i64) {
+    let local_midnight = |day: NaiveDate| {
+        let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+        Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|time| time.timestamp())
+            .unwrap_or_else(|| midnight.and_utc().timestamp())
+    };
+    let next = date.succ_opt().unwrap_or(date);
+    (local_midnight(date), local_midnight(next))
+}
+
+/// `[start, end)` cut at local midnights: the seconds on each day it touches.
+pub fn split_by_day(start: u64, end: u64) -> Vec<(NaiveDate, u64)> {
+    let mut parts = Vec::new();
+    let mut from = start;
+    while from < end {
+        let day = local_date(from);
+        let until = (day_bounds(day).1.max(0) as u64).clamp(from + 1, end);
+        parts.push((day, until - from));
+        from = until;
+    }
+    parts
+}
+
+pub fn local_date(timestamp: u64) -> NaiveDate {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .earliest()
+        .map(|time| time.date_naive())
+        .unwrap_or_default()
+}
+
+pub fn is_finalized(date: NaiveDate) -> bool {
+    date < Local::now().date_naive()
+}
+
+pub fn daily_report(db_path: &str, date: NaiveDate) -> SqlResult<DailyReport> {
+    let (start, end) = day_bounds(date);
+    let conn = Connection::open(db_path)?;
+    let origins = origin::by_app(&conn)?;
+    let display_names = apps::display_names(&conn)?;
+    // From the daily summary when it's up to date, otherwise the sessions
+    // reaching into the day, with only their part within it counted
+    let totals = match daily_summary::app_totals(&conn, date)? {
+        Some(totals) => totals,
+        None => day_app_totals(&conn, start, end)?,
+    };
+    let apps: Vec<AppUsage> = totals
+        .into_iter()
+        .map(|(app_name, duration)| AppUsage {
+            origin: origins.get(&app_name).copied(),
+            display_name: display_names.get(&app_name).cloned(),
+            app_name,
+            duration,
+        })
+        .collect();
+
+    Ok(DailyReport {
+        date: date.format("%Y-%m-%d").to_string(),
+        total_duration: apps.iter().map(|app| app.duration).sum(),
+        apps,
+        finalized: is_finalized(date),
+        trends: None,
+    })
+}
+
+fn day_app_totals(conn: &Connection, start: i64, end: i64) -> SqlResult<Vec<(String, DurationSecs)>> {
+    let mut stmt = conn.prepare(
+        "SELECT app_name, SUM(MIN(start + duration, ?2) - MAX(start, ?1)) AS seconds FROM (
+             SELECT app_name, timestamp - duration AS start, MAX(duration) AS duration
+             FROM usage_logs
+             WHERE timestamp > ?1 AND timestamp - duration < ?2
+             GROUP BY identifier, start
+         )
+         GROUP BY app_name
+         ORDER BY seconds DESC, app_name",
+    )?;
+    let totals = stmt.query_map(params![start, end], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+    totals
+}
+
+/// Per-app moving averages and trend direction on `date`.
+pub fn app_trends(db_path: &str, date: NaiveDate) -> SqlResult<Vec<Trend>> {
+    let conn = Connection::open(db_path)?;
+    let daily: Vec<(NaiveDate, String, u64)> = rollups::load(&conn, trends::window_start(date), date.succ_opt().unwrap_or(date))?
+        .into_iter()
+        .map(|total| (total.day, total.app_name, total.seconds.as_secs()))
+        .collect();
+    Ok(trends::compute(date, &daily))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Week(NaiveDate), // Its Monday
+    Month(i32, u32),
+    Year(i32),
+}
+
+impl Period {
+    /// The week, Monday to Sunday, `date` is in.
+    pub fn week_of(date: NaiveDate) -> Self {
+        Period::Week(goals::week_start(date))
+    }
+
+    /// `YYYY-MM`
+    pub fn parse_month(text: &str) -> Option<Self> {
+        let date = NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d").ok()?;
+        Some(Period::Month(date.year(), date.month()))
+    }
+
+    /// `YYYY`
+    pub fn parse_year(text: &str) -> Option<Self> {
+        let year: i32 = text.parse().ok().filter(|_| text.len() == 4)?;
+        NaiveDate::from_ymd_opt(year, 1, 1).map(|_| Period::Year(year))
+    }
+
+    /// Local days `[first, next period's first)`.
+    pub fn bounds(self) -> (NaiveDate, NaiveDate) {
+        let first = |year, month| NaiveDate::from_ymd_opt(year, month, 1).unwrap_or_default();
+        match self {
+            Period::Week(monday) => (monday, monday + chrono::Days::new(7)),
+            Period::Month(year, 12) => (first(year, 12), first(year + 1, 1)),
+            Period::Month(year, month) => (first(year, month), first(year, month + 1)),
+            Period::Year(year) => (first(year, 1), first(year + 1, 1)),
+        }
+    }
+
+    pub fn previous(self) -> Self {
+        match self {
+            Period::Week(monday) => Period::Week(monday - chrono::Days::new(7)),
+            Period::Month(year, 1) => Period::Month(year - 1, 12),
+            Period::Month(year, month) => Period::Month(year, month - 1),
+            Period::Year(year) => Period::Year(year - 1),
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            Period::Week(monday) => monday.format("%G-W%V").to_string(),
+            Period::Month(year, month) => format!("{:04}-{:02}", year, month),
+            Period::Year(year) => format!("{:04}", year),
+        }
+    }
+
+    // Trend points: days of a week or month, months of a year
+    fn trend_label(self, day: NaiveDate) -> String {
+        match self {
+            Period::Week(_) | Period::Month(..) => day.format("%Y-%m-%d").to_string(),
+            Period::Year(_) => day.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppTotal {
+    pub app_name: String,
+    pub seconds: DurationSecs,
+    pub sessions: u64,
+    pub percent: f64, // Of the period's total
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<AppOrigin>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DaySeconds {
+    pub date: String,
+    pub seconds: DurationSecs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrendPoint {
+    pub label: String,
+    pub seconds: DurationSecs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppChange {
+    pub app_name: String,
+    pub seconds: DurationSecs,
+    pub previous_secs: DurationSecs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeriodReport {
+    pub period: String,
+    pub from: String,
+    pub to: String, // Last day, inclusive
+    pub total_secs: DurationSecs,
+    pub sessions: u64,
+    pub active_days: usize,
+    pub average_active_day_secs: DurationSecs,
+    pub longest_streak_days: usize, // Consecutive days with any activity
+    pub previous_total_secs: DurationSecs,
+    pub change_percent: Option<f64>, // Against the previous period; None when it was empty
+    pub top_apps: Vec<AppTotal>,
+    pub busiest_days: Vec<DaySeconds>,
+    pub trend: Vec<TrendPoint>, // Every day of a week or month, every month of a year
+    pub rising: Vec<AppChange>, // Largest gains over the previous period
+    pub finalized: bool,
+}
+
+fn percent(part: DurationSecs, whole: DurationSecs) -> f64 {
+    (part.as_secs() as f64 * 1000.0 / whole.as_secs().max(1) as f64).round() / 10.0
+}
+
+fn per_app(totals: &[DayTotal]) -> BTreeMap<&str, (DurationSecs, u64)> {
+    let mut apps: BTreeMap<&str, (DurationSecs, u64)> = BTreeMap::new();
+    for total in totals {
+        let app = apps.entry(total.app_name.as_str()).or_default();
+        app.0 += total.seconds;
+        app.1 += total.sessions;
+    }
+    apps
+}
+
+pub fn summarize_period(period: Period, current: &[DayTotal], previous: &[DayTotal], today: NaiveDate) -> PeriodReport {
+    let (from, to) = period.bounds();
+    let total_secs: DurationSecs = current.iter().map(|total| total.seconds).sum();
+    let previous_total_secs: DurationSecs = previous.iter().map(|total| total.seconds).sum();
+
+    let mut days: BTreeMap<NaiveDate, DurationSecs> = BTreeMap::new();
+    for total in current {
+        *days.entry(total.day).or_default() += total.seconds;
+    }
+    let mut longest_streak_days = 0;
+    let mut streak = 0;
+    let mut last: Option<NaiveDate> = None;
+    for &day in days.keys() {
+        streak = if last.and_then(|last| last.succ_opt()) == Some(day) { streak + 1 } else { 1 };
+        longest_streak_days = longest_streak_days.max(streak);
+        last = Some(day);
+    }
+    let mut busiest_days: Vec<DaySeconds> =
+        days.iter().map(|(day, &seconds)| DaySeconds { date: day.format("%Y-%m-%d").to_string(), seconds }).collect();
+    busiest_days.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.date.cmp(&b.date)));
+    busiest_days.truncate(BUSIEST_DAYS);
+
+    let mut trend: BTreeMap<String, DurationSecs> = BTreeMap::new();
+    for day in from.iter_days().take_while(|day| *day < to) {
+        trend.entry(period.trend_label(day)).or_default();
+    }
+    for (day, seconds) in &days {
+        *trend.entry(period.trend_label(*day)).or_default() += *seconds;
+    }
+
+    let apps = per_app(current);
+    let before = per_app(previous);
+    let mut top_apps: Vec<AppTotal> = apps
+        .iter()
+        .map(|(app, &(seconds, sessions))| AppTotal {
+            app_name: app.to_string(),
+            seconds,
+            sessions,
+            percent: percent(seconds, total_secs),
+            origin: None,
+            display_name: None,
+        })
+        .collect();
+    top_apps.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.app_name.cmp(&b.app_name)));
+    top_apps.truncate(TOP_APPS);
+    let mut rising: Vec<AppChange> = apps
+        .iter()
+        .map(|(app, &(seconds, _))| AppChange {
+            app_name: app.to_string(),
+            seconds,
+            previous_secs: before.get(app).map(|app| app.0).unwrap_or_default(),
+        })
+        .filter(|change| change.seconds > change.previous_secs)
+        .collect();
+    rising.sort_by_key(|change| std::cmp::Reverse(change.seconds - change.previous_secs));
+    rising.truncate(RISING_APPS);
+
+    PeriodReport {
+        period: period.label(),
+        from: from.format("%Y-%m-%d").to_string(),
+        to: to.pred_opt().unwrap_or(to).format("%Y-%m-%d").to_string(),
+        total_secs,
+        sessions: current.iter().map(|total| total.sessions).sum(),
+        active_days: days.len(),
+        average_active_day_secs: DurationSecs::secs(total_secs.as_secs() / days.len().max(1) as u64),
+        longest_streak_days,
+        previous_total_secs,
+        change_percent: (previous_total_secs > DurationSecs::ZERO)
+            .then(|| percent(total_secs, previous_total_secs) - 100.0)
+            .map(|change| (change * 10.0).round() / 10.0),
+        top_apps,
+        busiest_days,
+        trend: trend.into_iter().map(|(label, seconds)| TrendPoint { label, seconds }).collect(),
+        rising,
+        finalized: to <= today,
+    }
+}
+
+pub fn period_report(db_path: &str, period: Period) -> SqlResult<PeriodReport> {
+    let conn = Connection::open(db_path)?;
+    let (from, to) = period.bounds();
+    let (previous_from, previous_to) = period.previous().bounds();
+    let current = rollups::load(&conn, from, to)?;
+    let previous = rollups::load(&conn, previous_from, previous_to)?;
+    let mut report = summarize_period(period, &current, &previous, Local::now().date_naive());
+    let origins = origin::by_app(&conn)?;
+    let display_names = apps::display_names(&conn)?;
+    for app in &mut report.top_apps {
+        app.origin = origins.get(&app.app_name).copied();
+        app.display_name = display_names.get(&app.app_name).cloned();
+    }
+    Ok(report)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryNode {
+    pub name: String, // The last part of `category`
+    pub category: String, // Full path, e.g. "Dev > Code Review"
+    pub own_secs: u64, // Spent in this category and none of its children
+    pub total_secs: u64, // Including all descendants
+    pub children: Vec<CategoryNode>, // Largest first
+}
+
+fn insert_node(nodes: &mut Vec<CategoryNode>, parts: &[&str], depth: usize, seconds: u64) {
+    let category = parts[..=depth].join(&format!(" {} ", categories::SEPARATOR));
+    let index = match nodes.iter().position(|node| node.category == category) {
+        Some(index) => index,
+        None => {
+            let name = parts[depth].to_string();
+            nodes.push(CategoryNode { name, category, own_secs: 0, total_secs: 0, children: Vec::new() });
+            nodes.len() - 1
+        }
+    };
+    let node = &mut nodes[index];
+    node.total_secs += seconds;
+    if depth + 1 == parts.len() {
+        node.own_secs += seconds;
+    } else {
+        insert_node(&mut node.children, parts, depth + 1, seconds);
+    }
+}
+
+fn sort_nodes(nodes: &mut [CategoryNode]) {
+    nodes.sort_by(|a, b| b.total_secs.cmp(&a.total_secs).then_with(|| a.name.cmp(&b.name)));
+    for node in nodes {
+        sort_nodes(&mut node.children);
+    }
+}
+
+/// Flat (category, seconds) totals as a tree of categories, largest first.
+pub fn category_tree<'a>(totals: impl IntoIterator<Item = (&'a str, u64)>) -> Vec<CategoryNode> {
+    let mut roots = Vec::new();
+    for (category, seconds) in totals {
+        let parts = categories::path(category);
+        if !parts.is_empty() {
+            insert_node(&mut roots, &parts, 0, seconds);
+        }
+    }
+    sort_nodes(&mut roots);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(day: u32, app: &str, seconds: u64) -> DayTotal {
+        DayTotal { day: NaiveDate::from_ymd_opt(2024, 2, day).unwrap(), app_name: app.to_string(), seconds: DurationSecs::secs(seconds), sessions: 1 }
+    }
+
+    #[test]
+    fn spans_are_cut_at_local_midnight() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let midnight = day_bounds(day).1 as u64;
+        let next = day.succ_opt().unwrap();
+        assert_eq!(split_by_day(midnight - 600, midnight + 900), vec![(day, 600), (next, 900)]);
+        assert_eq!(split_by_day(midnight, midnight + 60), vec![(next, 60)]);
+        assert!(split_by_day(midnight, midnight).is_empty());
+    }
+
+    #[test]
+    fn periods_parse_and_step_back() {
+        assert_eq!(Period::parse_month("2024-01"), Some(Period::Month(2024, 1)));
+        assert_eq!(Period::parse_month("2024-13"), None);
+        assert_eq!(Period::parse_year("24"), None);
+        assert_eq!(Period::Month(2024, 1).previous(), Period::Month(2023, 12));
+        let week = Period::week_of(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+        assert_eq!((week.label(), week.bounds().0.to_string()), ("2024-W09".to_string(), "2024-02-26".to_string()));
+        let (from, to) = Period::Month(2024, 2).bounds();
+        assert_eq!((to - from).num_days(), 29);
+    }
+
+    #[test]
+    fn monthly_summary_ranks_apps_days_and_gains() {
+        let current = vec![
+            total(1, "code.exe", 3600),
+            total(2, "code.exe", 1800),
+            total(2, "chrome.exe", 1900),
+            total(3, "chrome.exe", 600),
+            total(10, "slack.exe", 600),
+        ];
+        let mut previous = current.clone();
+        previous.retain(|total| total.app_name == "code.exe");
+        previous[0].seconds = DurationSecs::hours(2);
+
+        let today = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let report = summarize_period(Period::Month(2024, 2), &current, &previous, today);
+        assert_eq!((report.total_secs.as_secs(), report.active_days, report.longest_streak_days), (8500, 4, 3));
+        assert_eq!(report.top_apps[0].app_name, "code.exe");
+        assert_eq!(report.busiest_days[0], DaySeconds { date: "2024-02-02".into(), seconds: DurationSecs::secs(3700) });
+        assert_eq!(report.trend.len(), 29);
+        assert_eq!(report.rising.iter().map(|app| app.app_name.as_str()).collect::<Vec<_>>(), vec!["chrome.exe", "slack.exe"]);
+        assert_eq!(report.change_percent, Some(-5.6));
+        assert!(report.finalized && report.to == "2024-02-29");
+    }
+
+    #[test]
+    fn category_totals_roll_up_into_their_parents() {
+        let tree = category_tree([("Dev > Code Review", 600), ("Social", 900), ("Dev", 300), ("Dev>Builds", 120), ("Dev > Code Review > Nits", 60)]);
+        assert_eq!(tree.iter().map(|node| (node.category.as_str(), node.total_secs)).collect::<Vec<_>>(), vec![("Dev", 1080), ("Social", 900)]);
+        let dev = &tree[0];
+        assert_eq!(dev.own_secs, 300);
+        assert_eq!(dev.children.iter().map(|node| (node.name.as_str(), node.total_secs)).collect::<Vec<_>>(), vec![("Code Review", 660), ("Builds", 120)]);
+        assert_eq!((dev.children[0].own_secs, dev.children[0].children[0].category.as_str()), (600, "Dev > Code Review > Nits"));
+    }
+}
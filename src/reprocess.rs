@@ -0,0 +1,165 @@
+// `sysmonitor reprocess`: derive the stored sessions again from the event log.
+//
+// After a fix in how sessions are derived, or a change to the debounce or idle
+// settings, the sessions stored since a date still follow the old rules. This
+// projects the log with the current ones and compares both, per day and app,
+// before `--apply` replaces the stored sessions and the aggregates over them
+// (see `events::rebuild`).
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::Serialize;
+
+use crate::categories::CategoryConfig;
+use crate::debounce::DebounceConfig;
+use crate::idle::IdleConfig;
+use crate::units::Timestamp;
+use crate::{events, reports};
+
+const LISTED: usize = 30; // Differences printed
+
+/// Time of an app on a day that the projection would change.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    pub day: NaiveDate,
+    pub app_name: String,
+    pub stored: u64, // Seconds
+    pub projected: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    pub from: Timestamp, // After clamping to the log
+    pub stored_sessions: usize,
+    pub projected_sessions: usize,
+    pub differences: Vec<Difference>,
+}
+
+impl Comparison {
+    pub fn changes(&self) -> bool {
+        self.stored_sessions != self.projected_sessions || !self.differences.is_empty()
+    }
+}
+
+/// The stored tracker and manual sessions from `from` on against the
+/// projection of the log. None while the log is empty.
+pub fn compare(conn: &Connection, debounce: &DebounceConfig, idle: &IdleConfig, from: Timestamp) -> SqlResult<Option<Comparison>> {
+    let Some((from, projected)) = events::projection(conn, debounce, idle, from)? else {
+        return Ok(None);
+    };
+    let mut totals: BTreeMap<(NaiveDate, String), (u64, u64)> = BTreeMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT app_name, timestamp - duration AS start, MAX(duration) FROM usage_logs
+         WHERE timestamp - duration >= ?1 AND (source IS NULL OR source = ?2)
+         GROUP BY identifier, start",
+    )?;
+    let rows = stmt.query_map(params![from, events::MANUAL_SOURCE], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))?;
+    let mut stored_sessions = 0;
+    for row in rows {
+        let (app_name, start, duration) = row?;
+        let start = start.max(0) as u64;
+        for (day, seconds) in reports::split_by_day(start, start + duration.max(0) as u64) {
+            totals.entry((day, app_name.clone())).or_default().0 += seconds;
+        }
+        stored_sessions += 1;
+    }
+    for session in &projected {
+        totals.entry((reports::local_date(session.start.as_secs()), session.key.app_name.clone())).or_default().1 += session.duration.as_secs();
+    }
+    let differences = totals
+        .into_iter()
+        .filter(|(_, (stored, projected))| stored != projected)
+        .map(|((day, app_name), (stored, projected))| Difference { day, app_name, stored, projected })
+        .collect();
+    Ok(Some(Comparison { from, stored_sessions, projected_sessions: projected.len(), differences }))
+}
+
+/// `sysmonitor reprocess --from YYYY-MM-DD [--apply]`.
+pub fn run_command(conn: &mut Connection, categories: &CategoryConfig, debounce: &DebounceConfig, idle: &IdleConfig, args: &[String], now: Timestamp) -> Result<(), String> {
+    let sql = |e: rusqlite::Error| e.to_string();
+    let usage = "usage: sysmonitor reprocess --from YYYY-MM-DD [--apply]";
+    let value = args.iter().position(|arg| arg == "--from").and_then(|index| args.get(index + 1)).ok_or(usage)?;
+    let day = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("--from must be YYYY-MM-DD, got '{}'", value))?;
+    let from = Timestamp::from_secs(reports::day_bounds(day).0.max(0) as u64);
+
+    let Some(comparison) = compare(conn, debounce, idle, from).map_err(sql)? else {
+        println!("The event log is empty; there is nothing to reprocess from");
+        return Ok(());
+    };
+    println!(
+        "Since {}: {} sessions stored, {} derived from the log",
+        reports::local_date(comparison.from.as_secs()),
+        comparison.stored_sessions,
+        comparison.projected_sessions
+    );
+    for difference in comparison.differences.iter().take(LISTED) {
+        println!(
+            "  {} {}: {}s -> {}s ({:+}s)",
+            difference.day,
+            difference.app_name,
+            difference.stored,
+            difference.projected,
+            difference.projected as i64 - difference.stored as i64
+        );
+    }
+    if comparison.differences.len() > LISTED {
+        println!("  ... and {} more", comparison.differences.len() - LISTED);
+    }
+    if !comparison.changes() {
+        println!("Stored sessions already follow the current rules");
+        return Ok(());
+    }
+    if !args.iter().any(|arg| arg == "--apply") {
+        println!("Run again with --apply to replace the stored sessions");
+        return Ok(());
+    }
+    let rebuilt = events::rebuild(conn, categories, debounce, idle, comparison.from, now).map_err(sql)?;
+    println!("Replaced {} sessions with {}", rebuilt.removed, rebuilt.stored);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::identifier::ActivityKey;
+
+    #[test]
+    fn changed_debounce_shows_up_per_day_and_app() {
+        let conn = fixtures::empty().unwrap();
+        let start = reports::day_bounds(NaiveDate::from_ymd_opt(2026, 3, 2).unwrap()).0 as u64 + 3600;
+        let at = |offset: u64| Timestamp::from_secs(start + offset);
+        let key = ActivityKey::new("code.exe", "", None);
+        let glance = ActivityKey::new("slack.exe", "", None);
+        for event in [
+            events::Event::focus(at(0), key.clone(), false),
+            events::Event::focus(at(100), glance.clone(), false),
+            events::Event::focus(at(103), key.clone(), false),
+            events::Event::new(at(200), events::EventKind::Idle),
+        ] {
+            events::append(&conn, &event).unwrap();
+        }
+        // Stored while the minimum was 2 seconds: the 3-second glance was kept
+        let log = |key: &ActivityKey, start: u64, duration: u64| {
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?2, '', ?3, ?4)",
+                params![key.to_string(), key.app_name, start + duration, duration],
+            )
+            .unwrap();
+        };
+        log(&key, start, 100);
+        log(&glance, start + 100, 3);
+        log(&key, start + 103, 97);
+
+        let debounce = DebounceConfig { min_session_secs: 5, fold: true };
+        let comparison = compare(&conn, &debounce, &IdleConfig::default(), Timestamp::EPOCH).unwrap().unwrap();
+        assert_eq!((comparison.from, comparison.stored_sessions, comparison.projected_sessions), (at(0), 3, 1));
+        let changed: Vec<(&str, u64, u64)> =
+            comparison.differences.iter().map(|difference| (difference.app_name.as_str(), difference.stored, difference.projected)).collect();
+        assert_eq!(changed, vec![("code.exe", 197, 200), ("slack.exe", 3, 0)]);
+
+        assert!(!compare(&conn, &DebounceConfig::default(), &IdleConfig::default(), Timestamp::EPOCH).unwrap().unwrap().changes());
+    }
+}
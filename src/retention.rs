@@ -0,0 +1,261 @@
+// Retention per data class.
+//
+// Each class in `retention` gets its own lifetime in days; unset keeps it
+// forever. Sensitive details expire by redaction, so the row and its app, times
+// and category stay and app-level reports don't change:
+// - `urls_days`: URLs shrink to their origin (`https://host/`), both in `url`
+//   and in the identifier
+// - `titles_days`: window titles become `(title expired)`, and their full text
+//   in `title_history` is deleted
+// Whole rows go with `sessions_days` (usage_logs and focus transitions, after
+// their days were rolled up) and `aggregates_days` (rollups, the Grafana and
+// team tables, work blocks). The event log is deleted with the first of URLs,
+// titles and sessions to expire, since it names all three.
+// `usage_logs.redacted` records what was done to a row, so each one is only
+// rewritten once. Archived sessions get the same treatment in their monthly
+// files (`archive::enforce_retention`).
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::ActivityKey;
+use crate::{reports, signatures};
+
+pub const EXPIRED_TITLE: &str = "(title expired)";
+const REDACTED_URL: u32 = 1;
+const REDACTED_TITLE: u32 = 2;
+const BATCH_ROWS: i64 = 5000; // Rows rewritten per transaction
+const DAY_SECS: u64 = 86400;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub urls_days: Option<u32>,
+    pub titles_days: Option<u32>,
+    pub sessions_days: Option<u32>,
+    pub aggregates_days: Option<u32>,
+}
+
+impl RetentionConfig {
+    pub fn is_empty(&self) -> bool {
+        self.urls_days.is_none() && self.titles_days.is_none() && self.sessions_days.is_none() && self.aggregates_days.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Pruned {
+    pub urls_redacted: usize,
+    pub titles_redacted: usize,
+    pub sessions_deleted: usize,
+    pub aggregates_deleted: usize,
+}
+
+impl std::ops::Add for Pruned {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            urls_redacted: self.urls_redacted + other.urls_redacted,
+            titles_redacted: self.titles_redacted + other.titles_redacted,
+            sessions_deleted: self.sessions_deleted + other.sessions_deleted,
+            aggregates_deleted: self.aggregates_deleted + other.aggregates_deleted,
+        }
+    }
+}
+
+/// Timestamp before which a class has expired; 0 (nothing) when it never does.
+pub fn cutoff(days: Option<u32>, now: u64) -> u64 {
+    days.map_or(0, |days| now.saturating_sub(u64::from(days.max(1)) * DAY_SECS))
+}
+
+/// `scheme://host/` of a URL, which is what the domain rules still match.
+pub fn url_origin(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(parsed) if parsed.host_str().is_some() => format!("{}://{}/", parsed.scheme(), parsed.host_str().unwrap_or_default()),
+        _ => "(url expired)".to_string(),
+    }
+}
+
+struct DueRow {
+    id: i64,
+    identifier: String,
+    app_name: String,
+    window_title: String,
+    url: Option<String>,
+    start: i64,
+    redacted: u32,
+    signed: bool,
+}
+
+// Rewrites a batch of rows that have details due for redaction; how many of each
+fn redact_batch(conn: &mut Connection, url_cutoff: u64, title_cutoff: u64) -> SqlResult<(usize, usize)> {
+    let tx = conn.transaction()?;
+    let rows: Vec<DueRow> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, identifier, app_name, window_title, url, timestamp - duration, redacted, signature IS NOT NULL FROM usage_logs
+             WHERE (timestamp - duration < ?1 AND url IS NOT NULL AND redacted & 1 = 0)
+                OR (timestamp - duration < ?2 AND redacted & 2 = 0)
+             LIMIT ?3",
+        )?;
+        let rows = stmt
+            .query_map(params![url_cutoff, title_cutoff, BATCH_ROWS], |row| {
+                Ok(DueRow {
+                    id: row.get(0)?,
+                    identifier: row.get(1)?,
+                    app_name: row.get(2)?,
+                    window_title: row.get(3)?,
+                    url: row.get(4)?,
+                    start: row.get(5)?,
+                    redacted: row.get(6)?,
+                    signed: row.get(7)?,
+                })
+            })?
+            .collect::<SqlResult<_>>()?;
+        rows
+    };
+    let (mut urls, mut titles) = (0, 0);
+    for DueRow { id, identifier, app_name, mut window_title, mut url, start, mut redacted, signed } in rows {
+        if url.is_some() && redacted & REDACTED_URL == 0 && start < url_cutoff as i64 {
+            url = url.as_deref().map(url_origin);
+            redacted |= REDACTED_URL;
+            urls += 1;
+        }
+        if redacted & REDACTED_TITLE == 0 && start < title_cutoff as i64 {
+            window_title = EXPIRED_TITLE.to_string();
+            redacted |= REDACTED_TITLE;
+            titles += 1;
+        }
+        let key = ActivityKey::new(&app_name, &window_title, url.as_deref()).to_string();
+        tx.execute(
+            "UPDATE usage_logs SET identifier = ?1, window_title = ?2, url = ?3, redacted = ?4 WHERE id = ?5",
+            params![key, window_title, url, redacted, id],
+        )?;
+        if signed {
+            signatures::sign_row(&tx, id)?;
+        }
+        // The assignment stays with the session; a collision just loses a duplicate
+        tx.execute(
+            "UPDATE OR REPLACE session_categories SET identifier = ?1 WHERE identifier = ?2 AND start = ?3",
+            params![key, identifier, start],
+        )?;
+        // So do its bulk label and the history undo goes back through
+        tx.execute("UPDATE OR REPLACE session_labels SET identifier = ?1 WHERE identifier = ?2 AND start = ?3", params![key, identifier, start])?;
+        tx.execute(
+            "UPDATE OR REPLACE label_change_sessions SET identifier = ?1 WHERE identifier = ?2 AND start = ?3",
+            params![key, identifier, start],
+        )?;
+    }
+    tx.commit()?;
+    Ok((urls, titles))
+}
+
+/// Redacts and deletes whatever has outlived its class.
+pub fn enforce(conn: &mut Connection, config: &RetentionConfig, now: u64) -> SqlResult<Pruned> {
+    let mut pruned = Pruned::default();
+    let (url_cutoff, title_cutoff) = (cutoff(config.urls_days, now), cutoff(config.titles_days, now));
+    if url_cutoff > 0 || title_cutoff > 0 {
+        loop {
+            let (urls, titles) = redact_batch(conn, url_cutoff, title_cutoff)?;
+            pruned.urls_redacted += urls;
+            pruned.titles_redacted += titles;
+            if urls + titles == 0 {
+                break;
+            }
+        }
+        conn.execute("DELETE FROM title_history WHERE last_seen < ?1", params![title_cutoff])?;
+        // Conflicts name both URLs and titles, so they go with the first to expire
+        let conflict_cutoff = [url_cutoff, title_cutoff].into_iter().max().unwrap_or(0);
+        conn.execute("DELETE FROM ingest_conflicts WHERE end_time < ?1", params![conflict_cutoff])?;
+    }
+
+    let sessions_cutoff = cutoff(config.sessions_days, now);
+    if sessions_cutoff > 0 {
+        pruned.sessions_deleted = conn.execute("DELETE FROM usage_logs WHERE timestamp < ?1", params![sessions_cutoff])?;
+        conn.execute(
+            "DELETE FROM session_categories WHERE start < ?1 AND NOT EXISTS (
+                 SELECT 1 FROM usage_logs u
+                 WHERE u.identifier = session_categories.identifier AND u.timestamp - u.duration = session_categories.start
+             )",
+            params![sessions_cutoff],
+        )?;
+        conn.execute(
+            "DELETE FROM session_labels WHERE start < ?1 AND NOT EXISTS (
+                 SELECT 1 FROM usage_logs u
+                 WHERE u.identifier = session_labels.identifier AND u.timestamp - u.duration = session_labels.start
+             )",
+            params![sessions_cutoff],
+        )?;
+        conn.execute("DELETE FROM focus_transitions WHERE timestamp < ?1", params![sessions_cutoff])?;
+    }
+
+    let events_cutoff = [url_cutoff, title_cutoff, sessions_cutoff].into_iter().max().unwrap_or(0);
+    if events_cutoff > 0 {
+        conn.execute("DELETE FROM events WHERE timestamp < ?1", params![events_cutoff])?;
+    }
+
+    let aggregates_cutoff = cutoff(config.aggregates_days, now);
+    if aggregates_cutoff > 0 {
+        let day = reports::local_date(aggregates_cutoff).format("%Y-%m-%d").to_string();
+        pruned.aggregates_deleted += conn.execute("DELETE FROM daily_rollups WHERE day < ?1", params![day])?;
+        pruned.aggregates_deleted += conn.execute("DELETE FROM category_daily WHERE day < ?1", params![day])?;
+        pruned.aggregates_deleted += conn.execute("DELETE FROM app_usage_hourly WHERE hour_start < ?1", params![aggregates_cutoff])?;
+        pruned.aggregates_deleted += conn.execute("DELETE FROM team_aggregates WHERE hour < ?1", params![aggregates_cutoff])?;
+        pruned.aggregates_deleted += conn.execute("DELETE FROM work_blocks WHERE end_time < ?1", params![aggregates_cutoff])?;
+    }
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn expired_details_are_redacted_once_and_keep_their_category() {
+        let mut conn = fixtures::empty().unwrap();
+        let now = 200 * DAY_SECS;
+        let log = |conn: &Connection, title: &str, url: Option<&str>, days_ago: u64| {
+            let key = ActivityKey::new("chrome.exe", title, url).to_string();
+            let start = now - days_ago * DAY_SECS;
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, url, timestamp, duration) VALUES (?1, 'chrome.exe', ?2, ?3, ?4, 60)",
+                params![key, title, url, start + 60],
+            )
+            .unwrap();
+            conn.execute("INSERT INTO session_categories VALUES (?1, ?2, 'Research', 'v1')", params![key, start]).unwrap();
+        };
+        log(&conn, "Bank - Statement", Some("https://bank.example/accounts/42?x=1"), 100);
+        log(&conn, "Docs", Some("https://docs.rs/serde"), 40);
+        log(&conn, "Today", Some("https://news.example/today"), 1);
+
+        let config = RetentionConfig { urls_days: Some(30), titles_days: Some(90), ..Default::default() };
+        let pruned = enforce(&mut conn, &config, now).unwrap();
+        assert_eq!((pruned.urls_redacted, pruned.titles_redacted), (2, 1));
+        assert_eq!(enforce(&mut conn, &config, now).unwrap(), Pruned::default());
+
+        let rows: Vec<(String, String, Option<String>)> = conn
+            .prepare("SELECT identifier, window_title, url FROM usage_logs ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap();
+        assert_eq!(rows[0], ("chrome.exe:u:https\\://bank.example/".into(), EXPIRED_TITLE.into(), Some("https://bank.example/".into())));
+        assert_eq!((rows[1].1.as_str(), rows[1].2.as_deref()), ("Docs", Some("https://docs.rs/")));
+        assert_eq!(rows[2].2.as_deref(), Some("https://news.example/today"));
+        let categorized: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM session_categories c JOIN usage_logs u ON u.identifier = c.identifier AND u.timestamp - u.duration = c.start",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(categorized, 3);
+    }
+
+    #[test]
+    fn urls_keep_only_their_origin() {
+        assert_eq!(url_origin("https://mail.example.com:8443/inbox/123?token=abc"), "https://mail.example.com/");
+        assert_eq!(url_origin("not a url"), "(url expired)");
+    }
+}
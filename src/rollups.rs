@@ -0,0 +1,208 @@
+// Daily per-app totals kept in `daily_rollups`.
+//
+// Long-range reports would otherwise rebuild sessions from every usage_logs
+// row of the period, and rows moved to the archive would drop out of them.
+// Once a day is over its totals are rolled up; the last `REBUILD_DAYS` are
+// rolled up again with each new day, so late uploads still count. Days not
+// rolled up yet are computed on the fly by `load`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+
+use crate::units::{DurationSecs, Timestamp};
+use crate::{reports, sessions};
+
+const REBUILD_DAYS: u64 = 7;
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayTotal {
+    pub day: NaiveDate,
+    pub app_name: String,
+    pub seconds: DurationSecs,
+    pub sessions: u64,
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS daily_rollups (
+            day TEXT NOT NULL,
+            app_name TEXT NOT NULL,
+            seconds INTEGER NOT NULL,
+            sessions INTEGER NOT NULL,
+            PRIMARY KEY (day, app_name)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn parse_day(day: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(day, DAY_FORMAT).ok()
+}
+
+fn latest_rolled_day(conn: &Connection) -> SqlResult<Option<NaiveDate>> {
+    let day: Option<String> = conn.query_row("SELECT MAX(day) FROM daily_rollups", [], |row| row.get(0))?;
+    Ok(day.as_deref().and_then(parse_day))
+}
+
+// Per-app totals of the local days in `[since, until)`; a session over
+// midnight counts on both days, each with its part
+fn day_totals(conn: &Connection, since: Timestamp, until: Timestamp) -> SqlResult<Vec<DayTotal>> {
+    let mut days: BTreeMap<(NaiveDate, String), (DurationSecs, u64)> = BTreeMap::new();
+    for session in sessions::clipped(conn, since, until)? {
+        for (day, seconds) in reports::split_by_day(session.start.as_secs(), session.end.as_secs()) {
+            let total = days.entry((day, session.app_name.clone())).or_default();
+            total.0 += DurationSecs::secs(seconds);
+            total.1 += 1;
+        }
+    }
+    Ok(days.into_iter().map(|((day, app_name), (seconds, sessions))| DayTotal { day, app_name, seconds, sessions }).collect())
+}
+
+/// Rolls up the days before `today` not rolled up since the last new day.
+/// Returns how many days were written.
+pub fn refresh(conn: &mut Connection, today: NaiveDate) -> SqlResult<usize> {
+    let yesterday = today.pred_opt().unwrap_or(today);
+    let from = match latest_rolled_day(conn)? {
+        Some(latest) if latest >= yesterday => return Ok(0),
+        Some(latest) => latest - chrono::Days::new(REBUILD_DAYS - 1),
+        None => {
+            let first: Option<i64> =
+                conn.query_row("SELECT MIN(timestamp - duration) FROM usage_logs", [], |row| row.get(0)).optional()?.flatten();
+            match first {
+                Some(first) => reports::local_date(first.max(0) as u64),
+                None => return Ok(0),
+            }
+        }
+    };
+    let totals = day_totals(conn, reports::day_span(from).0, reports::day_span(yesterday).1)?;
+
+    let tx = conn.transaction()?;
+    let mut days = Vec::new();
+    for total in &totals {
+        let day = total.day.format(DAY_FORMAT).to_string();
+        // Days whose rows are gone (e.g. archived) keep their rollup
+        if !days.contains(&total.day) {
+            tx.execute("DELETE FROM daily_rollups WHERE day = ?1", params![day])?;
+            days.push(total.day);
+        }
+        tx.execute(
+            "INSERT INTO daily_rollups (day, app_name, seconds, sessions) VALUES (?1, ?2, ?3, ?4)",
+            params![day, total.app_name, total.seconds, total.sessions],
+        )?;
+    }
+    tx.commit()?;
+    Ok(days.len())
+}
+
+/// Rolls up `days` again if they already were, e.g. after their sessions
+/// were corrected. Returns how many days were written.
+pub fn rebuild(conn: &mut Connection, days: &BTreeSet<NaiveDate>) -> SqlResult<usize> {
+    let Some(latest) = latest_rolled_day(conn)? else {
+        return Ok(0);
+    };
+    let tx = conn.transaction()?;
+    let mut written = 0;
+    for day in days.iter().filter(|day| **day <= latest) {
+        let (since, until) = reports::day_span(*day);
+        tx.execute("DELETE FROM daily_rollups WHERE day = ?1", params![day.format(DAY_FORMAT).to_string()])?;
+        for total in day_totals(&tx, since, until)?.iter().filter(|total| total.day == *day) {
+            tx.execute(
+                "INSERT INTO daily_rollups (day, app_name, seconds, sessions) VALUES (?1, ?2, ?3, ?4)",
+                params![total.day.format(DAY_FORMAT).to_string(), total.app_name, total.seconds, total.sessions],
+            )?;
+        }
+        written += 1;
+    }
+    tx.commit()?;
+    Ok(written)
+}
+
+/// Per-day app totals for the local days `[from, to)`: rolled-up days as
+/// stored, later ones computed from usage_logs.
+pub fn load(conn: &Connection, from: NaiveDate, to: NaiveDate) -> SqlResult<Vec<DayTotal>> {
+    let live_from = latest_rolled_day(conn)?.and_then(|day| day.succ_opt()).unwrap_or(from).max(from);
+    let mut totals = Vec::new();
+    if live_from > from {
+        let mut stmt =
+            conn.prepare("SELECT day, app_name, seconds, sessions FROM daily_rollups WHERE day >= ?1 AND day < ?2")?;
+        let rows = stmt.query_map(
+            params![from.format(DAY_FORMAT).to_string(), live_from.format(DAY_FORMAT).to_string()],
+            |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?, row.get::<_, i64>(3)?)),
+        )?;
+        for row in rows {
+            let (day, app_name, seconds, sessions) = row?;
+            if let Some(day) = parse_day(&day) {
+                totals.push(DayTotal { day, app_name, seconds, sessions: sessions as u64 });
+            }
+        }
+    }
+    if live_from < to {
+        let (_, until) = reports::day_span(to.pred_opt().unwrap_or(to));
+        totals.extend(day_totals(conn, reports::day_span(live_from).0, until)?.into_iter().filter(|total| total.day < to));
+    }
+    Ok(totals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, Shape};
+
+    fn db() -> Connection {
+        fixtures::empty().unwrap()
+    }
+
+    fn log(conn: &Connection, app: &str, day: NaiveDate, duration: u64) {
+        let start = reports::day_bounds(day).0 as u64 + 3600;
+        conn.execute(
+            "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?1, '', ?2, ?3)",
+            params![app, start + duration, duration],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn finished_days_are_rolled_up_and_survive_their_rows() {
+        let mut conn = db();
+        let day = |d: u32| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        log(&conn, "code.exe", day(1), 600);
+        log(&conn, "code.exe", day(2), 300);
+        log(&conn, "chrome.exe", day(2), 100);
+        log(&conn, "code.exe", day(3), 50); // "Today"
+        // From half an hour before the 2nd until half an hour into it
+        let midnight = reports::day_bounds(day(2)).0 as u64;
+        conn.execute("INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('vlc.exe', 'vlc.exe', '', ?1, 3600)", params![midnight + 1800])
+            .unwrap();
+
+        assert_eq!(refresh(&mut conn, day(3)).unwrap(), 2);
+        assert_eq!(refresh(&mut conn, day(3)).unwrap(), 0);
+        conn.execute("DELETE FROM usage_logs WHERE duration = 600", []).unwrap();
+
+        let totals = load(&conn, day(1), day(4)).unwrap();
+        let summary: Vec<(u32, &str, u64)> =
+            totals.iter().map(|total| (chrono::Datelike::day(&total.day), total.app_name.as_str(), total.seconds.as_secs())).collect();
+        assert!(summary.contains(&(1, "code.exe", 600)));
+        assert!(summary.contains(&(2, "chrome.exe", 100)));
+        assert!(summary.contains(&(3, "code.exe", 50)));
+        assert!(summary.contains(&(1, "vlc.exe", 1800)) && summary.contains(&(2, "vlc.exe", 1800)));
+        assert_eq!(totals.len(), 6);
+        assert_eq!(load(&conn, day(2), day(3)).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn every_second_of_a_fixture_lands_on_some_day() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let now = reports::day_bounds(today).0 as u64 + 15 * 3600;
+        let shape = Shape { days: 21, apps: Some(12), midnight_spans: true, ..Shape::default() };
+        let mut conn = fixtures::database(&shape, now).unwrap();
+        refresh(&mut conn, today).unwrap();
+
+        let logged: u64 = conn.query_row("SELECT SUM(duration) FROM usage_logs", [], |row| row.get(0)).unwrap();
+        let rolled: DurationSecs = load(&conn, today - chrono::Days::new(30), today.succ_opt().unwrap()).unwrap().iter().map(|total| total.seconds).sum();
+        assert_eq!(rolled.as_secs(), logged);
+    }
+}
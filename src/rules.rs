@@ -0,0 +1,543 @@
+// Shareable rule bundles: category rules, productivity marks and exclusions
+// (apps whose profile sets `track: false`).
+//
+// Bundles are JSON, shaped like `RuleSet`, or CSV with one entry per line:
+//
+//   kind,category,app,host,title_contains,productivity
+//   category,Dev,code.exe,,,
+//   productivity,Dev,,,,productive
+//   exclude,,keepass.exe,,,
+//
+// An import is validated and diffed against the current rules first; only an
+// applied import writes the config file, replacing the rules wholesale. The
+// config is read at startup, so applied rules take effect on restart.
+//
+// `test_rules` is the sandbox for candidate bundles: it re-categorizes stored
+// sessions in memory and reports what would move where, persisting nothing.
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::categories::{CategoryConfig, CategoryRule, Productivity, UNCATEGORIZED};
+use crate::config::Config;
+use crate::csv;
+use crate::identifier::ActivityKey;
+use crate::sessions::Session;
+use crate::units::DurationSecs;
+
+const EXCLUDED: &str = "(excluded)";
+const MAX_EXAMPLES: usize = 3;
+const CSV_HEADER: [&str; 6] = ["kind", "category", "app", "host", "title_contains", "productivity"];
+pub const MAX_BUNDLE_BYTES: u64 = 1024 * 1024;
+pub const MAX_TEST_DAYS: i64 = 366;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleFormat {
+    Json,
+    Csv,
+}
+
+impl RuleFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "json" => Ok(RuleFormat::Json),
+            "csv" => Ok(RuleFormat::Csv),
+            _ => Err(format!("unknown format '{}', expected csv or json", value)),
+        }
+    }
+
+    /// `.csv` files are CSV, anything else JSON.
+    pub fn from_path(path: &str) -> Self {
+        if path.to_lowercase().ends_with(".csv") {
+            RuleFormat::Csv
+        } else {
+            RuleFormat::Json
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            RuleFormat::Json => "application/json",
+            RuleFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            RuleFormat::Json => "json",
+            RuleFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleSet {
+    pub categories: Vec<CategoryRule>,
+    pub productivity: BTreeMap<String, Productivity>,
+    pub exclusions: Vec<String>, // App names
+}
+
+fn productivity_name(productivity: Productivity) -> String {
+    serde_json::to_value(productivity).ok().and_then(|value| value.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+impl RuleSet {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            categories: config.categories.rules.clone(),
+            productivity: config.categories.productivity.clone(),
+            exclusions: config
+                .app_profiles
+                .iter()
+                .filter(|(_, profile)| !profile.track)
+                .map(|(app, _)| app.clone())
+                .collect(),
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let row = |fields: [&str; 6]| fields.iter().map(|field| csv::field(field)).collect::<Vec<_>>().join(",") + "\n";
+        let mut out = row(CSV_HEADER);
+        for rule in &self.categories {
+            out.push_str(&row([
+                "category",
+                &rule.category,
+                rule.app.as_deref().unwrap_or(""),
+                rule.host.as_deref().unwrap_or(""),
+                rule.title_contains.as_deref().unwrap_or(""),
+                "",
+            ]));
+        }
+        for (category, productivity) in &self.productivity {
+            out.push_str(&row(["productivity", category, "", "", "", &productivity_name(*productivity)]));
+        }
+        for app in &self.exclusions {
+            out.push_str(&row(["exclude", "", app, "", "", ""]));
+        }
+        out
+    }
+
+    pub fn from_csv(text: &str) -> Result<Self, String> {
+        let mut records = csv::parse(text)?.into_iter();
+        let header = records.next().ok_or("the bundle is empty")?;
+        if header.iter().map(|field| field.trim().to_lowercase()).ne(CSV_HEADER.iter().map(|field| field.to_string())) {
+            return Err(format!("the first line must be `{}`", CSV_HEADER.join(",")));
+        }
+
+        let mut rules = RuleSet::default();
+        for (line, mut record) in records.enumerate().map(|(index, record)| (index + 2, record)) {
+            record.resize(CSV_HEADER.len(), String::new());
+            let optional = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+            match record[0].trim() {
+                "category" => rules.categories.push(CategoryRule {
+                    category: record[1].trim().to_string(),
+                    app: optional(&record[2]),
+                    host: optional(&record[3]),
+                    title_contains: optional(&record[4]),
+                }),
+                "productivity" => {
+                    let productivity = serde_json::from_value(serde_json::Value::String(record[5].trim().to_string()))
+                        .map_err(|_| format!("line {}: productivity must be productive, distracting or neutral", line))?;
+                    rules.productivity.insert(record[1].trim().to_string(), productivity);
+                }
+                "exclude" => rules.exclusions.push(record[2].trim().to_string()),
+                kind => return Err(format!("line {}: unknown kind '{}'", line, kind)),
+            }
+        }
+        Ok(rules)
+    }
+
+    pub fn parse(text: &str, format: RuleFormat) -> Result<Self, String> {
+        match format {
+            RuleFormat::Json => serde_json::from_str(text).map_err(|e| format!("invalid rule bundle: {}", e)),
+            RuleFormat::Csv => Self::from_csv(text),
+        }
+    }
+
+    pub fn render(&self, format: RuleFormat) -> String {
+        match format {
+            RuleFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default() + "\n",
+            RuleFormat::Csv => self.to_csv(),
+        }
+    }
+
+    pub fn category_config(&self) -> CategoryConfig {
+        CategoryConfig { rules: self.categories.clone(), productivity: self.productivity.clone() }
+    }
+
+    /// The category a session gets under these rules, or `(excluded)`.
+    fn assign<'a>(&'a self, categories: &'a CategoryConfig, key: &ActivityKey) -> &'a str {
+        if self.exclusions.iter().any(|app| app.eq_ignore_ascii_case(&key.app_name)) {
+            return EXCLUDED;
+        }
+        categories.categorize(key).unwrap_or(UNCATEGORIZED)
+    }
+
+    /// Problems that would make the bundle misbehave; an empty list means valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (index, rule) in self.categories.iter().enumerate() {
+            let name = format!("rule {}", index + 1);
+            if rule.category.trim().is_empty() {
+                errors.push(format!("{}: category is required", name));
+            }
+            if rule.app.is_none() && rule.host.is_none() && rule.title_contains.is_none() {
+                errors.push(format!("{} ({}): needs app, host or title_contains", name, rule.category));
+            }
+            if rule.host.as_deref().is_some_and(|host| host.contains(['/', ':', ' '])) {
+                errors.push(format!("{} ({}): host must be a bare host name such as github.com", name, rule.category));
+            }
+        }
+        if self.productivity.keys().any(|category| category.trim().is_empty()) {
+            errors.push("productivity entries need a category".to_string());
+        }
+        let mut seen: Vec<String> = Vec::new();
+        for app in &self.exclusions {
+            let key = app.to_lowercase();
+            if app.trim().is_empty() {
+                errors.push("exclusions need an app name".to_string());
+            } else if seen.contains(&key) {
+                errors.push(format!("{} is excluded twice", app));
+            }
+            seen.push(key);
+        }
+        errors
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProductivityChange {
+    pub category: String,
+    pub from: Option<Productivity>,
+    pub to: Option<Productivity>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RuleDiff {
+    pub added_rules: Vec<CategoryRule>,
+    pub removed_rules: Vec<CategoryRule>,
+    pub reordered: bool, // Rules in both but in another order; the first match wins
+    pub productivity: Vec<ProductivityChange>,
+    pub added_exclusions: Vec<String>,
+    pub removed_exclusions: Vec<String>,
+}
+
+impl RuleDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == RuleDiff::default()
+    }
+
+    /// One line per change, `+`/`-`/`~` prefixed, for the CLI preview.
+    pub fn lines(&self) -> Vec<String> {
+        let describe = |rule: &CategoryRule| {
+            let conditions: Vec<String> = [("app", &rule.app), ("host", &rule.host), ("title_contains", &rule.title_contains)]
+                .iter()
+                .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+                .collect();
+            format!("{} <- {}", rule.category, conditions.join(" "))
+        };
+        let productivity = |value: Option<Productivity>| value.map(productivity_name).unwrap_or_else(|| "unset".to_string());
+        let mut lines = Vec::new();
+        lines.extend(self.added_rules.iter().map(|rule| format!("+ rule {}", describe(rule))));
+        lines.extend(self.removed_rules.iter().map(|rule| format!("- rule {}", describe(rule))));
+        if self.reordered {
+            lines.push("~ rule order changed".to_string());
+        }
+        lines.extend(self.productivity.iter().map(|change| {
+            format!("~ {} {} -> {}", change.category, productivity(change.from), productivity(change.to))
+        }));
+        lines.extend(self.added_exclusions.iter().map(|app| format!("+ exclude {}", app)));
+        lines.extend(self.removed_exclusions.iter().map(|app| format!("- exclude {}", app)));
+        lines
+    }
+}
+
+pub fn diff(current: &RuleSet, incoming: &RuleSet) -> RuleDiff {
+    let kept_current: Vec<&CategoryRule> =
+        current.categories.iter().filter(|rule| incoming.categories.contains(rule)).collect();
+    let kept_incoming: Vec<&CategoryRule> =
+        incoming.categories.iter().filter(|rule| current.categories.contains(rule)).collect();
+
+    let mut productivity = Vec::new();
+    let categories: std::collections::BTreeSet<&String> =
+        current.productivity.keys().chain(incoming.productivity.keys()).collect();
+    for category in categories {
+        let (from, to) = (current.productivity.get(category).copied(), incoming.productivity.get(category).copied());
+        if from != to {
+            productivity.push(ProductivityChange { category: category.clone(), from, to });
+        }
+    }
+
+    let contains = |list: &[String], app: &str| list.iter().any(|other| other.eq_ignore_ascii_case(app));
+    RuleDiff {
+        added_rules: incoming.categories.iter().filter(|rule| !current.categories.contains(rule)).cloned().collect(),
+        removed_rules: current.categories.iter().filter(|rule| !incoming.categories.contains(rule)).cloned().collect(),
+        reordered: kept_current != kept_incoming,
+        productivity,
+        added_exclusions: incoming.exclusions.iter().filter(|app| !contains(&current.exclusions, app)).cloned().collect(),
+        removed_exclusions: current.exclusions.iter().filter(|app| !contains(&incoming.exclusions, app)).cloned().collect(),
+    }
+}
+
+/// Writes `rules` into the config file, keeping every other setting.
+pub fn apply(config_path: &str, rules: &RuleSet) -> Result<(), String> {
+    let mut root: serde_json::Value = match fs::read_to_string(config_path) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|e| format!("{} is not valid JSON: {}", config_path, e))?,
+        Err(_) => serde_json::json!({}),
+    };
+    let root_map = root.as_object_mut().ok_or_else(|| format!("{} must hold a JSON object", config_path))?;
+
+    let categories = root_map.entry("categories").or_insert_with(|| serde_json::json!({}));
+    let categories = categories.as_object_mut().ok_or("`categories` must be an object")?;
+    categories.insert("rules".to_string(), serde_json::to_value(&rules.categories).map_err(|e| e.to_string())?);
+    categories.insert("productivity".to_string(), serde_json::to_value(&rules.productivity).map_err(|e| e.to_string())?);
+
+    let profiles = root_map.entry("app_profiles").or_insert_with(|| serde_json::json!({}));
+    let profiles = profiles.as_object_mut().ok_or("`app_profiles` must be an object")?;
+    // Lift exclusions the bundle no longer has, dropping profiles left empty
+    for (app, profile) in profiles.iter_mut() {
+        if !rules.exclusions.iter().any(|excluded| excluded.eq_ignore_ascii_case(app)) {
+            if let Some(profile) = profile.as_object_mut() {
+                profile.remove("track");
+            }
+        }
+    }
+    profiles.retain(|_, profile| profile.as_object().is_none_or(|profile| !profile.is_empty()));
+    for app in &rules.exclusions {
+        let key = profiles.keys().find(|key| key.eq_ignore_ascii_case(app)).cloned().unwrap_or_else(|| app.clone());
+        let profile = profiles.entry(key).or_insert_with(|| serde_json::json!({}));
+        if let Some(profile) = profile.as_object_mut() {
+            profile.insert("track".to_string(), serde_json::Value::Bool(false));
+        }
+    }
+
+    let contents = serde_json::to_string_pretty(&root).map_err(|e| e.to_string())? + "\n";
+    fs::write(config_path, contents).map_err(|e| format!("could not write {}: {}", config_path, e))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub diff: RuleDiff,
+    pub applied: bool, // Takes effect on restart
+}
+
+/// Validates and diffs a bundle, writing it only when `apply` is set and it is valid.
+pub fn import(config: &Config, config_path: &str, text: &str, format: RuleFormat, apply_rules: bool) -> Result<ImportReport, String> {
+    let incoming = RuleSet::parse(text, format)?;
+    let errors = incoming.validate();
+    let diff = diff(&RuleSet::from_config(config), &incoming);
+    let applied = apply_rules && errors.is_empty() && !diff.is_empty();
+    if applied {
+        apply(config_path, &incoming)?;
+    }
+    Ok(ImportReport { valid: errors.is_empty(), errors, diff, applied })
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleTest {
+    pub rules: RuleSet,
+    pub from: String, // YYYY-MM-DD, local days, inclusive
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Recategorization {
+    pub from: String,
+    pub to: String,
+    pub sessions: usize,
+    pub seconds: DurationSecs,
+    pub examples: Vec<String>, // `app: title or url` of the longest sessions
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub before_secs: DurationSecs,
+    pub after_secs: DurationSecs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleTestReport {
+    pub errors: Vec<String>, // Validation problems of the candidate
+    pub sessions: usize,
+    pub changed_sessions: usize,
+    pub changed_seconds: DurationSecs,
+    pub changes: Vec<Recategorization>, // Most time moved first
+    pub totals: Vec<CategoryTotal>,
+}
+
+/// How `sessions` would be categorized under `candidate` instead of `current`.
+pub fn test_rules(sessions: &[Session], current: &RuleSet, candidate: &RuleSet) -> RuleTestReport {
+    let (current_categories, candidate_categories) = (current.category_config(), candidate.category_config());
+    let mut sessions: Vec<&Session> = sessions.iter().collect();
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.duration()));
+
+    let mut changes: BTreeMap<(&str, &str), Recategorization> = BTreeMap::new();
+    let mut totals: BTreeMap<&str, (DurationSecs, DurationSecs)> = BTreeMap::new();
+    for session in &sessions {
+        let key = ActivityKey::parse(&session.identifier);
+        let before = current.assign(&current_categories, &key);
+        let after = candidate.assign(&candidate_categories, &key);
+        totals.entry(before).or_default().0 += session.duration();
+        totals.entry(after).or_default().1 += session.duration();
+        if before == after {
+            continue;
+        }
+        let change = changes.entry((before, after)).or_insert_with(|| Recategorization {
+            from: before.to_string(),
+            to: after.to_string(),
+            sessions: 0,
+            seconds: DurationSecs::ZERO,
+            examples: Vec::new(),
+        });
+        change.sessions += 1;
+        change.seconds += session.duration();
+        let example = format!("{}: {}", key.app_name, key.url().unwrap_or(key.window_title()));
+        if change.examples.len() < MAX_EXAMPLES && !change.examples.contains(&example) {
+            change.examples.push(example);
+        }
+    }
+
+    let mut changes: Vec<Recategorization> = changes.into_values().collect();
+    changes.sort_by_key(|change| std::cmp::Reverse(change.seconds));
+    RuleTestReport {
+        errors: candidate.validate(),
+        sessions: sessions.len(),
+        changed_sessions: changes.iter().map(|change| change.sessions).sum(),
+        changed_seconds: changes.iter().map(|change| change.seconds).sum(),
+        changes,
+        totals: totals
+            .into_iter()
+            .map(|(category, (before_secs, after_secs))| CategoryTotal { category: category.to_string(), before_secs, after_secs })
+            .collect(),
+    }
+}
+
+/// `sysmonitor rules export [--format csv|json]` or `rules import <file> [--format csv|json] [--apply]`
+pub fn run_command(config: &Config, config_path: &str, args: &[String]) -> Result<(), String> {
+    let format = args
+        .iter()
+        .position(|arg| arg == "--format")
+        .and_then(|index| args.get(index + 1))
+        .map(|format| RuleFormat::parse(format))
+        .transpose()?;
+    match args.first().map(String::as_str) {
+        Some("export") => {
+            print!("{}", RuleSet::from_config(config).render(format.unwrap_or(RuleFormat::Json)));
+            Ok(())
+        }
+        Some("import") => {
+            let path = args.get(1).filter(|path| !path.starts_with("--")).ok_or("usage: rules import <file> [--apply]")?;
+            let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+            let apply_rules = args.iter().any(|arg| arg == "--apply");
+            let report = import(config, config_path, &text, format.unwrap_or_else(|| RuleFormat::from_path(path)), apply_rules)?;
+            for error in &report.errors {
+                eprintln!("error: {}", error);
+            }
+            if report.diff.is_empty() {
+                println!("No changes against the current rules");
+            }
+            for line in report.diff.lines() {
+                println!("{}", line);
+            }
+            if !report.valid {
+                return Err("The bundle has errors; nothing was applied".to_string());
+            }
+            if report.applied {
+                println!("Applied to {}; restart sysmonitor to use the new rules", config_path);
+            } else if !report.diff.is_empty() {
+                println!("Preview only; run again with --apply to write {}", config_path);
+            }
+            Ok(())
+        }
+        _ => Err("usage: rules export|import".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Timestamp;
+
+    fn rule(category: &str, app: &str) -> CategoryRule {
+        CategoryRule { category: category.to_string(), app: Some(app.to_string()), ..CategoryRule::default() }
+    }
+
+    fn rule_set() -> RuleSet {
+        RuleSet {
+            categories: vec![
+                rule("Dev", "code.exe"),
+                CategoryRule { category: "Chat".into(), host: Some("slack.com".into()), ..CategoryRule::default() },
+            ],
+            productivity: BTreeMap::from([("Dev".to_string(), Productivity::Productive)]),
+            exclusions: vec!["KeePass, Pro.exe".to_string()],
+        }
+    }
+
+    #[test]
+    fn csv_bundles_round_trip() {
+        let rules = rule_set();
+        assert_eq!(RuleSet::from_csv(&rules.to_csv()).unwrap(), rules);
+        assert!(RuleSet::from_csv("kind,category\ncategory,Dev").is_err());
+        let bad_kind = format!("{}\nblock,Dev,,,,\n", CSV_HEADER.join(","));
+        assert_eq!(RuleSet::from_csv(&bad_kind).unwrap_err(), "line 2: unknown kind 'block'");
+    }
+
+    #[test]
+    fn validation_reports_each_problem() {
+        let mut rules = rule_set();
+        assert!(rules.validate().is_empty());
+        rules.categories.push(CategoryRule { category: "Empty".into(), ..CategoryRule::default() });
+        rules.categories.push(CategoryRule { category: "Web".into(), host: Some("https://x.com".into()), ..CategoryRule::default() });
+        rules.exclusions.push("keepass, pro.exe".to_string());
+        assert_eq!(rules.validate().len(), 3);
+    }
+
+    #[test]
+    fn diff_lists_changes_and_reordering() {
+        let current = rule_set();
+        let mut incoming = rule_set();
+        incoming.categories.reverse();
+        incoming.categories.push(rule("Games", "steam.exe"));
+        incoming.productivity.insert("Games".to_string(), Productivity::Distracting);
+        incoming.exclusions.clear();
+
+        let changes = diff(&current, &incoming);
+        assert_eq!(changes.added_rules, vec![rule("Games", "steam.exe")]);
+        assert!(changes.reordered && changes.removed_rules.is_empty());
+        assert_eq!(changes.productivity.len(), 1);
+        assert_eq!(changes.removed_exclusions, vec!["KeePass, Pro.exe".to_string()]);
+        assert!(diff(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn candidate_rules_are_tested_without_changing_anything() {
+        let session = |app: &str, title: &str, start: u64, end: u64| Session {
+            identifier: ActivityKey::new(app, title, None).to_string(),
+            app_name: app.to_string(),
+            start: Timestamp::from_secs(start),
+            end: Timestamp::from_secs(end),
+        };
+        let sessions = vec![
+            session("code.exe", "main.rs", 0, 600),
+            session("slack.exe", "general", 600, 700),
+            session("steam.exe", "Store", 700, 1000),
+        ];
+        let current = rule_set();
+        let mut candidate = rule_set();
+        candidate.categories.push(rule("Chat", "slack.exe"));
+        candidate.exclusions.push("steam.exe".to_string());
+
+        let report = test_rules(&sessions, &current, &candidate);
+        assert_eq!((report.sessions, report.changed_sessions, report.changed_seconds.as_secs()), (3, 2, 400));
+        assert_eq!((report.changes[0].from.as_str(), report.changes[0].to.as_str()), (UNCATEGORIZED, EXCLUDED));
+        assert_eq!(report.changes[1].examples, vec!["slack.exe: general".to_string()]);
+        let dev = report.totals.iter().find(|total| total.category == "Dev").unwrap();
+        assert_eq!((dev.before_secs, dev.after_secs), (DurationSecs::secs(600), DurationSecs::secs(600)));
+    }
+}
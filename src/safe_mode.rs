@@ -0,0 +1,143 @@
+// Safe mode after repeated crashes, from the `safe_mode` section of the config.
+//
+// While the tracker or the server starts up it keeps a marker file,
+// `sysmonitor.running` next to the database, holding how many starts before
+// it died early. A clean shutdown removes it, and so does running for
+// `stable_secs`. Finding it at the next start means the last run crashed
+// early, so the count goes up. After `after_crashes` such starts in a row the
+// process starts in safe mode, where a bad rule in the config can't take it
+// down again:
+//
+// - the tracker records apps and titles only: no URL extraction, title
+//   summaries, app profiles or host disambiguation
+// - no integrations run: notifications, Notion, InfluxDB, Home Assistant,
+//   telemetry, daily notes and the goal digest
+//
+// Category rules stay, since stored assignments and totals are built with
+// them. `/api/health`, the dashboard and a `Sysmonitor-Safe-Mode` header on
+// every answer say why. Safe mode lasts until the next start; one that runs
+// for `stable_secs` clears the count, so fixing the config and restarting is
+// enough. `--safe-mode` starts in it on purpose.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::notify::NotificationConfig;
+use crate::notion::NotionConfig;
+use crate::summarize::SummaryConfig;
+
+const MARKER: &str = "sysmonitor.running";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafeModeConfig {
+    pub after_crashes: u32, // Crashed starts in a row; 0 never starts in safe mode on its own
+    pub stable_secs: u64, // Running this long counts as a good start
+}
+
+impl Default for SafeModeConfig {
+    fn default() -> Self {
+        Self { after_crashes: 3, stable_secs: 300 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Marker {
+    crashed_starts: u32, // Before the start that wrote it
+    started_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafeMode {
+    pub reason: String,
+    pub crashed_starts: u32,
+    pub since: u64,
+}
+
+pub fn marker_path(db_path: &str) -> PathBuf {
+    Path::new(db_path).parent().unwrap_or(Path::new("")).join(MARKER)
+}
+
+/// Counts this start in `marker`; Some when it has to run in safe mode.
+pub fn start(config: &SafeModeConfig, marker: &Path, forced: bool, now: u64) -> Option<SafeMode> {
+    // A marker that can't be read still means the last start never finished
+    let crashed_starts = match fs::read_to_string(marker) {
+        Ok(contents) => serde_json::from_str::<Marker>(&contents).map_or(1, |last| last.crashed_starts + 1),
+        Err(_) => 0,
+    };
+    let contents = serde_json::to_string(&Marker { crashed_starts, started_at: now }).unwrap();
+    if let Err(e) = fs::write(marker, contents) {
+        crate::logs::error!("Error writing {}: {}", marker.display(), e);
+    }
+    let reason = if forced {
+        "started with --safe-mode".to_string()
+    } else if config.after_crashes > 0 && crashed_starts >= config.after_crashes {
+        format!("the last {} starts crashed within {} seconds", crashed_starts, config.stable_secs)
+    } else {
+        return None;
+    };
+    Some(SafeMode { reason, crashed_starts, since: now })
+}
+
+/// Forgets the crashed starts: this run ended cleanly or ran long enough.
+pub fn clear(marker: &Path) {
+    let _ = fs::remove_file(marker);
+}
+
+/// Turns off what safe mode runs without.
+pub fn restrict(config: &mut Config) {
+    config.app_profiles.clear();
+    config.title_summaries = SummaryConfig { builtin: false, rules: Vec::new() };
+    config.notifications = NotificationConfig::default();
+    config.notion = NotionConfig::default();
+    config.influx.url = None;
+    config.home_assistant.mqtt = None;
+    config.telemetry.endpoint = None;
+    config.daily_notes.dir = None;
+    config.goals.digest_hour = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_crashed_starts_lead_to_safe_mode_until_a_good_one() {
+        let dir = std::env::temp_dir().join(format!("sysmonitor-safe-mode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let marker = marker_path(dir.join("usage.db").to_str().unwrap());
+        clear(&marker);
+        let config = SafeModeConfig::default();
+
+        // Every start dies before clearing its marker
+        assert_eq!(start(&config, &marker, false, 100), None);
+        assert_eq!(start(&config, &marker, false, 110), None);
+        assert_eq!(start(&config, &marker, false, 120), None);
+        let safe = start(&config, &marker, false, 130).unwrap();
+        assert_eq!((safe.crashed_starts, safe.since), (3, 130));
+        assert!(start(&SafeModeConfig { after_crashes: 0, ..config.clone() }, &marker, false, 140).is_none());
+
+        clear(&marker);
+        assert_eq!(start(&config, &marker, false, 200), None);
+        assert_eq!(start(&config, &marker, true, 210).unwrap().reason, "started with --safe-mode");
+
+        fs::write(&marker, "garbage").unwrap();
+        assert!(start(&SafeModeConfig { after_crashes: 1, ..config }, &marker, false, 300).is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn safe_mode_turns_off_integrations_and_extractors() {
+        let mut config: Config = serde_json::from_str(
+            r#"{ "influx": { "url": "http://localhost:8086" }, "daily_notes": { "dir": "notes" },
+                 "app_profiles": { "code.exe": { "capture_urls": false } } }"#,
+        )
+        .unwrap();
+        restrict(&mut config);
+        assert!(config.influx.url.is_none() && config.daily_notes.dir.is_none() && config.goals.digest_hour.is_none());
+        assert!(config.app_profiles.is_empty() && !config.title_summaries.builtin);
+    }
+}
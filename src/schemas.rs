@@ -0,0 +1,564 @@
+// JSON Schemas (draft 2020-12) of the main API payloads, served at
+// `/api/schemas/<name>.json` for integrators to validate their clients.
+//
+// Each schema is written next to the serde type it describes, field for field,
+// with the serde attributes in mind: `skip_serializing_if` and `default`
+// fields are optional, untagged `Option`s are nullable. The tests serialize
+// real values and check them against the schemas, so a field added to a type
+// without its schema fails there.
+
+use serde_json::{json, Map, Value};
+
+use crate::export::ExportRow;
+use crate::identifier::{ActivityKey, ActivityKind};
+use crate::ingest::{BatchResult, ClockSkew, IngestBatch, IngestEvent, ItemResult};
+use crate::notify::{EventKind, FlatEvent};
+use crate::origin::AppOrigin;
+use crate::reports::{AppUsage, DailyReport};
+use crate::trends::{Direction, Trend};
+use crate::safe_mode::SafeMode;
+use crate::{DashboardData, RecentActivity};
+
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+pub trait JsonSchema {
+    fn schema() -> Value;
+}
+
+macro_rules! integer_schema {
+    ($($ty:ty => $minimum:expr),*) => {
+        $(impl JsonSchema for $ty {
+            fn schema() -> Value {
+                match $minimum {
+                    Some(minimum) => json!({ "type": "integer", "minimum": minimum }),
+                    None => json!({ "type": "integer" }),
+                }
+            }
+        })*
+    };
+}
+
+integer_schema!(u32 => Some(0), u64 => Some(0), usize => Some(0), i64 => None::<u64>);
+
+impl JsonSchema for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl JsonSchema for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl JsonSchema for f64 {
+    fn schema() -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl JsonSchema for Value {
+    fn schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn schema() -> Value {
+        let inner = T::schema();
+        match inner.get("type") {
+            Some(Value::String(kind)) if inner.get("enum").is_none() => {
+                let mut nullable = inner.clone();
+                nullable["type"] = json!([kind, "null"]);
+                nullable
+            }
+            _ => json!({ "anyOf": [inner, { "type": "null" }] }),
+        }
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl<A: JsonSchema, B: JsonSchema> JsonSchema for (A, B) {
+    fn schema() -> Value {
+        json!({ "type": "array", "prefixItems": [A::schema(), B::schema()], "items": false, "minItems": 2 })
+    }
+}
+
+// A property and whether it is always present
+struct Field {
+    name: &'static str,
+    schema: Value,
+    required: bool,
+}
+
+fn field<T: JsonSchema>(name: &'static str) -> Field {
+    Field { name, schema: T::schema(), required: true }
+}
+
+fn optional<T: JsonSchema>(name: &'static str) -> Field {
+    Field { name, schema: T::schema(), required: false }
+}
+
+fn described(mut field: Field, description: &str) -> Field {
+    field.schema["description"] = json!(description);
+    field
+}
+
+fn object(fields: Vec<Field>, closed: bool) -> Value {
+    let required: Vec<&str> = fields.iter().filter(|field| field.required).map(|field| field.name).collect();
+    let properties: Map<String, Value> = fields.into_iter().map(|field| (field.name.to_string(), field.schema)).collect();
+    json!({ "type": "object", "properties": properties, "required": required, "additionalProperties": !closed })
+}
+
+fn string_enum(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+impl JsonSchema for ActivityKind {
+    fn schema() -> Value {
+        string_enum(&["url", "title"])
+    }
+}
+
+impl JsonSchema for ActivityKey {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<String>("app_name"),
+                field::<ActivityKind>("kind"),
+                described(field::<String>("detail"), "The URL or window title"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for RecentActivity {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<ActivityKey>("key"),
+                field::<String>("app_name"),
+                field::<String>("window_title"),
+                field::<Option<String>>("url"),
+                field::<u64>("duration"),
+                described(field::<u64>("timestamp"), "Unix seconds"),
+                field::<bool>("partial"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for DashboardData {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<Option<String>>("current_app"),
+                field::<Option<String>>("current_window"),
+                field::<Option<String>>("current_url"),
+                described(field::<Vec<(ActivityKey, u64)>>("active_apps"), "Activities in focus, at most 10, and seconds since each started"),
+                field::<Vec<RecentActivity>>("recent_activity"),
+                field::<usize>("total_apps"),
+                described(field::<u64>("uptime"), "Seconds"),
+                described(field::<Option<SafeMode>>("safe_mode"), "Why this run started in safe mode, null otherwise"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for SafeMode {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<String>("reason"),
+                described(field::<u32>("crashed_starts"), "Starts in a row that crashed before this one"),
+                described(field::<u64>("since"), "Unix seconds"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for ExportRow {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<String>("identifier"),
+                field::<String>("app_name"),
+                field::<String>("window_title"),
+                field::<Option<String>>("url"),
+                field::<u64>("duration"),
+                described(field::<u64>("timestamp"), "Unix seconds at the end of the session"),
+                field::<bool>("partial"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for AppUsage {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<String>("app_name"),
+                field::<u64>("duration"),
+                described(optional::<AppOrigin>("origin"), "Where it was last launched from"),
+                described(optional::<String>("display_name"), "The executable's description, e.g. \"Microsoft Word\""),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for AppOrigin {
+    fn schema() -> Value {
+        string_enum(&["local", "removable", "network", "unknown"])
+    }
+}
+
+impl JsonSchema for Direction {
+    fn schema() -> Value {
+        string_enum(&["up", "down", "flat"])
+    }
+}
+
+impl JsonSchema for Trend {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<String>("name"),
+                field::<u64>("seconds"),
+                field::<u64>("avg_7d_secs"),
+                field::<u64>("avg_30d_secs"),
+                field::<Option<f64>>("change_vs_7d_percent"),
+                field::<Option<f64>>("change_vs_30d_percent"),
+                field::<Direction>("direction"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for DailyReport {
+    fn schema() -> Value {
+        object(
+            vec![
+                described(field::<String>("date"), "YYYY-MM-DD"),
+                field::<u64>("total_duration"),
+                field::<Vec<AppUsage>>("apps"),
+                field::<bool>("finalized"),
+                described(optional::<Vec<Trend>>("trends"), "With ?trends=true"),
+            ],
+            true,
+        )
+    }
+}
+
+// Fields newer than this build are ignored, so events stay open
+impl JsonSchema for IngestEvent {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<String>("app_name"),
+                optional::<String>("window_title"),
+                optional::<Option<String>>("url"),
+                described(field::<u64>("start"), "Unix seconds"),
+                field::<u64>("duration"),
+            ],
+            false,
+        )
+    }
+}
+
+impl JsonSchema for IngestBatch {
+    fn schema() -> Value {
+        object(
+            vec![
+                optional::<u32>("schema_version"),
+                optional::<String>("source"),
+                optional::<Option<String>>("device"),
+                described(optional::<Option<u64>>("sent_at"), "Collector clock in Unix seconds"),
+                field::<Vec<IngestEvent>>("events"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for ClockSkew {
+    fn schema() -> Value {
+        object(vec![field::<i64>("secs"), field::<bool>("corrected")], true)
+    }
+}
+
+impl JsonSchema for ItemResult {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<usize>("index"),
+                field::<bool>("accepted"),
+                field::<u64>("stored_secs"),
+                field::<usize>("conflicts"),
+                optional::<Vec<String>>("ignored_fields"),
+                field::<Option<String>>("error"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for BatchResult {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<u32>("schema_version"),
+                field::<usize>("accepted"),
+                field::<usize>("rejected"),
+                optional::<ClockSkew>("clock_skew"),
+                field::<Vec<ItemResult>>("items"),
+            ],
+            true,
+        )
+    }
+}
+
+impl JsonSchema for EventKind {
+    fn schema() -> Value {
+        let kinds: Vec<Value> = EventKind::ALL.iter().map(|kind| json!(kind)).collect();
+        json!({ "type": "string", "enum": kinds })
+    }
+}
+
+impl JsonSchema for FlatEvent {
+    fn schema() -> Value {
+        object(
+            vec![
+                field::<EventKind>("event"),
+                field::<String>("title"),
+                field::<String>("message"),
+                described(field::<u64>("timestamp"), "Unix time the event was raised"),
+                described(field::<String>("time"), "The same in local RFC 3339"),
+                described(field::<bool>("test"), "Sent by POST /api/notifications/test"),
+            ],
+            false,
+        )
+    }
+}
+
+struct SchemaFile {
+    name: &'static str,
+    description: &'static str,
+    schema: fn() -> Value,
+}
+
+const SCHEMAS: [SchemaFile; 6] = [
+    SchemaFile { name: "dashboard", description: "`data` of GET /api/dashboard", schema: DashboardData::schema },
+    SchemaFile { name: "session", description: "A row of /api/export/history.json", schema: ExportRow::schema },
+    SchemaFile { name: "daily-report", description: "`data` of GET /api/reports/daily/YYYY-MM-DD", schema: DailyReport::schema },
+    SchemaFile { name: "ingest-batch", description: "Body of POST /api/ingest/batch", schema: IngestBatch::schema },
+    SchemaFile { name: "ingest-result", description: "`data` of POST /api/ingest/batch", schema: BatchResult::schema },
+    SchemaFile { name: "webhook-event", description: "Body of webhooks with `\"format\": \"flat\"`", schema: FlatEvent::schema },
+];
+
+fn id(name: &str) -> String {
+    format!("/api/schemas/{}.json", name)
+}
+
+/// The schemas served, as `{name, description, url}`.
+pub fn index() -> Vec<Value> {
+    SCHEMAS
+        .iter()
+        .map(|file| json!({ "name": file.name, "description": file.description, "url": id(file.name) }))
+        .collect()
+}
+
+/// The schema document for a `<name>.json` file name.
+pub fn document(file_name: &str) -> Option<Value> {
+    let name = file_name.strip_suffix(".json")?;
+    let file = SCHEMAS.iter().find(|file| file.name == name)?;
+    let mut document = json!({ "$schema": DIALECT, "$id": id(name), "title": name, "description": file.description });
+    document.as_object_mut()?.extend((file.schema)().as_object()?.clone());
+    Some(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{DurationSecs, Timestamp};
+    use crate::archive::ArchiveRow;
+
+    // The parts of JSON Schema used above
+    fn check(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+            return options
+                .iter()
+                .find(|option| check(option, value, path).is_ok())
+                .map(|_| ())
+                .ok_or_else(|| format!("{}: matches no option", path));
+        }
+        let type_of = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        let allowed = |kind: &Value| kind == type_of || (kind == "number" && type_of == "integer");
+        match schema.get("type") {
+            Some(Value::Array(kinds)) if !kinds.iter().any(allowed) => return Err(format!("{}: {} not allowed", path, type_of)),
+            Some(kind @ Value::String(_)) if !allowed(kind) => return Err(format!("{}: {} is not {}", path, type_of, kind)),
+            _ => {}
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            if !values.contains(value) {
+                return Err(format!("{}: {} not in enum", path, value));
+            }
+        }
+        if let (Some(minimum), Some(number)) = (schema.get("minimum").and_then(Value::as_f64), value.as_f64()) {
+            if number < minimum {
+                return Err(format!("{}: below {}", path, minimum));
+            }
+        }
+        if let Value::Object(object) = value {
+            let properties = schema["properties"].as_object().cloned().unwrap_or_default();
+            for required in schema["required"].as_array().into_iter().flatten() {
+                if !object.contains_key(required.as_str().unwrap()) {
+                    return Err(format!("{}: missing {}", path, required));
+                }
+            }
+            for (key, item) in object {
+                match properties.get(key) {
+                    Some(property) => check(property, item, &format!("{}.{}", path, key))?,
+                    None if schema["additionalProperties"] == false => return Err(format!("{}: unexpected {}", path, key)),
+                    None => {}
+                }
+            }
+        }
+        if let Value::Array(items) = value {
+            let prefix = schema["prefixItems"].as_array().cloned().unwrap_or_default();
+            for (index, item) in items.iter().enumerate() {
+                let path = format!("{}[{}]", path, index);
+                match prefix.get(index) {
+                    Some(item_schema) => check(item_schema, item, &path)?,
+                    None if schema["items"] == false => return Err(format!("{}: too many items", path)),
+                    None if schema["items"].is_object() => check(&schema["items"], item, &path)?,
+                    None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn conforms<T: JsonSchema + serde::Serialize>(value: &T) -> Result<(), String> {
+        check(&T::schema(), &serde_json::to_value(value).unwrap(), "$")
+    }
+
+    #[test]
+    fn serialized_payloads_match_their_schemas() {
+        let key = ActivityKey::new("chrome.exe", "", Some("https://example.com"));
+        let dashboard = DashboardData {
+            current_app: Some("chrome.exe".into()),
+            current_window: None,
+            current_url: Some("https://example.com".into()),
+            active_apps: vec![(key.clone(), DurationSecs::secs(30))],
+            recent_activity: vec![RecentActivity {
+                key,
+                app_name: "chrome.exe".into(),
+                window_title: String::new(),
+                url: Some("https://example.com".into()),
+                duration: DurationSecs::secs(30),
+                timestamp: Timestamp::from_secs(1_700_000_000),
+                partial: false,
+            }],
+            total_apps: 1,
+            uptime: DurationSecs::mins(1),
+            safe_mode: Some(SafeMode { reason: "started with --safe-mode".into(), crashed_starts: 0, since: 1_700_000_000 }),
+        };
+        assert_eq!(conforms(&dashboard), Ok(()));
+
+        let row = ArchiveRow {
+            identifier: "code.exe:t:main.rs".into(),
+            app_name: "code.exe".into(),
+            window_title: "main.rs".into(),
+            url: None,
+            timestamp: Timestamp::from_secs(1_700_000_000),
+            duration: DurationSecs::mins(1),
+            partial: true,
+            source: None,
+            clock_skew: None,
+        };
+        assert_eq!(conforms(&ExportRow::from(row)), Ok(()));
+
+        let trend = Trend {
+            name: "code.exe".into(),
+            seconds: 60,
+            avg_7d_secs: 0,
+            avg_30d_secs: 0,
+            change_vs_7d_percent: None,
+            change_vs_30d_percent: Some(12.5),
+            direction: Direction::Up,
+        };
+        let report = DailyReport {
+            date: "2024-03-01".into(),
+            total_duration: DurationSecs::mins(1),
+            apps: vec![
+                AppUsage { app_name: "code.exe".into(), duration: DurationSecs::mins(1), origin: Some(AppOrigin::Network), display_name: Some("Visual Studio Code".into()) },
+                AppUsage { app_name: "chat.exe".into(), duration: DurationSecs::ZERO, origin: None, display_name: None },
+            ],
+            finalized: true,
+            trends: Some(vec![trend]),
+        };
+        assert_eq!(conforms(&report), Ok(()));
+
+        let result = BatchResult {
+            schema_version: 1,
+            accepted: 0,
+            rejected: 1,
+            clock_skew: Some(ClockSkew { secs: -400, corrected: false }),
+            items: vec![ItemResult {
+                index: 0,
+                accepted: false,
+                stored_secs: DurationSecs::ZERO,
+                conflicts: 0,
+                ignored_fields: vec!["color".into()],
+                error: Some("duration must be positive".into()),
+            }],
+        };
+        assert_eq!(conforms(&result), Ok(()));
+
+        let event = FlatEvent {
+            event: EventKind::Digest,
+            title: "Weekly goals".into(),
+            message: "Dev: 8h 00m of 20h 00m".into(),
+            timestamp: 1_760_000_000,
+            time: "2025-10-09T10:53:20+02:00".into(),
+            test: false,
+        };
+        assert_eq!(conforms(&event), Ok(()));
+    }
+
+    #[test]
+    fn batches_the_schema_accepts_deserialize() {
+        let schema = IngestBatch::schema();
+        let batch = json!({
+            "source": "phone",
+            "sent_at": 1_760_000_000u64,
+            "events": [{ "app_name": "maps", "start": 1_760_000_000u64, "duration": 60, "color": "blue" }],
+        });
+        assert_eq!(check(&schema, &batch, "$"), Ok(()));
+        assert!(serde_json::from_value::<IngestBatch>(batch).is_ok());
+        assert!(check(&schema, &json!({ "source": "phone" }), "$").is_err());
+        assert!(document("ingest-batch.json").is_some_and(|doc| doc["$schema"] == DIALECT));
+        assert!(document("nope.json").is_none());
+    }
+}
@@ -0,0 +1,309 @@
+// `sysmonitor server`: the HTTP API and storage without the desktop tracker,
+// meant to run in a container as the aggregation endpoint that collectors
+// upload to. Configured through the environment:
+//
+//   SYSMONITOR_BIND    address to listen on (default 0.0.0.0:3030)
+//   SYSMONITOR_DB      database path (default usage.db)
+//   SYSMONITOR_CONFIG  config file (default sysmonitor.json)
+//   SYSMONITOR_DEBUG   `1` or `true` for debug output
+//
+// The server is reachable beyond localhost, so every request needs a token.
+// `sysmonitor server install-unit` prints a service definition for it: a
+// hardened systemd unit, or an NSSM install script on Windows.
+
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::units::DurationSecs;
+use crate::{
+    clear_crash_marker_when_stable, crash, logs, safe_mode, web, SystemMonitor, CATEGORY_REFRESH, DAILY_NOTE_CHECK, DEVICE_CHECK, DIGEST_CHECK, GRAFANA_REFRESH,
+    INFLUX_CHECK, MAINTENANCE_CHECK, MEMORY_CHECK, NOTION_SYNC, PAUSE_CHECK, ROLLUP_REFRESH, TELEMETRY_EXPORT,
+};
+
+pub const BIND_ENV: &str = "SYSMONITOR_BIND";
+pub const DEBUG_ENV: &str = "SYSMONITOR_DEBUG";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerSettings {
+    pub bind: SocketAddr,
+    pub debug: bool,
+}
+
+impl ServerSettings {
+    pub fn from_env(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let bind = match lookup(BIND_ENV) {
+            Some(bind) => bind.parse().map_err(|_| format!("{} must be host:port, got '{}'", BIND_ENV, bind))?,
+            None => SocketAddr::from(([0, 0, 0, 0], web::WEB_PORT)),
+        };
+        let debug = lookup(DEBUG_ENV).is_some_and(|value| matches!(value.trim(), "1" | "true" | "yes"));
+        Ok(Self { bind, debug })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitOptions {
+    pub exe: String,
+    pub user: String,
+    pub data_dir: String,
+    pub bind: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitFormat {
+    Systemd,
+    Nssm,
+}
+
+impl UnitOptions {
+    fn from_args(args: &[String], format: UnitFormat, exe: String) -> Self {
+        let option = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|index| args.get(index + 1))
+                .cloned()
+        };
+        let (user, data_dir) = match format {
+            UnitFormat::Systemd => ("sysmonitor", "/var/lib/sysmonitor"),
+            UnitFormat::Nssm => ("NT AUTHORITY\\LocalService", "C:\\ProgramData\\sysmonitor"),
+        };
+        Self {
+            exe,
+            user: option("--user").unwrap_or_else(|| user.to_string()),
+            data_dir: option("--data-dir").unwrap_or_else(|| data_dir.to_string()),
+            bind: option("--bind").unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], web::WEB_PORT)).to_string()),
+        }
+    }
+
+    fn data_file(&self, name: &str, separator: char) -> String {
+        format!("{}{}{}", self.data_dir.trim_end_matches(separator), separator, name)
+    }
+}
+
+pub fn systemd_unit(options: &UnitOptions) -> String {
+    // `StateDirectory` creates the data directory owned by the service user
+    let state_directory = options
+        .data_dir
+        .strip_prefix("/var/lib/")
+        .map(|name| format!("StateDirectory={}\nStateDirectoryMode=0750\n", name))
+        .unwrap_or_default();
+    format!(
+        "[Unit]
+Description=sysmonitor aggregation server
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=simple
+User={user}
+Group={user}
+ExecStart={exe} server
+WorkingDirectory={data_dir}
+{state_directory}Environment=SYSMONITOR_BIND={bind}
+Environment=SYSMONITOR_DB={db}
+Environment=SYSMONITOR_CONFIG={config}
+Restart=on-failure
+RestartSec=5
+
+# Hardening: the server only needs its data directory and the network
+NoNewPrivileges=yes
+ProtectSystem=strict
+ProtectHome=yes
+ReadWritePaths={data_dir}
+PrivateTmp=yes
+PrivateDevices=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectKernelLogs=yes
+ProtectControlGroups=yes
+ProtectClock=yes
+ProtectHostname=yes
+RestrictAddressFamilies=AF_INET AF_INET6 AF_UNIX
+RestrictNamespaces=yes
+RestrictRealtime=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+MemoryDenyWriteExecute=yes
+SystemCallArchitectures=native
+CapabilityBoundingSet=
+UMask=0027
+
+[Install]
+WantedBy=multi-user.target
+",
+        user = options.user,
+        exe = options.exe,
+        data_dir = options.data_dir,
+        state_directory = state_directory,
+        bind = options.bind,
+        db = options.data_file("usage.db", '/'),
+        config = options.data_file("sysmonitor.json", '/'),
+    )
+}
+
+pub fn nssm_script(options: &UnitOptions) -> String {
+    let db = options.data_file("usage.db", '\\');
+    let config = options.data_file("sysmonitor.json", '\\');
+    let log = options.data_file("server.log", '\\');
+    format!(
+        "@echo off
+rem sysmonitor aggregation server as a Windows service (run as administrator)
+mkdir \"{data_dir}\" 2>nul
+nssm install sysmonitor \"{exe}\" server
+nssm set sysmonitor DisplayName \"sysmonitor aggregation server\"
+nssm set sysmonitor AppDirectory \"{data_dir}\"
+nssm set sysmonitor AppEnvironmentExtra SYSMONITOR_BIND={bind} \"SYSMONITOR_DB={db}\" \"SYSMONITOR_CONFIG={config}\"
+nssm set sysmonitor ObjectName \"{user}\"
+nssm set sysmonitor Start SERVICE_AUTO_START
+nssm set sysmonitor AppExit Default Restart
+nssm set sysmonitor AppRestartDelay 5000
+nssm set sysmonitor AppStdout \"{log}\"
+nssm set sysmonitor AppStderr \"{log}\"
+nssm set sysmonitor AppRotateFiles 1
+nssm set sysmonitor AppRotateBytes 10485760
+icacls \"{data_dir}\" /inheritance:r /grant:r \"{user}:(OI)(CI)M\" /grant:r \"Administrators:(OI)(CI)F\"
+nssm start sysmonitor
+",
+        data_dir = options.data_dir,
+        exe = options.exe,
+        bind = options.bind,
+        db = db,
+        config = config,
+        user = options.user,
+        log = log,
+    )
+}
+
+// `install-unit [--format systemd|nssm] [--user U] [--data-dir D] [--bind ADDR]`
+fn install_unit(args: &[String]) -> Result<(), String> {
+    let format = match args.iter().position(|arg| arg == "--format").and_then(|index| args.get(index + 1)) {
+        Some(format) if format == "systemd" => UnitFormat::Systemd,
+        Some(format) if format == "nssm" => UnitFormat::Nssm,
+        Some(format) => return Err(format!("unknown --format '{}', expected systemd or nssm", format)),
+        None if cfg!(windows) => UnitFormat::Nssm,
+        None => UnitFormat::Systemd,
+    };
+    let exe = env::current_exe().map_err(|e| format!("cannot locate the sysmonitor binary: {}", e))?;
+    let options = UnitOptions::from_args(args, format, exe.to_string_lossy().to_string());
+    match format {
+        UnitFormat::Systemd => {
+            print!("{}", systemd_unit(&options));
+            eprintln!("Save as /etc/systemd/system/sysmonitor.service, create the user with");
+            eprintln!("`useradd --system --no-create-home {}`, then `systemctl enable --now sysmonitor`", options.user);
+            if !options.data_dir.starts_with("/var/lib/") {
+                eprintln!("{} is not created by systemd; create it owned by {} first", options.data_dir, options.user);
+            }
+        }
+        UnitFormat::Nssm => {
+            print!("{}", nssm_script(&options));
+            eprintln!("Save as install-sysmonitor.cmd and run it from an elevated prompt with nssm on PATH");
+        }
+    }
+    Ok(())
+}
+
+/// `sysmonitor server [install-unit ...]`
+pub async fn command(args: &[String], debug_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match args.first().map(String::as_str) {
+        Some("install-unit") => Ok(install_unit(&args[1..])?),
+        _ => run(debug_mode).await,
+    }
+}
+
+async fn run(debug_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let settings = ServerSettings::from_env(|name| env::var(name).ok())?;
+    let mut monitor = SystemMonitor::starting(debug_mode || settings.debug, env::args().any(|arg| arg == "--safe-mode"));
+    monitor.require_token = true;
+    crash::install(&monitor.config.crash_reports, &monitor.db_path);
+    monitor.init_database()?;
+    monitor.report_capabilities();
+    let monitor = Arc::new(monitor);
+    tokio::spawn(clear_crash_marker_when_stable(monitor.clone()));
+
+    logs::info!("sysmonitor server listening on {} (database {})", settings.bind, monitor.db_path);
+    tokio::select! {
+        _ = web::start_web_server(monitor.clone(), settings.bind) => {}
+        _ = periodically(DEVICE_CHECK, "checking devices", monitor.clone(), SystemMonitor::check_devices) => {}
+        _ = periodically(PAUSE_CHECK, "resuming tracking", monitor.clone(), SystemMonitor::resume_expired_pause) => {}
+        _ = periodically(DIGEST_CHECK, "sending the goal digest", monitor.clone(), SystemMonitor::send_goal_digest) => {}
+        _ = periodically(DAILY_NOTE_CHECK, "writing the daily note", monitor.clone(), SystemMonitor::write_daily_note) => {}
+        _ = periodically(NOTION_SYNC, "syncing to Notion", monitor.clone(), SystemMonitor::sync_notion) => {}
+        _ = periodically(INFLUX_CHECK, "pushing to InfluxDB", monitor.clone(), SystemMonitor::push_influx) => {}
+        _ = periodically(TELEMETRY_EXPORT, "exporting telemetry", monitor.clone(), SystemMonitor::export_telemetry) => {}
+        _ = periodically(
+            CATEGORY_REFRESH,
+            "refreshing category assignments",
+            monitor.clone(),
+            SystemMonitor::refresh_categories,
+        ) => {}
+        _ = periodically(ROLLUP_REFRESH, "rolling up days", monitor.clone(), SystemMonitor::refresh_rollups) => {}
+        _ = periodically(GRAFANA_REFRESH, "refreshing the Grafana tables", monitor.clone(), SystemMonitor::refresh_grafana) => {}
+        _ = periodically(MAINTENANCE_CHECK, "running housekeeping", monitor.clone(), SystemMonitor::run_due_maintenance) => {}
+        _ = periodically(MEMORY_CHECK, "checking memory use", monitor.clone(), SystemMonitor::check_memory) => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    safe_mode::clear(&safe_mode::marker_path(&monitor.db_path));
+    Ok(())
+}
+
+// Runs `task` every `period`, the first time right away, on a blocking thread
+// so syncs waiting on the network don't hold up a runtime worker
+async fn periodically<T: Send + 'static, E: std::fmt::Display + Send + 'static>(
+    period: DurationSecs,
+    what: &str,
+    monitor: Arc<SystemMonitor>,
+    task: fn(&SystemMonitor) -> Result<T, E>,
+) {
+    let mut interval = tokio::time::interval(period.to_std());
+    loop {
+        interval.tick().await;
+        let monitor = monitor.clone();
+        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || task(&monitor)).await {
+            logs::error!("Error {}: {}", what, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(vars: &[(&str, &str)]) -> Result<ServerSettings, String> {
+        ServerSettings::from_env(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn defaults_listen_on_all_interfaces() {
+        let defaults = settings(&[]).unwrap();
+        assert_eq!(defaults.bind, SocketAddr::from(([0, 0, 0, 0], 3030)));
+        assert!(!defaults.debug);
+    }
+
+    #[test]
+    fn environment_overrides_are_validated() {
+        let custom = settings(&[(BIND_ENV, "127.0.0.1:8080"), (DEBUG_ENV, "1")]).unwrap();
+        assert_eq!((custom.bind.port(), custom.debug), (8080, true));
+        assert!(settings(&[(BIND_ENV, "localhost")]).is_err());
+    }
+
+    #[test]
+    fn systemd_unit_is_hardened_and_uses_the_data_dir() {
+        let args: Vec<String> = ["--user", "collector"].iter().map(|arg| arg.to_string()).collect();
+        let options = UnitOptions::from_args(&args, UnitFormat::Systemd, "/usr/local/bin/sysmonitor".to_string());
+        let unit = systemd_unit(&options);
+        assert!(unit.contains("ExecStart=/usr/local/bin/sysmonitor server\n"));
+        assert!(unit.contains("User=collector\n"));
+        assert!(unit.contains("StateDirectory=sysmonitor\n"));
+        assert!(unit.contains("Environment=SYSMONITOR_DB=/var/lib/sysmonitor/usage.db\n"));
+        assert!(unit.contains("ProtectSystem=strict\n") && unit.contains("Restart=on-failure\n"));
+    }
+
+    #[test]
+    fn nssm_script_sets_environment_and_restart() {
+        let options = UnitOptions::from_args(&[], UnitFormat::Nssm, "C:\\sysmonitor\\sysmonitor.exe".to_string());
+        let script = nssm_script(&options);
+        assert!(script.contains("nssm install sysmonitor \"C:\\sysmonitor\\sysmonitor.exe\" server"));
+        assert!(script.contains("SYSMONITOR_DB=C:\\ProgramData\\sysmonitor\\usage.db"));
+        assert!(script.contains("AppExit Default Restart"));
+    }
+}
@@ -0,0 +1,127 @@
+// Focus sessions reconstructed from `usage_logs`.
+//
+// Every flush writes the running duration of the active session, so one
+// session is stored as several rows sharing the same start
+// (`timestamp - duration`); the longest of them is the session.
+//
+// `load_sessions` picks sessions by where they start. Features that ask what
+// happened within a window (buckets, days, meetings, budgets) want every
+// session that reaches into it instead, however early it started:
+// `overlapping` returns those whole and `clipped` only their part within the
+// window.
+
+use rusqlite::{params, Connection, Result as SqlResult};
+
+use crate::units::{DurationSecs, Timestamp};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub identifier: String,
+    pub app_name: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+impl Session {
+    pub fn duration(&self) -> DurationSecs {
+        self.end.since(self.start)
+    }
+
+    /// Length of the session within `[from, until)`.
+    pub fn overlap(&self, from: Timestamp, until: Timestamp) -> DurationSecs {
+        self.end.min(until).since(self.start.max(from))
+    }
+
+    /// The part of the session within `[from, until)`, None when it has none.
+    pub fn clip(&self, from: Timestamp, until: Timestamp) -> Option<Session> {
+        (self.overlap(from, until) > DurationSecs::ZERO).then(|| Session { start: self.start.max(from), end: self.end.min(until), ..self.clone() })
+    }
+}
+
+fn session(row: &rusqlite::Row) -> SqlResult<Session> {
+    let start: Timestamp = row.get(2)?;
+    Ok(Session { identifier: row.get(0)?, app_name: row.get(1)?, start, end: start + row.get::<_, DurationSecs>(3)? })
+}
+
+/// Sessions starting in `[since, until)`, ordered by start.
+pub fn load_sessions(conn: &Connection, since: Timestamp, until: Timestamp) -> SqlResult<Vec<Session>> {
+    let mut stmt = conn.prepare(
+        "SELECT identifier, app_name, timestamp - duration AS start, MAX(duration)
+         FROM usage_logs
+         WHERE timestamp - duration >= ?1 AND timestamp - duration < ?2
+         GROUP BY identifier, start
+         ORDER BY start, identifier",
+    )?;
+    let sessions = stmt.query_map(params![since, until], session)?.collect();
+    sessions
+}
+
+/// Sessions with any part in `[from, until)`, whole, ordered by start.
+pub fn overlapping(conn: &Connection, from: Timestamp, until: Timestamp) -> SqlResult<Vec<Session>> {
+    // A session's longest row ends last, so it passes whenever any of its rows does
+    let mut stmt = conn.prepare(
+        "SELECT identifier, app_name, timestamp - duration AS start, MAX(duration)
+         FROM usage_logs
+         WHERE timestamp > ?1 AND timestamp - duration < ?2
+         GROUP BY identifier, start
+         ORDER BY start, identifier",
+    )?;
+    let sessions = stmt.query_map(params![from, until], session)?.collect();
+    sessions
+}
+
+/// The parts of sessions that fall in `[from, until)`, ordered by start.
+pub fn clipped(conn: &Connection, from: Timestamp, until: Timestamp) -> SqlResult<Vec<Session>> {
+    Ok(overlapping(conn, from, until)?.iter().filter_map(|session| session.clip(from, until)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn db(rows: &[(&str, u64, u64)]) -> Connection {
+        let conn = fixtures::empty().unwrap();
+        for (app, start, duration) in rows {
+            conn.execute("INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES (?1, ?1, '', ?2, ?3)", params![app, start + duration, duration])
+                .unwrap();
+        }
+        conn
+    }
+
+    fn spans(sessions: &[Session]) -> Vec<(&str, u64, u64)> {
+        sessions.iter().map(|session| (session.app_name.as_str(), session.start.as_secs(), session.end.as_secs())).collect()
+    }
+
+    fn at(secs: u64) -> Timestamp {
+        Timestamp::from_secs(secs)
+    }
+
+    #[test]
+    fn sessions_reaching_into_a_window_are_found_and_clipped() {
+        let conn = db(&[
+            ("before.exe", 0, 100),    // Ends where the window starts
+            ("early.exe", 50, 100),    // Started long before, flushed twice
+            ("early.exe", 50, 200),
+            ("inside.exe", 300, 50),
+            ("around.exe", 20, 1000),  // Covers the whole window
+            ("late.exe", 450, 100),
+            ("after.exe", 500, 10),    // Starts where the window ends
+        ]);
+        assert_eq!(
+            spans(&overlapping(&conn, at(100), at(500)).unwrap()),
+            vec![("around.exe", 20, 1020), ("early.exe", 50, 250), ("inside.exe", 300, 350), ("late.exe", 450, 550)]
+        );
+        assert_eq!(
+            spans(&clipped(&conn, at(100), at(500)).unwrap()),
+            vec![("around.exe", 100, 500), ("early.exe", 100, 250), ("inside.exe", 300, 350), ("late.exe", 450, 500)]
+        );
+        // load_sessions only sees what starts in the window
+        assert_eq!(load_sessions(&conn, at(100), at(500)).unwrap().len(), 2);
+
+        let session = Session { identifier: "x".into(), app_name: "x".into(), start: at(10), end: at(20) };
+        let overlaps = [session.overlap(at(0), at(15)), session.overlap(at(20), at(30)), session.overlap(at(0), at(100))];
+        assert_eq!(overlaps.map(DurationSecs::as_secs), [5, 0, 10]);
+        assert_eq!(session.clip(at(20), at(30)), None);
+    }
+}
@@ -0,0 +1,241 @@
+// Signatures on stored sessions, so later edits to the database show.
+//
+// With `signing.sessions` on, each usage_logs row gets an HMAC of its columns
+// and id under the device key as it is written: by tracker flushes, by ingest
+// and sync (`overlap::insert_session`), and again when retention redacts it.
+// The key comes from `signing.key_file` or `SYSMONITOR_SIGNING_KEY`. `sysmonitor
+// verify` recomputes every signature; a row edited since, or an unsigned row
+// among signed ones, is reported. Rows deleted outright leave no trace.
+
+use std::sync::OnceLock;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::reports;
+use crate::signing::SigningKey;
+
+const LISTED: usize = 20; // Tampered rows printed by `verify`
+
+static KEY: OnceLock<SigningKey> = OnceLock::new();
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SigningConfig {
+    pub sessions: bool,
+    pub key_file: Option<String>, // Hex key; `SYSMONITOR_SIGNING_KEY` when unset
+}
+
+impl SigningConfig {
+    pub fn key(&self) -> Result<SigningKey, String> {
+        match &self.key_file {
+            Some(path) => SigningKey::parse(&std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?),
+            None => SigningKey::from_env(),
+        }
+    }
+}
+
+/// Signs every row written from now on with `key`.
+pub fn install(key: SigningKey) {
+    let _ = KEY.set(key);
+}
+
+#[derive(Debug, Serialize)]
+struct Signed<'a> {
+    id: i64,
+    identifier: &'a str,
+    app_name: &'a str,
+    window_title: &'a str,
+    url: Option<&'a str>,
+    timestamp: i64,
+    duration: i64,
+    partial: bool,
+    source: Option<&'a str>,
+    clock_skew: Option<i64>,
+    redacted: u32,
+}
+
+struct Row {
+    identifier: String,
+    app_name: String,
+    window_title: String,
+    url: Option<String>,
+    timestamp: i64,
+    duration: i64,
+    partial: bool,
+    source: Option<String>,
+    clock_skew: Option<i64>,
+    redacted: u32,
+    signature: Option<String>,
+}
+
+const COLUMNS: &str = "identifier, app_name, window_title, url, timestamp, duration, partial, source, clock_skew, redacted, signature";
+
+fn from_row(row: &rusqlite::Row, offset: usize) -> SqlResult<Row> {
+    Ok(Row {
+        identifier: row.get(offset)?,
+        app_name: row.get(offset + 1)?,
+        window_title: row.get(offset + 2)?,
+        url: row.get(offset + 3)?,
+        timestamp: row.get(offset + 4)?,
+        duration: row.get(offset + 5)?,
+        partial: row.get(offset + 6)?,
+        source: row.get(offset + 7)?,
+        clock_skew: row.get(offset + 8)?,
+        redacted: row.get(offset + 9)?,
+        signature: row.get(offset + 10)?,
+    })
+}
+
+// What is signed: the row's columns in a fixed order
+fn message(id: i64, row: &Row) -> Vec<u8> {
+    let signed = Signed {
+        id,
+        identifier: &row.identifier,
+        app_name: &row.app_name,
+        window_title: &row.window_title,
+        url: row.url.as_deref(),
+        timestamp: row.timestamp,
+        duration: row.duration,
+        partial: row.partial,
+        source: row.source.as_deref(),
+        clock_skew: row.clock_skew,
+        redacted: row.redacted,
+    };
+    serde_json::to_vec(&signed).unwrap_or_default()
+}
+
+fn sign_with(conn: &Connection, id: i64, key: &SigningKey) -> SqlResult<()> {
+    let sql = format!("SELECT {} FROM usage_logs WHERE id = ?1", COLUMNS);
+    if let Some(row) = conn.query_row(&sql, params![id], |row| from_row(row, 0)).optional()? {
+        conn.execute("UPDATE usage_logs SET signature = ?1 WHERE id = ?2", params![key.sign(&message(id, &row)), id])?;
+    }
+    Ok(())
+}
+
+/// Signs row `id` as now stored; nothing unless signing is on.
+pub fn sign_row(conn: &Connection, id: i64) -> SqlResult<()> {
+    match KEY.get() {
+        Some(key) => sign_with(conn, id, key),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Tampered {
+    pub id: i64,
+    pub start: i64,
+    pub app_name: String,
+    pub window_title: String,
+    pub signed: bool, // False for an unsigned row newer than the first signed one
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub signed: usize, // Rows whose signature still matches
+    pub unsigned: usize, // Stored before signing was turned on
+    pub tampered: Vec<Tampered>,
+}
+
+/// Checks the rows of sessions starting in `[since, until)`.
+pub fn verify(conn: &Connection, key: &SigningKey, since: i64, until: i64) -> SqlResult<Report> {
+    let first_signed: Option<i64> = conn.query_row("SELECT MIN(id) FROM usage_logs WHERE signature IS NOT NULL", [], |row| row.get(0))?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, {} FROM usage_logs WHERE timestamp - duration >= ?1 AND timestamp - duration < ?2 ORDER BY id",
+        COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![since, until], |row| Ok((row.get::<_, i64>(0)?, from_row(row, 1)?)))?;
+    let mut report = Report::default();
+    for row in rows {
+        let (id, row) = row?;
+        let valid = match &row.signature {
+            Some(signature) => key.verify(&message(id, &row), signature),
+            None if first_signed.is_some_and(|first| id > first) => false,
+            None => {
+                report.unsigned += 1;
+                continue;
+            }
+        };
+        if valid {
+            report.signed += 1;
+        } else {
+            report.tampered.push(Tampered {
+                id,
+                start: row.timestamp - row.duration,
+                app_name: row.app_name,
+                window_title: row.window_title,
+                signed: row.signature.is_some(),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// `sysmonitor verify [--from YYYY-MM-DD] [--to YYYY-MM-DD]`.
+pub fn run_command(conn: &Connection, config: &SigningConfig, args: &[String]) -> Result<(), String> {
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+            .map(String::as_str)
+    };
+    let date = |name: &str| {
+        option(name)
+            .map(|value| NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("{} must be YYYY-MM-DD, got '{}'", name, value)))
+            .transpose()
+    };
+    let since = date("--from")?.map_or(i64::MIN, |day| reports::day_bounds(day).0);
+    let until = date("--to")?.map_or(i64::MAX, |day| reports::day_bounds(day).1);
+    let report = verify(conn, &config.key()?, since, until).map_err(|e| e.to_string())?;
+
+    println!("{} sessions intact, {} unsigned from before signing was turned on", report.signed, report.unsigned);
+    if report.tampered.is_empty() {
+        return Ok(());
+    }
+    for row in report.tampered.iter().take(LISTED) {
+        let when = chrono::DateTime::from_timestamp(row.start, 0).map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string());
+        let what = if row.signed { "changed since it was signed" } else { "unsigned among signed rows" };
+        println!("  #{} {} {} \"{}\": {}", row.id, when.unwrap_or_default(), row.app_name, row.window_title, what);
+    }
+    if report.tampered.len() > LISTED {
+        println!("  ... and {} more", report.tampered.len() - LISTED);
+    }
+    Err(format!("{} sessions do not match their signatures", report.tampered.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn edited_and_slipped_in_rows_are_reported() {
+        let conn = fixtures::empty().unwrap();
+        let key = SigningKey::generate();
+        let insert = |title: &str, sign: bool| {
+            conn.execute(
+                "INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('code.exe', 'code.exe', ?1, 1000, 600)",
+                params![title],
+            )
+            .unwrap();
+            if sign {
+                sign_with(&conn, conn.last_insert_rowid(), &key).unwrap();
+            }
+        };
+        insert("Before signing", false);
+        insert("Client A", true);
+        insert("Client B", true);
+        insert("Slipped in", false);
+
+        let report = verify(&conn, &key, 0, 2000).unwrap();
+        assert_eq!((report.signed, report.unsigned), (2, 1));
+        assert_eq!(report.tampered.iter().map(|row| (row.id, row.signed)).collect::<Vec<_>>(), vec![(4, false)]);
+
+        // Stretching a session is caught, as is checking with another key
+        conn.execute("UPDATE usage_logs SET duration = 900, timestamp = 1300 WHERE id = 2", []).unwrap();
+        let report = verify(&conn, &key, 0, 2000).unwrap();
+        assert_eq!(report.tampered.iter().map(|row| row.id).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(verify(&conn, &SigningKey::generate(), 0, 2000).unwrap().signed, 0);
+    }
+}
@@ -0,0 +1,96 @@
+// HMAC-SHA256 (RFC 2104, from the `hmac` crate) under a key kept only by the
+// user.
+//
+// Anyone holding `SYSMONITOR_SIGNING_KEY` (made by `sysmonitor export keygen`)
+// can check that signed data has not been changed since; share it with whoever
+// should be able to verify, e.g. an arbitrator.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+pub const KEY_ENV: &str = "SYSMONITOR_SIGNING_KEY";
+const KEY_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone)]
+pub struct SigningKey([u8; KEY_LEN]);
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; KEY_LEN];
+        getrandom::getrandom(&mut key).expect("operating system random source unavailable");
+        Self(key)
+    }
+
+    pub fn parse(hex: &str) -> Result<Self, String> {
+        let bytes = from_hex(hex.trim()).filter(|bytes| bytes.len() == KEY_LEN);
+        let bytes = bytes.ok_or_else(|| format!("the signing key must be {} hex characters", KEY_LEN * 2))?;
+        let mut key = [0u8; KEY_LEN];
+        key.copy_from_slice(&bytes);
+        Ok(Self(key))
+    }
+
+    /// The key from `SYSMONITOR_SIGNING_KEY`.
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&std::env::var(KEY_ENV).map_err(|_| format!("{} is not set; see `export keygen`", KEY_ENV))?)
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+
+    /// Names the key without giving it away, so a signature shows which one made it.
+    pub fn id(&self) -> String {
+        to_hex(&Sha256::digest(self.0)[..4])
+    }
+
+    fn mac(&self, message: &[u8]) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.0).expect("HMAC takes keys of any length").chain_update(message)
+    }
+
+    pub fn sign(&self, message: &[u8]) -> String {
+        to_hex(&self.mac(message).finalize().into_bytes())
+    }
+
+    /// Compared in constant time, so the time taken says nothing about where they differ.
+    pub fn verify(&self, message: &[u8], signature: &str) -> bool {
+        from_hex(signature.trim()).is_some_and(|signature| self.mac(message).verify_slice(&signature).is_ok())
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_only_for_their_key_and_message() {
+        // The MAC itself against RFC 4231 test case 2
+        let mac = HmacSha256::new_from_slice(b"Jefe").unwrap().chain_update(b"what do ya want for nothing?");
+        assert_eq!(to_hex(&mac.finalize().into_bytes()), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+
+        let key = SigningKey::generate();
+        let signature = key.sign(b"hours");
+        assert!(key.verify(b"hours", &signature));
+        assert!(key.verify(b"hours", &format!(" {}\n", signature)));
+        assert!(!key.verify(b"hours!", &signature));
+        assert!(!key.verify(b"hours", &signature[..62]));
+        assert!(!key.verify(b"hours", "not hex"));
+        assert!(!SigningKey::generate().verify(b"hours", &signature));
+    }
+}
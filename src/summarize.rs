@@ -0,0 +1,233 @@
+// Stable contexts for apps whose window titles churn.
+//
+// Chat apps put unread counts, typing markers and the like in their titles,
+// so "general" in Slack becomes a new activity every time a message arrives
+// anywhere. A summarizer maps such a title to the context it names, e.g.
+// "#general in Acme", before the title is keyed and stored. Slack, Discord,
+// Teams, Telegram and WhatsApp have built-in summarizers; other apps get one
+// from a `title_summaries` rule: a regex on the title and a template over its
+// capture groups. A rule takes precedence over the built-in summarizer of its
+// app, and a title nothing recognizes is kept as it is.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummaryConfig {
+    pub builtin: bool, // The summarizers for popular chat apps
+    pub rules: Vec<SummaryRule>,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self { builtin: true, rules: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummaryRule {
+    pub app: String, // App or exe name, case-insensitive
+    pub pattern: String,
+    pub template: String, // `$1` or `$name` for capture groups
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Builtin {
+    Slack,
+    Discord,
+    Teams,
+    Counter, // Apps whose title is their name and an unread count
+}
+
+/// The compiled summarizers.
+#[derive(Debug)]
+pub struct Summarizers {
+    builtin: bool,
+    rules: Vec<(String, Regex, String)>,
+}
+
+impl Summarizers {
+    /// Compiles `config`, leaving out rules whose pattern doesn't compile.
+    pub fn from_config(config: &SummaryConfig) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let rules = config
+            .rules
+            .iter()
+            .filter_map(|rule| match Regex::new(&rule.pattern) {
+                Ok(pattern) => Some((rule.app.to_lowercase(), pattern, rule.template.clone())),
+                Err(e) => {
+                    errors.push(format!("title summary for '{}': {}", rule.app, e));
+                    None
+                }
+            })
+            .collect();
+        (Self { builtin: config.builtin, rules }, errors)
+    }
+
+    /// The context `title` names, for the first of `names` (the app, then its
+    /// exe) that has a summarizer; None to keep the title.
+    pub fn summarize(&self, names: &[&str], title: &str) -> Option<String> {
+        for name in names {
+            let lower = name.to_lowercase();
+            let rules: Vec<_> = self.rules.iter().filter(|(app, _, _)| *app == lower).collect();
+            if !rules.is_empty() {
+                return rules.iter().find_map(|(_, pattern, template)| {
+                    let captures = pattern.captures(title)?;
+                    let mut summary = String::new();
+                    captures.expand(template, &mut summary);
+                    Some(summary.trim().to_string()).filter(|summary| !summary.is_empty())
+                });
+            }
+            if let Some(builtin) = self.builtin.then(|| builtin_for(&lower)).flatten() {
+                return summarize_builtin(builtin, title);
+            }
+        }
+        None
+    }
+}
+
+fn builtin_for(app: &str) -> Option<Builtin> {
+    match app.trim_end_matches(".exe") {
+        "slack" => Some(Builtin::Slack),
+        "discord" | "discordptb" | "discordcanary" => Some(Builtin::Discord),
+        "teams" | "ms-teams" | "microsoft teams" => Some(Builtin::Teams),
+        "telegram" | "whatsapp" | "signal" => Some(Builtin::Counter),
+        _ => None,
+    }
+}
+
+// "(3) ", "* ", "• " and the like in front of a title
+fn strip_unread(title: &str) -> &str {
+    let mut rest = title.trim();
+    loop {
+        let stripped = rest
+            .strip_prefix(['*', '!', '•'])
+            .or_else(|| {
+                let inner = rest.strip_prefix('(')?;
+                let (count, after) = inner.split_once(')')?;
+                (!count.is_empty() && count.chars().all(|c| c.is_ascii_digit() || c == '+')).then_some(after)
+            })
+            .map(str::trim_start);
+        match stripped {
+            Some(stripped) => rest = stripped,
+            None => return rest,
+        }
+    }
+}
+
+fn summarize_builtin(builtin: Builtin, title: &str) -> Option<String> {
+    let title = strip_unread(title);
+    match builtin {
+        Builtin::Slack => slack(title),
+        Builtin::Discord => discord(title),
+        Builtin::Teams => {
+            let rest = title.strip_suffix("| Microsoft Teams").or_else(|| title.strip_suffix("- Microsoft Teams"))?.trim();
+            let parts: Vec<&str> = rest.split(" | ").map(str::trim).filter(|part| !part.is_empty()).collect();
+            (!parts.is_empty()).then(|| parts.join(" | "))
+        }
+        Builtin::Counter => {
+            // "Telegram (15)" and "WhatsApp" alike become the app's own name
+            let name = title.split(['(', '-', '–', '|']).next()?.trim();
+            (!name.is_empty()).then(|| name.to_string())
+        }
+    }
+}
+
+// "general (Channel) - Acme - 3 new items - Slack", the older
+// "Slack | general | Acme" and "Jane Doe (DM) - Acme - Slack"
+fn slack(title: &str) -> Option<String> {
+    let parts: Vec<&str> = if let Some(rest) = title.strip_prefix("Slack |") {
+        rest.split(" | ").map(str::trim).collect()
+    } else {
+        let rest = title.strip_suffix("- Slack")?.trim();
+        rest.split(" - ").map(str::trim).filter(|part| !is_new_items(part)).collect()
+    };
+    let (conversation, workspace) = match parts.as_slice() {
+        [conversation, workspace, ..] => (*conversation, Some(*workspace)),
+        [conversation] => (*conversation, None),
+        [] => return None,
+    };
+    let context = if let Some(name) = conversation.strip_suffix("(DM)").or_else(|| conversation.strip_suffix("(Direct Message)")) {
+        format!("@{}", name.trim())
+    } else {
+        let name = conversation.strip_suffix("(Channel)").or_else(|| conversation.strip_suffix("(Private Channel)")).unwrap_or(conversation).trim();
+        if name.starts_with('#') || name.contains(' ') {
+            name.to_string()
+        } else {
+            format!("#{}", name)
+        }
+    };
+    Some(match workspace.filter(|workspace| !workspace.is_empty()) {
+        Some(workspace) => format!("{} in {}", context, workspace),
+        None => context,
+    })
+}
+
+fn is_new_items(part: &str) -> bool {
+    let mut words = part.split_whitespace();
+    words.next().is_some_and(|count| count.chars().all(|c| c.is_ascii_digit() || c == '+')) && words.next() == Some("new")
+}
+
+// "#general | Acme Server - Discord", "Discord | #general | Acme Server" and
+// "@jane - Discord"
+fn discord(title: &str) -> Option<String> {
+    let rest = title.strip_suffix("- Discord").or_else(|| title.strip_prefix("Discord |"))?.trim();
+    let parts: Vec<&str> = rest.split(" | ").map(str::trim).filter(|part| !part.is_empty()).collect();
+    match parts.as_slice() {
+        [channel, server, ..] => Some(format!("{} in {}", channel, server)),
+        [only] => Some(only.to_string()),
+        [] => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin() -> Summarizers {
+        Summarizers::from_config(&SummaryConfig::default()).0
+    }
+
+    #[test]
+    fn chat_titles_lose_their_counters() {
+        let summarizers = builtin();
+        let slack = |title: &str| summarizers.summarize(&["slack.exe"], title);
+        assert_eq!(slack("general (Channel) - Acme - Slack").as_deref(), Some("#general in Acme"));
+        assert_eq!(slack("* general (Channel) - Acme - 3 new items - Slack").as_deref(), Some("#general in Acme"));
+        assert_eq!(slack("Slack | random | Acme").as_deref(), Some("#random in Acme"));
+        assert_eq!(slack("Jane Doe (DM) - Acme - Slack").as_deref(), Some("@Jane Doe in Acme"));
+        assert_eq!(slack("Preferences"), None);
+
+        let discord = |title: &str| summarizers.summarize(&["Discord.exe"], title);
+        assert_eq!(discord("(12) #general | Rust Server - Discord").as_deref(), Some("#general in Rust Server"));
+        assert_eq!(discord("• Discord | #help | Rust Server").as_deref(), Some("#help in Rust Server"));
+        assert_eq!(discord("@jane - Discord").as_deref(), Some("@jane"));
+
+        assert_eq!(summarizers.summarize(&["ms-teams.exe"], "(2) Chat | Jane Doe | Microsoft Teams").as_deref(), Some("Chat | Jane Doe"));
+        assert_eq!(summarizers.summarize(&["Telegram.exe"], "Telegram (15)").as_deref(), Some("Telegram"));
+        assert_eq!(summarizers.summarize(&["code.exe"], "(1) main.rs - Visual Studio Code"), None);
+    }
+
+    #[test]
+    fn rules_override_the_builtin_summarizer() {
+        let config = SummaryConfig {
+            builtin: true,
+            rules: vec![
+                SummaryRule {
+                    app: "Mattermost".into(),
+                    pattern: r"^(?:\(\d+\) )?(?P<channel>.+?) - (?P<team>.+?) Mattermost$".into(),
+                    template: "~$channel in $team".into(),
+                },
+                SummaryRule { app: "slack.exe".into(), pattern: r"^(\w+) \| ".into(), template: "$1".into() },
+                SummaryRule { app: "broken".into(), pattern: "(".into(), template: String::new() },
+            ],
+        };
+        let (summarizers, errors) = Summarizers::from_config(&config);
+        assert_eq!(errors.len(), 1);
+        // The app name is tried before the exe
+        assert_eq!(summarizers.summarize(&["Mattermost", "electron.exe"], "(4) Town Square - Acme Mattermost").as_deref(), Some("~Town Square in Acme"));
+        assert_eq!(summarizers.summarize(&["slack.exe"], "design | Acme").as_deref(), Some("design"));
+        assert_eq!(summarizers.summarize(&["slack.exe"], "general (Channel) - Acme - Slack"), None);
+    }
+}
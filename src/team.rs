@@ -0,0 +1,295 @@
+// Aggregation-only team mode.
+//
+// With `team.aggregate_only`, ingested batches never reach `usage_logs`: each
+// event is categorized on arrival and only its seconds per user, hour and
+// category are kept, so app names, titles and URLs are never stored. The user
+// is the uploading token's name. Encrypted vault blobs are refused, since
+// their contents could not be checked.
+//
+// `summarize` turns the totals into team-level figures for admins. It only
+// answers for at least `min_group_size` users, so no figure describes one
+// person.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Caller;
+use crate::categories::{CategoryConfig, Productivity, UNCATEGORIZED};
+use crate::ingest::{self, BatchResult, IngestBatch, IngestConfig, ItemResult};
+use crate::units::Timestamp;
+
+pub const DEFAULT_DAYS: u64 = 30;
+const HOUR_SECS: u64 = 3600;
+const DAY_SECS: u64 = 86400;
+
+// Hours in meetings per user and day, by upper bound
+const MEETING_LOAD_BUCKETS: [(&str, u64); 4] =
+    [("under 1h", HOUR_SECS), ("1-2h", 2 * HOUR_SECS), ("2-4h", 4 * HOUR_SECS), ("4h or more", u64::MAX)];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TeamConfig {
+    pub aggregate_only: bool,
+    pub min_group_size: usize, // Fewer users than this and summaries are withheld
+    pub meeting_categories: Vec<String>,
+}
+
+impl Default for TeamConfig {
+    fn default() -> Self {
+        Self { aggregate_only: false, min_group_size: 3, meeting_categories: vec!["Meetings".to_string()] }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TeamQuery {
+    pub days: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateRow {
+    pub user: String,
+    pub hour: u64,
+    pub category: String,
+    pub seconds: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LoadBucket {
+    pub hours: &'static str,
+    pub user_days: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryShare {
+    pub category: String,
+    pub percent: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TeamSummary {
+    pub from: u64,
+    pub to: u64,
+    pub users: usize,
+    pub user_days: usize, // Days on which a user reported anything
+    pub avg_tracked_secs: u64, // Per user-day
+    pub avg_focus_secs: u64, // Per user-day, in productive categories
+    pub meeting_load: Vec<LoadBucket>,
+    pub category_mix: Vec<CategoryShare>, // Largest first
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS team_aggregates (
+            user TEXT NOT NULL,
+            hour INTEGER NOT NULL,
+            category TEXT NOT NULL,
+            seconds INTEGER NOT NULL,
+            PRIMARY KEY (user, hour, category)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Whose aggregates a request adds to.
+pub fn user_of(caller: &Caller) -> String {
+    match caller {
+        Caller::Token(token) => token.name.clone(),
+        Caller::Local => "local".to_string(),
+    }
+}
+
+/// `[start, start + duration)` cut at hour boundaries, as (hour start, seconds).
+pub fn hourly_buckets(start: u64, duration: u64) -> Vec<(u64, u64)> {
+    let end = start + duration;
+    let mut buckets = Vec::new();
+    let mut hour = start - start % HOUR_SECS;
+    while hour < end {
+        let seconds = end.min(hour + HOUR_SECS) - start.max(hour);
+        buckets.push((hour, seconds));
+        hour += HOUR_SECS;
+    }
+    buckets
+}
+
+fn add(conn: &Connection, user: &str, category: &str, start: u64, duration: u64) -> SqlResult<()> {
+    for (hour, seconds) in hourly_buckets(start, duration) {
+        conn.execute(
+            "INSERT INTO team_aggregates (user, hour, category, seconds) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(user, hour, category) DO UPDATE SET seconds = seconds + excluded.seconds",
+            params![user, hour, category, seconds],
+        )?;
+    }
+    Ok(())
+}
+
+/// Like `ingest::ingest_batch`, but keeping only hourly category totals.
+pub fn ingest_batch(
+    conn: &mut Connection,
+    batch: &IngestBatch,
+    config: &IngestConfig,
+    categories: &CategoryConfig,
+    user: &str,
+    now: Timestamp,
+) -> SqlResult<BatchResult> {
+    let skew = ingest::flagged_skew(batch.sent_at, now, &config.clock_skew);
+    let tx = conn.transaction()?;
+    let mut items = Vec::with_capacity(batch.events.len());
+    for (index, value) in batch.events.iter().enumerate() {
+        let mut item = ItemResult::pending(index);
+        if let Some(valid) = ingest::prepare_event(&mut item, value, skew, now) {
+            let category = categories.categorize(&valid.key).unwrap_or(UNCATEGORIZED);
+            add(&tx, user, category, valid.start.as_secs(), valid.duration.as_secs())?;
+            item.accepted = true;
+            item.stored_secs = valid.duration;
+        }
+        items.push(item);
+    }
+    tx.commit()?;
+    Ok(BatchResult::new(items, skew))
+}
+
+pub fn load(conn: &Connection, from: u64, to: u64) -> SqlResult<Vec<AggregateRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT user, hour, category, seconds FROM team_aggregates WHERE hour >= ?1 AND hour < ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![from, to], |row| {
+            Ok(AggregateRow {
+                user: row.get(0)?,
+                hour: row.get::<_, i64>(1)? as u64,
+                category: row.get(2)?,
+                seconds: row.get::<_, i64>(3)? as u64,
+            })
+        })?
+        .collect();
+    rows
+}
+
+/// Team-level figures for `[from, to)`, or `None` when too few users reported.
+pub fn summarize(
+    rows: &[AggregateRow],
+    config: &TeamConfig,
+    categories: &CategoryConfig,
+    from: u64,
+    to: u64,
+) -> Option<TeamSummary> {
+    let users: BTreeSet<&str> = rows.iter().map(|row| row.user.as_str()).collect();
+    if users.is_empty() || users.len() < config.min_group_size {
+        return None;
+    }
+
+    // (tracked, focus, meetings) per user and UTC day
+    let mut days: BTreeMap<(&str, u64), (u64, u64, u64)> = BTreeMap::new();
+    let mut by_category: BTreeMap<&str, u64> = BTreeMap::new();
+    for row in rows {
+        let day = days.entry((row.user.as_str(), row.hour / DAY_SECS)).or_default();
+        day.0 += row.seconds;
+        if categories.productivity.get(&row.category) == Some(&Productivity::Productive) {
+            day.1 += row.seconds;
+        }
+        if config.meeting_categories.contains(&row.category) {
+            day.2 += row.seconds;
+        }
+        *by_category.entry(row.category.as_str()).or_default() += row.seconds;
+    }
+
+    let user_days = days.len();
+    let average = |total: u64| total / user_days as u64;
+    let mut meeting_load: Vec<LoadBucket> =
+        MEETING_LOAD_BUCKETS.iter().map(|(hours, _)| LoadBucket { hours, user_days: 0 }).collect();
+    for (_, _, meetings) in days.values() {
+        let bucket = MEETING_LOAD_BUCKETS.iter().position(|(_, below)| meetings < below).unwrap_or(0);
+        meeting_load[bucket].user_days += 1;
+    }
+    let total: u64 = by_category.values().sum();
+    let mut category_mix: Vec<CategoryShare> = by_category
+        .into_iter()
+        .map(|(category, seconds)| CategoryShare {
+            category: category.to_string(),
+            percent: (seconds as f64 * 1000.0 / total.max(1) as f64).round() / 10.0,
+        })
+        .collect();
+    category_mix.sort_by(|a, b| b.percent.total_cmp(&a.percent));
+
+    Some(TeamSummary {
+        from,
+        to,
+        users: users.len(),
+        user_days,
+        avg_tracked_secs: average(days.values().map(|day| day.0).sum()),
+        avg_focus_secs: average(days.values().map(|day| day.1).sum()),
+        meeting_load,
+        category_mix,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_cut_at_hour_boundaries() {
+        assert_eq!(hourly_buckets(3600 + 100, 200), vec![(3600, 200)]);
+        assert_eq!(hourly_buckets(3600 - 600, 4200), vec![(0, 600), (3600, 3600)]);
+        assert_eq!(hourly_buckets(7200, 3600), vec![(7200, 3600)]);
+    }
+
+    #[test]
+    fn only_category_totals_are_stored() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        let categories: CategoryConfig =
+            serde_json::from_value(serde_json::json!({ "rules": [{ "category": "Dev", "app": "code.exe" }] })).unwrap();
+        let now = 1_800_000_000;
+        let hour = now - now % HOUR_SECS - HOUR_SECS;
+        let batch: IngestBatch = serde_json::from_value(serde_json::json!({
+            "events": [
+                { "app_name": "code.exe", "window_title": "secret.rs", "start": hour, "duration": 600 },
+                { "app_name": "code.exe", "window_title": "other.rs", "start": hour + 600, "duration": 300 },
+                { "app_name": "game.exe", "start": hour, "duration": 60 },
+                { "app_name": "", "start": hour, "duration": 60 }
+            ]
+        }))
+        .unwrap();
+        let result = ingest_batch(&mut conn, &batch, &IngestConfig::default(), &categories, "alice", Timestamp::from_secs(now)).unwrap();
+        assert_eq!((result.accepted, result.rejected), (3, 1));
+
+        let mut stmt = conn.prepare("SELECT user, category, seconds FROM team_aggregates ORDER BY category").unwrap();
+        let rows: Vec<(String, String, i64)> =
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            rows,
+            vec![("alice".into(), "Dev".into(), 900), ("alice".into(), UNCATEGORIZED.into(), 60)]
+        );
+    }
+
+    #[test]
+    fn summaries_need_a_large_enough_group() {
+        let row = |user: &str, hour: u64, category: &str, seconds: u64| AggregateRow {
+            user: user.to_string(),
+            hour,
+            category: category.to_string(),
+            seconds,
+        };
+        let categories: CategoryConfig =
+            serde_json::from_value(serde_json::json!({ "productivity": { "Dev": "productive" } })).unwrap();
+        let config = TeamConfig::default();
+        let mut rows = vec![
+            row("alice", 0, "Dev", 3600),
+            row("alice", 3600, "Meetings", 5400),
+            row("bob", 0, "Dev", 1800),
+        ];
+        assert!(summarize(&rows, &config, &categories, 0, DAY_SECS).is_none());
+
+        rows.push(row("carol", DAY_SECS, "Chat", 1800));
+        let summary = summarize(&rows, &config, &categories, 0, 2 * DAY_SECS).unwrap();
+        assert_eq!((summary.users, summary.user_days), (3, 3));
+        assert_eq!((summary.avg_tracked_secs, summary.avg_focus_secs), (4200, 1800));
+        let load: Vec<usize> = summary.meeting_load.iter().map(|bucket| bucket.user_days).collect();
+        assert_eq!(load, vec![2, 1, 0, 0]);
+        assert_eq!(summary.category_mix[0], CategoryShare { category: "Dev".into(), percent: 42.9 });
+    }
+}
@@ -0,0 +1,416 @@
+// Traces and metrics about sysmonitor itself, for OpenTelemetry collectors.
+//
+// Tracker polls, database writes and HTTP requests each record a span and feed
+// a duration histogram; the runtime counters go out as sums. Spans wait in a
+// bounded queue and everything is posted as OTLP/HTTP JSON to
+// `<endpoint>/v1/traces` and `/v1/metrics` every `TELEMETRY_EXPORT`.
+// HTTP spans join the caller's trace when it sends a `traceparent` header.
+// Only builds with the `otel` feature export; elsewhere, or without an
+// endpoint, recording does nothing.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::logs;
+use crate::metrics::MetricsSnapshot;
+
+const MAX_QUEUED_SPANS: usize = 2048; // Oldest are dropped while the collector is unreachable
+// Seconds; OpenTelemetry's advice for `http.server.request.duration`
+const BOUNDS: [f64; 14] = [0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 7.5, 10.0];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub endpoint: Option<String>, // OTLP/HTTP base, e.g. http://localhost:4318
+    pub service_name: String,
+    pub headers: BTreeMap<String, String>, // e.g. an API key the collector wants
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self { endpoint: None, service_name: "sysmonitor".to_string(), headers: BTreeMap::new() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpanKind {
+    Internal = 1,
+    Server = 2,
+}
+
+#[derive(Debug, Clone)]
+struct SpanRecord {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    name: String,
+    kind: SpanKind,
+    start_nanos: u64,
+    end_nanos: u64,
+    attributes: Vec<(&'static str, Value)>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    count: u64,
+    sum: f64,
+    buckets: [u64; BOUNDS.len() + 1],
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        self.count += 1;
+        self.sum += seconds;
+        self.buckets[BOUNDS.iter().position(|bound| seconds <= *bound).unwrap_or(BOUNDS.len())] += 1;
+    }
+}
+
+type HistogramKey = (String, Vec<(&'static str, String)>); // Metric name and its attributes
+
+/// A span underway, from `Telemetry::start`.
+pub struct Timer {
+    name: &'static str,
+    start_nanos: u64,
+    started: Instant,
+}
+
+#[derive(Default)]
+pub struct Telemetry {
+    config: Option<TelemetryConfig>, // Set when exporting
+    start_nanos: u64,
+    spans: Mutex<VecDeque<SpanRecord>>,
+    dropped_spans: AtomicU64,
+    histograms: Mutex<BTreeMap<HistogramKey, Histogram>>,
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+fn random_id<const N: usize>() -> [u8; N] {
+    let mut id = [0u8; N];
+    // An all-zero id is invalid, so a failed draw still yields a usable one
+    if getrandom::getrandom(&mut id).is_err() || id.iter().all(|byte| *byte == 0) {
+        id[..8.min(N)].copy_from_slice(&now_nanos().to_be_bytes()[..8.min(N)]);
+    }
+    id
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != N * 2 {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+    bytes.iter().any(|byte| *byte != 0).then_some(bytes)
+}
+
+/// Trace and parent span id of a W3C `traceparent` header.
+fn parse_traceparent(header: &str) -> Option<([u8; 16], [u8; 8])> {
+    let mut parts = header.trim().split('-');
+    let (version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+    if version == "ff" {
+        return None;
+    }
+    Some((parse_hex(trace_id)?, parse_hex(span_id)?))
+}
+
+fn attributes(pairs: &[(&'static str, Value)]) -> Vec<Value> {
+    pairs
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Number(number) if number.is_i64() || number.is_u64() => json!({ "intValue": number.to_string() }),
+                Value::Number(number) => json!({ "doubleValue": number }),
+                Value::Bool(flag) => json!({ "boolValue": flag }),
+                Value::String(text) => json!({ "stringValue": text }),
+                other => json!({ "stringValue": other.to_string() }),
+            };
+            json!({ "key": key, "value": value })
+        })
+        .collect()
+}
+
+impl Telemetry {
+    pub fn from_config(config: &TelemetryConfig) -> Self {
+        if config.endpoint.is_none() {
+            return Self::default();
+        }
+        if !cfg!(feature = "otel") {
+            logs::error!("Ignoring telemetry.endpoint: built without the `otel` feature");
+            return Self::default();
+        }
+        Self { config: Some(config.clone()), start_nanos: now_nanos(), ..Self::default() }
+    }
+
+    /// Whether an endpoint is configured to export to.
+    pub fn enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    pub fn start(&self, name: &'static str) -> Timer {
+        Timer { name, start_nanos: now_nanos(), started: Instant::now() }
+    }
+
+    /// Ends an internal span; `error` marks it failed.
+    pub fn finish(&self, timer: Timer, attributes: Vec<(&'static str, Value)>, error: Option<String>) {
+        if self.config.is_none() {
+            return;
+        }
+        let elapsed = timer.started.elapsed();
+        self.observe(format!("{}.duration", timer.name), Vec::new(), elapsed);
+        self.push(SpanRecord {
+            trace_id: random_id(),
+            span_id: random_id(),
+            parent_span_id: None,
+            name: timer.name.to_string(),
+            kind: SpanKind::Internal,
+            start_nanos: timer.start_nanos,
+            end_nanos: timer.start_nanos + elapsed.as_nanos() as u64,
+            attributes,
+            error,
+        });
+    }
+
+    /// A served request, e.g. from `warp::log::custom`.
+    pub fn record_http(&self, method: &str, path: &str, status: u16, elapsed: Duration, traceparent: Option<&str>) {
+        if self.config.is_none() {
+            return;
+        }
+        let metric_attributes = vec![("http.request.method", method.to_string()), ("http.response.status_code", status.to_string())];
+        self.observe("http.server.request.duration".to_string(), metric_attributes, elapsed);
+        let (trace_id, parent_span_id) = match traceparent.and_then(parse_traceparent) {
+            Some((trace_id, parent)) => (trace_id, Some(parent)),
+            None => (random_id(), None),
+        };
+        let end_nanos = now_nanos();
+        self.push(SpanRecord {
+            trace_id,
+            span_id: random_id(),
+            parent_span_id,
+            name: format!("{} {}", method, path),
+            kind: SpanKind::Server,
+            start_nanos: end_nanos.saturating_sub(elapsed.as_nanos() as u64),
+            end_nanos,
+            attributes: vec![
+                ("http.request.method", json!(method)),
+                ("url.path", json!(path)),
+                ("http.response.status_code", json!(status)),
+            ],
+            error: (status >= 500).then(|| format!("HTTP {}", status)),
+        });
+    }
+
+    fn observe(&self, name: String, attributes: Vec<(&'static str, String)>, elapsed: Duration) {
+        if self.config.is_some() {
+            self.histograms.lock().unwrap().entry((name, attributes)).or_default().observe(elapsed.as_secs_f64());
+        }
+    }
+
+    fn push(&self, span: SpanRecord) {
+        if self.config.is_none() {
+            return;
+        }
+        let mut spans = self.spans.lock().unwrap();
+        if spans.len() >= MAX_QUEUED_SPANS {
+            spans.pop_front();
+            self.dropped_spans.fetch_add(1, Ordering::Relaxed);
+        }
+        spans.push_back(span);
+    }
+
+    fn resource(&self) -> Value {
+        let service_name = self.config.as_ref().map_or("sysmonitor", |config| config.service_name.as_str());
+        json!({ "attributes": attributes(&[
+            ("service.name", json!(service_name)),
+            ("service.version", json!(env!("CARGO_PKG_VERSION"))),
+            ("host.name", json!(crate::vault::default_device())),
+        ]) })
+    }
+
+    fn scope() -> Value {
+        json!({ "name": "sysmonitor", "version": env!("CARGO_PKG_VERSION") })
+    }
+
+    /// OTLP JSON of `spans`, or None if there are none.
+    fn traces_body(&self, spans: &[SpanRecord]) -> Option<Value> {
+        if spans.is_empty() {
+            return None;
+        }
+        let spans: Vec<Value> = spans
+            .iter()
+            .map(|span| {
+                let mut value = json!({
+                    "traceId": hex(&span.trace_id),
+                    "spanId": hex(&span.span_id),
+                    "name": span.name,
+                    "kind": span.kind as u8,
+                    "startTimeUnixNano": span.start_nanos.to_string(),
+                    "endTimeUnixNano": span.end_nanos.to_string(),
+                    "attributes": attributes(&span.attributes),
+                    "status": match span.error {
+                        Some(ref message) => json!({ "code": 2, "message": message }),
+                        None => json!({ "code": 0 }),
+                    },
+                });
+                if let Some(parent) = span.parent_span_id {
+                    value["parentSpanId"] = json!(hex(&parent));
+                }
+                value
+            })
+            .collect();
+        Some(json!({ "resourceSpans": [{ "resource": self.resource(), "scopeSpans": [{ "scope": Self::scope(), "spans": spans }] }] }))
+    }
+
+    /// OTLP JSON of the histograms and counters, cumulative since startup.
+    fn metrics_body(&self, runtime: Option<&MetricsSnapshot>, now: u64) -> Value {
+        let (start, now) = (self.start_nanos.to_string(), now.to_string());
+        let mut by_name: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for ((name, pairs), histogram) in self.histograms.lock().unwrap().iter() {
+            let pairs: Vec<(&'static str, Value)> = pairs.iter().map(|(key, value)| (*key, json!(value))).collect();
+            by_name.entry(name.clone()).or_default().push(json!({
+                "attributes": attributes(&pairs),
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "count": histogram.count.to_string(),
+                "sum": histogram.sum,
+                "bucketCounts": histogram.buckets.iter().map(u64::to_string).collect::<Vec<_>>(),
+                "explicitBounds": BOUNDS,
+            }));
+        }
+        let mut metrics: Vec<Value> = by_name
+            .into_iter()
+            .map(|(name, points)| json!({ "name": name, "unit": "s", "histogram": { "aggregationTemporality": 2, "dataPoints": points } }))
+            .collect();
+        let mut sum = |name: &str, value: u64, monotonic: bool| {
+            let point = json!({ "startTimeUnixNano": start, "timeUnixNano": now, "asInt": value.to_string() });
+            metrics.push(json!({ "name": name, "sum": { "aggregationTemporality": 2, "isMonotonic": monotonic, "dataPoints": [point] } }));
+        };
+        sum("sysmonitor.telemetry.dropped_spans", self.dropped_spans.load(Ordering::Relaxed), true);
+        if let Some(runtime) = runtime {
+            sum("sysmonitor.events_processed", runtime.events_processed, true);
+            sum("sysmonitor.focus_changes", runtime.focus_changes, true);
+            sum("sysmonitor.db.flushes", runtime.flushes, true);
+            sum("sysmonitor.db.flush_errors", runtime.flush_errors, true);
+            sum("sysmonitor.db.size", runtime.db_size_now, false);
+        }
+        json!({ "resourceMetrics": [{ "resource": self.resource(), "scopeMetrics": [{ "scope": Self::scope(), "metrics": metrics }] }] })
+    }
+
+    /// Posts the queued spans and current metrics; the number of spans sent.
+    /// Spans stay queued when the collector can't be reached.
+    pub fn export(&self, runtime: Option<&MetricsSnapshot>) -> Result<usize, String> {
+        let Some(config) = self.config.as_ref() else {
+            return Ok(0);
+        };
+        let endpoint = config.endpoint.as_deref().unwrap_or_default().trim_end_matches('/');
+        let spans: Vec<SpanRecord> = self.spans.lock().unwrap().drain(..).collect();
+        if let Some(body) = self.traces_body(&spans) {
+            if let Err(e) = post(&format!("{}/v1/traces", endpoint), &config.headers, &body) {
+                let mut queued = self.spans.lock().unwrap();
+                for span in spans.into_iter().rev() {
+                    if queued.len() < MAX_QUEUED_SPANS {
+                        queued.push_front(span);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        post(&format!("{}/v1/metrics", endpoint), &config.headers, &self.metrics_body(runtime, now_nanos()))?;
+        Ok(spans.len())
+    }
+}
+
+#[cfg(feature = "otel")]
+fn post(url: &str, headers: &BTreeMap<String, String>, body: &Value) -> Result<(), String> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use crate::curl::Secrets;
+
+    let mut args: Vec<String> = ["-sS", "-f", "--max-time", "10", "-X", "POST", "-H", "Content-Type: application/json"]
+        .map(String::from)
+        .to_vec();
+    // Configured headers may carry an API key
+    let secrets = headers.iter().fold(Secrets::default(), |secrets, (name, value)| secrets.header(&format!("{}: {}", name, value))).write()?;
+    args.extend(secrets.args());
+    args.extend(["--data-binary".to_string(), "@-".to_string(), url.to_string()]);
+    let mut child = Command::new("curl")
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body.to_string().as_bytes()).map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("OTLP export failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn post(_url: &str, _headers: &BTreeMap<String, String>, _body: &Value) -> Result<(), String> {
+    Err("built without the `otel` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> Telemetry {
+        let config = TelemetryConfig { endpoint: Some("http://collector:4318".into()), ..Default::default() };
+        Telemetry { config: Some(config), start_nanos: 1, ..Telemetry::default() }
+    }
+
+    #[test]
+    fn requests_join_the_callers_trace() {
+        let telemetry = enabled();
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        telemetry.record_http("GET", "/api/health", 200, Duration::from_millis(30), Some(traceparent));
+        telemetry.record_http("POST", "/api/ingest/batch", 503, Duration::from_millis(3), Some("garbage"));
+        let spans: Vec<SpanRecord> = telemetry.spans.lock().unwrap().drain(..).collect();
+        let body = telemetry.traces_body(&spans).unwrap();
+        let sent = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(sent[0]["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(sent[0]["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!((&sent[0]["name"], &sent[0]["kind"]), (&json!("GET /api/health"), &json!(2)));
+        assert!(sent[1].get("parentSpanId").is_none() && sent[1]["traceId"].as_str().unwrap().len() == 32);
+        assert_eq!(sent[1]["status"]["code"], 2);
+
+        // Nothing is kept while telemetry is off
+        let off = Telemetry::default();
+        off.record_http("GET", "/", 200, Duration::ZERO, None);
+        assert!(off.spans.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn durations_land_in_cumulative_histograms() {
+        let telemetry = enabled();
+        telemetry.finish(telemetry.start("sysmonitor.db.flush"), vec![("rows", json!(3))], None);
+        telemetry.observe("sysmonitor.db.flush.duration".into(), Vec::new(), Duration::from_millis(300));
+        let body = telemetry.metrics_body(None, 2);
+        let metrics = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "sysmonitor.db.flush.duration");
+        let point = &metrics[0]["histogram"]["dataPoints"][0];
+        assert_eq!(point["count"], "2");
+        assert_eq!(point["bucketCounts"][7], "1"); // 0.3 s falls under the 0.5 bound
+        assert_eq!(metrics[1]["sum"]["dataPoints"][0]["asInt"], "0");
+    }
+}
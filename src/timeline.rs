@@ -0,0 +1,207 @@
+// Usage bucketed for a zoomable timeline.
+//
+// Each zoom level shows a fixed span around `center` in 96-365 buckets, from
+// 5-second buckets over ten minutes up to days over a year, so the client
+// draws about the same number of segments at any zoom. Levels with buckets of
+// whole hours are summed from `app_usage_hourly`, which keeps a year's view to
+// a few thousand rows; only the hours it hasn't materialized yet are read from
+// the sessions. Finer levels clip the sessions themselves.
+
+use std::collections::BTreeMap;
+
+use chrono::{Local, Offset, TimeZone};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::units::Timestamp;
+use crate::{reports, sessions};
+
+const HOUR_SECS: u64 = 3600;
+const DAY_SECS: u64 = 86400;
+pub const DEFAULT_ZOOM: usize = 3;
+
+/// (span, bucket) of each zoom level in seconds, closest first.
+pub const LEVELS: [(u64, u64); 7] = [
+    (600, 5),
+    (HOUR_SECS, 30),
+    (6 * HOUR_SECS, 180),
+    (DAY_SECS, 900),
+    (7 * DAY_SECS, HOUR_SECS),
+    (30 * DAY_SECS, 6 * HOUR_SECS),
+    (365 * DAY_SECS, DAY_SECS),
+];
+
+#[derive(Debug, Deserialize)]
+pub struct TimelineQuery {
+    pub center: Option<Timestamp>, // Now by default
+    pub zoom: Option<usize>, // Index into `LEVELS`
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bucket {
+    pub start: u64,
+    pub end: u64,
+    pub seconds: u64, // Tracked in the bucket
+    pub top_app: String,
+    pub top_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineWindow {
+    pub center: u64,
+    pub zoom: usize,
+    pub levels: usize,
+    pub start: u64,
+    pub end: u64,
+    pub bucket_secs: u64,
+    pub resolution: &'static str,
+    pub source: &'static str, // `hourly` or `sessions`
+    pub buckets: Vec<Bucket>, // Empty buckets left out
+}
+
+// Bucket boundaries of `[start, end)`: local days for day buckets, otherwise
+// multiples of `size` in local time
+fn boundaries(start: u64, end: u64, size: u64) -> Vec<u64> {
+    let mut bounds = Vec::new();
+    if size >= DAY_SECS {
+        let mut day = reports::local_date(start);
+        while (reports::day_bounds(day).0.max(0) as u64) < end {
+            bounds.push(reports::day_bounds(day).0.max(0) as u64);
+            day = day.succ_opt().unwrap_or(day);
+        }
+        bounds.push(end);
+        if let Some(first) = bounds.first_mut() {
+            *first = (*first).max(start);
+        }
+        return bounds;
+    }
+    let offset = Local.timestamp_opt(start as i64, 0).earliest().map_or(0, |time| i64::from(time.offset().fix().local_minus_utc()));
+    let local = (start as i64 + offset).max(0) as u64;
+    let mut bound = ((local - local % size) as i64 - offset).max(0) as u64;
+    while bound < end {
+        bounds.push(bound.max(start));
+        bound += size;
+    }
+    bounds.push(end);
+    bounds
+}
+
+// Seconds per app of each bucket, summed from `parts` of (start, end, app)
+fn fill(bounds: &[u64], parts: impl IntoIterator<Item = (u64, u64, String)>) -> Vec<BTreeMap<String, u64>> {
+    let mut buckets = vec![BTreeMap::new(); bounds.len().saturating_sub(1)];
+    for (start, end, app) in parts {
+        let first = bounds.partition_point(|&bound| bound <= start).saturating_sub(1);
+        for (index, bucket) in buckets.iter_mut().enumerate().skip(first) {
+            let (bucket_start, bucket_end) = (bounds[index], bounds[index + 1]);
+            if bucket_start >= end {
+                break;
+            }
+            let seconds = end.min(bucket_end).saturating_sub(start.max(bucket_start));
+            if seconds > 0 {
+                *bucket.entry(app.clone()).or_default() += seconds;
+            }
+        }
+    }
+    buckets
+}
+
+/// The buckets of one zoom level around `center`.
+pub fn window(conn: &Connection, center: u64, zoom: usize) -> Result<TimelineWindow, String> {
+    let &(span, size) = LEVELS.get(zoom).ok_or_else(|| format!("zoom must be 0 to {}", LEVELS.len() - 1))?;
+    let start = center.saturating_sub(span / 2);
+    let end = start + span;
+    let bounds = boundaries(start, end, size);
+    let sql = |e: rusqlite::Error| e.to_string();
+
+    let mut parts = Vec::new();
+    let mut raw_from = start;
+    let hourly = size % HOUR_SECS == 0;
+    if hourly {
+        // The newest materialized hour may still be growing, so it is read raw
+        let latest: Option<i64> = conn.query_row("SELECT MAX(hour_start) FROM app_usage_hourly", [], |row| row.get(0)).map_err(sql)?;
+        raw_from = latest.map_or(start, |latest| (latest.max(0) as u64).clamp(start, end));
+        let mut stmt = conn
+            .prepare("SELECT hour_start, app_name, seconds FROM app_usage_hourly WHERE hour_start >= ?1 AND hour_start < ?2")
+            .map_err(sql)?;
+        let rows = stmt
+            .query_map(params![start - start % HOUR_SECS, raw_from], |row| {
+                let hour = row.get::<_, i64>(0)?.max(0) as u64;
+                // Spread over the hour, which may straddle the window's first bucket
+                Ok((hour, hour + row.get::<_, i64>(2)?.clamp(0, HOUR_SECS as i64) as u64, row.get::<_, String>(1)?))
+            })
+            .map_err(sql)?;
+        for row in rows {
+            let (hour, until, app) = row.map_err(sql)?;
+            parts.push((hour.max(start), until.max(start), app));
+        }
+    }
+    for session in sessions::clipped(conn, Timestamp::from_secs(raw_from), Timestamp::from_secs(end)).map_err(sql)? {
+        parts.push((session.start.as_secs(), session.end.as_secs(), session.app_name));
+    }
+
+    let buckets = fill(&bounds, parts)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, apps)| !apps.is_empty())
+        .map(|(index, apps)| {
+            let (top_app, top_seconds) = apps.iter().max_by_key(|(app, seconds)| (**seconds, std::cmp::Reverse(*app))).unwrap();
+            Bucket {
+                start: bounds[index],
+                end: bounds[index + 1],
+                // Overlapping sessions can't fill a bucket past its length
+                seconds: apps.values().sum::<u64>().min(bounds[index + 1] - bounds[index]),
+                top_app: top_app.clone(),
+                top_seconds: *top_seconds,
+            }
+        })
+        .collect();
+    let resolution = match size {
+        size if size < 60 => "seconds",
+        size if size < HOUR_SECS => "minutes",
+        size if size < DAY_SECS => "hours",
+        _ => "days",
+    };
+    Ok(TimelineWindow {
+        center,
+        zoom,
+        levels: LEVELS.len(),
+        start,
+        end,
+        bucket_secs: size,
+        resolution,
+        source: if hourly { "hourly" } else { "sessions" },
+        buckets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn coarse_levels_use_the_hourly_table_and_fine_ones_the_sessions() {
+        let conn = fixtures::empty().unwrap();
+        let hour = 1_760_000_400 - 1_760_000_400 % HOUR_SECS;
+        // Two hours materialized four days back, and a session still being tracked
+        for (offset, app, seconds) in [(-100, "code.exe", 3000), (-99, "code.exe", 600), (-99, "chrome.exe", 1200)] {
+            conn.execute("INSERT INTO app_usage_hourly VALUES (?1, ?2, ?3)", params![(hour as i64 + offset * HOUR_SECS as i64), app, seconds])
+                .unwrap();
+        }
+        conn.execute("INSERT INTO app_usage_hourly VALUES (?1, 'code.exe', 60)", params![hour]).unwrap();
+        conn.execute("INSERT INTO usage_logs (identifier, app_name, window_title, timestamp, duration) VALUES ('slack.exe', 'slack.exe', '', ?1, 300)", params![hour + 400]).unwrap();
+
+        let week = window(&conn, hour - 50 * HOUR_SECS, 4).unwrap();
+        assert_eq!((week.source, week.resolution, week.bucket_secs), ("hourly", "hours", HOUR_SECS));
+        let summary: Vec<(u64, &str, u64)> =
+            week.buckets.iter().map(|bucket| ((bucket.start - week.buckets[0].start) / HOUR_SECS, bucket.top_app.as_str(), bucket.seconds)).collect();
+        // The newest hour comes from the sessions, not its stale row
+        assert_eq!(summary, vec![(0, "code.exe", 3000), (1, "chrome.exe", 1800), (100, "slack.exe", 300)]);
+
+        let close = window(&conn, hour + 250, 0).unwrap();
+        assert_eq!((close.source, close.resolution, close.bucket_secs), ("sessions", "seconds", 5));
+        assert_eq!(close.buckets.len(), 60);
+        assert!(close.buckets.iter().all(|bucket| bucket.seconds == 5 && bucket.top_app == "slack.exe"));
+        assert!(window(&conn, hour, LEVELS.len()).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+// Window title normalization applied before titles are matched or stored.
+//
+// Titles arrive with whatever the application put there: bidi embedding marks,
+// zero-width joiners used as separators, decomposed accents, and so on. Two
+// titles that render identically should map to the same activity.
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub const MAX_TITLE_GRAPHEMES: usize = 256;
+// Room reserved at the end of a truncated title for the ` #xxxxxxxx` hash suffix
+const HASH_SUFFIX_GRAPHEMES: usize = 10;
+
+// Characters that change rendering direction or are invisible, and that only
+// ever fragment sessions when they appear in a title. ZWJ/ZWNJ are kept since
+// they affect emoji sequences and shaping in some scripts.
+fn is_invisible_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' // zero-width space
+            | '\u{200E}' | '\u{200F}' // LRM, RLM
+            | '\u{202A}'..='\u{202E}' // bidi embeddings and overrides
+            | '\u{2060}'..='\u{2064}' // word joiner and invisible operators
+            | '\u{2066}'..='\u{2069}' // bidi isolates
+            | '\u{FEFF}' // byte order mark
+    )
+}
+
+pub fn normalize_title(title: &str) -> String {
+    let cleaned: String = title
+        .nfc()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .filter(|c| !is_invisible_format(*c))
+        .collect();
+
+    // Collapse runs of whitespace left behind by stripped control characters
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// FNV-1a, chosen over `DefaultHasher` because its output must never change
+// between builds: the hash is part of stored identifiers
+fn stable_hash(value: &str) -> u32 {
+    let mut hash: u32 = 0x811C_9DC5;
+    for byte in value.as_bytes() {
+        hash ^= u32::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// Title as used in activity keys and `usage_logs`. Titles longer than
+/// `MAX_TITLE_GRAPHEMES` are cut and tagged with a hash of the full title, so
+/// two long titles sharing a prefix stay distinct and the same long title always
+/// maps to the same key. The full text goes to `title_history`.
+pub fn storage_title(title: &str) -> String {
+    if title.graphemes(true).nth(MAX_TITLE_GRAPHEMES).is_none() {
+        return title.to_string();
+    }
+    let prefix = clamp_graphemes(title, MAX_TITLE_GRAPHEMES - HASH_SUFFIX_GRAPHEMES);
+    format!("{} #{:08x}", prefix.trim_end(), stable_hash(title))
+}
+
+// Cut after `max` grapheme clusters so combining marks and emoji sequences are
+// never split in half
+pub fn clamp_graphemes(value: &str, max: usize) -> String {
+    match value.grapheme_indices(true).nth(max) {
+        Some((end, _)) => value[..end].to_string(),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decomposed_and_composed_titles_match() {
+        assert_eq!(normalize_title("Cafe\u{301}"), normalize_title("Caf\u{e9}"));
+    }
+
+    #[test]
+    fn bidi_and_control_characters_are_removed() {
+        assert_eq!(normalize_title("\u{202B}Inbox\u{202C}\t-\n Mail\u{200B}"), "Inbox - Mail");
+    }
+
+    #[test]
+    fn long_titles_get_a_stable_hash_suffix() {
+        let long_a = format!("{}a", "x".repeat(300));
+        let long_b = format!("{}b", "x".repeat(300));
+        assert_eq!(storage_title("short"), "short");
+        assert_eq!(storage_title(&long_a), storage_title(&long_a));
+        assert_ne!(storage_title(&long_a), storage_title(&long_b));
+        assert_eq!(storage_title(&long_a).graphemes(true).count(), MAX_TITLE_GRAPHEMES);
+    }
+
+    #[test]
+    fn clamping_keeps_grapheme_clusters_whole() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let title = format!("a{}b", family);
+        assert_eq!(clamp_graphemes(&title, 2), format!("a{}", family));
+    }
+}
@@ -0,0 +1,104 @@
+// App-to-app focus switches as a weighted directed graph, for Sankey and
+// graph views of workflow patterns ("where do I go after Slack?").
+
+use std::collections::BTreeMap;
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::units::{DurationSecs, Timestamp};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TransitionQuery {
+    pub since: Option<Timestamp>, // Inclusive; defaults to a week ago
+    pub until: Option<Timestamp>, // Exclusive; defaults to just after now
+    pub min_count: Option<u64>, // Drop edges seen fewer times than this
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransitionNode {
+    pub app_name: String,
+    pub switches_in: u64,
+    pub switches_out: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransitionEdge {
+    pub from: String,
+    pub to: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransitionGraph {
+    pub since: Timestamp,
+    pub until: Timestamp,
+    pub nodes: Vec<TransitionNode>,
+    pub edges: Vec<TransitionEdge>,
+}
+
+const DEFAULT_WINDOW: DurationSecs = DurationSecs::days(7);
+
+pub fn record(conn: &Connection, from: &str, to: &str, timestamp: Timestamp) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO focus_transitions (from_app, to_app, timestamp) VALUES (?1, ?2, ?3)",
+        params![from, to, timestamp],
+    )?;
+    Ok(())
+}
+
+pub fn load_graph(db_path: &str, query: &TransitionQuery, now: Timestamp) -> SqlResult<TransitionGraph> {
+    let until = query.until.unwrap_or(now + DurationSecs::secs(1));
+    let since = query.since.unwrap_or(until - DEFAULT_WINDOW);
+    let min_count = query.min_count.unwrap_or(1);
+
+    let conn = Connection::open(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT from_app, to_app, COUNT(*) FROM focus_transitions
+         WHERE timestamp >= ?1 AND timestamp < ?2
+         GROUP BY from_app, to_app
+         HAVING COUNT(*) >= ?3
+         ORDER BY COUNT(*) DESC, from_app, to_app",
+    )?;
+    let edges = stmt
+        .query_map(params![since, until, min_count], |row| {
+            Ok(TransitionEdge {
+                from: row.get(0)?,
+                to: row.get(1)?,
+                count: row.get::<_, i64>(2)? as u64,
+            })
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    Ok(TransitionGraph { since, until, nodes: nodes_for(&edges), edges })
+}
+
+fn nodes_for(edges: &[TransitionEdge]) -> Vec<TransitionNode> {
+    let mut nodes: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for edge in edges {
+        nodes.entry(&edge.from).or_default().1 += edge.count;
+        nodes.entry(&edge.to).or_default().0 += edge.count;
+    }
+    nodes
+        .into_iter()
+        .map(|(app_name, (switches_in, switches_out))| TransitionNode {
+            app_name: app_name.to_string(),
+            switches_in,
+            switches_out,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodes_sum_edge_weights() {
+        let edge = |from: &str, to: &str, count| TransitionEdge { from: from.into(), to: to.into(), count };
+        let nodes = nodes_for(&[edge("slack.exe", "chrome.exe", 3), edge("chrome.exe", "slack.exe", 1)]);
+        assert_eq!(nodes.len(), 2);
+        assert_eq!((nodes[0].app_name.as_str(), nodes[0].switches_in, nodes[0].switches_out), ("chrome.exe", 3, 1));
+        assert_eq!((nodes[1].app_name.as_str(), nodes[1].switches_in, nodes[1].switches_out), ("slack.exe", 1, 3));
+    }
+}
@@ -0,0 +1,129 @@
+// Moving averages and trend direction for the stats endpoints.
+//
+// A day's time per app or category is compared with its average over the 7
+// and 30 days before it, so clients can show "up 15% on your average" without
+// fetching the history. Averages only count days on which anything was
+// tracked, so days off don't drag them down.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+pub const LONG_WINDOW_DAYS: u64 = 30;
+const SHORT_WINDOW_DAYS: u64 = 7;
+const FLAT_PERCENT: f64 = 10.0; // Changes within this much of the 7-day average are flat
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TrendQuery {
+    #[serde(default)]
+    pub trends: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+    Flat,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trend {
+    pub name: String,
+    pub seconds: u64,
+    pub avg_7d_secs: u64,
+    pub avg_30d_secs: u64,
+    pub change_vs_7d_percent: Option<f64>, // None without any earlier time
+    pub change_vs_30d_percent: Option<f64>,
+    pub direction: Direction,
+}
+
+/// First day of the history `compute` needs for `day`.
+pub fn window_start(day: NaiveDate) -> NaiveDate {
+    day - Days::new(LONG_WINDOW_DAYS)
+}
+
+fn change_percent(seconds: u64, average: u64) -> Option<f64> {
+    (average > 0).then(|| ((seconds as f64 / average as f64 - 1.0) * 1000.0).round() / 10.0)
+}
+
+/// Trends on `day` for everything in `daily` (day, name, seconds), ordered by
+/// that day's time.
+pub fn compute(day: NaiveDate, daily: &[(NaiveDate, String, u64)]) -> Vec<Trend> {
+    let short_from = day - Days::new(SHORT_WINDOW_DAYS);
+    let long_from = window_start(day);
+    let active = |from: NaiveDate| -> u64 {
+        let days: BTreeSet<NaiveDate> = daily.iter().map(|(date, _, _)| *date).filter(|date| (from..day).contains(date)).collect();
+        days.len().max(1) as u64
+    };
+    let (short_days, long_days) = (active(short_from), active(long_from));
+
+    // name -> (that day, last 7 days, last 30 days)
+    let mut totals: BTreeMap<&str, (u64, u64, u64)> = BTreeMap::new();
+    for (date, name, seconds) in daily {
+        let total = totals.entry(name.as_str()).or_default();
+        if *date == day {
+            total.0 += seconds;
+        } else if (long_from..day).contains(date) {
+            total.2 += seconds;
+            if *date >= short_from {
+                total.1 += seconds;
+            }
+        }
+    }
+
+    let mut trends: Vec<Trend> = totals
+        .into_iter()
+        .map(|(name, (seconds, short, long))| {
+            let (avg_7d_secs, avg_30d_secs) = (short / short_days, long / long_days);
+            let change_vs_7d_percent = change_percent(seconds, avg_7d_secs);
+            let direction = match change_vs_7d_percent {
+                Some(change) if change > FLAT_PERCENT => Direction::Up,
+                Some(change) if change < -FLAT_PERCENT => Direction::Down,
+                Some(_) => Direction::Flat,
+                None if seconds > 0 => Direction::Up,
+                None => Direction::Flat,
+            };
+            Trend {
+                name: name.to_string(),
+                seconds,
+                avg_7d_secs,
+                avg_30d_secs,
+                change_vs_7d_percent,
+                change_vs_30d_percent: change_percent(seconds, avg_30d_secs),
+                direction,
+            }
+        })
+        .collect();
+    trends.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.name.cmp(&b.name)));
+    trends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_compare_with_averages_of_tracked_days() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let before = |days: u64| day - Days::new(days);
+        let daily = vec![
+            (day, "code.exe".to_string(), 4600),
+            (before(1), "code.exe".to_string(), 4000),
+            (before(3), "code.exe".to_string(), 4000),
+            (before(20), "code.exe".to_string(), 1000),
+            (before(1), "chat.exe".to_string(), 1000),
+            (before(40), "code.exe".to_string(), 99999), // Outside both windows
+        ];
+        let trends = compute(day, &daily);
+        let code = &trends[0];
+        assert_eq!((code.avg_7d_secs, code.avg_30d_secs), (4000, 3000));
+        assert_eq!(code.change_vs_7d_percent, Some(15.0));
+        assert_eq!(code.direction, Direction::Up);
+
+        let chat = &trends[1];
+        assert_eq!((chat.seconds, chat.avg_7d_secs), (0, 500));
+        assert_eq!(chat.direction, Direction::Down);
+    }
+}
@@ -0,0 +1,235 @@
+// Seconds with their unit in the type.
+//
+// Times are Unix seconds and lengths whole seconds throughout, in the database
+// and the API alike, so both serialize and store as the bare number. Keeping
+// them apart catches a timestamp passed as a duration, or hours added to
+// seconds, at compile time: a `Timestamp` plus a `DurationSecs` is a
+// `Timestamp`, the difference of two is a `DurationSecs`, and lengths in other
+// units only come in through `mins`, `hours` and `days`. Subtraction stops at
+// zero: a clock that went backwards gives an empty length, not a panic or a
+// wrapped one.
+//
+// Stored rows and sessions (`sessions`, `overlap`, `archive`, `pauses`,
+// `blocks`), the day and period reports with their rollups and summary, the
+// `/api/v1` structs, ingested events and devices, API tokens, saved panels,
+// queries and layouts, vault blobs, the outbound breakers and integrations,
+// maintenance runs, bundle manifests, transitions and language totals use
+// them. Plain seconds are left where they are a setting's number
+// (`*_secs`, `*_mins` in the config) and in code doing arithmetic on offsets
+// within a day or bucket (forecasts, timelines, team aggregates, the Grafana
+// and Influx buckets), which converts where it reads sessions.
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+/// A point in time, in seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+
+/// A length of time in whole seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DurationSecs(u64);
+
+impl Timestamp {
+    pub const EPOCH: Self = Self(0);
+    pub const MAX: Self = Self(u64::MAX);
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs)
+    }
+
+    /// The wall clock now; the epoch if it reads earlier.
+    pub fn now() -> Self {
+        Self(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs())
+    }
+
+    pub const fn as_secs(self) -> u64 {
+        self.0
+    }
+
+    /// Seconds from `earlier` to this; zero if `earlier` is later.
+    pub fn since(self, earlier: Self) -> DurationSecs {
+        DurationSecs(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn saturating_sub(self, length: DurationSecs) -> Self {
+        Self(self.0.saturating_sub(length.0))
+    }
+}
+
+impl DurationSecs {
+    pub const ZERO: Self = Self(0);
+
+    pub const fn secs(secs: u64) -> Self {
+        Self(secs)
+    }
+
+    pub const fn mins(mins: u64) -> Self {
+        Self(mins * MINUTE)
+    }
+
+    pub const fn hours(hours: u64) -> Self {
+        Self(hours * HOUR)
+    }
+
+    pub const fn days(days: u64) -> Self {
+        Self(days * DAY)
+    }
+
+    pub const fn as_secs(self) -> u64 {
+        self.0
+    }
+
+    pub const fn to_std(self) -> Duration {
+        Duration::from_secs(self.0)
+    }
+}
+
+impl From<Duration> for DurationSecs {
+    /// Whole seconds, rounded down.
+    fn from(duration: Duration) -> Self {
+        Self(duration.as_secs())
+    }
+}
+
+impl Add<DurationSecs> for Timestamp {
+    type Output = Self;
+
+    fn add(self, length: DurationSecs) -> Self {
+        Self(self.0 + length.0)
+    }
+}
+
+impl AddAssign<DurationSecs> for Timestamp {
+    fn add_assign(&mut self, length: DurationSecs) {
+        self.0 += length.0;
+    }
+}
+
+impl Sub<DurationSecs> for Timestamp {
+    type Output = Self;
+
+    fn sub(self, length: DurationSecs) -> Self {
+        Self(self.0.saturating_sub(length.0))
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = DurationSecs;
+
+    fn sub(self, earlier: Self) -> DurationSecs {
+        DurationSecs(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl Add for DurationSecs {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl AddAssign for DurationSecs {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for DurationSecs {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+}
+
+impl SubAssign for DurationSecs {
+    fn sub_assign(&mut self, other: Self) {
+        self.0 = self.0.saturating_sub(other.0);
+    }
+}
+
+impl std::iter::Sum for DurationSecs {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        Self(iter.map(|length| length.0).sum())
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for DurationSecs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}s", self.0)
+    }
+}
+
+// SQLite integers are signed; seconds before the epoch or past i64 don't occur
+macro_rules! sql_seconds {
+    ($type:ident) => {
+        impl ToSql for $type {
+            fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+                self.0.to_sql()
+            }
+        }
+
+        impl FromSql for $type {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                i64::column_result(value).map(|secs| Self(secs.max(0) as u64))
+            }
+        }
+    };
+}
+
+sql_seconds!(Timestamp);
+sql_seconds!(DurationSecs);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[test]
+    fn arithmetic_keeps_units_apart() {
+        let start = Timestamp::from_secs(1_000);
+        let end = start + DurationSecs::mins(2);
+        assert_eq!(end, Timestamp::from_secs(1_120));
+        assert_eq!(end - start, DurationSecs::secs(120));
+        assert_eq!(start.since(end), DurationSecs::ZERO);
+        assert_eq!(start - end, DurationSecs::ZERO);
+        assert_eq!(Timestamp::EPOCH - DurationSecs::secs(1), Timestamp::EPOCH);
+        let mut left = DurationSecs::secs(30);
+        left -= DurationSecs::mins(1);
+        assert_eq!(left, DurationSecs::ZERO);
+        assert_eq!(DurationSecs::hours(24), DurationSecs::days(1));
+        assert_eq!(DurationSecs::from(Duration::from_millis(2_900)), DurationSecs::secs(2));
+        assert_eq!([DurationSecs::secs(30), DurationSecs::mins(1)].into_iter().sum::<DurationSecs>(), DurationSecs::secs(90));
+    }
+
+    #[test]
+    fn serializes_and_stores_as_plain_seconds() {
+        let json = serde_json::to_string(&(Timestamp::from_secs(1_760_000_000), DurationSecs::secs(45))).unwrap();
+        assert_eq!(json, "[1760000000,45]");
+        assert_eq!(serde_json::from_str::<Timestamp>("1760000000").unwrap(), Timestamp::from_secs(1_760_000_000));
+
+        let conn = Connection::open_in_memory().unwrap();
+        let (at, length): (Timestamp, DurationSecs) = conn
+            .query_row("SELECT ?1, ?2", rusqlite::params![Timestamp::from_secs(7), DurationSecs::hours(1)], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((at.as_secs(), length.as_secs()), (7, 3600));
+    }
+}
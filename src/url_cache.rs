@@ -0,0 +1,131 @@
+// Browser URLs remembered per window, from the `url_cache` section of the config.
+//
+// Reading a tab's URL through UI Automation or the DevTools protocol costs far
+// more than the rest of a poll, and the answer rarely changes between polls.
+// The URL read for a window is kept with a hash of the title it was read
+// under: switching tabs or navigating changes the title, which forces a new
+// read, and so does `ttl_secs` passing, for pages that change their URL but
+// not their title.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UrlCacheConfig {
+    pub ttl_secs: u64, // 0 reads the URL on every poll
+    pub max_windows: usize,
+}
+
+impl Default for UrlCacheConfig {
+    fn default() -> Self {
+        Self { ttl_secs: 30, max_windows: 64 }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    title_hash: u64,
+    url: Option<String>,
+    read_at: Instant,
+}
+
+/// URLs last read per window handle.
+#[derive(Debug, Default)]
+pub struct UrlCache {
+    entries: HashMap<u64, Entry>,
+}
+
+impl UrlCache {
+    /// The URL of `window` showing `title`: the one read before if the title
+    /// is the same and it isn't stale, otherwise a fresh one from `read`.
+    /// Also tells whether it came from the cache.
+    pub fn get_or_read(
+        &mut self,
+        config: &UrlCacheConfig,
+        window: u64,
+        title: &str,
+        now: Instant,
+        read: impl FnOnce() -> Option<String>,
+    ) -> (Option<String>, bool) {
+        let ttl = Duration::from_secs(config.ttl_secs);
+        let title_hash = hash_title(title);
+        if let Some(entry) = self.entries.get(&window) {
+            if entry.title_hash == title_hash && now.saturating_duration_since(entry.read_at) < ttl {
+                return (entry.url.clone(), true);
+            }
+        }
+
+        let url = read();
+        if !ttl.is_zero() && config.max_windows > 0 {
+            self.entries.retain(|_, entry| now.saturating_duration_since(entry.read_at) < ttl);
+            if self.entries.len() >= config.max_windows && !self.entries.contains_key(&window) {
+                let oldest = self.entries.iter().min_by_key(|(_, entry)| entry.read_at).map(|(&window, _)| window);
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+            self.entries.insert(window, Entry { title_hash, url: url.clone(), read_at: now });
+        }
+        (url, false)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.entries.shrink_to_fit();
+    }
+}
+
+fn hash_title(title: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    title.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn reads_again_only_when_the_tab_changes_or_goes_stale() {
+        let config = UrlCacheConfig::default();
+        let mut cache = UrlCache::default();
+        let reads = Cell::new(0);
+        let read = |url: &str| {
+            let url = url.to_string();
+            let reads = &reads;
+            move || {
+                reads.set(reads.get() + 1);
+                Some(url)
+            }
+        };
+        let start = Instant::now();
+        assert_eq!(cache.get_or_read(&config, 1, "Docs", start, read("https://a.example")), (Some("https://a.example".into()), false));
+        assert_eq!(cache.get_or_read(&config, 1, "Docs", start + Duration::from_secs(5), read("https://b.example")), (Some("https://a.example".into()), true));
+        // Another tab in the same window, another window, and the same tab once stale
+        assert!(!cache.get_or_read(&config, 1, "News", start + Duration::from_secs(6), read("https://b.example")).1);
+        assert!(!cache.get_or_read(&config, 2, "News", start + Duration::from_secs(6), read("https://b.example")).1);
+        assert!(!cache.get_or_read(&config, 1, "News", start + Duration::from_secs(40), read("https://b.example")).1);
+        assert_eq!(reads.get(), 4);
+
+        let off = UrlCacheConfig { ttl_secs: 0, ..config };
+        assert!(!cache.get_or_read(&off, 1, "News", start + Duration::from_secs(41), read("https://b.example")).1);
+    }
+
+    #[test]
+    fn the_oldest_window_makes_room() {
+        let config = UrlCacheConfig { ttl_secs: 60, max_windows: 2 };
+        let mut cache = UrlCache::default();
+        let start = Instant::now();
+        for window in 1..=3 {
+            cache.get_or_read(&config, window, "Docs", start + Duration::from_secs(window), || None);
+        }
+        assert_eq!(cache.entries.len(), 2);
+        assert!(!cache.entries.contains_key(&1));
+    }
+}
@@ -0,0 +1,269 @@
+// Shared checks on JSON request bodies, run before any handler sees them.
+//
+// `json_body` takes the place of `warp::body::json`: it caps the body size,
+// rejects over-long strings and deep nesting anywhere in the document, then
+// deserializes it and runs the type's own `Validate` checks (timestamp
+// windows, counts). Everything found is rejected at once as `Invalid`, which
+// `web::handle_rejection` answers with a 400 listing each violation by field.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::units::{DurationSecs, Timestamp};
+use crate::{dashboard, devices, events, goals, ingest, labels, panels, queries, rules, vault, writer};
+
+const MAX_DEPTH: usize = 32;
+const MAX_VIOLATIONS: usize = 20; // Listed per response; a hostile body could have millions
+const MAX_CLOCK_AHEAD: DurationSecs = DurationSecs::days(366); // Collector clocks may be off, but not by a unit
+
+/// Size limits of one route's body.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub body_bytes: u64,
+    pub string_chars: usize, // Of every string and object key
+}
+
+pub const SETTINGS: Limits = Limits { body_bytes: 64 * 1024, string_chars: 1024 };
+pub const INGEST: Limits = Limits { body_bytes: ingest::MAX_BATCH_BYTES, string_chars: 8192 };
+pub const RULES: Limits = Limits { body_bytes: rules::MAX_BUNDLE_BYTES, string_chars: 4096 };
+// Sealed blobs are one hex string each, checked by `SealedBlob::validate`
+pub const VAULT: Limits = Limits { body_bytes: vault::MAX_UPLOAD_BYTES, string_chars: vault::MAX_UPLOAD_BYTES as usize };
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub field: String, // e.g. `events[3].window_title`; empty for the body as a whole
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Violations(Vec<Violation>);
+
+impl Violations {
+    pub fn push(&mut self, field: impl Into<String>, code: &'static str, message: impl Into<String>) {
+        if self.0.len() < MAX_VIOLATIONS {
+            self.0.push(Violation { field: field.into(), code, message: message.into() });
+        }
+    }
+
+    /// A clock reading sent by a client: Unix seconds, not wildly ahead.
+    pub fn clock(&mut self, field: &str, timestamp: Option<Timestamp>, now: Timestamp) {
+        if let Some(timestamp) = timestamp {
+            if timestamp < ingest::MIN_TIMESTAMP || timestamp > now + MAX_CLOCK_AHEAD {
+                self.push(field, "out_of_range", "must be Unix seconds close to the current time");
+            }
+        }
+    }
+
+    /// An expiry or deadline: Unix seconds, after `now`.
+    pub fn future(&mut self, field: &str, timestamp: Option<Timestamp>, now: Timestamp) {
+        if let Some(timestamp) = timestamp {
+            if timestamp <= now {
+                self.push(field, "out_of_range", "must be in the future, in Unix seconds");
+            }
+        }
+    }
+}
+
+/// Checks of a body type beyond its shape.
+pub trait Validate {
+    fn validate(&self, _now: Timestamp, _violations: &mut Violations) {}
+}
+
+/// A body that failed validation, answered as a structured 400 (413 if too large).
+#[derive(Debug)]
+pub struct Invalid {
+    pub status: StatusCode,
+    pub violations: Vec<Violation>,
+}
+
+impl warp::reject::Reject for Invalid {}
+
+#[derive(Serialize)]
+struct InvalidResponse<'a> {
+    success: bool,
+    data: Option<()>,
+    error: String,
+    violations: &'a [Violation],
+}
+
+impl Invalid {
+    pub fn new(status: StatusCode, field: &str, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, violations: vec![Violation { field: field.to_string(), code, message: message.into() }] }
+    }
+
+    pub fn response(&self) -> warp::reply::Response {
+        let error = self
+            .violations
+            .iter()
+            .map(|violation| match violation.field.as_str() {
+                "" => violation.message.clone(),
+                field => format!("{}: {}", field, violation.message),
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let body = InvalidResponse { success: false, data: None, error, violations: &self.violations };
+        warp::reply::with_status(warp::reply::json(&body), self.status).into_response()
+    }
+}
+
+fn check_value(value: &Value, path: &str, depth: usize, limits: &Limits, violations: &mut Violations) {
+    if depth > MAX_DEPTH {
+        violations.push(path, "too_deep", format!("nested deeper than {} levels", MAX_DEPTH));
+        return;
+    }
+    let too_long = |text: &str| text.chars().count() > limits.string_chars;
+    match value {
+        Value::String(text) if too_long(text) => {
+            violations.push(path, "too_long", format!("longer than {} characters", limits.string_chars));
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                check_value(item, &format!("{}[{}]", path, index), depth + 1, limits, violations);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let field_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                if too_long(key) {
+                    violations.push(path, "too_long", format!("has a key longer than {} characters", limits.string_chars));
+                } else {
+                    check_value(field, &field_path, depth + 1, limits, violations);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parses and validates a body; all violations found, or the value.
+pub fn parse<T: DeserializeOwned + Validate>(body: &[u8], limits: &Limits, now: Timestamp) -> Result<T, Invalid> {
+    let invalid = |violations: Violations| Invalid { status: StatusCode::BAD_REQUEST, violations: violations.0 };
+    let value: Value = serde_json::from_slice(body)
+        .map_err(|e| Invalid::new(StatusCode::BAD_REQUEST, "", "malformed", format!("Body is not valid JSON: {}", e)))?;
+    let mut violations = Violations::default();
+    check_value(&value, "", 0, limits, &mut violations);
+    if !violations.0.is_empty() {
+        return Err(invalid(violations));
+    }
+    let parsed: T = serde_json::from_value(value).map_err(|e| Invalid::new(StatusCode::BAD_REQUEST, "", "invalid", e.to_string()))?;
+    parsed.validate(now, &mut violations);
+    if !violations.0.is_empty() {
+        return Err(invalid(violations));
+    }
+    Ok(parsed)
+}
+
+/// A JSON body within `limits` that passed `T`'s checks.
+pub fn json_body<T>(limits: Limits) -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+where
+    T: DeserializeOwned + Validate + Send,
+{
+    warp::body::content_length_limit(limits.body_bytes).and(warp::body::bytes()).and_then(
+        move |body: warp::hyper::body::Bytes| async move {
+            parse::<T>(&body, &limits, Timestamp::now()).map_err(warp::reject::custom)
+        },
+    )
+}
+
+/// Warp's own body rejections in the same shape.
+pub fn from_rejection(rejection: &Rejection) -> Option<Invalid> {
+    if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        return Some(Invalid::new(StatusCode::PAYLOAD_TOO_LARGE, "", "too_large", "Body is larger than this endpoint accepts"));
+    }
+    if rejection.find::<warp::reject::LengthRequired>().is_some() {
+        return Some(Invalid::new(StatusCode::LENGTH_REQUIRED, "", "length_required", "Send the body with a Content-Length"));
+    }
+    None
+}
+
+impl Validate for ingest::IngestBatch {
+    fn validate(&self, now: Timestamp, violations: &mut Violations) {
+        if self.schema_version == 0 {
+            violations.push("schema_version", "out_of_range", "starts at 1");
+        }
+        if self.events.len() > ingest::MAX_BATCH_EVENTS {
+            violations.push(
+                "events",
+                "too_many",
+                format!("batch has {} events; the limit is {}", self.events.len(), ingest::MAX_BATCH_EVENTS),
+            );
+        }
+        if let Some(Err(e)) = self.device.as_deref().map(devices::validate_id) {
+            violations.push("device", "invalid", e);
+        }
+        violations.clock("sent_at", self.sent_at, now);
+    }
+}
+
+impl Validate for devices::Heartbeat {
+    fn validate(&self, now: Timestamp, violations: &mut Violations) {
+        if let Some(Err(e)) = self.device.as_deref().map(devices::validate_id) {
+            violations.push("device", "invalid", e);
+        }
+        violations.clock("sent_at", self.sent_at, now);
+    }
+}
+
+impl Validate for dashboard::Layout {}
+
+impl Validate for panels::PanelSpec {}
+impl Validate for queries::SavedQuery {}
+
+impl Validate for goals::WeeklyGoals {}
+
+impl Validate for labels::BulkLabel {}
+
+impl Validate for rules::RuleTest {}
+
+impl Validate for writer::FlushUpdate {
+    fn validate(&self, _now: Timestamp, violations: &mut Violations) {
+        if self.max_events.is_some_and(|events| !(1..=writer::MAX_EVENTS).contains(&events)) {
+            violations.push("max_events", "out_of_range", format!("must be between 1 and {}", writer::MAX_EVENTS));
+        }
+        if self.interval_secs.is_some_and(|secs| !(1..=events::HEARTBEAT.as_secs()).contains(&secs)) {
+            violations.push("interval_secs", "out_of_range", format!("must be between 1 and {}", events::HEARTBEAT.as_secs()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_violation_is_reported_by_field() {
+        let now = Timestamp::from_secs(1_760_000_000);
+        let body = serde_json::json!({
+            "schema_version": 0,
+            "sent_at": now.as_secs() * 1000,
+            "events": [{ "app_name": "code.exe", "window_title": "x".repeat(9000), "start": now, "duration": 5 }],
+        });
+        let invalid = parse::<ingest::IngestBatch>(body.to_string().as_bytes(), &INGEST, now).unwrap_err();
+        let fields: Vec<&str> = invalid.violations.iter().map(|violation| violation.field.as_str()).collect();
+        // Strings are checked first; the rest only once the body is sane
+        assert_eq!(fields, vec!["events[0].window_title"]);
+
+        let body = serde_json::json!({ "schema_version": 0, "sent_at": now.as_secs() * 1000, "events": [] });
+        let invalid = parse::<ingest::IngestBatch>(body.to_string().as_bytes(), &INGEST, now).unwrap_err();
+        let fields: Vec<&str> = invalid.violations.iter().map(|violation| violation.field.as_str()).collect();
+        assert_eq!(fields, vec!["schema_version", "sent_at"]);
+        assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+
+        assert_eq!(parse::<ingest::IngestBatch>(b"{\"events\": [", &INGEST, now).unwrap_err().violations[0].code, "malformed");
+        let body = serde_json::json!({ "sent_at": now + DurationSecs::mins(1), "events": [] });
+        assert!(parse::<ingest::IngestBatch>(body.to_string().as_bytes(), &INGEST, now).is_ok());
+    }
+
+    #[test]
+    fn nesting_is_capped() {
+        let body = format!("{}{}", "[".repeat(40), "]".repeat(40));
+        let invalid = parse::<Value>(body.as_bytes(), &SETTINGS, Timestamp::EPOCH).unwrap_err();
+        assert_eq!(invalid.violations[0].code, "too_deep");
+    }
+
+    impl Validate for Value {}
+}
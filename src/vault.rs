@@ -0,0 +1,379 @@
+// End-to-end encrypted sync through an untrusted server.
+//
+// Devices share a key that never leaves them (`SYSMONITOR_VAULT_KEY`, made by
+// `sysmonitor vault keygen`). `vault push` seals the sessions tracked since the
+// last push as ingest batches with ChaCha20-Poly1305 (RFC 8439), bound to the
+// device id, and uploads them to `/api/vault`. The server keeps the blobs as
+// they are; `vault pull` fetches the other devices' blobs, opens them and
+// ingests them locally, so reports only ever exist on the devices.
+
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::curl::Secrets;
+use crate::ingest::{self, IngestBatch, IngestConfig};
+use crate::units::Timestamp;
+use crate::{devices, overlap};
+
+pub const KEY_ENV: &str = "SYSMONITOR_VAULT_KEY";
+pub const TOKEN_ENV: &str = "SYSMONITOR_TOKEN";
+pub const MAX_BLOBS_PER_UPLOAD: usize = 100;
+pub const MAX_UPLOAD_BYTES: u64 = 4 * ingest::MAX_BATCH_BYTES; // Hex doubles the sealed size
+pub const DEFAULT_LIMIT: u64 = 100;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const PUSH_CHUNK_EVENTS: usize = 1000;
+
+#[derive(Clone)]
+pub struct VaultKey([u8; KEY_LEN]);
+
+impl VaultKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; KEY_LEN];
+        getrandom::getrandom(&mut key).expect("operating system random source unavailable");
+        Self(key)
+    }
+
+    pub fn parse(hex: &str) -> Result<Self, String> {
+        let bytes = from_hex(hex.trim()).filter(|bytes| bytes.len() == KEY_LEN);
+        let bytes = bytes.ok_or_else(|| format!("the vault key must be {} hex characters", KEY_LEN * 2))?;
+        let mut key = [0u8; KEY_LEN];
+        key.copy_from_slice(&bytes);
+        Ok(Self(key))
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+}
+
+/// An encrypted payload as uploaded and stored, hex encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SealedBlob {
+    pub nonce: String,
+    pub ciphertext: String, // Ciphertext followed by the 16-byte tag
+}
+
+impl SealedBlob {
+    pub fn validate(&self) -> Result<(), String> {
+        match (from_hex(&self.nonce), from_hex(&self.ciphertext)) {
+            (Some(nonce), Some(ciphertext)) if nonce.len() == NONCE_LEN && ciphertext.len() >= TAG_LEN => Ok(()),
+            _ => Err(format!("blobs need a {}-byte nonce and a tagged ciphertext, hex encoded", NONCE_LEN)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredBlob {
+    pub id: i64,
+    pub device: String,
+    pub received_at: Timestamp,
+    #[serde(flatten)]
+    pub blob: SealedBlob,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VaultQuery {
+    #[serde(default)]
+    pub after: i64, // Last blob id already fetched
+    pub limit: Option<u64>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+fn cipher(key: &VaultKey) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(&Key::from(key.0))
+}
+
+/// Encrypts `plaintext` for the devices sharing `key`, bound to `device`.
+pub fn seal(key: &VaultKey, device: &str, plaintext: &[u8]) -> SealedBlob {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).expect("operating system random source unavailable");
+    let sealed = cipher(key)
+        .encrypt(&Nonce::from(nonce), Payload { msg: plaintext, aad: device.as_bytes() })
+        .expect("ChaCha20-Poly1305 encrypts any payload that fits in memory");
+    SealedBlob { nonce: to_hex(&nonce), ciphertext: to_hex(&sealed) }
+}
+
+/// Fails for another key, another device, or a blob altered on the way.
+pub fn open(key: &VaultKey, device: &str, blob: &SealedBlob) -> Result<Vec<u8>, String> {
+    let nonce: [u8; NONCE_LEN] = from_hex(&blob.nonce)
+        .and_then(|nonce| nonce.try_into().ok())
+        .ok_or("invalid nonce")?;
+    let sealed = from_hex(&blob.ciphertext).ok_or("invalid ciphertext")?;
+    cipher(key)
+        .decrypt(&Nonce::from(nonce), Payload { msg: &sealed, aad: device.as_bytes() })
+        .map_err(|_| "blob does not open with this key".to_string())
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    // Server side: opaque uploads
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS vault_blobs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            device TEXT NOT NULL,
+            received_at INTEGER NOT NULL,
+            nonce TEXT NOT NULL,
+            ciphertext TEXT NOT NULL
+        )",
+        [],
+    )?;
+    // Device side: how far each server has been pushed to and pulled from
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS vault_sync (
+            server TEXT PRIMARY KEY,
+            pushed_until INTEGER NOT NULL DEFAULT 0,
+            pulled_after INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn store(conn: &Connection, device: &str, blob: &SealedBlob, now: Timestamp) -> SqlResult<i64> {
+    conn.execute(
+        "INSERT INTO vault_blobs (device, received_at, nonce, ciphertext) VALUES (?1, ?2, ?3, ?4)",
+        params![device, now, blob.nonce, blob.ciphertext],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn list(conn: &Connection, query: &VaultQuery) -> SqlResult<Vec<StoredBlob>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, 1000);
+    let mut stmt = conn.prepare(
+        "SELECT id, device, received_at, nonce, ciphertext FROM vault_blobs WHERE id > ?1 ORDER BY id LIMIT ?2",
+    )?;
+    let blobs = stmt
+        .query_map(params![query.after, limit], |row| {
+            Ok(StoredBlob {
+                id: row.get(0)?,
+                device: row.get(1)?,
+                received_at: row.get(2)?,
+                blob: SealedBlob { nonce: row.get(3)?, ciphertext: row.get(4)? },
+            })
+        })?
+        .collect();
+    blobs
+}
+
+/// How far sessions were pushed to each server synced with, by server.
+pub fn pushed_until(conn: &Connection) -> SqlResult<Vec<(String, Timestamp)>> {
+    let mut stmt = conn.prepare("SELECT server, pushed_until FROM vault_sync ORDER BY server")?;
+    let servers = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+    servers
+}
+
+fn sync_state(conn: &Connection, server: &str) -> SqlResult<(Timestamp, i64)> {
+    conn.execute("INSERT OR IGNORE INTO vault_sync (server) VALUES (?1)", params![server])?;
+    conn.query_row(
+        "SELECT pushed_until, pulled_after FROM vault_sync WHERE server = ?1",
+        params![server],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+// `curl` like the notification channels, returning the API response's `data`
+fn request(args: &[&str], token: &str, body: Option<&[u8]>) -> Result<serde_json::Value, String> {
+    let secrets = Secrets::default().header(&format!("Authorization: Bearer {}", token)).write()?;
+    let mut child = Command::new("curl")
+        .arg("-sS")
+        .args(secrets.args())
+        .args(args)
+        .stdin(if body.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if let (Some(body), Some(mut stdin)) = (body, child.stdin.take()) {
+        stdin.write_all(body).map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("curl failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let response: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(|_| "the server did not answer with JSON".to_string())?;
+    match response.get("success").and_then(|success| success.as_bool()) {
+        Some(true) => Ok(response["data"].clone()),
+        _ => Err(format!("server refused: {}", response["error"].as_str().unwrap_or("unknown error"))),
+    }
+}
+
+pub fn default_device() -> String {
+    env::var("COMPUTERNAME")
+        .or_else(|_| env::var("HOSTNAME"))
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| devices::validate_id(name).is_ok())
+        .unwrap_or_else(|| "desktop".to_string())
+}
+
+fn push(conn: &Connection, key: &VaultKey, server: &str, device: &str, token: &str) -> Result<(), String> {
+    let now = Timestamp::now();
+    let (pushed_until, _) = sync_state(conn, server).map_err(|e| e.to_string())?;
+    // Only the tracker's own rows; synced and imported ones came from elsewhere
+    let sessions: Vec<_> = overlap::overlapping(conn, pushed_until, now)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|session| session.source.is_none())
+        .collect();
+    if sessions.is_empty() {
+        println!("Nothing to push since the last push");
+        return Ok(());
+    }
+
+    let blobs: Vec<SealedBlob> = sessions
+        .chunks(PUSH_CHUNK_EVENTS)
+        .map(|chunk| {
+            let events: Vec<_> = chunk
+                .iter()
+                .map(|session| {
+                    serde_json::json!({
+                        "app_name": session.app_name,
+                        "window_title": session.window_title,
+                        "url": session.url,
+                        "start": session.start,
+                        "duration": session.end - session.start,
+                    })
+                })
+                .collect();
+            let batch = serde_json::json!({ "schema_version": ingest::SCHEMA_VERSION, "source": device, "events": events });
+            seal(key, device, batch.to_string().as_bytes())
+        })
+        .collect();
+    let url = format!("{}/api/vault", server.trim_end_matches('/'));
+    for upload in blobs.chunks(MAX_BLOBS_PER_UPLOAD) {
+        let body = serde_json::json!({ "device": device, "blobs": upload });
+        let args = ["-X", "POST", "-H", "Content-Type: application/json", "--data-binary", "@-", &url];
+        request(&args, token, Some(body.to_string().as_bytes()))?;
+    }
+
+    let until = sessions.iter().map(|session| session.end).max().unwrap_or(now);
+    conn.execute("UPDATE vault_sync SET pushed_until = ?1 WHERE server = ?2", params![until, server])
+        .map_err(|e| e.to_string())?;
+    println!("Pushed {} sessions in {} encrypted blobs to {}", sessions.len(), blobs.len(), server);
+    Ok(())
+}
+
+fn pull(
+    conn: &mut Connection,
+    config: &IngestConfig,
+    key: &VaultKey,
+    server: &str,
+    device: &str,
+    token: &str,
+) -> Result<(), String> {
+    let (_, mut after) = sync_state(conn, server).map_err(|e| e.to_string())?;
+    let (mut accepted, mut opened) = (0, 0);
+    loop {
+        let url = format!("{}/api/vault?after={}&limit={}", server.trim_end_matches('/'), after, DEFAULT_LIMIT);
+        let blobs: Vec<StoredBlob> =
+            serde_json::from_value(request(&[&url], token, None)?).map_err(|e| format!("unexpected response: {}", e))?;
+        let Some(last) = blobs.last().map(|blob| blob.id) else {
+            break;
+        };
+        let now = Timestamp::now();
+        for stored in blobs.iter().filter(|stored| stored.device != device) {
+            // One bad blob must not stop the others from syncing
+            let batch = open(key, &stored.device, &stored.blob).and_then(|plaintext| {
+                serde_json::from_slice::<IngestBatch>(&plaintext).map_err(|e| format!("not a batch: {}", e))
+            });
+            match batch {
+                Ok(batch) => {
+                    let result = ingest::ingest_batch(conn, &batch, config, now).map_err(|e| e.to_string())?;
+                    accepted += result.accepted;
+                    opened += 1;
+                }
+                Err(e) => eprintln!("Skipping blob {} from {}: {}", stored.id, stored.device, e),
+            }
+        }
+        after = last;
+        conn.execute("UPDATE vault_sync SET pulled_after = ?1 WHERE server = ?2", params![after, server])
+            .map_err(|e| e.to_string())?;
+    }
+    println!("Pulled {} blobs from {}; {} sessions stored", opened, server, accepted);
+    Ok(())
+}
+
+/// `sysmonitor vault keygen|push|pull --server <url> [--device <name>]`
+pub fn run_command(conn: &mut Connection, config: &IngestConfig, args: &[String]) -> Result<(), String> {
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+            .map(String::as_str)
+    };
+    let command = args.first().map(String::as_str);
+    if command == Some("keygen") {
+        println!("{}={}", KEY_ENV, VaultKey::generate().to_hex());
+        println!("Set this on every device; the server never needs it.");
+        return Ok(());
+    }
+    if !matches!(command, Some("push" | "pull")) {
+        return Err("usage: vault keygen|push|pull --server <url> [--device <name>]".to_string());
+    }
+
+    let key = VaultKey::parse(&env::var(KEY_ENV).map_err(|_| format!("{} is not set; see `vault keygen`", KEY_ENV))?)?;
+    let token = env::var(TOKEN_ENV).map_err(|_| format!("{} must hold an `ingest` token for the server", TOKEN_ENV))?;
+    let server = option("--server").ok_or("--server is required")?;
+    let device = match option("--device") {
+        Some(device) => devices::validate_id(device)?.to_string(),
+        None => default_device(),
+    };
+    match command {
+        Some("push") => push(conn, &key, server, &device, &token),
+        _ => pull(conn, config, &key, server, &device, &token),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        from_hex(&hex.replace([' ', ':'], "")).unwrap()
+    }
+
+    #[test]
+    fn blob_format_is_rfc_8439_ciphertext_then_tag() {
+        let key = VaultKey((0x80..=0x9f).collect::<Vec<u8>>().try_into().unwrap());
+        let nonce: [u8; NONCE_LEN] = bytes("07 00 00 00 40 41 42 43 44 45 46 47").try_into().unwrap();
+        let aad = bytes("50 51 52 53 c0 c1 c2 c3 c4 c5 c6 c7");
+        let plaintext: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let sealed = cipher(&key).encrypt(&Nonce::from(nonce), Payload { msg: plaintext, aad: &aad }).unwrap();
+        assert_eq!(to_hex(&sealed[..16]), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(to_hex(&sealed[sealed.len() - TAG_LEN..]), "1ae10b594f09e26a7e902ecbd0600691");
+    }
+
+    #[test]
+    fn blobs_only_open_with_the_key_and_device_they_were_sealed_for() {
+        let key = VaultKey::generate();
+        let blob = seal(&key, "laptop", b"{\"events\":[]}");
+        assert!(blob.validate().is_ok());
+        assert_eq!(open(&key, "laptop", &blob).unwrap(), b"{\"events\":[]}");
+        assert!(open(&key, "desktop", &blob).is_err());
+        assert!(open(&VaultKey::generate(), "laptop", &blob).is_err());
+
+        let mut tampered = blob.clone();
+        tampered.ciphertext.replace_range(0..2, if blob.ciphertext.starts_with("00") { "01" } else { "00" });
+        assert!(open(&key, "laptop", &tampered).is_err());
+        assert_eq!(VaultKey::parse(&key.to_hex()).unwrap().0, key.0);
+    }
+}
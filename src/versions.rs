@@ -0,0 +1,149 @@
+// API versions side by side, and the headers announcing the end of one.
+//
+// Routes live under `/api/v<n>`; the unversioned `/api` routes from before
+// versioning count as version 0. Every `/api` response says which version
+// answered, in an `Api-Version` header and, in the `ApiResponse` envelope, a
+// `version` field. A deprecated version, or an unversioned route that has a
+// successor, also gets `Deprecation` and `Sunset` headers (RFC 9745 and 8594)
+// and a `Link` to the same route in the newest version that has it, so the
+// extension and sync clients learn of it from the responses they already get.
+//
+// A `/api/v2` is added with its routes and an entry in `VERSIONS`, where v1
+// then gets its dates; both are served until v1's sunset.
+
+use warp::http::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, LINK};
+use warp::hyper::body::{to_bytes, Body};
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::Reply;
+
+use crate::http_cache;
+
+pub const UNVERSIONED: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecated {
+    pub since: i64, // Unix seconds
+    pub sunset: i64, // When the routes may go away
+}
+
+pub struct Version {
+    pub number: u32,
+    pub routes: &'static [&'static str], // Below `/api/v<n>/`
+    pub deprecated: Option<Deprecated>,
+}
+
+// Unversioned routes that have a successor, from 2026-10-15 to 2027-04-15
+const UNVERSIONED_DEPRECATED: Deprecated = Deprecated { since: 1_792_022_400, sunset: 1_807_747_200 };
+
+pub const VERSIONS: &[Version] = &[Version {
+    number: 1,
+    routes: &["dashboard", "dashboard/layout", "tracking", "tracking/pause", "tracking/resume", "forecast/today", "goals/weekly"],
+    deprecated: None,
+}];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Served {
+    pub version: u32,
+    pub deprecated: Option<Deprecated>,
+    pub successor: Option<String>, // Path of the route in the newest version that has it
+}
+
+/// The version answering `path`; None outside `/api` and for versions that
+/// don't exist.
+pub fn resolve(path: &str) -> Option<Served> {
+    let rest = path.strip_prefix("/api/")?;
+    let (version, route) = match rest.split_once('/') {
+        Some((prefix, route)) => match prefix.strip_prefix('v').and_then(|number| number.parse::<u32>().ok()).filter(|&number| number > UNVERSIONED) {
+            Some(number) => (number, route),
+            None => (UNVERSIONED, rest),
+        },
+        None => (UNVERSIONED, rest),
+    };
+    let successor = VERSIONS
+        .iter()
+        .filter(|newer| newer.number > version && newer.deprecated.is_none() && newer.routes.contains(&route))
+        .max_by_key(|newer| newer.number)
+        .map(|newer| format!("/api/v{}/{}", newer.number, route));
+    let deprecated = if version == UNVERSIONED {
+        successor.as_ref().map(|_| UNVERSIONED_DEPRECATED)
+    } else {
+        VERSIONS.iter().find(|known| known.number == version)?.deprecated
+    };
+    Some(Served { version, deprecated, successor })
+}
+
+/// Adds the version to an `/api` response: headers, and the `version` field
+/// of the envelope.
+pub async fn mark(path: FullPath, reply: impl Reply) -> Result<Response, warp::Rejection> {
+    let mut response = reply.into_response();
+    let Some(served) = resolve(path.as_str()) else {
+        return Ok(response);
+    };
+    let headers = response.headers_mut();
+    headers.insert("api-version", HeaderValue::from(served.version));
+    if let Some(deprecated) = served.deprecated {
+        if let Ok(since) = HeaderValue::from_str(&format!("@{}", deprecated.since)) {
+            headers.insert("deprecation", since);
+        }
+        if let Ok(sunset) = HeaderValue::from_str(&http_cache::http_date(deprecated.sunset)) {
+            headers.insert("sunset", sunset);
+        }
+        if let Some(link) = served.successor.and_then(|successor| HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor)).ok()) {
+            headers.append(LINK, link);
+        }
+    }
+
+    let json = response.headers().get(CONTENT_TYPE).is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if !json {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Ok(Response::from_parts(parts, Body::empty())),
+    };
+    match stamp(&bytes, served.version) {
+        Some(stamped) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, Body::from(stamped)))
+        }
+        None => Ok(Response::from_parts(parts, Body::from(bytes))),
+    }
+}
+
+// The envelope with `version` as its first field; None for other bodies,
+// which may have a `version` of their own
+fn stamp(body: &[u8], version: u32) -> Option<Vec<u8>> {
+    let rest = body.strip_prefix(b"{\"success\":")?;
+    let mut stamped = format!("{{\"version\":{},\"success\":", version).into_bytes();
+    stamped.extend_from_slice(rest);
+    Some(stamped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_routes_with_a_successor_are_deprecated() {
+        let served = resolve("/api/dashboard").unwrap();
+        assert_eq!(served.version, UNVERSIONED);
+        assert_eq!(served.deprecated, Some(UNVERSIONED_DEPRECATED));
+        assert_eq!(served.successor.as_deref(), Some("/api/v1/dashboard"));
+
+        assert_eq!(resolve("/api/ingest/batch"), Some(Served { version: UNVERSIONED, deprecated: None, successor: None }));
+        assert_eq!(resolve("/api/v1/goals/weekly"), Some(Served { version: 1, deprecated: None, successor: None }));
+        assert_eq!(resolve("/api/v7/dashboard"), None);
+        assert_eq!(resolve("/static/script.js"), None);
+    }
+
+    #[test]
+    fn only_the_envelope_gets_a_version_field() {
+        let body = br#"{"success":true,"data":{"version":"x"},"error":null}"#;
+        let stamped: serde_json::Value = serde_json::from_slice(&stamp(body, 1).unwrap()).unwrap();
+        assert_eq!(stamped["version"], 1);
+        assert_eq!(stamped["data"]["version"], "x");
+        assert_eq!(stamp(b"{\"targets\":[]}", 1), None);
+    }
+}
@@ -0,0 +1,105 @@
+// Picking the open session up again after a restart, from the `warm_start`
+// section of the config.
+//
+// Stopping the tracker ends its open session, and starting it again with the
+// same window in front would begin a new one from zero: an update or a reboot
+// of the tracker splits the session in two. On a clean shutdown the open
+// session is kept in `open_session`; if the first window the tracker sees
+// afterwards is the same one, within `grace_secs` of the shutdown, its session
+// goes on from the old start, with the restart counted in it. The row is read
+// once, so only the first focus after starting can pick it up.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::ActivityKey;
+use crate::units::Timestamp;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WarmStartConfig {
+    pub grace_secs: u64, // 0 always starts a new session
+}
+
+impl Default for WarmStartConfig {
+    fn default() -> Self {
+        Self { grace_secs: 120 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenSession {
+    pub key: ActivityKey,
+    pub start: Timestamp,
+    pub stopped_at: Timestamp,
+}
+
+impl OpenSession {
+    /// Whether focusing `key` at `now` continues this session.
+    pub fn resumes(&self, config: &WarmStartConfig, key: &ActivityKey, now: Timestamp) -> bool {
+        self.key == *key && self.start <= self.stopped_at && now >= self.stopped_at && now.since(self.stopped_at).as_secs() <= config.grace_secs
+    }
+}
+
+pub fn init_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS open_session (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            identifier TEXT NOT NULL,
+            start INTEGER NOT NULL,
+            stopped_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Keeps `session` for the next start, or forgets the last one when None.
+pub fn save(conn: &Connection, session: Option<&OpenSession>) -> SqlResult<()> {
+    conn.execute("DELETE FROM open_session", [])?;
+    if let Some(session) = session {
+        conn.execute(
+            "INSERT INTO open_session (id, identifier, start, stopped_at) VALUES (1, ?1, ?2, ?3)",
+            params![session.key.to_string(), session.start.as_secs(), session.stopped_at.as_secs()],
+        )?;
+    }
+    Ok(())
+}
+
+/// The session kept by the last shutdown, removed so it is used only once.
+pub fn take(conn: &Connection) -> SqlResult<Option<OpenSession>> {
+    let session = conn
+        .query_row("SELECT identifier, start, stopped_at FROM open_session WHERE id = 1", [], |row| {
+            Ok(OpenSession {
+                key: ActivityKey::parse(&row.get::<_, String>(0)?),
+                start: Timestamp::from_secs(row.get::<_, i64>(1)?.max(0) as u64),
+                stopped_at: Timestamp::from_secs(row.get::<_, i64>(2)?.max(0) as u64),
+            })
+        })
+        .optional()?;
+    save(conn, None)?;
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_same_window_within_the_grace_resumes_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_tables(&conn).unwrap();
+        let key = ActivityKey::new("code.exe", "main.rs - sysmonitor", None);
+        let session = OpenSession { key: key.clone(), start: Timestamp::from_secs(1_000), stopped_at: Timestamp::from_secs(1_600) };
+        save(&conn, Some(&session)).unwrap();
+        let taken = take(&conn).unwrap().unwrap();
+        assert_eq!(taken, session);
+        assert_eq!(take(&conn).unwrap(), None);
+
+        let config = WarmStartConfig::default();
+        assert!(taken.resumes(&config, &key, Timestamp::from_secs(1_700)));
+        assert!(!taken.resumes(&config, &key, Timestamp::from_secs(1_800)));
+        assert!(!taken.resumes(&config, &ActivityKey::new("code.exe", "web.rs - sysmonitor", None), Timestamp::from_secs(1_610)));
+        assert!(!taken.resumes(&WarmStartConfig { grace_secs: 0 }, &key, Timestamp::from_secs(1_601)));
+    }
+}
@@ -0,0 +1,2054 @@
+// HTTP API and dashboard, served on localhost by warp.
+//
+// Every route except `/api/health` needs a token with the matching scope
+// (see `auth`); responses share the `ApiResponse` shape, except for streams
+// such as `/api/logs/tail`. What the dashboard reads is also served under
+// `/api/v1` in the stable camelCase shape of `api`; `versions` marks each
+// response with the version that answered.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use warp::{Filter, Reply};
+
+use crate::auth::{self, Scope};
+use crate::notify::EventKind;
+use crate::units::{DurationSecs, Timestamp};
+use crate::{
+    api, apps, archive, assets, assignments, audit, blocks, compression, daily_summary, dashboard, devices, distractions, export, favicons, forecast, formats, goals, grafana,
+    home_assistant, http_cache, ingest, integrations, labels, language, live, logs, maintenance, overlap, panels, pauses, queries, refresh, reports, rules, schemas, sessions, team, timeline, transitions, quotas, trends, validation, vault, versions, widgets, writer,
+    metrics::RuntimeMetrics, SystemMonitor, ACTIVITY_RETENTION,
+};
+
+pub const WEB_PORT: u16 = 3030;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApiResponse {
+    success: bool,
+    data: Option<serde_json::Value>,
+    error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<serde_json::Value>, // Refresh hints on dashboard and stats answers
+}
+
+// `?date=YYYY-MM-DD`, today when missing
+#[derive(Debug, Clone, Default, Deserialize)]
+struct DateQuery {
+    date: Option<String>,
+}
+
+pub async fn start_web_server(monitor: Arc<SystemMonitor>, addr: SocketAddr) {
+    let read = auth::guard(Scope::Read, monitor.clone());
+    let write = auth::guard(Scope::Write, monitor.clone());
+    let admin = || auth::require(Scope::Admin, monitor.clone());
+    let monitor_filter = warp::any().map({
+        let monitor = monitor.clone();
+        move || monitor.clone()
+    });
+    
+    // Serve embedded static files, fingerprinted names are cached forever
+    let static_files = warp::path!("static" / String)
+        .and(warp::get())
+        .and(conditional_headers())
+        .and_then(handle_static);
+    
+    // API routes
+    let api_routes = warp::path("api")
+        .and(
+            // Dashboard data endpoint
+            warp::path("dashboard")
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(read.clone())
+                .and(accepted_format())
+                .and(monitor_filter.clone())
+                .and_then(handle_dashboard)
+                .or(
+                    // Widget arrangement, shared by every browser and frontend
+                    warp::path!("dashboard" / "layout")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_dashboard_layout)
+                )
+                .or(
+                    warp::path!("dashboard" / "layout")
+                        .and(warp::put())
+                        .and(write.clone())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_set_dashboard_layout)
+                )
+                .or(
+                    // Panels defined as query specs, evaluated here
+                    warp::path("panels")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_panels)
+                )
+                .or(
+                    warp::path!("panels" / "preview")
+                        .and(warp::post())
+                        .and(write.clone())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_preview_panel)
+                )
+                .or(
+                    warp::path!("panels" / String)
+                        .and(warp::put())
+                        .and(write.clone())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_save_panel)
+                )
+                .or(
+                    warp::path!("panels" / String)
+                        .and(warp::delete())
+                        .and(write.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_delete_panel)
+                )
+                .or(
+                    warp::path!("panels" / String / "data")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_panel_data)
+                )
+                .or(
+                    // Site icon for domain-level views, fetched once and cached
+                    warp::path!("domains" / String / "icon")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_domain_icon)
+                )
+                .or(
+                    // Named stats and timeline queries over range presets
+                    warp::path("saved-queries")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_saved_queries)
+                )
+                .or(
+                    warp::path!("saved-queries" / String)
+                        .and(warp::put())
+                        .and(write.clone())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_save_query)
+                )
+                .or(
+                    warp::path!("saved-queries" / String)
+                        .and(warp::delete())
+                        .and(write.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_delete_query)
+                )
+                .or(
+                    warp::path!("saved-queries" / String / "run")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_run_query)
+                )
+                .or(
+                    // Health check endpoint
+                    warp::path("health")
+                        .and(warp::get())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_health)
+                )
+                .or(
+                    // JSON Schemas of the main payloads, public like the health check
+                    warp::path("schemas")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and_then(handle_schemas)
+                )
+                .or(
+                    warp::path!("schemas" / String)
+                        .and(warp::get())
+                        .and(conditional_headers())
+                        .and_then(handle_schema)
+                )
+                .or(
+                    // Internal runtime counters
+                    warp::path("metrics")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_metrics)
+                )
+                .or(
+                    // Full history downloads, e.g. /api/export/history.csv.zst
+                    warp::path!("export" / String)
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<archive::ArchiveQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_export)
+                )
+                .or(
+                    // Weighted app-to-app switch graph
+                    warp::path!("stats" / "transitions")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<transitions::TransitionQuery>())
+                        .and(accepted_format())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_transitions)
+                )
+                .or(
+                    // First and last use of every app, stalest first
+                    warp::path!("apps" / "lifecycle")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<apps::LifecycleQuery>())
+                        .and(accepted_format())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_app_lifecycle)
+                )
+                .or(
+                    // Sessions clustered into work blocks
+                    warp::path("blocks")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<blocks::BlockQuery>())
+                        .and(accepted_format())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_blocks)
+                )
+                .or(
+                    // Time per title language
+                    warp::path("languages")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<language::LanguageQuery>())
+                        .and(accepted_format())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_languages)
+                )
+                .or(
+                    // Time per category for a day, from stored assignments
+                    warp::path("categories")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<DateQuery>())
+                        .and(warp::query::<trends::TrendQuery>())
+                        .and(accepted_format())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_categories)
+                )
+                .or(
+                    // Short productive sessions that led into long distracting runs
+                    warp::path!("insights" / "distractions")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<DateQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_distractions)
+                )
+                .or(
+                    // Projected productive time and budget use by the end of today
+                    warp::path!("forecast" / "today")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_forecast)
+                )
+                .or(
+                    // Weekly targets per category and the week's progress
+                    warp::path!("goals" / "weekly")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<DateQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_weekly_goals)
+                )
+                .or(
+                    warp::path!("goals" / "weekly")
+                        .and(warp::put())
+                        .and(admin())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_set_weekly_goals)
+                )
+                .or(
+                    // Flat sensor state for Home Assistant's RESTful integration
+                    warp::path("homeassistant")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_home_assistant)
+                )
+                .or(
+                    warp::path!("homeassistant" / "config.yaml")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::header::optional::<String>("host"))
+                        .and_then(handle_home_assistant_config)
+                )
+                .or(
+                    // Sample event to one channel, to try a webhook out
+                    warp::path!("notifications" / "test")
+                        .and(warp::post())
+                        .and(admin())
+                        .and(warp::query::<TestFireQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_test_notification)
+                )
+                .or(
+                    // Grafana JSON datasource: connection test, metric list and time series
+                    warp::path("grafana")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .map(|| api_result(Ok(grafana::TABLES)))
+                )
+                .or(
+                    warp::path!("grafana" / "search")
+                        .and(warp::post())
+                        .and(read.clone())
+                        .map(|| warp::reply::json(&grafana::TABLES))
+                )
+                .or(
+                    warp::path!("grafana" / "metrics")
+                        .and(warp::post())
+                        .and(read.clone())
+                        .map(handle_grafana_metrics)
+                )
+                .or(
+                    warp::path!("grafana" / "query")
+                        .and(warp::post())
+                        .and(read.clone())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_grafana_query)
+                )
+                .or(
+                    // Flat rows of one Grafana table, for the Infinity plugin
+                    warp::path!("grafana" / String)
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<GrafanaRange>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_grafana_rows)
+                )
+                .or(
+                    // Usage in buckets sized for the zoom level, for timeline scrubbing
+                    warp::path!("timeline" / "window")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<timeline::TimelineQuery>())
+                        .and(accepted_format())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_timeline_window)
+                )
+                .or(
+                    // Per-day usage report, cacheable once the day is over
+                    warp::path!("reports" / "daily" / String)
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<trends::TrendQuery>())
+                        .and(conditional_headers())
+                        .and(accepted_format())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_daily_report)
+                )
+                .or(
+                    // Monthly and yearly summaries from the daily rollups
+                    warp::path!("reports" / "monthly" / String)
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(conditional_headers())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_monthly_report)
+                )
+                .or(
+                    warp::path!("reports" / "yearly" / String)
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(conditional_headers())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_yearly_report)
+                )
+                .or(
+                    // The week of `date` (this week by default) as a PDF
+                    warp::path!("reports" / "weekly.pdf")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<DateQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_weekly_pdf)
+                )
+                .or(
+                    // Pause state and reason presets. Pausing only stops
+                    // collection, so the dashboard may do it without a token;
+                    // read-only tokens may not
+                    warp::path("tracking")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_tracking)
+                )
+                .or(
+                    warp::path!("tracking" / "pause")
+                        .and(warp::post())
+                        .and(write.clone())
+                        .and(warp::query::<pauses::PauseQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_pause)
+                )
+                .or(
+                    warp::path!("tracking" / "resume")
+                        .and(warp::post())
+                        .and(write.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_resume)
+                )
+                .or(
+                    // The coalescing writer: settings and what is queued, a
+                    // change to them until restart, and a write right away
+                    warp::path("flush")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_flush_status)
+                )
+                .or(
+                    warp::path("flush")
+                        .and(warp::path::end())
+                        .and(warp::put())
+                        .and(admin())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_set_flush)
+                )
+                .or(
+                    warp::path("flush")
+                        .and(warp::path::end())
+                        .and(warp::post())
+                        .and(write.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_flush)
+                )
+                .or(
+                    // What works in this environment, for hiding what doesn't
+                    warp::path("capabilities")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_capabilities)
+                )
+                .or(
+                    // Housekeeping: each job's last run and next time, and a run right away
+                    warp::path("maintenance")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_maintenance_status)
+                )
+                .or(
+                    warp::path!("maintenance" / String)
+                        .and(warp::post())
+                        .and(admin())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_run_maintenance)
+                )
+                .or(
+                    // Pauses and untracked stretches of a day
+                    warp::path!("tracking" / "gaps")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<DateQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_gaps)
+                )
+                .or(
+                    // Server-sent events: the last lines of the process log, then new ones
+                    warp::path!("logs" / "tail")
+                        .and(warp::get())
+                        .and(admin())
+                        .and(warp::query::<logs::TailQuery>())
+                        .and_then(handle_log_tail)
+                )
+                .or(
+                    // API token management
+                    warp::path("tokens")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(admin())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_list_tokens)
+                )
+                .or(
+                    warp::path("tokens")
+                        .and(warp::path::end())
+                        .and(warp::post())
+                        .and(admin())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_create_token)
+                )
+                .or(
+                    warp::path!("tokens" / String)
+                        .and(warp::put())
+                        .and(admin())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_update_token)
+                )
+                .or(
+                    // Bulk event upload from watchers, importers and sync clients
+                    warp::path!("ingest" / "batch")
+                        .and(warp::post())
+                        .and(auth::require(Scope::Ingest, monitor.clone()))
+                        .and(warp::header::<u64>("content-length"))
+                        .and(validation::json_body(validation::INGEST))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_ingest_batch)
+                )
+                .or(
+                    // Collectors that reported here, with online status
+                    warp::path("devices")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_devices)
+                )
+                .or(
+                    warp::path!("devices" / "heartbeat")
+                        .and(warp::post())
+                        .and(auth::require(Scope::Ingest, monitor.clone()))
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_heartbeat)
+                )
+                .or(
+                    // End-to-end encrypted blobs; only devices hold the key
+                    warp::path("vault")
+                        .and(warp::path::end())
+                        .and(warp::post())
+                        .and(auth::require(Scope::Ingest, monitor.clone()))
+                        .and(warp::header::<u64>("content-length"))
+                        .and(validation::json_body(validation::VAULT))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_vault_upload)
+                )
+                .or(
+                    warp::path("vault")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(auth::guard(Scope::Ingest, monitor.clone()))
+                        .and(warp::query::<vault::VaultQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_vault_list)
+                )
+                .or(
+                    warp::path!("ingest" / "conflicts")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<overlap::ConflictQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_ingest_conflicts)
+                )
+                .or(
+                    // Category rules and exclusions as a shareable bundle
+                    warp::path!("rules" / "export")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<RulesQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_rules_export)
+                )
+                .or(
+                    // Dry run of candidate rules against stored sessions
+                    warp::path!("rules" / "test")
+                        .and(warp::post())
+                        .and(read.clone())
+                        .and(warp::query::<archive::ArchiveQuery>())
+                        .and(validation::json_body(validation::RULES))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_rules_test)
+                )
+                .or(
+                    warp::path!("rules" / "import")
+                        .and(warp::post())
+                        .and(admin())
+                        .and(warp::query::<RulesQuery>())
+                        .and(warp::body::content_length_limit(rules::MAX_BUNDLE_BYTES))
+                        .and(warp::body::bytes())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_rules_import)
+                )
+                .or(
+                    // Relabel every session a filter matches, as one change set
+                    warp::path!("labels" / "bulk")
+                        .and(warp::post())
+                        .and(admin())
+                        .and(validation::json_body(validation::SETTINGS))
+                        .and(monitor_filter.clone())
+                        .and_then(handle_bulk_label)
+                )
+                .or(
+                    warp::path!("labels" / "changes")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_label_changes)
+                )
+                .or(
+                    warp::path!("labels" / "changes" / i64 / "undo")
+                        .and(warp::post())
+                        .and(admin())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_undo_labels)
+                )
+                .or(
+                    // Health of each configured integration, with why it isn't ok
+                    warp::path("integrations")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_integrations)
+                )
+                .or(
+                    // Breakers of the integrations and the messages that never got through
+                    warp::path!("integrations" / "status")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_integrations_status)
+                )
+                .or(
+                    // Everything seen since the start, beyond the dashboard's active apps
+                    warp::path!("activity" / "live")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<live::LiveQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_live_activity)
+                )
+                .or(
+                    // Anonymized figures from aggregation-only mode
+                    warp::path!("team" / "summary")
+                        .and(warp::get())
+                        .and(admin())
+                        .and(warp::query::<team::TeamQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_team_summary)
+                )
+                .or(
+                    // Mutating requests by token
+                    warp::path("audit")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(admin())
+                        .and(warp::query::<audit::AuditQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_audit)
+                )
+                .or(
+                    warp::path!("tokens" / String)
+                        .and(warp::delete())
+                        .and(admin())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_revoke_token)
+                )
+        );
+    
+    // The versioned API, with the same scopes as the routes it mirrors
+    let api_v1 = warp::path!("api" / "v1" / ..)
+        .and(
+            warp::path("dashboard")
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(read.clone())
+                .and(monitor_filter.clone())
+                .and_then(handle_v1_dashboard)
+                .or(
+                    warp::path!("dashboard" / "layout")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_v1_dashboard_layout)
+                )
+                .or(
+                    warp::path("tracking")
+                        .and(warp::path::end())
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_v1_tracking)
+                )
+                .or(
+                    warp::path!("tracking" / "pause")
+                        .and(warp::post())
+                        .and(write.clone())
+                        .and(warp::query::<pauses::PauseQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_v1_pause)
+                )
+                .or(
+                    warp::path!("tracking" / "resume")
+                        .and(warp::post())
+                        .and(write.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_v1_resume)
+                )
+                .or(
+                    warp::path!("forecast" / "today")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_v1_forecast)
+                )
+                .or(
+                    warp::path!("goals" / "weekly")
+                        .and(warp::get())
+                        .and(read.clone())
+                        .and(warp::query::<DateQuery>())
+                        .and(monitor_filter.clone())
+                        .and_then(handle_v1_weekly_goals)
+                )
+        );
+
+    // Embeddable tiles, outside /api since they are pages rather than data
+    let widgets = warp::path!("widget" / String)
+        .and(warp::get())
+        .and(read.clone())
+        .and(warp::query::<widgets::WidgetQuery>())
+        .and(monitor_filter.clone())
+        .and_then(handle_widget);
+
+    // Serve main HTML page
+    let index = warp::path::end()
+        .and(warp::get())
+        .and(conditional_headers())
+        .map(|conditional: http_cache::Conditional| assets::manifest().index_response(&conditional));
+    
+    let audit_monitor = monitor.clone();
+    let routes = audit::context(audit_monitor.clone())
+        .and(index.or(static_files).or(widgets).or(api_v1).or(api_routes).recover(handle_rejection))
+        .map(move |context: audit::AuditContext, reply| {
+            let mut response = warp::Reply::into_response(reply);
+            audit::record(&audit_monitor, &context, response.status());
+            if let Some(value) = audit_monitor.safe_mode.as_ref().and_then(|safe| warp::http::HeaderValue::from_str(&safe.reason).ok()) {
+                response.headers_mut().insert("sysmonitor-safe-mode", value);
+            }
+            response
+        })
+        .and(warp::path::full())
+        .and_then(|response, path| versions::mark(path, response))
+        .and(warp::header::optional::<String>("accept-encoding"))
+        .and_then(|reply, accept_encoding| compression::compress_reply(accept_encoding, reply))
+        .with(warp::log::custom(move |info| {
+            let traceparent = info.request_headers().get("traceparent").and_then(|value| value.to_str().ok());
+            monitor.telemetry.record_http(info.method().as_str(), info.path(), info.status().as_u16(), info.elapsed(), traceparent);
+        }));
+    
+    warp::serve(routes).run(addr).await;
+}
+
+// The format of `Accept`, for the endpoints that offer more than JSON
+fn accepted_format() -> impl Filter<Extract = (formats::Format,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("accept").map(|accept: Option<String>| accept.as_deref().map_or(formats::Format::Json, formats::negotiate))
+}
+
+fn conditional_headers() -> impl Filter<Extract = (http_cache::Conditional,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("if-none-match")
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .map(|if_none_match, if_modified_since| http_cache::Conditional { if_none_match, if_modified_since })
+}
+
+async fn handle_static(name: String, conditional: http_cache::Conditional) -> Result<warp::reply::Response, warp::Rejection> {
+    assets::manifest()
+        .static_response(&name, &conditional)
+        .ok_or_else(warp::reject::not_found)
+}
+
+async fn handle_widget(name: String, query: widgets::WidgetQuery, monitor: Arc<SystemMonitor>) -> Result<warp::reply::Response, warp::Rejection> {
+    let theme = match query.theme() {
+        Ok(theme) => theme,
+        Err(e) => return Ok(api_result::<()>(Err(e))),
+    };
+    let today = || reports::daily_report(&monitor.db_path, chrono::Local::now().date_naive()).map_err(|e| format!("Failed to load today's usage: {}", e));
+    let tile = match name.as_str() {
+        "today-total" => today().map(|report| widgets::today_total(&theme, report.total_duration.as_secs(), report.apps.len())),
+        "current-app" => {
+            let data = monitor.get_dashboard_data();
+            let for_secs = data.active_apps.first().map_or(0, |(_, length)| length.as_secs());
+            let title = data.current_window.as_deref().filter(|_| query.show_title.unwrap_or(false));
+            Ok(widgets::current_app(&theme, data.current_app.as_deref(), title, for_secs, monitor.current_pause().is_some()))
+        }
+        "top5" => today().map(|report| {
+            let apps: Vec<(String, u64)> = report.apps.into_iter().map(|app| (app.app_name, app.duration.as_secs())).collect();
+            widgets::top_apps(&theme, &apps)
+        }),
+        _ => return Err(warp::reject::not_found()),
+    };
+    Ok(match tile {
+        Ok(tile) => warp::http::Response::builder()
+            .header("Content-Type", theme.content_type())
+            .header("Cache-Control", "no-store")
+            .body(tile.into())
+            .unwrap(),
+        Err(e) => api_result::<()>(Err(e)),
+    })
+}
+
+async fn handle_domain_icon(domain: String, monitor: Arc<SystemMonitor>) -> Result<warp::reply::Response, warp::Rejection> {
+    let config = monitor.config.favicons.clone();
+    let dir = config.dir_for(&monitor.db_path);
+    // Fetching runs curl, which may take seconds
+    let icon = tokio::task::spawn_blocking(move || favicons::icon(&config, &dir, &domain, SystemTime::now()))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    Ok(match icon {
+        // Badges are cached briefly, so the real icon shows up once fetched
+        Ok(icon) => warp::http::Response::builder()
+            .header("Content-Type", icon.image_type.content_type())
+            .header("Cache-Control", if icon.fallback { "max-age=3600" } else { "max-age=86400" })
+            .header("Content-Security-Policy", "default-src 'none'; style-src 'unsafe-inline'")
+            .header("X-Content-Type-Options", "nosniff")
+            .body(icon.bytes.into())
+            .unwrap(),
+        Err(e) => api_result::<()>(Err(e)),
+    })
+}
+
+async fn handle_dashboard(format: formats::Format, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let meta = refresh_meta(&monitor, refresh::Freshness::Live);
+    Ok(negotiated_with_meta(format, "/recent_activity", Ok::<_, String>(monitor.get_dashboard_data()), Some(meta)))
+}
+
+// Poll hints for an answer of `freshness`, as of now
+fn refresh_meta(monitor: &SystemMonitor, freshness: refresh::Freshness) -> refresh::Meta {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let paused = monitor.current_pause().is_some();
+    refresh::meta(&monitor.config.refresh, freshness, paused, monitor.writer.settings().interval_secs, monitor.writer.last_flush(), now)
+}
+
+async fn handle_dashboard_layout(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(dashboard_layout(&monitor)))
+}
+
+fn dashboard_layout(monitor: &SystemMonitor) -> Result<dashboard::StoredLayout, String> {
+    Connection::open(&monitor.db_path)
+        .and_then(|conn| dashboard::load(&conn))
+        .map_err(|e| format!("Failed to load the dashboard layout: {}", e))
+}
+
+// Open to the dashboard itself, like pausing: a layout is a preference, not data
+async fn handle_set_dashboard_layout(layout: dashboard::Layout, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let saved = dashboard::validate(&layout).and_then(|()| {
+        Connection::open(&monitor.db_path)
+            .and_then(|conn| {
+                dashboard::save(&conn, &layout, now)?;
+                dashboard::load(&conn)
+            })
+            .map_err(|e| format!("Failed to save the dashboard layout: {}", e))
+    });
+    Ok(api_result(saved))
+}
+
+async fn handle_panels(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let panels = Connection::open(&monitor.db_path)
+        .and_then(|conn| panels::list(&conn))
+        .map_err(|e| format!("Failed to load panels: {}", e));
+    Ok(api_result(panels))
+}
+
+// Open to the dashboard like its layout; panels only read what `read` can
+async fn handle_save_panel(id: String, spec: panels::PanelSpec, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let saved = panels::validate_id(&id).and_then(|()| panels::validate(&spec)).and_then(|()| {
+        let failed = |e: rusqlite::Error| format!("Failed to save the panel: {}", e);
+        let conn = Connection::open(&monitor.db_path).map_err(failed)?;
+        panels::save(&conn, &id, &spec, now).map_err(failed)??;
+        panels::get(&conn, &id).map_err(failed)
+    });
+    Ok(api_result(saved))
+}
+
+async fn handle_delete_panel(id: String, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let deleted = Connection::open(&monitor.db_path)
+        .and_then(|conn| panels::delete(&conn, &id))
+        .map_err(|e| format!("Failed to delete the panel: {}", e))
+        .and_then(|deleted| if deleted { Ok(id.clone()) } else { Err(format!("No panel with id {}", id)) });
+    Ok(api_result(deleted))
+}
+
+async fn handle_panel_data(id: String, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let data = Connection::open(&monitor.db_path)
+        .and_then(|conn| match panels::get(&conn, &id)? {
+            Some(panel) => panels::evaluate(&conn, &monitor.config.categories, &panel.spec, now).map(Some),
+            None => Ok(None),
+        })
+        .map_err(|e| format!("Failed to evaluate the panel: {}", e))
+        .and_then(|data| data.ok_or_else(|| format!("No panel with id {}", id)));
+    Ok(api_result(data))
+}
+
+// A spec evaluated without storing it, for editors to show as it's written
+async fn handle_preview_panel(spec: panels::PanelSpec, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let data = panels::validate(&spec).and_then(|()| {
+        Connection::open(&monitor.db_path)
+            .and_then(|conn| panels::evaluate(&conn, &monitor.config.categories, &spec, now))
+            .map_err(|e| format!("Failed to evaluate the panel: {}", e))
+    });
+    Ok(api_result(data))
+}
+
+async fn handle_saved_queries(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let saved = Connection::open(&monitor.db_path)
+        .and_then(|conn| queries::list(&conn))
+        .map_err(|e| format!("Failed to load saved queries: {}", e));
+    Ok(api_result(saved))
+}
+
+// Like panels, saved queries only read what `read` can
+async fn handle_save_query(name: String, query: queries::SavedQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let saved = queries::validate_name(&name).and_then(|()| queries::validate(&query, &monitor.config.queries)).and_then(|()| {
+        let failed = |e: rusqlite::Error| format!("Failed to save the query: {}", e);
+        let conn = Connection::open(&monitor.db_path).map_err(failed)?;
+        queries::save(&conn, &name, &query, now).map_err(failed)??;
+        queries::get(&conn, &name).map_err(failed)
+    });
+    Ok(api_result(saved))
+}
+
+async fn handle_delete_query(name: String, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let deleted = Connection::open(&monitor.db_path)
+        .and_then(|conn| queries::delete(&conn, &name))
+        .map_err(|e| format!("Failed to delete the query: {}", e))
+        .and_then(|deleted| if deleted { Ok(name.clone()) } else { Err(format!("No saved query named {}", name)) });
+    Ok(api_result(deleted))
+}
+
+async fn handle_run_query(name: String, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let ran = Connection::open(&monitor.db_path)
+        .and_then(|conn| Ok((queries::get(&conn, &name)?, conn)))
+        .map_err(|e| format!("Failed to run the query: {}", e))
+        .and_then(|(stored, conn)| {
+            let stored = stored.ok_or_else(|| format!("No saved query named {}", name))?;
+            queries::run(&conn, &monitor.config.categories, &monitor.config.queries, &stored, now)
+        });
+    Ok(api_result(ran))
+}
+
+async fn handle_log_tail(_caller: auth::Caller, query: logs::TailQuery) -> Result<impl warp::Reply, warp::Rejection> {
+    let (recent, live) = logs::tail(query.lines.unwrap_or(logs::DEFAULT_LINES).min(logs::CAPACITY));
+    let event = |record: logs::Record| warp::sse::Event::default().id(record.seq.to_string()).event("log").json_data(record);
+    let backlog = futures::stream::iter(recent.into_iter().map(event));
+    let follow = futures::stream::unfold(live, move |mut live| async move {
+        match live.recv().await {
+            Ok(record) => Some((event(record), live)),
+            // A tail that fell behind is told how many records it missed
+            Err(RecvError::Lagged(missed)) => Some((Ok(warp::sse::Event::default().event("lagged").data(missed.to_string())), live)),
+            Err(RecvError::Closed) => None,
+        }
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(backlog.chain(follow))))
+}
+
+async fn handle_health(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({"status": "healthy", "safe_mode": monitor.safe_mode})),
+        error: None,
+        meta: None,
+    }))
+}
+
+async fn handle_schemas() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(Ok::<_, String>(schemas::index())))
+}
+
+async fn handle_schema(file_name: String, conditional: http_cache::Conditional) -> Result<warp::reply::Response, warp::Rejection> {
+    let document = schemas::document(&file_name).ok_or_else(warp::reject::not_found)?;
+    let body = serde_json::to_vec_pretty(&document).unwrap();
+    Ok(http_cache::conditional_response(&conditional, body, "application/schema+json", http_cache::REVALIDATE, None))
+}
+
+async fn handle_metrics(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    if !cfg!(feature = "system-metrics") {
+        return Err(warp::reject::not_found());
+    }
+    Ok(warp::reply::json(&ApiResponse {
+        success: true,
+        data: Some(serde_json::to_value(monitor.metrics_snapshot()).unwrap()),
+        error: None,
+        meta: None,
+    }))
+}
+
+async fn handle_export(
+    file_name: String,
+    query: archive::ArchiveQuery,
+    monitor: Arc<SystemMonitor>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let format = match export::ExportFormat::from_file_name(&file_name) {
+        Some(format) => format,
+        None => return Err(warp::reject::not_found()),
+    };
+
+    let archived = if query.include_archive {
+        archive::load_rows(&monitor.config.archive.dir_for(&monitor.db_path), Timestamp::EPOCH, Timestamp::MAX)
+    } else {
+        Ok(Vec::new())
+    };
+    let body = archived
+        .and_then(|archived| {
+            let stored = export::load_history(&monitor.db_path).map_err(|e| e.to_string())?;
+            Ok(archived.into_iter().map(export::ExportRow::from).chain(stored).collect::<Vec<_>>())
+        })
+        .and_then(|rows| export::render(&rows, format).map_err(|e| e.to_string()));
+    match body {
+        Ok(body) => {
+            let mut response = warp::http::Response::builder()
+                .header("Content-Type", format.content_type())
+                .header("Content-Disposition", format!("attachment; filename=\"{}\"", file_name));
+            if format.is_compressed() {
+                // Keep the API layer from encoding the archive a second time
+                response = response.header("Content-Encoding", "identity");
+            }
+            Ok(response.body(body.into()).unwrap())
+        }
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("Export failed: {}", e)),
+                meta: None,
+            }),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response()),
+    }
+}
+
+async fn handle_transitions(
+    query: transitions::TransitionQuery,
+    format: formats::Format,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let graph = transitions::load_graph(&monitor.db_path, &query, now).map_err(|e| format!("Failed to load transitions: {}", e));
+    Ok(negotiated_with_meta(format, "/edges", graph, Some(refresh_meta(&monitor, refresh::Freshness::Stored))))
+}
+
+async fn handle_app_lifecycle(query: apps::LifecycleQuery, format: formats::Format, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let today = reports::local_date(now.as_secs());
+    let result = Connection::open(&monitor.db_path).and_then(|conn| apps::lifecycle(&conn, today, now, query.stale_days.unwrap_or(apps::STALE_DAYS)));
+    Ok(negotiated(format, "", result.map_err(|e| format!("Failed to load app lifecycles: {}", e))))
+}
+
+async fn handle_blocks(query: blocks::BlockQuery, format: formats::Format, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let until = query.until.unwrap_or(Timestamp::now() + DurationSecs::secs(1));
+    let since = query.since.unwrap_or(until.saturating_sub(ACTIVITY_RETENTION));
+
+    // Refresh first so the block in progress is current
+    let result = monitor.refresh_blocks().and_then(|_| {
+        let conn = Connection::open(&monitor.db_path)?;
+        blocks::load_blocks(&conn, since, until)
+    });
+    Ok(negotiated(format, "", result.map_err(|e| format!("Failed to load work blocks: {}", e))))
+}
+
+async fn handle_languages(query: language::LanguageQuery, format: formats::Format, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let until = query.until.unwrap_or(Timestamp::now() + DurationSecs::secs(1));
+    let since = query.since.unwrap_or(until - DurationSecs::days(7));
+    let totals = Connection::open(&monitor.db_path)
+        .and_then(|mut conn| language::totals(&mut conn, since, until))
+        .map_err(|e| format!("Failed to total languages: {}", e));
+    Ok(negotiated(format, "", totals))
+}
+
+async fn handle_categories(
+    query: DateQuery,
+    trend_query: trends::TrendQuery,
+    format: formats::Format,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let date = match query.date {
+        Some(ref date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date)),
+        None => Ok(chrono::Local::now().date_naive()),
+    };
+    let totals = date.and_then(|date| {
+        let (since, until) = reports::day_bounds(date);
+        let categories = &monitor.config.categories;
+        let conn = Connection::open(&monitor.db_path).map_err(|e| format!("Failed to load category totals: {}", e))?;
+        let totals = match daily_summary::category_totals(&conn, categories, date) {
+            Ok(Some(totals)) => Ok(totals),
+            Ok(None) => assignments::totals(&conn, categories, since.max(0) as u64, until.max(0) as u64),
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("Failed to load category totals: {}", e))?;
+        let mut response = serde_json::json!({
+            "date": date.format("%Y-%m-%d").to_string(),
+            "rules_version": totals.rules_version,
+            "pending": totals.pending,
+            "tree": reports::category_tree(totals.categories.iter().map(|total| (total.category.as_str(), total.seconds))),
+            "categories": totals.categories,
+        });
+        if trend_query.trends {
+            let window_start = trends::window_start(date);
+            let window_since = reports::day_bounds(window_start).0.max(0) as u64;
+            let daily = match daily_summary::daily_totals(&conn, categories, window_start, date.succ_opt().unwrap_or(date)) {
+                Ok(Some(daily)) => Ok(daily),
+                Ok(None) => assignments::daily_totals(&conn, categories, window_since, until.max(0) as u64),
+                Err(e) => Err(e),
+            }
+            .map_err(|e| format!("Failed to load category trends: {}", e))?;
+            response["trends"] = serde_json::to_value(trends::compute(date, &daily)).unwrap();
+        }
+        Ok(response)
+    });
+    Ok(negotiated_with_meta(format, "/categories", totals, Some(refresh_meta(&monitor, refresh::Freshness::Stored))))
+}
+
+async fn handle_tracking(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let pause = monitor.current_pause();
+    Ok(api_result(Ok::<_, String>(serde_json::json!({
+        "paused": pause.is_some(),
+        "remaining_secs": pause.as_ref().and_then(|pause| pause.remaining(Timestamp::now())),
+        "pause": pause,
+        "reasons": monitor.config.pauses.reasons,
+    }))))
+}
+
+async fn handle_pause(query: pauses::PauseQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(pause_tracking(query, &monitor)))
+}
+
+fn pause_tracking(query: pauses::PauseQuery, monitor: &SystemMonitor) -> Result<pauses::Pause, String> {
+    pauses::validate_reason(query.reason).and_then(|reason| {
+        let minutes = pauses::validate_minutes(query.minutes)?;
+        monitor.pause_tracking(reason, minutes).map_err(|e| format!("Failed to pause tracking: {}", e))
+    })
+}
+
+async fn handle_resume(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let pause = monitor.resume_tracking().map_err(|e| format!("Failed to resume tracking: {}", e));
+    Ok(api_result(pause.map(|pause| serde_json::json!({ "resumed": pause }))))
+}
+
+async fn handle_flush_status(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(Ok(serde_json::json!({
+        "settings": monitor.writer.settings(),
+        "pending_events": monitor.pending_count(),
+    }))))
+}
+
+async fn handle_set_flush(_caller: auth::Caller, update: writer::FlushUpdate, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(monitor.writer.update(update)))
+}
+
+// Open to the dashboard like pausing: it only writes sooner what would be written anyway
+async fn handle_flush(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    RuntimeMetrics::increment(&monitor.metrics.flushes);
+    let flushed = monitor.flush_to_database().map_err(|e| {
+        RuntimeMetrics::increment(&monitor.metrics.flush_errors);
+        format!("Failed to flush: {}", e)
+    });
+    Ok(api_result(flushed))
+}
+
+// The first call probes, which runs a few programs
+async fn handle_capabilities(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let capabilities = tokio::task::spawn_blocking(move || monitor.capabilities().to_vec()).await.map_err(|e| e.to_string());
+    Ok(api_result(capabilities))
+}
+
+async fn handle_maintenance_status(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(monitor.maintenance_status().map_err(|e| format!("Failed to load maintenance runs: {}", e))))
+}
+
+// A vacuum or backup of a large database takes a while
+async fn handle_run_maintenance(job: String, _caller: auth::Caller, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let Some(job) = maintenance::Job::parse(&job) else {
+        let names: Vec<&str> = maintenance::Job::ALL.iter().map(|job| job.name()).collect();
+        return Ok(api_result::<()>(Err(format!("Unknown job '{}', expected one of {}", job, names.join(", ")))));
+    };
+    let run = tokio::task::spawn_blocking(move || monitor.run_maintenance(job)).await.unwrap_or_else(|e| Err(e.to_string()));
+    Ok(api_result(run))
+}
+
+async fn handle_gaps(query: DateQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let date = match query.date {
+        Some(ref date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date)),
+        None => Ok(chrono::Local::now().date_naive()),
+    };
+    let gaps = date.and_then(|date| {
+        let (since, until) = reports::day_span(date);
+        let until = until.min(Timestamp::now());
+        let conn = Connection::open(&monitor.db_path).map_err(|e| e.to_string())?;
+        let loaded = sessions::load_sessions(&conn, since, until).and_then(|sessions| Ok((sessions, pauses::list(&conn, since, until)?)));
+        let (sessions, pauses) = loaded.map_err(|e| format!("Failed to load gaps: {}", e))?;
+        let gaps = pauses::gaps(&sessions, &pauses, since, until);
+        let total = |kind: pauses::GapKind| -> DurationSecs { gaps.iter().filter(|gap| gap.kind == kind).map(|gap| gap.end - gap.start).sum() };
+        Ok(serde_json::json!({
+            "date": date.format("%Y-%m-%d").to_string(),
+            "paused_secs": total(pauses::GapKind::Paused),
+            "untracked_secs": total(pauses::GapKind::Untracked),
+            "gaps": gaps,
+        }))
+    });
+    Ok(api_result(gaps))
+}
+
+async fn handle_distractions(query: DateQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let date = match query.date {
+        Some(ref date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date)),
+        None => Ok(chrono::Local::now().date_naive()),
+    };
+    let report = date.and_then(|date| {
+        distractions::daily_report(&monitor.db_path, date, &monitor.config.categories)
+            .map_err(|e| format!("Failed to detect distraction chains: {}", e))
+    });
+    let response = match report {
+        Ok(report) => ApiResponse {
+            success: true,
+            data: Some(serde_json::to_value(report).unwrap()),
+            error: None,
+            meta: None,
+        },
+        Err(e) => ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e),
+            meta: None,
+        },
+    };
+    Ok(warp::reply::json(&response))
+}
+
+async fn handle_forecast(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(forecast_today(&monitor)))
+}
+
+fn forecast_today(monitor: &SystemMonitor) -> Result<forecast::Forecast, String> {
+    let now = Timestamp::now();
+    let date = reports::local_date(now.as_secs());
+    Connection::open(&monitor.db_path)
+        .and_then(|conn| forecast::today(&conn, &monitor.config.categories, &monitor.config.forecast, date, now))
+        .map_err(|e| format!("Failed to forecast today: {}", e))
+}
+
+async fn handle_timeline_window(query: timeline::TimelineQuery, format: formats::Format, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let center = query.center.unwrap_or_else(Timestamp::now).as_secs();
+    let zoom = query.zoom.unwrap_or(timeline::DEFAULT_ZOOM);
+    let window = Connection::open(&monitor.db_path)
+        .map_err(|e| e.to_string())
+        .and_then(|conn| timeline::window(&conn, center, zoom));
+    Ok(negotiated(format, "/buckets", window))
+}
+
+async fn handle_weekly_goals(query: DateQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(weekly_goals(query, &monitor)))
+}
+
+fn weekly_goals(query: DateQuery, monitor: &SystemMonitor) -> Result<goals::WeekProgress, String> {
+    let today = chrono::Local::now().date_naive();
+    let date = match query.date {
+        Some(ref date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date)),
+        None => Ok(today),
+    };
+    date.and_then(|date| {
+        Connection::open(&monitor.db_path)
+            .and_then(|conn| goals::week(&conn, &monitor.config.categories, date, today))
+            .map_err(|e| format!("Failed to load weekly goals: {}", e))
+    })
+}
+
+async fn handle_v1_dashboard(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let meta = api::Meta::from(refresh_meta(&monitor, refresh::Freshness::Live));
+    Ok(api_result_with_meta(Ok::<_, String>(api::Dashboard::from(monitor.get_dashboard_data())), Some(meta)))
+}
+
+async fn handle_v1_dashboard_layout(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(dashboard_layout(&monitor).map(api::Layout::from)))
+}
+
+async fn handle_v1_tracking(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let tracking = api::Tracking::new(monitor.current_pause(), monitor.config.pauses.reasons.clone(), Timestamp::now());
+    Ok(api_result(Ok::<_, String>(tracking)))
+}
+
+async fn handle_v1_pause(query: pauses::PauseQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(pause_tracking(query, &monitor).map(api::Pause::from)))
+}
+
+async fn handle_v1_resume(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let pause = monitor.resume_tracking().map_err(|e| format!("Failed to resume tracking: {}", e));
+    Ok(api_result(pause.map(|pause| serde_json::json!({ "resumed": pause.map(api::Pause::from) }))))
+}
+
+async fn handle_v1_forecast(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(forecast_today(&monitor).map(api::Forecast::from)))
+}
+
+async fn handle_v1_weekly_goals(query: DateQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(weekly_goals(query, &monitor).map(api::Week::from)))
+}
+
+async fn handle_set_weekly_goals(
+    _caller: auth::Caller,
+    request: goals::WeeklyGoals,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let saved = goals::validate(request.goals).and_then(|goals| {
+        let mut conn = Connection::open(&monitor.db_path).map_err(|e| e.to_string())?;
+        goals::replace(&mut conn, &goals, now).map_err(|e| format!("Failed to save weekly goals: {}", e))?;
+        Ok(serde_json::json!({ "goals": goals }))
+    });
+    Ok(api_result(saved))
+}
+
+// Unwrapped, so sensor templates can read `value_json.current_app`
+async fn handle_home_assistant(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(match monitor.home_assistant_state() {
+        Ok(state) => warp::reply::json(&state).into_response(),
+        Err(e) => api_result::<()>(Err(format!("Failed to read sensor state: {}", e))),
+    })
+}
+
+async fn handle_home_assistant_config(host: Option<String>) -> Result<impl warp::Reply, warp::Rejection> {
+    let base_url = match host {
+        Some(host) => format!("http://{}", host),
+        None => format!("http://localhost:{}", WEB_PORT),
+    };
+    Ok(warp::reply::with_header(home_assistant::rest_config(&base_url), "Content-Type", "text/yaml; charset=utf-8"))
+}
+
+const GRAFANA_DEFAULT_RANGE_SECS: u64 = 7 * 86400;
+
+#[derive(Debug, Deserialize)]
+struct GrafanaRange {
+    from: Option<u64>, // Unix milliseconds, as in Grafana's `${__from}` and `${__to}`
+    to: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GrafanaQuery {
+    range: GrafanaQueryRange,
+    targets: Vec<GrafanaTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GrafanaQueryRange {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GrafanaTarget {
+    #[serde(default)]
+    target: String,
+}
+
+impl validation::Validate for GrafanaQuery {}
+
+// The JSON plugin lists labelled options where SimpleJSON's `/search` lists names
+fn handle_grafana_metrics() -> warp::reply::Json {
+    let metrics: Vec<serde_json::Value> =
+        grafana::TABLES.iter().map(|table| serde_json::json!({ "label": table, "value": table })).collect();
+    warp::reply::json(&metrics)
+}
+
+async fn handle_grafana_rows(
+    table: String,
+    range: GrafanaRange,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !grafana::TABLES.contains(&table.as_str()) {
+        return Ok(api_result::<()>(Err(format!("Unknown table '{}', expected one of {}", table, grafana::TABLES.join(", ")))));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let to = range.to.map_or(now + 1, |to| to / 1000);
+    let from = range.from.map_or(to.saturating_sub(GRAFANA_DEFAULT_RANGE_SECS), |from| from / 1000);
+    Ok(match Connection::open(&monitor.db_path).and_then(|conn| grafana::rows(&conn, &table, from, to)) {
+        Ok(rows) => warp::reply::json(&rows).into_response(),
+        Err(e) => api_result::<()>(Err(format!("Failed to load {}: {}", table, e))),
+    })
+}
+
+async fn handle_grafana_query(query: GrafanaQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let parse = |time: &str| {
+        chrono::DateTime::parse_from_rfc3339(time)
+            .map(|time| time.timestamp().max(0) as u64)
+            .map_err(|_| format!("Invalid time '{}', expected RFC 3339", time))
+    };
+    let series = parse(&query.range.from).and_then(|from| {
+        let to = parse(&query.range.to)?;
+        let conn = Connection::open(&monitor.db_path).map_err(|e| e.to_string())?;
+        let mut series = Vec::new();
+        for target in query.targets.iter().filter(|target| grafana::TABLES.contains(&target.target.as_str())) {
+            let rows = grafana::rows(&conn, &target.target, from, to).map_err(|e| format!("Failed to load {}: {}", target.target, e))?;
+            series.extend(grafana::series(rows));
+        }
+        Ok(series)
+    });
+    Ok(match series {
+        Ok(series) => warp::reply::json(&series).into_response(),
+        Err(e) => api_result::<()>(Err(e)),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct TestFireQuery {
+    channel: String,
+    event: Option<EventKind>,
+}
+
+async fn handle_test_notification(
+    _caller: auth::Caller,
+    query: TestFireQuery,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let sent = match query.event.unwrap_or(EventKind::Info) {
+        EventKind::Unknown => Err("Unknown event type".to_string()),
+        kind => monitor.notifier.test_fire(&query.channel, kind, now),
+    };
+    Ok(api_result(sent.map(|notification| serde_json::json!({ "channel": query.channel, "sent": notification }))))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CreateTokenRequest {
+    name: String,
+    scopes: Vec<Scope>,
+    expires_at: Option<Timestamp>, // Never expires when missing
+}
+
+// `expires_at: null` clears the expiry, a missing field leaves it unchanged
+#[derive(Debug, Clone, Deserialize)]
+struct UpdateTokenRequest {
+    name: Option<String>,
+    scopes: Option<Vec<Scope>>,
+    #[serde(default, deserialize_with = "some_if_present")]
+    expires_at: Option<Option<Timestamp>>,
+}
+
+impl validation::Validate for CreateTokenRequest {
+    fn validate(&self, now: Timestamp, violations: &mut validation::Violations) {
+        if self.name.trim().is_empty() {
+            violations.push("name", "required", "a token needs a name");
+        }
+        if self.scopes.is_empty() {
+            violations.push("scopes", "required", "a token needs at least one scope");
+        }
+        violations.future("expires_at", self.expires_at, now);
+    }
+}
+
+impl validation::Validate for UpdateTokenRequest {
+    fn validate(&self, now: Timestamp, violations: &mut validation::Violations) {
+        if self.name.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            violations.push("name", "required", "a token needs a name");
+        }
+        if self.scopes.as_ref().is_some_and(|scopes| scopes.is_empty()) {
+            violations.push("scopes", "required", "a token needs at least one scope");
+        }
+        violations.future("expires_at", self.expires_at.flatten(), now);
+    }
+}
+
+fn some_if_present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+// A result in the negotiated format, varying by `Accept`; errors stay JSON
+fn negotiated<T: Serialize>(format: formats::Format, table: &str, result: Result<T, String>) -> warp::reply::Response {
+    negotiated_with_meta(format, table, result, None::<()>)
+}
+
+// The same with `meta` in the JSON envelope; the other formats have no place for it
+fn negotiated_with_meta<T: Serialize, M: Serialize>(format: formats::Format, table: &str, result: Result<T, String>, meta: Option<M>) -> warp::reply::Response {
+    let mut response = match (format, result) {
+        (formats::Format::Json, result) | (_, result @ Err(_)) => api_result_with_meta(result, meta),
+        (format, Ok(data)) => warp::http::Response::builder()
+            .header("Content-Type", format.content_type())
+            .body(formats::render(format, &serde_json::to_value(data).unwrap(), table).into())
+            .unwrap(),
+    };
+    response.headers_mut().append(warp::http::header::VARY, warp::http::HeaderValue::from_static("accept"));
+    response
+}
+
+fn api_result<T: Serialize>(result: Result<T, String>) -> warp::reply::Response {
+    api_result_with_meta(result, None::<()>)
+}
+
+// `meta` goes with a success only
+fn api_result_with_meta<T: Serialize, M: Serialize>(result: Result<T, String>, meta: Option<M>) -> warp::reply::Response {
+    match result {
+        Ok(data) => warp::reply::json(&ApiResponse {
+            success: true,
+            data: Some(serde_json::to_value(data).unwrap()),
+            error: None,
+            meta: meta.map(|meta| serde_json::to_value(meta).unwrap()),
+        })
+        .into_response(),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&ApiResponse {
+                success: false,
+                data: None,
+                error: Some(e),
+                meta: None,
+            }),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+        .into_response(),
+    }
+}
+
+async fn handle_list_tokens(_caller: auth::Caller, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let tokens = Connection::open(&monitor.db_path)
+        .and_then(|conn| auth::list_tokens(&conn))
+        .map_err(|e| format!("Failed to list tokens: {}", e));
+    Ok(api_result(tokens))
+}
+
+async fn handle_create_token(
+    _caller: auth::Caller,
+    request: CreateTokenRequest,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let created = Connection::open(&monitor.db_path)
+        .and_then(|conn| auth::create_token(&conn, request.name.trim(), &request.scopes, request.expires_at))
+        .map(|(token, secret)| serde_json::json!({ "token": token, "secret": secret }))
+        .map_err(|e| format!("Failed to create token: {}", e));
+    Ok(api_result(created))
+}
+
+async fn handle_update_token(
+    id: String,
+    _caller: auth::Caller,
+    request: UpdateTokenRequest,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let updated = Connection::open(&monitor.db_path)
+        .and_then(|conn| {
+            auth::update_token(&conn, &id, request.name.as_deref(), request.scopes.as_deref(), request.expires_at)
+        })
+        .map_err(|e| format!("Failed to update token: {}", e))
+        .and_then(|token| token.ok_or_else(|| format!("No token with id {}", id)));
+    Ok(api_result(updated))
+}
+
+async fn handle_revoke_token(id: String, _caller: auth::Caller, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let revoked = Connection::open(&monitor.db_path)
+        .and_then(|conn| auth::revoke_token(&conn, &id))
+        .map_err(|e| format!("Failed to revoke token: {}", e))
+        .and_then(|revoked| if revoked { Ok(id.clone()) } else { Err(format!("No token with id {}", id)) });
+    Ok(api_result(revoked))
+}
+
+// The collector behind a request: the id it sends, or its token's name
+fn device_id(device: Option<&str>, caller: &auth::Caller) -> Result<String, String> {
+    match (device, caller) {
+        (Some(device), _) => devices::validate_id(device).map(str::to_string),
+        (None, auth::Caller::Token(token)) => Ok(token.name.clone()),
+        (None, auth::Caller::Local) => Ok("local".to_string()),
+    }
+}
+
+// Charges a request to its ingest quotas
+fn charge_quota(
+    monitor: &SystemMonitor,
+    caller: &auth::Caller,
+    source: Option<&str>,
+    events: usize,
+    body_bytes: u64,
+) -> quotas::Decision {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let name = match caller {
+        auth::Caller::Token(token) => token.name.as_str(),
+        auth::Caller::Local => "local",
+    };
+    let decision = monitor.quotas.charge(&monitor.config.ingest.quotas, name, source, events as u64, body_bytes, now);
+    if let (Some(exceeded), true) = (&decision.exceeded, monitor.debug_mode) {
+        logs::info!("Quota: refused {} events from {}: {}", events, name, exceeded);
+    }
+    decision
+}
+
+fn quota_refusal(decision: &quotas::Decision) -> warp::reply::Response {
+    let exceeded = decision.exceeded.clone().unwrap_or_default();
+    let mut response = with_quota_headers(api_result::<()>(Err(format!("Quota exceeded: {}", exceeded))), decision);
+    *response.status_mut() = warp::http::StatusCode::TOO_MANY_REQUESTS;
+    response.headers_mut().insert("Retry-After", decision.retry_after_secs.into());
+    response
+}
+
+fn with_quota_headers(mut response: warp::reply::Response, decision: &quotas::Decision) -> warp::reply::Response {
+    let headers = response.headers_mut();
+    for (kind, status) in [("Events", decision.events), ("Bytes", decision.bytes)] {
+        if let Some(status) = status {
+            for (name, value) in [("Limit", status.limit), ("Remaining", status.remaining), ("Reset", status.reset_secs)] {
+                let name = warp::http::header::HeaderName::try_from(format!("X-Quota-{}-{}", kind, name)).unwrap();
+                headers.insert(name, value.into());
+            }
+        }
+    }
+    response
+}
+
+async fn handle_ingest_batch(
+    caller: auth::Caller,
+    body_bytes: u64,
+    batch: ingest::IngestBatch,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let quota = charge_quota(&monitor, &caller, Some(&batch.source), batch.events.len(), body_bytes);
+    if quota.exceeded.is_some() {
+        return Ok(quota_refusal(&quota));
+    }
+    let device = match device_id(batch.device.as_deref(), &caller) {
+        Ok(device) => device,
+        Err(e) => return Ok(api_result::<()>(Err(e))),
+    };
+    let now = Timestamp::now();
+    let timer = monitor.telemetry.start("sysmonitor.db.ingest");
+    let result = Connection::open(&monitor.db_path).and_then(|mut conn| {
+        let config = &monitor.config;
+        let result = if config.team.aggregate_only {
+            let user = team::user_of(&caller);
+            team::ingest_batch(&mut conn, &batch, &config.ingest, &config.categories, &user, now)
+        } else {
+            ingest::ingest_batch(&mut conn, &batch, &config.ingest, now)
+        };
+        // A failed batch still shows the device is alive
+        let accepted = result.as_ref().map(|result| result.accepted as u64).unwrap_or(0);
+        let skew = batch.sent_at.map(|sent_at| ingest::skew_secs(sent_at, now));
+        devices::check_in(&conn, &device, accepted, skew, now)?;
+        result
+    });
+    let error = result.as_ref().err().map(|e| e.to_string());
+    monitor.telemetry.finish(timer, vec![("sysmonitor.events", batch.events.len().into())], error);
+    Ok(with_quota_headers(api_result(result.map_err(|e| format!("Ingest failed: {}", e))), &quota))
+}
+
+#[derive(Deserialize)]
+struct VaultUpload {
+    device: Option<String>,
+    blobs: Vec<vault::SealedBlob>,
+}
+
+impl validation::Validate for VaultUpload {
+    fn validate(&self, _now: Timestamp, violations: &mut validation::Violations) {
+        if let Some(Err(e)) = self.device.as_deref().map(devices::validate_id) {
+            violations.push("device", "invalid", e);
+        }
+        if self.blobs.len() > vault::MAX_BLOBS_PER_UPLOAD {
+            violations.push("blobs", "too_many", format!("at most {} blobs per upload", vault::MAX_BLOBS_PER_UPLOAD));
+        }
+        for (index, blob) in self.blobs.iter().enumerate() {
+            if let Err(e) = blob.validate() {
+                violations.push(format!("blobs[{}]", index), "invalid", e);
+            }
+        }
+    }
+}
+
+async fn handle_vault_upload(
+    caller: auth::Caller,
+    body_bytes: u64,
+    upload: VaultUpload,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if monitor.config.team.aggregate_only {
+        return Ok(api_result::<()>(Err("This server only accepts aggregates; encrypted blobs are refused".to_string())));
+    }
+    let device = match device_id(upload.device.as_deref(), &caller) {
+        Ok(device) => device,
+        Err(e) => return Ok(api_result::<()>(Err(e))),
+    };
+    // Each blob counts as one event
+    let quota = charge_quota(&monitor, &caller, None, upload.blobs.len(), body_bytes);
+    if quota.exceeded.is_some() {
+        return Ok(quota_refusal(&quota));
+    }
+    let now = Timestamp::now();
+    let stored = Connection::open(&monitor.db_path).and_then(|mut conn| {
+        let tx = conn.transaction()?;
+        let ids = upload
+            .blobs
+            .iter()
+            .map(|blob| vault::store(&tx, &device, blob, now))
+            .collect::<SqlResult<Vec<i64>>>()?;
+        // Blobs are opaque, so there are no events to count
+        devices::check_in(&tx, &device, 0, None, now)?;
+        tx.commit()?;
+        Ok(ids)
+    });
+    let stored = stored
+        .map(|ids| serde_json::json!({ "device": device, "stored": ids.len(), "ids": ids }))
+        .map_err(|e| format!("Failed to store blobs: {}", e));
+    Ok(with_quota_headers(api_result(stored), &quota))
+}
+
+async fn handle_vault_list(query: vault::VaultQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let blobs = Connection::open(&monitor.db_path)
+        .and_then(|conn| vault::list(&conn, &query))
+        .map_err(|e| format!("Failed to list blobs: {}", e));
+    Ok(api_result(blobs))
+}
+
+async fn handle_devices(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let devices = Connection::open(&monitor.db_path)
+        .and_then(|conn| devices::list(&conn, &monitor.config.devices, now))
+        .map_err(|e| format!("Failed to list devices: {}", e));
+    Ok(api_result(devices))
+}
+
+async fn handle_heartbeat(
+    caller: auth::Caller,
+    heartbeat: devices::Heartbeat,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let skew = heartbeat.sent_at.map(|sent_at| ingest::skew_secs(sent_at, now));
+    let result = device_id(heartbeat.device.as_deref(), &caller).and_then(|device| {
+        Connection::open(&monitor.db_path)
+            .and_then(|conn| devices::check_in(&conn, &device, 0, skew, now))
+            .map(|_| serde_json::json!({ "device": device, "server_time": now, "clock_skew": skew }))
+            .map_err(|e| format!("Failed to record heartbeat: {}", e))
+    });
+    Ok(api_result(result))
+}
+
+async fn handle_ingest_conflicts(query: overlap::ConflictQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let report = Connection::open(&monitor.db_path)
+        .and_then(|conn| overlap::load_conflicts(&conn, &query))
+        .map_err(|e| format!("Failed to load conflicts: {}", e));
+    Ok(api_result(report))
+}
+
+async fn handle_audit(
+    _caller: auth::Caller,
+    query: audit::AuditQuery,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let entries = Connection::open(&monitor.db_path)
+        .and_then(|conn| audit::load(&conn, &query))
+        .map_err(|e| format!("Failed to read audit log: {}", e));
+    Ok(api_result(entries))
+}
+
+#[derive(Debug, Deserialize)]
+struct RulesQuery {
+    format: Option<String>, // csv or json (default)
+    #[serde(default)]
+    apply: bool, // Import only previews unless set
+}
+
+fn rules_format(query: &RulesQuery) -> Result<rules::RuleFormat, String> {
+    query.format.as_deref().map(rules::RuleFormat::parse).unwrap_or(Ok(rules::RuleFormat::Json))
+}
+
+async fn handle_rules_export(query: RulesQuery, monitor: Arc<SystemMonitor>) -> Result<warp::reply::Response, warp::Rejection> {
+    let format = match rules_format(&query) {
+        Ok(format) => format,
+        Err(e) => return Ok(api_result::<()>(Err(e))),
+    };
+    let body = rules::RuleSet::from_config(&monitor.config).render(format);
+    Ok(warp::http::Response::builder()
+        .header("Content-Type", format.content_type())
+        .header("Content-Disposition", format!("attachment; filename=\"sysmonitor-rules.{}\"", format.extension()))
+        .body(body.into())
+        .unwrap())
+}
+
+async fn handle_rules_import(
+    _caller: auth::Caller,
+    query: RulesQuery,
+    body: warp::hyper::body::Bytes,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let report = rules_format(&query).and_then(|format| {
+        let text = std::str::from_utf8(&body).map_err(|_| "The bundle must be UTF-8".to_string())?;
+        rules::import(&monitor.config, &monitor.config_path, text, format, query.apply)
+    });
+    Ok(api_result(report))
+}
+
+async fn handle_rules_test(
+    query: archive::ArchiveQuery,
+    test: rules::RuleTest,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let parse = |date: &str| {
+        chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date))
+    };
+    let range = parse(&test.from).and_then(|from| Ok((from, parse(&test.to)?))).and_then(|(from, to)| {
+        if to < from || (to - from).num_days() > rules::MAX_TEST_DAYS {
+            return Err(format!("to must be on or after from, at most {} days later", rules::MAX_TEST_DAYS));
+        }
+        Ok((Timestamp::from_secs(reports::day_bounds(from).0.max(0) as u64), Timestamp::from_secs(reports::day_bounds(to).1.max(0) as u64)))
+    });
+    let report = range.and_then(|(since, until)| {
+        let dir = monitor.config.archive.dir_for(&monitor.db_path);
+        Connection::open(&monitor.db_path)
+            .map_err(|e| e.to_string())
+            .and_then(|conn| archive::load_sessions(&conn, &dir, since, until, query.include_archive))
+            .map_err(|e| format!("Failed to load sessions: {}", e))
+            .map(|sessions| rules::test_rules(&sessions, &rules::RuleSet::from_config(&monitor.config), &test.rules))
+    });
+    Ok(api_result(report))
+}
+
+async fn handle_bulk_label(
+    _caller: auth::Caller,
+    request: labels::BulkLabel,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let dry_run = request.dry_run;
+    let change = labels::validate(request).and_then(|(selection, category)| {
+        let mut conn = Connection::open(&monitor.db_path).map_err(|e| e.to_string())?;
+        labels::relabel(&mut conn, &selection, &category, dry_run, now).map_err(|e| format!("Failed to relabel sessions: {}", e))
+    });
+    Ok(api_result(change))
+}
+
+async fn handle_label_changes(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let changes = Connection::open(&monitor.db_path)
+        .and_then(|conn| labels::list(&conn, labels::LISTED_CHANGES))
+        .map_err(|e| format!("Failed to load label changes: {}", e));
+    Ok(api_result(changes))
+}
+
+async fn handle_undo_labels(id: i64, _caller: auth::Caller, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let undone = Connection::open(&monitor.db_path)
+        .and_then(|mut conn| labels::undo(&mut conn, id, now))
+        .map_err(|e| format!("Failed to undo label change: {}", e))
+        .and_then(|undone| undone.ok_or_else(|| format!("Label change {} doesn't exist or was already undone", id)));
+    Ok(api_result(undone))
+}
+
+async fn handle_integrations(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let integrations = Connection::open(&monitor.db_path)
+        .and_then(|conn| {
+            let breakers = monitor.outbound.status(&conn, now)?.integrations;
+            integrations::summarize(&conn, &monitor.config, &breakers, now)
+        })
+        .map_err(|e| format!("Failed to load integrations: {}", e));
+    Ok(api_result(integrations))
+}
+
+async fn handle_integrations_status(monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = Timestamp::now();
+    let status = Connection::open(&monitor.db_path)
+        .and_then(|conn| monitor.outbound.status(&conn, now))
+        .map_err(|e| format!("Failed to load integration status: {}", e));
+    Ok(api_result(status))
+}
+
+async fn handle_live_activity(query: live::LiveQuery, monitor: Arc<SystemMonitor>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(api_result(Ok::<_, String>(live::page(monitor.live_activities(), &query))))
+}
+
+async fn handle_team_summary(
+    _caller: auth::Caller,
+    query: team::TeamQuery,
+    monitor: Arc<SystemMonitor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let to = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let from = to.saturating_sub(query.days.unwrap_or(team::DEFAULT_DAYS).clamp(1, 365) * 86400);
+    let config = &monitor.config;
+    let summary = Connection::open(&monitor.db_path)
+        .and_then(|conn| team::load(&conn, from, to))
+        .map_err(|e| format!("Failed to read team aggregates: {}", e))
+        .and_then(|rows| {
+            team::summarize(&rows, &config.team, &config.categories, from, to).ok_or_else(|| {
+                format!(
+                    "Fewer than {} users reported in this period; team figures are withheld",
+                    config.team.min_group_size
+                )
+            })
+        });
+    Ok(api_result(summary))
+}
+
+// Auth failures become the usual JSON error shape; anything else keeps warp's handling
+async fn handle_rejection(rejection: warp::Rejection) -> Result<warp::reply::Response, warp::Rejection> {
+    if let Some(invalid) = rejection.find::<validation::Invalid>() {
+        return Ok(invalid.response());
+    }
+    if let Some(invalid) = validation::from_rejection(&rejection) {
+        return Ok(invalid.response());
+    }
+    if let Some(error) = rejection.find::<auth::AuthError>() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&ApiResponse {
+                success: false,
+                data: None,
+                error: Some(error.message.clone()),
+                meta: None,
+            }),
+            error.status,
+        )
+        .into_response());
+    }
+    Err(rejection)
+}
+
+async fn handle_monthly_report(
+    month: String,
+    conditional: http_cache::Conditional,
+    monitor: Arc<SystemMonitor>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let period = reports::Period::parse_month(&month).ok_or_else(|| format!("Invalid month '{}', expected YYYY-MM", month));
+    Ok(period_response(period, &conditional, &monitor))
+}
+
+async fn handle_yearly_report(
+    year: String,
+    conditional: http_cache::Conditional,
+    monitor: Arc<SystemMonitor>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let period = reports::Period::parse_year(&year).ok_or_else(|| format!("Invalid year '{}', expected YYYY", year));
+    Ok(period_response(period, &conditional, &monitor))
+}
+
+async fn handle_weekly_pdf(query: DateQuery, monitor: Arc<SystemMonitor>) -> Result<warp::reply::Response, warp::Rejection> {
+    let date = match query.date {
+        Some(ref date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date)),
+        None => Ok(chrono::Local::now().date_naive()),
+    };
+    match date.and_then(|date| monitor.weekly_pdf(date).map_err(|e| format!("Report failed: {}", e))) {
+        Ok((file_name, pdf)) => Ok(warp::http::Response::builder()
+            .header("Content-Type", "application/pdf")
+            .header("Content-Disposition", format!("attachment; filename=\"{}\"", file_name))
+            .body(pdf.into())
+            .unwrap()),
+        Err(e) => Ok(api_result::<()>(Err(e))),
+    }
+}
+
+fn period_response(
+    period: Result<reports::Period, String>,
+    conditional: &http_cache::Conditional,
+    monitor: &SystemMonitor,
+) -> warp::reply::Response {
+    let report = period.and_then(|period| {
+        reports::period_report(&monitor.db_path, period).map_err(|e| format!("Report failed: {}", e))
+    });
+    match report {
+        // Late uploads still change the last days of a period, so always revalidate
+        Ok(report) => {
+            let body = serde_json::to_vec(&ApiResponse {
+                success: true,
+                data: Some(serde_json::to_value(&report).unwrap()),
+                error: None,
+                meta: None,
+            })
+            .unwrap();
+            http_cache::conditional_response(conditional, body, "application/json", http_cache::REVALIDATE, None)
+        }
+        Err(e) => api_result::<()>(Err(e)),
+    }
+}
+
+async fn handle_daily_report(
+    date: String,
+    trend_query: trends::TrendQuery,
+    conditional: http_cache::Conditional,
+    format: formats::Format,
+    monitor: Arc<SystemMonitor>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let date = match chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+        Ok(date) => date,
+        Err(_) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(format!("Invalid date '{}', expected YYYY-MM-DD", date)),
+                    meta: None,
+                }),
+                warp::http::StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+
+    let report = reports::daily_report(&monitor.db_path, date).and_then(|mut report| {
+        if trend_query.trends {
+            report.trends = Some(reports::app_trends(&monitor.db_path, date)?);
+        }
+        Ok(report)
+    });
+    let response = match report {
+        Ok(report) => ApiResponse {
+            success: true,
+            data: Some(serde_json::to_value(&report).unwrap()),
+            error: None,
+            meta: None,
+        },
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(format!("Report failed: {}", e)),
+                    meta: None,
+                }),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response())
+        }
+    };
+
+    let body = match (format, &response.data) {
+        (formats::Format::Json, _) | (_, None) => serde_json::to_vec(&response).unwrap(),
+        (format, Some(data)) => formats::render(format, data, "/apps"),
+    };
+    let mut response = if reports::is_finalized(date) {
+        let (_, day_end) = reports::day_bounds(date);
+        http_cache::conditional_response(&conditional, body, format.content_type(), http_cache::IMMUTABLE, Some(day_end))
+    } else {
+        // Today's report still grows with every flush
+        http_cache::conditional_response(&conditional, body, format.content_type(), http_cache::REVALIDATE, None)
+    };
+    response.headers_mut().append(warp::http::header::VARY, warp::http::HeaderValue::from_static("accept"));
+    Ok(response)
+}
@@ -0,0 +1,247 @@
+// Small self-contained tiles for embedding elsewhere: Notion, a start page, a
+// desktop widget host.
+//
+// Each renders as a minimal HTML page that reloads itself, or with
+// `?format=svg` as a standalone image. Colors and size come from the query,
+// so a tile can match whatever it sits in; colors must be hex (`0f172a`,
+// with or without `#`) or `transparent`, which keeps the query out of the markup.
+
+use serde::Deserialize;
+
+const DEFAULT_REFRESH_SECS: u64 = 60;
+const MIN_REFRESH_SECS: u64 = 5;
+const MAX_TOP_APPS: usize = 5;
+const LABEL_CHARS: usize = 28;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WidgetQuery {
+    pub theme: Option<String>, // `dark` (default) or `light`
+    pub background: Option<String>,
+    pub color: Option<String>, // Text
+    pub accent: Option<String>, // Bars and highlights
+    pub font_size: Option<u32>, // Pixels
+    pub refresh: Option<u64>, // Seconds between reloads; 0 never reloads
+    pub format: Option<String>, // `html` (default) or `svg`
+    pub show_title: Option<bool>, // The window title under the current app
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    background: String,
+    color: String,
+    muted: String,
+    accent: String,
+    font_size: u32,
+    refresh: u64,
+    svg: bool,
+}
+
+fn color(name: &str, value: Option<&str>, default: &str) -> Result<String, String> {
+    let Some(value) = value else {
+        return Ok(default.to_string());
+    };
+    if value == "transparent" {
+        return Ok(value.to_string());
+    }
+    let hex = value.trim_start_matches('#');
+    if matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        Ok(format!("#{}", hex))
+    } else {
+        Err(format!("{} must be a hex color or 'transparent', got '{}'", name, value))
+    }
+}
+
+impl WidgetQuery {
+    pub fn theme(&self) -> Result<Theme, String> {
+        let (background, color_default, muted) = match self.theme.as_deref().unwrap_or("dark") {
+            "dark" => ("#0f172a", "#e2e8f0", "#94a3b8"),
+            "light" => ("#ffffff", "#0f172a", "#64748b"),
+            other => return Err(format!("theme must be 'dark' or 'light', got '{}'", other)),
+        };
+        let svg = match self.format.as_deref().unwrap_or("html") {
+            "html" => false,
+            "svg" => true,
+            other => return Err(format!("format must be 'html' or 'svg', got '{}'", other)),
+        };
+        Ok(Theme {
+            background: color("background", self.background.as_deref(), background)?,
+            color: color("color", self.color.as_deref(), color_default)?,
+            muted: muted.to_string(),
+            accent: color("accent", self.accent.as_deref(), "#3b82f6")?,
+            font_size: self.font_size.unwrap_or(16).clamp(8, 64),
+            refresh: match self.refresh.unwrap_or(DEFAULT_REFRESH_SECS) {
+                0 => 0,
+                secs => secs.max(MIN_REFRESH_SECS),
+            },
+            svg,
+        })
+    }
+}
+
+impl Theme {
+    pub fn content_type(&self) -> &'static str {
+        if self.svg {
+            "image/svg+xml; charset=utf-8"
+        } else {
+            "text/html; charset=utf-8"
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() <= LABEL_CHARS {
+        return text.to_string();
+    }
+    format!("{}…", text.chars().take(LABEL_CHARS - 1).collect::<String>())
+}
+
+fn duration(seconds: u64) -> String {
+    match (seconds / 3600, seconds % 3600 / 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h {:02}m", hours, minutes),
+    }
+}
+
+// One line of text in a tile
+struct Line {
+    text: String,
+    scale: f32, // Of the theme's font size
+    muted: bool,
+    bar: Option<f64>, // Fraction of a full-width bar drawn under the text
+}
+
+fn line(text: impl Into<String>, scale: f32, muted: bool) -> Line {
+    Line { text: text.into(), scale, muted, bar: None }
+}
+
+fn render(theme: &Theme, title: &str, lines: &[Line]) -> String {
+    let size = theme.font_size as f32;
+    let width = (size * 16.0).round();
+    if theme.svg {
+        let mut y = size * 0.5;
+        let mut body = String::new();
+        for line in lines {
+            y += size * line.scale * 1.3;
+            let fill = if line.muted { &theme.muted } else { &theme.color };
+            body.push_str(&format!(
+                "<text x=\"{:.0}\" y=\"{:.1}\" font-size=\"{:.1}\" fill=\"{}\">{}</text>",
+                size * 0.75,
+                y,
+                size * line.scale,
+                fill,
+                escape(&line.text)
+            ));
+            if let Some(fraction) = line.bar {
+                let full = width - size * 1.5;
+                body.push_str(&format!(
+                    "<rect x=\"{:.0}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"2\" fill=\"{}\"/>",
+                    size * 0.75,
+                    y + size * 0.25,
+                    (full * fraction.clamp(0.0, 1.0) as f32).max(2.0),
+                    size * 0.3,
+                    theme.accent
+                ));
+                y += size * 0.5;
+            }
+        }
+        return format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.0}\" height=\"{h:.0}\" viewBox=\"0 0 {w:.0} {h:.0}\" font-family=\"system-ui, sans-serif\"><title>{title}</title><rect width=\"100%\" height=\"100%\" rx=\"8\" fill=\"{bg}\"/>{body}</svg>",
+            w = width,
+            h = y + size * 0.75,
+            title = escape(title),
+            bg = theme.background,
+            body = body
+        );
+    }
+
+    let refresh = match theme.refresh {
+        0 => String::new(),
+        secs => format!("<meta http-equiv=\"refresh\" content=\"{}\">", secs),
+    };
+    let body: String = lines
+        .iter()
+        .map(|line| {
+            let bar = line.bar.map_or(String::new(), |fraction| format!("<div class=\"bar\" style=\"width:{:.1}%\"></div>", fraction.clamp(0.0, 1.0) * 100.0));
+            let class = if line.muted { " class=\"muted\"" } else { "" };
+            format!("<div{} style=\"font-size:{:.2}em\">{}</div>{}", class, line.scale, escape(&line.text), bar)
+        })
+        .collect();
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">{refresh}<title>{title}</title><style>\
+         body{{margin:0;padding:0.75em;background:{bg};color:{fg};font:{size}px system-ui,sans-serif}}\
+         .muted{{color:{muted}}}.bar{{height:0.3em;margin:0.2em 0 0.4em;border-radius:2px;background:{accent}}}\
+         div{{white-space:nowrap;overflow:hidden;text-overflow:ellipsis}}</style></head><body>{body}</body></html>",
+        refresh = refresh,
+        title = escape(title),
+        bg = theme.background,
+        fg = theme.color,
+        size = theme.font_size,
+        muted = theme.muted,
+        accent = theme.accent,
+        body = body
+    )
+}
+
+/// Time tracked today.
+pub fn today_total(theme: &Theme, total_secs: u64, apps: usize) -> String {
+    let detail = match apps {
+        1 => "today, in 1 app".to_string(),
+        apps => format!("today, in {} apps", apps),
+    };
+    render(theme, "Tracked today", &[line(duration(total_secs), 2.0, false), line(detail, 0.85, true)])
+}
+
+/// The app in focus now, or that tracking is paused.
+pub fn current_app(theme: &Theme, app: Option<&str>, title: Option<&str>, for_secs: u64, paused: bool) -> String {
+    let mut lines = match (paused, app) {
+        (true, _) => vec![line("Paused", 1.4, false)],
+        (false, Some(app)) => vec![line(shorten(app), 1.4, false), line(format!("for {}", duration(for_secs)), 0.85, true)],
+        (false, None) => vec![line("Nothing in focus", 1.4, true)],
+    };
+    if let (false, Some(title)) = (paused, title.filter(|title| !title.is_empty())) {
+        lines.insert(1, line(shorten(title), 0.9, true));
+    }
+    render(theme, "Current app", &lines)
+}
+
+/// Today's five busiest apps, each with a bar against the first.
+pub fn top_apps(theme: &Theme, apps: &[(String, u64)]) -> String {
+    let top = apps.first().map_or(1, |(_, seconds)| (*seconds).max(1));
+    let mut lines = vec![line("Top apps today", 1.0, true)];
+    for (app, seconds) in apps.iter().take(MAX_TOP_APPS) {
+        let mut entry = line(format!("{}  {}", shorten(app), duration(*seconds)), 0.95, false);
+        entry.bar = Some(*seconds as f64 / top as f64);
+        lines.push(entry);
+    }
+    if apps.is_empty() {
+        lines.push(line("Nothing tracked yet", 0.95, true));
+    }
+    render(theme, "Top apps today", &lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn themes_are_checked_and_text_is_escaped() {
+        let query = WidgetQuery { theme: Some("light".to_string()), accent: Some("#f97316".to_string()), ..Default::default() };
+        let theme = query.theme().unwrap();
+        assert_eq!((theme.background.as_str(), theme.accent.as_str(), theme.refresh), ("#ffffff", "#f97316", DEFAULT_REFRESH_SECS));
+        assert!(WidgetQuery { accent: Some("red;}</style>".to_string()), ..Default::default() }.theme().is_err());
+        assert!(WidgetQuery { theme: Some("neon".to_string()), ..Default::default() }.theme().is_err());
+
+        let html = top_apps(&theme, &[("<b>code</b>".to_string(), 3600), ("chrome".to_string(), 1800)]);
+        assert!(html.contains("&lt;b&gt;code&lt;/b&gt;  1h 00m") && !html.contains("<b>"));
+        assert!(html.contains("width:50.0%") && html.contains("content=\"60\""));
+
+        let svg = WidgetQuery { format: Some("svg".to_string()), refresh: Some(0), ..Default::default() }.theme().unwrap();
+        let tile = current_app(&svg, Some("code.exe"), Some("main.rs"), 300, false);
+        assert!(tile.starts_with("<svg") && tile.contains(">main.rs</text>") && tile.contains(">for 5m</text>"));
+        assert!(current_app(&svg, Some("code.exe"), Some("main.rs"), 300, true).contains(">Paused</text>"));
+    }
+}
@@ -0,0 +1,168 @@
+// When the tracker writes, from the `flush` section of the config.
+//
+// Focus changes, transitions and session ends queue up in memory and go to the
+// database together. A write happens when `max_events` of them are waiting or
+// `interval_secs` passed since the last one, whichever comes first, so a burst
+// of switching doesn't wait out the interval and a quiet hour doesn't write
+// more often than needed. Both can be changed while running through
+// `PUT /api/flush`; that lasts until the next start, which reads the config
+// again. `POST /api/flush` writes right away, e.g. before a backup.
+//
+// The interval is capped at the heartbeat period, since heartbeats are queued
+// with the writes and a session without one for too long reads as ended.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::HEARTBEAT;
+
+pub const MAX_EVENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlushConfig {
+    pub max_events: usize,
+    pub interval_secs: u64,
+}
+
+impl Default for FlushConfig {
+    fn default() -> Self {
+        Self { max_events: 100, interval_secs: 5 }
+    }
+}
+
+impl FlushConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_EVENTS).contains(&self.max_events) {
+            return Err(format!("max_events must be between 1 and {}", MAX_EVENTS));
+        }
+        if !(1..=HEARTBEAT.as_secs()).contains(&self.interval_secs) {
+            return Err(format!("interval_secs must be between 1 and {}", HEARTBEAT.as_secs()));
+        }
+        Ok(())
+    }
+}
+
+/// A change of the settings while running; what is left out stays.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct FlushUpdate {
+    pub max_events: Option<usize>,
+    pub interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Flushed {
+    pub rows: usize, // Session rows, open ones included
+    pub events: usize, // Events and transitions that were waiting
+}
+
+pub struct Writer {
+    max_events: AtomicUsize,
+    interval_secs: AtomicU64,
+    last: Mutex<Instant>,
+    flushed_at: AtomicU64, // Unix seconds of the last write that went through, 0 before one
+    writing: Mutex<()>, // Held through a write, so two never take the same queued events
+}
+
+impl Writer {
+    /// Starts from `config`, or the defaults when it is out of range.
+    pub fn from_config(config: &FlushConfig) -> (Self, Option<String>) {
+        let (settings, error) = match config.validate() {
+            Ok(()) => (*config, None),
+            Err(e) => (FlushConfig::default(), Some(e)),
+        };
+        let writer = Self {
+            max_events: AtomicUsize::new(settings.max_events),
+            interval_secs: AtomicU64::new(settings.interval_secs),
+            last: Mutex::new(Instant::now()),
+            flushed_at: AtomicU64::new(0),
+            writing: Mutex::new(()),
+        };
+        (writer, error)
+    }
+
+    pub fn settings(&self) -> FlushConfig {
+        FlushConfig { max_events: self.max_events.load(Ordering::Relaxed), interval_secs: self.interval_secs.load(Ordering::Relaxed) }
+    }
+
+    pub fn update(&self, update: FlushUpdate) -> Result<FlushConfig, String> {
+        let current = self.settings();
+        let settings = FlushConfig {
+            max_events: update.max_events.unwrap_or(current.max_events),
+            interval_secs: update.interval_secs.unwrap_or(current.interval_secs),
+        };
+        settings.validate()?;
+        self.max_events.store(settings.max_events, Ordering::Relaxed);
+        self.interval_secs.store(settings.interval_secs, Ordering::Relaxed);
+        Ok(settings)
+    }
+
+    /// Whether `pending` queued events, at `now`, call for a write.
+    pub fn due(&self, pending: usize, now: Instant) -> bool {
+        let settings = self.settings();
+        pending >= settings.max_events
+            || now.saturating_duration_since(*self.last.lock().unwrap()) >= Duration::from_secs(settings.interval_secs)
+    }
+
+    pub fn mark_flushed(&self, now: u64) {
+        self.flushed_at.store(now, Ordering::Relaxed);
+    }
+
+    /// When the last write went through, None before the first since starting.
+    pub fn last_flush(&self) -> Option<u64> {
+        Some(self.flushed_at.load(Ordering::Relaxed)).filter(|&secs| secs > 0)
+    }
+
+    /// Held while writing; the interval counts from when it is dropped.
+    pub fn start(&self) -> WriteGuard<'_> {
+        WriteGuard { _writing: self.writing.lock().unwrap(), last: &self.last }
+    }
+}
+
+pub struct WriteGuard<'a> {
+    _writing: MutexGuard<'a, ()>,
+    last: &'a Mutex<Instant>,
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        *self.last.lock().unwrap() = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_on_either_count_or_time() {
+        let (writer, error) = Writer::from_config(&FlushConfig { max_events: 3, interval_secs: 10 });
+        assert!(error.is_none());
+        let start = *writer.last.lock().unwrap();
+        assert!(!writer.due(2, start + Duration::from_secs(9)));
+        assert!(writer.due(3, start));
+        assert!(writer.due(0, start + Duration::from_secs(10)));
+
+        let updated = writer.update(FlushUpdate { max_events: Some(50), interval_secs: None }).unwrap();
+        assert_eq!(updated, FlushConfig { max_events: 50, interval_secs: 10 });
+        assert!(!writer.due(3, start + Duration::from_secs(9)));
+        drop(writer.start());
+        assert!(*writer.last.lock().unwrap() > start);
+
+        assert_eq!(writer.last_flush(), None);
+        writer.mark_flushed(1_000);
+        assert_eq!(writer.last_flush(), Some(1_000));
+    }
+
+    #[test]
+    fn out_of_range_settings_are_refused() {
+        let (writer, error) = Writer::from_config(&FlushConfig { max_events: 0, interval_secs: 5 });
+        assert_eq!(error.as_deref(), Some("max_events must be between 1 and 10000"));
+        assert_eq!(writer.settings(), FlushConfig::default());
+        assert!(writer.update(FlushUpdate { max_events: Some(10), interval_secs: Some(HEARTBEAT.as_secs() + 1) }).is_err());
+        assert_eq!(writer.settings(), FlushConfig::default());
+    }
+}
@@ -1,13 +1,24 @@
 class SystemMonitorDashboard {
     constructor() {
-        this.updateInterval = 500; // Update every 500ms for faster debugging
-        this.lastUpdateTime = null;
+        this.updateInterval = 5000; // Until the server recommends one in `meta.pollSecs`
+        this.lastFlush = null; // When the data reached the database, from `meta.lastFlush`
+        this.tracking = null; // Pause state from /api/v1/tracking
+        this.forecastInterval = 60000; // Forecast and goals move slowly and read weeks of history
         this.init();
     }
 
     init() {
+        this.loadLayout();
+        this.loadCapabilities();
         this.loadDashboardData();
         this.startAutoUpdate();
+        this.loadForecast();
+        this.loadWeeklyGoals();
+        setInterval(() => {
+            this.loadForecast();
+            this.loadWeeklyGoals();
+        }, this.forecastInterval);
+        document.getElementById('pause-toggle').addEventListener('click', () => this.togglePause());
         this.updateLastUpdatedTime();
     }
 
@@ -16,10 +27,15 @@ class SystemMonitorDashboard {
             // Show loading indicator
             this.showLoadingIndicator();
             
-            const response = await fetch('/api/dashboard');
+            const response = await fetch('/api/v1/dashboard');
             const result = await response.json();
+            await this.loadTrackingState();
             
             if (result.success && result.data) {
+                if (result.meta) {
+                    this.updateInterval = result.meta.pollSecs * 1000;
+                    this.lastFlush = result.meta.lastFlush;
+                }
                 this.updateDashboard(result.data);
                 this.hideLoadingIndicator();
             } else {
@@ -34,34 +50,150 @@ class SystemMonitorDashboard {
         }
     }
 
+    async loadTrackingState() {
+        try {
+            const result = await (await fetch('/api/v1/tracking')).json();
+            this.tracking = result.success ? result.data : null;
+        } catch (error) {
+            this.tracking = null;
+        }
+    }
+
+    // Names what runs degraded here in the footer, with why and what it costs
+    // on hover; media sessions, hooks and elevation are missing almost everywhere
+    async loadCapabilities() {
+        const shown = ['window_tracking', 'url_capture', 'notifications', 'idle_detection', 'sleep_detection'];
+        try {
+            const result = await (await fetch('/api/capabilities')).json();
+            if (!result.success || !result.data) {
+                return;
+            }
+            const missing = result.data.filter(capability => !capability.available && shown.includes(capability.id));
+            const element = document.getElementById('degraded');
+            element.textContent = missing.length ? ` | Limited: ${missing.map(capability => capability.id.replace('_', ' ')).join(', ')}` : '';
+            element.title = missing.map(capability => `${capability.id}: ${capability.detail}; ${capability.without}`).join('\n');
+        } catch (error) {
+            console.error('Error fetching capabilities:', error);
+        }
+    }
+
+    // Orders and hides the cards as in the saved layout from /api/v1/dashboard/layout
+    async loadLayout() {
+        try {
+            const result = await (await fetch('/api/v1/dashboard/layout')).json();
+            if (!result.success || !result.data) {
+                return;
+            }
+            const { columns, widgets } = result.data;
+            for (const widget of widgets) {
+                const card = document.querySelector(`.dashboard-grid > .card.${CSS.escape(widget.id)}`);
+                if (!card) {
+                    continue; // A widget of another frontend
+                }
+                card.style.order = widget.row * columns + widget.column;
+                card.style.gridColumn = widget.width >= columns ? '1 / -1' : 'auto';
+                card.classList.toggle('layout-hidden', widget.hidden);
+            }
+        } catch (error) {
+            console.error('Error fetching dashboard layout:', error);
+        }
+    }
+
+    // Productive time today is on track for, from /api/v1/forecast/today
+    async loadForecast() {
+        try {
+            const result = await (await fetch('/api/v1/forecast/today')).json();
+            if (result.success && result.data) {
+                const productive = result.data.productive;
+                const element = document.getElementById('focus-forecast');
+                element.textContent = this.formatDuration(productive.projectedSecs);
+                element.title = `${this.formatDuration(productive.soFarSecs)} so far`;
+            }
+        } catch (error) {
+            console.error('Error fetching forecast:', error);
+        }
+    }
+
+    // Progress of each weekly goal; the card stays hidden without goals
+    async loadWeeklyGoals() {
+        try {
+            const result = await (await fetch('/api/v1/goals/weekly')).json();
+            const goals = result.success && result.data ? result.data.goals : [];
+            document.getElementById('weekly-goals').style.display = goals.length ? '' : 'none';
+            document.getElementById('weekly-goals-list').innerHTML = goals.map(goal => {
+                const pace = goal.remainingSecs === 0
+                    ? 'reached'
+                    : `${this.formatDuration(goal.requiredDailySecs)}/day to go`;
+                return `
+                    <div class="app-item">
+                        <div class="app-name">${this.escapeHtml(goal.category)}</div>
+                        <div class="app-duration">${this.formatDuration(goal.doneSecs)} / ${this.formatDuration(goal.targetSecs)}, ${pace}</div>
+                    </div>
+                `;
+            }).join('');
+        } catch (error) {
+            console.error('Error fetching weekly goals:', error);
+        }
+    }
+
+    // Pauses for 30 minutes, or resumes right away when paused
+    async togglePause() {
+        const paused = this.tracking && this.tracking.paused;
+        const url = paused ? '/api/v1/tracking/resume' : '/api/v1/tracking/pause?minutes=30';
+        try {
+            await fetch(url, { method: 'POST' });
+        } catch (error) {
+            console.error('Error changing pause state:', error);
+        }
+        this.loadDashboardData();
+    }
+
+    trackingStatusText() {
+        if (!this.tracking || !this.tracking.paused) {
+            return 'Monitoring Active';
+        }
+        const reason = this.tracking.pause.reason ? ` (${this.tracking.pause.reason})` : '';
+        const remaining = this.tracking.remainingSecs;
+        return remaining == null ? `Paused${reason}` : `Paused${reason}, resumes in ${this.formatDuration(remaining)}`;
+    }
+
     updateDashboard(data) {
+        // Says why this run tracks apps and titles only
+        const banner = document.getElementById('safe-mode-banner');
+        if (banner) {
+            banner.hidden = !data.safeMode;
+            if (data.safeMode) {
+                banner.textContent = `Safe mode: ${data.safeMode.reason}. URLs, title summaries and integrations are off until the next start.`;
+            }
+        }
+
         // Update current activity
-        this.updateElement('current-app', data.current_app || '-');
-        this.updateElement('current-window', data.current_window || '-');
+        this.updateElement('current-app', data.currentApp || '-');
+        this.updateElement('current-window', data.currentWindow || '-');
         
         const urlItem = document.getElementById('url-item');
         const currentUrl = document.getElementById('current-url');
         
-        if (data.current_url) {
+        if (data.currentUrl) {
             urlItem.style.display = 'block';
-            currentUrl.textContent = data.current_url;
+            currentUrl.textContent = data.currentUrl;
             currentUrl.style.color = 'var(--accent-color)';
             currentUrl.style.cursor = 'pointer';
-            currentUrl.onclick = () => window.open(data.current_url, '_blank');
+            currentUrl.onclick = () => window.open(data.currentUrl, '_blank');
         } else {
             urlItem.style.display = 'none';
         }
 
         // Update statistics
-        this.updateElement('uptime', this.formatDuration(data.uptime));
-        this.updateElement('total-apps', data.total_apps);
-        this.updateElement('active-count', data.active_apps.length);
+        this.updateElement('uptime', this.formatDuration(data.uptimeSecs));
+        this.updateElement('total-apps', data.totalApps);
+        this.updateElement('active-count', data.activeApps.length);
 
         // Update active applications list
-        // this.updateActiveAppsList(data.active_apps); // Removed for compact UI
+        // this.updateActiveAppsList(data.activeApps); // Removed for compact UI
 
         // Update recent activity (using active apps as recent activity for now)
-        this.updateRecentActivity(data.recent_activity);
+        this.updateRecentActivity(data.recentActivity);
 
         this.updateLastUpdatedTime();
     }
@@ -88,12 +220,17 @@ class SystemMonitorDashboard {
         container.innerHTML = groupedActivities.map(browserGroup => {
             const browserTotalTime = browserGroup.total_duration;
             const latestActivity = browserGroup.latest_activity;
-            const timeAgo = this.formatTimeAgo(latestActivity.timestamp);
+            const timeAgo = this.formatTimeAgo(latestActivity.endedAt);
             
             // Create individual site entries
             const siteEntries = browserGroup.sites.map(site => {
+                const host = this.siteHost(site.site_name);
+                const icon = host
+                    ? `<img class="site-icon" src="/api/domains/${encodeURIComponent(host)}/icon" alt="" loading="lazy">`
+                    : '';
                 return `
                     <div class="site-entry">
+                        ${icon}
                         <div class="site-name">${this.escapeHtml(site.site_name)}</div>
                         <div class="site-duration">${this.formatDuration(site.duration)}</div>
                     </div>
@@ -125,7 +262,7 @@ class SystemMonitorDashboard {
         
         // First, group by browser
         activities.forEach(activity => {
-            const appName = activity.app_name;
+            const appName = activity.appName;
             
             if (!browserGroups.has(appName)) {
                 browserGroups.set(appName, {
@@ -137,22 +274,22 @@ class SystemMonitorDashboard {
             }
             
             const browserGroup = browserGroups.get(appName);
-            browserGroup.total_duration += activity.duration;
+            browserGroup.total_duration += activity.durationSecs;
             
             // Keep track of the most recent activity for this browser
-            if (activity.timestamp > browserGroup.latest_activity.timestamp) {
+            if (activity.endedAt > browserGroup.latest_activity.endedAt) {
                 browserGroup.latest_activity = activity;
             }
             
             // Group sites within this browser
-            const siteName = activity.url || activity.window_title;
+            const siteName = activity.url || activity.windowTitle;
             if (!browserGroup.sites.has(siteName)) {
                 browserGroup.sites.set(siteName, {
                     site_name: siteName,
                     duration: 0
                 });
             }
-            browserGroup.sites.get(siteName).duration += activity.duration;
+            browserGroup.sites.get(siteName).duration += activity.durationSecs;
         });
         
         // Convert to array format and sort
@@ -167,14 +304,24 @@ class SystemMonitorDashboard {
                 latest_activity: browserGroup.latest_activity,
                 sites: sitesArray
             };
-        }).sort((a, b) => b.latest_activity.timestamp - a.latest_activity.timestamp);
+        }).sort((a, b) => b.latest_activity.endedAt - a.latest_activity.endedAt);
+    }
+
+    // Host of a URL, for its icon; null for window titles
+    siteHost(name) {
+        try {
+            const url = new URL(name);
+            return url.protocol.startsWith('http') ? url.hostname : null;
+        } catch {
+            return null;
+        }
     }
 
     groupActivitiesByApp(activities) {
         const groups = new Map();
         
         activities.forEach(activity => {
-            const appName = activity.app_name;
+            const appName = activity.appName;
             if (!groups.has(appName)) {
                 groups.set(appName, {
                     app_name: appName,
@@ -186,12 +333,12 @@ class SystemMonitorDashboard {
 
         // Sort activities within each group by timestamp (most recent first)
         groups.forEach(group => {
-            group.activities.sort((a, b) => b.timestamp - a.timestamp);
+            group.activities.sort((a, b) => b.endedAt - a.endedAt);
         });
 
         // Convert to array and sort groups by most recent activity
         return Array.from(groups.values()).sort((a, b) => 
-            b.activities[0].timestamp - a.activities[0].timestamp
+            b.activities[0].endedAt - a.activities[0].endedAt
         );
     }
 
@@ -236,12 +383,15 @@ class SystemMonitorDashboard {
     updateLastUpdatedTime() {
         const now = new Date();
         const timeString = now.toLocaleTimeString();
-        this.updateElement('last-updated', timeString);
+        const asOf = this.lastFlush ? ` (data as of ${new Date(this.lastFlush * 1000).toLocaleTimeString()})` : '';
+        this.updateElement('last-updated', timeString + asOf);
     }
 
+    // Rescheduled after each load, so a new `meta.pollSecs` applies to the next one
     startAutoUpdate() {
-        setInterval(() => {
-            this.loadDashboardData();
+        setTimeout(async () => {
+            await this.loadDashboardData();
+            this.startAutoUpdate();
         }, this.updateInterval);
     }
 
@@ -261,7 +411,15 @@ class SystemMonitorDashboard {
         
         if (statusDot && statusText) {
             statusDot.style.animation = 'pulse 2s infinite';
-            statusText.textContent = 'Monitoring Active';
+            statusText.textContent = this.trackingStatusText();
+        }
+        const paused = this.tracking && this.tracking.paused;
+        if (statusDot) {
+            statusDot.classList.toggle('active', !paused);
+        }
+        const toggle = document.getElementById('pause-toggle');
+        if (toggle) {
+            toggle.innerHTML = paused ? '<i class="fas fa-play"></i> Resume' : '<i class="fas fa-pause"></i> Pause 30m';
         }
     }
 